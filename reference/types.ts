@@ -1,4 +1,4 @@
-export type HttpHeaders = { [key: string]: string };
+export type HttpHeaders = { [key: string]: string | string[] };
 
 export type ContentEncodingType =
   | "gzip"
@@ -7,29 +7,149 @@ export type ContentEncodingType =
   | "br"
   | "identity";
 
+export interface EarlyHint {
+  offsetMs: number;
+  headers: HttpHeaders;
+}
+
+export interface FormPart {
+  name: string;
+  value?: string; // text fields
+  filename?: string;
+  contentType?: string;
+  filePath?: string; // uploaded file, relative to the inventory directory
+  size: number;
+}
+
+export interface RedirectTarget {
+  method: string;
+  url: string;
+  occurrence?: number;
+}
+
+export interface ByteRange {
+  start: number; // first byte, inclusive
+  end: number; // last byte, inclusive
+}
+
+export interface StreamEvent {
+  offsetMs: number; // from the response headers to the event's last byte
+  size: number; // bytes, including the blank line ending the event
+}
+
+export interface ClientMetadata {
+  userAgent?: string;
+  acceptEncoding?: string;
+  secChUa?: string;
+  secChUaMobile?: string;
+  secChUaPlatform?: string;
+  viewportWidth?: string;
+}
+
 export interface Resource {
   method: string;
   url: string;
+  occurrence?: number;
+  requestStartMs?: number;
   ttfbMs: number;
+  connectMs?: number; // upstream DNS/TCP/TLS setup, not included in ttfbMs
+  protocol?: HttpProtocol; // protocol the origin answered over
+  uploadMs?: number; // time the client took to send the request body, not included in ttfbMs
+  uploadBytes?: number;
   mbps?: number;
+  chunkTimings?: [number, number][]; // [bytes received, ms after the headers] as the body arrived
   statusCode?: number;
+  reasonPhrase?: string;
+  earlyHints?: EarlyHint[];
+  redirectTo?: RedirectTarget; // for a redirect: the recorded request the client followed it with
+  requestForm?: FormPart[]; // multipart/form-data request body
+  requestClient?: ClientMetadata; // client headers the request was sent with
+  requestBodySha1?: string; // hex SHA-1 of the request body, for body-hash matching
+  requestHeaders?: HttpHeaders; // headers of the recorded request; cookies and credentials are REDACTED
+  matchHeaders?: HttpHeaders; // request headers required by header matching
+  eventStream?: StreamEvent[]; // text/event-stream body, replayed event by event
+  eventStreamOpen?: boolean; // stream was still open when recording stopped
+  openEnded?: boolean; // body had not ended when the client gave up or recording stopped; content is what arrived
+  byteRanges?: ByteRange[]; // object recorded in 206 responses: spans the content holds back to back
+  completeLength?: number; // the object's full length, when the server stated it
+  errorKind?: ErrorKind; // why no response was received
   errorMessage?: string;
   rawHeaders?: HttpHeaders;
   contentEncoding?: ContentEncodingType;
   contentTypeMime?: string;
+  sniffedMime?: string; // type told from the body when Content-Type was missing, generic or wrong
   contentCharset?: string;
+  // The content file holds the body in contentCharset as received, not converted to UTF-8
+  charsetPreserved?: boolean;
+  // The text body as received, next to its UTF-8 content file (recording --charset both)
+  originalFilePath?: string;
   contentFilePath?: string;
   contentUtf8?: string;
   contentBase64?: string;
   minify?: boolean;
+  // The script's source map, next to its beautified content file (recording --source-maps)
+  sourceMapFilePath?: string;
+  sourcesDir?: string; // original sources the map embeds (recording --source-map-sources)
+  originalSize?: number; // body size as received, before decompression
+  availableAfterMs?: number; // playback answers unavailableStatus before this
+  unavailableStatus?: number; // default 404
+  urlMatch?: UrlMatch; // glob/regex: url is a pattern and the body a template
 }
 
+export type UrlMatch = "exact" | "glob" | "regex";
+
+export type HttpProtocol = "http/1.0" | "http/1.1" | "h2" | "h3";
+
+export type ErrorKind =
+  | "dnsFailure"
+  | "connectionRefused"
+  | "connectTimeout"
+  | "tlsError"
+  | "resetByPeer"
+  | "readTimeout"
+  | "truncatedBody" // the body broke off; the content is what arrived
+  | "internal";
+
+export type MatchStrategy =
+  | "exact"
+  | "ignore-query"
+  | "query-subset"
+  | "header"
+  | "body-hash";
+
 export type DeviceType = "desktop" | "mobile";
 
 export interface Inventory {
   entryUrl?: string;
   deviceType?: DeviceType;
   resources: Resource[];
+  opaqueTunnels?: OpaqueTunnel[];
+  seed?: number; // generated inventories
+  matchStrategies?: MatchStrategy[]; // tried in order when playback has no --match
+  origins?: OriginAddress[]; // recorded inventories
+  clientConnections?: HostConnections[]; // recorded inventories
+}
+
+export interface OpaqueTunnel {
+  host: string;
+  connections: number;
+  bytesSent: number;
+  bytesReceived: number;
+  durationMs: number;
+}
+
+export interface OriginAddress {
+  host: string;
+  resolved?: string[]; // every address DNS returned
+  connected?: string[]; // addresses connections were opened to
+  ipVersion?: 'ipv4' | 'ipv6' | 'both';
+}
+
+export interface HostConnections {
+  host: string;
+  connections: number; // client connections the browser opened to the host
+  requests?: number[]; // requests per connection, in the order they were opened
+  multiplexed?: boolean; // HTTP/2: requests shared a connection concurrently
 }
 
 export interface BodyChunk {
@@ -42,6 +162,8 @@ export interface Transaction {
   url: string;
   ttfb: number;
   statusCode?: number;
+  reasonPhrase?: string;
+  errorKind?: ErrorKind;
   errorMessage?: string;
   rawHeaders?: HttpHeaders;
   chunks: BodyChunk[];
@@ -3,6 +3,8 @@ use html5ever::tendril::TendrilSink;
 use markup5ever_rcdom::{Handle, NodeData, RcDom};
 use std::default::Default;
 
+use crate::minify::{shield_css_strings, unshield_css_strings};
+
 /// Format JavaScript code using swc
 pub fn format_javascript(input: &str) -> Result<String> {
     use swc_common::{FileName, GLOBALS, SourceMap, sync::Lrc};
@@ -57,7 +59,9 @@ pub fn format_css(input: &str) -> Result<String> {
         .map(|line| line.trim())
         .find(|line| line.starts_with("@charset"));
 
-    let sheet = StyleSheet::parse(input, ParserOptions::default())
+    // Strings lightningcss would print differently are kept as written
+    let (shielded, strings) = shield_css_strings(input);
+    let sheet = StyleSheet::parse(&shielded, ParserOptions::default())
         .map_err(|e| anyhow::anyhow!("Failed to parse CSS: {:?}", e))?;
     let out = sheet
         .to_css(PrinterOptions {
@@ -65,12 +69,13 @@ pub fn format_css(input: &str) -> Result<String> {
             ..Default::default()
         })
         .map_err(|e| anyhow::anyhow!("Failed to format CSS: {:?}", e))?;
+    let code = unshield_css_strings(out.code, &strings);
 
     // Re-add @charset declaration at the beginning if it existed
     if let Some(charset) = charset_line {
-        Ok(format!("{}\n{}", charset, out.code))
+        Ok(format!("{}\n{}", charset, code))
     } else {
-        Ok(out.code)
+        Ok(code)
     }
 }
 
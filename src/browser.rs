@@ -0,0 +1,305 @@
+//! Headless browser integration for one-command capture and replay
+//!
+//! Launches a headless Chromium pointed at the proxy, waits until the proxy has
+//! observed network idle (no in-flight requests for a quiet period) and then
+//! returns so the caller can run its normal graceful shutdown path.
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::types::DeviceType;
+
+/// Default quiet period (no in-flight requests) that counts as network idle
+pub const DEFAULT_NETWORK_IDLE_MS: u64 = 1000;
+
+/// Upper bound for a whole browser session, in case the page never goes idle
+const BROWSER_SESSION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Tracks in-flight requests passing through a proxy handler
+#[derive(Debug)]
+pub struct NetworkActivity {
+    in_flight: AtomicUsize,
+    total_requests: AtomicU64,
+    last_activity: Mutex<Instant>,
+}
+
+/// Marks a request as in flight until dropped
+#[derive(Debug)]
+pub struct ActivityGuard {
+    activity: Arc<NetworkActivity>,
+}
+
+impl NetworkActivity {
+    pub fn new() -> Self {
+        Self {
+            in_flight: AtomicUsize::new(0),
+            total_requests: AtomicU64::new(0),
+            last_activity: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Register a new in-flight request; it completes when the guard is dropped
+    pub fn begin(self: &Arc<Self>) -> ActivityGuard {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        self.total_requests.fetch_add(1, Ordering::SeqCst);
+        self.touch();
+        ActivityGuard {
+            activity: Arc::clone(self),
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn total_requests(&self) -> u64 {
+        self.total_requests.load(Ordering::SeqCst)
+    }
+
+    fn touch(&self) {
+        if let Ok(mut last) = self.last_activity.lock() {
+            *last = Instant::now();
+        }
+    }
+
+    fn quiet_for(&self) -> Duration {
+        self.last_activity
+            .lock()
+            .map(|last| last.elapsed())
+            .unwrap_or_default()
+    }
+
+    /// Whether at least one request was seen and nothing has happened for `quiet`
+    pub fn is_idle(&self, quiet: Duration) -> bool {
+        self.total_requests() > 0 && self.in_flight() == 0 && self.quiet_for() >= quiet
+    }
+
+    /// Wait until the network has been idle for `quiet`
+    pub async fn wait_for_idle(&self, quiet: Duration) {
+        let poll = std::cmp::max(quiet / 4, Duration::from_millis(10));
+        while !self.is_idle(quiet) {
+            tokio::time::sleep(poll).await;
+        }
+    }
+}
+
+impl Default for NetworkActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        self.activity.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.activity.touch();
+    }
+}
+
+/// Locate a Chromium-family executable
+///
+/// Checks the explicit path first, then `CHROME_PATH`, then well-known names per platform.
+pub fn find_chrome_executable(explicit: Option<&PathBuf>) -> Result<PathBuf> {
+    if let Some(path) = explicit {
+        return Ok(path.clone());
+    }
+    if let Some(path) = std::env::var("CHROME_PATH").ok().filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+
+    #[cfg(target_os = "macos")]
+    let candidates: &[&str] = &[
+        "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+        "/Applications/Chromium.app/Contents/MacOS/Chromium",
+    ];
+    #[cfg(windows)]
+    let candidates: &[&str] = &[
+        r"C:\Program Files\Google\Chrome\Application\chrome.exe",
+        r"C:\Program Files (x86)\Google\Chrome\Application\chrome.exe",
+    ];
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let candidates: &[&str] = &[
+        "google-chrome",
+        "google-chrome-stable",
+        "chromium",
+        "chromium-browser",
+    ];
+
+    for candidate in candidates {
+        let path = PathBuf::from(candidate);
+        if path.is_absolute() {
+            if path.exists() {
+                return Ok(path);
+            }
+            continue;
+        }
+        if let Some(paths) = std::env::var_os("PATH") {
+            for dir in std::env::split_paths(&paths) {
+                let full = dir.join(candidate);
+                if full.is_file() {
+                    return Ok(full);
+                }
+            }
+        }
+    }
+
+    anyhow::bail!("Chrome/Chromium executable not found. Set CHROME_PATH or pass --chrome-path")
+}
+
+/// Build the Chromium command line for a proxied headless session
+pub fn chrome_args(
+    proxy_port: u16,
+    url: &str,
+    user_data_dir: &std::path::Path,
+    device: Option<&DeviceType>,
+) -> Vec<String> {
+    let window_size = match device {
+        Some(DeviceType::Desktop) => "1350,940",
+        _ => "412,915",
+    };
+    vec![
+        "--headless=new".to_string(),
+        "--disable-gpu".to_string(),
+        "--no-first-run".to_string(),
+        "--no-default-browser-check".to_string(),
+        "--disable-background-networking".to_string(),
+        format!("--proxy-server=http://127.0.0.1:{}", proxy_port),
+        "--proxy-bypass-list=<-loopback>".to_string(),
+        // The MITM CA is generated per run, so trust it by ignoring certificate errors
+        "--ignore-certificate-errors".to_string(),
+        format!("--user-data-dir={}", user_data_dir.display()),
+        format!("--window-size={}", window_size),
+        url.to_string(),
+    ]
+}
+
+/// Launch headless Chromium through the proxy, navigate to `url` and wait for network idle
+pub async fn run_browser_session(
+    chrome_path: Option<&PathBuf>,
+    proxy_port: u16,
+    url: &str,
+    device: Option<&DeviceType>,
+    activity: Arc<NetworkActivity>,
+    network_idle: Duration,
+) -> Result<()> {
+    let executable = find_chrome_executable(chrome_path)?;
+    let user_data_dir = std::env::temp_dir().join(format!(
+        "http-playback-proxy-chrome-{}-{}",
+        std::process::id(),
+        proxy_port
+    ));
+
+    info!("Launching headless browser: {}", executable.display());
+    let mut child = tokio::process::Command::new(&executable)
+        .args(chrome_args(proxy_port, url, &user_data_dir, device))
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to launch {}", executable.display()))?;
+
+    let waited = tokio::time::timeout(BROWSER_SESSION_TIMEOUT, async {
+        tokio::select! {
+            _ = activity.wait_for_idle(network_idle) => Ok(()),
+            status = child.wait() => {
+                anyhow::bail!("Browser exited before network idle: {:?}", status)
+            }
+        }
+    })
+    .await;
+
+    match waited {
+        Ok(Ok(())) => info!(
+            "Network idle after {} requests, closing browser",
+            activity.total_requests()
+        ),
+        Ok(Err(e)) => warn!("{}", e),
+        Err(_) => warn!(
+            "Browser session did not reach network idle within {}s, closing browser",
+            BROWSER_SESSION_TIMEOUT.as_secs()
+        ),
+    }
+
+    let _ = child.kill().await;
+    let _ = tokio::fs::remove_dir_all(&user_data_dir).await;
+
+    Ok(())
+}
+
+/// Command-line options shared by recording and playback for headless browser sessions
+#[derive(Debug, Clone, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BrowserOptions {
+    #[arg(
+        long,
+        help = "Launch headless Chrome through the proxy, open the entry URL and shut down after network idle"
+    )]
+    pub launch_chrome: bool,
+
+    #[arg(
+        long,
+        help = "Path to the Chrome/Chromium executable (default: $CHROME_PATH or auto-detect)"
+    )]
+    pub chrome_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Quiet period in milliseconds that counts as network idle (default: 1000)"
+    )]
+    pub network_idle_ms: Option<u64>,
+}
+
+impl BrowserOptions {
+    pub fn network_idle(&self) -> Duration {
+        Duration::from_millis(self.network_idle_ms.unwrap_or(DEFAULT_NETWORK_IDLE_MS))
+    }
+}
+
+/// Wait for a shutdown signal, or for the headless browser session to end when enabled
+pub async fn wait_for_shutdown_or_browser<S>(
+    shutdown_signal: S,
+    options: &BrowserOptions,
+    proxy_port: u16,
+    entry_url: Option<&str>,
+    device: Option<&DeviceType>,
+    activity: Arc<NetworkActivity>,
+) where
+    S: std::future::Future<Output = Result<(), std::io::Error>>,
+{
+    let url = match entry_url {
+        Some(url) if options.launch_chrome => url,
+        _ => {
+            if let Err(e) = shutdown_signal.await {
+                tracing::error!("Signal handler error: {}", e);
+            }
+            return;
+        }
+    };
+
+    tokio::select! {
+        result = shutdown_signal => {
+            if let Err(e) = result {
+                tracing::error!("Signal handler error: {}", e);
+            }
+        }
+        result = run_browser_session(
+            options.chrome_path.as_ref(),
+            proxy_port,
+            url,
+            device,
+            activity,
+            options.network_idle(),
+        ) => {
+            if let Err(e) = result {
+                tracing::error!("Browser session error: {}", e);
+            }
+        }
+    }
+}
+
+mod tests;
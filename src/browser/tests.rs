@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod browser_tests {
+    use crate::browser::{NetworkActivity, chrome_args};
+    use crate::types::DeviceType;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_network_activity_not_idle_before_first_request() {
+        let activity = NetworkActivity::new();
+        assert!(!activity.is_idle(Duration::from_millis(0)));
+    }
+
+    #[test]
+    fn test_network_activity_guard_tracks_in_flight() {
+        let activity = Arc::new(NetworkActivity::new());
+
+        let guard = activity.begin();
+        assert_eq!(activity.in_flight(), 1);
+        assert!(!activity.is_idle(Duration::from_millis(0)));
+
+        drop(guard);
+        assert_eq!(activity.in_flight(), 0);
+        assert_eq!(activity.total_requests(), 1);
+        assert!(activity.is_idle(Duration::from_millis(0)));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_idle_returns_after_quiet_period() {
+        let activity = Arc::new(NetworkActivity::new());
+        drop(activity.begin());
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(2),
+            activity.wait_for_idle(Duration::from_millis(50)),
+        )
+        .await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_chrome_args_use_proxy_and_device_size() {
+        let args = chrome_args(
+            18080,
+            "https://example.com/",
+            Path::new("/tmp/profile"),
+            Some(&DeviceType::Desktop),
+        );
+
+        assert!(args.contains(&"--proxy-server=http://127.0.0.1:18080".to_string()));
+        assert!(args.contains(&"--window-size=1350,940".to_string()));
+        assert_eq!(args.last().unwrap(), "https://example.com/");
+    }
+}
@@ -0,0 +1,373 @@
+//! Detect resources that changed at the origin since they were recorded
+//!
+//! Every recorded GET is fetched again and its body is put through the same batch
+//! processing as a recording (decompression, UTF-8 conversion, beautification), so the
+//! result can be compared byte for byte with the stored content file.
+
+use anyhow::Result;
+use futures::StreamExt;
+use regex::Regex;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::recording::batch_processor::{BatchProcessor, CharsetPolicy};
+use crate::traits::{
+    FileSystem, HttpClient, HttpResponse, RealFileSystem, RealHttpClient, RealTimeProvider,
+};
+use crate::types::{HeaderValue, HttpHeaders, Inventory, Resource, UrlMatch};
+
+/// Response headers whose change means the origin published a different version
+const VALIDATOR_HEADERS: &[&str] = &["etag", "last-modified", "content-type"];
+
+/// Requests in flight at once
+const CHECK_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct CheckOptions {
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        help = "Only check URLs matching this regex (repeatable)"
+    )]
+    pub include: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        help = "Skip URLs matching this regex (repeatable)"
+    )]
+    pub exclude: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Where to write the JSON report (default: <inventory>/check-report.json)"
+    )]
+    pub report: Option<PathBuf>,
+}
+
+/// Include/exclude regexes over resource URLs
+#[derive(Debug, Default)]
+pub struct UrlFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl UrlFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| Regex::new(p).map_err(|e| anyhow::anyhow!("Invalid pattern {}: {}", p, e)))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// Included (or no include patterns given) and not excluded
+    pub fn matches(&self, url: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.is_match(url)))
+            && !self.exclude.iter().any(|p| p.is_match(url))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckOutcome {
+    Unchanged,
+    Changed,
+    Error,
+}
+
+/// Comparison of one recorded resource with what the origin serves now
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceCheck {
+    pub method: String,
+    pub url: String,
+    pub outcome: CheckOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recorded_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recorded_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_hash: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed_headers: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ResourceCheck {
+    pub fn body_changed(&self) -> bool {
+        self.recorded_hash != self.current_hash
+    }
+}
+
+pub async fn run_check(inventory_dir: PathBuf, options: CheckOptions) -> Result<()> {
+    let filter = UrlFilter::new(&options.include, &options.exclude)?;
+    let file_system = Arc::new(RealFileSystem);
+    let inventory = crate::playback::load_inventory(&inventory_dir, file_system.clone()).await?;
+
+    let scratch_dir = inventory_dir.join(".check");
+    let client = Arc::new(RealHttpClient::new()?);
+    let checks = check_inventory(
+        &inventory,
+        &inventory_dir,
+        &scratch_dir,
+        &filter,
+        client,
+        file_system.clone(),
+    )
+    .await;
+    let _ = tokio::fs::remove_dir_all(&scratch_dir).await;
+
+    print_checks(&checks);
+
+    let report_path = options
+        .report
+        .unwrap_or_else(|| inventory_dir.join("check-report.json"));
+    file_system
+        .write_string(&report_path, &serde_json::to_string_pretty(&checks)?)
+        .await?;
+    println!("Report written to {:?}", report_path);
+
+    Ok(())
+}
+
+/// Check every recorded GET that passes `filter`, in inventory order
+///
+/// Fresh bodies are processed under `scratch_dir`, one subdirectory per resource.
+pub async fn check_inventory<C: HttpClient, F: FileSystem>(
+    inventory: &Inventory,
+    inventory_dir: &Path,
+    scratch_dir: &Path,
+    filter: &UrlFilter,
+    client: Arc<C>,
+    file_system: Arc<F>,
+) -> Vec<ResourceCheck> {
+    let candidates = inventory.resources.iter().enumerate().filter(|(_, r)| {
+        // URL patterns are hand-authored stubs with nothing to fetch
+        r.method.eq_ignore_ascii_case("GET")
+            && r.url_match.unwrap_or_default() == UrlMatch::Exact
+            && filter.matches(&r.url)
+    });
+
+    futures::stream::iter(candidates)
+        .map(|(index, resource)| {
+            let client = client.clone();
+            let file_system = file_system.clone();
+            let resource_scratch = scratch_dir.join(index.to_string());
+            async move {
+                check_resource(
+                    resource,
+                    inventory_dir,
+                    &resource_scratch,
+                    client.as_ref(),
+                    file_system,
+                )
+                .await
+            }
+        })
+        .buffered(CHECK_CONCURRENCY)
+        .collect()
+        .await
+}
+
+async fn check_resource<C: HttpClient, F: FileSystem>(
+    resource: &Resource,
+    inventory_dir: &Path,
+    scratch_dir: &Path,
+    client: &C,
+    file_system: Arc<F>,
+) -> ResourceCheck {
+    let mut check = ResourceCheck {
+        method: resource.method.clone(),
+        url: resource.url.clone(),
+        outcome: CheckOutcome::Error,
+        recorded_status: resource.status_code,
+        current_status: None,
+        recorded_hash: None,
+        current_hash: None,
+        changed_headers: Vec::new(),
+        error: None,
+    };
+
+    let result = async {
+        check.recorded_hash = stored_content(resource, inventory_dir, file_system.as_ref())
+            .await?
+            .map(|content| sha1_hex(&content));
+
+        let response = client
+            .request(&resource.method, &resource.url, None, None)
+            .await?;
+        check.current_status = Some(response.status_code);
+
+        let fresh = response_to_resource(resource, &response);
+        check.changed_headers = changed_validators(resource, &fresh);
+
+        // Stored the way the recording stored it, so only origin changes differ
+        let charset = if resource.charset_preserved == Some(true) {
+            CharsetPolicy::Original
+        } else {
+            CharsetPolicy::Utf8
+        };
+        let content = processed_content(fresh, scratch_dir, file_system.clone(), charset).await?;
+        check.current_hash = content.map(|content| sha1_hex(&content));
+        anyhow::Ok(())
+    }
+    .await;
+
+    check.outcome = match result {
+        Err(e) => {
+            check.error = Some(e.to_string());
+            CheckOutcome::Error
+        }
+        Ok(()) if check.recorded_status.unwrap_or(200) != check.current_status.unwrap_or(200) => {
+            CheckOutcome::Changed
+        }
+        Ok(()) if check.body_changed() || !check.changed_headers.is_empty() => {
+            CheckOutcome::Changed
+        }
+        Ok(()) => CheckOutcome::Unchanged,
+    };
+    check
+}
+
+/// The stored body, as written by batch processing
+pub async fn stored_content<F: FileSystem>(
+    resource: &Resource,
+    inventory_dir: &Path,
+    file_system: &F,
+) -> Result<Option<Vec<u8>>> {
+    if let Some(file_path) = &resource.content_file_path {
+        let full_path = inventory_dir.join(file_path);
+        if file_system.exists(&full_path).await {
+            return Ok(Some(file_system.read(&full_path).await?));
+        }
+    }
+    if let Some(utf8) = &resource.content_utf8 {
+        return Ok(Some(utf8.as_bytes().to_vec()));
+    }
+    if let Some(base64_content) = &resource.content_base64 {
+        use base64::{Engine as _, engine::general_purpose};
+        return Ok(Some(general_purpose::STANDARD.decode(base64_content)?));
+    }
+    Ok(None)
+}
+
+/// A resource as the recording handler would have captured this response
+fn response_to_resource(recorded: &Resource, response: &HttpResponse) -> Resource {
+    let mut resource = Resource::new(recorded.method.clone(), recorded.url.clone());
+    resource.status_code = Some(response.status_code);
+
+    let mut headers = HttpHeaders::new();
+    for (name, value) in &response.headers {
+        headers.append(name, value.clone());
+    }
+    if let Some(HeaderValue::Single(encoding)) = headers.get("content-encoding") {
+        resource.content_encoding = encoding.parse().ok();
+    }
+    if let Some(HeaderValue::Single(content_type)) = headers.get("content-type") {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or(content_type)
+            .trim();
+        resource.content_type_mime = Some(mime.to_string());
+    }
+    resource.raw_headers = Some(headers);
+    resource.raw_body = Some(response.body.clone());
+    resource
+}
+
+/// Run a fresh resource through batch processing and return its content file
+async fn processed_content<F: FileSystem>(
+    fresh: Resource,
+    scratch_dir: &Path,
+    file_system: Arc<F>,
+    charset: CharsetPolicy,
+) -> Result<Option<Vec<u8>>> {
+    let mut inventory = Inventory::new();
+    inventory.resources.push(fresh);
+
+    BatchProcessor::new(
+        scratch_dir.to_path_buf(),
+        file_system.clone(),
+        Arc::new(RealTimeProvider::new()),
+    )
+    .with_charset(charset)
+    .process_all(&mut inventory)
+    .await?;
+
+    match &inventory.resources[0].content_file_path {
+        Some(file_path) => Ok(Some(file_system.read(&scratch_dir.join(file_path)).await?)),
+        None => Ok(None),
+    }
+}
+
+fn changed_validators(recorded: &Resource, fresh: &Resource) -> Vec<String> {
+    let value = |resource: &Resource, name: &str| {
+        resource
+            .raw_headers
+            .as_ref()
+            .and_then(|h| h.get(name))
+            .map(|v| v.as_vec().join(", "))
+    };
+    VALIDATOR_HEADERS
+        .iter()
+        .filter(|name| value(recorded, name) != value(fresh, name))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+pub fn sha1_hex(content: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(content);
+    hex::encode(hasher.finalize())
+}
+
+fn print_checks(checks: &[ResourceCheck]) {
+    for check in checks {
+        match check.outcome {
+            CheckOutcome::Unchanged => {}
+            CheckOutcome::Changed => {
+                let mut reasons = Vec::new();
+                if check.recorded_status != check.current_status {
+                    reasons.push(format!(
+                        "status {} -> {}",
+                        check.recorded_status.unwrap_or(200),
+                        check.current_status.unwrap_or(200)
+                    ));
+                }
+                if check.body_changed() {
+                    reasons.push("body".to_string());
+                }
+                reasons.extend(check.changed_headers.iter().cloned());
+                println!("CHANGED  {} ({})", check.url, reasons.join(", "));
+            }
+            CheckOutcome::Error => println!(
+                "ERROR    {} ({})",
+                check.url,
+                check.error.as_deref().unwrap_or("unknown error")
+            ),
+        }
+    }
+
+    let count = |outcome| checks.iter().filter(|c| c.outcome == outcome).count();
+    println!(
+        "{} checked: {} unchanged, {} changed, {} failed",
+        checks.len(),
+        count(CheckOutcome::Unchanged),
+        count(CheckOutcome::Changed),
+        count(CheckOutcome::Error)
+    );
+}
+
+mod tests;
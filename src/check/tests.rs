@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod check_tests {
+    use crate::check::{CheckOutcome, UrlFilter, check_inventory};
+    use crate::recording::batch_processor::BatchProcessor;
+    use crate::traits::HttpResponse;
+    use crate::traits::mocks::{MockFileSystem, MockHttpClient, MockTimeProvider};
+    use crate::types::{HeaderValue, HttpHeaders, Inventory, Resource};
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn recorded_resource(method: &str, url: &str, body: &str, etag: &str) -> Resource {
+        let mut resource = Resource::new(method.to_string(), url.to_string());
+        resource.status_code = Some(200);
+        resource.content_type_mime = Some("text/plain".to_string());
+        let mut headers = HttpHeaders::new();
+        headers.set(
+            "content-type",
+            HeaderValue::Single("text/plain".to_string()),
+        );
+        headers.set("etag", HeaderValue::Single(etag.to_string()));
+        resource.raw_headers = Some(headers);
+        resource.raw_body = Some(body.as_bytes().to_vec());
+        resource
+    }
+
+    fn origin_response(body: &str, etag: &str) -> HttpResponse {
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), "text/plain".to_string());
+        headers.insert("ETag".to_string(), etag.to_string());
+        HttpResponse {
+            status_code: 200,
+            headers,
+            body: body.as_bytes().to_vec(),
+            elapsed_ms: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_reports_changed_resources() {
+        let file_system = Arc::new(MockFileSystem::new());
+        let inventory_dir = Path::new("/inventory");
+
+        let mut inventory = Inventory::new();
+        inventory.resources = vec![
+            recorded_resource("GET", "https://example.com/same.txt", "hello", "\"1\""),
+            recorded_resource("GET", "https://example.com/body.txt", "old", "\"1\""),
+            recorded_resource("GET", "https://example.com/etag.txt", "same", "\"1\""),
+            recorded_resource("POST", "https://example.com/form", "ok", "\"1\""),
+            recorded_resource("GET", "https://cdn.example.com/skip.txt", "x", "\"1\""),
+        ];
+        BatchProcessor::new(
+            inventory_dir.to_path_buf(),
+            file_system.clone(),
+            Arc::new(MockTimeProvider::new(0)),
+        )
+        .process_all(&mut inventory)
+        .await
+        .unwrap();
+
+        let client = Arc::new(MockHttpClient::new());
+        client.set_response(
+            "GET:https://example.com/same.txt",
+            origin_response("hello", "\"1\""),
+        );
+        client.set_response(
+            "GET:https://example.com/body.txt",
+            origin_response("new", "\"1\""),
+        );
+        client.set_response(
+            "GET:https://example.com/etag.txt",
+            origin_response("same", "\"2\""),
+        );
+
+        let filter = UrlFilter::new(&[], &["cdn\\.".to_string()]).unwrap();
+        let checks = check_inventory(
+            &inventory,
+            inventory_dir,
+            &inventory_dir.join(".check"),
+            &filter,
+            client.clone(),
+            file_system.clone(),
+        )
+        .await;
+
+        // POST and excluded URLs are never fetched
+        let requested: Vec<String> = client.get_requests().into_iter().map(|(_, u)| u).collect();
+        assert_eq!(requested.len(), 3, "unexpected requests: {:?}", requested);
+
+        assert_eq!(checks.len(), 3);
+        assert_eq!(checks[0].outcome, CheckOutcome::Unchanged);
+        assert_eq!(checks[0].recorded_hash, checks[0].current_hash);
+
+        assert_eq!(checks[1].outcome, CheckOutcome::Changed);
+        assert!(checks[1].body_changed());
+        assert!(checks[1].changed_headers.is_empty());
+
+        assert_eq!(checks[2].outcome, CheckOutcome::Changed);
+        assert!(!checks[2].body_changed());
+        assert_eq!(checks[2].changed_headers, vec!["etag".to_string()]);
+    }
+
+    #[test]
+    fn test_url_filter() {
+        let filter =
+            UrlFilter::new(&["example\\.com".to_string()], &["\\.png$".to_string()]).unwrap();
+        assert!(filter.matches("https://example.com/app.js"));
+        assert!(!filter.matches("https://example.com/logo.png"));
+        assert!(!filter.matches("https://other.test/app.js"));
+
+        assert!(UrlFilter::new(&["(".to_string()], &[]).is_err());
+    }
+}
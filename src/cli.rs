@@ -1,5 +1,10 @@
-use crate::types::DeviceType;
 use clap::{Parser, Subcommand};
+use http_playback_proxy::check::CheckOptions;
+use http_playback_proxy::playback::PlaybackOptions;
+use http_playback_proxy::query::QueryOptions;
+use http_playback_proxy::recording::RecordingOptions;
+use http_playback_proxy::recording::batch_processor::CharsetPolicy;
+use http_playback_proxy::types::{DeviceType, MatchStrategy};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -34,6 +39,9 @@ pub enum Commands {
             help = "Inventory directory"
         )]
         inventory: PathBuf,
+
+        #[command(flatten)]
+        options: RecordingOptions,
     },
 
     #[command(about = "Playback recorded HTTP traffic")]
@@ -52,6 +60,237 @@ pub enum Commands {
             help = "Inventory directory"
         )]
         inventory: PathBuf,
+
+        #[command(flatten)]
+        options: PlaybackOptions,
+    },
+
+    #[command(
+        about = "Replay a recording as-is and with overridden files on adjacent ports, then compare served timing"
+    )]
+    Experiment {
+        #[arg(
+            short,
+            long,
+            help = "Port for the baseline proxy; the variant uses the next free port (default: auto-detect from 18080)"
+        )]
+        port: Option<u16>,
+
+        #[arg(
+            short,
+            long,
+            default_value = "./inventory",
+            help = "Inventory directory"
+        )]
+        inventory: PathBuf,
+
+        #[arg(
+            long,
+            help = "Directory of replacement files laid out like the inventory (e.g. contents/get/https/example.com/app.js)"
+        )]
+        overrides: PathBuf,
+
+        #[arg(
+            long,
+            help = "Where to write the JSON comparison report (default: <inventory>/experiment-report.json)"
+        )]
+        report: Option<PathBuf>,
+
+        #[command(flatten)]
+        options: PlaybackOptions,
+    },
+
+    #[command(about = "Rebuild the inventory of a recording that was interrupted before saving")]
+    Recover {
+        #[arg(
+            short,
+            long,
+            default_value = "./inventory",
+            help = "Inventory directory"
+        )]
+        inventory: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "BYTES",
+            help = "Also embed binary bodies up to this size as contentBase64 in index.json (default: never)"
+        )]
+        inline_base64_max_bytes: Option<usize>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "utf8",
+            help = "Store text bodies as the interrupted recording would have: utf8, original or both"
+        )]
+        charset: CharsetPolicy,
+
+        #[arg(
+            long,
+            help = "Take over the inventory lock left behind by the interrupted recording"
+        )]
+        force: bool,
+    },
+
+    #[command(about = "Re-fetch recorded URLs and report which resources changed at the origin")]
+    Check {
+        #[arg(
+            short,
+            long,
+            default_value = "./inventory",
+            help = "Inventory directory"
+        )]
+        inventory: PathBuf,
+
+        #[command(flatten)]
+        options: CheckOptions,
+    },
+
+    #[command(about = "Create an inventory of synthetic resources from a spec, without any origin")]
+    Generate {
+        #[arg(
+            help = "JSON spec: resources with url, contentType, size, ttfbMs and mbps or durationMs"
+        )]
+        spec: PathBuf,
+
+        #[arg(
+            short,
+            long,
+            default_value = "./inventory",
+            help = "Inventory directory"
+        )]
+        inventory: PathBuf,
+
+        #[arg(
+            long,
+            default_value_t = 0,
+            help = "Seed for the generated binary content; the same seed reproduces the same bytes"
+        )]
+        seed: u64,
+
+        #[arg(long, help = "Replace an existing inventory and take over its lock")]
+        force: bool,
+    },
+
+    #[command(
+        about = "Convert a HAR file (browser devtools, WebPageTest) into an inventory for playback"
+    )]
+    ImportHar {
+        #[arg(help = "HAR file to import")]
+        har: PathBuf,
+
+        #[arg(
+            short,
+            long,
+            default_value = "./inventory",
+            help = "Inventory directory"
+        )]
+        inventory: PathBuf,
+
+        #[arg(short, long, help = "Device type the HAR was captured with")]
+        device: Option<DeviceType>,
+
+        #[arg(long, help = "Replace an existing inventory and take over its lock")]
+        force: bool,
+    },
+
+    #[command(about = "Show which recorded resource playback would serve for a request, and why")]
+    MatchTest {
+        #[arg(
+            short,
+            long,
+            default_value = "./inventory",
+            help = "Inventory directory"
+        )]
+        inventory: PathBuf,
+
+        #[arg(long, help = "Request URL, e.g. https://example.com/x?y=1")]
+        url: String,
+
+        #[arg(long, default_value = "GET", help = "Request method")]
+        method: String,
+
+        #[command(flatten)]
+        query: QueryOptions,
+
+        #[arg(
+            long = "match",
+            value_enum,
+            value_name = "STRATEGY",
+            help = "Request-matching strategy to try, as in playback (repeatable; default: the inventory's matchStrategies, else exact)"
+        )]
+        strategies: Vec<MatchStrategy>,
+    },
+
+    #[command(
+        about = "Write a copy of an inventory with secrets in headers, cookies, URLs and bodies replaced"
+    )]
+    Redact {
+        #[arg(
+            short,
+            long,
+            default_value = "./inventory",
+            help = "Inventory directory"
+        )]
+        inventory: PathBuf,
+
+        #[arg(
+            long,
+            help = "YAML rules: headers, cookies, queryParams, bodyPatterns and replacement"
+        )]
+        rules: PathBuf,
+
+        #[arg(short, long, help = "Directory for the redacted copy")]
+        output: PathBuf,
+
+        #[arg(long, help = "Replace an existing inventory in the output directory")]
+        force: bool,
+    },
+
+    #[command(
+        about = "Check an inventory for broken file references, invalid content and duplicate resources"
+    )]
+    Validate {
+        #[arg(
+            short,
+            long,
+            default_value = "./inventory",
+            help = "Inventory directory"
+        )]
+        inventory: PathBuf,
+    },
+
+    #[command(about = "List the redirect chains of an inventory with the time each cost")]
+    Redirects {
+        #[arg(
+            short,
+            long,
+            default_value = "./inventory",
+            help = "Inventory directory"
+        )]
+        inventory: PathBuf,
+
+        #[arg(long, help = "Print the chains as JSON")]
+        json: bool,
+    },
+
+    #[command(
+        about = "Serve configurable test responses (size, delays, charset, encoding) described by each request's query"
+    )]
+    MockOrigin {
+        #[arg(
+            short,
+            long,
+            help = "Port to listen on (default: auto-detect from 18080)"
+        )]
+        port: Option<u16>,
+
+        #[arg(
+            long,
+            default_value_t = 0,
+            help = "Seed for the bytes of binary bodies"
+        )]
+        seed: u64,
     },
 
     /// Send signal to a process (internal helper, primarily for Windows)
@@ -0,0 +1,251 @@
+//! Built-in endpoints served by the proxy itself under the magic host `hpp.local`
+//!
+//! Requests for this host never reach an origin or the inventory. A device configured
+//! to use the proxy can open `http://hpp.local/` to download and install the MITM CA,
+//! the same way mitmproxy serves `mitm.it`.
+//!
+//! `/_start` redirects to the session's entry URL. It is also answered when the proxy
+//! port is opened directly (`http://127.0.0.1:18080/_start`), so automation can begin a
+//! replay without knowing the recorded URL. `POST /_shutdown` and `POST /_reload`,
+//! answered the same way, stop the proxy as SIGINT would and have playback read its
+//! inventory again. `/status` reports the state of a playback run as JSON.
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hudsucker::{
+    Body,
+    hyper::{Method, Request, Response, StatusCode},
+};
+
+use std::sync::{Arc, RwLock};
+use tokio::sync::Notify;
+
+use crate::server::ShutdownHandle;
+
+/// Host name intercepted by the proxy for its own endpoints
+pub const CONTROL_HOST: &str = "hpp.local";
+
+/// Path redirecting to the entry URL, on `hpp.local` and the proxy's own address
+pub const START_PATH: &str = "/_start";
+
+/// Path stopping the proxy on POST, on `hpp.local` and the proxy's own address
+pub const SHUTDOWN_PATH: &str = "/_shutdown";
+
+/// Path reloading the inventory of a playback run on POST, on `hpp.local` and the
+/// proxy's own address
+pub const RELOAD_PATH: &str = "/_reload";
+
+/// Path of the JSON status report of a playback run
+pub const STATUS_PATH: &str = "/status";
+
+/// Whether a request targets the proxy's own endpoints rather than an origin
+pub fn is_control_request<B>(req: &Request<B>) -> bool {
+    let host = req.uri().host().map(str::to_string).or_else(|| {
+        req.headers()
+            .get("host")
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.split(':').next().unwrap_or(h).to_string())
+    });
+    host.is_some_and(|h| {
+        let path = req.uri().path();
+        h.eq_ignore_ascii_case(CONTROL_HOST)
+            || ([START_PATH, SHUTDOWN_PATH, RELOAD_PATH].contains(&path) && is_loopback(&h))
+    })
+}
+
+fn is_loopback(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// JSON answered at `/status`, shared with whatever replaces it while the proxy runs
+#[derive(Debug, Clone)]
+pub struct StatusReport(Arc<RwLock<serde_json::Value>>);
+
+impl StatusReport {
+    pub fn new(status: serde_json::Value) -> Self {
+        Self(Arc::new(RwLock::new(status)))
+    }
+
+    /// Answer later requests with `status`
+    pub fn set(&self, status: serde_json::Value) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+
+    /// The JSON answered at the moment
+    pub fn current(&self) -> serde_json::Value {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Responses for the `hpp.local` endpoints
+#[derive(Debug, Clone)]
+pub struct ControlEndpoints {
+    ca_cert_pem: String,
+    ca_cert_der: Vec<u8>,
+    entry_url: Option<String>,
+    status: Option<StatusReport>,
+    shutdown: Option<ShutdownHandle>,
+    reload: Option<Arc<Notify>>,
+}
+
+impl ControlEndpoints {
+    pub fn new(ca_cert_pem: String, ca_cert_der: Vec<u8>) -> Self {
+        Self {
+            ca_cert_pem,
+            ca_cert_der,
+            entry_url: None,
+            status: None,
+            shutdown: None,
+            reload: None,
+        }
+    }
+
+    /// Redirect `/_start` to this URL
+    pub fn with_entry_url(mut self, entry_url: Option<String>) -> Self {
+        self.entry_url = entry_url;
+        self
+    }
+
+    /// Answer `/status` with the current JSON of this report
+    pub fn with_status(mut self, status: Option<StatusReport>) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Stop the proxy through this handle on `POST /_shutdown`
+    pub fn with_shutdown(mut self, shutdown: ShutdownHandle) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Ask for a reload through `reload` on `POST /_reload`
+    pub fn with_reload(mut self, reload: Option<Arc<Notify>>) -> Self {
+        self.reload = reload;
+        self
+    }
+
+    /// Answer a request for which [`is_control_request`] returned true
+    pub fn respond<B>(&self, req: &Request<B>) -> Response<Body> {
+        match req.uri().path() {
+            "/" | "/index.html" => Response::builder()
+                .header("content-type", "text/html; charset=utf-8")
+                .body(Body::from(INDEX_HTML.to_string()))
+                .unwrap(),
+            "/cert.pem" => Response::builder()
+                .header("content-type", "application/x-pem-file")
+                .header(
+                    "content-disposition",
+                    "attachment; filename=\"http-playback-proxy-ca.pem\"",
+                )
+                .body(Body::from(self.ca_cert_pem.clone()))
+                .unwrap(),
+            "/cert.crt" => Response::builder()
+                .header("content-type", "application/x-x509-ca-cert")
+                .header(
+                    "content-disposition",
+                    "attachment; filename=\"http-playback-proxy-ca.crt\"",
+                )
+                .body(Body::from(Full::new(Bytes::from(self.ca_cert_der.clone()))))
+                .unwrap(),
+            START_PATH => match &self.entry_url {
+                Some(entry_url) => Response::builder()
+                    .status(StatusCode::FOUND)
+                    .header("location", entry_url.as_str())
+                    .header("cache-control", "no-store")
+                    .body(Body::empty())
+                    .unwrap(),
+                None => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from("This session has no entry URL"))
+                    .unwrap(),
+            },
+            SHUTDOWN_PATH => match &self.shutdown {
+                // A GET could come from a prefetch or a crawler
+                Some(_) if req.method() != Method::POST => Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .header("allow", "POST")
+                    .body(Body::from("Use POST to stop the proxy"))
+                    .unwrap(),
+                Some(shutdown) => {
+                    shutdown.shutdown();
+                    Response::builder()
+                        .status(StatusCode::ACCEPTED)
+                        .header("cache-control", "no-store")
+                        .body(Body::from("Shutting down"))
+                        .unwrap()
+                }
+                None => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from("This proxy cannot be stopped remotely"))
+                    .unwrap(),
+            },
+            RELOAD_PATH => match &self.reload {
+                Some(_) if req.method() != Method::POST => Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .header("allow", "POST")
+                    .body(Body::from("Use POST to reload the inventory"))
+                    .unwrap(),
+                Some(reload) => {
+                    // Requests arriving during a reload coalesce into one more
+                    reload.notify_one();
+                    Response::builder()
+                        .status(StatusCode::ACCEPTED)
+                        .header("cache-control", "no-store")
+                        .body(Body::from("Reloading the inventory"))
+                        .unwrap()
+                }
+                None => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from("This proxy does not reload its inventory"))
+                    .unwrap(),
+            },
+            STATUS_PATH => match &self.status {
+                Some(status) => Response::builder()
+                    .header("content-type", "application/json")
+                    .header("cache-control", "no-store")
+                    .body(Body::from(
+                        serde_json::to_string_pretty(&status.current()).unwrap_or_default(),
+                    ))
+                    .unwrap(),
+                None => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from("This proxy reports no status"))
+                    .unwrap(),
+            },
+            path => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from(format!(
+                    "Unknown {} endpoint: {}",
+                    CONTROL_HOST, path
+                )))
+                .unwrap(),
+        }
+    }
+}
+
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>http-playback-proxy</title>
+</head>
+<body>
+<h1>http-playback-proxy CA certificate</h1>
+<p>Install the certificate of this proxy run to let HTTPS traffic through it.</p>
+<ul>
+<li><a href="/cert.pem">cert.pem</a> (PEM: macOS, Linux, Firefox)</li>
+<li><a href="/cert.crt">cert.crt</a> (DER: iOS, Android, Windows)</li>
+</ul>
+<p><a href="/_start">Start</a> at the entry URL of this session.</p>
+<p>The CA is generated per run, so install it again after restarting the proxy.</p>
+</body>
+</html>
+"#;
+
+mod tests;
@@ -0,0 +1,138 @@
+#[cfg(test)]
+mod control_tests {
+    use crate::control::{ControlEndpoints, StatusReport, is_control_request};
+    use http_body_util::BodyExt;
+    use hudsucker::hyper::Request;
+
+    fn endpoints() -> ControlEndpoints {
+        ControlEndpoints::new(
+            "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n".to_string(),
+            vec![0x30, 0x82, 0x01, 0x0a],
+        )
+    }
+
+    #[test]
+    fn test_is_control_request() {
+        let absolute = Request::get("http://hpp.local/cert.pem").body(()).unwrap();
+        assert!(is_control_request(&absolute));
+
+        let with_host = Request::get("/cert.crt")
+            .header("host", "HPP.local:443")
+            .body(())
+            .unwrap();
+        assert!(is_control_request(&with_host));
+
+        let origin = Request::get("https://example.com/").body(()).unwrap();
+        assert!(!is_control_request(&origin));
+
+        // The start redirect is also answered on the proxy's own address
+        let direct = Request::get("/_start")
+            .header("host", "127.0.0.1:18080")
+            .body(())
+            .unwrap();
+        assert!(is_control_request(&direct));
+        let recorded = Request::get("https://example.com/_start").body(()).unwrap();
+        assert!(!is_control_request(&recorded));
+    }
+
+    #[test]
+    fn test_start_redirects_to_entry_url() {
+        let req = Request::get("http://hpp.local/_start").body(()).unwrap();
+        assert_eq!(endpoints().respond(&req).status(), 404);
+
+        let control = endpoints().with_entry_url(Some("https://example.com/app".to_string()));
+        let res = control.respond(&req);
+        assert_eq!(res.status(), 302);
+        assert_eq!(res.headers()["location"], "https://example.com/app");
+    }
+
+    #[tokio::test]
+    async fn test_status_endpoint() {
+        let req = Request::get("http://hpp.local/status").body(()).unwrap();
+        assert_eq!(endpoints().respond(&req).status(), 404);
+
+        let report = StatusReport::new(serde_json::json!({ "memory": { "totalBytes": 42 } }));
+        let control = endpoints().with_status(Some(report.clone()));
+        let res = control.respond(&req);
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["content-type"], "application/json");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let status: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status["memory"]["totalBytes"], 42);
+
+        // A replaced report is answered from then on
+        report.set(serde_json::json!({ "memory": { "totalBytes": 7 } }));
+        let body = control.respond(&req).into_body().collect().await.unwrap();
+        let status: serde_json::Value = serde_json::from_slice(&body.to_bytes()).unwrap();
+        assert_eq!(status["memory"]["totalBytes"], 7);
+    }
+
+    #[test]
+    fn test_shutdown_endpoint() {
+        use crate::server::ShutdownHandle;
+
+        let req = Request::post("/_shutdown")
+            .header("host", "127.0.0.1:18080")
+            .body(())
+            .unwrap();
+        assert!(is_control_request(&req));
+        assert_eq!(endpoints().respond(&req).status(), 404);
+
+        let shutdown = ShutdownHandle::new();
+        let control = endpoints().with_shutdown(shutdown.clone());
+        let get = Request::get("http://hpp.local/_shutdown").body(()).unwrap();
+        assert_eq!(control.respond(&get).status(), 405);
+        assert!(!shutdown.is_requested());
+
+        assert_eq!(control.respond(&req).status(), 202);
+        assert!(shutdown.is_requested());
+    }
+
+    #[tokio::test]
+    async fn test_reload_endpoint() {
+        use std::sync::Arc;
+        use std::time::Duration;
+        use tokio::sync::Notify;
+
+        let req = Request::post("/_reload")
+            .header("host", "localhost:18080")
+            .body(())
+            .unwrap();
+        assert!(is_control_request(&req));
+        assert_eq!(endpoints().respond(&req).status(), 404);
+
+        let reload = Arc::new(Notify::new());
+        let control = endpoints().with_reload(Some(reload.clone()));
+        let get = Request::get("http://hpp.local/_reload").body(()).unwrap();
+        assert_eq!(control.respond(&get).status(), 405);
+
+        assert_eq!(control.respond(&req).status(), 202);
+        tokio::time::timeout(Duration::from_secs(1), reload.notified())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cert_endpoints() {
+        let control = endpoints();
+
+        let req = Request::get("http://hpp.local/cert.pem").body(()).unwrap();
+        let res = control.respond(&req);
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["content-type"], "application/x-pem-file");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.starts_with(b"-----BEGIN CERTIFICATE-----"));
+
+        let req = Request::get("http://hpp.local/cert.crt").body(()).unwrap();
+        let res = control.respond(&req);
+        assert_eq!(res.headers()["content-type"], "application/x-x509-ca-cert");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], &[0x30, 0x82, 0x01, 0x0a]);
+
+        let req = Request::get("http://hpp.local/").body(()).unwrap();
+        assert_eq!(control.respond(&req).status(), 200);
+
+        let req = Request::get("http://hpp.local/missing").body(()).unwrap();
+        assert_eq!(control.respond(&req).status(), 404);
+    }
+}
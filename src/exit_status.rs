@@ -0,0 +1,131 @@
+//! Exit codes CI pipelines can gate on
+//!
+//! A run that fails exits with 1. A playback run that completed exits with 0 unless a
+//! quality gate was asked for and missed: with `--fail-on-miss` it exits with 2 when
+//! any request had no recording to answer it, and with `--min-timing-fidelity` with 3
+//! when too few responses were on time. A recording, or a playback journal or fallback
+//! recording, that could not be saved exits with 4. When several apply, the highest
+//! code wins.
+
+use std::fmt;
+
+use crate::playback::served_log::{DEFAULT_TIMING_TOLERANCE_MS, ServedSummary};
+use crate::utils::parse_duration_ms;
+
+pub const EXIT_MISSES: u8 = 2;
+pub const EXIT_TIMING: u8 = 3;
+pub const EXIT_SAVE_FAILED: u8 = 4;
+
+/// Why a run that got to its end still fails, attached to its error
+///
+/// `main` exits with [`RunFailure::code`] for errors carrying one, as the error itself
+/// or as context.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunFailure {
+    /// Requests that no recording answered
+    Misses(usize),
+    /// Percent of responses on time, below the required percent
+    TimingFidelity {
+        on_time_percent: f64,
+        min_percent: f64,
+    },
+    /// Results could not be written
+    SaveFailed,
+}
+
+impl RunFailure {
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Misses(_) => EXIT_MISSES,
+            Self::TimingFidelity { .. } => EXIT_TIMING,
+            Self::SaveFailed => EXIT_SAVE_FAILED,
+        }
+    }
+}
+
+impl fmt::Display for RunFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Misses(misses) => write!(f, "{} requests had no recording", misses),
+            Self::TimingFidelity {
+                on_time_percent,
+                min_percent,
+            } => write!(
+                f,
+                "{:.1}% of responses were on time, below --min-timing-fidelity {}%",
+                on_time_percent, min_percent
+            ),
+            Self::SaveFailed => write!(f, "Failed to save the results of the run"),
+        }
+    }
+}
+
+impl std::error::Error for RunFailure {}
+
+/// The exit code for a run that ended with `error`
+pub fn code_of(error: &anyhow::Error) -> u8 {
+    error
+        .downcast_ref::<RunFailure>()
+        .map_or(1, RunFailure::code)
+}
+
+#[derive(Debug, Clone, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityGates {
+    #[arg(
+        long,
+        help = "Exit with code 2 when any request had no recording to answer it"
+    )]
+    pub fail_on_miss: bool,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_parser = parse_percent,
+        help = "Exit with code 3 when fewer than this percent of responses had their headers and last byte on time"
+    )]
+    pub min_timing_fidelity: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "How late a response's headers or last byte may be and still count as on time (default: 200ms)"
+    )]
+    pub timing_tolerance: Option<u64>,
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent: f64 = value
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("Invalid percent {:?}", value))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!("Percent {} is not between 0 and 100", percent));
+    }
+    Ok(percent)
+}
+
+impl QualityGates {
+    pub fn tolerance_ms(&self) -> u64 {
+        self.timing_tolerance.unwrap_or(DEFAULT_TIMING_TOLERANCE_MS)
+    }
+
+    /// The gate `summary` misses, highest code first
+    pub fn check(&self, summary: &ServedSummary) -> Option<RunFailure> {
+        let on_time_percent = summary.on_time_percent();
+        match self.min_timing_fidelity {
+            Some(min_percent) if on_time_percent < min_percent => {
+                return Some(RunFailure::TimingFidelity {
+                    on_time_percent,
+                    min_percent,
+                });
+            }
+            _ => {}
+        }
+        (self.fail_on_miss && summary.misses > 0).then_some(RunFailure::Misses(summary.misses))
+    }
+}
+
+mod tests;
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod exit_status_tests {
+    use crate::exit_status::{QualityGates, RunFailure, code_of};
+    use crate::playback::served_log::ServedLog;
+    use anyhow::Context;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_quality_gates() {
+        let log = ServedLog::totals_only().with_timing_tolerance(100);
+        // Headers are 500ms late for the first response only
+        let received = Instant::now() - Duration::from_millis(500);
+        drop(
+            log.begin("GET", "https://example.com/", 200, 10, received)
+                .expecting("https://example.com/", 0, 0),
+        );
+        for _ in 0..3 {
+            drop(
+                log.begin("GET", "https://example.com/a.js", 200, 10, Instant::now())
+                    .expecting("https://example.com/a.js", 1000, 2000),
+            );
+        }
+        log.miss();
+        let summary = log.summary();
+        assert_eq!(summary.requests, 4);
+        assert_eq!(summary.on_time, 3);
+        assert_eq!(summary.misses, 1);
+        assert_eq!(summary.on_time_percent(), 75.0);
+
+        assert_eq!(QualityGates::default().check(&summary), None);
+        let fail_on_miss = QualityGates {
+            fail_on_miss: true,
+            ..Default::default()
+        };
+        assert_eq!(fail_on_miss.check(&summary), Some(RunFailure::Misses(1)));
+        let strict = QualityGates {
+            fail_on_miss: true,
+            min_timing_fidelity: Some(80.0),
+            ..Default::default()
+        };
+        // The higher code wins
+        assert_eq!(strict.check(&summary).unwrap().code(), 3);
+        let lenient = QualityGates {
+            min_timing_fidelity: Some(75.0),
+            ..Default::default()
+        };
+        assert_eq!(lenient.check(&summary), None);
+        // Nothing served is not a timing failure
+        assert_eq!(strict.check(&ServedLog::new().summary()), None);
+    }
+
+    #[test]
+    fn test_exit_codes_of_errors() {
+        assert_eq!(code_of(&anyhow::anyhow!("Invalid port")), 1);
+        assert_eq!(code_of(&RunFailure::Misses(3).into()), 2);
+
+        let save: anyhow::Result<()> = Err(anyhow::anyhow!("Disk full"));
+        let error = save
+            .context(RunFailure::SaveFailed)
+            .context("Recording stopped")
+            .unwrap_err();
+        assert_eq!(code_of(&error), 4);
+        assert!(format!("{:?}", error).contains("Disk full"));
+    }
+}
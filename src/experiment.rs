@@ -0,0 +1,291 @@
+//! A/B asset experiments
+//!
+//! Runs two playback proxies from the same inventory on adjacent ports: the baseline
+//! replays the recording as-is, the variant replaces content files with an override
+//! set. After shutdown the timing both proxies actually served is written as a report.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::playback::served_log::{ServedEntry, ServedLog};
+use crate::playback::{self, PlaybackOptions};
+use crate::traits::{FileSystem, RealFileSystem};
+use crate::types::Inventory;
+use crate::utils::{find_available_port, get_port_or_default};
+
+/// Served timing of both runs, per resource and overall
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExperimentReport {
+    pub baseline_port: u16,
+    pub variant_port: u16,
+    pub overridden_urls: Vec<String>,
+    pub baseline: RunSummary,
+    pub variant: RunSummary,
+    pub resources: Vec<ResourceComparison>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunSummary {
+    pub requests: usize,
+    pub bytes: usize,
+    /// From the first request arriving to the last response finishing
+    pub span_ms: u64,
+}
+
+/// First served response for a method+URL in each run
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceComparison {
+    pub method: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<ServedEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<ServedEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta_ms: Option<i64>,
+}
+
+pub async fn run_experiment(
+    port: Option<u16>,
+    inventory_dir: PathBuf,
+    overrides_dir: PathBuf,
+    report_path: Option<PathBuf>,
+    options: PlaybackOptions,
+) -> Result<()> {
+    let baseline_port = get_port_or_default(port)?;
+    let variant_port = find_available_port(baseline_port.saturating_add(1))?;
+
+    let file_system = Arc::new(RealFileSystem);
+    let mut baseline = playback::load_inventory(&inventory_dir, file_system.clone()).await?;
+    playback::adjust_inventory(&mut baseline, &options)?;
+    playback::apply_header_overrides(&mut baseline, &inventory_dir, file_system.as_ref()).await?;
+    if options.browser.launch_chrome && baseline.entry_url.is_none() {
+        anyhow::bail!("--launch-chrome requires an inventory with entryUrl");
+    }
+
+    let mut variant = baseline.clone();
+    let overridden_urls =
+        apply_overrides(&mut variant, &inventory_dir, &overrides_dir, file_system).await?;
+    if overridden_urls.is_empty() {
+        anyhow::bail!(
+            "No file in {:?} matches a content file of the inventory",
+            overrides_dir
+        );
+    }
+
+    println!("Baseline playback on port {}", baseline_port);
+    println!(
+        "Variant playback on port {} ({} resources overridden)",
+        variant_port,
+        overridden_urls.len()
+    );
+
+    let baseline_log = ServedLog::new();
+    let variant_log = ServedLog::new();
+    tokio::try_join!(
+        playback::serve_inventory(
+            baseline_port,
+            baseline,
+            inventory_dir.clone(),
+            options.clone(),
+            Some(baseline_log.clone()),
+            false,
+        ),
+        playback::serve_inventory(
+            variant_port,
+            variant,
+            inventory_dir.clone(),
+            options,
+            Some(variant_log.clone()),
+            false,
+        ),
+    )?;
+
+    let report = build_report(
+        baseline_port,
+        variant_port,
+        overridden_urls,
+        &baseline_log.entries(),
+        &variant_log.entries(),
+    );
+    print_report(&report);
+
+    let report_path = report_path.unwrap_or_else(|| inventory_dir.join("experiment-report.json"));
+    RealFileSystem
+        .write_string(&report_path, &serde_json::to_string_pretty(&report)?)
+        .await?;
+    println!("Report written to {:?}", report_path);
+
+    Ok(())
+}
+
+/// Point resources at replacement files laid out like the inventory directory
+///
+/// An override for `contents/get/https/example.com/app.js` may live at that path under
+/// `overrides_dir` or without the `contents/` prefix. The recorded throughput is kept, so
+/// transfer time scales with the new body size. Returns the overridden URLs.
+pub async fn apply_overrides<F: FileSystem>(
+    inventory: &mut Inventory,
+    inventory_dir: &Path,
+    overrides_dir: &Path,
+    file_system: Arc<F>,
+) -> Result<Vec<String>> {
+    let mut overridden = Vec::new();
+
+    for resource in &mut inventory.resources {
+        let Some(content_path) = resource.content_file_path.clone() else {
+            continue;
+        };
+        let candidates = [
+            overrides_dir.join(&content_path),
+            overrides_dir.join(content_path.trim_start_matches("contents/")),
+        ];
+        let mut override_path = None;
+        for candidate in candidates {
+            if file_system.exists(&candidate).await {
+                override_path = Some(candidate);
+                break;
+            }
+        }
+        let Some(override_path) = override_path else {
+            continue;
+        };
+
+        let original_size = file_system
+            .read_bytes(&inventory_dir.join(&content_path))
+            .await
+            .map(|b| b.len())
+            .unwrap_or(0);
+        let override_size = file_system.read_bytes(&override_path).await?.len();
+        if let Some(duration_ms) = resource.duration_ms.filter(|_| original_size > 0) {
+            resource.duration_ms =
+                Some((duration_ms as f64 * override_size as f64 / original_size as f64) as u64);
+        }
+
+        // Overrides are served as shipped, without the minify pass for beautified captures
+        let override_path = std::path::absolute(&override_path)?;
+        resource.content_file_path = Some(override_path.to_string_lossy().into_owned());
+        resource.minify = None;
+        resource.original_size = None;
+        overridden.push(resource.url.clone());
+    }
+
+    Ok(overridden)
+}
+
+/// Pair the first served response per method+URL from both runs
+pub fn build_report(
+    baseline_port: u16,
+    variant_port: u16,
+    overridden_urls: Vec<String>,
+    baseline: &[ServedEntry],
+    variant: &[ServedEntry],
+) -> ExperimentReport {
+    let mut order: Vec<(String, String)> = Vec::new();
+    let mut pairs: HashMap<(String, String), (Option<ServedEntry>, Option<ServedEntry>)> =
+        HashMap::new();
+
+    let mut add = |entries: &[ServedEntry], is_baseline: bool| {
+        let mut sorted: Vec<&ServedEntry> = entries.iter().collect();
+        sorted.sort_by_key(|e| e.start_ms);
+        for entry in sorted {
+            let key = (entry.method.clone(), entry.url.clone());
+            let pair = pairs.entry(key.clone()).or_insert_with(|| {
+                order.push(key);
+                (None, None)
+            });
+            let slot = if is_baseline {
+                &mut pair.0
+            } else {
+                &mut pair.1
+            };
+            if slot.is_none() {
+                *slot = Some(entry.clone());
+            }
+        }
+    };
+    add(baseline, true);
+    add(variant, false);
+
+    let resources = order
+        .into_iter()
+        .map(|key| {
+            let (baseline, variant) = pairs.remove(&key).unwrap_or_default();
+            let delta_ms = match (&baseline, &variant) {
+                (Some(b), Some(v)) => Some(v.total_ms as i64 - b.total_ms as i64),
+                _ => None,
+            };
+            ResourceComparison {
+                method: key.0,
+                url: key.1,
+                baseline,
+                variant,
+                delta_ms,
+            }
+        })
+        .collect();
+
+    ExperimentReport {
+        baseline_port,
+        variant_port,
+        overridden_urls,
+        baseline: summarize(baseline),
+        variant: summarize(variant),
+        resources,
+    }
+}
+
+fn summarize(entries: &[ServedEntry]) -> RunSummary {
+    let first_start = entries.iter().map(|e| e.start_ms).min().unwrap_or(0);
+    let last_end = entries
+        .iter()
+        .map(|e| e.start_ms + e.total_ms)
+        .max()
+        .unwrap_or(0);
+    RunSummary {
+        requests: entries.len(),
+        bytes: entries.iter().map(|e| e.bytes).sum(),
+        span_ms: last_end.saturating_sub(first_start),
+    }
+}
+
+fn print_report(report: &ExperimentReport) {
+    println!();
+    println!("  baseline    variant    delta  resource");
+    let ms = |entry: &Option<ServedEntry>| {
+        entry
+            .as_ref()
+            .map(|e| format!("{}ms", e.total_ms))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    for resource in &report.resources {
+        println!(
+            "{:>10} {:>10} {:>8}  {} {}",
+            ms(&resource.baseline),
+            ms(&resource.variant),
+            resource
+                .delta_ms
+                .map(|d| format!("{:+}", d))
+                .unwrap_or_else(|| "-".to_string()),
+            resource.method,
+            resource.url
+        );
+    }
+    println!();
+    println!(
+        "Baseline: {} requests, {} bytes, {}ms span",
+        report.baseline.requests, report.baseline.bytes, report.baseline.span_ms
+    );
+    println!(
+        "Variant:  {} requests, {} bytes, {}ms span",
+        report.variant.requests, report.variant.bytes, report.variant.span_ms
+    );
+}
+
+mod tests;
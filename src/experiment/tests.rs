@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod experiment_tests {
+    use crate::experiment::{apply_overrides, build_report};
+    use crate::playback::served_log::ServedEntry;
+    use crate::traits::mocks::MockFileSystem;
+    use crate::types::{Inventory, Resource};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn entry(url: &str, start_ms: u64, total_ms: u64, bytes: usize) -> ServedEntry {
+        ServedEntry {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            request_url: url.to_string(),
+            status: 200,
+            bytes,
+            start_ms,
+            ttfb_ms: 10,
+            total_ms,
+            target_ttfb_ms: 10,
+            target_total_ms: total_ms,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_overrides_scales_duration() {
+        let inventory_dir = PathBuf::from("/inventory");
+        let overrides_dir = PathBuf::from("/overrides");
+        let mock_fs = Arc::new(MockFileSystem::new());
+        mock_fs.set_file(
+            "/inventory/contents/get/https/example.com/app.js",
+            vec![b'a'; 1000],
+        );
+        // Accepted without the contents/ prefix
+        mock_fs.set_file("/overrides/get/https/example.com/app.js", vec![b'b'; 250]);
+
+        let mut inventory = Inventory::new();
+        let mut app = Resource::new("GET".to_string(), "https://example.com/app.js".to_string());
+        app.content_file_path = Some("contents/get/https/example.com/app.js".to_string());
+        app.duration_ms = Some(400);
+        app.minify = Some(true);
+        app.original_size = Some(1000);
+        inventory.resources.push(app);
+
+        let mut page = Resource::new("GET".to_string(), "https://example.com/".to_string());
+        page.content_file_path = Some("contents/get/https/example.com/index.html".to_string());
+        page.duration_ms = Some(100);
+        inventory.resources.push(page);
+
+        let overridden = apply_overrides(&mut inventory, &inventory_dir, &overrides_dir, mock_fs)
+            .await
+            .unwrap();
+
+        assert_eq!(overridden, vec!["https://example.com/app.js".to_string()]);
+        let app = &inventory.resources[0];
+        let app_path = app.content_file_path.as_deref().unwrap().replace('\\', "/");
+        assert!(app_path.ends_with("/overrides/get/https/example.com/app.js"));
+        assert_eq!(app.duration_ms, Some(100));
+        assert_eq!(app.minify, None);
+        assert_eq!(app.original_size, None);
+
+        let page = &inventory.resources[1];
+        assert_eq!(
+            page.content_file_path.as_deref(),
+            Some("contents/get/https/example.com/index.html")
+        );
+        assert_eq!(page.duration_ms, Some(100));
+    }
+
+    #[test]
+    fn test_build_report_pairs_first_responses() {
+        let baseline = vec![
+            entry("https://example.com/", 0, 200, 5000),
+            entry("https://example.com/app.js", 210, 400, 1000),
+            entry("https://example.com/app.js", 900, 50, 1000),
+        ];
+        let variant = vec![
+            entry("https://example.com/app.js", 205, 120, 250),
+            entry("https://example.com/", 0, 190, 5000),
+        ];
+
+        let report = build_report(
+            18080,
+            18081,
+            vec!["https://example.com/app.js".to_string()],
+            &baseline,
+            &variant,
+        );
+
+        assert_eq!(report.resources.len(), 2);
+        assert_eq!(report.resources[0].url, "https://example.com/");
+        assert_eq!(report.resources[0].delta_ms, Some(-10));
+
+        let app = &report.resources[1];
+        assert_eq!(app.baseline.as_ref().unwrap().total_ms, 400);
+        assert_eq!(app.variant.as_ref().unwrap().total_ms, 120);
+        assert_eq!(app.delta_ms, Some(-280));
+
+        assert_eq!(report.baseline.requests, 3);
+        assert_eq!(report.baseline.bytes, 7000);
+        assert_eq!(report.baseline.span_ms, 950);
+        assert_eq!(report.variant.requests, 2);
+        assert_eq!(report.variant.span_ms, 325);
+    }
+}
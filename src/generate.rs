@@ -0,0 +1,203 @@
+//! Synthetic inventories
+//!
+//! Builds an inventory from a JSON spec of resources (size, MIME type, TTFB and
+//! bandwidth) without contacting any origin, so playback can serve as a plain
+//! network-condition simulator for benchmarking clients. Bodies are filler: text types
+//! get comment or text padding in their own syntax, everything else incompressible
+//! bytes drawn from `--seed`.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use crate::lock::InventoryLock;
+use crate::types::{DeviceType, HeaderValue, HttpHeaders, Inventory, Resource};
+use crate::utils::SeededRng;
+
+const FILLER_LINE: &str =
+    "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor.\n";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateSpec {
+    pub entry_url: Option<String>,
+    pub device_type: Option<DeviceType>,
+    pub resources: Vec<ResourceSpec>,
+}
+
+/// One generated resource, or `count` of them with `{n}` in the URL numbered from 1
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ResourceSpec {
+    pub url: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default = "default_status")]
+    pub status_code: u16,
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+    /// Body size in bytes
+    pub size: u64,
+    #[serde(default)]
+    pub ttfb_ms: u64,
+    /// Transfer rate; `durationMs` takes precedence when both are given
+    pub mbps: Option<f64>,
+    pub duration_ms: Option<u64>,
+    pub request_start_ms: Option<u64>,
+    pub count: Option<u32>,
+    /// Further response headers
+    pub headers: Option<HttpHeaders>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+fn default_content_type() -> String {
+    "application/octet-stream".to_string()
+}
+
+pub async fn run_generate(
+    spec_path: PathBuf,
+    inventory_dir: PathBuf,
+    seed: u64,
+    force: bool,
+) -> Result<()> {
+    let spec = std::fs::read_to_string(&spec_path)
+        .with_context(|| format!("Failed to read spec {:?}", spec_path))?;
+    let spec: GenerateSpec = serde_json::from_str(&spec)
+        .with_context(|| format!("Failed to parse spec {:?}", spec_path))?;
+
+    let index_path = inventory_dir.join("index.json");
+    if index_path.exists() && !force {
+        anyhow::bail!("{:?} already exists; use --force to replace it", index_path);
+    }
+    let _lock = InventoryLock::acquire(&inventory_dir, "generate", force)?;
+
+    let mut inventory = build_inventory(&spec, seed)?;
+    crate::recording::proxy::finish_inventory(
+        &mut inventory,
+        &inventory_dir,
+        None,
+        crate::recording::batch_processor::CharsetPolicy::default(),
+        None,
+    )
+    .await?;
+
+    println!(
+        "Generated {} resources in {:?} (seed {})",
+        inventory.resources.len(),
+        inventory_dir,
+        seed
+    );
+    Ok(())
+}
+
+/// The inventory a spec describes, with bodies in `raw_body` as a recording leaves them
+///
+/// Binary filler is drawn from `seed`, which is kept in the inventory.
+pub fn build_inventory(spec: &GenerateSpec, seed: u64) -> Result<Inventory> {
+    let mut inventory = Inventory::new();
+    inventory.entry_url = spec.entry_url.clone();
+    inventory.device_type = spec.device_type.clone();
+    inventory.seed = Some(seed);
+
+    for entry in &spec.resources {
+        if entry.mbps.is_some_and(|mbps| mbps <= 0.0) {
+            anyhow::bail!("mbps of {} must be positive", entry.url);
+        }
+        match entry.count {
+            Some(count) => {
+                for n in 1..=count {
+                    let url = entry.url.replace("{n}", &n.to_string());
+                    inventory
+                        .resources
+                        .push(generate_resource(entry, url, seed));
+                }
+            }
+            None => inventory
+                .resources
+                .push(generate_resource(entry, entry.url.clone(), seed)),
+        }
+    }
+
+    Ok(inventory)
+}
+
+fn generate_resource(entry: &ResourceSpec, url: String, seed: u64) -> Resource {
+    let mime = entry
+        .content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let body = filler_body(&mime, entry.size as usize, &url, seed);
+
+    let mut headers = entry.headers.clone().unwrap_or_default();
+    headers.set(
+        "content-type",
+        HeaderValue::Single(entry.content_type.clone()),
+    );
+    headers.set(
+        "content-length",
+        HeaderValue::Single(body.len().to_string()),
+    );
+
+    // Whichever of duration and bandwidth is missing follows from the other
+    let megabits = body.len() as f64 * 8.0 / 1_000_000.0;
+    let (duration_ms, mbps) = match (entry.duration_ms, entry.mbps) {
+        (Some(duration_ms), _) => (
+            Some(duration_ms),
+            (duration_ms > 0).then(|| megabits / (duration_ms as f64 / 1000.0)),
+        ),
+        (None, Some(mbps)) => (Some((megabits / mbps * 1000.0).round() as u64), Some(mbps)),
+        (None, None) => (Some(0), None),
+    };
+
+    let mut resource = Resource::new(entry.method.to_ascii_uppercase(), url);
+    resource.status_code = Some(entry.status_code);
+    resource.ttfb_ms = entry.ttfb_ms;
+    resource.duration_ms = duration_ms;
+    resource.mbps = mbps;
+    resource.request_start_ms = entry.request_start_ms;
+    resource.raw_headers = Some(headers);
+    resource.content_type_mime = Some(mime);
+    resource.raw_body = Some(body);
+    resource
+}
+
+/// `size` bytes of content that is valid enough for `mime` to survive beautification
+pub(crate) fn filler_body(mime: &str, size: usize, url: &str, seed: u64) -> Vec<u8> {
+    let (prefix, suffix) = match mime {
+        "text/html" => (
+            "<!DOCTYPE html>\n<html>\n<body>\n<!--\n",
+            "-->\n</body>\n</html>\n",
+        ),
+        "text/css" | "application/javascript" | "text/javascript" => ("/*\n", "*/\n"),
+        "application/json" => ("{\"padding\": \"", "\"}\n"),
+        _ if mime.starts_with("text/") => ("", ""),
+        _ => return SeededRng::derive(seed, url).bytes(size),
+    };
+    let line = if mime == "application/json" {
+        FILLER_LINE.trim_end()
+    } else {
+        FILLER_LINE
+    };
+
+    if size < prefix.len() + suffix.len() {
+        return line.bytes().cycle().take(size).collect();
+    }
+    let mut body = Vec::with_capacity(size);
+    body.extend_from_slice(prefix.as_bytes());
+    let filler = size - prefix.len() - suffix.len();
+    body.extend(line.bytes().cycle().take(filler));
+    body.extend_from_slice(suffix.as_bytes());
+    body
+}
+
+mod tests;
@@ -0,0 +1,151 @@
+#[cfg(test)]
+mod generate_tests {
+    use crate::generate::{GenerateSpec, build_inventory};
+
+    fn spec(json: &str) -> GenerateSpec {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_build_inventory_from_spec() {
+        let inventory = build_inventory(
+            &spec(
+                r#"{
+                "entryUrl": "https://bench.example/",
+                "resources": [
+                    {"url": "https://bench.example/", "contentType": "text/html; charset=utf-8",
+                     "size": 20000, "ttfbMs": 120, "mbps": 8},
+                    {"url": "https://bench.example/img-{n}.png", "contentType": "image/png",
+                     "size": 5000, "ttfbMs": 40, "durationMs": 250, "count": 3,
+                     "headers": {"cache-control": "max-age=600"}}
+                ]
+            }"#,
+            ),
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            inventory.entry_url.as_deref(),
+            Some("https://bench.example/")
+        );
+        let urls: Vec<&str> = inventory.resources.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://bench.example/",
+                "https://bench.example/img-1.png",
+                "https://bench.example/img-2.png",
+                "https://bench.example/img-3.png",
+            ]
+        );
+
+        let page = &inventory.resources[0];
+        let body = page.raw_body.as_ref().unwrap();
+        assert_eq!(body.len(), 20000);
+        assert!(body.starts_with(b"<!DOCTYPE html>"));
+        assert_eq!(page.content_type_mime.as_deref(), Some("text/html"));
+        assert_eq!(page.ttfb_ms, 120);
+        // 160 kilobits at 8 Mbps
+        assert_eq!(page.duration_ms, Some(20));
+
+        let image = &inventory.resources[1];
+        let headers = image.raw_headers.as_ref().unwrap();
+        assert_eq!(
+            headers.get("content-length").unwrap().as_vec(),
+            vec!["5000"]
+        );
+        assert_eq!(
+            headers.get("cache-control").unwrap().as_vec(),
+            vec!["max-age=600"]
+        );
+        assert_eq!(image.duration_ms, Some(250));
+        assert!((image.mbps.unwrap() - 0.16).abs() < 1e-9);
+        // Binary filler is the same on every run but differs between URLs
+        let again = build_inventory(&spec(
+            r#"{"resources": [{"url": "https://bench.example/img-{n}.png", "size": 5000, "count": 2}]}"#,
+        ), 0)
+        .unwrap();
+        assert_eq!(again.resources[0].raw_body, image.raw_body);
+        assert_ne!(again.resources[1].raw_body, image.raw_body);
+        assert_eq!(inventory.seed, Some(0));
+
+        let reseeded = build_inventory(
+            &spec(r#"{"resources": [{"url": "https://bench.example/img-1.png", "size": 5000}]}"#),
+            42,
+        )
+        .unwrap();
+        assert_ne!(reseeded.resources[0].raw_body, image.raw_body);
+        assert_eq!(reseeded.seed, Some(42));
+    }
+
+    #[test]
+    fn test_tiny_and_invalid_specs() {
+        let inventory = build_inventory(&spec(
+            r#"{"resources": [{"url": "https://bench.example/a.js", "contentType": "application/javascript", "size": 2}]}"#,
+        ), 0)
+        .unwrap();
+        assert_eq!(inventory.resources[0].raw_body.as_ref().unwrap().len(), 2);
+        assert_eq!(inventory.resources[0].duration_ms, Some(0));
+
+        assert!(
+            build_inventory(
+                &spec(
+                    r#"{"resources": [{"url": "https://bench.example/", "size": 1, "mbps": 0}]}"#,
+                ),
+                0
+            )
+            .is_err()
+        );
+        assert!(
+            serde_json::from_str::<GenerateSpec>(
+                r#"{"resources": [{"url": "https://bench.example/", "size": 1, "ttfb": 5}]}"#,
+            )
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generated_inventory_replays_at_spec_size() {
+        use crate::playback::transaction::convert_resources_to_transactions;
+        use crate::recording::proxy::finish_inventory;
+        use crate::traits::RealFileSystem;
+        use std::sync::Arc;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut inventory = build_inventory(&spec(
+            r#"{"resources": [
+                {"url": "https://bench.example/", "contentType": "text/html", "size": 3000},
+                {"url": "https://bench.example/app.js", "contentType": "application/javascript", "size": 4000},
+                {"url": "https://bench.example/data.bin", "size": 70000}
+            ]}"#,
+        ), 0)
+        .unwrap();
+        finish_inventory(
+            &mut inventory,
+            temp_dir.path(),
+            None,
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        let inventory = crate::playback::load_inventory(temp_dir.path(), Arc::new(RealFileSystem))
+            .await
+            .unwrap();
+
+        let transactions = convert_resources_to_transactions(
+            &inventory,
+            temp_dir.path(),
+            Arc::new(RealFileSystem),
+        )
+        .await
+        .unwrap();
+        let sizes: Vec<usize> = transactions
+            .iter()
+            .map(|t| t.chunks.iter().map(|c| c.chunk.len()).sum())
+            .collect();
+        assert_eq!(sizes, vec![3000, 4000, 70000]);
+    }
+}
@@ -0,0 +1,304 @@
+//! HAR import
+//!
+//! Converts a HAR file, as saved by browser devtools or WebPageTest, into an inventory
+//! so the captured session can be replayed with its timings. Each entry becomes a
+//! resource: `wait` is its TTFB, `receive` its transfer duration, DNS and connect its
+//! `connectMs`, and `startedDateTime` its offset into the session. HAR bodies are stored
+//! decoded, so they are compressed again with the recorded Content-Encoding.
+
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose};
+use chrono::DateTime;
+use serde::Deserialize;
+use std::path::PathBuf;
+use tracing::warn;
+
+use crate::lock::InventoryLock;
+use crate::playback::transaction::compress_content;
+use crate::redact::Redactor;
+use crate::types::{
+    ClientMetadata, ContentEncodingType, DeviceType, ErrorKind, HttpHeaders, HttpProtocol,
+    Inventory, Resource,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct Har {
+    pub log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HarLog {
+    #[serde(default)]
+    pub entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarEntry {
+    pub started_date_time: String,
+    pub request: HarRequest,
+    pub response: HarResponse,
+    #[serde(default)]
+    pub timings: HarTimings,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HarRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<HarHeader>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub status_text: String,
+    #[serde(default)]
+    pub http_version: String,
+    #[serde(default)]
+    pub headers: Vec<HarHeader>,
+    #[serde(default)]
+    pub content: HarContent,
+    // Chrome's reason for entries that got no response (e.g. net::ERR_CONNECTION_REFUSED)
+    #[serde(rename = "_error")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HarHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarContent {
+    pub mime_type: Option<String>,
+    pub text: Option<String>,
+    pub encoding: Option<String>,
+}
+
+/// Phases of an entry in milliseconds; -1 (or absent) means not applicable
+#[derive(Debug, Deserialize)]
+pub struct HarTimings {
+    #[serde(default = "not_applicable")]
+    pub dns: f64,
+    #[serde(default = "not_applicable")]
+    pub connect: f64,
+    #[serde(default = "not_applicable")]
+    pub wait: f64,
+    #[serde(default = "not_applicable")]
+    pub receive: f64,
+}
+
+impl Default for HarTimings {
+    fn default() -> Self {
+        Self {
+            dns: -1.0,
+            connect: -1.0,
+            wait: -1.0,
+            receive: -1.0,
+        }
+    }
+}
+
+fn not_applicable() -> f64 {
+    -1.0
+}
+
+/// Round a HAR phase to whole milliseconds, None when not applicable
+fn phase_ms(ms: f64) -> Option<u64> {
+    (ms >= 0.0).then(|| ms.round() as u64)
+}
+
+pub async fn run_import_har(
+    har_path: PathBuf,
+    inventory_dir: PathBuf,
+    device: Option<DeviceType>,
+    force: bool,
+) -> Result<()> {
+    let har = std::fs::read_to_string(&har_path)
+        .with_context(|| format!("Failed to read HAR {:?}", har_path))?;
+    let har: Har = serde_json::from_str(&har)
+        .with_context(|| format!("Failed to parse HAR {:?}", har_path))?;
+
+    let index_path = inventory_dir.join("index.json");
+    if index_path.exists() && !force {
+        anyhow::bail!("{:?} already exists; use --force to replace it", index_path);
+    }
+    let _lock = InventoryLock::acquire(&inventory_dir, "import-har", force)?;
+
+    let mut inventory = build_inventory(&har)?;
+    inventory.device_type = device;
+    crate::recording::proxy::finish_inventory(
+        &mut inventory,
+        &inventory_dir,
+        None,
+        crate::recording::batch_processor::CharsetPolicy::default(),
+        None,
+    )
+    .await?;
+
+    println!(
+        "Imported {} of {} HAR entries into {:?}",
+        inventory.resources.len(),
+        har.log.entries.len(),
+        inventory_dir
+    );
+    Ok(())
+}
+
+/// The inventory a HAR describes, with bodies in `raw_body` as a recording leaves them
+///
+/// Entries for other schemes than http(s), such as `data:` URLs, are skipped. The
+/// first imported entry becomes the entry URL.
+pub fn build_inventory(har: &Har) -> Result<Inventory> {
+    let mut inventory = Inventory::new();
+
+    let mut entries = Vec::new();
+    for entry in &har.log.entries {
+        let url = &entry.request.url;
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            continue;
+        }
+        let started =
+            DateTime::parse_from_rfc3339(&entry.started_date_time).with_context(|| {
+                format!(
+                    "Invalid startedDateTime {:?} of {}",
+                    entry.started_date_time, url
+                )
+            })?;
+        entries.push((started, entry));
+    }
+    // Stable, so entries started in the same millisecond keep their HAR order
+    entries.sort_by_key(|(started, _)| *started);
+
+    let Some(&(session_start, first)) = entries.first() else {
+        return Ok(inventory);
+    };
+    inventory.entry_url = Some(first.request.url.clone());
+
+    let mut missing_bodies = 0;
+    for (started, entry) in entries {
+        let request_start_ms = (started - session_start).num_milliseconds().max(0) as u64;
+        let resource = import_entry(entry, request_start_ms, &mut missing_bodies)?;
+        inventory.resources.push(resource);
+    }
+    if missing_bodies > 0 {
+        warn!(
+            "{} HAR entries have no saved content; they replay with empty bodies",
+            missing_bodies
+        );
+    }
+
+    Ok(inventory)
+}
+
+fn import_entry(
+    entry: &HarEntry,
+    request_start_ms: u64,
+    missing_bodies: &mut usize,
+) -> Result<Resource> {
+    let response = &entry.response;
+    let mut resource = Resource::new(entry.request.method.clone(), entry.request.url.clone());
+    resource.request_start_ms = Some(request_start_ms);
+    resource.request_client = ClientMetadata::from_headers(&header_map(&entry.request.headers));
+    let mut request_headers = HttpHeaders::new();
+    for header in entry
+        .request
+        .headers
+        .iter()
+        .filter(|h| !h.name.starts_with(':'))
+    {
+        request_headers.append(&header.name, header.value.clone());
+    }
+    if !request_headers.is_empty() {
+        resource.request_headers =
+            Some(Redactor::for_request_headers(&[]).redact_headers(&request_headers));
+    }
+
+    let timings = &entry.timings;
+    let connect_ms = phase_ms(timings.dns).unwrap_or(0) + phase_ms(timings.connect).unwrap_or(0);
+    resource.connect_ms = (connect_ms > 0).then_some(connect_ms);
+    resource.ttfb_ms = phase_ms(timings.wait).unwrap_or(0);
+
+    // Entries that never got a response carry status 0
+    if response.status == 0 {
+        let message = response
+            .error
+            .clone()
+            .unwrap_or_else(|| "No response recorded in HAR".to_string());
+        resource.error_kind = Some(ErrorKind::from_message(&message));
+        resource.error_message = Some(message);
+        return Ok(resource);
+    }
+    resource.status_code = Some(response.status);
+    resource.protocol = HttpProtocol::from_name(&response.http_version);
+    let canonical = hyper::StatusCode::from_u16(response.status)
+        .ok()
+        .and_then(|status| status.canonical_reason());
+    if !response.status_text.is_empty() && Some(response.status_text.as_str()) != canonical {
+        resource.reason_phrase = Some(response.status_text.clone());
+    }
+
+    // HTTP/2 pseudo-headers are not response headers
+    let mut headers = HttpHeaders::new();
+    for header in response.headers.iter().filter(|h| !h.name.starts_with(':')) {
+        headers.append(&header.name, header.value.clone());
+    }
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.as_vec().first().map(|v| v.to_string()))
+    };
+    resource.content_type_mime = header("content-type")
+        .or_else(|| response.content.mime_type.clone())
+        .map(|ct| ct.split(';').next().unwrap_or_default().trim().to_string())
+        .filter(|mime| !mime.is_empty());
+    resource.content_encoding = header("content-encoding")
+        .and_then(|encoding| encoding.parse::<ContentEncodingType>().ok());
+
+    let body = match (&response.content.text, response.content.encoding.as_deref()) {
+        (Some(text), Some("base64")) => general_purpose::STANDARD
+            .decode(text)
+            .with_context(|| format!("Invalid base64 content of {}", entry.request.url))?,
+        (Some(text), _) => text.clone().into_bytes(),
+        (None, _) => {
+            *missing_bodies += 1;
+            Vec::new()
+        }
+    };
+    let raw_body = match &resource.content_encoding {
+        Some(encoding) => compress_content(&body, encoding)?,
+        None => body,
+    };
+
+    let duration_ms = phase_ms(timings.receive).unwrap_or(0);
+    resource.duration_ms = Some(duration_ms);
+    if duration_ms > 0 {
+        let megabits = raw_body.len() as f64 * 8.0 / 1_000_000.0;
+        resource.mbps = Some(megabits / (duration_ms as f64 / 1000.0));
+    }
+    resource.raw_headers = Some(headers);
+    resource.raw_body = Some(raw_body);
+    Ok(resource)
+}
+
+fn header_map(headers: &[HarHeader]) -> hyper::HeaderMap {
+    let mut map = hyper::HeaderMap::new();
+    for header in headers {
+        let (Ok(name), Ok(value)) = (
+            hyper::header::HeaderName::from_bytes(header.name.as_bytes()),
+            hyper::header::HeaderValue::from_str(&header.value),
+        ) else {
+            continue;
+        };
+        map.append(name, value);
+    }
+    map
+}
+
+mod tests;
@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod har_tests {
+    use crate::har::{Har, build_inventory};
+    use crate::types::ContentEncodingType;
+    use base64::{Engine as _, engine::general_purpose};
+
+    fn har(json: &str) -> Har {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_build_inventory_from_har() {
+        let png = general_purpose::STANDARD.encode([0x89, b'P', b'N', b'G']);
+        let inventory = build_inventory(&har(&format!(
+            r#"{{"log": {{"version": "1.2", "entries": [
+                {{"startedDateTime": "2024-05-01T10:00:00.350+02:00",
+                  "request": {{"method": "GET", "url": "https://example.com/logo.png", "headers": []}},
+                  "response": {{"status": 200, "statusText": "OK",
+                    "headers": [{{"name": "content-type", "value": "image/png"}}],
+                    "content": {{"size": 4, "mimeType": "image/png", "text": "{png}", "encoding": "base64"}}}},
+                  "timings": {{"blocked": 1, "dns": -1, "connect": -1, "send": 0, "wait": 40.4, "receive": 9.6}}}},
+                {{"startedDateTime": "2024-05-01T08:00:00.100Z",
+                  "request": {{"method": "GET", "url": "https://example.com/",
+                    "headers": [{{"name": "User-Agent", "value": "Mozilla/5.0 (iPhone)"}},
+                                {{"name": "Accept-Encoding", "value": "gzip, br"}},
+                                {{"name": "Cookie", "value": "session=abc"}}]}},
+                  "response": {{"status": 200, "statusText": "",
+                    "headers": [{{"name": ":status", "value": "200"}},
+                                {{"name": "Content-Type", "value": "text/html; charset=utf-8"}},
+                                {{"name": "Content-Encoding", "value": "gzip"}},
+                                {{"name": "Set-Cookie", "value": "a=1"}},
+                                {{"name": "Set-Cookie", "value": "b=2"}}],
+                    "content": {{"size": 20, "mimeType": "text/html", "text": "<html>hello</html>"}}}},
+                  "timings": {{"dns": 12, "connect": 30, "ssl": 20, "send": 0, "wait": 120, "receive": 25}}}},
+                {{"startedDateTime": "2024-05-01T08:00:00.200Z",
+                  "request": {{"method": "GET", "url": "data:image/gif;base64,R0lGOD", "headers": []}},
+                  "response": {{"status": 200, "headers": [], "content": {{}}}}}},
+                {{"startedDateTime": "2024-05-01T08:00:00.400Z",
+                  "request": {{"method": "GET", "url": "https://down.example/", "headers": []}},
+                  "response": {{"status": 0, "statusText": "", "headers": [], "content": {{}},
+                    "_error": "net::ERR_NAME_NOT_RESOLVED"}},
+                  "timings": {{"wait": -1, "receive": -1}}}}
+            ]}}}}"#
+        )))
+        .unwrap();
+
+        // Entries are ordered by start time across time zones; data: URLs are skipped
+        assert_eq!(inventory.entry_url.as_deref(), Some("https://example.com/"));
+        let urls: Vec<&str> = inventory.resources.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/",
+                "https://example.com/logo.png",
+                "https://down.example/"
+            ]
+        );
+
+        let page = &inventory.resources[0];
+        assert_eq!(page.request_start_ms, Some(0));
+        assert_eq!(page.ttfb_ms, 120);
+        assert_eq!(page.connect_ms, Some(42));
+        assert_eq!(page.duration_ms, Some(25));
+        assert_eq!(page.content_type_mime.as_deref(), Some("text/html"));
+        assert_eq!(page.content_encoding, Some(ContentEncodingType::Gzip));
+        // The decoded HAR text is compressed again to match Content-Encoding
+        assert!(page.raw_body.as_ref().unwrap().starts_with(&[0x1f, 0x8b]));
+        let headers = page.raw_headers.as_ref().unwrap();
+        assert!(!headers.contains(":status"));
+        assert_eq!(headers.get("set-cookie").unwrap().as_vec(), ["a=1", "b=2"]);
+        let client = page.request_client.as_ref().unwrap();
+        assert_eq!(client.accept_encoding.as_deref(), Some("gzip, br"));
+        // Request headers are kept in full, except for secrets
+        let request_headers = page.request_headers.as_ref().unwrap();
+        let names: Vec<&str> = request_headers.names().collect();
+        assert_eq!(names, ["User-Agent", "Accept-Encoding", "Cookie"]);
+        assert_eq!(
+            request_headers.get("cookie").unwrap().first(),
+            "session=REDACTED"
+        );
+
+        let logo = &inventory.resources[1];
+        assert_eq!(logo.request_start_ms, Some(250));
+        assert_eq!((logo.ttfb_ms, logo.connect_ms), (40, None));
+        assert_eq!(logo.duration_ms, Some(10));
+        assert_eq!(
+            logo.raw_body.as_deref(),
+            Some(&[0x89, b'P', b'N', b'G'][..])
+        );
+        assert!(logo.request_client.is_none());
+        assert!(logo.request_headers.is_none());
+
+        let failed = &inventory.resources[2];
+        assert_eq!(failed.status_code, None);
+        assert_eq!(
+            failed.error_message.as_deref(),
+            Some("net::ERR_NAME_NOT_RESOLVED")
+        );
+        assert_eq!(failed.error_kind, Some(crate::types::ErrorKind::DnsFailure));
+    }
+}
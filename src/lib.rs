@@ -0,0 +1,38 @@
+//! HTTP playback proxy as a library
+//!
+//! The `http-playback-proxy` binary is a command line over this crate. Test harnesses
+//! can run the same recording and playback proxies in-process with [`ProxyServer`],
+//! which reports the port it bound and stops on request, or call [`run_recording_mode`]
+//! and [`run_playback_mode`] directly. Inventories are read and written as [`Inventory`]
+//! with one [`Resource`] per recorded response; playback serves them as [`Transaction`]s.
+
+pub mod beautify;
+pub mod browser;
+pub mod check;
+pub mod control;
+pub mod exit_status;
+pub mod experiment;
+pub mod generate;
+pub mod har;
+pub mod lock;
+pub mod manifest;
+pub mod minify;
+pub mod mock_origin;
+pub mod multipart;
+pub mod playback;
+pub mod query;
+pub mod recording;
+pub mod redact;
+pub mod redirects;
+pub mod server;
+pub mod signal_sender;
+pub mod traits;
+pub mod types;
+pub mod url_pattern;
+pub mod utils;
+pub mod validate;
+
+pub use playback::{PlaybackOptions, run_playback_mode};
+pub use recording::{RecordingOptions, run_recording_mode};
+pub use server::{ProxyServer, RunningProxy, ShutdownHandle};
+pub use types::{DeviceType, Inventory, Resource, Transaction};
@@ -0,0 +1,92 @@
+//! Advisory lock on an inventory directory
+//!
+//! Commands that write `index.json` hold `.hpp.lock` in the inventory directory for as
+//! long as they run, so a second writer fails up front instead of interleaving with the
+//! first one. The lock is advisory: `--force` takes it over, e.g. after a crash left a
+//! stale lock behind.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+pub const LOCK_FILE_NAME: &str = ".hpp.lock";
+
+/// Who holds the lock, written into the lock file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockOwner {
+    pub pid: u32,
+    pub command: String,
+    pub started_at: String,
+}
+
+/// Held lock; the lock file is removed when dropped
+#[derive(Debug)]
+pub struct InventoryLock {
+    path: PathBuf,
+}
+
+impl InventoryLock {
+    /// Take the lock for `command`, or fail with the current holder unless `force` is set
+    pub fn acquire(inventory_dir: &Path, command: &str, force: bool) -> Result<Self> {
+        std::fs::create_dir_all(inventory_dir)
+            .with_context(|| format!("Failed to create inventory directory {:?}", inventory_dir))?;
+        let path = inventory_dir.join(LOCK_FILE_NAME);
+
+        let owner = LockOwner {
+            pid: std::process::id(),
+            command: command.to_string(),
+            started_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let content = serde_json::to_string(&owner)?;
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => file.write_all(content.as_bytes())?,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let holder = Self::describe_holder(&path);
+                if !force {
+                    anyhow::bail!(
+                        "Inventory {:?} is locked by {}. If no other process is using it, \
+                         remove {:?} or pass --force",
+                        inventory_dir,
+                        holder,
+                        path
+                    );
+                }
+                warn!("Taking over inventory lock held by {}", holder);
+                std::fs::write(&path, content)?;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create lock file {:?}", path));
+            }
+        }
+
+        Ok(Self { path })
+    }
+
+    fn describe_holder(path: &Path) -> String {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<LockOwner>(&content).ok())
+            .map(|owner| {
+                format!(
+                    "'{}' (pid {}, since {})",
+                    owner.command, owner.pid, owner.started_at
+                )
+            })
+            .unwrap_or_else(|| "another process".to_string())
+    }
+}
+
+impl Drop for InventoryLock {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!("Failed to remove lock file {:?}: {}", self.path, e);
+        }
+    }
+}
+
+mod tests;
@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod lock_tests {
+    use crate::lock::{InventoryLock, LOCK_FILE_NAME};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_second_writer_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().join("inventory");
+
+        let lock = InventoryLock::acquire(&inventory_dir, "recording", false).unwrap();
+        assert!(inventory_dir.join(LOCK_FILE_NAME).exists());
+
+        let err = InventoryLock::acquire(&inventory_dir, "recording", false).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("'recording'"));
+        assert!(message.contains("--force"));
+
+        drop(lock);
+        assert!(!inventory_dir.join(LOCK_FILE_NAME).exists());
+        InventoryLock::acquire(&inventory_dir, "recording", false).unwrap();
+    }
+
+    #[test]
+    fn test_force_takes_over_stale_lock() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join(LOCK_FILE_NAME), "garbage").unwrap();
+
+        assert!(InventoryLock::acquire(temp_dir.path(), "recording", false).is_err());
+
+        let lock = InventoryLock::acquire(temp_dir.path(), "recording", true).unwrap();
+        let content = std::fs::read_to_string(temp_dir.path().join(LOCK_FILE_NAME)).unwrap();
+        assert!(content.contains("\"command\":\"recording\""));
+        drop(lock);
+    }
+}
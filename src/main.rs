@@ -1,36 +1,122 @@
 use clap::Parser;
+use std::process::ExitCode;
+
+use http_playback_proxy::{
+    check, exit_status, experiment, generate, har, mock_origin, playback, recording, redact,
+    redirects, signal_sender, validate,
+};
 
-mod beautify;
 mod cli;
-mod playback;
-mod recording;
-mod signal_sender;
-mod traits;
-mod types;
-mod utils;
 
 use cli::{Cli, Commands};
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
+async fn main() -> ExitCode {
+    // Write logs from a background thread so playback pacing never waits on stdout
+    let (log_writer, _log_guard) = tracing_appender::non_blocking(std::io::stdout());
+    // RUST_LOG narrows or widens the output; without it, INFO and above as before
+    let filter = tracing_subscriber::EnvFilter::builder()
+        .with_default_directive(tracing_subscriber::filter::LevelFilter::INFO.into())
+        .from_env_lossy();
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(log_writer)
+        .init();
 
     let cli = Cli::parse();
 
+    // Failed quality gates and saves get their own codes, see exit_status
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit_status::code_of(&e))
+        }
+    }
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
     match cli.command {
         Commands::Recording {
             entry_url,
             port,
             device,
             inventory,
+            options,
+        } => {
+            recording::run_recording_mode(entry_url, port, device, inventory, options).await?;
+        }
+        Commands::Playback {
+            port,
+            inventory,
+            options,
+        } => {
+            playback::run_playback_mode(port, inventory, options).await?;
+        }
+        Commands::Recover {
+            inventory,
+            inline_base64_max_bytes,
+            charset,
+            force,
+        } => {
+            recording::run_recover_mode(inventory, inline_base64_max_bytes, charset, force).await?;
+        }
+        Commands::Check { inventory, options } => {
+            check::run_check(inventory, options).await?;
+        }
+        Commands::Experiment {
+            port,
+            inventory,
+            overrides,
+            report,
+            options,
+        } => {
+            experiment::run_experiment(port, inventory, overrides, report, options).await?;
+        }
+        Commands::Generate {
+            spec,
+            inventory,
+            seed,
+            force,
+        } => {
+            generate::run_generate(spec, inventory, seed, force).await?;
+        }
+        Commands::ImportHar {
+            har,
+            inventory,
+            device,
+            force,
+        } => {
+            har::run_import_har(har, inventory, device, force).await?;
+        }
+        Commands::MatchTest {
+            inventory,
+            url,
+            method,
+            query,
+            strategies,
+        } => {
+            playback::run_match_test(inventory, url, method, query, strategies).await?;
+        }
+        Commands::Redact {
+            inventory,
+            rules,
+            output,
+            force,
         } => {
-            recording::run_recording_mode(entry_url, port, device, inventory).await?;
+            redact::run_redact(inventory, rules, output, force).await?;
+        }
+        Commands::Validate { inventory } => {
+            validate::run_validate(inventory).await?;
+        }
+        Commands::Redirects { inventory, json } => {
+            redirects::run_redirects(inventory, json).await?;
         }
-        Commands::Playback { port, inventory } => {
-            playback::run_playback_mode(port, inventory).await?;
+        Commands::MockOrigin { port, seed } => {
+            mock_origin::run_mock_origin(port, seed).await?;
         }
         Commands::Signal { pid, kind } => {
-            let signal_kind = signal_sender::SignalKind::from_str(&kind)?;
+            let signal_kind = kind.parse::<signal_sender::SignalKind>()?;
             signal_sender::send_signal(pid, signal_kind)?;
             println!("Signal sent successfully to process {}", pid);
         }
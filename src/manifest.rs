@@ -0,0 +1,176 @@
+//! Manifests describing how a run can be reproduced
+//!
+//! Every recording and playback run ends by writing a manifest next to the inventory:
+//! the version of the binary, its command line and the effective options, the seed a
+//! generated inventory was drawn from, a hash of the inventory files, and summary
+//! metrics of the run. Kept as a CI artifact, it says exactly which build replayed which
+//! inventory how, and whether two runs can be compared. A manifest that cannot be
+//! written is reported without failing the run.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::types::Inventory;
+
+/// Directories of an inventory whose files are part of its hash, besides `index.json`
+const CONTENT_DIRS: [&str; 3] = ["contents", "originals", "uploads"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunMode {
+    Recording,
+    Playback,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunManifest {
+    pub mode: RunMode,
+    pub version: String,
+    /// Command line of the process, program name first
+    pub arguments: Vec<String>,
+    pub port: u16,
+    /// Options in effect, defaults included
+    pub config: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    pub started_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inventory: Option<InventoryHash>,
+    pub summary: serde_json::Value,
+}
+
+/// Content hash of an inventory directory
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryHash {
+    pub path: PathBuf,
+    /// SHA-1 over the relative path and bytes of every file, in path order
+    pub sha1: String,
+    pub files: usize,
+}
+
+/// What a recording captured
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingSummary {
+    pub resources: usize,
+    /// Requests that got no response from the origin
+    pub failed: usize,
+    pub hosts: usize,
+    pub opaque_tunnels: usize,
+}
+
+impl RecordingSummary {
+    pub fn of(inventory: &Inventory) -> Self {
+        let hosts: HashSet<String> = inventory
+            .resources
+            .iter()
+            .filter_map(|resource| resource.url.parse::<hyper::Uri>().ok())
+            .filter_map(|uri| uri.host().map(str::to_ascii_lowercase))
+            .collect();
+        Self {
+            resources: inventory.resources.len(),
+            failed: inventory
+                .resources
+                .iter()
+                .filter(|resource| resource.error_kind.is_some())
+                .count(),
+            hosts: hosts.len(),
+            opaque_tunnels: inventory.opaque_tunnels.len(),
+        }
+    }
+}
+
+impl RunManifest {
+    /// Start describing a run with these options
+    pub fn start(mode: RunMode, port: u16, config: &impl Serialize) -> Result<Self> {
+        Ok(Self {
+            mode,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            arguments: std::env::args().collect(),
+            port,
+            config: serde_json::to_value(config)?,
+            seed: None,
+            started_at: Utc::now(),
+            finished_at: None,
+            inventory: None,
+            summary: serde_json::Value::Null,
+        })
+    }
+
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Hash the inventory, note the summary and the end of the run, and write the manifest
+    pub async fn finish(
+        mut self,
+        path: &Path,
+        inventory_dir: &Path,
+        summary: &impl Serialize,
+    ) -> Result<()> {
+        self.finished_at = Some(Utc::now());
+        self.summary = serde_json::to_value(summary)?;
+        let dir = inventory_dir.to_path_buf();
+        self.inventory = Some(tokio::task::spawn_blocking(move || hash_inventory(&dir)).await??);
+        tokio::fs::write(path, serde_json::to_string_pretty(&self)?).await?;
+        Ok(())
+    }
+
+    /// `finish`, reporting failure instead of returning it
+    pub async fn finish_or_warn(self, path: &Path, inventory_dir: &Path, summary: &impl Serialize) {
+        match self.finish(path, inventory_dir, summary).await {
+            Ok(()) => println!("Run manifest written to {:?}", path),
+            Err(e) => warn!("Failed to write run manifest {:?}: {}", path, e),
+        }
+    }
+}
+
+/// Hash `index.json` and the files under the inventory's content directories
+pub fn hash_inventory(inventory_dir: &Path) -> Result<InventoryHash> {
+    let mut files = vec![PathBuf::from("index.json")];
+    let mut dirs: Vec<PathBuf> = CONTENT_DIRS.iter().map(PathBuf::from).collect();
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(inventory_dir.join(&dir)) else {
+            continue;
+        };
+        for entry in entries {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                dirs.push(relative);
+            } else {
+                files.push(relative);
+            }
+        }
+    }
+    files.sort();
+
+    let mut hasher = Sha1::new();
+    for file in &files {
+        // Separators are normalized so the hash is the same on every platform
+        let name = file.to_string_lossy().replace('\\', "/");
+        hasher.update((name.len() as u64).to_le_bytes());
+        hasher.update(name.as_bytes());
+        // Streamed, as content files can be larger than memory
+        let mut content = std::fs::File::open(inventory_dir.join(file))?;
+        hasher.update(content.metadata()?.len().to_le_bytes());
+        std::io::copy(&mut content, &mut hasher)?;
+    }
+    Ok(InventoryHash {
+        path: inventory_dir.to_path_buf(),
+        sha1: hex::encode(hasher.finalize()),
+        files: files.len(),
+    })
+}
+
+mod tests;
@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod manifest_tests {
+    use crate::manifest::{RecordingSummary, RunManifest, RunMode, hash_inventory};
+    use crate::playback::PlaybackOptions;
+    use crate::playback::served_log::ServedLog;
+    use crate::types::{ErrorKind, Inventory, Resource};
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_inventory_hash_covers_index_and_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir.join("contents/get/https/example.com")).unwrap();
+        std::fs::write(dir.join("index.json"), r#"{"resources":[]}"#).unwrap();
+        std::fs::write(
+            dir.join("contents/get/https/example.com/index.html"),
+            "<p>a</p>",
+        )
+        .unwrap();
+        // Not part of the inventory
+        std::fs::write(dir.join("manifest.json"), "{}").unwrap();
+
+        let first = hash_inventory(dir).unwrap();
+        assert_eq!(first.files, 2);
+        assert_eq!(first, hash_inventory(dir).unwrap());
+
+        std::fs::write(dir.join("manifest.json"), r#"{"mode":"playback"}"#).unwrap();
+        assert_eq!(first.sha1, hash_inventory(dir).unwrap().sha1);
+
+        std::fs::write(
+            dir.join("contents/get/https/example.com/index.html"),
+            "<p>b</p>",
+        )
+        .unwrap();
+        assert_ne!(first.sha1, hash_inventory(dir).unwrap().sha1);
+    }
+
+    #[tokio::test]
+    async fn test_playback_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("index.json"), r#"{"resources":[]}"#).unwrap();
+
+        let options = PlaybackOptions {
+            max_bandwidth: Some(20.0),
+            timing_headers: true,
+            ..Default::default()
+        };
+        let manifest = RunManifest::start(RunMode::Playback, 8080, &options)
+            .unwrap()
+            .with_seed(Some(7));
+
+        // Only the totals are kept without a journal
+        let served = ServedLog::totals_only();
+        let received = Instant::now();
+        drop(served.begin("GET", "https://example.com/", 200, 100, received));
+        drop(served.begin("GET", "https://example.com/app.js", 200, 50, received));
+        assert!(served.entries().is_empty());
+        let summary = served.summary();
+        assert_eq!(summary.requests, 2);
+        assert_eq!(summary.bytes, 150);
+
+        let path = dir.join("playback-manifest.json");
+        manifest.finish(&path, dir, &summary).await.unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["mode"], "playback");
+        assert_eq!(written["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(written["port"], 8080);
+        assert_eq!(written["seed"], 7);
+        assert_eq!(written["config"]["maxBandwidth"], 20.0);
+        assert_eq!(written["config"]["http2"], "recorded");
+        assert_eq!(written["config"]["timingHeaders"], true);
+        assert!(written["config"].get("hooks").is_none());
+        assert_eq!(written["inventory"]["files"], 1);
+        assert_eq!(written["summary"]["requests"], 2);
+        assert!(written["finishedAt"].is_string());
+    }
+
+    #[test]
+    fn test_recording_summary() {
+        let mut inventory = Inventory::new();
+        for url in [
+            "https://example.com/",
+            "https://EXAMPLE.com/app.js",
+            "https://cdn.example.com/font.woff2",
+        ] {
+            inventory
+                .resources
+                .push(Resource::new("GET".to_string(), url.to_string()));
+        }
+        inventory.resources[2].error_kind = Some(ErrorKind::DnsFailure);
+
+        let summary = RecordingSummary::of(&inventory);
+        assert_eq!(
+            summary,
+            RecordingSummary {
+                resources: 3,
+                failed: 1,
+                hosts: 2,
+                opaque_tunnels: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,483 @@
+//! Minify JavaScript, CSS and HTML again for playback
+//!
+//! Recording beautifies minified bodies so they can be read and edited, and marks them
+//! `minify` so playback serves them about as small as they were sent. Each body is
+//! parsed by the same parser that beautified it and printed back compactly, so template
+//! literals, strings, regular expressions and `<pre>` blocks come through unchanged. A
+//! body that does not parse is an error, and the caller serves it as it is.
+
+use anyhow::Result;
+use html5ever::tendril::{StrTendril, TendrilSink};
+use markup5ever_rcdom::{Handle, NodeData, RcDom, SerializableHandle};
+use std::borrow::Cow;
+
+/// Elements whose whitespace is rendered as written
+const PREFORMATTED: &[&str] = &["pre", "textarea", "listing", "plaintext"];
+
+/// Elements that are not laid out inline, so whitespace-only text between them and
+/// at their start or end renders nothing
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "base",
+    "blockquote",
+    "body",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "link",
+    "main",
+    "meta",
+    "nav",
+    "noscript",
+    "ol",
+    "p",
+    "pre",
+    "script",
+    "section",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "ul",
+];
+
+/// Minify JavaScript using swc
+pub fn minify_javascript(input: &str) -> Result<String> {
+    emit_javascript(input, false)
+}
+
+/// Parse JavaScript as a module or a script and print it without whitespace or
+/// comments; `inline_script` escapes `</script` for code inside an HTML document
+fn emit_javascript(input: &str, inline_script: bool) -> Result<String> {
+    use swc_common::{FileName, GLOBALS, SourceMap, sync::Lrc};
+    use swc_ecma_codegen::{Config, Emitter, text_writer::JsWriter};
+    use swc_ecma_parser::{EsSyntax, Parser, StringInput, Syntax, lexer::Lexer};
+
+    use bytes_str::BytesStr;
+
+    GLOBALS.set(&Default::default(), || {
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(
+            FileName::Custom("input.js".into()).into(),
+            BytesStr::from(input.to_string()),
+        );
+        let lexer = Lexer::new(
+            Syntax::Es(EsSyntax::default()),
+            Default::default(),
+            StringInput::from(&*fm),
+            None,
+        );
+        let mut parser = Parser::new_from(lexer);
+        let program = parser
+            .parse_program()
+            .map_err(|e| anyhow::anyhow!("Failed to parse JavaScript: {:?}", e))?;
+
+        let mut buf = Vec::new();
+        let writer = JsWriter::new(cm.clone(), "\n", &mut buf, None);
+        let mut emitter = Emitter {
+            cfg: Config::default()
+                .with_minify(true)
+                .with_inline_script(inline_script),
+            comments: None,
+            cm: cm.clone(),
+            wr: writer,
+        };
+        emitter
+            .emit_program(&program)
+            .map_err(|e| anyhow::anyhow!("Failed to emit JavaScript: {:?}", e))?;
+
+        Ok(String::from_utf8(buf)?)
+    })
+}
+
+/// Swap quoted strings that lightningcss would not print back as written for
+/// placeholders; returns the CSS to parse and the original literals, in order
+///
+/// lightningcss prints a font family name as identifiers where it can, so
+/// `"Open  Sans"` comes out unquoted and its whitespace collapses when parsed again.
+/// Strings with leading, trailing or repeated spaces are shielded; comments are skipped.
+pub(crate) fn shield_css_strings(input: &str) -> (Cow<'_, str>, Vec<&str>) {
+    let mut strings = Vec::new();
+    let mut shielded = String::new();
+    let mut copied = 0;
+    let mut chars = input.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '/' if chars.peek().is_some_and(|&(_, next)| next == '*') => {
+                let Some(len) = input[start + 2..].find("*/") else {
+                    break;
+                };
+                let end = start + 2 + len + 2;
+                while chars.peek().is_some_and(|&(i, _)| i < end) {
+                    chars.next();
+                }
+            }
+            '"' | '\'' => {
+                let mut end = input.len();
+                while let Some((i, next)) = chars.next() {
+                    if next == '\\' {
+                        chars.next();
+                    } else if next == c || next == '\n' {
+                        end = i + next.len_utf8();
+                        break;
+                    }
+                }
+                let literal = &input[start..end];
+                let value = literal[1..].strip_suffix(c).unwrap_or(&literal[1..]);
+                if value.starts_with(char::is_whitespace)
+                    || value.ends_with(char::is_whitespace)
+                    || value.contains("  ")
+                {
+                    shielded.push_str(&input[copied..start]);
+                    shielded.push_str(&format!("\"{}\"", css_placeholder(strings.len())));
+                    strings.push(literal);
+                    copied = end;
+                }
+            }
+            _ => {}
+        }
+    }
+    if strings.is_empty() {
+        return (Cow::Borrowed(input), strings);
+    }
+    shielded.push_str(&input[copied..]);
+    (Cow::Owned(shielded), strings)
+}
+
+/// Put the literals `shield_css_strings` took out back into printed CSS, whether the
+/// placeholder was printed quoted or as an identifier
+pub(crate) fn unshield_css_strings(mut css: String, strings: &[&str]) -> String {
+    for (i, literal) in strings.iter().enumerate() {
+        let placeholder = css_placeholder(i);
+        css = css
+            .replace(&format!("\"{}\"", placeholder), literal)
+            .replace(&placeholder, literal);
+    }
+    css
+}
+
+fn css_placeholder(index: usize) -> String {
+    format!("__hpp_string_{}__", index)
+}
+
+/// Minify CSS using lightningcss
+/// Note: Preserves @charset declaration as it's removed during parsing
+pub fn minify_css(input: &str) -> Result<String> {
+    use lightningcss::printer::PrinterOptions;
+    use lightningcss::stylesheet::{MinifyOptions, ParserOptions, StyleSheet};
+
+    // Extract @charset declaration if present (must be first per CSS spec); minified
+    // input has the rules on the same line
+    let charset_line = input
+        .trim_start()
+        .starts_with("@charset")
+        .then(|| input.trim_start().split_inclusive(';').next())
+        .flatten();
+
+    let (shielded, strings) = shield_css_strings(input);
+    let mut sheet = StyleSheet::parse(&shielded, ParserOptions::default())
+        .map_err(|e| anyhow::anyhow!("Failed to parse CSS: {:?}", e))?;
+    sheet
+        .minify(MinifyOptions::default())
+        .map_err(|e| anyhow::anyhow!("Failed to minify CSS: {:?}", e))?;
+    let out = sheet
+        .to_css(PrinterOptions {
+            minify: true,
+            ..Default::default()
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to print CSS: {:?}", e))?;
+    let code = unshield_css_strings(out.code, &strings);
+
+    // Re-add @charset declaration at the beginning if it existed
+    if let Some(charset) = charset_line {
+        Ok(format!("{}{}", charset, code))
+    } else {
+        Ok(code)
+    }
+}
+
+/// Minify HTML using html5ever: whitespace is collapsed outside preformatted
+/// elements, comments are dropped and inline scripts and styles are minified
+pub fn minify_html(input: &str) -> Result<String> {
+    use html5ever::parse_document;
+    use html5ever::serialize::{SerializeOpts, serialize};
+
+    let dom: RcDom = parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut input.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Failed to parse HTML: {:?}", e))?;
+    compact_html(&dom.document, false);
+
+    let mut out = Vec::new();
+    let document: SerializableHandle = dom.document.clone().into();
+    serialize(&mut out, &document, SerializeOpts::default())
+        .map_err(|e| anyhow::anyhow!("Failed to serialize HTML: {:?}", e))?;
+    Ok(String::from_utf8(out)?)
+}
+
+fn element_name(handle: &Handle) -> Option<String> {
+    match &handle.data {
+        NodeData::Element { name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+fn attribute(handle: &Handle, attribute: &str) -> Option<String> {
+    match &handle.data {
+        NodeData::Element { attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|a| &*a.name.local == attribute)
+            .map(|a| a.value.to_string()),
+        _ => None,
+    }
+}
+
+/// Whether `handle` is absent or an element laid out as a block
+fn is_block(handle: Option<&Handle>) -> bool {
+    handle.is_none_or(|handle| {
+        element_name(handle).is_some_and(|name| BLOCKS.contains(&name.as_str()))
+    })
+}
+
+fn is_whitespace(ch: char) -> bool {
+    matches!(ch, ' ' | '\t' | '\n' | '\r' | '\x0C')
+}
+
+/// Collapse each run of whitespace to a single space
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut prev_was_space = false;
+    for ch in text.chars() {
+        if is_whitespace(ch) {
+            if !prev_was_space {
+                out.push(' ');
+            }
+            prev_was_space = true;
+        } else {
+            out.push(ch);
+            prev_was_space = false;
+        }
+    }
+    out
+}
+
+/// The minified contents of a `<script>` or `<style>`, or None to keep them as written
+fn minify_raw_text(element: &Handle, name: &str, text: &str) -> Option<String> {
+    let kind = attribute(element, "type").map(|t| t.trim().to_ascii_lowercase());
+    match (name, kind.as_deref()) {
+        ("script", None | Some("" | "module" | "text/javascript" | "application/javascript")) => {
+            emit_javascript(text, true).ok()
+        }
+        ("style", None | Some("" | "text/css")) => minify_css(text).ok(),
+        _ => None,
+    }
+}
+
+/// Minify the children of `handle` in place
+fn compact_html(handle: &Handle, preformatted: bool) {
+    let name = element_name(handle);
+    let starts_preformatted = name
+        .as_deref()
+        .is_some_and(|name| PREFORMATTED.contains(&name));
+    let preformatted = preformatted || starts_preformatted;
+
+    // The serializer only writes children, so a template's contents become its children
+    let template = match &handle.data {
+        NodeData::Element {
+            template_contents, ..
+        } => template_contents.borrow_mut().take(),
+        _ => None,
+    };
+    if let Some(contents) = template {
+        let fragment = contents.children.take();
+        handle.children.borrow_mut().extend(fragment);
+    }
+
+    // Comments render nothing; conditional comments are kept for old IE. Dropping one
+    // leaves the texts around it side by side, as parsing the page again would join them
+    let mut children: Vec<Handle> = Vec::new();
+    for child in handle.children.take() {
+        match (&child.data, children.last().map(|last| &last.data)) {
+            (NodeData::Comment { contents }, _) if !contents.trim_start().starts_with("[if") => {}
+            (NodeData::Text { contents }, Some(NodeData::Text { contents: last })) => {
+                last.borrow_mut().push_tendril(&contents.borrow())
+            }
+            _ => children.push(child),
+        }
+    }
+
+    let mut kept = Vec::with_capacity(children.len());
+    for (index, child) in children.iter().enumerate() {
+        let NodeData::Text { contents } = &child.data else {
+            compact_html(child, preformatted);
+            kept.push(child.clone());
+            continue;
+        };
+        let text = contents.borrow().to_string();
+        let compacted = match name.as_deref() {
+            Some(raw @ ("script" | "style")) => minify_raw_text(handle, raw, &text),
+            // The parser drops a newline right after the start tag, so a newline that
+            // starts the text is written twice to survive parsing again
+            _ if preformatted => (starts_preformatted && index == 0 && text.starts_with('\n'))
+                .then(|| format!("\n{}", text)),
+            _ if text.chars().all(is_whitespace)
+                && is_block(Some(handle))
+                && is_block(index.checked_sub(1).map(|i| &children[i]))
+                && is_block(children.get(index + 1)) =>
+            {
+                continue;
+            }
+            _ => Some(collapse_whitespace(&text)),
+        };
+        if let Some(compacted) = compacted {
+            *contents.borrow_mut() = StrTendril::from_slice(&compacted);
+        }
+        kept.push(child.clone());
+    }
+
+    // An empty head is implied by the parser and need not be written
+    if name.as_deref() == Some("html") {
+        kept.retain(|child| {
+            !(element_name(child).as_deref() == Some("head")
+                && child.children.borrow().is_empty()
+                && has_no_attributes(child))
+        });
+    }
+    *handle.children.borrow_mut() = kept;
+}
+
+fn has_no_attributes(handle: &Handle) -> bool {
+    match &handle.data {
+        NodeData::Element { attrs, .. } => attrs.borrow().is_empty(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beautify::{format_css, format_javascript};
+
+    /// The text of the first `tag` element in `html` as a browser would parse it
+    fn text_of(html: &str, tag: &str) -> String {
+        fn find(handle: &Handle, tag: &str) -> Option<Handle> {
+            if element_name(handle).as_deref() == Some(tag) {
+                return Some(handle.clone());
+            }
+            handle
+                .children
+                .borrow()
+                .iter()
+                .find_map(|child| find(child, tag))
+        }
+        fn text(handle: &Handle, out: &mut String) {
+            if let NodeData::Text { contents } = &handle.data {
+                out.push_str(&contents.borrow());
+            }
+            for child in handle.children.borrow().iter() {
+                text(child, out);
+            }
+        }
+        let dom: RcDom = html5ever::parse_document(RcDom::default(), Default::default())
+            .from_utf8()
+            .read_from(&mut html.as_bytes())
+            .unwrap();
+        let mut out = String::new();
+        text(&find(&dom.document, tag).unwrap(), &mut out);
+        out
+    }
+
+    // Minified output is printed from the syntax tree alone, so two sources that
+    // minify to the same text parse to the same program
+
+    #[test]
+    fn test_minify_javascript_is_equivalent() {
+        let sources = [
+            "const greeting = `Hello,\n    ${name}!  // not a comment`;\nconsole.log(greeting);",
+            "function f(a, b, c, s) {\n  let ratio = a / b / c; const pattern = /\\/\\/ +x/g;\n  if (pattern.test(s)) {\n    // a comment\n    return ratio;\n  }\n}",
+            "var s = 'it\\'s'\nvar t = \"two  spaces\"\n(function () {})()",
+            "import { a } from './a.js';\nexport default function () {\n  return a + 1;\n}",
+        ];
+        for source in sources {
+            let minified = minify_javascript(source).unwrap();
+            assert!(minified.len() < source.len(), "{}", minified);
+            assert_eq!(minify_javascript(&minified).unwrap(), minified);
+            let beautified = format_javascript(&minified).unwrap();
+            assert_eq!(minify_javascript(&beautified).unwrap(), minified);
+        }
+
+        let minified = minify_javascript(sources[0]).unwrap();
+        assert!(minified.contains("`Hello,\n    ${name}!  // not a comment`"));
+        // Without a semicolon the next line continues the expression, and must stay so
+        let minified = minify_javascript(sources[2]).unwrap();
+        assert!(minified.contains("\"two  spaces\"(function(){})()"));
+    }
+
+    #[test]
+    fn test_minify_css_is_equivalent() {
+        let source = "@charset \"UTF-8\";\nbody {\n  margin: 0;\n  font-family: \"Open  Sans\", sans-serif;\n}\n\n.a::before {\n  content: \"  two spaces  \";\n}\n@media (min-width: 768px) {\n  .b { color: #ff0000; }\n}";
+        let minified = minify_css(source).unwrap();
+        assert!(minified.starts_with("@charset \"UTF-8\";"));
+        assert!(!minified.contains('\n'));
+        assert!(minified.contains("\"  two spaces  \""));
+        assert!(minified.contains("font-family:\"Open  Sans\""));
+        assert_eq!(minify_css(&minified).unwrap(), minified);
+        let beautified = format_css(source).unwrap();
+        assert_eq!(minify_css(&beautified).unwrap(), minified);
+    }
+
+    #[test]
+    fn test_minify_html_is_equivalent() {
+        let source = "<!DOCTYPE html>\n<html>\n  <head>\n    <title>Test</title>\n    <!-- a comment -->\n    <script>\n      const t = `a\n        b`;\n      if (1 < 2) document.title = \"</scr\" + \"ipt>\";\n    </script>\n  </head>\n  <body>\n    <p>Some   <b>bold</b>  text</p>\n    <pre>\n\n  indented\n    code</pre>\n    <textarea>  kept\n  as is</textarea>\n    <template><p>  inert  </p></template>\n  </body>\n</html>";
+        let minified = minify_html(source).unwrap();
+        assert!(minified.len() < source.len());
+        assert!(!minified.contains("a comment"));
+        assert!(minified.contains("<p>Some <b>bold</b> text</p>"));
+        assert!(minified.contains("<template><p> inert </p></template>"));
+        for tag in ["pre", "textarea", "title"] {
+            assert_eq!(text_of(&minified, tag), text_of(source, tag), "{}", tag);
+        }
+        assert_eq!(text_of(&minified, "pre"), "\n  indented\n    code");
+        let script = text_of(&minified, "script");
+        assert_eq!(
+            script,
+            minify_javascript(&text_of(source, "script")).unwrap()
+        );
+        assert!(script.contains("`a\n        b`"));
+        assert_eq!(minify_html(&minified).unwrap(), minified);
+
+        // A head left empty is implied again when the page is parsed
+        let minified =
+            minify_html("<html>\n  <body>\n    <h1>Test</h1>\n  </body>\n</html>").unwrap();
+        assert_eq!(minified, "<html><body><h1>Test</h1></body></html>");
+    }
+}
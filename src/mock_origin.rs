@@ -0,0 +1,329 @@
+//! A configurable origin server for tests
+//!
+//! Recording needs something to record from, and every e2e suite used to bring its own
+//! hyper server with hand-written routes for sized bodies, slow responses, Japanese
+//! charsets and compressed content. `mock-origin` serves all of those from one binary:
+//! any path answers, and the query string describes the response, e.g.
+//! `/page.html?size=200KB&ttfb=300ms&transfer=2s&charset=shift_jis&encoding=gzip`.
+//! Bodies are deterministic for a given `--seed`, path and query, so the same URL
+//! yields the same bytes across runs and machines.
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use encoding_rs::Encoding;
+use futures::stream;
+use http_body_util::{BodyExt, Full, StreamBody, combinators::BoxBody};
+use hyper::body::{Frame, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+use crate::generate::filler_body;
+use crate::playback::transaction::compress_content;
+use crate::types::ContentEncodingType;
+use crate::utils::{get_port_or_default, parse_duration_ms, parse_size_bytes};
+
+/// Body size when the query does not give one
+const DEFAULT_SIZE: u64 = 1024;
+
+/// Filler of text bodies with a charset, so conversion is actually exercised
+const JAPANESE_LINE: &str = "日本語のテスト本文です。文字コードの変換を確認します。\n";
+
+/// MIME types of the extensions a path may end with
+const EXTENSION_TYPES: &[(&str, &str)] = &[
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "application/javascript"),
+    ("mjs", "application/javascript"),
+    ("json", "application/json"),
+    ("txt", "text/plain"),
+    ("xml", "application/xml"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("woff2", "font/woff2"),
+    ("mp4", "video/mp4"),
+];
+
+/// The response described by a request's path and query
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockResponse {
+    pub status: u16,
+    /// MIME type without parameters
+    pub content_type: String,
+    /// Body size before compression, in bytes
+    pub size: u64,
+    pub ttfb_ms: u64,
+    /// Time from the first to the last body byte
+    pub transfer_ms: u64,
+    /// Number of writes the body is split into over `transfer_ms`
+    pub chunks: u64,
+    /// Charset of text bodies, also named in Content-Type
+    pub charset: Option<&'static Encoding>,
+    pub encoding: Option<ContentEncodingType>,
+}
+
+impl MockResponse {
+    /// Read the response from `path` and the `query` string
+    ///
+    /// Recognized parameters are `status`, `type`, `size` (e.g. `64KB`), `ttfb` and
+    /// `transfer` (e.g. `250ms`, `2s`), `chunks`, `charset` and `encoding` (`gzip`,
+    /// `br`, `deflate`). Without `type`, the path's extension decides.
+    pub fn from_request(path: &str, query: Option<&str>) -> Result<Self> {
+        let mut response = Self {
+            status: 200,
+            content_type: type_of_path(path).to_string(),
+            size: DEFAULT_SIZE,
+            ttfb_ms: 0,
+            transfer_ms: 0,
+            chunks: 10,
+            charset: None,
+            encoding: None,
+        };
+        let query = query.unwrap_or_default();
+        for (name, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match name.as_ref() {
+                "status" => {
+                    response.status = value
+                        .parse()
+                        .ok()
+                        .filter(|status| (100..=999).contains(status))
+                        .with_context(|| format!("Invalid status {:?}", value))?
+                }
+                "type" => response.content_type = value.trim().to_ascii_lowercase(),
+                "size" => response.size = parse_size_bytes(&value)?,
+                "ttfb" => response.ttfb_ms = parse_duration_ms(&value)?,
+                "transfer" => response.transfer_ms = parse_duration_ms(&value)?,
+                "chunks" => {
+                    response.chunks = value
+                        .parse()
+                        .ok()
+                        .filter(|chunks| *chunks > 0)
+                        .with_context(|| format!("Invalid chunk count {:?}", value))?
+                }
+                "charset" => {
+                    response.charset = Some(
+                        Encoding::for_label(value.as_bytes())
+                            .with_context(|| format!("Unknown charset {:?}", value))?,
+                    )
+                }
+                "encoding" => {
+                    let encoding: ContentEncodingType =
+                        value.parse().map_err(|e: String| anyhow::anyhow!(e))?;
+                    if encoding == ContentEncodingType::Compress {
+                        anyhow::bail!("Content-Encoding compress is not supported");
+                    }
+                    response.encoding =
+                        Some(encoding).filter(|e| *e != ContentEncodingType::Identity);
+                }
+                other => anyhow::bail!("Unknown parameter {:?}", other),
+            }
+        }
+        Ok(response)
+    }
+
+    /// Content-Type header value
+    pub fn content_type_header(&self) -> String {
+        match self.charset {
+            Some(charset) => format!("{}; charset={}", self.content_type, charset.name()),
+            None => self.content_type.clone(),
+        }
+    }
+
+    /// The body as sent, compressed when an encoding was asked for
+    ///
+    /// `label` (the path and query) and `seed` pick the bytes of binary bodies.
+    pub fn body(&self, label: &str, seed: u64) -> Result<Vec<u8>> {
+        let size = self.size as usize;
+        let body = match self.charset {
+            Some(charset) if is_text(&self.content_type) => {
+                charset_body(&self.content_type, size, charset)
+            }
+            _ => filler_body(&self.content_type, size, label, seed),
+        };
+        match &self.encoding {
+            Some(encoding) => compress_content(&body, encoding),
+            None => Ok(body),
+        }
+    }
+}
+
+fn type_of_path(path: &str) -> &'static str {
+    let file_name = path.rsplit('/').next().unwrap_or_default();
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension {
+        Some(extension) => EXTENSION_TYPES
+            .iter()
+            .find(|(known, _)| *known == extension)
+            .map(|(_, mime)| *mime)
+            .unwrap_or("application/octet-stream"),
+        // Extensionless paths are pages
+        None => "text/html",
+    }
+}
+
+fn is_text(mime: &str) -> bool {
+    mime.starts_with("text/") || mime.ends_with("javascript") || mime.ends_with("json")
+}
+
+/// `size` bytes of text in `charset`, which names itself where the syntax allows it
+fn charset_body(mime: &str, size: usize, charset: &'static Encoding) -> Vec<u8> {
+    let name = charset.name();
+    let (prefix, suffix) = match mime {
+        "text/html" => (
+            format!(
+                "<!DOCTYPE html>\n<html>\n<head><meta charset=\"{}\"></head>\n<body>\n<!--\n",
+                name
+            ),
+            "-->\n</body>\n</html>\n",
+        ),
+        "text/css" => (format!("@charset \"{}\";\n/*\n", name), "*/\n"),
+        "application/javascript" | "text/javascript" => ("/*\n".to_string(), "*/\n"),
+        _ => (String::new(), ""),
+    };
+    let (line, _, _) = charset.encode(JAPANESE_LINE);
+
+    let framed = size >= prefix.len() + suffix.len();
+    let end = if framed { size - suffix.len() } else { size };
+    let mut body = Vec::with_capacity(size);
+    if framed {
+        body.extend_from_slice(prefix.as_bytes());
+    }
+    // Whole lines only, so no character is cut in half, then ASCII up to the size
+    while body.len() + line.len() <= end {
+        body.extend_from_slice(&line);
+    }
+    body.resize(end, b' ');
+    if framed {
+        body.extend_from_slice(suffix.as_bytes());
+    }
+    body
+}
+
+/// When each chunk of a `len`-byte body is due after the headers, and its length
+fn chunk_schedule(len: usize, chunks: u64, transfer_ms: u64) -> Vec<(u64, usize)> {
+    let chunks = (chunks as usize).clamp(1, len.max(1));
+    (0..chunks)
+        .map(|i| {
+            let start = len * i / chunks;
+            let end = len * (i + 1) / chunks;
+            let due_ms = transfer_ms * (i as u64 + 1) / chunks as u64;
+            (due_ms, end - start)
+        })
+        .collect()
+}
+
+type MockBody = BoxBody<Bytes, Infallible>;
+
+async fn respond(req: Request<Incoming>, seed: u64) -> Result<Response<MockBody>, Infallible> {
+    let uri = req.uri();
+    let label = uri
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .unwrap_or("/");
+    let mock = MockResponse::from_request(uri.path(), uri.query())
+        .and_then(|mock| mock.body(label, seed).map(|body| (mock, body)));
+    let (mock, body) = match mock {
+        Ok(mock) => mock,
+        Err(e) => {
+            warn!("Bad mock request {} {}: {}", req.method(), label, e);
+            let response = Response::builder()
+                .status(400)
+                .header("content-type", "text/plain; charset=utf-8")
+                .body(Full::new(Bytes::from(format!("{}\n", e))).boxed())
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    debug!(
+        "{} {} -> {} ({} bytes)",
+        req.method(),
+        label,
+        mock.status,
+        body.len()
+    );
+
+    tokio::time::sleep(Duration::from_millis(mock.ttfb_ms)).await;
+
+    let mut builder = Response::builder()
+        .status(mock.status)
+        .header("content-type", mock.content_type_header())
+        .header("content-length", body.len())
+        .header("cache-control", "no-store");
+    if let Some(encoding) = &mock.encoding {
+        builder = builder.header("content-encoding", encoding_name(encoding));
+    }
+    let body = if mock.transfer_ms == 0 {
+        Full::new(Bytes::from(body)).boxed()
+    } else {
+        let headers_sent = tokio::time::Instant::now();
+        let body = Bytes::from(body);
+        let schedule = chunk_schedule(body.len(), mock.chunks, mock.transfer_ms);
+        let frames = stream::unfold(
+            (schedule.into_iter(), body),
+            move |(mut schedule, mut rest)| async move {
+                let (due_ms, len) = schedule.next()?;
+                tokio::time::sleep_until(headers_sent + Duration::from_millis(due_ms)).await;
+                let chunk = rest.split_to(len);
+                Some((Ok(Frame::data(chunk)), (schedule, rest)))
+            },
+        );
+        StreamBody::new(frames).boxed()
+    };
+    Ok(builder.body(body).unwrap())
+}
+
+fn encoding_name(encoding: &ContentEncodingType) -> &'static str {
+    match encoding {
+        ContentEncodingType::Gzip => "gzip",
+        ContentEncodingType::Deflate => "deflate",
+        ContentEncodingType::Br => "br",
+        ContentEncodingType::Compress => "compress",
+        ContentEncodingType::Identity => "identity",
+    }
+}
+
+/// Answer connections on `listener` until the task is dropped
+pub async fn serve(listener: TcpListener, seed: u64) -> Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            let service = service_fn(move |req| respond(req, seed));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(socket), service)
+                .await
+            {
+                debug!("Mock origin connection ended: {}", e);
+            }
+        });
+    }
+}
+
+pub async fn run_mock_origin(port: Option<u16>, seed: u64) -> Result<()> {
+    let port = get_port_or_default(port)?;
+    let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await?;
+    info!("Mock origin listening on http://127.0.0.1:{}", port);
+    println!("Mock origin listening on http://127.0.0.1:{}", port);
+
+    tokio::select! {
+        result = serve(listener, seed) => result,
+        result = crate::playback::signal_handler::wait_for_shutdown_signal() => {
+            info!("Mock origin stopped");
+            Ok(result?)
+        }
+    }
+}
+
+mod tests;
@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod mock_origin_tests {
+    use crate::mock_origin::{MockResponse, serve};
+    use crate::playback::transaction::decompress_content;
+    use crate::types::ContentEncodingType;
+    use encoding_rs::SHIFT_JIS;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_mock_response_from_query() {
+        let mock = MockResponse::from_request(
+            "/assets/app.js",
+            Some("size=2KB&ttfb=300ms&transfer=1.5s&chunks=4&encoding=br"),
+        )
+        .unwrap();
+        assert_eq!(mock.status, 200);
+        assert_eq!(mock.content_type, "application/javascript");
+        assert_eq!(mock.size, 2048);
+        assert_eq!(mock.ttfb_ms, 300);
+        assert_eq!(mock.transfer_ms, 1500);
+        assert_eq!(mock.chunks, 4);
+        assert_eq!(mock.encoding, Some(ContentEncodingType::Br));
+
+        let page = MockResponse::from_request("/", None).unwrap();
+        assert_eq!(page.content_type, "text/html");
+        assert_eq!(page.size, 1024);
+        let typed =
+            MockResponse::from_request("/data", Some("type=application/json&status=404")).unwrap();
+        assert_eq!(typed.content_type, "application/json");
+        assert_eq!(typed.status, 404);
+        assert_eq!(
+            MockResponse::from_request("/blob.bin", None)
+                .unwrap()
+                .content_type,
+            "application/octet-stream"
+        );
+
+        for bad in [
+            "sise=10",
+            "size=ten",
+            "charset=klingon",
+            "encoding=zip",
+            "chunks=0",
+        ] {
+            assert!(
+                MockResponse::from_request("/", Some(bad)).is_err(),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_mock_bodies() {
+        let html =
+            MockResponse::from_request("/page.html", Some("size=5000&charset=shift_jis")).unwrap();
+        assert_eq!(html.content_type_header(), "text/html; charset=Shift_JIS");
+        let body = html.body("/page.html", 0).unwrap();
+        assert_eq!(body.len(), 5000);
+        let (text, _, had_errors) = SHIFT_JIS.decode(&body);
+        assert!(!had_errors);
+        assert!(text.contains("<meta charset=\"Shift_JIS\">"));
+        assert!(text.contains("日本語"));
+        assert!(text.ends_with("</html>\n"));
+
+        // Sizes are before compression
+        let css = MockResponse::from_request("/a.css", Some("size=3000&encoding=gzip")).unwrap();
+        let compressed = css.body("/a.css", 0).unwrap();
+        let plain = decompress_content(&compressed, &ContentEncodingType::Gzip).unwrap();
+        assert_eq!(plain.len(), 3000);
+        assert!(compressed.len() < plain.len());
+
+        // Binary bodies depend on the seed and the URL only
+        let image = MockResponse::from_request("/i.png", Some("size=100")).unwrap();
+        assert_eq!(image.body("/i.png?size=100", 1).unwrap().len(), 100);
+        assert_eq!(
+            image.body("/i.png?size=100", 1).unwrap(),
+            image.body("/i.png?size=100", 1).unwrap()
+        );
+        assert_ne!(
+            image.body("/i.png?size=100", 1).unwrap(),
+            image.body("/i.png?size=100", 2).unwrap()
+        );
+    }
+
+    async fn get(port: u16, target: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: origin.test\r\nConnection: close\r\n\r\n",
+            target
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_serve_mock_responses() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(serve(listener, 0));
+
+        let started = std::time::Instant::now();
+        let response = get(port, "/slow.txt?size=64&ttfb=100ms&transfer=100ms&chunks=4").await;
+        assert!(started.elapsed().as_millis() >= 200);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("content-type: text/plain\r\n"));
+        assert!(response.contains("content-length: 64\r\n"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        assert_eq!(body.len(), 64);
+
+        let rejected = get(port, "/?size=lots").await;
+        assert!(rejected.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(rejected.contains("Invalid size"));
+
+        server.abort();
+    }
+}
@@ -0,0 +1,196 @@
+//! multipart/form-data request bodies
+//!
+//! Recording keeps the parts of form uploads: text fields go into the inventory and
+//! file parts are written next to the content files. Playback can compare the fields
+//! of an incoming upload with the recorded ones; the boundary string changes with every
+//! submission, so only names, values and file names are compared.
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use crate::types::FormPart;
+
+/// One part of a multipart body as sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Bytes,
+}
+
+/// Boundary of a `multipart/form-data` Content-Type, or None for other types
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    let mime = params.next()?.trim();
+    if !mime.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case("boundary")
+                .then(|| value.trim().trim_matches('"').to_string())
+        })
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Split a multipart body into its parts; parts are slices of `body`, not copies
+pub fn parse(body: &Bytes, boundary: &str) -> Result<Vec<Part>> {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+    let close = [b"\r\n", delimiter].concat();
+
+    let mut pos = find(body, delimiter, 0)
+        .ok_or_else(|| anyhow::anyhow!("Multipart body has no {:?} boundary", boundary))?
+        + delimiter.len();
+    let mut parts = Vec::new();
+
+    loop {
+        if body[pos..].starts_with(b"--") {
+            return Ok(parts);
+        }
+        // The rest of the delimiter line may hold transport padding
+        let headers_start = find(body, b"\r\n", pos)
+            .ok_or_else(|| anyhow::anyhow!("Multipart body ends inside a delimiter"))?
+            + 2;
+        let (headers, data_start) = if body[headers_start..].starts_with(b"\r\n") {
+            ("", headers_start + 2)
+        } else {
+            let headers_end = find(body, b"\r\n\r\n", headers_start)
+                .ok_or_else(|| anyhow::anyhow!("Multipart part headers are not terminated"))?;
+            let headers = std::str::from_utf8(&body[headers_start..headers_end])?;
+            (headers, headers_end + 4)
+        };
+        let data_end = find(body, &close, data_start)
+            .ok_or_else(|| anyhow::anyhow!("Multipart body is missing its closing boundary"))?;
+
+        parts.push(parse_part(headers, body.slice(data_start..data_end))?);
+        pos = data_end + close.len();
+    }
+}
+
+fn parse_part(headers: &str, data: Bytes) -> Result<Part> {
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+
+    for line in headers.split("\r\n") {
+        let Some((header, value)) = line.split_once(':') else {
+            continue;
+        };
+        match header.trim().to_ascii_lowercase().as_str() {
+            "content-disposition" => {
+                for (key, value) in disposition_params(value) {
+                    match key.as_str() {
+                        "name" => name = Some(value),
+                        "filename" => filename = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+            "content-type" => content_type = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+
+    Ok(Part {
+        name: name.ok_or_else(|| anyhow::anyhow!("Multipart part has no name"))?,
+        filename,
+        content_type,
+        data,
+    })
+}
+
+/// `key=value` parameters of a Content-Disposition value, honoring quoted strings
+fn disposition_params(value: &str) -> Vec<(String, String)> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            _ if escaped => {
+                current.push(c);
+                escaped = false;
+            }
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => segments.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    segments.push(current);
+
+    segments
+        .iter()
+        .filter_map(|segment| {
+            let (key, value) = segment.split_once('=')?;
+            Some((key.trim().to_ascii_lowercase(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|offset| from + offset)
+}
+
+/// Inventory form of parsed parts: text fields keep their value, files keep their bytes
+/// until batch processing writes them out
+pub fn to_form_parts(parts: Vec<Part>) -> Vec<FormPart> {
+    parts
+        .into_iter()
+        .map(|part| {
+            let text = match &part.filename {
+                Some(_) => None,
+                None => std::str::from_utf8(&part.data).ok().map(str::to_string),
+            };
+            FormPart {
+                name: part.name,
+                size: part.data.len() as u64,
+                data: text.is_none().then(|| part.data.to_vec()),
+                value: text,
+                filename: part.filename,
+                content_type: part.content_type,
+                file_path: None,
+            }
+        })
+        .collect()
+}
+
+/// What form matching compares: each field's name with its value, or file name for
+/// uploads, in a stable order
+pub fn form_signature(parts: &[FormPart]) -> Vec<(String, String)> {
+    signature(parts.iter().map(|part| {
+        let value = part.value.as_deref().or(part.filename.as_deref());
+        (part.name.as_str(), value)
+    }))
+}
+
+/// `form_signature` of a body as sent, without copying uploaded files
+pub fn parts_signature(parts: &[Part]) -> Vec<(String, String)> {
+    signature(parts.iter().map(|part| {
+        let value = match &part.filename {
+            Some(filename) => Some(filename.as_str()),
+            None => std::str::from_utf8(&part.data).ok(),
+        };
+        (part.name.as_str(), value)
+    }))
+}
+
+fn signature<'a>(
+    fields: impl Iterator<Item = (&'a str, Option<&'a str>)>,
+) -> Vec<(String, String)> {
+    let mut signature: Vec<(String, String)> = fields
+        .map(|(name, value)| (name.to_string(), value.unwrap_or_default().to_string()))
+        .collect();
+    signature.sort();
+    signature
+}
+
+mod tests;
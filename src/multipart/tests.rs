@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod multipart_tests {
+    use crate::multipart::{boundary, form_signature, parse, parts_signature, to_form_parts};
+    use bytes::Bytes;
+
+    fn form_body(boundary: &str) -> Bytes {
+        Bytes::from(format!(
+            "--{b}\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\
+             \r\n\
+             Hello; world\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"photo\"; filename=\"C:\\\\tmp\\\\cat.jpg\"\r\n\
+             Content-Type: image/jpeg\r\n\
+             \r\n\
+             \u{1}\u{2}binary\r\n\
+             --{b}--\r\n",
+            b = boundary
+        ))
+    }
+
+    #[test]
+    fn test_boundary() {
+        assert_eq!(
+            boundary("multipart/form-data; boundary=----WebKitFormBoundaryX").as_deref(),
+            Some("----WebKitFormBoundaryX")
+        );
+        assert_eq!(
+            boundary("Multipart/Form-Data; charset=utf-8; Boundary=\"abc\"").as_deref(),
+            Some("abc")
+        );
+        assert_eq!(boundary("multipart/form-data"), None);
+        assert_eq!(boundary("application/x-www-form-urlencoded"), None);
+    }
+
+    #[test]
+    fn test_parse_fields_and_files() {
+        let parts = parse(&form_body("xyz"), "xyz").unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "title");
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(&parts[0].data[..], b"Hello; world");
+        assert_eq!(parts[1].name, "photo");
+        assert_eq!(parts[1].filename.as_deref(), Some("C:\\tmp\\cat.jpg"));
+        assert_eq!(parts[1].content_type.as_deref(), Some("image/jpeg"));
+        assert_eq!(&parts[1].data[..], b"\x01\x02binary");
+
+        let form = to_form_parts(parts);
+        assert_eq!(form[0].value.as_deref(), Some("Hello; world"));
+        assert_eq!(form[0].data, None);
+        assert_eq!(form[1].value, None);
+        assert_eq!(form[1].size, 8);
+        assert_eq!(form[1].data.as_deref(), Some(&b"\x01\x02binary"[..]));
+    }
+
+    #[test]
+    fn test_signature_ignores_boundary() {
+        let recorded = to_form_parts(parse(&form_body("first"), "first").unwrap());
+        let replayed = parse(&form_body("second"), "second").unwrap();
+
+        assert_eq!(form_signature(&recorded), parts_signature(&replayed));
+        assert_eq!(
+            parts_signature(&replayed),
+            vec![
+                ("photo".to_string(), "C:\\tmp\\cat.jpg".to_string()),
+                ("title".to_string(), "Hello; world".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_truncated_body() {
+        let body =
+            Bytes::from_static(b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nvalue");
+        assert!(parse(&body, "b").is_err());
+        assert!(parse(&Bytes::from_static(b"no parts"), "b").is_err());
+    }
+}
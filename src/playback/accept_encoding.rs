@@ -0,0 +1,111 @@
+//! Content-Encoding negotiation with the replaying client
+//!
+//! Responses are replayed with the encoding the recording browser accepted, usually
+//! brotli. A client that does not accept it (curl without brotli, a test harness
+//! sending no Accept-Encoding) would get a body it cannot decode, so such responses are
+//! re-encoded to the client's preferred coding, or sent as identity. The new body keeps
+//! the recorded chunk schedule, so TTFB and transfer time stay as recorded.
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use super::transaction::{compress_content, decompress_content, with_body};
+use crate::types::{ContentEncodingType, HeaderValue, Transaction};
+
+/// Codings playback can produce, in order of preference among equal q-values
+const SUPPORTED: [(&str, ContentEncodingType); 3] = [
+    ("br", ContentEncodingType::Br),
+    ("gzip", ContentEncodingType::Gzip),
+    ("deflate", ContentEncodingType::Deflate),
+];
+
+/// The transaction re-encoded for a client sending `accept_encoding`, or None when the
+/// recorded encoding is acceptable as it is
+///
+/// A missing Accept-Encoding header is taken as identity only, which is what origins
+/// send such clients. Bodies streamed from disk are never compressed.
+pub fn negotiate_encoding(
+    transaction: &Transaction,
+    accept_encoding: Option<&str>,
+) -> Result<Option<Transaction>> {
+    if transaction.streamed.is_some() {
+        return Ok(None);
+    }
+    let Some(recorded) = transaction
+        .raw_headers
+        .as_ref()
+        .and_then(|headers| headers.get("content-encoding"))
+        .and_then(|value| {
+            value
+                .as_vec()
+                .first()
+                .map(|v| v.trim().to_ascii_lowercase())
+        })
+    else {
+        return Ok(None);
+    };
+    let Ok(recorded_encoding) = recorded.parse::<ContentEncodingType>() else {
+        return Ok(None);
+    };
+    if matches!(
+        recorded_encoding,
+        ContentEncodingType::Identity | ContentEncodingType::Compress
+    ) || quality(accept_encoding, &recorded) > 0.0
+    {
+        return Ok(None);
+    }
+
+    // Highest q-value wins; ties go to the better compression
+    let mut chosen: Option<(&str, &ContentEncodingType, f32)> = None;
+    for (name, encoding) in &SUPPORTED {
+        let q = quality(accept_encoding, name);
+        if q > 0.0 && chosen.is_none_or(|(_, _, best)| q > best) {
+            chosen = Some((*name, encoding, q));
+        }
+    }
+
+    let body: Vec<u8> = transaction
+        .chunks
+        .iter()
+        .flat_map(|c| c.chunk.iter().copied())
+        .collect();
+    let decoded = decompress_content(&body, &recorded_encoding)?;
+    let (body, coding) = match chosen {
+        Some((name, encoding, _)) => (compress_content(&decoded, encoding)?, Some(name)),
+        None => (decoded, None),
+    };
+
+    let mut negotiated = with_body(transaction, Bytes::from(body));
+    if let Some(headers) = &mut negotiated.raw_headers {
+        match coding {
+            Some(name) => headers.set("content-encoding", HeaderValue::Single(name.to_string())),
+            None => {
+                headers.remove("content-encoding");
+            }
+        }
+    }
+    Ok(Some(negotiated))
+}
+
+/// The q-value an Accept-Encoding header gives `coding`, 0 when it is not acceptable
+fn quality(accept_encoding: Option<&str>, coding: &str) -> f32 {
+    let Some(accept_encoding) = accept_encoding else {
+        return 0.0;
+    };
+    let mut wildcard = None;
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let q = params
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if name.eq_ignore_ascii_case(coding) {
+            return q;
+        }
+        if name == "*" {
+            wildcard = Some(q);
+        }
+    }
+    wildcard.unwrap_or(0.0)
+}
@@ -0,0 +1,75 @@
+//! Transformed bodies kept between requests
+//!
+//! Lazily loaded transactions are minified, re-encoded and compressed on first use.
+//! Without a cache every request for a hot asset pays for that again; keeping every
+//! body would hold cold ones in memory for the whole session. The cache keeps the most
+//! recently used bodies up to a byte budget and evicts the least recently used.
+
+use indexmap::IndexMap;
+use std::sync::{Arc, Mutex};
+
+use crate::types::Transaction;
+
+#[derive(Debug)]
+pub struct BodyCache {
+    max_bytes: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    // Keyed by resource index, least recently used first
+    transactions: IndexMap<usize, Arc<Transaction>>,
+    bytes: usize,
+}
+
+/// Bytes a cached transaction holds in memory; streamed bodies hold none
+fn cached_size(transaction: &Transaction) -> usize {
+    transaction.chunks.iter().map(|c| c.chunk.len()).sum()
+}
+
+impl BodyCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// The cached transaction of a resource, now the most recently used
+    pub fn get(&self, resource_index: usize) -> Option<Arc<Transaction>> {
+        let mut entries = self.entries.lock().unwrap();
+        let transaction = entries.transactions.shift_remove(&resource_index)?;
+        entries
+            .transactions
+            .insert(resource_index, transaction.clone());
+        Some(transaction)
+    }
+
+    /// Keep `transaction`, evicting the least recently used until it fits
+    ///
+    /// Bodies larger than the whole budget are not cached.
+    pub fn insert(&self, transaction: Arc<Transaction>) {
+        let size = cached_size(&transaction);
+        if size > self.max_bytes {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(previous) = entries
+            .transactions
+            .shift_remove(&transaction.resource_index)
+        {
+            entries.bytes -= cached_size(&previous);
+        }
+        while entries.bytes + size > self.max_bytes {
+            let Some((_, evicted)) = entries.transactions.shift_remove_index(0) else {
+                break;
+            };
+            entries.bytes -= cached_size(&evicted);
+        }
+        entries.bytes += size;
+        entries
+            .transactions
+            .insert(transaction.resource_index, transaction);
+    }
+}
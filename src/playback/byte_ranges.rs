@@ -0,0 +1,202 @@
+//! Answering Range requests
+//!
+//! An object stitched from 206 responses while recording holds the spans of it the
+//! recorded player fetched. A `Range: bytes=` request starting within a span is answered
+//! `206 Partial Content` with the requested bytes, up to the end of that span, paced at
+//! the object's recorded rate; the client asks again for the rest. Only the first range
+//! of a multi-range request is served. Without a Range header the whole object is sent
+//! when every byte of it was recorded, and the request is taken as `bytes=0-` otherwise.
+//! A range starting outside the recorded spans is answered `416 Range Not Satisfiable`.
+//!
+//! A response recorded whole is sliced the same way, so media elements that seek with
+//! Range requests get the bytes they ask for rather than the full body. The slice takes
+//! its proportional share of the recorded transfer time.
+
+use crate::types::{BodyChunk, ByteRange, HeaderValue, RangeMap, StreamedBody, Transaction};
+
+/// First byte and, unless open-ended, last byte a Range header asks for
+///
+/// None when the header is absent, malformed or not in bytes, in which case it is ignored.
+fn requested(range: Option<&str>, ranges: &RangeMap) -> Option<(u64, Option<u64>)> {
+    let spec = range?.trim().strip_prefix("bytes=")?;
+    let first = spec.split(',').next()?.trim();
+    let (start, end) = first.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        // The last N bytes of the object
+        let suffix: u64 = end.parse().ok()?;
+        let length = ranges
+            .complete_length
+            .or_else(|| ranges.spans.last().map(|span| span.end + 1))?;
+        return (suffix > 0).then(|| (length.saturating_sub(suffix), None));
+    }
+    let start: u64 = start.parse().ok()?;
+    if end.is_empty() {
+        return Some((start, None));
+    }
+    let end: u64 = end.parse().ok()?;
+    (end >= start).then_some((start, Some(end)))
+}
+
+/// The response to a request with `range` for a transaction with `byte_ranges`
+pub fn select(transaction: &Transaction, range: Option<&str>) -> Transaction {
+    let Some(ranges) = &transaction.byte_ranges else {
+        return transaction.clone();
+    };
+    let requested = requested(range, ranges);
+    let complete = matches!(
+        (ranges.spans.as_slice(), ranges.complete_length),
+        ([span], Some(length)) if span.start == 0 && span.end + 1 == length
+    );
+    if requested.is_none() && complete {
+        let mut selected = transaction.clone();
+        let mut headers = selected.raw_headers.take().unwrap_or_default();
+        headers.set("accept-ranges", HeaderValue::Single("bytes".to_string()));
+        headers.remove("content-range");
+        selected.raw_headers = Some(headers);
+        selected.reason_phrase = None;
+        selected.status_code = Some(200);
+        return selected;
+    }
+    let (start, end) = requested.unwrap_or((0, None));
+    partial(transaction, ranges, start, end)
+}
+
+/// The response to a request with `range` for a transaction recorded whole, or None
+/// when the whole body is to be sent
+///
+/// That is when the Range header is absent or malformed, the recording is not a plain
+/// 200 with a body, or an `If-Range` validator no longer matches it.
+pub fn select_whole(
+    transaction: &Transaction,
+    range: Option<&str>,
+    if_range: Option<&str>,
+) -> Option<Transaction> {
+    let length = transaction.body_size() as u64;
+    let sliceable = transaction.status_code.unwrap_or(200) == 200
+        && transaction.error_kind.is_none()
+        && transaction.error_message.is_none()
+        && transaction.byte_ranges.is_none()
+        && transaction.event_stream.is_none()
+        && !transaction.open_ended
+        && length > 0;
+    if !sliceable || !if_range.is_none_or(|validator| still_valid(transaction, validator)) {
+        return None;
+    }
+    let ranges = RangeMap {
+        spans: vec![ByteRange {
+            start: 0,
+            end: length - 1,
+        }],
+        complete_length: Some(length),
+    };
+    let (start, end) = requested(range, &ranges)?;
+    Some(partial(transaction, &ranges, start, end))
+}
+
+/// Whether an `If-Range` validator still names the recorded response, compared strongly
+fn still_valid(transaction: &Transaction, validator: &str) -> bool {
+    let validator = validator.trim();
+    let Some(headers) = &transaction.raw_headers else {
+        return false;
+    };
+    if validator.starts_with('"') {
+        headers
+            .get("etag")
+            .is_some_and(|etag| etag.first().trim() == validator)
+    } else {
+        headers
+            .get("last-modified")
+            .is_some_and(|modified| modified.first().trim() == validator)
+    }
+}
+
+/// The 206 with bytes `start` through `end` of an object with `ranges` recorded, or the
+/// 416 when `start` is outside them
+fn partial(
+    transaction: &Transaction,
+    ranges: &RangeMap,
+    start: u64,
+    end: Option<u64>,
+) -> Transaction {
+    let mut selected = transaction.clone();
+    let complete_length = ranges
+        .complete_length
+        .map_or("*".to_string(), |length| length.to_string());
+    let mut headers = selected.raw_headers.take().unwrap_or_default();
+    headers.set("accept-ranges", HeaderValue::Single("bytes".to_string()));
+    selected.reason_phrase = None;
+
+    let mut offset = 0;
+    let mut found = None;
+    for span in &ranges.spans {
+        if (span.start..=span.end).contains(&start) {
+            let end = end.map_or(span.end, |end| end.min(span.end));
+            found = Some((offset + start - span.start, end - start + 1, end));
+            break;
+        }
+        offset += span.length();
+    }
+    let Some((offset, length, end)) = found else {
+        headers.set(
+            "content-range",
+            HeaderValue::Single(format!("bytes */{}", complete_length)),
+        );
+        selected.raw_headers = Some(headers);
+        selected.status_code = Some(416);
+        selected.chunks = Vec::new();
+        selected.streamed = None;
+        selected.target_close_time = 0;
+        return selected;
+    };
+
+    headers.set(
+        "content-range",
+        HeaderValue::Single(format!("bytes {}-{}/{}", start, end, complete_length)),
+    );
+    selected.raw_headers = Some(headers);
+    selected.status_code = Some(206);
+    slice_body(&mut selected, offset, length);
+    selected
+}
+
+/// Keep `length` bytes of the body from `offset`, sent at the rate the whole body is
+fn slice_body(transaction: &mut Transaction, offset: u64, length: u64) {
+    let total = transaction.body_size() as u64;
+    let close_time = transaction.target_close_time;
+    let due = |bytes: u64| (bytes as f64 * close_time as f64 / total.max(1) as f64) as u64;
+    transaction.target_close_time = due(length);
+    // A slice is paced evenly over its share of the transfer
+    transaction.timed = false;
+
+    // Bodies streamed from disk are read from the slice's place in the file
+    if let Some(body) = &transaction.streamed {
+        transaction.streamed = Some(StreamedBody {
+            offset: body.offset + offset,
+            len: length,
+            timings: None,
+            ..body.clone()
+        });
+        return;
+    }
+
+    let (from, to) = (offset, offset + length);
+    let mut chunks = Vec::new();
+    let mut position = 0;
+    for chunk in &transaction.chunks {
+        let (chunk_start, chunk_end) = (position, position + chunk.chunk.len() as u64);
+        position = chunk_end;
+        if chunk_end <= from || chunk_start >= to {
+            continue;
+        }
+        let start = chunk_start.max(from);
+        let end = chunk_end.min(to);
+        chunks.push(BodyChunk {
+            chunk: chunk
+                .chunk
+                .slice((start - chunk_start) as usize..(end - chunk_start) as usize),
+            target_time: due(start - from),
+        });
+    }
+    transaction.chunks = chunks;
+}
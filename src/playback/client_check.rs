@@ -0,0 +1,135 @@
+//! Warnings when the replaying client is not the kind that was recorded
+//!
+//! Timings only compare between like clients: a client that does not accept the
+//! recorded Content-Encoding, or a phone replaying a desktop recording, measures a
+//! different page. Each resource keeps the client headers it was recorded with, and
+//! every distinct difference is logged once.
+
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::types::ClientMetadata;
+
+/// A client header that differs materially between recording and playback
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientMismatch {
+    pub header: &'static str,
+    pub recorded: String,
+    pub replaying: String,
+}
+
+/// Warns about each client mismatch the first time it is seen
+#[derive(Debug, Default)]
+pub struct ClientCheck {
+    warned: Mutex<HashSet<ClientMismatch>>,
+}
+
+impl ClientCheck {
+    pub fn check(&self, recorded: &ClientMetadata, headers: &hyper::HeaderMap) {
+        let replaying = ClientMetadata::from_headers(headers).unwrap_or_default();
+        for mismatch in client_mismatches(recorded, &replaying) {
+            if !self.warned.lock().unwrap().insert(mismatch.clone()) {
+                continue;
+            }
+            warn!(
+                "Replaying client differs from the recording client in {}: recorded {:?}, now {:?}; timings may not be comparable",
+                mismatch.header, mismatch.recorded, mismatch.replaying
+            );
+        }
+    }
+}
+
+/// Headers that differ in more than version numbers
+///
+/// Accept-Encoding is compared as a set of codings, a missing one meaning identity
+/// only. The other headers are compared when both clients sent them.
+pub fn client_mismatches(
+    recorded: &ClientMetadata,
+    replaying: &ClientMetadata,
+) -> Vec<ClientMismatch> {
+    let mut mismatches = Vec::new();
+
+    let recorded_codings = codings(recorded.accept_encoding.as_deref());
+    if recorded_codings != codings(replaying.accept_encoding.as_deref()) {
+        mismatches.push(ClientMismatch {
+            header: "Accept-Encoding",
+            recorded: recorded.accept_encoding.clone().unwrap_or_default(),
+            replaying: replaying.accept_encoding.clone().unwrap_or_default(),
+        });
+    }
+
+    let headers = [
+        (
+            "User-Agent",
+            &recorded.user_agent,
+            &replaying.user_agent,
+            true,
+        ),
+        ("Sec-CH-UA", &recorded.sec_ch_ua, &replaying.sec_ch_ua, true),
+        (
+            "Sec-CH-UA-Mobile",
+            &recorded.sec_ch_ua_mobile,
+            &replaying.sec_ch_ua_mobile,
+            false,
+        ),
+        (
+            "Sec-CH-UA-Platform",
+            &recorded.sec_ch_ua_platform,
+            &replaying.sec_ch_ua_platform,
+            false,
+        ),
+        (
+            "Viewport-Width",
+            &recorded.viewport_width,
+            &replaying.viewport_width,
+            false,
+        ),
+    ];
+    for (header, recorded, replaying, ignore_versions) in headers {
+        let (Some(recorded), Some(replaying)) = (recorded, replaying) else {
+            continue;
+        };
+        let differs = if ignore_versions {
+            without_versions(recorded) != without_versions(replaying)
+        } else {
+            recorded.trim() != replaying.trim()
+        };
+        if differs {
+            mismatches.push(ClientMismatch {
+                header,
+                recorded: recorded.clone(),
+                replaying: replaying.clone(),
+            });
+        }
+    }
+
+    mismatches
+}
+
+/// Codings an Accept-Encoding value allows, ignoring `q=0` and identity
+fn codings(accept_encoding: Option<&str>) -> BTreeSet<String> {
+    accept_encoding
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let coding = params.next()?.trim().to_ascii_lowercase();
+            let refused = params.any(|p| {
+                p.trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            (!coding.is_empty() && coding != "identity" && !refused).then_some(coding)
+        })
+        .collect()
+}
+
+/// A browser identification with version numbers removed, so updates do not count
+fn without_versions(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_ascii_digit() && *c != '.' && *c != '_')
+        .collect()
+}
@@ -0,0 +1,102 @@
+//! 304 Not Modified for revalidating clients
+//!
+//! On reload a browser revalidates what it has cached with `If-None-Match` and
+//! `If-Modified-Since`, and the origin answered most of those with a bodiless 304.
+//! Replaying the recorded 200 instead sends every body again and makes reloads look
+//! slower than they were. With `--conditional`, a GET or HEAD whose validators match
+//! the recorded `ETag` or `Last-Modified` is answered with a 304 at the recorded TTFB,
+//! following RFC 9110 §13.
+
+use chrono::DateTime;
+
+use crate::types::Transaction;
+
+/// Headers describing the content, which a 304 has none of
+const CONTENT_HEADERS: &[&str] = &[
+    "content-type",
+    "content-length",
+    "content-encoding",
+    "content-language",
+    "content-range",
+    "content-disposition",
+    "transfer-encoding",
+];
+
+/// Whether `etag` is among the entity tags of an `If-None-Match` value, compared
+/// weakly (`W/"x"` matches `"x"`)
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Whether a response last modified at `last_modified` is unchanged since the
+/// `If-Modified-Since` date
+fn unmodified_since(if_modified_since: &str, last_modified: &str) -> bool {
+    match (
+        DateTime::parse_from_rfc2822(if_modified_since.trim()),
+        DateTime::parse_from_rfc2822(last_modified.trim()),
+    ) {
+        (Ok(since), Ok(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// The 304 answering a `method` request with `headers` in place of `transaction`, or
+/// None when the request is not conditional or its validators do not match
+pub fn not_modified(
+    transaction: &Transaction,
+    method: &str,
+    headers: &hyper::HeaderMap,
+) -> Option<Transaction> {
+    let recorded_ok = transaction.status_code.unwrap_or(200) == 200
+        && transaction.error_kind.is_none()
+        && transaction.url_pattern.is_none();
+    if !matches!(method, "GET" | "HEAD") || !recorded_ok {
+        return None;
+    }
+    let recorded = transaction.raw_headers.as_ref()?;
+    let header = |name: &str| {
+        let values: Vec<&str> = headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        (!values.is_empty()).then(|| values.join(", "))
+    };
+    // If-Modified-Since only counts when there is no If-None-Match
+    let unchanged = match header("if-none-match") {
+        Some(if_none_match) => recorded
+            .get("etag")
+            .is_some_and(|etag| etag_matches(&if_none_match, etag.first())),
+        None => header("if-modified-since").is_some_and(|since| {
+            recorded
+                .get("last-modified")
+                .is_some_and(|modified| unmodified_since(&since, modified.first()))
+        }),
+    };
+    if !unchanged {
+        return None;
+    }
+
+    let mut response_headers = recorded.clone();
+    for name in CONTENT_HEADERS {
+        response_headers.remove(name);
+    }
+    Some(Transaction {
+        status_code: Some(304),
+        reason_phrase: None,
+        raw_headers: Some(response_headers),
+        chunks: Vec::new(),
+        target_close_time: 0,
+        lazy: false,
+        streamed: None,
+        event_stream: None,
+        byte_ranges: None,
+        open_ended: false,
+        timed: false,
+        ..transaction.clone()
+    })
+}
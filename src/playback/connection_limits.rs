@@ -0,0 +1,54 @@
+//! Holding clients to the connections the recording browser used
+//!
+//! A browser that opened two HTTP/1.1 connections to a host could have at most two of
+//! its requests there answered at a time; the rest waited for a connection to free up,
+//! and that wait is part of the TTFBs it observed. With `--limit-connections`, each
+//! client gets that many slots per host, and a response holds one from the moment its
+//! request is sent until the body has been streamed. Hosts the recording reached over
+//! HTTP/2 multiplexed their requests and are not limited.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::types::HostConnections;
+
+#[derive(Debug, Default)]
+pub struct ConnectionLimits {
+    // Recorded connections per host
+    limits: HashMap<String, usize>,
+    // One pool per client and host, created on first request
+    pools: Mutex<HashMap<(IpAddr, String), Arc<Semaphore>>>,
+}
+
+impl ConnectionLimits {
+    pub fn new(hosts: &[HostConnections]) -> Self {
+        let limits = hosts
+            .iter()
+            .filter(|host| !host.multiplexed && host.connections > 0)
+            .map(|host| (host.host.clone(), host.connections as usize))
+            .collect();
+        Self {
+            limits,
+            pools: Mutex::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.limits.is_empty()
+    }
+
+    /// Wait for a free connection of `client` to `host`; None when the host is not limited
+    pub async fn acquire(&self, client: IpAddr, host: &str) -> Option<OwnedSemaphorePermit> {
+        let limit = *self.limits.get(host)?;
+        let pool = self
+            .pools
+            .lock()
+            .unwrap()
+            .entry((client, host.to_string()))
+            .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+            .clone();
+        pool.acquire_owned().await.ok()
+    }
+}
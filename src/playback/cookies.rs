@@ -0,0 +1,117 @@
+//! Cookies a replaying client holds
+//!
+//! Pages answer signed-in and anonymous visitors differently, so a recording that logs
+//! in has the same URL recorded both ways. Recorded requests keep the names of the
+//! cookies they carried, and `--vary-on-cookie session` serves the recording whose
+//! request had the `session` cookie exactly when the client has it now. A client has a
+//! cookie when it sends one, or with `--cookie-jar` when a replayed `Set-Cookie` gave
+//! it one that has not expired or been deleted since, for clients that do not keep
+//! cookies themselves.
+
+use chrono::{DateTime, FixedOffset, Utc};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::types::HttpHeaders;
+
+/// Names of the cookies in a `Cookie` header value
+pub fn cookie_names(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(';')
+        .filter_map(|pair| pair.split_once('=').map(|(name, _)| name.trim()))
+        .filter(|name| !name.is_empty())
+}
+
+/// What a `Set-Cookie` does to the cookie it names
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lifetime {
+    /// Kept until the client goes away
+    Session,
+    /// Kept for this long after the response
+    For(Duration),
+    /// Removed
+    Deleted,
+}
+
+/// The name a `Set-Cookie` value sets and its lifetime
+///
+/// `Expires` is read relative to `date`, the response's recorded `Date`, so a cookie
+/// that lasted an hour when recorded lasts an hour from its replay.
+fn parse_set_cookie(value: &str, date: Option<DateTime<FixedOffset>>) -> Option<(&str, Lifetime)> {
+    let mut parts = value.split(';');
+    let (name, _) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let mut max_age = None;
+    let mut expires = None;
+    for attribute in parts {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let key = key.trim();
+        if key.eq_ignore_ascii_case("max-age") {
+            max_age = value.trim().parse::<i64>().ok();
+        } else if key.eq_ignore_ascii_case("expires") {
+            expires = DateTime::parse_from_rfc2822(value.trim()).ok();
+        }
+    }
+    // Max-Age wins over Expires
+    let lifetime = match (max_age, expires) {
+        (Some(seconds), _) if seconds <= 0 => Lifetime::Deleted,
+        (Some(seconds), _) => Lifetime::For(Duration::from_secs(seconds as u64)),
+        (None, Some(expires)) => {
+            let reference = date.unwrap_or_else(|| Utc::now().fixed_offset());
+            match (expires - reference).to_std() {
+                Ok(remaining) if !remaining.is_zero() => Lifetime::For(remaining),
+                _ => Lifetime::Deleted,
+            }
+        }
+        (None, None) => Lifetime::Session,
+    };
+    Some((name, lifetime))
+}
+
+/// Cookies each client was given by replayed responses, with when they expire
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    clients: Mutex<HashMap<IpAddr, HashMap<String, Option<Instant>>>>,
+}
+
+impl CookieJar {
+    /// Take in the `Set-Cookie` headers of a response replayed to `client` at `now`
+    pub fn store(&self, client: IpAddr, headers: &HttpHeaders, now: Instant) {
+        let Some(set_cookies) = headers.get("set-cookie") else {
+            return;
+        };
+        let date = headers
+            .get("date")
+            .and_then(|date| DateTime::parse_from_rfc2822(date.first()).ok());
+        let mut clients = self.clients.lock().unwrap();
+        let jar = clients.entry(client).or_default();
+        for value in set_cookies.as_vec() {
+            match parse_set_cookie(value, date) {
+                Some((name, Lifetime::Session)) => {
+                    jar.insert(name.to_string(), None);
+                }
+                Some((name, Lifetime::For(duration))) => {
+                    jar.insert(name.to_string(), Some(now + duration));
+                }
+                Some((name, Lifetime::Deleted)) => {
+                    jar.remove(name);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Whether `client` holds cookie `name` at `now`
+    pub fn holds(&self, client: IpAddr, name: &str, now: Instant) -> bool {
+        let clients = self.clients.lock().unwrap();
+        clients
+            .get(&client)
+            .and_then(|jar| jar.get(name))
+            .is_some_and(|expires| expires.is_none_or(|expires| now < expires))
+    }
+}
@@ -0,0 +1,54 @@
+//! How requests that failed while recording are replayed
+//!
+//! A request the recording proxy could not forward is recorded with its `errorKind`.
+//! By default playback answers it as the recording proxy answered the browser: a 502,
+//! 504 or 500 carrying the error message. With `--error-replay connection` it fails the
+//! way the origin did instead: once the recorded time has passed, the connection is
+//! closed without a response, which is how a refused, reset, timed-out or failed TLS
+//! connection looks to a client that reaches the origin directly. A body that broke off
+//! while recording is always replayed as far as it arrived and then cut off.
+
+use bytes::Bytes;
+use futures::stream;
+use hudsucker::Body;
+
+use crate::types::ErrorKind;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorReplay {
+    /// Answer with the status the recording proxy answered with
+    #[default]
+    Status,
+    /// Close the connection without a response
+    Connection,
+}
+
+impl ErrorReplay {
+    /// Whether a request recorded as failing with `kind` is answered by closing the
+    /// connection; the proxy's own errors keep their status either way
+    pub fn closes_connection(self, kind: ErrorKind) -> bool {
+        self == ErrorReplay::Connection && kind != ErrorKind::Internal
+    }
+}
+
+/// The I/O error a body stream ends with to reproduce a failure of `kind`
+pub fn io_error(kind: ErrorKind, message: &str) -> std::io::Error {
+    let io_kind = match kind {
+        ErrorKind::ConnectionRefused => std::io::ErrorKind::ConnectionRefused,
+        ErrorKind::ResetByPeer => std::io::ErrorKind::ConnectionReset,
+        ErrorKind::ConnectTimeout | ErrorKind::ReadTimeout => std::io::ErrorKind::TimedOut,
+        ErrorKind::TruncatedBody => std::io::ErrorKind::UnexpectedEof,
+        ErrorKind::DnsFailure | ErrorKind::TlsError | ErrorKind::Internal => {
+            std::io::ErrorKind::Other
+        }
+    };
+    std::io::Error::new(io_kind, message.to_string())
+}
+
+/// A body that fails when first read, so hyper drops the connection before the
+/// response is complete
+pub fn aborted_body(kind: ErrorKind, message: &str) -> Body {
+    let failed: Result<Bytes, std::io::Error> = Err(io_error(kind, message));
+    Body::from_stream(stream::iter([failed]))
+}
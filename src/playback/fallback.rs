@@ -0,0 +1,152 @@
+//! Answers for requests the inventory has no recording of
+//!
+//! An inventory recorded from one page visit rarely covers every URL a later visit
+//! asks for. `--fallback origin` forwards such requests to the live server instead of
+//! answering 404, and `--fallback-record` keeps those responses so they are added to
+//! the inventory when playback stops; `--fallback stub` answers with an empty 200.
+
+use http_body_util::{BodyExt, Full};
+use hudsucker::{
+    Body,
+    hyper::{Response, StatusCode},
+};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::types::{ContentEncodingType, HttpHeaders, Resource};
+
+/// What playback answers when no recording matches a request
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fallback {
+    /// 404 Not Found
+    #[default]
+    Error,
+    /// Forward the request to the live origin
+    Origin,
+    /// An empty 200 with a Content-Type guessed from the URL
+    Stub,
+}
+
+/// Content-Type of a stub, from the extension of the URL path
+fn stub_content_type(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        None | Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "application/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+pub fn stub_response(url: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", stub_content_type(url))
+        .header("content-length", "0")
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Client, method and URI of a forwarded request, to find it again from its response
+type RequestKey = (SocketAddr, String, String);
+
+/// Responses of forwarded requests, kept as resources of a recording
+#[derive(Debug)]
+pub struct FallbackRecorder {
+    started: Instant,
+    // When each forwarded request was sent, and the URL it is recorded under
+    forwarded: Mutex<HashMap<RequestKey, (Instant, String)>>,
+    resources: Mutex<Vec<Resource>>,
+}
+
+impl Default for FallbackRecorder {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            forwarded: Mutex::default(),
+            resources: Mutex::default(),
+        }
+    }
+}
+
+impl FallbackRecorder {
+    /// Note a request for `uri` being forwarded now, to be recorded as `url`
+    pub fn forwarded(&self, client: SocketAddr, method: &str, uri: &str, url: &str) {
+        self.forwarded.lock().unwrap().insert(
+            (client, method.to_string(), uri.to_string()),
+            (Instant::now(), url.to_string()),
+        );
+    }
+
+    /// Keep the response of a forwarded request, passing it on with the buffered body
+    ///
+    /// Responses to requests that were not forwarded are returned untouched.
+    pub async fn capture(
+        &self,
+        client: SocketAddr,
+        method: &str,
+        uri: &str,
+        res: Response<Body>,
+    ) -> Response<Body> {
+        let key = (client, method.to_string(), uri.to_string());
+        let Some((request_start, url)) = self.forwarded.lock().unwrap().remove(&key) else {
+            return res;
+        };
+        let ttfb_ms = request_start.elapsed().as_millis() as u64;
+
+        let (parts, body) = res.into_parts();
+        let body = match body.collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(e) => {
+                warn!("Failed to read the origin's response for {}: {}", url, e);
+                return Response::from_parts(parts, Body::empty());
+            }
+        };
+
+        let mut resource = Resource::new(method.to_string(), url.clone());
+        resource.request_start_ms =
+            Some(request_start.duration_since(self.started).as_millis() as u64);
+        resource.ttfb_ms = ttfb_ms;
+        resource.duration_ms =
+            Some((request_start.elapsed().as_millis() as u64).saturating_sub(ttfb_ms));
+        resource.status_code = Some(parts.status.as_u16());
+        let mut headers = HttpHeaders::new();
+        for (name, value) in parts.headers.iter() {
+            if let Ok(value) = value.to_str() {
+                headers.append(name.as_str(), value.to_string());
+            }
+        }
+        resource.raw_headers = Some(headers);
+        resource.content_encoding = parts
+            .headers
+            .get("content-encoding")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<ContentEncodingType>().ok());
+        resource.content_type_mime = parts
+            .headers
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .map(|ct| ct.split(';').next().unwrap_or(ct).trim().to_string());
+        resource.raw_body = Some(body.to_vec());
+        info!("Recorded fallback response {} {}", method, url);
+        self.resources.lock().unwrap().push(resource);
+
+        Response::from_parts(parts, Body::from(Full::new(body)))
+    }
+
+    /// The resources recorded so far, leaving none
+    pub fn take_resources(&self) -> Vec<Resource> {
+        std::mem::take(&mut *self.resources.lock().unwrap())
+    }
+}
@@ -0,0 +1,158 @@
+//! External programs that rewrite responses before they are served
+//!
+//! `--filter-cmd` runs a command through the shell for every matched response. It gets
+//! the response as one JSON object on stdin and prints the response to serve as JSON on
+//! stdout; fields it leaves out keep their recorded values. Bodies are passed as
+//! `bodyUtf8` when they are uncompressed text, else as `bodyBase64`.
+
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+use super::transaction::{has_content_encoding, with_body};
+use crate::types::{HttpHeaders, Transaction};
+
+/// What the filter reads on stdin
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilterInput<'a> {
+    method: &'a str,
+    url: &'a str,
+    request_url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<&'a HttpHeaders>,
+    ttfb_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_utf8: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+}
+
+/// What the filter prints on stdout
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FilterOutput {
+    status_code: Option<u16>,
+    headers: Option<HttpHeaders>,
+    ttfb_ms: Option<u64>,
+    body_utf8: Option<String>,
+    body_base64: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FilterCommand {
+    command: String,
+}
+
+impl FilterCommand {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+
+    /// The transaction as the filter rewrote it
+    ///
+    /// Bodies streamed from disk are too large to pipe through and are served as
+    /// recorded.
+    pub async fn apply(&self, transaction: &Transaction, request_url: &str) -> Result<Transaction> {
+        if transaction.streamed.is_some() {
+            return Ok(transaction.clone());
+        }
+
+        let body: Vec<u8> = transaction
+            .chunks
+            .iter()
+            .flat_map(|c| c.chunk.iter().copied())
+            .collect();
+        let text = std::str::from_utf8(&body)
+            .ok()
+            .filter(|_| !has_content_encoding(transaction));
+        let input = FilterInput {
+            method: &transaction.method,
+            url: &transaction.url,
+            request_url,
+            status_code: transaction.status_code,
+            headers: transaction.raw_headers.as_ref(),
+            ttfb_ms: transaction.ttfb,
+            body_utf8: text,
+            body_base64: text
+                .is_none()
+                .then(|| general_purpose::STANDARD.encode(&body)),
+        };
+
+        let output = self.run(&serde_json::to_vec(&input)?).await?;
+        let output: FilterOutput = serde_json::from_slice(&output).with_context(|| {
+            format!("--filter-cmd printed invalid JSON for {}", transaction.url)
+        })?;
+
+        let mut filtered = transaction.clone();
+        if let Some(status_code) = output.status_code {
+            filtered.status_code = Some(status_code);
+        }
+        if let Some(headers) = output.headers {
+            filtered.raw_headers = Some(headers);
+        }
+        if let Some(ttfb_ms) = output.ttfb_ms {
+            filtered.ttfb = ttfb_ms;
+        }
+        let new_body = match (output.body_utf8, output.body_base64) {
+            (Some(text), _) => Some(Bytes::from(text)),
+            (None, Some(base64)) => Some(Bytes::from(general_purpose::STANDARD.decode(base64)?)),
+            (None, None) => None,
+        };
+        Ok(match new_body {
+            Some(body) => with_body(&filtered, body),
+            None => filtered,
+        })
+    }
+
+    /// Run the command with `input` on stdin and return its stdout
+    async fn run(&self, input: &[u8]) -> Result<Vec<u8>> {
+        pipe_through(&self.command, "--filter-cmd", input).await
+    }
+}
+
+/// Run `command`, given as `option`, through the shell with `input` on stdin and return
+/// its stdout
+pub async fn pipe_through(command: &str, option: &str, input: &[u8]) -> Result<Vec<u8>> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start {} {:?}", option, command))?;
+
+    // Written while stdout is read, so a program that streams cannot block on a full pipe
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let write = async move {
+        let written = stdin.write_all(input).await;
+        drop(stdin);
+        written
+    };
+    let (written, output) = tokio::join!(write, child.wait_with_output());
+    let output = output?;
+    if !output.status.success() {
+        anyhow::bail!("{} {:?} exited with {}", option, command, output.status);
+    }
+    written?;
+    Ok(output.stdout)
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> tokio::process::Command {
+    let mut shell = tokio::process::Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> tokio::process::Command {
+    let mut shell = tokio::process::Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
@@ -0,0 +1,192 @@
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use crate::playback::filter::FilterCommand;
+    use crate::playback::transform::TransformCommand;
+    use crate::types::{BodyChunk, HeaderValue, HttpHeaders, Transaction};
+    use bytes::Bytes;
+
+    fn response(body: &str) -> Transaction {
+        let mut headers = HttpHeaders::new();
+        headers.set(
+            "content-type",
+            HeaderValue::Single("text/plain".to_string()),
+        );
+        headers.set(
+            "content-length",
+            HeaderValue::Single(body.len().to_string()),
+        );
+        Transaction {
+            method: "GET".to_string(),
+            url: "https://example.com/greeting".to_string(),
+            ttfb: 50,
+            status_code: Some(200),
+            reason_phrase: None,
+            error_kind: None,
+            error_message: None,
+            raw_headers: Some(headers),
+            chunks: vec![BodyChunk {
+                chunk: Bytes::from(body.to_string()),
+                target_time: 0,
+            }],
+            target_close_time: 100,
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability: None,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: false,
+            request_headers: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filter_rewrites_body() {
+        let filter = FilterCommand::new("sed 's/Hello/Goodbye/'".to_string());
+        let filtered = filter
+            .apply(&response("Hello, world"), "https://example.com/greeting")
+            .await
+            .unwrap();
+
+        let body: Vec<u8> = filtered
+            .chunks
+            .iter()
+            .flat_map(|c| c.chunk.iter().copied())
+            .collect();
+        assert_eq!(body, b"Goodbye, world");
+        let headers = filtered.raw_headers.unwrap();
+        assert_eq!(headers.get("content-length").unwrap().as_vec(), vec!["14"]);
+        // Fields the filter echoes unchanged keep their recorded values
+        assert_eq!(filtered.status_code, Some(200));
+        assert_eq!(filtered.ttfb, 50);
+    }
+
+    #[tokio::test]
+    async fn test_filter_overrides_status_and_failures_surface() {
+        let filter =
+            FilterCommand::new(r#"cat > /dev/null; echo '{"statusCode": 503}'"#.to_string());
+        let filtered = filter
+            .apply(&response("Hello"), "https://example.com/greeting")
+            .await
+            .unwrap();
+        assert_eq!(filtered.status_code, Some(503));
+        assert_eq!(filtered.chunks[0].chunk, Bytes::from("Hello"));
+
+        let failing = FilterCommand::new("cat > /dev/null; exit 3".to_string());
+        assert!(
+            failing
+                .apply(&response("Hello"), "https://example.com/greeting")
+                .await
+                .is_err()
+        );
+        let garbage = FilterCommand::new("cat > /dev/null; echo nope".to_string());
+        assert!(
+            garbage
+                .apply(&response("Hello"), "https://example.com/greeting")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transform_edits_content_files() {
+        use crate::playback::transaction::{
+            ChunkPacing, PreloadPolicy, TransactionLoader, prepare_transactions,
+        };
+        use crate::traits::mocks::MockFileSystem;
+        use crate::types::{Inventory, Resource};
+        use std::path::Path;
+        use std::sync::Arc;
+
+        let inventory_dir = Path::new("/inventory");
+        let fs = Arc::new(MockFileSystem::new());
+        let mut inventory = Inventory::new();
+        for (url, mime, body) in [
+            (
+                "https://example.com/",
+                "text/html",
+                "<html><head></head><body>Hi</body></html>",
+            ),
+            (
+                "https://example.com/app.js",
+                "application/javascript",
+                "run()",
+            ),
+        ] {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            resource.status_code = Some(200);
+            resource.content_type_mime = Some(mime.to_string());
+            let file_path = format!("contents/{}", mime.replace('/', "-"));
+            fs.set_file(
+                &inventory_dir.join(&file_path).to_string_lossy(),
+                body.as_bytes().to_vec(),
+            );
+            resource.content_file_path = Some(file_path);
+            inventory.resources.push(resource);
+        }
+
+        // Injects a script into pages and prints nothing for everything else
+        let transform = TransformCommand::new(
+            r#"input=$(cat); case "$input" in *'"mimeType":"text/html"'*) printf '%s' "$input" | sed 's/.*"bodyUtf8":"\(.*\)"}$/\1/; s#</head>#<script src=/ab.js></script></head>#' ;; esac"#
+                .to_string(),
+        );
+        let body = |transaction: &Transaction| -> Vec<u8> {
+            transaction
+                .chunks
+                .iter()
+                .flat_map(|c| c.chunk.iter().copied())
+                .collect()
+        };
+
+        let preloaded = prepare_transactions(
+            &inventory,
+            inventory_dir,
+            fs.clone(),
+            &PreloadPolicy::All,
+            &ChunkPacing::default(),
+            Some(&transform),
+        )
+        .await
+        .unwrap();
+        let injected = b"<html><head><script src=/ab.js></script></head><body>Hi</body></html>";
+        assert_eq!(body(&preloaded[0]), injected);
+        let headers = preloaded[0].raw_headers.as_ref().unwrap();
+        assert_eq!(
+            headers.get("content-length").unwrap().as_vec(),
+            vec![injected.len().to_string()]
+        );
+        assert_eq!(body(&preloaded[1]), b"run()");
+
+        // Bodies loaded on demand go through it too
+        let lazy = prepare_transactions(
+            &inventory,
+            inventory_dir,
+            fs.clone(),
+            &PreloadPolicy::None,
+            &ChunkPacing::default(),
+            Some(&transform),
+        )
+        .await
+        .unwrap();
+        let loader = TransactionLoader::new(inventory.resources.clone(), inventory_dir.into(), fs)
+            .with_transform(Some(transform));
+        let loaded = loader.materialize(Arc::new(lazy[0].clone())).await.unwrap();
+        assert_eq!(body(&loaded), injected);
+
+        let failing = TransformCommand::new("cat > /dev/null; exit 3".to_string());
+        assert!(
+            failing
+                .apply(&inventory.resources[1], Bytes::from("run()"))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Response header rules kept with the inventory
+//!
+//! A test against a replayed page often needs different response headers than the
+//! origin sent: no CSP so an injected script runs, `Cache-Control: no-store` on a
+//! bundle, CORS headers for a page served from another origin. `header-overrides.yaml`
+//! in the inventory directory lists rules applied to the headers of every matching
+//! response when playback loads the inventory, so `index.json` keeps the headers as
+//! recorded:
+//!
+//! ```yaml
+//! rules:
+//!   - url: "https://example.com/**"
+//!     remove: [Content-Security-Policy]
+//!     set:
+//!       Cache-Control: no-store
+//!     add:
+//!       Access-Control-Allow-Origin: "*"
+//! ```
+//!
+//! `url` is a glob over the whole URL (`*` within one path segment, `**` across them),
+//! or a regex with `urlMatch: regex`; a rule without `url` applies to every response.
+//! Every matching rule applies in file order: its headers are removed, then set
+//! (replacing any recorded value in place), then added (after any recorded value, as
+//! for `Set-Cookie`).
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::traits::FileSystem;
+use crate::types::{HeaderValue, HttpHeaders, Inventory, UrlMatch};
+use crate::url_pattern::UrlPattern;
+
+/// File name of the rules in the inventory directory
+pub const HEADER_OVERRIDES_FILE: &str = "header-overrides.yaml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderOverrideRules {
+    #[serde(default)]
+    pub rules: Vec<HeaderOverrideRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct HeaderOverrideRule {
+    /// URLs the rule applies to (default: every URL)
+    pub url: Option<String>,
+    /// How `url` is matched (default: glob)
+    #[serde(default = "default_url_match")]
+    pub url_match: UrlMatch,
+    /// Header names (any case) removed from the response
+    #[serde(default)]
+    pub remove: Vec<String>,
+    /// Headers replacing the recorded ones of the same name
+    #[serde(default)]
+    pub set: IndexMap<String, String>,
+    /// Headers added after the recorded ones of the same name
+    #[serde(default)]
+    pub add: IndexMap<String, String>,
+}
+
+fn default_url_match() -> UrlMatch {
+    UrlMatch::Glob
+}
+
+/// Compiled header override rules
+#[derive(Debug, Default)]
+pub struct HeaderOverrides {
+    rules: Vec<(UrlFilter, HeaderOverrideRule)>,
+}
+
+#[derive(Debug)]
+enum UrlFilter {
+    Any,
+    Exact(String),
+    Pattern(UrlPattern),
+}
+
+impl UrlFilter {
+    fn is_match(&self, url: &str) -> bool {
+        match self {
+            UrlFilter::Any => true,
+            UrlFilter::Exact(exact) => exact == url,
+            UrlFilter::Pattern(pattern) => pattern.is_match(url),
+        }
+    }
+}
+
+impl HeaderOverrides {
+    pub fn new(rules: HeaderOverrideRules) -> Result<Self> {
+        let rules = rules
+            .rules
+            .into_iter()
+            .map(|rule| {
+                let filter = match &rule.url {
+                    None => UrlFilter::Any,
+                    Some(url) => match UrlPattern::new(url, rule.url_match)? {
+                        Some(pattern) => UrlFilter::Pattern(pattern),
+                        None => UrlFilter::Exact(url.clone()),
+                    },
+                };
+                Ok((filter, rule))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    /// The rules in `inventory_dir`, None when it has no rules file
+    pub async fn load<F: FileSystem>(
+        inventory_dir: &Path,
+        file_system: &F,
+    ) -> Result<Option<Self>> {
+        let path = inventory_dir.join(HEADER_OVERRIDES_FILE);
+        if !file_system.exists(&path).await {
+            return Ok(None);
+        }
+        let rules = file_system.read_to_string(&path).await?;
+        let rules: HeaderOverrideRules =
+            serde_yaml::from_str(&rules).with_context(|| format!("Failed to parse {:?}", path))?;
+        Self::new(rules).map(Some)
+    }
+
+    /// Rewrite the headers of every matching resource, returning how many changed
+    pub fn apply(&self, inventory: &mut Inventory) -> usize {
+        let mut changed = 0;
+        for resource in &mut inventory.resources {
+            let recorded = resource.raw_headers.clone().unwrap_or_default();
+            let mut headers = recorded.clone();
+            for (filter, rule) in &self.rules {
+                if filter.is_match(&resource.url) {
+                    apply_rule(rule, &mut headers);
+                }
+            }
+            if headers != recorded {
+                resource.raw_headers = Some(headers);
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
+
+fn apply_rule(rule: &HeaderOverrideRule, headers: &mut HttpHeaders) {
+    for name in &rule.remove {
+        headers.remove(name);
+    }
+    for (name, value) in &rule.set {
+        headers.set(name, HeaderValue::Single(value.clone()));
+    }
+    for (name, value) in &rule.add {
+        headers.append(name, value.clone());
+    }
+}
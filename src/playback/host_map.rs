@@ -0,0 +1,183 @@
+//! Serving a recording under other hostnames
+//!
+//! Recordings are matched by host, so a recording of `www.example.com` answers nothing
+//! once the same pages are opened on `staging.example.com` or a local port. Each
+//! `--map-host FROM=TO` answers requests for FROM (with its port, if any) from the
+//! recordings of TO, as if the client had asked TO. Replayed responses are rewritten to
+//! keep the client on FROM: a `Location` on TO points to the same path on FROM, and a
+//! `Set-Cookie` whose `Domain` does not cover FROM loses it, so the browser keeps the
+//! cookie for FROM. Bodies are served as recorded.
+
+use anyhow::Result;
+
+use crate::types::{HeaderValue, Transaction};
+
+#[derive(Debug, Clone)]
+struct HostMapping {
+    /// Requested host, lowercased, with its port if any
+    from: String,
+    /// Recorded host, lowercased, with its port if any
+    to: String,
+    /// Scheme of the recorded URLs, when it is not the request's
+    to_scheme: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HostMap {
+    mappings: Vec<HostMapping>,
+}
+
+/// A request for a mapped host, and how its responses are sent back
+#[derive(Debug, Clone)]
+pub struct MappedRequest {
+    /// The request's URL on the recorded host
+    pub url: String,
+    /// The recorded host recordings are matched against
+    pub host: String,
+    /// Scheme and host the client asked for, e.g. `http://localhost:8080`
+    requested_origin: String,
+    requested_host: String,
+}
+
+impl HostMap {
+    /// Parse `--map-host FROM=TO` values, TO optionally with the scheme of its
+    /// recordings (`localhost:8080=https://www.example.com`)
+    ///
+    /// None when no hosts are mapped.
+    pub fn new(specs: &[String]) -> Result<Option<Self>> {
+        if specs.is_empty() {
+            return Ok(None);
+        }
+        let mappings = specs
+            .iter()
+            .map(|spec| {
+                let invalid = || anyhow::anyhow!("Invalid --map-host {:?}: expected FROM=TO", spec);
+                let (from, to) = spec.split_once('=').ok_or_else(invalid)?;
+                let (to_scheme, to) = match to.split_once("://") {
+                    Some((scheme @ ("http" | "https"), host)) => (Some(scheme.to_string()), host),
+                    Some(_) => return Err(invalid()),
+                    None => (None, to),
+                };
+                let (from, to) = (from.trim(), to.trim().trim_end_matches('/'));
+                let is_host = |host: &str| !host.is_empty() && !host.contains(['/', '?', '#', ' ']);
+                if !is_host(from) || !is_host(to) {
+                    return Err(invalid());
+                }
+                Ok(HostMapping {
+                    from: from.to_ascii_lowercase(),
+                    to: to.to_ascii_lowercase(),
+                    to_scheme,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(Self { mappings }))
+    }
+
+    /// How a request for `url` is answered, None when its host is not mapped
+    pub fn map_request(&self, url: &str) -> Option<MappedRequest> {
+        let (scheme, host, rest) = split_origin(url)?;
+        let requested = host.to_ascii_lowercase();
+        let mapping = self.mappings.iter().find(|m| m.from == requested)?;
+        let to_scheme = mapping.to_scheme.as_deref().unwrap_or(scheme);
+        Some(MappedRequest {
+            url: format!("{}://{}{}", to_scheme, mapping.to, rest),
+            host: mapping.to.clone(),
+            requested_origin: format!("{}://{}", scheme, requested),
+            requested_host: without_port(&requested).to_string(),
+        })
+    }
+}
+
+impl MappedRequest {
+    /// `transaction` with its `Location` and cookie domains pointing back to the
+    /// requested host, None when it needs no change
+    pub fn rewrite_response(&self, transaction: &Transaction) -> Option<Transaction> {
+        let recorded = transaction.raw_headers.as_ref()?;
+        let mut headers = recorded.clone();
+
+        let location = recorded
+            .get("location")
+            .and_then(|location| self.requested_location(location.first()));
+        if let Some(location) = location {
+            headers.set("location", HeaderValue::Single(location));
+        }
+
+        if let Some(cookies) = recorded.get("set-cookie") {
+            let rewritten = match cookies {
+                HeaderValue::Single(cookie) => HeaderValue::Single(self.requested_cookie(cookie)),
+                HeaderValue::Multiple(cookies) => HeaderValue::Multiple(
+                    cookies
+                        .iter()
+                        .map(|cookie| self.requested_cookie(cookie))
+                        .collect(),
+                ),
+            };
+            headers.set("set-cookie", rewritten);
+        }
+
+        if headers == *recorded {
+            return None;
+        }
+        let mut rewritten = transaction.clone();
+        rewritten.raw_headers = Some(headers);
+        Some(rewritten)
+    }
+
+    /// A redirect target on the recorded host moved to the requested one
+    fn requested_location(&self, location: &str) -> Option<String> {
+        // Protocol-relative targets keep the scheme the client used
+        if let Some(target) = location.strip_prefix("//") {
+            let (host, rest) = split_host(target);
+            let (_, requested) = self.requested_origin.split_once("://")?;
+            return host
+                .eq_ignore_ascii_case(&self.host)
+                .then(|| format!("//{}{}", requested, rest));
+        }
+        let (_, host, rest) = split_origin(location)?;
+        host.eq_ignore_ascii_case(&self.host)
+            .then(|| format!("{}{}", self.requested_origin, rest))
+    }
+
+    /// `cookie` without a `Domain` attribute the requested host is outside of
+    fn requested_cookie(&self, cookie: &str) -> String {
+        let mut parts = cookie.split(';');
+        let pair = parts.next().unwrap_or_default();
+        let attributes: Vec<&str> = parts
+            .filter(|attribute| match attribute.split_once('=') {
+                Some((name, domain)) if name.trim().eq_ignore_ascii_case("domain") => {
+                    let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+                    self.requested_host == domain
+                        || self.requested_host.ends_with(&format!(".{}", domain))
+                }
+                _ => true,
+            })
+            .collect();
+        std::iter::once(pair)
+            .chain(attributes)
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+/// Scheme, host (with port) and the rest of an absolute http(s) URL
+fn split_origin(url: &str) -> Option<(&str, &str, &str)> {
+    let (scheme, target) = url.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+    let (host, rest) = split_host(target);
+    Some((scheme, host, rest))
+}
+
+/// The host (with port) a URL continues with, and what follows it
+fn split_host(target: &str) -> (&str, &str) {
+    target.split_at(target.find(['/', '?', '#']).unwrap_or(target.len()))
+}
+
+fn without_port(host: &str) -> &str {
+    match host.rfind(':') {
+        // An IPv6 literal has colons of its own, inside its brackets
+        Some(colon) if !host[colon..].contains(']') => &host[..colon],
+        _ => host,
+    }
+}
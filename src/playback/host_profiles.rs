@@ -0,0 +1,118 @@
+//! Network conditions per origin
+//!
+//! A page's first-party server, its CDN and its ad network sit behind different paths.
+//! `--host-profile` replays the resources of matching hosts at a fixed bandwidth and
+//! with a minimum TTFB of one round trip, instead of the conditions they were recorded
+//! under. The first matching profile applies; other hosts keep their recorded timing.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::types::Inventory;
+use crate::utils::parse_duration_ms;
+
+#[derive(Debug, Clone)]
+pub struct HostProfile {
+    host: Regex,
+    pub mbps: Option<f64>,
+    pub rtt_ms: Option<u64>,
+}
+
+impl HostProfile {
+    /// Parse a `--host-profile HOST=MBPS[,RTT]` value
+    ///
+    /// HOST may use `*` for any run of characters (`*.cdn.example.com`). Either side of
+    /// the comma may be left empty: `ads.example=,300ms` only sets the round trip.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = || {
+            format!(
+                "Invalid --host-profile {:?}: expected HOST=MBPS[,RTT]",
+                spec
+            )
+        };
+        let (host, conditions) = spec
+            .split_once('=')
+            .filter(|(host, _)| !host.is_empty())
+            .with_context(invalid)?;
+        let (mbps, rtt) = conditions.split_once(',').unwrap_or((conditions, ""));
+
+        let mbps = match mbps.trim() {
+            "" => None,
+            mbps => Some(
+                mbps.trim_end_matches("mbps")
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|mbps| *mbps > 0.0)
+                    .with_context(invalid)?,
+            ),
+        };
+        let rtt_ms = match rtt.trim() {
+            "" => None,
+            rtt => Some(parse_duration_ms(rtt).with_context(invalid)?),
+        };
+        if mbps.is_none() && rtt_ms.is_none() {
+            anyhow::bail!(invalid());
+        }
+
+        let pattern = format!(
+            "^{}$",
+            regex::escape(&host.to_ascii_lowercase()).replace(r"\*", ".*")
+        );
+        Ok(Self {
+            host: Regex::new(&pattern)?,
+            mbps,
+            rtt_ms,
+        })
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        self.host.is_match(&host.to_ascii_lowercase())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HostProfiles {
+    profiles: Vec<HostProfile>,
+}
+
+impl HostProfiles {
+    pub fn new(specs: &[String]) -> Result<Self> {
+        let profiles = specs
+            .iter()
+            .map(|spec| HostProfile::parse(spec))
+            .collect::<Result<_>>()?;
+        Ok(Self { profiles })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    /// Replay each resource of a profiled host at the profile's conditions
+    ///
+    /// The bandwidth replaces the recorded transfer duration, and TTFBs shorter than
+    /// the round trip are raised to it. Returns how many resources were affected.
+    pub fn apply(&self, inventory: &mut Inventory) -> usize {
+        let mut adjusted = 0;
+        for resource in &mut inventory.resources {
+            let Ok(uri) = resource.url.parse::<hyper::Uri>() else {
+                continue;
+            };
+            let Some(profile) = uri
+                .host()
+                .and_then(|host| self.profiles.iter().find(|p| p.matches(host)))
+            else {
+                continue;
+            };
+            if let Some(mbps) = profile.mbps {
+                resource.mbps = Some(mbps);
+                resource.duration_ms = None;
+            }
+            if let Some(rtt_ms) = profile.rtt_ms {
+                resource.ttfb_ms = resource.ttfb_ms.max(rtt_ms);
+            }
+            adjusted += 1;
+        }
+        adjusted
+    }
+}
@@ -1,55 +1,462 @@
-use bytes::Bytes;
-use http_body_util::StreamBody;
+use http_body::Body as _;
+use http_body_util::{BodyExt, Full, StreamBody};
 use hudsucker::{
     Body, HttpContext, HttpHandler, RequestOrResponse,
     hyper::{Request, Response, StatusCode},
 };
+use std::borrow::Cow;
 use std::future::Future;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
-use tracing::{error, info};
+use tokio::sync::{OwnedSemaphorePermit, RwLock};
+use tracing::{Level, debug, error, info, trace};
 
-use crate::types::Transaction;
+use super::accept_encoding::negotiate_encoding;
+use super::byte_ranges;
+use super::client_check::ClientCheck;
+use super::conditional::not_modified;
+use super::connection_limits::ConnectionLimits;
+use super::cookies::{CookieJar, cookie_names};
+use super::error_replay::{ErrorReplay, aborted_body, io_error};
+use super::fallback::{Fallback, FallbackRecorder, stub_response};
+use super::filter::FilterCommand;
+use super::host_map::HostMap;
+use super::matching::{
+    MatchRequest, find_matching, prefer_cookies, prefer_request_headers, preflight_strategies,
+};
+use super::network_profile::NetworkLinks;
+use super::open_ended::OpenEndedOptions;
+use super::pacing::{Pacer, TokenBucket};
+use super::rate_limits::RateLimits;
+use super::self_test::SELF_TEST_HEADER;
+use super::sequence::{RepeatMode, Sequences};
+use super::served_log::ServedLog;
+use super::sessions::{Session, Sessions};
+use super::tokens::TokenRewriter;
+use super::transaction::{TransactionLoader, render_template};
+use super::watchdog::{StreamWatch, Watchdog};
+use crate::browser::{ActivityGuard, NetworkActivity};
+use crate::control::{ControlEndpoints, is_control_request};
+use crate::query::QueryOptions;
+use crate::traits::{FileSystem, RealTimeProvider, SleepMode, TimeProvider};
+use crate::types::{ErrorKind, MatchStrategy, StreamEnd, Transaction, UploadTiming};
 use futures::stream;
 use hyper::body::Frame;
 
+/// Emits a behind-schedule summary only once per this many late chunks
+const BEHIND_SCHEDULE_LOG_EVERY: u64 = 100;
+
+/// Count of chunks sent later than their target time, shared by all replays
+static BEHIND_SCHEDULE_CHUNKS: AtomicU64 = AtomicU64::new(0);
+
+/// Diagnostics headers added with `--timing-headers`, in milliseconds from the request
+const TARGET_TTFB_HEADER: &str = "x-hpp-target-ttfb";
+const ACTUAL_TTFB_HEADER: &str = "x-hpp-actual-ttfb";
+/// Index of the served resource in the inventory's `resources`
+const TRANSACTION_ID_HEADER: &str = "x-hpp-transaction-id";
+
 /// Playback handler for Hudsucker MITM proxy
-#[derive(Clone)]
-pub struct PlaybackHandler {
-    transactions: Arc<RwLock<Arc<Vec<Transaction>>>>,
+pub struct PlaybackHandler<F: FileSystem + 'static> {
+    // The main inventory, swapped as a whole when it is reloaded
+    main: Arc<RwLock<Session<F>>>,
     start_time: Arc<Instant>,
+    activity: Arc<NetworkActivity>,
+    synthesize_options: bool,
+    control: Option<Arc<ControlEndpoints>>,
+    served_log: Option<ServedLog>,
+    self_test_log: Option<ServedLog>,
+    // TTFB and chunk deadlines are taken from and waited for on this clock
+    clock: Arc<dyn TimeProvider>,
+    query: QueryOptions,
+    match_form: bool,
+    match_strategies: Arc<[MatchStrategy]>,
+    vary: Arc<[String]>,
+    vary_cookies: Arc<[String]>,
+    cookie_jar: Option<Arc<CookieJar>>,
+    conditional: bool,
+    sequences: Arc<Sequences>,
+    fallback: Fallback,
+    fallback_recorder: Option<Arc<FallbackRecorder>>,
+    watchdog: Option<Arc<Watchdog>>,
+    open_ended: OpenEndedOptions,
+    error_replay: ErrorReplay,
+    tokens: Option<Arc<TokenRewriter>>,
+    filter: Option<Arc<FilterCommand>>,
+    host_map: Option<Arc<HostMap>>,
+    sessions: Arc<Sessions<F>>,
+    client_check: Arc<ClientCheck>,
+    connection_limits: Option<Arc<ConnectionLimits>>,
+    network_links: Option<Arc<NetworkLinks>>,
+    // Shared by every response body, whichever client it goes to
+    bandwidth: Option<Arc<TokenBucket>>,
+    timing_headers: bool,
+    rate_limits: Option<Arc<RateLimits>>,
 }
 
-impl PlaybackHandler {
-    pub fn new(transactions: Vec<Transaction>) -> Self {
+// Manual impl: derive(Clone) would needlessly require F: Clone
+impl<F: FileSystem + 'static> Clone for PlaybackHandler<F> {
+    fn clone(&self) -> Self {
         Self {
-            transactions: Arc::new(RwLock::new(Arc::new(transactions))),
+            main: self.main.clone(),
+            start_time: self.start_time.clone(),
+            activity: self.activity.clone(),
+            synthesize_options: self.synthesize_options,
+            control: self.control.clone(),
+            served_log: self.served_log.clone(),
+            self_test_log: self.self_test_log.clone(),
+            clock: self.clock.clone(),
+            query: self.query,
+            match_form: self.match_form,
+            match_strategies: self.match_strategies.clone(),
+            vary: self.vary.clone(),
+            vary_cookies: self.vary_cookies.clone(),
+            cookie_jar: self.cookie_jar.clone(),
+            conditional: self.conditional,
+            sequences: self.sequences.clone(),
+            fallback: self.fallback,
+            fallback_recorder: self.fallback_recorder.clone(),
+            watchdog: self.watchdog.clone(),
+            open_ended: self.open_ended,
+            error_replay: self.error_replay,
+            tokens: self.tokens.clone(),
+            filter: self.filter.clone(),
+            host_map: self.host_map.clone(),
+            sessions: self.sessions.clone(),
+            client_check: self.client_check.clone(),
+            connection_limits: self.connection_limits.clone(),
+            network_links: self.network_links.clone(),
+            bandwidth: self.bandwidth.clone(),
+            timing_headers: self.timing_headers,
+            rate_limits: self.rate_limits.clone(),
+        }
+    }
+}
+
+impl<F: FileSystem + 'static> PlaybackHandler<F> {
+    pub fn new(transactions: Vec<Transaction>, loader: TransactionLoader<F>) -> Self {
+        Self {
+            main: Arc::new(RwLock::new(Session::new(transactions, loader))),
             start_time: Arc::new(Instant::now()),
+            activity: Arc::new(NetworkActivity::new()),
+            synthesize_options: false,
+            control: None,
+            served_log: None,
+            self_test_log: None,
+            clock: Arc::new(RealTimeProvider::new()),
+            query: QueryOptions::default(),
+            match_form: false,
+            match_strategies: Arc::new([MatchStrategy::Exact]),
+            vary: Arc::new([]),
+            vary_cookies: Arc::new([]),
+            cookie_jar: None,
+            conditional: false,
+            sequences: Arc::new(Sequences::default()),
+            fallback: Fallback::default(),
+            fallback_recorder: None,
+            watchdog: None,
+            open_ended: OpenEndedOptions::default(),
+            error_replay: ErrorReplay::default(),
+            tokens: None,
+            filter: None,
+            host_map: None,
+            sessions: Arc::new(Sessions::default()),
+            client_check: Arc::new(ClientCheck::default()),
+            connection_limits: None,
+            network_links: None,
+            bandwidth: None,
+            timing_headers: false,
+            rate_limits: None,
         }
     }
+
+    /// Record the timing of every served response into `log`
+    pub fn with_served_log(mut self, log: ServedLog) -> Self {
+        self.served_log = Some(log);
+        self
+    }
+
+    /// Record the timing of the proxy's own self-test requests into `log` instead
+    pub fn with_self_test_log(mut self, log: ServedLog) -> Self {
+        self.self_test_log = Some(log);
+        self
+    }
+
+    /// Serve the proxy's own `hpp.local` endpoints ahead of the inventory
+    pub fn with_control(mut self, control: ControlEndpoints) -> Self {
+        self.control = Some(Arc::new(control));
+        self
+    }
+
+    /// Answer unrecorded OPTIONS requests with a permissive CORS preflight response
+    pub fn with_options_synthesis(mut self, enabled: bool) -> Self {
+        self.synthesize_options = enabled;
+        self
+    }
+
+    /// Spin through the last milliseconds of TTFB and chunk waits for sub-ms accuracy
+    pub fn with_precise_timing(self, enabled: bool) -> Self {
+        let sleep_mode = if enabled {
+            SleepMode::Precise
+        } else {
+            SleepMode::Coarse
+        };
+        self.with_time_provider(Arc::new(
+            RealTimeProvider::new().with_sleep_mode(sleep_mode),
+        ))
+    }
+
+    /// Take TTFB and chunk deadlines from `clock` and wait for them on it; playback
+    /// starts at the clock's present
+    pub fn with_time_provider(mut self, clock: Arc<dyn TimeProvider>) -> Self {
+        self.start_time = Arc::new(clock.now());
+        self.clock = clock;
+        self
+    }
+
+    /// Compare request queries with recorded ones in canonical form
+    pub fn with_query_options(mut self, query: QueryOptions) -> Self {
+        self.query = query;
+        self
+    }
+
+    /// Prefer the recording whose multipart fields equal those of the upload
+    pub fn with_form_matching(mut self, enabled: bool) -> Self {
+        self.match_form = enabled;
+        self
+    }
+
+    /// Try these strategies in order to find the recording answering a request; an
+    /// empty list keeps exact matching
+    pub fn with_match_strategies(mut self, strategies: Vec<MatchStrategy>) -> Self {
+        if !strategies.is_empty() {
+            self.match_strategies = strategies.into();
+        }
+        self
+    }
+
+    /// Tell recordings of one URL apart by the values their requests had for these headers
+    pub fn with_vary_headers(mut self, names: Vec<String>) -> Self {
+        self.vary = names.into();
+        self
+    }
+
+    /// Tell recordings of one URL apart by whether their requests carried these cookies
+    pub fn with_vary_cookies(mut self, names: Vec<String>) -> Self {
+        self.vary_cookies = names.into();
+        self
+    }
+
+    /// Track the cookies replayed responses set on each client
+    pub fn with_cookie_jar(mut self, enabled: bool) -> Self {
+        self.cookie_jar = enabled.then(|| Arc::new(CookieJar::default()));
+        self
+    }
+
+    /// Answer revalidations whose validators match the recording with 304
+    pub fn with_conditional(mut self, enabled: bool) -> Self {
+        self.conditional = enabled;
+        self
+    }
+
+    /// What repeated requests get after every recording of their URL has been served
+    pub fn with_repeat_mode(mut self, mode: RepeatMode) -> Self {
+        self.sequences = Arc::new(Sequences::new(mode));
+        self
+    }
+
+    /// Answer requests without a recording this way instead of with 404
+    pub fn with_fallback(mut self, fallback: Fallback) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Keep the responses of requests forwarded to the origin in `recorder`
+    pub fn with_fallback_recorder(mut self, recorder: Option<Arc<FallbackRecorder>>) -> Self {
+        self.fallback_recorder = recorder;
+        self
+    }
+
+    /// Abort bodies that fall behind or stop being read, as `watchdog` decides
+    pub fn with_watchdog(mut self, watchdog: Option<Arc<Watchdog>>) -> Self {
+        self.watchdog = watchdog;
+        self
+    }
+
+    /// Hold or close responses that never ended once their recorded part is sent
+    pub fn with_open_ended(mut self, options: OpenEndedOptions) -> Self {
+        self.open_ended = options;
+        self
+    }
+
+    /// Answer requests that failed while recording with a status or a closed connection
+    pub fn with_error_replay(mut self, error_replay: ErrorReplay) -> Self {
+        self.error_replay = error_replay;
+        self
+    }
+
+    /// Swap recorded session tokens for the ones the client presents
+    pub fn with_token_rewriter(mut self, tokens: Option<TokenRewriter>) -> Self {
+        self.tokens = tokens.map(Arc::new);
+        self
+    }
+
+    /// Let an external program rewrite each matched response before it is served
+    pub fn with_filter_command(mut self, filter: Option<FilterCommand>) -> Self {
+        self.filter = filter.map(Arc::new);
+        self
+    }
+
+    /// Answer requests for mapped hosts from the recordings of the hosts they stand for
+    pub fn with_host_map(mut self, host_map: Option<HostMap>) -> Self {
+        self.host_map = host_map.map(Arc::new);
+        self
+    }
+
+    /// Serve other inventories to requests that select them
+    pub fn with_sessions(mut self, sessions: Sessions<F>) -> Self {
+        self.sessions = Arc::new(sessions);
+        self
+    }
+
+    /// Queue each client's responses behind the connections it recorded per host
+    pub fn with_connection_limits(mut self, limits: Option<ConnectionLimits>) -> Self {
+        self.connection_limits = limits.map(Arc::new);
+        self
+    }
+
+    /// Pace each client's response bodies through one shared download link
+    pub fn with_network_links(mut self, links: Option<NetworkLinks>) -> Self {
+        self.network_links = links.map(Arc::new);
+        self
+    }
+
+    /// Hold the combined rate of all response bodies to `mbps`
+    pub fn with_bandwidth_limit(mut self, mbps: Option<f64>) -> Self {
+        self.bandwidth = mbps.map(|mbps| Arc::new(TokenBucket::for_link(mbps)));
+        self
+    }
+
+    /// Tell clients each response's transaction and its target and actual TTFB
+    pub fn with_timing_headers(mut self, enabled: bool) -> Self {
+        self.timing_headers = enabled;
+        self
+    }
+
+    /// Answer requests over a configured rate limit with 429
+    pub fn with_rate_limits(mut self, limits: Option<RateLimits>) -> Self {
+        self.rate_limits = limits.map(Arc::new);
+        self
+    }
+
+    pub fn get_activity(&self) -> Arc<NetworkActivity> {
+        self.activity.clone()
+    }
+
+    /// The main inventory, for swapping in a reloaded one
+    pub fn get_main(&self) -> Arc<RwLock<Session<F>>> {
+        self.main.clone()
+    }
+
+    pub fn get_sequences(&self) -> Arc<Sequences> {
+        self.sequences.clone()
+    }
 }
 
-impl HttpHandler for PlaybackHandler {
+impl<F: FileSystem + 'static> HttpHandler for PlaybackHandler<F> {
     fn handle_request(
         &mut self,
-        _ctx: &HttpContext,
+        ctx: &HttpContext,
         req: Request<Body>,
     ) -> impl Future<Output = RequestOrResponse> + Send {
-        let transactions = self.transactions.clone();
+        let client_addr = ctx.client_addr;
+        let client_ip = client_addr.ip();
+        let main = self.main.clone();
         let start_time = self.start_time.clone();
+        let activity = self.activity.clone();
+        let synthesize_options = self.synthesize_options;
+        let control = self.control.clone();
+        let served_log = self.served_log.clone();
+        let self_test_log = self.self_test_log.clone();
+        let clock = self.clock.clone();
+        let query = self.query;
+        let match_form = self.match_form;
+        let match_strategies = self.match_strategies.clone();
+        let vary = self.vary.clone();
+        let vary_cookies = self.vary_cookies.clone();
+        let cookie_jar = self.cookie_jar.clone();
+        let conditional = self.conditional;
+        let sequences = self.sequences.clone();
+        let fallback = self.fallback;
+        let fallback_recorder = self.fallback_recorder.clone();
+        let watchdog = self.watchdog.clone();
+        let open_ended = self.open_ended;
+        let error_replay = self.error_replay;
+        let tokens = self.tokens.clone();
+        let filter = self.filter.clone();
+        let host_map = self.host_map.clone();
+        let sessions = self.sessions.clone();
+        let client_check = self.client_check.clone();
+        let connection_limits = self.connection_limits.clone();
+        // Buckets this client's response bodies share with others
+        let shared: Vec<Arc<TokenBucket>> = self
+            .network_links
+            .as_ref()
+            .map(|links| links.link(client_ip))
+            .into_iter()
+            .chain(self.bandwidth.clone())
+            .collect();
+        let timing_headers = self.timing_headers;
+        let rate_limits = self.rate_limits.clone();
 
         async move {
+            if let Some(control) = control.filter(|_| is_control_request(&req)) {
+                return RequestOrResponse::Response(control.respond(&req));
+            }
+
+            // Only form and body-hash matching need the request body; it is buffered and
+            // passed on unchanged should the request be forwarded to a fallback origin
+            let (req, body_sha1) = if match_strategies.contains(&MatchStrategy::BodyHash) {
+                read_body_sha1(req).await
+            } else {
+                (req, None)
+            };
+            let (req, request_form) = if match_form {
+                read_form_fields(req).await
+            } else {
+                (req, None)
+            };
+
+            // TTFB is measured from request arrival, so on-demand loading overlaps the wait
+            let request_received = clock.now();
             let method = req.method().to_string();
             let uri = req.uri().clone();
             let headers = req.headers();
+            // The proxy's own self-test is logged apart and leaves repeats alone
+            let self_test = self_test_log.is_some() && headers.contains_key(SELF_TEST_HEADER);
+            let served_log = if self_test { self_test_log } else { served_log };
+            let accept_encoding = headers
+                .get("accept-encoding")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+            let range = headers
+                .get("range")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+            let if_range = headers
+                .get("if-range")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
 
             // Skip CONNECT requests - they are for tunnel establishment, not actual HTTP requests
             if method == "CONNECT" {
-                info!("Skipping CONNECT request (tunnel): {}", uri);
+                debug!("Skipping CONNECT request (tunnel): {}", uri);
                 return RequestOrResponse::Request(req);
             }
 
+            // Held until the response body has been fully streamed
+            let activity_guard = activity.begin();
+
             // Reconstruct full URL from URI and Host header (including query parameters)
             let url = if uri.scheme().is_some() {
                 // Full URL in request (proxy-style)
@@ -73,40 +480,101 @@ impl HttpHandler for PlaybackHandler {
                 }
             };
 
-            info!(
+            // A mapped host is answered as the recorded host it stands for
+            let mapped = host_map.as_ref().and_then(|map| map.map_request(&url));
+            let url = match &mapped {
+                Some(mapped) => {
+                    debug!("Mapped {} to {}", url, mapped.url);
+                    mapped.url.clone()
+                }
+                None => url,
+            };
+
+            debug!(
                 "Handling playback request: {} {} (reconstructed URL: {})",
                 method, uri, url
             );
 
+            // Refused before matching, so repeated URLs keep their place in the sequence
+            let retry_after = rate_limits
+                .as_ref()
+                .and_then(|limits| limits.check(client_ip, &url, request_received));
+            if let Some(seconds) = retry_after {
+                info!(
+                    "Rate limited {} {} for {} (retry after {}s)",
+                    method, url, client_ip, seconds
+                );
+                return RequestOrResponse::Response(
+                    Response::builder()
+                        .status(StatusCode::TOO_MANY_REQUESTS)
+                        .header("retry-after", seconds.to_string())
+                        .header("content-type", "text/plain")
+                        .body(Body::from("Too Many Requests"))
+                        .unwrap(),
+                );
+            }
+
             // Extract request components for matching
             let request_path = uri.path();
-            let request_query = uri.query();
-            let request_host = headers
-                .get("host")
-                .and_then(|h| h.to_str().ok())
-                .or_else(|| uri.authority().map(|a| a.as_str()));
+            if let Some(tokens) = &tokens {
+                tokens.observe_request(headers, uri.query());
+            }
+            let recorded_query = uri.query().map(|query| match &tokens {
+                Some(tokens) => tokens.recorded_query(query),
+                None => Cow::Borrowed(query),
+            });
+            let request_query = recorded_query.as_deref();
+            let request_host = match &mapped {
+                Some(mapped) => Some(mapped.host.as_str()),
+                None => headers
+                    .get("host")
+                    .and_then(|h| h.to_str().ok())
+                    .or_else(|| uri.authority().map(|a| a.as_str())),
+            };
 
-            info!(
+            debug!(
                 "Looking for transaction: method={}, host={:?}, path={}, query={:?}",
                 method, request_host, request_path, request_query
             );
 
-            // Read transactions with RwLock
-            let transactions_snapshot = {
-                let txn_read = transactions.read().await;
-                txn_read.clone() // Clone the Arc<Vec<Transaction>>
+            // Read the main inventory with RwLock, unless the request selects another session
+            // Connection limits come from the main inventory's recording
+            let (transactions_snapshot, loader, connection_limits) = match sessions
+                .select(headers, client_ip)
+            {
+                Ok(Some(session)) => (session.transactions.clone(), session.loader.clone(), None),
+                Ok(None) => {
+                    let main = main.read().await;
+                    (
+                        main.transactions.clone(),
+                        main.loader.clone(),
+                        connection_limits,
+                    )
+                }
+                Err(name) => {
+                    info!("Unknown playback session {:?} for {} {}", name, method, url);
+                    return RequestOrResponse::Response(
+                        Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::from(format!("Unknown playback session: {}", name)))
+                            .unwrap(),
+                    );
+                }
             };
 
-            info!(
+            debug!(
                 "Total transactions available: {}",
                 transactions_snapshot.len()
             );
 
-            // Debug: List all available transactions
-            for (idx, t) in transactions_snapshot.iter().enumerate() {
-                if let Ok(transaction_uri) = t.url.parse::<hyper::Uri>() {
+            // Listing every candidate parses every URL, so only do it when tracing
+            if tracing::enabled!(Level::TRACE) {
+                for (idx, t) in transactions_snapshot.iter().enumerate() {
+                    let Ok(transaction_uri) = t.url.parse::<hyper::Uri>() else {
+                        continue;
+                    };
                     let t_host = transaction_uri.authority().map(|a| a.as_str());
-                    info!(
+                    trace!(
                         "  Transaction[{}]: method={}, host={:?}, url={}, path={}, query={:?}",
                         idx,
                         t.method,
@@ -118,117 +586,645 @@ impl HttpHandler for PlaybackHandler {
                 }
             }
 
-            let transaction = transactions_snapshot
+            let strategies = preflight_strategies(&method, headers, &match_strategies);
+            let sent_cookies: Vec<&str> = headers
+                .get_all("cookie")
                 .iter()
-                .find(|t| {
-                    // Match method
-                    if t.method != method {
-                        return false;
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(cookie_names)
+                .collect();
+            let has_cookie = |name: &str| {
+                sent_cookies.contains(&name)
+                    || cookie_jar
+                        .as_ref()
+                        .is_some_and(|jar| jar.holds(client_ip, name, request_received))
+            };
+            // Recorded URLs take precedence over hand-authored URL patterns
+            let find = |wanted: &str| {
+                let request = MatchRequest {
+                    method: wanted,
+                    host: request_host,
+                    path: request_path,
+                    query: request_query,
+                    headers: Some(headers),
+                    body_sha1: body_sha1.as_deref(),
+                };
+                find_matching(
+                    &transactions_snapshot,
+                    &request,
+                    &strategies,
+                    &query,
+                    request_form.as_deref(),
+                )
+                .map(|(transaction, strategy)| {
+                    if strategy != MatchStrategy::Exact {
+                        debug!("Matched {} by {:?}", url, strategy);
+                    }
+                    // A recording picked by headers, body or form fields is the answer;
+                    // otherwise repeats of the URL replay in recorded order
+                    let specific =
+                        matches!(strategy, MatchStrategy::Header | MatchStrategy::BodyHash)
+                            || request_form.is_some();
+                    if specific || self_test {
+                        transaction
+                    } else if let Some(preferred) =
+                        prefer_request_headers(&transactions_snapshot, &transaction, headers, &vary)
+                    {
+                        debug!("Matched {} by its request headers", url);
+                        preferred
+                    } else if let Some(preferred) = prefer_cookies(
+                        &transactions_snapshot,
+                        &transaction,
+                        &vary_cookies,
+                        has_cookie,
+                    ) {
+                        debug!("Matched {} by the cookies its client has", url);
+                        preferred
+                    } else {
+                        sequences.next(&transactions_snapshot, transaction)
                     }
+                })
+                .or_else(|| find_pattern_transaction(&transactions_snapshot, wanted, &url))
+            };
 
-                    // Parse transaction URL to extract components
-                    if let Ok(transaction_uri) = t.url.parse::<hyper::Uri>() {
-                        let t_path = transaction_uri.path();
-                        let t_query = transaction_uri.query();
-                        let t_host = transaction_uri.authority().map(|a| a.as_str());
-
-                        // Match host (if available in both request and transaction)
-                        // This prevents cross-origin mismatches
-                        let host_matches = match (request_host, t_host) {
-                            (Some(req_h), Some(t_h)) => req_h == t_h,
-                            // If either is missing, fall back to path-only matching for backward compatibility
-                            _ => true,
-                        };
+            // A HEAD for a URL recorded only as GET is answered from the GET response
+            let mut head_only = false;
+            let mut transaction = find(&method);
+            if transaction.is_none() && method == "HEAD" {
+                transaction = find("GET");
+                head_only = transaction.is_some();
+            }
 
-                        // Match path and query
-                        let matches =
-                            host_matches && t_path == request_path && t_query == request_query;
-                        if matches {
-                            info!("Found matching transaction: {}", t.url);
-                        }
-                        matches
+            if let Some(t) = &transaction {
+                debug!(
+                    "Found matching transaction: {}{}",
+                    t.url,
+                    if head_only {
+                        " (HEAD derived from GET)"
                     } else {
-                        false
+                        ""
                     }
-                })
-                .cloned();
+                );
+            }
+            if let Some(recorded) = transaction.as_ref().and_then(|t| t.request_client.as_ref()) {
+                client_check.check(recorded, headers);
+            }
+
+            // Resources that appeared mid-session are answered as missing until then
+            let unavailable = transaction
+                .as_ref()
+                .and_then(|t| t.availability)
+                .filter(|a| {
+                    clock.now().saturating_duration_since(*start_time)
+                        < Duration::from_millis(a.after_ms)
+                });
+            if let Some(availability) = unavailable {
+                info!(
+                    "{} is not available until {}ms into playback",
+                    url, availability.after_ms
+                );
+                let status =
+                    StatusCode::from_u16(availability.status).unwrap_or(StatusCode::NOT_FOUND);
+                return RequestOrResponse::Response(
+                    Response::builder()
+                        .status(status)
+                        .body(Body::empty())
+                        .unwrap(),
+                );
+            }
+
+            // What the client is given now decides which recordings later requests get
+            let set_cookies = transaction.as_ref().and_then(|t| t.raw_headers.as_ref());
+            if let (Some(jar), Some(headers), false) = (&cookie_jar, set_cookies, self_test) {
+                jar.store(client_ip, headers, request_received);
+            }
 
             match transaction {
-                Some(transaction) => match serve_transaction(transaction, start_time).await {
-                    Ok(response) => RequestOrResponse::Response(response),
-                    Err(e) => {
-                        error!("Error serving transaction: {}", e);
-                        let response = Response::builder()
-                            .status(StatusCode::INTERNAL_SERVER_ERROR)
-                            .body(Body::from(format!("Transaction error: {}", e)))
-                            .unwrap();
-                        RequestOrResponse::Response(response)
+                Some(transaction) => {
+                    // A request queued behind the client's busy connections is sent, and
+                    // its TTFB starts, once one frees up
+                    let connection_host = uri.host().or_else(|| {
+                        request_host.map(|host| host.split(':').next().unwrap_or(host))
+                    });
+                    let (connection, request_sent) =
+                        match connection_limits.as_ref().zip(connection_host) {
+                            Some((limits, host)) => match limits.acquire(client_ip, host).await {
+                                Some(permit) => (Some(permit), clock.now()),
+                                None => (None, request_received),
+                            },
+                            None => (None, request_received),
+                        };
+                    // A revalidation the recording still satisfies is answered without
+                    // loading its body
+                    let unchanged = conditional
+                        .then(|| not_modified(&transaction, &method, headers))
+                        .flatten();
+                    let transaction = match unchanged {
+                        Some(unchanged) => {
+                            debug!("{} is not modified since the client's copy", url);
+                            Arc::new(unchanged)
+                        }
+                        None => transaction,
+                    };
+                    let materialized = match loader.materialize(transaction).await {
+                        Ok(transaction) => {
+                            // An object recorded in ranges answers with the requested slice
+                            let transaction = match &transaction.byte_ranges {
+                                Some(_) => {
+                                    Arc::new(byte_ranges::select(&transaction, range.as_deref()))
+                                }
+                                None => transaction,
+                            };
+                            let transaction = match render_template(&transaction, &method, &url) {
+                                Some(rendered) => Arc::new(rendered),
+                                None => transaction,
+                            };
+                            let transaction =
+                                match tokens.as_ref().and_then(|t| t.apply(&transaction)) {
+                                    Some(rewritten) => Arc::new(rewritten),
+                                    None => transaction,
+                                };
+                            let transaction = match mapped
+                                .as_ref()
+                                .and_then(|m| m.rewrite_response(&transaction))
+                            {
+                                Some(rewritten) => Arc::new(rewritten),
+                                None => transaction,
+                            };
+                            let filtered = match &filter {
+                                Some(filter) => {
+                                    filter.apply(&transaction, &url).await.map(Arc::new)
+                                }
+                                None => Ok(transaction),
+                            };
+                            let negotiated = filtered.and_then(|transaction| {
+                                match negotiate_encoding(&transaction, accept_encoding.as_deref())?
+                                {
+                                    Some(negotiated) => {
+                                        debug!(
+                                            "Re-encoded {} for Accept-Encoding {:?}",
+                                            url, accept_encoding
+                                        );
+                                        Ok(Arc::new(negotiated))
+                                    }
+                                    None => Ok(transaction),
+                                }
+                            });
+                            // A GET for a range of a body recorded whole gets that slice
+                            // of what would have been sent
+                            negotiated.map(|transaction| {
+                                let slice = (method == "GET")
+                                    .then(|| {
+                                        byte_ranges::select_whole(
+                                            &transaction,
+                                            range.as_deref(),
+                                            if_range.as_deref(),
+                                        )
+                                    })
+                                    .flatten();
+                                match slice {
+                                    Some(slice) => {
+                                        debug!(
+                                            "Serving {} of {}",
+                                            range.as_deref().unwrap_or_default(),
+                                            url
+                                        );
+                                        Arc::new(slice)
+                                    }
+                                    None => transaction,
+                                }
+                            })
+                        }
+                        Err(e) => Err(e),
+                    };
+                    let served = match materialized {
+                        Ok(transaction) => {
+                            // The recorded server answered only once the upload was in
+                            let uploaded = match transaction.upload {
+                                Some(upload) => {
+                                    consume_upload(
+                                        req.into_body(),
+                                        upload,
+                                        request_sent,
+                                        clock.as_ref(),
+                                    )
+                                    .await
+                                }
+                                None => request_sent,
+                            };
+                            serve_transaction(
+                                transaction,
+                                loader,
+                                &url,
+                                request_received,
+                                uploaded,
+                                start_time,
+                                activity_guard,
+                                head_only,
+                                served_log,
+                                clock,
+                                watchdog,
+                                open_ended,
+                                error_replay,
+                                connection,
+                                shared,
+                                timing_headers,
+                            )
+                            .await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    match served {
+                        Ok(response) => RequestOrResponse::Response(response),
+                        Err(e) => {
+                            error!("Error serving transaction: {}", e);
+                            let response = Response::builder()
+                                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                                .body(Body::from(format!("Transaction error: {}", e)))
+                                .unwrap();
+                            RequestOrResponse::Response(response)
+                        }
+                    }
+                }
+                None if method == "OPTIONS" && synthesize_options => {
+                    let allowed: Vec<&str> = transactions_snapshot
+                        .iter()
+                        .filter(|t| {
+                            transaction_matches(
+                                t,
+                                &t.method,
+                                request_host,
+                                request_path,
+                                request_query,
+                                &query,
+                            )
+                        })
+                        .map(|t| t.method.as_str())
+                        .collect();
+                    if allowed.is_empty() {
+                        if let Some(log) = &served_log {
+                            log.miss();
+                        }
+                        return RequestOrResponse::Response(not_found(&method, &url));
                     }
-                },
+                    debug!("Synthesizing OPTIONS response for {}", url);
+                    RequestOrResponse::Response(synthesize_options_response(headers, &allowed))
+                }
                 None => {
                     info!(
                         "No transaction found for: {} {} (url: {})",
                         method, uri, url
                     );
-                    let response = Response::builder()
-                        .status(StatusCode::NOT_FOUND)
-                        .body(Body::from(format!(
-                            "Resource not found in playback data: {} {}",
-                            method, url
-                        )))
-                        .unwrap();
-                    RequestOrResponse::Response(response)
+                    if let Some(log) = &served_log {
+                        log.miss();
+                    }
+                    match fallback {
+                        Fallback::Error => RequestOrResponse::Response(not_found(&method, &url)),
+                        Fallback::Origin => {
+                            info!("Forwarding {} {} to the origin", method, url);
+                            if let Some(recorder) = &fallback_recorder {
+                                recorder.forwarded(client_addr, &method, &uri.to_string(), &url);
+                            }
+                            RequestOrResponse::Request(req)
+                        }
+                        Fallback::Stub => RequestOrResponse::Response(stub_response(&url)),
+                    }
                 }
             }
         }
     }
 
-    async fn handle_response(&mut self, _ctx: &HttpContext, res: Response<Body>) -> Response<Body> {
-        // Pass through responses unchanged
-        res
+    async fn handle_response(&mut self, ctx: &HttpContext, res: Response<Body>) -> Response<Body> {
+        // Only requests forwarded by --fallback origin get here
+        match &self.fallback_recorder {
+            Some(recorder) => {
+                let uri = ctx.request_uri.to_string();
+                recorder
+                    .capture(ctx.client_addr, ctx.request_method.as_str(), &uri, res)
+                    .await
+            }
+            None => res,
+        }
+    }
+}
+
+/// Whether a recorded transaction answers a request for `method` on the given host/path/query
+pub fn transaction_matches(
+    transaction: &Transaction,
+    method: &str,
+    request_host: Option<&str>,
+    request_path: &str,
+    request_query: Option<&str>,
+    query: &QueryOptions,
+) -> bool {
+    let request = MatchRequest {
+        method,
+        host: request_host,
+        path: request_path,
+        query: request_query,
+        headers: None,
+        body_sha1: None,
+    };
+    MatchStrategy::Exact.matches(transaction, &request, query)
+}
+
+/// First URL pattern resource matching `url`
+pub fn find_pattern_transaction(
+    transactions: &[Arc<Transaction>],
+    method: &str,
+    url: &str,
+) -> Option<Arc<Transaction>> {
+    transactions
+        .iter()
+        .find(|t| t.method == method && t.url_pattern.as_ref().is_some_and(|p| p.is_match(url)))
+        .cloned()
+}
+
+/// Buffer the request body and hash it, for requests that have one
+async fn read_body_sha1(req: Request<Body>) -> (Request<Body>, Option<String>) {
+    if req.body().is_end_stream() {
+        return (req, None);
+    }
+    let (parts, body) = req.into_parts();
+    let body_bytes = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            debug!("Failed to read request body: {}", e);
+            return (Request::from_parts(parts, Body::empty()), None);
+        }
+    };
+    let sha1 = (!body_bytes.is_empty()).then(|| crate::check::sha1_hex(&body_bytes));
+    (
+        Request::from_parts(parts, Body::from(Full::new(body_bytes))),
+        sha1,
+    )
+}
+
+/// Split off the multipart fields of a form upload, leaving other requests untouched
+async fn read_form_fields(req: Request<Body>) -> (Request<Body>, Option<Vec<(String, String)>>) {
+    let Some(boundary) = req
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .and_then(crate::multipart::boundary)
+    else {
+        return (req, None);
+    };
+
+    let (parts, body) = req.into_parts();
+    let body_bytes = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            debug!("Failed to read form body: {}", e);
+            return (Request::from_parts(parts, Body::empty()), None);
+        }
+    };
+    let fields = match crate::multipart::parse(&body_bytes, &boundary) {
+        Ok(form) => Some(crate::multipart::parts_signature(&form)),
+        Err(e) => {
+            debug!("Ignoring malformed form body: {}", e);
+            None
+        }
+    };
+    (
+        Request::from_parts(parts, Body::from(Full::new(body_bytes))),
+        fields,
+    )
+}
+
+fn not_found(method: &str, url: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(format!(
+            "Resource not found in playback data: {} {}",
+            method, url
+        )))
+        .unwrap()
+}
+
+/// Permissive answer to an unrecorded OPTIONS (CORS preflight) request
+///
+/// Echoes the requested origin, method and headers so the real request goes ahead
+/// and is then served from the recording.
+pub fn synthesize_options_response(
+    request_headers: &hyper::HeaderMap,
+    allowed_methods: &[&str],
+) -> Response<Body> {
+    let header_str = |name: &str| {
+        request_headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+    };
+
+    let mut methods: Vec<&str> = allowed_methods.to_vec();
+    if let Some(requested) = header_str("access-control-request-method") {
+        methods.push(requested);
+    }
+    methods.push("OPTIONS");
+    methods.sort_unstable();
+    methods.dedup();
+    let methods = methods.join(", ");
+
+    let mut builder = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("allow", methods.as_str())
+        .header("access-control-allow-methods", methods.as_str())
+        .header("access-control-max-age", "86400");
+
+    builder = match header_str("origin") {
+        // Credentialed requests require an explicit origin rather than `*`
+        Some(origin) => builder
+            .header("access-control-allow-origin", origin)
+            .header("access-control-allow-credentials", "true")
+            .header("vary", "Origin"),
+        None => builder.header("access-control-allow-origin", "*"),
+    };
+    if let Some(requested) = header_str("access-control-request-headers") {
+        builder = builder.header("access-control-allow-headers", requested);
+    }
+
+    builder.body(Body::empty()).unwrap()
+}
+
+/// Read a request body no faster than the recorded client sent its upload
+///
+/// Returns when the body has been read, which is where the recorded TTFB starts. A body
+/// already consumed for form matching only waits out the rest of the recorded time.
+async fn consume_upload(
+    mut body: Body,
+    upload: UploadTiming,
+    request_received: Instant,
+    clock: &dyn TimeProvider,
+) -> Instant {
+    let due = |received: u64| {
+        let elapsed = received.min(upload.bytes) as u128 * upload.duration_ms as u128
+            / upload.bytes.max(1) as u128;
+        request_received + Duration::from_millis(elapsed as u64)
+    };
+    let mut received = 0u64;
+    while let Some(frame) = body.frame().await {
+        match frame {
+            Ok(frame) => {
+                if let Some(data) = frame.data_ref() {
+                    received += data.len() as u64;
+                    clock.sleep_until(due(received)).await;
+                }
+            }
+            Err(e) => {
+                debug!("Request body ended early: {}", e);
+                return clock.now();
+            }
+        }
     }
+    clock.sleep_until(due(upload.bytes)).await;
+    clock.now()
 }
 
-async fn serve_transaction(
-    transaction: Transaction,
+/// Whether responses with `status` may carry content (RFC 9110: not 1xx, 204, 205 or 304)
+fn status_has_content(status: u16) -> bool {
+    !((100..200).contains(&status) || matches!(status, 204 | 205 | 304))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn serve_transaction<F: FileSystem + 'static>(
+    transaction: Arc<Transaction>,
+    loader: Arc<TransactionLoader<F>>,
+    request_url: &str,
+    request_received: Instant,
+    ttfb_start: Instant,
     _start_time: Arc<Instant>,
+    activity_guard: ActivityGuard,
+    head_only: bool,
+    served_log: Option<ServedLog>,
+    clock: Arc<dyn TimeProvider>,
+    watchdog: Option<Arc<Watchdog>>,
+    open_ended: OpenEndedOptions,
+    error_replay: ErrorReplay,
+    connection: Option<OwnedSemaphorePermit>,
+    shared: Vec<Arc<TokenBucket>>,
+    timing_headers: bool,
 ) -> anyhow::Result<Response<Body>> {
     // Wait for TTFB before sending response headers
     // This ensures the client measures TTFB accurately
     let ttfb_ms = transaction.ttfb;
-    info!(
-        "Waiting {}ms for TTFB before sending response headers",
-        ttfb_ms
+    let waited_ms = clock
+        .now()
+        .saturating_duration_since(ttfb_start)
+        .as_millis() as u64;
+    let remaining_ms = ttfb_ms.saturating_sub(waited_ms);
+    debug!(
+        "Waiting {}ms for TTFB before sending response headers (TTFB: {}ms)",
+        remaining_ms, ttfb_ms
     );
-    tokio::time::sleep(Duration::from_millis(ttfb_ms)).await;
-    info!("TTFB wait completed, now sending response headers");
+    clock
+        .sleep_until(ttfb_start + Duration::from_millis(ttfb_ms))
+        .await;
+    trace!("TTFB wait completed, now sending response headers");
+
+    // Includes any wait for a connection and the upload
+    let target_ttfb_ms = ttfb_start
+        .saturating_duration_since(request_received)
+        .as_millis() as u64
+        + ttfb_ms;
+    let diagnostics = timing_headers.then(|| {
+        [
+            (TARGET_TTFB_HEADER, target_ttfb_ms.to_string()),
+            (
+                ACTUAL_TTFB_HEADER,
+                (clock
+                    .now()
+                    .saturating_duration_since(request_received)
+                    .as_millis() as u64)
+                    .to_string(),
+            ),
+            (
+                TRANSACTION_ID_HEADER,
+                transaction.resource_index.to_string(),
+            ),
+        ]
+    });
+
+    // Logged when dropped: right away for bodiless responses, else once the stream ends
+    let served = served_log.map(|log| {
+        let status = transaction
+            .error_status()
+            .unwrap_or(transaction.status_code.unwrap_or(200));
+        let bytes = if head_only || !status_has_content(status) {
+            0
+        } else {
+            transaction.body_size()
+        };
+        let target_total_ms = if bytes == 0 {
+            target_ttfb_ms
+        } else {
+            target_ttfb_ms + transaction.target_close_time
+        };
+        log.begin(
+            &transaction.method,
+            &transaction.url,
+            status,
+            bytes,
+            request_received,
+        )
+        .expecting(request_url, target_ttfb_ms, target_total_ms)
+    });
 
     // Record the time after TTFB wait (when we start sending body)
     // Chunks have target_time relative to this point
-    let ttfb_end_instant = Instant::now();
+    let ttfb_end_instant = clock.now();
 
-    info!("Serving transaction for URL: {}", transaction.url);
-    info!("  Status code: {:?}", transaction.status_code);
-    info!("  Number of chunks: {}", transaction.chunks.len());
     info!(
-        "  Target close time: {}ms (relative to TTFB)",
+        "Serving {} (status: {:?}, {} chunks{}, close at {}ms after TTFB)",
+        transaction.url,
+        transaction.status_code,
+        transaction
+            .streamed
+            .as_ref()
+            .map_or(transaction.chunks.len(), |body| body.chunk_count()),
+        if transaction.streamed.is_some() {
+            " streamed from disk"
+        } else {
+            ""
+        },
         transaction.target_close_time
     );
 
-    // If there's an error message, return error response
-    if let Some(error_msg) = &transaction.error_message {
-        error!("Transaction has error message: {}", error_msg);
-        return Ok(Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
-            .body(Body::from(error_msg.clone()))?);
+    // The origin did not respond while recording; answer as the proxy did then
+    if let Some(status) = transaction.error_status() {
+        let error_msg = transaction.error_message.clone().unwrap_or_default();
+        error!(
+            "Transaction has error {:?}: {}",
+            transaction.error_kind, error_msg
+        );
+        let mut response_builder = Response::builder().status(status);
+        for (name, value) in diagnostics.iter().flatten() {
+            response_builder = response_builder.header(*name, value);
+        }
+        // The origin's failure rather than the recording proxy's answer to it
+        if let Some(kind) = transaction
+            .error_kind
+            .filter(|kind| error_replay.closes_connection(*kind))
+        {
+            return Ok(response_builder.body(aborted_body(kind, &error_msg))?);
+        }
+        return Ok(response_builder.body(Body::from(error_msg))?);
     }
 
     // Build response
     let mut response_builder = Response::builder().status(transaction.status_code.unwrap_or(200));
 
-    // Add headers (skip hop-by-hop headers that Hyper manages automatically)
+    // Custom reason phrases are written on HTTP/1.1 and ignored by HTTP/2
+    if let Some(reason) = &transaction.reason_phrase {
+        match hyper::ext::ReasonPhrase::try_from(reason.clone()) {
+            Ok(reason_phrase) => response_builder = response_builder.extension(reason_phrase),
+            Err(_) => debug!("Ignoring invalid reason phrase: {:?}", reason),
+        }
+    }
+
+    // Add headers in recorded order (skip hop-by-hop headers that Hyper manages automatically)
     if let Some(headers) = &transaction.raw_headers {
-        for (key, value) in headers {
+        for (key, value) in headers.iter() {
             // Skip headers that Hyper manages automatically to avoid UnexpectedHeader error
             let key_lower = key.to_lowercase();
             // Extended list of hop-by-hop headers per RFC 2616 Section 13.5.1
@@ -262,97 +1258,202 @@ async fn serve_transaction(
         }
     }
 
-    // Log chunk details
-    for (idx, chunk) in transaction.chunks.iter().enumerate() {
-        info!(
-            "  Chunk[{}]: size={} bytes, target_time={}ms (relative to TTFB)",
-            idx,
-            chunk.chunk.len(),
-            chunk.target_time
-        );
+    for (name, value) in diagnostics.iter().flatten() {
+        response_builder = response_builder.header(*name, value);
     }
 
-    // Create streaming body with timing control
-    // Chunks have target_time as relative time from TTFB completion (0-based)
-    // After all chunks are sent, wait until target_close_time before closing the connection
-    let chunks = transaction.chunks.clone();
-    let target_close_time = transaction.target_close_time;
-    let total_chunks = chunks.len();
+    // Content stored for a status that has none would break the framing of the
+    // connection, so it is dropped; there is no Content-Length to describe it either
+    if !status_has_content(transaction.status_code.unwrap_or(200)) {
+        return Ok(response_builder.body(Body::empty())?);
+    }
+
+    if head_only {
+        // Same headers as the GET; Content-Length still describes the GET body
+        return Ok(response_builder
+            .header(hyper::header::CONTENT_LENGTH, transaction.body_size())
+            .body(Body::empty())?);
+    }
+
+    // A body sent in full keeps the recorded framing: Content-Length, not chunked.
+    // Bodies that break off, never end or stream events have no length to announce.
+    let complete = transaction.error_kind != Some(ErrorKind::TruncatedBody)
+        && !transaction.open_ended
+        && transaction.event_stream.is_none();
+    if complete {
+        response_builder =
+            response_builder.header(hyper::header::CONTENT_LENGTH, transaction.body_size());
+    }
+
+    if tracing::enabled!(Level::TRACE) {
+        for (idx, chunk) in transaction.chunks.iter().enumerate() {
+            trace!(
+                "  Chunk[{}]: size={} bytes, target_time={}ms (relative to TTFB)",
+                idx,
+                chunk.chunk.len(),
+                chunk.target_time
+            );
+        }
+    }
 
+    // Create streaming body paced by the recorded transfer rate, and by the client's
+    // link under --network-profile and the proxy's under --max-bandwidth
+    // Chunks have target_time as relative time from TTFB completion (0-based); the pacer
+    // reproduces that schedule and holds the connection until target_close_time
+    // Chunks are read straight out of the shared transaction; cloning a Bytes chunk
+    // only bumps a reference count, so concurrent replays share one copy of the body.
+    // Streamed bodies are read from disk only when hyper polls for the next chunk, so a
+    // client that reads slowly keeps the server's buffering to one chunk.
+    // A body with recorded chunk timings is sent on that schedule instead of at an even
+    // rate, so stalls and bursts are replayed as they happened
+    let pacer = if transaction.timed {
+        Pacer::for_schedule(&shared)
+    } else {
+        Pacer::for_transfer(
+            transaction.body_size(),
+            transaction.target_close_time,
+            &shared,
+        )
+    }
+    .with_clock(clock);
+    let watch = StreamWatch::new(
+        watchdog.as_ref(),
+        &transaction.url,
+        (activity_guard, served, connection),
+    );
     let stream = stream::unfold(
-        (
-            chunks.into_iter().peekable(),
-            ttfb_end_instant,
-            target_close_time,
-            total_chunks,
-            0usize,
-            false,
-        ),
-        |(mut iter, ttfb_instant, close_time, total, chunk_idx, sent_all)| async move {
-            if sent_all {
-                // All chunks have been sent, now wait until target_close_time before closing
-                let elapsed = ttfb_instant.elapsed().as_millis() as u64;
-                if close_time > elapsed {
-                    let wait_time = close_time - elapsed;
-                    info!(
-                        "All {} chunks sent, waiting {}ms until target_close_time before closing connection",
-                        total, wait_time
+        (transaction, ttfb_end_instant, 0usize, pacer, loader, watch),
+        move |(transaction, ttfb_instant, chunk_idx, pacer, loader, watch)| async move {
+            let total = chunk_idx;
+            if let Err(reason) = watch.resume() {
+                return Some((
+                    Err(std::io::Error::other(format!(
+                        "aborted by watchdog: {}",
+                        reason
+                    ))),
+                    (transaction, ttfb_instant, usize::MAX, pacer, loader, watch),
+                ));
+            }
+
+            let chunk = match loader.chunk(&transaction, chunk_idx).await {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    // The stream ends with an error so hyper aborts the response
+                    error!("Failed to read body of {}: {}", transaction.url, e);
+                    return Some((
+                        Err(std::io::Error::other(e.to_string())),
+                        (transaction, ttfb_instant, usize::MAX, pacer, loader, watch),
+                    ));
+                }
+            };
+            let Some(chunk) = chunk else {
+                if transaction.error_kind == Some(ErrorKind::TruncatedBody) {
+                    // The origin broke the body off here while recording
+                    debug!(
+                        "All {} recorded chunks of {} sent; cutting the body off",
+                        total, transaction.url
                     );
-                    tokio::time::sleep(Duration::from_millis(wait_time)).await;
-                } else {
-                    let behind_ms = elapsed - close_time;
-                    info!(
-                        "All {} chunks sent, already {}ms past target_close_time, closing immediately",
-                        total, behind_ms
+                    let message = transaction.error_message.clone().unwrap_or_default();
+                    return Some((
+                        Err(io_error(ErrorKind::TruncatedBody, &message)),
+                        (transaction, ttfb_instant, usize::MAX, pacer, loader, watch),
+                    ));
+                }
+                if transaction.open_ended || transaction.event_stream == Some(StreamEnd::HoldOpen) {
+                    // The recorded response never ended; held open, it no longer counts
+                    // as network activity
+                    debug!(
+                        "All {} recorded chunks of {} sent; the response never ended",
+                        total, transaction.url
                     );
+                    watch.release();
+                    open_ended.hold().await;
+                    return None;
+                }
+                // All chunks have been sent, now wait until target_close_time before closing
+                pacer.drain().await;
+                if transaction.timed {
+                    let close = ttfb_instant + Duration::from_millis(transaction.target_close_time);
+                    pacer.wait_until(close).await;
                 }
+                debug!(
+                    "All {} chunks sent, closing at {}ms after TTFB (target: {}ms)",
+                    total,
+                    pacer
+                        .now()
+                        .saturating_duration_since(ttfb_instant)
+                        .as_millis(),
+                    transaction.target_close_time
+                );
                 // Stream ends here - connection will close
                 return None;
-            }
-
-            if let Some(chunk) = iter.next() {
-                // Check current elapsed time since TTFB completion
-                let elapsed = ttfb_instant.elapsed().as_millis() as u64;
+            };
 
-                // Wait until target_time for this chunk
-                if chunk.target_time > elapsed {
-                    let wait_time = chunk.target_time - elapsed;
-                    info!(
-                        "Chunk[{}]: Waiting {}ms before sending (target: {}ms, elapsed: {}ms)",
-                        chunk_idx, wait_time, chunk.target_time, elapsed
-                    );
-                    tokio::time::sleep(Duration::from_millis(wait_time)).await;
-                } else if chunk.target_time > 0 && elapsed > chunk.target_time {
-                    // We're behind schedule - log it but send immediately
-                    let behind_ms = elapsed - chunk.target_time;
-                    info!(
-                        "Chunk[{}]: Behind schedule by {}ms, sending immediately (target: {}ms, elapsed: {}ms)",
-                        chunk_idx, behind_ms, chunk.target_time, elapsed
+            let elapsed = pacer
+                .now()
+                .saturating_duration_since(ttfb_instant)
+                .as_millis() as u64;
+            if chunk.target_time > 0 && elapsed > chunk.target_time {
+                // We're behind schedule - the pacer lets the chunk through right away
+                let behind_ms = elapsed - chunk.target_time;
+                trace!(
+                    "Chunk[{}]: Behind schedule by {}ms (target: {}ms, elapsed: {}ms)",
+                    chunk_idx, behind_ms, chunk.target_time, elapsed
+                );
+                let late = BEHIND_SCHEDULE_CHUNKS.fetch_add(1, Ordering::Relaxed) + 1;
+                if late == 1 || late.is_multiple_of(BEHIND_SCHEDULE_LOG_EVERY) {
+                    debug!(
+                        "{} chunks sent behind schedule so far (latest: {} chunk {} by {}ms)",
+                        late, transaction.url, chunk_idx, behind_ms
                     );
                 }
-
-                // Send chunk
-                info!("Chunk[{}]: Sending {} bytes", chunk_idx, chunk.chunk.len());
-                let frame = Frame::data(Bytes::from(chunk.chunk));
-
-                // Check if this was the last chunk
-                let is_last = iter.peek().is_none();
-
-                Some((
-                    Ok::<_, std::io::Error>(frame),
-                    (
-                        iter,
-                        ttfb_instant,
-                        close_time,
-                        total,
-                        chunk_idx + 1,
-                        is_last,
-                    ),
-                ))
+                if let Err(reason) = watch.check_lag(behind_ms) {
+                    return Some((
+                        Err(std::io::Error::other(format!(
+                            "aborted by watchdog: {}",
+                            reason
+                        ))),
+                        (transaction, ttfb_instant, usize::MAX, pacer, loader, watch),
+                    ));
+                }
+            }
+            if transaction.event_stream.is_some() {
+                // Events go out when they arrived, however small they are
+                let due = ttfb_instant + Duration::from_millis(chunk.target_time);
+                pacer.wait_until(due).await;
+            } else if transaction.timed {
+                let due = ttfb_instant + Duration::from_millis(chunk.target_time);
+                pacer.wait_until(due).await;
+                pacer.pace(chunk.chunk.len()).await;
             } else {
-                // Shouldn't reach here but handle gracefully
-                None
+                pacer.pace(chunk.chunk.len()).await;
             }
+
+            // Send chunk
+            trace!(
+                "Chunk[{}]: Sending {} bytes at {}ms (target: {}ms)",
+                chunk_idx,
+                chunk.chunk.len(),
+                pacer
+                    .now()
+                    .saturating_duration_since(ttfb_instant)
+                    .as_millis(),
+                chunk.target_time
+            );
+            let frame = Frame::data(chunk.chunk);
+            watch.handed_over();
+
+            Some((
+                Ok::<_, std::io::Error>(frame),
+                (
+                    transaction,
+                    ttfb_instant,
+                    chunk_idx + 1,
+                    pacer,
+                    loader,
+                    watch,
+                ),
+            ))
         },
     );
 
@@ -363,7 +1464,8 @@ async fn serve_transaction(
     use futures::TryStreamExt;
     let bytes_stream = stream_body.map_ok(|frame| frame.into_data().unwrap_or_default());
 
-    let body = Body::from_stream(bytes_stream);
+    // Reads of content files are Send but not Sync, as Body::from_stream requires
+    let body = Body::from_stream(sync_wrapper::SyncStream::new(bytes_stream));
 
     let response = response_builder.body(body)?;
 
@@ -187,14 +187,13 @@ mod tests {
         resource.mbps = Some(0.5);
         resource.error_message = Some("Rate limited".to_string());
 
-        use std::collections::HashMap;
-        let mut headers = HashMap::new();
-        headers.insert(
-            "content-type".to_string(),
+        let mut headers = crate::types::HttpHeaders::new();
+        headers.set(
+            "content-type",
             crate::types::HeaderValue::Single("application/json".to_string()),
         );
-        headers.insert(
-            "x-rate-limit".to_string(),
+        headers.set(
+            "x-rate-limit",
             crate::types::HeaderValue::Single("100".to_string()),
         );
         resource.raw_headers = Some(headers);
@@ -0,0 +1,285 @@
+//! Dry-run request matching
+//!
+//! `match-test` runs one request through the same lookup playback uses and prints the
+//! rules it consulted, which resource answers, and for misses the recorded resources
+//! that came closest, so a 404 can be debugged without starting a browser.
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::hudsucker_handler::find_pattern_transaction;
+use super::matching::{MatchRequest, find_matching};
+use super::transaction::{ChunkPacing, PreloadPolicy, prepare_transactions};
+use crate::query::QueryOptions;
+use crate::traits::RealFileSystem;
+use crate::types::{Inventory, MatchStrategy, Transaction};
+
+/// Near misses listed at most
+const MAX_NEAR_MISSES: usize = 10;
+
+/// Which lookup selected the transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchRule {
+    Exact,
+    // A looser or stricter strategy from --match
+    Strategy(MatchStrategy),
+    HeadFromGet,
+    Pattern,
+}
+
+#[derive(Debug)]
+pub struct MatchExplanation {
+    /// Index of the answering resource in the inventory
+    pub selected: Option<(usize, MatchRule)>,
+    pub steps: Vec<String>,
+    pub near_misses: Vec<String>,
+}
+
+pub async fn run_match_test(
+    inventory_dir: PathBuf,
+    url: String,
+    method: String,
+    query: QueryOptions,
+    strategies: Vec<MatchStrategy>,
+) -> Result<()> {
+    let file_system = Arc::new(RealFileSystem);
+    let inventory = super::load_inventory(&inventory_dir, file_system.clone()).await?;
+    let transactions = prepare_transactions(
+        &inventory,
+        &inventory_dir,
+        file_system,
+        &PreloadPolicy::None,
+        &ChunkPacing::default(),
+        None,
+    )
+    .await?;
+    let transactions: Vec<Arc<Transaction>> = transactions.into_iter().map(Arc::new).collect();
+    let strategies = if strategies.is_empty() {
+        inventory
+            .match_strategies
+            .clone()
+            .unwrap_or_else(|| vec![MatchStrategy::Exact])
+    } else {
+        strategies
+    };
+
+    let explanation = explain_match(
+        &inventory,
+        &transactions,
+        &method.to_ascii_uppercase(),
+        &url,
+        &query,
+        &strategies,
+    )?;
+
+    println!("Rules consulted:");
+    for (n, step) in explanation.steps.iter().enumerate() {
+        println!("  {}. {}", n + 1, step);
+    }
+    match explanation.selected {
+        Some((index, rule)) => {
+            let resource = &inventory.resources[index];
+            println!(
+                "Selected: resource #{} {} {} ({:?}, status {})",
+                index,
+                resource.method,
+                resource.url,
+                rule,
+                resource
+                    .status_code
+                    .map_or("none".to_string(), |s| s.to_string())
+            );
+        }
+        None => println!("Selected: nothing; playback answers 404"),
+    }
+    if !explanation.near_misses.is_empty() {
+        println!("Closest recorded resources:");
+        for miss in &explanation.near_misses {
+            println!("  {}", miss);
+        }
+    }
+    Ok(())
+}
+
+/// Look `method url` up as the playback handler does, describing each step
+pub fn explain_match(
+    inventory: &Inventory,
+    transactions: &[Arc<Transaction>],
+    method: &str,
+    url: &str,
+    query: &QueryOptions,
+    strategies: &[MatchStrategy],
+) -> Result<MatchExplanation> {
+    let uri: hyper::Uri = url
+        .parse()
+        .with_context(|| format!("Invalid URL {:?}", url))?;
+    let host = uri.authority().map(|a| a.as_str());
+    let path = uri.path();
+    let request_query = uri.query();
+
+    let mut steps = Vec::new();
+    let canonical = request_query.map(|q| query.canonicalize(q).into_owned());
+    steps.push(format!(
+        "Requested host {:?}, path {:?}, query {:?}{}",
+        host.unwrap_or_default(),
+        path,
+        canonical.as_deref().unwrap_or_default(),
+        if *query == QueryOptions::default() {
+            " (compared as written)"
+        } else {
+            " (canonicalized by the query options)"
+        }
+    ));
+    let served = transactions.len();
+    if served < inventory.resources.len() {
+        steps.push(format!(
+            "{} of {} resources have no content and are never served",
+            inventory.resources.len() - served,
+            inventory.resources.len()
+        ));
+    }
+
+    let mut wanted = vec![method];
+    if method == "HEAD" {
+        wanted.push("GET");
+    }
+    let mut selected = None;
+    for wanted_method in wanted {
+        let head_from_get = wanted_method != method;
+        let request = MatchRequest {
+            method: wanted_method,
+            host,
+            path,
+            query: request_query,
+            headers: None,
+            body_sha1: None,
+        };
+        for &strategy in strategies {
+            let found = find_matching(transactions, &request, &[strategy], query, None);
+            match &found {
+                Some((t, _)) => {
+                    let candidates = transactions
+                        .iter()
+                        .filter(|c| c.method == t.method && c.url == t.url)
+                        .count();
+                    steps.push(format!(
+                        "{:?} {} match: resource #{}{}",
+                        strategy,
+                        wanted_method,
+                        t.resource_index,
+                        if candidates > 1 {
+                            format!(
+                                ", the first of {} recordings (others are chosen only by --match-form)",
+                                candidates
+                            )
+                        } else {
+                            String::new()
+                        }
+                    ));
+                }
+                None => steps.push(format!("{:?} {} match: none", strategy, wanted_method)),
+            }
+            if let Some((t, _)) = found {
+                let rule = match strategy {
+                    _ if head_from_get => MatchRule::HeadFromGet,
+                    MatchStrategy::Exact => MatchRule::Exact,
+                    strategy => MatchRule::Strategy(strategy),
+                };
+                selected = Some((t, rule));
+                break;
+            }
+        }
+        if selected.is_some() {
+            break;
+        }
+
+        match find_pattern_transaction(transactions, wanted_method, url) {
+            Some(t) => {
+                steps.push(format!(
+                    "{} URL pattern: resource #{} {}",
+                    wanted_method, t.resource_index, t.url
+                ));
+                selected = Some((t, MatchRule::Pattern));
+                break;
+            }
+            None => steps.push(format!("{} URL pattern: none", wanted_method)),
+        }
+    }
+
+    if let Some(availability) = selected.as_ref().and_then(|(t, _)| t.availability) {
+        steps.push(format!(
+            "Available after {:?}: answered with {} before then",
+            Duration::from_millis(availability.after_ms),
+            availability.status
+        ));
+    }
+
+    let near_misses = if selected.is_none() {
+        near_misses(
+            inventory,
+            transactions,
+            method,
+            host,
+            path,
+            canonical.as_deref(),
+            query,
+        )
+    } else {
+        Vec::new()
+    };
+
+    Ok(MatchExplanation {
+        selected: selected.map(|(t, rule)| (t.resource_index, rule)),
+        steps,
+        near_misses,
+    })
+}
+
+/// Recorded resources differing from the request in one respect
+fn near_misses(
+    inventory: &Inventory,
+    transactions: &[Arc<Transaction>],
+    method: &str,
+    host: Option<&str>,
+    path: &str,
+    canonical_query: Option<&str>,
+    query: &QueryOptions,
+) -> Vec<String> {
+    let mut misses = Vec::new();
+    for (index, resource) in inventory.resources.iter().enumerate() {
+        let Ok(uri) = resource.url.parse::<hyper::Uri>() else {
+            continue;
+        };
+        let same_host = uri.authority().map(|a| a.as_str()) == host;
+        let same_path = uri.path() == path;
+        let recorded_query = uri.query().map(|q| query.canonicalize(q).into_owned());
+        let same_query = recorded_query.as_deref() == canonical_query;
+        let same_method = resource.method == method;
+
+        let reason = match (same_method, same_host, same_path, same_query) {
+            (true, true, true, true) if !transactions.iter().any(|t| t.resource_index == index) => {
+                "has no content, so it is never served".to_string()
+            }
+            (true, true, true, false) => format!(
+                "query differs: recorded {:?}",
+                recorded_query.unwrap_or_default()
+            ),
+            (false, true, true, true) => format!("method differs: recorded {}", resource.method),
+            (true, false, true, true) => format!(
+                "host differs: recorded {:?}",
+                uri.authority().map(|a| a.as_str()).unwrap_or_default()
+            ),
+            _ => continue,
+        };
+        misses.push(format!(
+            "#{} {} {}: {}",
+            index, resource.method, resource.url, reason
+        ));
+        if misses.len() == MAX_NEAR_MISSES {
+            break;
+        }
+    }
+    misses
+}
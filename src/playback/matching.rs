@@ -0,0 +1,248 @@
+//! Request-matching strategies
+//!
+//! By default a request is answered by the recording with the same method, host, path
+//! and query. Pages that add cache-busting parameters, APIs that answer one URL
+//! differently per header or per POST body, need looser or stricter rules. `--match`
+//! (or `matchStrategies` in `index.json`) lists strategies to try in order; the first
+//! one that finds a recording answers.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+use tracing::debug;
+
+use super::cookies::cookie_names;
+use crate::query::QueryOptions;
+use crate::types::{MatchStrategy, Transaction};
+
+/// The parts of a request the strategies compare
+#[derive(Debug, Clone, Copy)]
+pub struct MatchRequest<'a> {
+    pub method: &'a str,
+    pub host: Option<&'a str>,
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+    pub headers: Option<&'a hyper::HeaderMap>,
+    /// SHA-1 of the request body, None when it had none
+    pub body_sha1: Option<&'a str>,
+}
+
+impl MatchStrategy {
+    /// Whether `transaction` answers `request` under this strategy
+    pub fn matches(
+        self,
+        transaction: &Transaction,
+        request: &MatchRequest,
+        query: &QueryOptions,
+    ) -> bool {
+        if transaction.method != request.method || transaction.url_pattern.is_some() {
+            return false;
+        }
+        let Ok(uri) = transaction.url.parse::<hyper::Uri>() else {
+            return false;
+        };
+        // A missing host on either side falls back to path-only matching
+        let host_matches = match (request.host, uri.authority()) {
+            (Some(request_host), Some(authority)) => request_host == authority.as_str(),
+            _ => true,
+        };
+        if !host_matches || uri.path() != request.path {
+            return false;
+        }
+
+        let recorded_query = uri.query().map(|q| query.canonicalize(q));
+        let request_query = request.query.map(|q| query.canonicalize(q));
+        let same_query = recorded_query == request_query;
+        match self {
+            Self::Exact => same_query,
+            Self::IgnoreQuery => true,
+            Self::QuerySubset => {
+                let request_params: Vec<&str> = request_query.as_deref().map_or(Vec::new(), params);
+                recorded_query
+                    .as_deref()
+                    .map_or(Vec::new(), params)
+                    .iter()
+                    .all(|param| request_params.contains(param))
+            }
+            Self::Header => {
+                same_query
+                    && transaction.match_headers.as_ref().is_some_and(|wanted| {
+                        wanted.iter().all(|(name, value)| {
+                            let sent: Vec<&str> = request
+                                .headers
+                                .map(|headers| {
+                                    headers
+                                        .get_all(name)
+                                        .iter()
+                                        .filter_map(|v| v.to_str().ok())
+                                        .collect()
+                                })
+                                .unwrap_or_default();
+                            !sent.is_empty() && sent.join(", ") == value.as_vec().join(", ")
+                        })
+                    })
+            }
+            Self::BodyHash => {
+                same_query && transaction.request_body_sha1.as_deref() == request.body_sha1
+            }
+        }
+    }
+}
+
+/// `strategies`, with header matching first for a CORS preflight
+///
+/// Recorded preflights carry their `Access-Control-Request-*` headers as `matchHeaders`,
+/// so a preflight is answered by the one sent ahead of the same method and headers;
+/// the configured strategies still answer when none of them was.
+pub fn preflight_strategies<'a>(
+    method: &str,
+    headers: &hyper::HeaderMap,
+    strategies: &'a [MatchStrategy],
+) -> Cow<'a, [MatchStrategy]> {
+    let is_preflight = method == "OPTIONS" && headers.contains_key("access-control-request-method");
+    if !is_preflight || strategies.first() == Some(&MatchStrategy::Header) {
+        return Cow::Borrowed(strategies);
+    }
+    let mut ordered = vec![MatchStrategy::Header];
+    ordered.extend(strategies.iter().filter(|&&s| s != MatchStrategy::Header));
+    Cow::Owned(ordered)
+}
+
+/// Non-empty `name=value` pairs of a query string
+fn params(query: &str) -> Vec<&str> {
+    query.split('&').filter(|param| !param.is_empty()).collect()
+}
+
+/// The recorded query's parameter count, how closely a query-subset match fits
+fn specificity(transaction: &Transaction) -> usize {
+    transaction
+        .url
+        .parse::<hyper::Uri>()
+        .ok()
+        .and_then(|uri| uri.query().map(|q| params(q).len()))
+        .unwrap_or(0)
+}
+
+/// First transaction answering `request` under the first strategy that finds one
+///
+/// With `form` set, a recording with the same multipart fields is preferred among a
+/// strategy's candidates, falling back to the first of them.
+pub fn find_matching(
+    transactions: &[Arc<Transaction>],
+    request: &MatchRequest,
+    strategies: &[MatchStrategy],
+    query: &QueryOptions,
+    form: Option<&[(String, String)]>,
+) -> Option<(Arc<Transaction>, MatchStrategy)> {
+    strategies.iter().find_map(|&strategy| {
+        let mut candidates: Vec<&Arc<Transaction>> = transactions
+            .iter()
+            .filter(|t| strategy.matches(t, request, query))
+            .collect();
+        if strategy == MatchStrategy::QuerySubset {
+            // Stable, so equally specific recordings keep their order
+            candidates.sort_by_key(|t| std::cmp::Reverse(specificity(t)));
+        }
+        prefer_form(candidates.into_iter(), form).map(|t| (t, strategy))
+    })
+}
+
+/// The first recording of `matched`'s method and URL whose request sent the values
+/// `headers` has for every header in `vary`
+///
+/// None when `vary` is empty or the headers do not tell the recordings apart (none or
+/// all of them sent those values), so such repeats still replay in recorded order.
+pub fn prefer_request_headers(
+    transactions: &[Arc<Transaction>],
+    matched: &Transaction,
+    headers: &hyper::HeaderMap,
+    vary: &[String],
+) -> Option<Arc<Transaction>> {
+    if vary.is_empty() {
+        return None;
+    }
+    prefer_recording(transactions, matched, |t| {
+        sent_same_headers(t, headers, vary)
+    })
+}
+
+/// The first recording of `matched`'s method and URL whose request carried each cookie
+/// in `vary` exactly when `has_cookie` says the client has it now
+///
+/// None when `vary` is empty or the cookies do not tell the recordings apart, as for
+/// [`prefer_request_headers`].
+pub fn prefer_cookies(
+    transactions: &[Arc<Transaction>],
+    matched: &Transaction,
+    vary: &[String],
+    has_cookie: impl Fn(&str) -> bool,
+) -> Option<Arc<Transaction>> {
+    if vary.is_empty() {
+        return None;
+    }
+    prefer_recording(transactions, matched, |t| {
+        let Some(recorded) = &t.request_headers else {
+            return false;
+        };
+        let sent: Vec<&str> = recorded
+            .get("cookie")
+            .map(|value| value.as_vec().into_iter().flat_map(cookie_names).collect())
+            .unwrap_or_default();
+        vary.iter()
+            .all(|name| sent.contains(&name.as_str()) == has_cookie(name))
+    })
+}
+
+/// The first recording of `matched`'s method and URL that is `wanted`, unless none or
+/// all of them are
+fn prefer_recording(
+    transactions: &[Arc<Transaction>],
+    matched: &Transaction,
+    wanted: impl Fn(&Transaction) -> bool,
+) -> Option<Arc<Transaction>> {
+    let recordings: Vec<&Arc<Transaction>> = transactions
+        .iter()
+        .filter(|t| t.method == matched.method && t.url == matched.url)
+        .collect();
+    let same: Vec<&Arc<Transaction>> = recordings.iter().copied().filter(|t| wanted(t)).collect();
+    (!same.is_empty() && same.len() < recordings.len()).then(|| same[0].clone())
+}
+
+/// Whether the recorded request had the same values as `headers` (or lacked the header
+/// too) for each name in `vary`
+fn sent_same_headers(
+    transaction: &Transaction,
+    headers: &hyper::HeaderMap,
+    vary: &[String],
+) -> bool {
+    let Some(recorded) = &transaction.request_headers else {
+        return false;
+    };
+    vary.iter().all(|name| {
+        let sent: Vec<&str> = headers
+            .get_all(name.as_str())
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        let sent = (!sent.is_empty()).then(|| sent.join(", "));
+        recorded.get(name).map(|value| value.as_vec().join(", ")) == sent
+    })
+}
+
+/// The first candidate, or with `form` set the first one recorded with those fields
+fn prefer_form<'a>(
+    mut candidates: impl Iterator<Item = &'a Arc<Transaction>>,
+    form: Option<&[(String, String)]>,
+) -> Option<Arc<Transaction>> {
+    let first = candidates.next()?;
+    let Some(form) = form else {
+        return Some(first.clone());
+    };
+    std::iter::once(first)
+        .chain(candidates)
+        .find(|t| t.form_fields.as_deref() == Some(form))
+        .or_else(|| {
+            debug!("No recording of {} has the same form fields", first.url);
+            Some(first)
+        })
+        .cloned()
+}
@@ -0,0 +1,150 @@
+//! Memory footprint of preloaded bodies
+//!
+//! Preloaded bodies stay in memory for the whole run, so an inventory with a few huge
+//! resources can exhaust a small CI machine. Before transactions are built, each body
+//! is sized from its content file (decompressed, as stored); with `--max-memory`, the
+//! largest bodies the preload policy selects are loaded on demand instead until the rest
+//! fit. `http://hpp.local/status` reports the decision per resource.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use super::transaction::{ChunkPacing, PreloadPolicy, is_served_verbatim};
+use crate::traits::FileSystem;
+use crate::types::{Inventory, Resource};
+
+/// How a resource's body is held during playback
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Serving {
+    /// In memory from startup
+    Preloaded,
+    /// Loaded on first request, as the preload policy asks
+    OnDemand,
+    /// Loaded on first request to stay within `--max-memory`
+    OverBudget,
+    /// Read from disk chunk by chunk while sending
+    Streamed,
+    /// Nothing to hold
+    NoBody,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceMemory {
+    pub method: String,
+    pub url: String,
+    /// Decompressed body size
+    pub bytes: u64,
+    pub serving: Serving,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryPlan {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_memory: Option<u64>,
+    /// Bytes needed to preload every body that is not streamed
+    pub total_bytes: u64,
+    /// Bytes of the bodies actually preloaded
+    pub preloaded_bytes: u64,
+    pub resources: Vec<ResourceMemory>,
+}
+
+/// Decompressed size of a resource's body, or None when it has none
+async fn body_size<F: FileSystem>(
+    resource: &Resource,
+    inventory_dir: &Path,
+    file_system: &F,
+) -> Result<Option<u64>> {
+    if let Some(file_path) = &resource.content_file_path {
+        let full_path = inventory_dir.join(file_path);
+        if file_system.exists(&full_path).await {
+            return Ok(Some(file_system.file_len(&full_path).await?));
+        }
+    }
+    if let Some(content) = &resource.content_utf8 {
+        return Ok(Some(content.len() as u64));
+    }
+    Ok(resource.content_base64.as_ref().map(|encoded| {
+        let padding = encoded.bytes().rev().take_while(|&b| b == b'=').count();
+        (encoded.len() / 4 * 3).saturating_sub(padding) as u64
+    }))
+}
+
+impl MemoryPlan {
+    /// Size every body and decide which of those `policy` preloads stay in memory
+    pub async fn new<F: FileSystem>(
+        inventory: &Inventory,
+        inventory_dir: &Path,
+        file_system: &F,
+        policy: &PreloadPolicy,
+        pacing: &ChunkPacing,
+        max_memory: Option<u64>,
+    ) -> Result<Self> {
+        let mut plan = MemoryPlan {
+            max_memory,
+            ..Default::default()
+        };
+        for (index, resource) in inventory.resources.iter().enumerate() {
+            let size = body_size(resource, inventory_dir, file_system).await?;
+            let streamed = resource.content_file_path.is_some()
+                && is_served_verbatim(resource)
+                && size.is_some_and(|size| size > 0 && size >= pacing.stream_threshold);
+            let serving = match size {
+                None => Serving::NoBody,
+                Some(_) if streamed => Serving::Streamed,
+                Some(_) if policy.should_preload(index, &resource.url) => Serving::Preloaded,
+                Some(_) => Serving::OnDemand,
+            };
+            if !streamed {
+                plan.total_bytes += size.unwrap_or(0);
+            }
+            plan.resources.push(ResourceMemory {
+                method: resource.method.clone(),
+                url: resource.url.clone(),
+                bytes: size.unwrap_or(0),
+                serving,
+            });
+        }
+
+        let mut preloaded: Vec<usize> = (0..plan.resources.len())
+            .filter(|&i| plan.resources[i].serving == Serving::Preloaded)
+            .collect();
+        plan.preloaded_bytes = preloaded.iter().map(|&i| plan.resources[i].bytes).sum();
+        if let Some(max_memory) = max_memory {
+            // Largest first, so as few bodies as possible leave memory
+            preloaded.sort_by_key(|&i| std::cmp::Reverse(plan.resources[i].bytes));
+            for i in preloaded {
+                if plan.preloaded_bytes <= max_memory {
+                    break;
+                }
+                plan.resources[i].serving = Serving::OverBudget;
+                plan.preloaded_bytes -= plan.resources[i].bytes;
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Number of resources served as `serving`
+    pub fn count(&self, serving: Serving) -> usize {
+        self.resources
+            .iter()
+            .filter(|r| r.serving == serving)
+            .count()
+    }
+
+    /// `policy` with the bodies over budget loaded on demand instead
+    pub fn preload_policy(&self, policy: &PreloadPolicy) -> PreloadPolicy {
+        PreloadPolicy::Resources(
+            self.resources
+                .iter()
+                .enumerate()
+                .map(|(index, r)| {
+                    r.serving != Serving::OverBudget && policy.should_preload(index, &r.url)
+                })
+                .collect(),
+        )
+    }
+}
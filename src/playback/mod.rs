@@ -1,15 +1,59 @@
-use crate::traits::{FileSystem, RealFileSystem};
-use crate::types::Inventory;
-use crate::utils::get_port_or_default;
-use anyhow::Result;
+use crate::browser::BrowserOptions;
+use crate::exit_status::{QualityGates, RunFailure};
+use crate::lock::InventoryLock;
+use crate::manifest::{RunManifest, RunMode};
+use crate::query::QueryOptions;
+use crate::server::ServerHooks;
+use crate::traits::{FileSystem, PooledFileSystem, RealFileSystem};
+use crate::types::{Inventory, MatchStrategy, Transaction};
+use crate::utils::{
+    get_port_or_default, parse_bandwidth_mbps, parse_duration_ms, parse_size_bytes,
+};
+use anyhow::{Context, Result};
+use regex::Regex;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+pub use match_test::run_match_test;
+
+mod accept_encoding;
+mod body_cache;
+mod byte_ranges;
+mod client_check;
+mod conditional;
+mod connection_limits;
+mod cookies;
+mod error_replay;
+mod fallback;
+mod filter;
+mod header_overrides;
+mod host_map;
+mod host_profiles;
 mod hudsucker_handler;
+mod match_test;
+mod matching;
+mod memory;
+mod network_profile;
+mod open_ended;
+pub(crate) mod pacing;
+mod protocols;
 mod proxy;
-mod signal_handler;
+mod rate_limits;
+mod reload;
+mod security_headers;
+mod self_test;
+mod sequence;
+pub mod served_log;
+mod sessions;
+pub(crate) mod signal_handler;
+mod socket;
 mod tests;
-mod transaction;
+mod time_scale;
+mod timing_limits;
+mod tokens;
+pub mod transaction;
+mod transform;
+mod watchdog;
 
 #[cfg(test)]
 mod transaction_tests;
@@ -17,7 +61,403 @@ mod transaction_tests;
 #[cfg(test)]
 mod inventory_tests;
 
-pub async fn run_playback_mode(port: Option<u16>, inventory_dir: PathBuf) -> Result<()> {
+#[cfg(test)]
+mod pacing_tests;
+
+#[cfg(test)]
+mod tokens_tests;
+
+#[cfg(test)]
+mod sessions_tests;
+
+#[cfg(test)]
+mod filter_tests;
+
+/// Optional playback features
+#[derive(Debug, Clone, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackOptions {
+    #[command(flatten)]
+    pub browser: BrowserOptions,
+
+    #[command(flatten)]
+    pub socket: socket::SocketOptions,
+
+    #[command(flatten)]
+    pub query: QueryOptions,
+
+    #[command(flatten)]
+    pub timing_limits: timing_limits::TimingLimits,
+
+    #[command(flatten)]
+    pub time_scale: time_scale::TimeScale,
+
+    #[command(flatten)]
+    pub watchdog: watchdog::WatchdogOptions,
+
+    #[command(flatten)]
+    pub open_ended: open_ended::OpenEndedOptions,
+
+    #[command(flatten)]
+    pub self_test: self_test::SelfTestOptions,
+
+    #[command(flatten)]
+    pub quality: QualityGates,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "PROFILE",
+        help = "Replace recorded timing with a synthetic network: slow-3g, fast-3g, 4g or cable (fixed round trip, bandwidth shared by each client's concurrent responses), or unthrottled"
+    )]
+    pub network_profile: Option<network_profile::NetworkProfile>,
+
+    #[arg(
+        long,
+        value_name = "RATE",
+        value_parser = parse_bandwidth_mbps,
+        help = "Cap the combined rate of all response bodies at this link capacity (e.g. 100mbps, 500kbps)"
+    )]
+    pub max_bandwidth: Option<f64>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t = protocols::Http2Policy::Recorded,
+        help = "Offer HTTP/2 to clients: recorded (hosts not recorded over HTTP/1.x only), always or never"
+    )]
+    pub http2: protocols::Http2Policy,
+
+    #[arg(
+        long = "host-profile",
+        value_name = "HOST=MBPS[,RTT]",
+        help = "Replay resources of matching hosts (* as wildcard) at this bandwidth and with TTFBs of at least this round trip, e.g. '*.adnet.com=2,300ms' (repeatable; first match wins)"
+    )]
+    pub host_profiles: Vec<String>,
+
+    #[arg(
+        long = "map-host",
+        value_name = "FROM=TO",
+        help = "Answer requests for host FROM (with its port, if any) from the recordings of host TO, pointing their Location and cookie domains back to FROM, e.g. 'localhost:8080=www.example.com' (repeatable)"
+    )]
+    pub host_maps: Vec<String>,
+
+    #[arg(
+        long = "rate-limit",
+        value_name = "URL=N/WINDOW",
+        help = "Answer 429 with Retry-After once a client has sent N requests to matching URLs (* and ** as wildcards) within the window, e.g. 'https://api.example.com/**=10/1s' (repeatable)"
+    )]
+    pub rate_limits: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Decompress, transform and compress every transaction into memory before starting (default: load on demand)"
+    )]
+    pub preload: bool,
+
+    #[arg(
+        long = "preload-urls",
+        value_name = "PATTERN",
+        help = "Regex of URLs to warm before starting when not using --preload (repeatable)"
+    )]
+    pub preload_urls: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Serve multipart/form-data uploads from the recording with the same field values (boundaries are ignored)"
+    )]
+    pub match_form: bool,
+
+    #[arg(
+        long = "match",
+        value_enum,
+        value_name = "STRATEGY",
+        help = "Request-matching strategy to try, in order of the flags: exact, ignore-query, query-subset, header or body-hash (repeatable; default: the inventory's matchStrategies, else exact)"
+    )]
+    pub match_strategies: Vec<MatchStrategy>,
+
+    #[arg(
+        long = "vary-on",
+        value_name = "HEADER",
+        help = "Among recordings of the same URL, serve the one whose recorded request had the same value for this header (e.g. Accept; repeatable)"
+    )]
+    pub vary_on: Vec<String>,
+
+    #[arg(
+        long = "vary-on-cookie",
+        value_name = "NAME",
+        help = "Among recordings of the same URL, serve the one whose recorded request carried this cookie exactly when the client has it (e.g. a session cookie; repeatable)"
+    )]
+    pub vary_on_cookie: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Keep the cookies replayed Set-Cookie headers give each client, until they expire or are deleted, and count them for --vary-on-cookie even when the client does not send them"
+    )]
+    pub cookie_jar: bool,
+
+    #[arg(
+        long,
+        help = "Answer GET and HEAD requests whose If-None-Match or If-Modified-Since matches the recorded ETag or Last-Modified with 304 Not Modified"
+    )]
+    pub conditional: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "last",
+        help = "Repeated requests for a URL recorded several times get its recordings in order; after the last one, keep serving it (last) or start over (cycle)"
+    )]
+    pub repeat_mode: sequence::RepeatMode,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "status",
+        help = "Replay requests that failed while recording with the status the recording proxy answered (status), or by closing the connection without a response as the origin failed (connection)"
+    )]
+    pub error_replay: error_replay::ErrorReplay,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "error",
+        help = "Answer requests without a recording with 404 (error), by forwarding them to the live origin (origin), or with an empty 200 (stub)"
+    )]
+    pub fallback: fallback::Fallback,
+
+    #[arg(
+        long,
+        help = "With --fallback origin, add the forwarded responses to the inventory when playback stops"
+    )]
+    pub fallback_record: bool,
+
+    #[arg(
+        long = "token",
+        value_name = "NAME[=FIELD]",
+        help = "Header or query parameter carrying a session token, optionally with the response field issuing it; the client's value replaces the recorded one (repeatable)"
+    )]
+    pub tokens: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "CMD",
+        help = "Pipe each matched response through CMD as JSON on stdin and serve the JSON it prints"
+    )]
+    pub filter_cmd: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "CMD",
+        help = "Pipe each body loaded from the inventory through CMD, with the resource as JSON on stdin, and serve what it prints instead (printing nothing keeps the body)"
+    )]
+    pub transform: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "keep",
+        help = "Security headers of replayed responses: keep as recorded, strip HSTS/CSP/X-Frame-Options, or harden (drop HSTS and other headers the browser persists, enforce page policies)"
+    )]
+    pub security_headers: security_headers::SecurityHeaders,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value = "replay",
+        help = "Recorded redirects: replay every hop with its timing, or collapse (answer each at once, pointing at the end of its chain)"
+    )]
+    pub redirects: crate::redirects::RedirectMode,
+
+    #[arg(
+        long = "session",
+        value_name = "NAME=DIR",
+        help = "Also load the inventory in DIR, served to requests with an X-HPP-Session: NAME header (repeatable)"
+    )]
+    pub sessions: Vec<String>,
+
+    #[arg(
+        long = "session-client",
+        value_name = "IP=NAME",
+        help = "Serve requests from this client IP without an X-HPP-Session header from session NAME (repeatable)"
+    )]
+    pub session_clients: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Reload the inventory when index.json or a content file changes (POST http://hpp.local/_reload always does)"
+    )]
+    pub watch: bool,
+
+    #[arg(
+        long,
+        help = "Answer each client's requests to a host over no more concurrent responses than the recording opened HTTP/1.1 connections to it"
+    )]
+    pub limit_connections: bool,
+
+    #[arg(
+        long,
+        help = "Answer unrecorded OPTIONS/CORS preflight requests with a permissive 204 response"
+    )]
+    pub synthesize_options: bool,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "Pretend playback starts this far into the session (e.g. 30s); earlier requests replay with no delay"
+    )]
+    pub start_offset: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Send bodies in chunks of this size (default: derived from each resource's transfer rate)"
+    )]
+    pub chunk_size: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = transaction::DEFAULT_PACING_RESOLUTION_MS,
+        help = "Target interval between body chunks used to derive the chunk size"
+    )]
+    pub pacing_resolution_ms: u64,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "Model TCP slow start with this round-trip time: the start of each body is sent in doubling windows (e.g. 80ms)"
+    )]
+    pub slow_start_rtt: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "SEGMENTS",
+        default_value_t = transaction::DEFAULT_INIT_CWND,
+        help = "Initial congestion window for --slow-start-rtt, in 1460-byte segments"
+    )]
+    pub init_cwnd: u64,
+
+    #[arg(
+        long,
+        help = "Add X-HPP-Target-TTFB, X-HPP-Actual-TTFB and X-HPP-Transaction-Id headers to replayed responses for debugging"
+    )]
+    pub timing_headers: bool,
+
+    #[arg(
+        long,
+        help = "Busy-wait the last milliseconds of TTFB and chunk delays for sub-millisecond accuracy (uses more CPU)"
+    )]
+    pub precise_timing: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Open at most this many content files at once; further reads wait (default: unlimited)"
+    )]
+    pub max_open_files: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Write playback-journal.jsonl into the inventory at shutdown: one line per served request with its timing"
+    )]
+    pub journal: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the playback journal to this path instead (implies --journal)"
+    )]
+    pub journal_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the run manifest to this path instead of playback-manifest.json in the inventory"
+    )]
+    pub manifest: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = transaction::DEFAULT_STREAM_THRESHOLD,
+        help = "Read uncompressed content files at least this large from disk as the client consumes them"
+    )]
+    pub stream_threshold: u64,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = transaction::DEFAULT_BODY_CACHE_SIZE,
+        help = "Keep up to this many bytes of bodies loaded on demand, least recently used evicted first (0 disables)"
+    )]
+    pub body_cache_size: usize,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size_bytes,
+        help = "Keep preloaded and cached bodies within this size (e.g. 2GB); the largest preloads load on demand instead"
+    )]
+    pub max_memory: Option<u64>,
+
+    /// Set when the proxy runs embedded through `ProxyServer`
+    #[arg(skip)]
+    #[serde(skip)]
+    pub hooks: ServerHooks,
+}
+
+impl PlaybackOptions {
+    fn chunk_pacing(&self) -> transaction::ChunkPacing {
+        transaction::ChunkPacing {
+            chunk_size: self.chunk_size,
+            resolution_ms: self.pacing_resolution_ms,
+            stream_threshold: self.stream_threshold,
+            slow_start: self
+                .slow_start_rtt
+                .map(|rtt_ms| transaction::SlowStart::new(self.init_cwnd, rtt_ms)),
+        }
+    }
+
+    fn journal_path(&self, inventory_dir: &Path) -> Option<PathBuf> {
+        match &self.journal_path {
+            Some(path) => Some(path.clone()),
+            None if self.journal => Some(inventory_dir.join("playback-journal.jsonl")),
+            None => None,
+        }
+    }
+
+    fn transform_command(&self) -> Option<transform::TransformCommand> {
+        self.transform.clone().map(transform::TransformCommand::new)
+    }
+
+    fn preload_policy(&self) -> Result<transaction::PreloadPolicy> {
+        if self.preload {
+            return Ok(transaction::PreloadPolicy::All);
+        }
+        if self.preload_urls.is_empty() {
+            return Ok(transaction::PreloadPolicy::None);
+        }
+        let patterns = self
+            .preload_urls
+            .iter()
+            .map(|p| {
+                Regex::new(p).map_err(|e| anyhow::anyhow!("Invalid --preload-urls {}: {}", p, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(transaction::PreloadPolicy::Matching(patterns))
+    }
+}
+
+pub async fn run_playback_mode(
+    port: Option<u16>,
+    inventory_dir: PathBuf,
+    options: PlaybackOptions,
+) -> Result<()> {
     let port = get_port_or_default(port)?;
 
     println!("Starting playback mode on port {}", port);
@@ -25,24 +465,409 @@ pub async fn run_playback_mode(port: Option<u16>, inventory_dir: PathBuf) -> Res
 
     // Load inventory
     let file_system = Arc::new(RealFileSystem);
-    let inventory = load_inventory(&inventory_dir, file_system.clone()).await?;
+    let mut inventory = load_inventory(&inventory_dir, file_system.clone()).await?;
 
     println!(
         "Loaded {} resources from inventory",
         inventory.resources.len()
     );
 
-    // Convert resources to transactions
-    let transactions = transaction::convert_resources_to_transactions(
-        &inventory,
-        &inventory_dir,
+    adjust_inventory(&mut inventory, &options)?;
+    apply_header_overrides(&mut inventory, &inventory_dir, file_system.as_ref()).await?;
+
+    if options.browser.launch_chrome && inventory.entry_url.is_none() {
+        anyhow::bail!("--launch-chrome requires an inventory with entryUrl");
+    }
+    if options.self_test.self_test && inventory.entry_url.is_none() {
+        anyhow::bail!("--self-test requires an inventory with entryUrl");
+    }
+
+    let manifest = RunManifest::start(RunMode::Playback, port, &options)?.with_seed(inventory.seed);
+    let manifest_path = options
+        .manifest
+        .clone()
+        .unwrap_or_else(|| inventory_dir.join("playback-manifest.json"));
+
+    // Served responses are always counted for the manifest, and kept for the journal
+    let journal_path = options.journal_path(&inventory_dir);
+    let served = match journal_path {
+        Some(_) => served_log::ServedLog::new(),
+        None => served_log::ServedLog::totals_only(),
+    }
+    .with_timing_tolerance(options.quality.tolerance_ms());
+    let quality = options.quality.clone();
+    serve_inventory(
+        port,
+        inventory,
+        inventory_dir.clone(),
+        options,
+        Some(served.clone()),
+        true,
+    )
+    .await?;
+
+    if let Some(path) = journal_path {
+        file_system
+            .write_string(&path, &served.to_jsonl()?)
+            .await
+            .context(RunFailure::SaveFailed)?;
+        println!(
+            "Journal of {} served requests written to {:?}",
+            served.entries().len(),
+            path
+        );
+    }
+    let summary = served.summary();
+    println!(
+        "Served {} requests, {:.1}% on time, {} without a recording",
+        summary.requests,
+        summary.on_time_percent(),
+        summary.misses
+    );
+    manifest
+        .finish_or_warn(&manifest_path, &inventory_dir, &summary)
+        .await;
+    match quality.check(&summary) {
+        Some(failure) => Err(failure.into()),
+        None => Ok(()),
+    }
+}
+
+/// Apply the network profile, timing limits, host profiles, time scale, start offset,
+/// header preset and redirect mode of `options` to a loaded inventory
+pub fn adjust_inventory(inventory: &mut Inventory, options: &PlaybackOptions) -> Result<()> {
+    if let Some(profile) = options.network_profile {
+        let adjusted = profile.apply(inventory);
+        println!(
+            "Network profile {:?}: recorded timing of {} resources replaced",
+            profile, adjusted
+        );
+    }
+
+    if !options.timing_limits.is_empty() {
+        let adjusted = options.timing_limits.apply(inventory)?;
+        println!("Timing limits: {} resources clamped", adjusted);
+    }
+
+    let host_profiles = host_profiles::HostProfiles::new(&options.host_profiles)?;
+    if !host_profiles.is_empty() {
+        let adjusted = host_profiles.apply(inventory);
+        println!("Host profiles: {} resources adjusted", adjusted);
+    }
+
+    if !options.time_scale.is_empty() {
+        let adjusted = options.time_scale.apply(inventory);
+        println!("Time scale: {} resources rescaled", adjusted);
+    }
+
+    if let Some(offset_ms) = options.start_offset {
+        let skipped = apply_start_offset(inventory, offset_ms);
+        println!(
+            "Start offset {}ms: {} resources requested earlier replay with no delay",
+            offset_ms, skipped
+        );
+    }
+
+    let rewritten = options.security_headers.apply(inventory);
+    if rewritten > 0 {
+        println!(
+            "Security headers ({:?}): {} responses rewritten",
+            options.security_headers, rewritten
+        );
+    }
+
+    let collapsed = options.redirects.apply(inventory);
+    if collapsed > 0 {
+        println!(
+            "Redirects collapsed: {} redirects answered at once with the end of their chain",
+            collapsed
+        );
+    }
+    Ok(())
+}
+
+/// Apply the rules of `header-overrides.yaml` in `inventory_dir`, when it has one, to a
+/// loaded inventory
+pub async fn apply_header_overrides<F: FileSystem>(
+    inventory: &mut Inventory,
+    inventory_dir: &Path,
+    file_system: &F,
+) -> Result<()> {
+    let Some(overrides) =
+        header_overrides::HeaderOverrides::load(inventory_dir, file_system).await?
+    else {
+        return Ok(());
+    };
+    let rewritten = overrides.apply(inventory);
+    println!("Header overrides: {} responses rewritten", rewritten);
+    Ok(())
+}
+
+/// Load the inventories of `--session` and their `--session-client` assignments
+async fn load_sessions<F: FileSystem + 'static>(
+    options: &PlaybackOptions,
+    file_system: Arc<F>,
+    preload_policy: &transaction::PreloadPolicy,
+    pacing: &transaction::ChunkPacing,
+) -> Result<sessions::Sessions<F>> {
+    let mut loaded = sessions::Sessions::default();
+    for spec in &options.sessions {
+        let (name, dir) = sessions::parse_session_spec(spec)?;
+        let mut inventory = load_inventory(&dir, file_system.clone())
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to load session {:?} from {:?}: {}", name, dir, e)
+            })?;
+        adjust_inventory(&mut inventory, options)?;
+        apply_header_overrides(&mut inventory, &dir, file_system.as_ref()).await?;
+
+        let transactions = transaction::prepare_transactions(
+            &inventory,
+            &dir,
+            file_system.clone(),
+            preload_policy,
+            pacing,
+            options.transform_command().as_ref(),
+        )
+        .await?;
+        println!(
+            "Session {:?}: {} transactions from {:?}",
+            name,
+            transactions.len(),
+            dir
+        );
+        let loader =
+            transaction::TransactionLoader::new(inventory.resources, dir, file_system.clone())
+                .with_pacing(*pacing)
+                .with_cache(options.body_cache_size)
+                .with_transform(options.transform_command());
+        loaded.insert(name, transactions, loader);
+    }
+    for spec in &options.session_clients {
+        let (client, name) = sessions::parse_client_spec(spec)?;
+        loaded.assign_client(client, name)?;
+    }
+    Ok(loaded)
+}
+
+/// Transactions of the main inventory and the loader for their bodies, within the
+/// memory budget of `options`
+async fn prepare_main<F: FileSystem + 'static>(
+    inventory: &Inventory,
+    inventory_dir: &Path,
+    file_system: Arc<F>,
+    options: &PlaybackOptions,
+) -> Result<(
+    Vec<Transaction>,
+    transaction::TransactionLoader<F>,
+    memory::MemoryPlan,
+)> {
+    let preload_policy = options.preload_policy()?;
+    let pacing = options.chunk_pacing();
+    let memory_plan = memory::MemoryPlan::new(
+        inventory,
+        inventory_dir,
+        file_system.as_ref(),
+        &preload_policy,
+        &pacing,
+        options.max_memory,
+    )
+    .await?;
+    println!(
+        "Preloading every body would take {} bytes of memory ({} large files stream from disk)",
+        memory_plan.total_bytes,
+        memory_plan.count(memory::Serving::Streamed)
+    );
+    let main_policy = match options.max_memory {
+        Some(max_memory) => {
+            println!(
+                "Memory budget {} bytes: {} bodies load on demand to stay within it",
+                max_memory,
+                memory_plan.count(memory::Serving::OverBudget)
+            );
+            memory_plan.preload_policy(&preload_policy)
+        }
+        None => preload_policy.clone(),
+    };
+
+    // Convert resources to transactions (bodies outside the preload policy load on demand)
+    let transform = options.transform_command();
+    let transactions = transaction::prepare_transactions(
+        inventory,
+        inventory_dir,
         file_system.clone(),
+        &main_policy,
+        &pacing,
+        transform.as_ref(),
     )
     .await?;
 
+    let preloaded: Vec<&Transaction> = transactions.iter().filter(|t| !t.lazy).collect();
+    let preloaded_bytes: usize = preloaded
+        .iter()
+        .filter(|t| t.streamed.is_none())
+        .map(|t| t.body_size())
+        .sum();
     println!("Created {} transactions", transactions.len());
+    println!(
+        "Preloaded {} transactions ({} bytes in memory), {} load on demand",
+        preloaded.len(),
+        preloaded_bytes,
+        transactions.len() - preloaded.len()
+    );
+
+    let loader = transaction::TransactionLoader::new(
+        inventory.resources.clone(),
+        inventory_dir.to_path_buf(),
+        file_system,
+    )
+    .with_pacing(pacing)
+    .with_transform(transform)
+    // The cache gets what the budget leaves after preloading
+    .with_cache(match options.max_memory {
+        Some(max_memory) => options
+            .body_cache_size
+            .min(max_memory.saturating_sub(memory_plan.preloaded_bytes) as usize),
+        None => options.body_cache_size,
+    });
+    Ok((transactions, loader, memory_plan))
+}
+
+/// Prepare transactions for a loaded inventory and run a playback proxy until shutdown
+///
+/// When `served_log` is given, every served response is recorded into it. A
+/// `reloadable` proxy reads the inventory in `inventory_dir` again on request, so it
+/// must be what `inventory` was loaded from.
+pub async fn serve_inventory(
+    port: u16,
+    inventory: Inventory,
+    inventory_dir: PathBuf,
+    options: PlaybackOptions,
+    served_log: Option<served_log::ServedLog>,
+    reloadable: bool,
+) -> Result<()> {
+    // Lazy loading opens files per request, so parallel clients are capped here.
+    // Files edited under --watch may shrink while served, so they are never mapped.
+    let file_system = Arc::new(
+        PooledFileSystem::new(RealFileSystem, options.max_open_files)
+            .with_memory_maps(!options.watch),
+    );
+
+    let (transactions, loader, memory_plan) =
+        prepare_main(&inventory, &inventory_dir, file_system.clone(), &options).await?;
+    let sessions = load_sessions(
+        &options,
+        file_system.clone(),
+        &options.preload_policy()?,
+        &options.chunk_pacing(),
+    )
+    .await?;
+
+    // --match overrides the strategies the inventory asks for
+    let mut options = options;
+    if options.match_strategies.is_empty() {
+        options.match_strategies = inventory.match_strategies.clone().unwrap_or_default();
+    }
+    if !options.match_strategies.is_empty() {
+        println!("Matching strategies: {:?}", options.match_strategies);
+    }
+
+    // The inventory is rewritten when playback stops, so nothing else may write it
+    let fallback_recorder = if options.fallback_record {
+        if options.fallback != fallback::Fallback::Origin {
+            anyhow::bail!("--fallback-record requires --fallback origin");
+        }
+        let lock = InventoryLock::acquire(&inventory_dir, "playback --fallback-record", false)?;
+        Some((lock, Arc::new(fallback::FallbackRecorder::default())))
+    } else {
+        None
+    };
+
+    let reloader = reloadable.then(|| {
+        if options.watch {
+            println!("Watching {:?} for changes", inventory_dir);
+        }
+        reload::Reloader::new(inventory_dir.clone(), options.clone(), file_system.clone())
+    });
+
+    let connection_limits = if options.limit_connections {
+        let limits = connection_limits::ConnectionLimits::new(&inventory.client_connections);
+        if limits.is_empty() {
+            println!("Connection limits: the inventory records no HTTP/1.1 client connections");
+        } else {
+            println!(
+                "Connection limits: {} hosts",
+                inventory
+                    .client_connections
+                    .iter()
+                    .filter(|host| !host.multiplexed)
+                    .count()
+            );
+        }
+        Some(limits)
+    } else {
+        None
+    };
+
+    proxy::start_playback_proxy::<PooledFileSystem<RealFileSystem>>(
+        port,
+        transactions,
+        loader,
+        sessions,
+        connection_limits,
+        protocols::http1_hosts(&inventory),
+        inventory.entry_url.clone(),
+        inventory.device_type.clone(),
+        options,
+        served_log,
+        memory_plan,
+        fallback_recorder
+            .as_ref()
+            .map(|(_, recorder)| recorder.clone()),
+        reloader,
+    )
+    .await?;
+
+    if let Some((_lock, recorder)) = fallback_recorder {
+        let resources = recorder.take_resources();
+        if !resources.is_empty() {
+            let summary = crate::recording::add_resources(&inventory_dir, resources)
+                .await
+                .context(RunFailure::SaveFailed)?;
+            println!(
+                "Fallback recording: {} added, {} updated in {:?}",
+                summary.added, summary.updated, inventory_dir
+            );
+        }
+    }
+    Ok(())
+}
 
-    proxy::start_playback_proxy::<RealFileSystem>(port, transactions).await
+/// Zero the timing of resources requested before `offset_ms` into the recorded session
+///
+/// Returns how many resources were fast-forwarded. Resources recorded without a
+/// request start time keep their timing.
+pub fn apply_start_offset(inventory: &mut Inventory, offset_ms: u64) -> usize {
+    let mut skipped = 0;
+    for resource in &mut inventory.resources {
+        if resource
+            .request_start_ms
+            .is_some_and(|start_ms| start_ms < offset_ms)
+        {
+            resource.ttfb_ms = 0;
+            resource.duration_ms = Some(0);
+            skipped += 1;
+        }
+    }
+    if skipped == 0
+        && inventory
+            .resources
+            .iter()
+            .all(|r| r.request_start_ms.is_none())
+    {
+        tracing::warn!("Inventory has no requestStartMs; --start-offset has no effect");
+    }
+    skipped
 }
 
 pub async fn load_inventory<F: FileSystem>(
@@ -0,0 +1,123 @@
+//! Standardized lab network conditions
+//!
+//! `--network-profile` replaces the timing recorded for every resource with a synthetic
+//! model of the client's access link, so replays do not depend on the network the
+//! recording machine happened to be on. Each response starts one round trip after its
+//! request (the server is taken to answer instantly), request bodies go up at the
+//! link's upload rate, and response bodies come down at its download rate, which a
+//! client's concurrent responses share. The presets follow WebPageTest's connectivity
+//! profiles; `unthrottled` answers everything without delay.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use super::pacing::TokenBucket;
+use crate::types::Inventory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NetworkProfile {
+    /// 400 Kbps down, 400 Kbps up, 400ms round trip
+    #[value(name = "slow-3g")]
+    #[serde(rename = "slow-3g")]
+    Slow3g,
+    /// 1.6 Mbps down, 768 Kbps up, 150ms round trip
+    #[value(name = "fast-3g")]
+    #[serde(rename = "fast-3g")]
+    Fast3g,
+    /// 9 Mbps down, 9 Mbps up, 170ms round trip
+    #[value(name = "4g")]
+    #[serde(rename = "4g")]
+    Lte,
+    /// 5 Mbps down, 1 Mbps up, 28ms round trip
+    Cable,
+    /// No delay at all
+    Unthrottled,
+}
+
+/// Bandwidth and latency of a client's access link
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkConditions {
+    pub down_mbps: f64,
+    pub up_mbps: f64,
+    pub rtt_ms: u64,
+}
+
+impl NetworkProfile {
+    /// The link this profile models; None when unthrottled
+    pub fn conditions(self) -> Option<LinkConditions> {
+        let (down_mbps, up_mbps, rtt_ms) = match self {
+            NetworkProfile::Slow3g => (0.4, 0.4, 400),
+            NetworkProfile::Fast3g => (1.6, 0.768, 150),
+            NetworkProfile::Lte => (9.0, 9.0, 170),
+            NetworkProfile::Cable => (5.0, 1.0, 28),
+            NetworkProfile::Unthrottled => return None,
+        };
+        Some(LinkConditions {
+            down_mbps,
+            up_mbps,
+            rtt_ms,
+        })
+    }
+
+    /// Replace the recorded TTFB, transfer and upload timing of every resource
+    ///
+    /// Bodies are paced at the full download rate here; `NetworkLinks` slows down
+    /// responses that overlap. Returns how many resources were adjusted.
+    pub fn apply(self, inventory: &mut Inventory) -> usize {
+        let conditions = self.conditions();
+        for resource in &mut inventory.resources {
+            match conditions {
+                Some(link) => {
+                    resource.ttfb_ms = link.rtt_ms;
+                    resource.mbps = Some(link.down_mbps);
+                    resource.duration_ms = None;
+                    resource.upload_ms = resource
+                        .upload_bytes
+                        .map(|bytes| (bytes as f64 / bytes_per_ms(link.up_mbps)).round() as u64);
+                }
+                None => {
+                    resource.ttfb_ms = 0;
+                    resource.mbps = None;
+                    resource.duration_ms = Some(0);
+                    resource.upload_ms = None;
+                }
+            }
+        }
+        inventory.resources.len()
+    }
+}
+
+fn bytes_per_ms(mbps: f64) -> f64 {
+    mbps * 1000.0 * 1000.0 / 8.0 / 1000.0
+}
+
+/// The download bandwidth each client's concurrent responses share
+#[derive(Debug)]
+pub struct NetworkLinks {
+    down_mbps: f64,
+    // One bucket per client, created on its first response
+    links: Mutex<HashMap<IpAddr, Arc<TokenBucket>>>,
+}
+
+impl NetworkLinks {
+    /// None when the profile does not limit bandwidth
+    pub fn new(profile: NetworkProfile) -> Option<Self> {
+        let link = profile.conditions()?;
+        Some(Self {
+            down_mbps: link.down_mbps,
+            links: Mutex::default(),
+        })
+    }
+
+    /// The bucket every response body to `client` is paced through
+    pub fn link(&self, client: IpAddr) -> Arc<TokenBucket> {
+        self.links
+            .lock()
+            .unwrap()
+            .entry(client)
+            .or_insert_with(|| Arc::new(TokenBucket::for_link(self.down_mbps)))
+            .clone()
+    }
+}
@@ -0,0 +1,54 @@
+//! What replayed responses that never ended do after their recorded part
+//!
+//! A long poll the client gave up on, a stream still running when recording stopped
+//! and an event stream that was still open are recorded with what had arrived. Once
+//! that is sent, the connection is held open like the origin held it, until the client
+//! disconnects or for `--hold-open`, or `--open-ended close` ends the response right
+//! away so clients that poll again do so without waiting.
+
+use std::time::Duration;
+
+use crate::utils::parse_duration_ms;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OpenEnded {
+    /// Keep the response open after its recorded part
+    #[default]
+    Hold,
+    /// End the response after its recorded part
+    Close,
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenEndedOptions {
+    #[arg(
+        long,
+        value_enum,
+        default_value = "hold",
+        help = "After the recorded part of a response that never ended (a long poll or stream cut off while recording), hold the connection open or close it"
+    )]
+    pub open_ended: OpenEnded,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "Close held responses this long after their recorded part (e.g. 30s; default: when the client disconnects)"
+    )]
+    pub hold_open: Option<u64>,
+}
+
+impl OpenEndedOptions {
+    /// Wait for as long as the response stays open after its recorded part
+    pub async fn hold(&self) {
+        match (self.open_ended, self.hold_open) {
+            (OpenEnded::Close, _) => {}
+            (OpenEnded::Hold, Some(hold_ms)) => {
+                tokio::time::sleep(Duration::from_millis(hold_ms)).await
+            }
+            (OpenEnded::Hold, None) => std::future::pending().await,
+        }
+    }
+}
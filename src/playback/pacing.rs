@@ -0,0 +1,164 @@
+//! Token-bucket pacing shared by every playback stream
+//!
+//! Each response body is paced by its own bucket filling at the recorded transfer
+//! rate, optionally combined with buckets shared between streams to cap their
+//! aggregate throughput. A chunk is sent as soon as the previous ones are paid for, and
+//! waits are computed from the bucket state rather than accumulated, so oversleeping
+//! one chunk shortens the wait for the next instead of shifting the whole transfer.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::traits::{RealTimeProvider, TimeProvider};
+
+/// Longest stretch of delayed sending a stream may catch up on in one burst
+pub const CATCH_UP_WINDOW_MS: f64 = 250.0;
+
+/// Byte-rate limiter that can be shared between streams
+///
+/// Sending is allowed while the bucket is not in debt; sent bytes are taken out
+/// afterwards, so the first send is immediate and each later one waits for the bytes
+/// before it. Unused credit is capped at `capacity`, which bounds how much a stream
+/// that fell behind sends at once when it resumes.
+#[derive(Debug)]
+pub struct TokenBucket {
+    bytes_per_ms: f64,
+    capacity: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(bytes_per_ms: f64, capacity: f64) -> Self {
+        Self::new_at(bytes_per_ms, capacity, Instant::now())
+    }
+
+    /// An empty bucket that starts filling at `now`
+    pub fn new_at(bytes_per_ms: f64, capacity: f64, now: Instant) -> Self {
+        Self {
+            bytes_per_ms,
+            capacity,
+            state: Mutex::new(BucketState {
+                tokens: 0.0,
+                updated: now,
+            }),
+        }
+    }
+
+    /// A bucket for a link of `mbps` megabits per second that several streams share
+    pub fn for_link(mbps: f64) -> Self {
+        let bytes_per_ms = mbps * 1000.0 * 1000.0 / 8.0 / 1000.0;
+        Self::new(bytes_per_ms, bytes_per_ms * CATCH_UP_WINDOW_MS)
+    }
+
+    /// Account for `bytes` about to be sent at `now`; returns how long to wait first
+    pub fn reserve_at(&self, bytes: usize, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.refill(&mut state, now);
+        let wait = self.debt_wait(&state);
+        state.tokens -= bytes as f64;
+        wait
+    }
+
+    /// How long until everything reserved so far has been paid for
+    pub fn drain_at(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.refill(&mut state, now);
+        self.debt_wait(&state)
+    }
+
+    fn refill(&self, state: &mut BucketState, now: Instant) {
+        let elapsed_ms = now.saturating_duration_since(state.updated).as_secs_f64() * 1000.0;
+        state.tokens = (state.tokens + elapsed_ms * self.bytes_per_ms).min(self.capacity);
+        state.updated = now;
+    }
+
+    fn debt_wait(&self, state: &BucketState) -> Duration {
+        if state.tokens >= 0.0 || self.bytes_per_ms <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-state.tokens / self.bytes_per_ms / 1000.0)
+    }
+}
+
+/// Paces one response body through its own bucket and any shared ones
+pub struct Pacer {
+    own: Option<TokenBucket>,
+    shared: Vec<Arc<TokenBucket>>,
+    clock: Arc<dyn TimeProvider>,
+}
+
+impl Pacer {
+    /// Pace `total_bytes` so they finish `duration_ms` after the first one is sent
+    pub fn for_transfer(total_bytes: usize, duration_ms: u64, shared: &[Arc<TokenBucket>]) -> Self {
+        let own = (total_bytes > 0 && duration_ms > 0).then(|| {
+            let bytes_per_ms = total_bytes as f64 / duration_ms as f64;
+            TokenBucket::new(bytes_per_ms, bytes_per_ms * CATCH_UP_WINDOW_MS)
+        });
+        Self {
+            own,
+            shared: shared.to_vec(),
+            clock: Arc::new(RealTimeProvider::new()),
+        }
+    }
+
+    /// Pace a body whose chunks keep their own schedule through the shared buckets only
+    pub fn for_schedule(shared: &[Arc<TokenBucket>]) -> Self {
+        Self {
+            own: None,
+            shared: shared.to_vec(),
+            clock: Arc::new(RealTimeProvider::new()),
+        }
+    }
+
+    /// Take the time from and wait on `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn TimeProvider>) -> Self {
+        // The stream's own bucket starts filling at the clock's present
+        self.own = self
+            .own
+            .map(|own| TokenBucket::new_at(own.bytes_per_ms, own.capacity, clock.now()));
+        self.clock = clock;
+        self
+    }
+
+    /// Current time on the pacer's clock
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Wait until `deadline` on the pacer's clock, regardless of the buckets
+    pub async fn wait_until(&self, deadline: Instant) {
+        self.clock.sleep_until(deadline).await;
+    }
+
+    /// Wait until `bytes` may be sent
+    pub async fn pace(&self, bytes: usize) {
+        let now = self.clock.now();
+        let wait = self
+            .own
+            .iter()
+            .chain(self.shared.iter().map(Arc::as_ref))
+            .map(|bucket| bucket.reserve_at(bytes, now))
+            .max()
+            .unwrap_or_default();
+        self.clock.sleep_until(now + wait).await;
+    }
+
+    /// Wait until this stream's bytes have been paid for, i.e. the transfer's end time
+    ///
+    /// Shared buckets are not drained: other streams' traffic must not hold this one open.
+    pub async fn drain(&self) {
+        let now = self.clock.now();
+        let wait = self
+            .own
+            .as_ref()
+            .map(|bucket| bucket.drain_at(now))
+            .unwrap_or_default();
+        self.clock.sleep_until(now + wait).await;
+    }
+}
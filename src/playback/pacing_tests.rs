@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use crate::playback::pacing::{CATCH_UP_WINDOW_MS, TokenBucket};
+    use std::time::{Duration, Instant};
+
+    fn assert_ms(actual: Duration, expected_ms: f64) {
+        let actual_ms = actual.as_secs_f64() * 1000.0;
+        assert!(
+            (actual_ms - expected_ms).abs() < 0.001,
+            "expected {}ms, got {}ms",
+            expected_ms,
+            actual_ms
+        );
+    }
+
+    #[test]
+    fn test_bucket_spaces_sends_by_rate() {
+        let start = Instant::now();
+        // 100 bytes/ms
+        let bucket = TokenBucket::new(100.0, 100.0 * CATCH_UP_WINDOW_MS);
+
+        // First send is immediate, the next waits for the bytes before it
+        assert_eq!(bucket.reserve_at(1000, start), Duration::ZERO);
+        assert_ms(bucket.reserve_at(1000, start), 10.0);
+
+        // Waking up on time: the next chunk waits only for the remaining debt
+        let wait = bucket.reserve_at(1000, start + Duration::from_millis(10));
+        assert_ms(wait, 10.0);
+
+        // Oversleeping shortens the following wait instead of shifting the schedule
+        let wait = bucket.reserve_at(1000, start + Duration::from_millis(25));
+        assert_ms(wait, 5.0);
+
+        assert_ms(bucket.drain_at(start + Duration::from_millis(30)), 10.0);
+    }
+
+    #[test]
+    fn test_bucket_bounds_catch_up_after_stall() {
+        let start = Instant::now();
+        let bucket = TokenBucket::new(1.0, 50.0);
+
+        assert_eq!(bucket.reserve_at(10, start), Duration::ZERO);
+
+        // A long stall only earns `capacity` worth of credit
+        let resumed = start + Duration::from_secs(10);
+        assert_eq!(bucket.reserve_at(40, resumed), Duration::ZERO);
+        assert_eq!(bucket.reserve_at(40, resumed), Duration::ZERO);
+        assert_ms(bucket.reserve_at(40, resumed), 30.0);
+    }
+
+    #[tokio::test]
+    async fn test_pacer_finishes_at_transfer_duration() {
+        use crate::playback::pacing::Pacer;
+        use crate::traits::TimeProvider;
+        use crate::traits::mocks::MockTimeProvider;
+        use std::sync::Arc;
+
+        let clock = Arc::new(MockTimeProvider::new(0));
+        let pacer = Pacer::for_transfer(4000, 40, &[]).with_clock(clock.clone());
+        // Each chunk waits for the bytes of the one before it
+        let mut sent_at = Vec::new();
+        for _ in 0..4 {
+            pacer.pace(1000).await;
+            sent_at.push(clock.now_ms());
+        }
+        assert_eq!(sent_at, [0, 10, 20, 30]);
+        pacer.drain().await;
+        assert_eq!(clock.now_ms(), 40);
+
+        // Falling behind is caught up on instead of shifting the rest of the transfer
+        let pacer = Pacer::for_transfer(4000, 40, &[]).with_clock(clock.clone());
+        pacer.pace(1000).await;
+        clock.advance(25);
+        pacer.pace(1000).await;
+        pacer.pace(1000).await;
+        assert_eq!(clock.now_ms(), 65);
+        pacer.pace(1000).await;
+        pacer.drain().await;
+        assert_eq!(clock.now_ms(), 80);
+    }
+
+    #[tokio::test]
+    async fn test_shared_link_caps_concurrent_transfers() {
+        use crate::playback::pacing::Pacer;
+        use crate::traits::TimeProvider;
+        use crate::traits::mocks::MockTimeProvider;
+        use std::sync::Arc;
+
+        // 8 Mbps is 1000 bytes/ms, as fast as each transfer alone would go
+        let clock = Arc::new(MockTimeProvider::new(0));
+        let link = Arc::new(TokenBucket::for_link(8.0));
+        let transfer = || async {
+            let pacer = Pacer::for_transfer(10_000, 10, std::slice::from_ref(&link))
+                .with_clock(clock.clone());
+            for _ in 0..10 {
+                pacer.pace(1000).await;
+            }
+            pacer.drain().await;
+        };
+
+        tokio::join!(transfer(), transfer());
+        let elapsed = clock.now_ms();
+        assert!((19..=21).contains(&elapsed), "took {}ms", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_precise_sleep_does_not_wake_early() {
+        use crate::traits::{SleepMode, sleep_until};
+
+        for delay_us in [500, 3_000, 7_500] {
+            let deadline = Instant::now() + Duration::from_micros(delay_us);
+            sleep_until(deadline, SleepMode::Precise).await;
+            let now = Instant::now();
+            assert!(now >= deadline);
+            assert!(
+                now - deadline < Duration::from_millis(5),
+                "woke {:?} late",
+                now - deadline
+            );
+        }
+    }
+}
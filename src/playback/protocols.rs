@@ -0,0 +1,100 @@
+//! Offering clients the HTTP version each origin spoke
+//!
+//! The proxy advertises both h2 and http/1.1 when it intercepts a TLS connection, so a
+//! browser would replay over HTTP/2 even from origins it reached over HTTP/1.1, and
+//! multiplex requests that queued behind six connections in the recording. With
+//! `--http2 recorded` (the default) a host is only offered http/1.1 when every resource
+//! recorded from it came over HTTP/1.x; hosts reached over h2, or recorded before the
+//! protocol was, keep h2. `always` offers h2 to every host and `never` to none.
+
+use http::uri::Authority;
+use hudsucker::certificate_authority::CertificateAuthority;
+use hudsucker::rustls::ServerConfig;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::types::{HttpProtocol, Inventory};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Http2Policy {
+    /// h2 for hosts the recording reached over HTTP/2 or did not record a protocol for
+    #[default]
+    Recorded,
+    /// h2 for every host
+    Always,
+    /// HTTP/1.1 only
+    Never,
+}
+
+/// Hosts every recorded resource of which was answered over HTTP/1.x
+pub fn http1_hosts(inventory: &Inventory) -> HashSet<String> {
+    let mut hosts: HashMap<String, bool> = HashMap::new();
+    for resource in &inventory.resources {
+        let Ok(uri) = resource.url.parse::<hyper::Uri>() else {
+            continue;
+        };
+        let Some(host) = uri.host() else {
+            continue;
+        };
+        let http1 = matches!(
+            resource.protocol,
+            Some(HttpProtocol::Http10 | HttpProtocol::Http11)
+        );
+        *hosts.entry(host.to_ascii_lowercase()).or_insert(true) &= http1;
+    }
+    hosts
+        .into_iter()
+        .filter(|(_, http1)| *http1)
+        .map(|(host, _)| host)
+        .collect()
+}
+
+/// Certificate authority that restricts the ALPN protocols offered per host
+pub struct ProtocolAuthority<CA> {
+    inner: CA,
+    policy: Http2Policy,
+    http1_hosts: HashSet<String>,
+    // HTTP/1.1-only copies of the inner authority's server configs
+    configs: Mutex<HashMap<Authority, Arc<ServerConfig>>>,
+}
+
+impl<CA> ProtocolAuthority<CA> {
+    /// `http1_hosts` only matters to the `recorded` policy
+    pub fn new(inner: CA, policy: Http2Policy, http1_hosts: HashSet<String>) -> Self {
+        Self {
+            inner,
+            policy,
+            http1_hosts,
+            configs: Mutex::default(),
+        }
+    }
+
+    pub fn offers_h2(&self, host: &str) -> bool {
+        match self.policy {
+            Http2Policy::Recorded => !self.http1_hosts.contains(&host.to_ascii_lowercase()),
+            Http2Policy::Always => true,
+            Http2Policy::Never => false,
+        }
+    }
+}
+
+impl<CA: CertificateAuthority> CertificateAuthority for ProtocolAuthority<CA> {
+    async fn gen_server_config(&self, authority: &Authority) -> Arc<ServerConfig> {
+        if self.offers_h2(authority.host()) {
+            return self.inner.gen_server_config(authority).await;
+        }
+        let cached = self.configs.lock().unwrap().get(authority).cloned();
+        if let Some(config) = cached {
+            return config;
+        }
+        let mut config = (*self.inner.gen_server_config(authority).await).clone();
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let config = Arc::new(config);
+        self.configs
+            .lock()
+            .unwrap()
+            .insert(authority.clone(), config.clone());
+        config
+    }
+}
@@ -1,10 +1,30 @@
 use anyhow::Result;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::Notify;
 use tracing::{error, info};
 
+use crate::control::{ControlEndpoints, StatusReport};
 use crate::traits::FileSystem;
-use crate::types::Transaction;
+use crate::types::{DeviceType, Transaction};
 
+use super::PlaybackOptions;
+use super::connection_limits::ConnectionLimits;
+use super::fallback::FallbackRecorder;
+use super::filter::FilterCommand;
+use super::host_map::HostMap;
 use super::hudsucker_handler::PlaybackHandler;
+use super::memory::MemoryPlan;
+use super::network_profile::NetworkLinks;
+use super::protocols::ProtocolAuthority;
+use super::rate_limits::RateLimits;
+use super::reload::Reloader;
+use super::self_test;
+use super::served_log::ServedLog;
+use super::sessions::Sessions;
+use super::tokens::TokenRewriter;
+use super::transaction::TransactionLoader;
+use super::watchdog::Watchdog;
 use hudsucker::{
     Proxy as HudsuckerProxy,
     certificate_authority::RcgenAuthority,
@@ -12,9 +32,21 @@ use hudsucker::{
     rustls::crypto::aws_lc_rs,
 };
 
+#[allow(clippy::too_many_arguments)]
 pub async fn start_playback_proxy<F: FileSystem + 'static>(
     port: u16,
     transactions: Vec<Transaction>,
+    loader: TransactionLoader<F>,
+    sessions: Sessions<F>,
+    connection_limits: Option<ConnectionLimits>,
+    http1_hosts: HashSet<String>,
+    entry_url: Option<String>,
+    device: Option<DeviceType>,
+    options: PlaybackOptions,
+    served_log: Option<ServedLog>,
+    memory_plan: MemoryPlan,
+    fallback_recorder: Option<Arc<FallbackRecorder>>,
+    reloader: Option<Reloader<F>>,
 ) -> Result<()> {
     info!("Starting HTTPS MITM playback proxy on port {}", port);
 
@@ -37,30 +69,94 @@ pub async fn start_playback_proxy<F: FileSystem + 'static>(
     let issuer = Issuer::from_ca_cert_pem(&cert.pem(), key_pair)?;
 
     let ca = RcgenAuthority::new(issuer, 1_000, aws_lc_rs::default_provider());
+    let ca = ProtocolAuthority::new(ca, options.http2, http1_hosts);
+
+    // The same CA is offered for download at http://hpp.local/, next to /_start
+    // /status reports how each body is held in memory, POST /_shutdown stops playback
+    // and POST /_reload reads the inventory again
+    let reload_requests = reloader.as_ref().map(|_| Arc::new(Notify::new()));
+    let status = StatusReport::new(serde_json::json!({ "memory": memory_plan }));
+    let reloader = reloader.map(|reloader| reloader.with_status(Some(status.clone())));
+    let control = ControlEndpoints::new(cert.pem(), cert.der().to_vec())
+        .with_entry_url(entry_url.clone())
+        .with_status(Some(status))
+        .with_shutdown(options.hooks.shutdown_handle())
+        .with_reload(reload_requests.clone());
+
+    let watchdog = options
+        .watchdog
+        .is_enabled()
+        .then(|| Arc::new(Watchdog::new(&options.watchdog)));
 
     // Create the playback handler
-    let handler = PlaybackHandler::new(transactions);
+    let mut handler = PlaybackHandler::new(transactions, loader)
+        .with_options_synthesis(options.synthesize_options)
+        .with_precise_timing(options.precise_timing)
+        .with_query_options(options.query)
+        .with_form_matching(options.match_form)
+        .with_match_strategies(options.match_strategies.clone())
+        .with_vary_headers(options.vary_on.clone())
+        .with_vary_cookies(options.vary_on_cookie.clone())
+        .with_cookie_jar(options.cookie_jar)
+        .with_conditional(options.conditional)
+        .with_repeat_mode(options.repeat_mode)
+        .with_fallback(options.fallback)
+        .with_fallback_recorder(fallback_recorder)
+        .with_watchdog(watchdog.clone())
+        .with_open_ended(options.open_ended)
+        .with_error_replay(options.error_replay)
+        .with_token_rewriter(TokenRewriter::new(&options.tokens)?)
+        .with_filter_command(options.filter_cmd.clone().map(FilterCommand::new))
+        .with_host_map(HostMap::new(&options.host_maps)?)
+        .with_sessions(sessions)
+        .with_connection_limits(connection_limits)
+        .with_network_links(options.network_profile.and_then(NetworkLinks::new))
+        .with_bandwidth_limit(options.max_bandwidth)
+        .with_timing_headers(options.timing_headers)
+        .with_rate_limits(RateLimits::new(&options.rate_limits)?)
+        .with_control(control);
+    if let Some(log) = served_log {
+        handler = handler.with_served_log(log);
+    }
+    let self_test_log = options.self_test.self_test.then(ServedLog::new);
+    if let Some(log) = &self_test_log {
+        handler = handler.with_self_test_log(log.clone());
+    }
+
+    let activity = handler.get_activity();
+    let reload_task = reloader.zip(reload_requests).map(|(reloader, requests)| {
+        tokio::spawn(reloader.run(
+            handler.get_main(),
+            handler.get_sequences(),
+            requests,
+            options.watch,
+        ))
+    });
 
     // Build the proxy with standard TLS configuration
     let crypto_provider = aws_lc_rs::default_provider();
 
     // Bind to the socket first to get the actual port (important when port=0)
-    let listener =
-        tokio::net::TcpListener::bind((std::net::Ipv4Addr::new(127, 0, 0, 1), port)).await?;
+    // Socket options set here carry over to accepted client connections
+    let listener = options.socket.bind_listener(port)?;
     let actual_addr = listener.local_addr()?;
     let actual_port = actual_addr.port();
 
     // Build the proxy
-    let proxy = HudsuckerProxy::builder()
+    let mut builder = HudsuckerProxy::builder()
         .with_listener(listener)
         .with_ca(ca)
         .with_rustls_connector(crypto_provider)
-        .with_http_handler(handler)
-        .build()?;
+        .with_http_handler(handler);
+    if let Some(server) = options.socket.server_builder() {
+        builder = builder.with_server(server);
+    }
+    let proxy = builder.build()?;
 
     // Start the proxy server
     info!("HTTPS MITM Proxy listening on 127.0.0.1:{}", actual_port);
     info!("Configure your client to trust the self-signed CA certificate or use --insecure");
+    info!("Download the CA certificate through the proxy at http://hpp.local/");
 
     // Run proxy and signal handler concurrently
     let proxy_task = tokio::spawn(async move {
@@ -69,10 +165,30 @@ pub async fn start_playback_proxy<F: FileSystem + 'static>(
         }
     });
 
-    // Wait for shutdown signal
-    if let Err(e) = super::signal_handler::wait_for_shutdown_signal().await {
-        error!("Signal handler error: {}", e);
+    // Clients are told about the proxy only once it has answered its own request
+    if let (Some(log), Some(url)) = (&self_test_log, entry_url.as_deref()) {
+        let tolerance_ms = options.self_test.tolerance_ms();
+        if let Err(e) = self_test::run(actual_port, url, log, tolerance_ms).await {
+            proxy_task.abort();
+            return Err(e);
+        }
     }
+    options.hooks.listening(actual_port);
+
+    let watchdog_task = watchdog.map(|watchdog| tokio::spawn(watchdog.run()));
+
+    // Wait for shutdown signal (or the end of the headless browser session)
+    crate::browser::wait_for_shutdown_or_browser(
+        options
+            .hooks
+            .wait_for_shutdown(super::signal_handler::wait_for_shutdown_signal()),
+        &options.browser,
+        actual_port,
+        entry_url.as_deref(),
+        device.as_ref(),
+        activity,
+    )
+    .await;
 
     // Signal received, stop accepting new connections
     info!("Shutdown signal received, stopping playback proxy");
@@ -86,6 +202,12 @@ pub async fn start_playback_proxy<F: FileSystem + 'static>(
 
     // Abort proxy task
     proxy_task.abort();
+    if let Some(task) = watchdog_task {
+        task.abort();
+    }
+    if let Some(task) = reload_task {
+        task.abort();
+    }
 
     Ok(())
 }
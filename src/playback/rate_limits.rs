@@ -0,0 +1,122 @@
+//! Simulated rate limiting on top of the recording
+//!
+//! `--rate-limit 'https://api.example.com/**=10/1s'` lets each client send 10 requests
+//! to matching URLs per one-second window; further requests in the same window are
+//! answered `429 Too Many Requests` with `Retry-After` set to the seconds until the
+//! window ends, without touching the recordings. URLs are globs as in hand-authored
+//! resources (`*` within a path segment, `**` across them). A request matching several
+//! limits counts against each of them and is refused if any is exhausted; refused
+//! requests are not counted.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::types::UrlMatch;
+use crate::url_pattern::UrlPattern;
+use crate::utils::parse_duration_ms;
+
+#[derive(Debug)]
+struct RateLimit {
+    pattern: UrlPattern,
+    requests: u32,
+    window: Duration,
+}
+
+/// Requests counted in the current window of one limit and client
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    requests: u32,
+}
+
+#[derive(Debug)]
+pub struct RateLimits {
+    limits: Vec<RateLimit>,
+    // Keyed by limit index and client
+    windows: Mutex<HashMap<(usize, IpAddr), Window>>,
+}
+
+impl RateLimit {
+    /// Parse a `--rate-limit URL=N/WINDOW` value
+    fn parse(spec: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid --rate-limit {:?}: expected URL=N/WINDOW", spec);
+        // URLs may contain '=' in their query, the limit never does
+        let (url, limit) = spec.rsplit_once('=').ok_or_else(invalid)?;
+        let (requests, window) = limit.split_once('/').ok_or_else(invalid)?;
+        let requests: u32 = requests.trim().parse().map_err(|_| invalid())?;
+        let window = Duration::from_millis(parse_duration_ms(window)?);
+        if url.is_empty() || requests == 0 || window.is_zero() {
+            return Err(invalid());
+        }
+        let pattern = UrlPattern::new(url, UrlMatch::Glob)?.ok_or_else(invalid)?;
+        Ok(Self {
+            pattern,
+            requests,
+            window,
+        })
+    }
+}
+
+impl RateLimits {
+    /// None when no limits are configured
+    pub fn new(specs: &[String]) -> Result<Option<Self>> {
+        if specs.is_empty() {
+            return Ok(None);
+        }
+        let limits = specs
+            .iter()
+            .map(|spec| RateLimit::parse(spec))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Self {
+            limits,
+            windows: Mutex::default(),
+        }))
+    }
+
+    /// Count a request of `client` to `url` at `now`
+    ///
+    /// Returns the whole seconds to wait when a limit is exhausted, None when the
+    /// request may be served.
+    pub fn check(&self, client: IpAddr, url: &str, now: Instant) -> Option<u64> {
+        let matching: Vec<usize> = self
+            .limits
+            .iter()
+            .enumerate()
+            .filter(|(_, limit)| limit.pattern.is_match(url))
+            .map(|(index, _)| index)
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+
+        let mut windows = self.windows.lock().unwrap();
+        let mut retry_after: Option<Duration> = None;
+        for &index in &matching {
+            let limit = &self.limits[index];
+            let window = windows.entry((index, client)).or_insert(Window {
+                started: now,
+                requests: 0,
+            });
+            let elapsed = now.saturating_duration_since(window.started);
+            if elapsed >= limit.window {
+                window.started = now;
+                window.requests = 0;
+            } else if window.requests >= limit.requests {
+                let remaining = limit.window - elapsed;
+                retry_after = Some(retry_after.map_or(remaining, |wait| wait.max(remaining)));
+            }
+        }
+        if let Some(wait) = retry_after {
+            return Some(wait.as_secs_f64().ceil().max(1.0) as u64);
+        }
+        for index in matching {
+            if let Some(window) = windows.get_mut(&(index, client)) {
+                window.requests += 1;
+            }
+        }
+        None
+    }
+}
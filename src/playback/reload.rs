@@ -0,0 +1,172 @@
+//! Picking up edits to the inventory without restarting playback
+//!
+//! `POST http://hpp.local/_reload`, or with `--watch` any change to `index.json`,
+//! `header-overrides.yaml` or a file under `contents/`, reads the inventory again and swaps the main inventory's
+//! transactions and body loader in one step. Requests already being answered finish
+//! with what they started with, bodies cached from the old files are dropped with
+//! their loader, and repeated URLs start over at their first recording. An inventory
+//! that fails to load is reported and the previous one keeps serving.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{Notify, RwLock};
+use tracing::{error, info};
+
+use super::header_overrides::HEADER_OVERRIDES_FILE;
+use super::sequence::Sequences;
+use super::sessions::Session;
+use super::{
+    PlaybackOptions, adjust_inventory, apply_header_overrides, load_inventory, prepare_main,
+};
+use crate::control::StatusReport;
+use crate::traits::FileSystem;
+
+/// How often `--watch` looks for changed files
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reads the main inventory again into a running playback proxy
+pub struct Reloader<F: FileSystem + 'static> {
+    inventory_dir: PathBuf,
+    options: PlaybackOptions,
+    file_system: Arc<F>,
+    status: Option<StatusReport>,
+}
+
+impl<F: FileSystem + 'static> Reloader<F> {
+    pub fn new(inventory_dir: PathBuf, options: PlaybackOptions, file_system: Arc<F>) -> Self {
+        Self {
+            inventory_dir,
+            options,
+            file_system,
+            status: None,
+        }
+    }
+
+    /// Replace the memory plan in `status` with the one of each reloaded inventory
+    pub fn with_status(mut self, status: Option<StatusReport>) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Load the inventory and swap it into `main`; returns the number of transactions
+    pub async fn reload(&self, main: &RwLock<Session<F>>, sequences: &Sequences) -> Result<usize> {
+        let mut inventory = load_inventory(&self.inventory_dir, self.file_system.clone()).await?;
+        // Content files may have been edited since recording, so the recorded sizes no
+        // longer describe them
+        for resource in &mut inventory.resources {
+            resource.original_size = None;
+        }
+        adjust_inventory(&mut inventory, &self.options)?;
+        apply_header_overrides(
+            &mut inventory,
+            &self.inventory_dir,
+            self.file_system.as_ref(),
+        )
+        .await?;
+        let (transactions, loader, memory_plan) = prepare_main(
+            &inventory,
+            &self.inventory_dir,
+            self.file_system.clone(),
+            &self.options,
+        )
+        .await?;
+        let count = transactions.len();
+
+        let mut main = main.write().await;
+        *main = Session::new(transactions, loader);
+        sequences.reset();
+        if let Some(status) = &self.status {
+            status.set(serde_json::json!({ "memory": memory_plan }));
+        }
+        Ok(count)
+    }
+
+    /// Reload on every request through `requests`, and on file changes when `watch` is set
+    pub async fn run(
+        self,
+        main: Arc<RwLock<Session<F>>>,
+        sequences: Arc<Sequences>,
+        requests: Arc<Notify>,
+        watch: bool,
+    ) {
+        let mut last_change = if watch {
+            latest_change(&self.inventory_dir).await
+        } else {
+            None
+        };
+        loop {
+            if watch {
+                tokio::select! {
+                    () = requests.notified() => {}
+                    () = tokio::time::sleep(WATCH_INTERVAL) => {
+                        let change = latest_change(&self.inventory_dir).await;
+                        if change == last_change {
+                            continue;
+                        }
+                        last_change = change;
+                        info!("Inventory files changed");
+                    }
+                }
+            } else {
+                requests.notified().await;
+            }
+
+            match self.reload(&main, &sequences).await {
+                Ok(count) => {
+                    info!(
+                        "Reloaded {} transactions from {:?}",
+                        count, self.inventory_dir
+                    );
+                    println!("Reloaded inventory: {} transactions", count);
+                }
+                Err(e) => error!(
+                    "Failed to reload {:?}, still serving the previous inventory: {}",
+                    self.inventory_dir, e
+                ),
+            }
+        }
+    }
+}
+
+/// Newest modification time and file count of `index.json`, `header-overrides.yaml` and
+/// the files under `contents/`
+pub async fn latest_change(inventory_dir: &Path) -> Option<(SystemTime, usize)> {
+    let inventory_dir = inventory_dir.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut latest = std::fs::metadata(inventory_dir.join("index.json"))
+            .and_then(|m| m.modified())
+            .ok()?;
+        let mut files = 1;
+        let overrides =
+            std::fs::metadata(inventory_dir.join(HEADER_OVERRIDES_FILE)).and_then(|m| m.modified());
+        if let Ok(modified) = overrides {
+            latest = latest.max(modified);
+            files += 1;
+        }
+        let mut dirs = vec![inventory_dir.join("contents")];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    dirs.push(entry.path());
+                    continue;
+                }
+                files += 1;
+                if let Ok(modified) = metadata.modified() {
+                    latest = latest.max(modified);
+                }
+            }
+        }
+        Some((latest, files))
+    })
+    .await
+    .ok()
+    .flatten()
+}
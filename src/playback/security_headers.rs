@@ -0,0 +1,92 @@
+//! Security header presets for replayed responses
+//!
+//! Some response headers outlive the page: a replayed `Strict-Transport-Security` from
+//! a production domain is pinned in the browser profile and breaks later plain-HTTP
+//! tests against the same hostname. `--security-headers` rewrites these headers on
+//! every response before transactions are built.
+
+use crate::types::{HeaderValue, HttpHeaders, Inventory};
+
+/// Headers the browser remembers beyond the response that sent them
+const PERSISTENT_HEADERS: &[&str] = &[
+    "strict-transport-security",
+    "public-key-pins",
+    "public-key-pins-report-only",
+    "expect-ct",
+];
+
+/// Headers restricting what the page itself may do
+const POLICY_HEADERS: &[&str] = &[
+    "content-security-policy",
+    "content-security-policy-report-only",
+    "x-frame-options",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SecurityHeaders {
+    /// Replay as recorded
+    #[default]
+    Keep,
+    /// Remove HSTS, HPKP, Expect-CT, CSP and X-Frame-Options
+    Strip,
+    /// Remove the headers the browser persists and enforce the page policies
+    Harden,
+}
+
+impl SecurityHeaders {
+    /// Rewrite the headers of every resource, returning how many changed
+    pub fn apply(self, inventory: &mut Inventory) -> usize {
+        if self == SecurityHeaders::Keep {
+            return 0;
+        }
+        let mut changed = 0;
+        for resource in &mut inventory.resources {
+            let is_html = resource.mime_type() == Some("text/html");
+            let Some(headers) = &mut resource.raw_headers else {
+                continue;
+            };
+            let before = headers.clone();
+            match self {
+                SecurityHeaders::Keep => {}
+                SecurityHeaders::Strip => {
+                    for name in PERSISTENT_HEADERS.iter().chain(POLICY_HEADERS) {
+                        headers.remove(name);
+                    }
+                }
+                SecurityHeaders::Harden => harden(headers, is_html),
+            }
+            if *headers != before {
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
+
+fn harden(headers: &mut HttpHeaders, is_html: bool) {
+    for name in PERSISTENT_HEADERS {
+        headers.remove(name);
+    }
+    // A policy that was only reported while recording is enforced
+    let report_only = (!headers.contains("content-security-policy"))
+        .then(|| headers.remove("content-security-policy-report-only"))
+        .flatten();
+    if let Some(policy) = report_only {
+        headers.set("Content-Security-Policy", policy);
+    }
+    if is_html {
+        if !headers.contains("x-frame-options") {
+            headers.set(
+                "X-Frame-Options",
+                HeaderValue::Single("SAMEORIGIN".to_string()),
+            );
+        }
+        if !headers.contains("x-content-type-options") {
+            headers.set(
+                "X-Content-Type-Options",
+                HeaderValue::Single("nosniff".to_string()),
+            );
+        }
+    }
+}
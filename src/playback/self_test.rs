@@ -0,0 +1,131 @@
+//! Smoke test of a playback proxy before clients use it
+//!
+//! With `--self-test`, the proxy requests the inventory's entry URL through itself once
+//! it listens, the way a browser would (CONNECT, then TLS with the proxy's certificate).
+//! Playback stops with an error unless the request was answered from a recording with a
+//! 2xx status, its headers and last byte each within `--self-test-tolerance` of the
+//! recorded timing. The request carries `X-HPP-Self-Test`, so it is not journaled and
+//! does not count as the first of its URL's repeats.
+
+use anyhow::{Context, Result};
+use std::time::{Duration, Instant};
+
+use super::served_log::{ServedEntry, ServedLog};
+use crate::utils::parse_duration_ms;
+
+/// Marks the proxy's own request, answered like any other but logged apart
+pub const SELF_TEST_HEADER: &str = "x-hpp-self-test";
+
+pub const DEFAULT_SELF_TEST_TOLERANCE_MS: u64 = 200;
+
+/// How long the served log may lag behind the client finishing the body
+const LOG_WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestOptions {
+    #[arg(
+        long,
+        help = "Once listening, request the entry URL through the proxy and stop with an error unless a recording answers it with a 2xx on time"
+    )]
+    pub self_test: bool,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "How far the self-test response's TTFB and total time may be from the recorded ones (default: 200ms)"
+    )]
+    pub self_test_tolerance: Option<u64>,
+}
+
+impl SelfTestOptions {
+    pub fn tolerance_ms(&self) -> u64 {
+        self.self_test_tolerance
+            .unwrap_or(DEFAULT_SELF_TEST_TOLERANCE_MS)
+    }
+}
+
+/// Whether the proxy answered the self-test request from a recording, with a 2xx on time
+///
+/// `entry` is the served log's entry for the request, None when no recording answered.
+pub fn check(url: &str, status: u16, entry: Option<&ServedEntry>, tolerance_ms: u64) -> Result<()> {
+    let Some(entry) = entry else {
+        anyhow::bail!(
+            "Self-test: {} was not answered from a recording (status {})",
+            url,
+            status
+        );
+    };
+    if !(200..300).contains(&status) {
+        anyhow::bail!(
+            "Self-test: {} answered with status {}, expected 2xx",
+            url,
+            status
+        );
+    }
+    for (what, actual, target) in [
+        ("TTFB", entry.ttfb_ms, entry.target_ttfb_ms),
+        ("total time", entry.total_ms, entry.target_total_ms),
+    ] {
+        if actual.abs_diff(target) > tolerance_ms {
+            anyhow::bail!(
+                "Self-test: {} of {} was {}ms, recorded {}ms (tolerance {}ms)",
+                what,
+                url,
+                actual,
+                target,
+                tolerance_ms
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Request `url` through the proxy on `port` and check the response logged into `log`
+pub async fn run(port: u16, url: &str, log: &ServedLog, tolerance_ms: u64) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(format!("http://127.0.0.1:{}", port))?)
+        // The proxy's certificate is generated per run
+        .danger_accept_invalid_certs(true)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let response = client
+        .get(url)
+        .header(SELF_TEST_HEADER, "1")
+        .send()
+        .await
+        .with_context(|| format!("Self-test: request for {} failed", url))?;
+    let status = response.status().as_u16();
+    let body = response
+        .bytes()
+        .await
+        .with_context(|| format!("Self-test: body of {} failed", url))?;
+
+    // The entry is logged once the proxy drops the finished body stream
+    let deadline = Instant::now() + LOG_WAIT;
+    let entry = loop {
+        if let Some(entry) = log.entries().pop() {
+            break Some(entry);
+        }
+        if Instant::now() >= deadline {
+            break None;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    check(url, status, entry.as_ref(), tolerance_ms)?;
+
+    if let Some(entry) = entry {
+        println!(
+            "Self-test passed: {} answered {} with {} bytes, TTFB {}ms (recorded {}ms), total {}ms (recorded {}ms)",
+            url,
+            status,
+            body.len(),
+            entry.ttfb_ms,
+            entry.target_ttfb_ms,
+            entry.total_ms,
+            entry.target_total_ms
+        );
+    }
+    Ok(())
+}
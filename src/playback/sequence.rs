@@ -0,0 +1,74 @@
+//! Repeated requests answered in recorded order
+//!
+//! A URL requested several times while recording (a polling endpoint, a retried API
+//! call) is stored once per response. Playback answers the first request with the
+//! first recording, the second with the second, and so on; after the last one it
+//! keeps serving the last or starts over, as `--repeat-mode` asks.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::types::Transaction;
+
+/// What repeated requests get once every recording of their URL has been served
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepeatMode {
+    /// Keep serving the last recording
+    #[default]
+    Last,
+    /// Start over with the first recording
+    Cycle,
+}
+
+/// Requests served so far per recorded method and URL
+#[derive(Debug, Default)]
+pub struct Sequences {
+    mode: RepeatMode,
+    // Keyed by transaction list and first recording, so sessions count separately
+    served: Mutex<HashMap<(usize, usize), usize>>,
+}
+
+impl Sequences {
+    pub fn new(mode: RepeatMode) -> Self {
+        Self {
+            mode,
+            served: Mutex::default(),
+        }
+    }
+
+    /// The recording of `matched`'s method and URL that is next in line
+    pub fn next(
+        &self,
+        transactions: &Arc<Vec<Arc<Transaction>>>,
+        matched: Arc<Transaction>,
+    ) -> Arc<Transaction> {
+        let recordings: Vec<&Arc<Transaction>> = transactions
+            .iter()
+            .filter(|t| {
+                t.url_pattern.is_none() && t.method == matched.method && t.url == matched.url
+            })
+            .collect();
+        if recordings.len() < 2 {
+            return matched;
+        }
+
+        let key = (
+            Arc::as_ptr(transactions) as usize,
+            recordings[0].resource_index,
+        );
+        let mut served = self.served.lock().unwrap();
+        let count = served.entry(key).or_insert(0);
+        let index = match self.mode {
+            RepeatMode::Last => (*count).min(recordings.len() - 1),
+            RepeatMode::Cycle => *count % recordings.len(),
+        };
+        *count += 1;
+        recordings[index].clone()
+    }
+
+    /// Start every URL over at its first recording
+    pub fn reset(&self) {
+        self.served.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,230 @@
+//! Log of responses as the playback proxy actually served them
+//!
+//! Used by `experiment` to compare runs and written as `playback-journal.jsonl` with
+//! `playback --journal`, so test frameworks can assert on what a browser fetched.
+//! Without a journal only the totals for the run manifest are kept.
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// How late a response's headers or last byte may be and still count as on time
+pub const DEFAULT_TIMING_TOLERANCE_MS: u64 = 200;
+
+/// Timing of one served response, relative to when the log was created
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServedEntry {
+    pub method: String,
+    /// Recorded URL of the response that was served
+    pub url: String,
+    /// URL as the client requested it
+    pub request_url: String,
+    pub status: u16,
+    pub bytes: usize,
+    pub start_ms: u64,
+    pub ttfb_ms: u64,
+    pub total_ms: u64,
+    /// When the recording says the headers and the last byte were due
+    pub target_ttfb_ms: u64,
+    pub target_total_ms: u64,
+}
+
+/// Totals over every served response
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServedSummary {
+    pub requests: usize,
+    pub bytes: usize,
+    /// From the first request arriving to the last response finishing
+    pub span_ms: u64,
+    /// Most any response's headers were sent after the recording says they were due
+    pub max_ttfb_lag_ms: u64,
+    /// Responses whose headers and last byte were within the timing tolerance of due
+    pub on_time: usize,
+    /// Requests no recording answered
+    pub misses: usize,
+}
+
+impl ServedSummary {
+    /// Percent of served responses that were on time, 100 when none were served
+    pub fn on_time_percent(&self) -> f64 {
+        match self.requests {
+            0 => 100.0,
+            requests => self.on_time as f64 * 100.0 / requests as f64,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    summary: ServedSummary,
+    first_start_ms: Option<u64>,
+    last_end_ms: u64,
+}
+
+/// Shared, append-only list of served responses
+#[derive(Debug, Clone)]
+pub struct ServedLog {
+    started: Instant,
+    entries: Arc<Mutex<Vec<ServedEntry>>>,
+    // False when only the totals are wanted
+    keep_entries: bool,
+    timing_tolerance_ms: u64,
+    totals: Arc<Mutex<Totals>>,
+}
+
+/// Records its entry into the log when dropped, i.e. once the body is done or abandoned
+#[derive(Debug)]
+pub struct ServedRecorder {
+    log: ServedLog,
+    entry: ServedEntry,
+    request_received: Instant,
+}
+
+impl ServedLog {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            entries: Arc::new(Mutex::new(Vec::new())),
+            keep_entries: true,
+            timing_tolerance_ms: DEFAULT_TIMING_TOLERANCE_MS,
+            totals: Arc::default(),
+        }
+    }
+
+    /// A log that counts served responses without keeping them
+    pub fn totals_only() -> Self {
+        Self {
+            keep_entries: false,
+            ..Self::new()
+        }
+    }
+
+    /// Count responses late by up to `tolerance_ms` as on time
+    pub fn with_timing_tolerance(mut self, tolerance_ms: u64) -> Self {
+        self.timing_tolerance_ms = tolerance_ms;
+        self
+    }
+
+    /// Count a request that no recording answered
+    pub fn miss(&self) {
+        if let Ok(mut totals) = self.totals.lock() {
+            totals.summary.misses += 1;
+        }
+    }
+
+    /// Start timing a response whose headers are being sent now
+    pub fn begin(
+        &self,
+        method: &str,
+        url: &str,
+        status: u16,
+        bytes: usize,
+        request_received: Instant,
+    ) -> ServedRecorder {
+        let start_ms = request_received
+            .saturating_duration_since(self.started)
+            .as_millis() as u64;
+        ServedRecorder {
+            log: self.clone(),
+            entry: ServedEntry {
+                method: method.to_string(),
+                url: url.to_string(),
+                request_url: url.to_string(),
+                status,
+                bytes,
+                start_ms,
+                ttfb_ms: request_received.elapsed().as_millis() as u64,
+                total_ms: 0,
+                target_ttfb_ms: 0,
+                target_total_ms: 0,
+            },
+            request_received,
+        }
+    }
+
+    pub fn entries(&self) -> Vec<ServedEntry> {
+        self.entries
+            .lock()
+            .map(|entries| entries.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn summary(&self) -> ServedSummary {
+        self.totals
+            .lock()
+            .map(|totals| totals.summary.clone())
+            .unwrap_or_default()
+    }
+
+    /// One JSON object per line, in the order responses completed
+    pub fn to_jsonl(&self) -> serde_json::Result<String> {
+        let mut jsonl = String::new();
+        for entry in self.entries() {
+            jsonl.push_str(&serde_json::to_string(&entry)?);
+            jsonl.push('\n');
+        }
+        Ok(jsonl)
+    }
+}
+
+impl Default for ServedLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Totals {
+    fn add(&mut self, entry: &ServedEntry, tolerance_ms: u64) {
+        let first_start_ms = self
+            .first_start_ms
+            .map_or(entry.start_ms, |ms| ms.min(entry.start_ms));
+        self.first_start_ms = Some(first_start_ms);
+        self.last_end_ms = self.last_end_ms.max(entry.start_ms + entry.total_ms);
+
+        let summary = &mut self.summary;
+        summary.requests += 1;
+        summary.bytes += entry.bytes;
+        summary.span_ms = self.last_end_ms.saturating_sub(first_start_ms);
+        summary.max_ttfb_lag_ms = summary
+            .max_ttfb_lag_ms
+            .max(entry.ttfb_ms.saturating_sub(entry.target_ttfb_ms));
+        let late = |actual: u64, due: u64| actual.saturating_sub(due) > tolerance_ms;
+        if !late(entry.ttfb_ms, entry.target_ttfb_ms)
+            && !late(entry.total_ms, entry.target_total_ms)
+        {
+            summary.on_time += 1;
+        }
+    }
+}
+
+impl ServedRecorder {
+    /// Note the URL the client asked for and when the recording says the response is due
+    pub fn expecting(
+        mut self,
+        request_url: &str,
+        target_ttfb_ms: u64,
+        target_total_ms: u64,
+    ) -> Self {
+        self.entry.request_url = request_url.to_string();
+        self.entry.target_ttfb_ms = target_ttfb_ms;
+        self.entry.target_total_ms = target_total_ms;
+        self
+    }
+}
+
+impl Drop for ServedRecorder {
+    fn drop(&mut self) {
+        self.entry.total_ms = self.request_received.elapsed().as_millis() as u64;
+        if let Ok(mut totals) = self.log.totals.lock() {
+            totals.add(&self.entry, self.log.timing_tolerance_ms);
+        }
+        if !self.log.keep_entries {
+            return;
+        }
+        if let Ok(mut entries) = self.log.entries.lock() {
+            entries.push(self.entry.clone());
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Several inventories served by one playback proxy
+//!
+//! A farm of browser workers can share one proxy: each worker names the inventory it
+//! replays with an `X-HPP-Session` request header, or is assigned one by its client IP.
+//! Requests that select no session are answered from the main inventory.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::transaction::TransactionLoader;
+use crate::traits::FileSystem;
+use crate::types::Transaction;
+
+pub const SESSION_HEADER: &str = "x-hpp-session";
+
+/// Transactions of one inventory and the loader for their bodies
+pub struct Session<F: FileSystem + 'static> {
+    pub transactions: Arc<Vec<Arc<Transaction>>>,
+    pub loader: Arc<TransactionLoader<F>>,
+}
+
+impl<F: FileSystem + 'static> Session<F> {
+    pub fn new(transactions: Vec<Transaction>, loader: TransactionLoader<F>) -> Self {
+        Self {
+            transactions: Arc::new(transactions.into_iter().map(Arc::new).collect()),
+            loader: Arc::new(loader),
+        }
+    }
+}
+
+pub struct Sessions<F: FileSystem + 'static> {
+    named: HashMap<String, Session<F>>,
+    clients: HashMap<IpAddr, String>,
+}
+
+impl<F: FileSystem + 'static> Default for Sessions<F> {
+    fn default() -> Self {
+        Self {
+            named: HashMap::new(),
+            clients: HashMap::new(),
+        }
+    }
+}
+
+impl<F: FileSystem + 'static> Sessions<F> {
+    pub fn insert(
+        &mut self,
+        name: String,
+        transactions: Vec<Transaction>,
+        loader: TransactionLoader<F>,
+    ) {
+        self.named.insert(name, Session::new(transactions, loader));
+    }
+
+    /// Serve requests from `client` without a session header from session `name`
+    pub fn assign_client(&mut self, client: IpAddr, name: String) -> Result<()> {
+        if !self.named.contains_key(&name) {
+            anyhow::bail!(
+                "--session-client {} refers to unknown session {:?}",
+                client,
+                name
+            );
+        }
+        self.clients.insert(client, name);
+        Ok(())
+    }
+
+    /// The session a request selects, None for the main inventory
+    ///
+    /// The header wins over the client mapping. Err carries a session name given in the
+    /// header that is not loaded.
+    pub fn select(
+        &self,
+        headers: &hyper::HeaderMap,
+        client: IpAddr,
+    ) -> Result<Option<&Session<F>>, String> {
+        if let Some(name) = headers
+            .get(SESSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+        {
+            return self
+                .named
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| name.to_string());
+        }
+        Ok(self
+            .clients
+            .get(&client)
+            .and_then(|name| self.named.get(name)))
+    }
+}
+
+/// Parse a `--session NAME=DIR` value
+pub fn parse_session_spec(spec: &str) -> Result<(String, PathBuf)> {
+    match spec.split_once('=') {
+        Some((name, dir)) if !name.is_empty() && !dir.is_empty() => {
+            Ok((name.to_string(), PathBuf::from(dir)))
+        }
+        _ => anyhow::bail!("Invalid --session {:?}: expected NAME=DIR", spec),
+    }
+}
+
+/// Parse a `--session-client IP=NAME` value
+pub fn parse_client_spec(spec: &str) -> Result<(IpAddr, String)> {
+    let (ip, name) = spec
+        .split_once('=')
+        .filter(|(_, name)| !name.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Invalid --session-client {:?}: expected IP=NAME", spec))?;
+    let ip = ip
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid --session-client {:?}: {}", spec, e))?;
+    Ok((ip, name.to_string()))
+}
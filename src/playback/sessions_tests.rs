@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use crate::playback::sessions::{Sessions, parse_client_spec, parse_session_spec};
+    use crate::playback::transaction::TransactionLoader;
+    use crate::traits::mocks::MockFileSystem;
+    use crate::types::Transaction;
+    use std::net::IpAddr;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn transaction(url: &str) -> Transaction {
+        Transaction {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            ttfb: 0,
+            status_code: Some(200),
+            reason_phrase: None,
+            error_kind: None,
+            error_message: None,
+            raw_headers: None,
+            chunks: Vec::new(),
+            target_close_time: 0,
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability: None,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: false,
+            request_headers: None,
+        }
+    }
+
+    fn sessions() -> Sessions<MockFileSystem> {
+        let mut sessions = Sessions::default();
+        for name in ["run-41", "run-42"] {
+            let loader = TransactionLoader::new(
+                Vec::new(),
+                PathBuf::from(name),
+                Arc::new(MockFileSystem::new()),
+            );
+            let url = format!("https://example.com/{}", name);
+            sessions.insert(name.to_string(), vec![transaction(&url)], loader);
+        }
+        sessions
+    }
+
+    fn selected_url(
+        sessions: &Sessions<MockFileSystem>,
+        header: Option<&str>,
+        client: &str,
+    ) -> Result<Option<String>, String> {
+        let mut headers = hyper::HeaderMap::new();
+        if let Some(name) = header {
+            headers.insert("x-hpp-session", name.parse().unwrap());
+        }
+        let client: IpAddr = client.parse().unwrap();
+        sessions
+            .select(&headers, client)
+            .map(|session| session.map(|s| s.transactions[0].url.clone()))
+    }
+
+    #[test]
+    fn test_select_by_header_then_client() {
+        let mut sessions = sessions();
+        sessions
+            .assign_client("10.0.0.2".parse().unwrap(), "run-41".to_string())
+            .unwrap();
+        assert!(
+            sessions
+                .assign_client("10.0.0.3".parse().unwrap(), "missing".to_string())
+                .is_err()
+        );
+
+        assert_eq!(
+            selected_url(&sessions, Some("run-42"), "10.0.0.1"),
+            Ok(Some("https://example.com/run-42".to_string()))
+        );
+        // The header wins over the client's assignment
+        assert_eq!(
+            selected_url(&sessions, Some("run-42"), "10.0.0.2"),
+            Ok(Some("https://example.com/run-42".to_string()))
+        );
+        assert_eq!(
+            selected_url(&sessions, None, "10.0.0.2"),
+            Ok(Some("https://example.com/run-41".to_string()))
+        );
+        // Unassigned clients get the main inventory
+        assert_eq!(selected_url(&sessions, None, "10.0.0.1"), Ok(None));
+        assert_eq!(
+            selected_url(&sessions, Some("run-7"), "10.0.0.1"),
+            Err("run-7".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_specs() {
+        assert_eq!(
+            parse_session_spec("run-42=./inventories/42").unwrap(),
+            ("run-42".to_string(), PathBuf::from("./inventories/42"))
+        );
+        assert!(parse_session_spec("./inventories/42").is_err());
+        assert!(parse_session_spec("=dir").is_err());
+
+        assert_eq!(
+            parse_client_spec("::1=run-42").unwrap(),
+            ("::1".parse::<IpAddr>().unwrap(), "run-42".to_string())
+        );
+        assert!(parse_client_spec("worker-1=run-42").is_err());
+        assert!(parse_client_spec("10.0.0.2=").is_err());
+    }
+}
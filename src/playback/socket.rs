@@ -0,0 +1,70 @@
+//! Socket and HTTP/1 write settings for playback connections
+//!
+//! Kernel send buffers of several hundred KB absorb paced chunks and release them to the
+//! client as one burst. A small send buffer and TCP_NODELAY keep what reaches the wire
+//! close to the pacing schedule.
+
+use anyhow::Result;
+use hyper_util::rt::TokioExecutor;
+use hyper_util::server::conn::auto::Builder as ServerBuilder;
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::net::{TcpListener, TcpSocket};
+
+#[derive(Debug, Clone, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SocketOptions {
+    #[arg(
+        long,
+        help = "Disable Nagle's algorithm so small paced chunks are sent immediately"
+    )]
+    pub tcp_nodelay: bool,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Kernel send buffer size for client connections (default: OS-managed)"
+    )]
+    pub send_buffer_size: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Copy response headers and body chunks into one buffer before each write instead of vectored writes"
+    )]
+    pub coalesce_writes: bool,
+}
+
+impl SocketOptions {
+    /// Bind the listening socket on 127.0.0.1
+    ///
+    /// Accepted connections inherit TCP_NODELAY and the send buffer size from the
+    /// listener on Linux and macOS; the proxy library does the accepting, so this is the
+    /// only place they can be set.
+    pub fn bind_listener(&self, port: u16) -> Result<TcpListener> {
+        let socket = TcpSocket::new_v4()?;
+        #[cfg(unix)]
+        socket.set_reuseaddr(true)?;
+        if self.tcp_nodelay {
+            socket.set_nodelay(true)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        socket.bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))?;
+        Ok(socket.listen(1024)?)
+    }
+
+    /// HTTP server settings, or None to keep the proxy library's defaults
+    pub fn server_builder(&self) -> Option<ServerBuilder<TokioExecutor>> {
+        if !self.coalesce_writes {
+            return None;
+        }
+        // Same header handling as the library default, plus flattened writes
+        let mut builder = ServerBuilder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .title_case_headers(true)
+            .preserve_header_case(true)
+            .writev(false);
+        Some(builder)
+    }
+}
@@ -271,14 +271,12 @@ mod playback_tests {
                     i
                 );
 
-                // Based on 64KB chunks at 1 Mbps:
-                // 64KB = 65536 bytes = 524288 bits
-                // 1 Mbps = 1000000 bits/sec
-                // Expected time: ~524ms
+                // At 1 Mbps chunks are sized to the default 50ms pacing resolution
+                // (~6KB each) instead of one 64KB chunk every ~524ms
                 // Allow for some variance
                 assert!(
-                    (400..=700).contains(&relative_delay),
-                    "Chunk {} delay {}ms is outside expected range (400-700ms) for 64KB at 1Mbps",
+                    (40..=60).contains(&relative_delay),
+                    "Chunk {} delay {}ms is outside expected range (40-60ms) at 1Mbps",
                     i,
                     relative_delay
                 );
@@ -447,6 +445,58 @@ mod playback_tests {
         assert_eq!(tx.status_code, Some(504));
     }
 
+    #[tokio::test]
+    async fn test_convert_resource_with_reason_phrase() {
+        use crate::playback::transaction::convert_resource_to_transaction;
+        use crate::traits::RealFileSystem;
+
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        let json = r#"{"method":"GET","url":"https://example.com/limited","ttfbMs":10,"statusCode":429,"reasonPhrase":"Slow Down","contentUtf8":"retry later"}"#;
+        let resource: Resource = serde_json::from_str(json).unwrap();
+        assert_eq!(resource.reason_phrase.as_deref(), Some("Slow Down"));
+
+        let tx =
+            convert_resource_to_transaction(&resource, &inventory_dir, Arc::new(RealFileSystem))
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(tx.status_code, Some(429));
+        assert_eq!(tx.reason_phrase.as_deref(), Some("Slow Down"));
+    }
+
+    #[tokio::test]
+    async fn test_convert_resource_merges_early_hint_links() {
+        use crate::playback::transaction::convert_resource_to_transaction;
+        use crate::traits::RealFileSystem;
+        use crate::types::HeaderValue;
+
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        let json = r#"{"method":"GET","url":"https://example.com/","ttfbMs":300,"statusCode":200,
+            "earlyHints":[{"offsetMs":20,"headers":{"link":["</app.css>; rel=preload; as=style","</app.js>; rel=preload; as=script"]}}],
+            "rawHeaders":{"content-type":"text/html","link":"</app.css>; rel=preload; as=style"},
+            "contentUtf8":"<html></html>"}"#;
+        let resource: Resource = serde_json::from_str(json).unwrap();
+        assert_eq!(resource.early_hints.as_ref().unwrap()[0].offset_ms, 20);
+
+        let tx =
+            convert_resource_to_transaction(&resource, &inventory_dir, Arc::new(RealFileSystem))
+                .await
+                .unwrap()
+                .unwrap();
+        let headers = tx.raw_headers.unwrap();
+        assert_eq!(
+            headers.get("link"),
+            Some(&HeaderValue::Multiple(vec![
+                "</app.css>; rel=preload; as=style".to_string(),
+                "</app.js>; rel=preload; as=script".to_string(),
+            ]))
+        );
+    }
+
     #[test]
     fn test_minify_javascript_content() {
         use crate::playback::transaction::minify_content;
@@ -505,4 +555,2371 @@ mod playback_tests {
         assert!(ContentEncodingType::from_str("unknown").is_err());
         assert!(ContentEncodingType::from_str("").is_err());
     }
+
+    #[tokio::test]
+    async fn test_convert_large_binary_resource_memory_mapped() {
+        use crate::playback::transaction::convert_resource_to_transaction;
+        use crate::traits::{FileSystem, RealFileSystem};
+
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        // Above the mmap threshold so the file is served without being read into a Vec
+        let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let file_path = "contents/get/https/example.com/video.mp4";
+        let full_path = inventory_dir.join(file_path);
+        tokio::fs::create_dir_all(full_path.parent().unwrap())
+            .await
+            .unwrap();
+        tokio::fs::write(&full_path, &content).await.unwrap();
+
+        let bytes = RealFileSystem.read_bytes(&full_path).await.unwrap();
+        assert_eq!(bytes.len(), content.len());
+
+        let mut resource = Resource::new(
+            "GET".to_string(),
+            "https://example.com/video.mp4".to_string(),
+        );
+        resource.status_code = Some(200);
+        resource.content_file_path = Some(file_path.to_string());
+        resource.duration_ms = Some(100);
+
+        let transaction =
+            convert_resource_to_transaction(&resource, &inventory_dir, Arc::new(RealFileSystem))
+                .await
+                .unwrap()
+                .unwrap();
+
+        let mut combined = Vec::with_capacity(content.len());
+        for chunk in &transaction.chunks {
+            combined.extend_from_slice(&chunk.chunk);
+        }
+        assert_eq!(combined, content);
+    }
+
+    #[test]
+    fn test_transaction_matches_method_host_path_query() {
+        use crate::playback::hudsucker_handler::transaction_matches;
+        use crate::query::QueryOptions;
+        use crate::types::Transaction;
+
+        let transaction = Transaction {
+            method: "GET".to_string(),
+            url: "https://example.com/api/data?id=1".to_string(),
+            ttfb: 0,
+            status_code: Some(200),
+            reason_phrase: None,
+            error_kind: None,
+            error_message: None,
+            raw_headers: None,
+            chunks: vec![],
+            target_close_time: 0,
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability: None,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: false,
+            request_headers: None,
+        };
+
+        let host = Some("example.com");
+        let query = QueryOptions::default();
+        assert!(transaction_matches(
+            &transaction,
+            "GET",
+            host,
+            "/api/data",
+            Some("id=1"),
+            &query
+        ));
+        assert!(!transaction_matches(
+            &transaction,
+            "HEAD",
+            host,
+            "/api/data",
+            Some("id=1"),
+            &query
+        ));
+        assert!(!transaction_matches(
+            &transaction,
+            "GET",
+            Some("other.com"),
+            "/api/data",
+            Some("id=1"),
+            &query
+        ));
+        assert!(!transaction_matches(
+            &transaction,
+            "GET",
+            host,
+            "/api/data",
+            None,
+            &query
+        ));
+        // Missing request host falls back to path-only matching
+        assert!(transaction_matches(
+            &transaction,
+            "GET",
+            None,
+            "/api/data",
+            Some("id=1"),
+            &query
+        ));
+
+        // Parameter order only matters without --sort-query
+        let transaction = Transaction {
+            url: "https://example.com/api/data?id=1&lang=en".to_string(),
+            ..transaction
+        };
+        assert!(!transaction_matches(
+            &transaction,
+            "GET",
+            host,
+            "/api/data",
+            Some("lang=en&id=1"),
+            &query
+        ));
+        let sorted = QueryOptions {
+            sort_query: true,
+            lowercase_query_keys: false,
+        };
+        assert!(transaction_matches(
+            &transaction,
+            "GET",
+            host,
+            "/api/data",
+            Some("lang=en&id=1"),
+            &sorted
+        ));
+    }
+
+    #[test]
+    fn test_find_transaction_prefers_same_form_fields() {
+        use crate::playback::matching::{MatchRequest, find_matching};
+        use crate::query::QueryOptions;
+        use crate::types::{MatchStrategy, Transaction};
+        use std::sync::Arc;
+
+        let submission = |status: u16, title: &str| {
+            Arc::new(Transaction {
+                method: "POST".to_string(),
+                url: "https://example.com/upload".to_string(),
+                ttfb: 0,
+                status_code: Some(status),
+                reason_phrase: None,
+                error_kind: None,
+                error_message: None,
+                raw_headers: None,
+                chunks: vec![],
+                target_close_time: 0,
+                resource_index: 0,
+                lazy: false,
+                streamed: None,
+                form_fields: Some(vec![("title".to_string(), title.to_string())]),
+                upload: None,
+                availability: None,
+                url_pattern: None,
+                request_client: None,
+                event_stream: None,
+                request_body_sha1: None,
+                match_headers: None,
+                byte_ranges: None,
+                open_ended: false,
+                timed: false,
+                request_headers: None,
+            })
+        };
+        let transactions = vec![submission(201, "first"), submission(409, "second")];
+        let query = QueryOptions::default();
+        let request = MatchRequest {
+            method: "POST",
+            host: Some("example.com"),
+            path: "/upload",
+            query: None,
+            headers: None,
+            body_sha1: None,
+        };
+        let find = |form: Option<&[(String, String)]>| {
+            find_matching(
+                &transactions,
+                &request,
+                &[MatchStrategy::Exact],
+                &query,
+                form,
+            )
+            .and_then(|(t, _)| t.status_code)
+        };
+
+        let second = vec![("title".to_string(), "second".to_string())];
+        let unknown = vec![("title".to_string(), "third".to_string())];
+        assert_eq!(find(None), Some(201));
+        assert_eq!(find(Some(&second)), Some(409));
+        // No recording with these fields: the first match still answers
+        assert_eq!(find(Some(&unknown)), Some(201));
+    }
+
+    #[tokio::test]
+    async fn test_url_pattern_resources_answer_unrecorded_urls() {
+        use crate::playback::hudsucker_handler::find_pattern_transaction;
+        use crate::playback::matching::{MatchRequest, find_matching};
+        use crate::playback::transaction::{convert_resources_to_transactions, render_template};
+        use crate::query::QueryOptions;
+        use crate::traits::RealFileSystem;
+        use crate::types::{MatchStrategy, UrlMatch};
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut inventory = Inventory::new();
+        let mut pattern = Resource::new(
+            "GET".to_string(),
+            "https://api.example.com/api/users/*".to_string(),
+        );
+        pattern.url_match = Some(UrlMatch::Glob);
+        pattern.status_code = Some(200);
+        pattern.content_utf8 = Some(r#"{"id": "{{1}}"}"#.to_string());
+        inventory.resources.push(pattern);
+        let mut recorded = Resource::new(
+            "GET".to_string(),
+            "https://api.example.com/api/users/me".to_string(),
+        );
+        recorded.status_code = Some(200);
+        recorded.content_utf8 = Some(r#"{"id": "recorded"}"#.to_string());
+        inventory.resources.push(recorded);
+
+        let transactions: Vec<_> = convert_resources_to_transactions(
+            &inventory,
+            temp_dir.path(),
+            Arc::new(RealFileSystem),
+        )
+        .await
+        .unwrap()
+        .into_iter()
+        .map(Arc::new)
+        .collect();
+        let find = |path: &str| {
+            let request = MatchRequest {
+                method: "GET",
+                host: Some("api.example.com"),
+                path,
+                query: None,
+                headers: None,
+                body_sha1: None,
+            };
+            find_matching(
+                &transactions,
+                &request,
+                &[MatchStrategy::Exact],
+                &QueryOptions::default(),
+                None,
+            )
+            .map(|(t, _)| t)
+            .or_else(|| {
+                let url = format!("https://api.example.com{}", path);
+                find_pattern_transaction(&transactions, "GET", &url)
+            })
+        };
+        let body = |transaction: &crate::types::Transaction| -> String {
+            transaction
+                .chunks
+                .iter()
+                .map(|c| String::from_utf8_lossy(&c.chunk).into_owned())
+                .collect()
+        };
+
+        // The recorded URL wins over the pattern that also matches it
+        let me = find("/api/users/me").unwrap();
+        assert_eq!(me.url, "https://api.example.com/api/users/me");
+
+        let other = find("/api/users/42").unwrap();
+        assert_eq!(other.url, "https://api.example.com/api/users/*");
+        let rendered =
+            render_template(&other, "GET", "https://api.example.com/api/users/42").unwrap();
+        assert_eq!(body(&rendered), r#"{"id": "42"}"#);
+
+        assert!(find("/api/users/42/posts").is_none());
+    }
+
+    #[test]
+    fn test_synthesize_options_response_echoes_preflight() {
+        use crate::playback::hudsucker_handler::synthesize_options_response;
+
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("origin", "https://app.example.com".parse().unwrap());
+        headers.insert("access-control-request-method", "PUT".parse().unwrap());
+        headers.insert(
+            "access-control-request-headers",
+            "content-type, x-token".parse().unwrap(),
+        );
+
+        let response = synthesize_options_response(&headers, &["GET", "GET"]);
+        assert_eq!(response.status(), 204);
+        let h = response.headers();
+        assert_eq!(h["access-control-allow-origin"], "https://app.example.com");
+        assert_eq!(h["access-control-allow-credentials"], "true");
+        assert_eq!(h["access-control-allow-methods"], "GET, OPTIONS, PUT");
+        assert_eq!(h["access-control-allow-headers"], "content-type, x-token");
+        assert_eq!(h["allow"], "GET, OPTIONS, PUT");
+
+        // Without an Origin header the wildcard is used
+        let response = synthesize_options_response(&hyper::HeaderMap::new(), &["POST"]);
+        assert_eq!(response.headers()["access-control-allow-origin"], "*");
+        assert!(
+            response
+                .headers()
+                .get("access-control-allow-credentials")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_apply_start_offset() {
+        use crate::playback::apply_start_offset;
+
+        let mut inventory = Inventory::new();
+        for (url, start_ms) in [
+            ("https://example.com/early", Some(1_000)),
+            ("https://example.com/late", Some(40_000)),
+            ("https://example.com/unknown", None),
+        ] {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            resource.request_start_ms = start_ms;
+            resource.ttfb_ms = 250;
+            resource.duration_ms = Some(500);
+            inventory.resources.push(resource);
+        }
+
+        let skipped = apply_start_offset(&mut inventory, 30_000);
+        assert_eq!(skipped, 1);
+
+        let early = &inventory.resources[0];
+        assert_eq!(early.ttfb_ms, 0);
+        assert_eq!(early.duration_ms, Some(0));
+
+        for resource in &inventory.resources[1..] {
+            assert_eq!(resource.ttfb_ms, 250);
+            assert_eq!(resource.duration_ms, Some(500));
+        }
+    }
+
+    #[test]
+    fn test_timing_limits_clamp_outliers() {
+        use crate::playback::timing_limits::TimingLimits;
+
+        let mut inventory = Inventory::new();
+        for (url, ttfb_ms, duration_ms) in [
+            ("https://example.com/cached", 0, Some(0)),
+            ("https://example.com/normal", 120, Some(400)),
+            ("https://example.com/stall", 30_000, Some(90_000)),
+            ("https://example.com/no-duration", 50, None),
+        ] {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            resource.ttfb_ms = ttfb_ms;
+            resource.duration_ms = duration_ms;
+            inventory.resources.push(resource);
+        }
+
+        let limits = TimingLimits {
+            min_ttfb: Some(20),
+            max_ttfb: Some(2_000),
+            min_transfer: None,
+            max_transfer: Some(5_000),
+        };
+        assert_eq!(limits.apply(&mut inventory).unwrap(), 2);
+
+        let timings: Vec<(u64, Option<u64>)> = inventory
+            .resources
+            .iter()
+            .map(|r| (r.ttfb_ms, r.duration_ms))
+            .collect();
+        assert_eq!(
+            timings,
+            vec![
+                (20, Some(0)),
+                (120, Some(400)),
+                (2_000, Some(5_000)),
+                (50, None)
+            ]
+        );
+
+        let inverted = TimingLimits {
+            min_ttfb: Some(500),
+            max_ttfb: Some(100),
+            ..Default::default()
+        };
+        assert!(inverted.apply(&mut inventory).is_err());
+    }
+
+    #[test]
+    fn test_time_scale() {
+        use crate::playback::time_scale::TimeScale;
+        use crate::types::StreamEvent;
+
+        let resource = || {
+            let mut resource =
+                Resource::new("GET".to_string(), "https://example.com/app.js".to_string());
+            resource.request_start_ms = Some(1_000);
+            resource.ttfb_ms = 100;
+            resource.duration_ms = Some(400);
+            resource.upload_ms = Some(60);
+            resource.event_stream = Some(vec![StreamEvent {
+                offset_ms: 500,
+                size: 10,
+            }]);
+            resource
+        };
+        let scaled = |time_scale: TimeScale| {
+            let mut inventory = Inventory::new();
+            inventory.resources.push(resource());
+            assert_eq!(time_scale.apply(&mut inventory), 1);
+            inventory.resources.remove(0)
+        };
+
+        let faster = scaled(TimeScale {
+            speed_factor: Some(2.0),
+            ..Default::default()
+        });
+        assert_eq!(faster.request_start_ms, Some(500));
+        assert_eq!(faster.ttfb_ms, 50);
+        assert_eq!(faster.duration_ms, Some(200));
+        assert_eq!(faster.upload_ms, Some(30));
+        assert_eq!(faster.event_stream.unwrap()[0].offset_ms, 250);
+
+        // Latency and bandwidth scale independently, and combine with the speed
+        let slower = scaled(TimeScale {
+            speed_factor: Some(2.0),
+            ttfb_scale: Some(3.0),
+            bandwidth_scale: Some(0.5),
+        });
+        assert_eq!(slower.ttfb_ms, 150);
+        assert_eq!(slower.duration_ms, Some(400));
+        assert_eq!(slower.request_start_ms, Some(500));
+    }
+
+    #[test]
+    fn test_network_profile() {
+        use crate::playback::network_profile::{NetworkLinks, NetworkProfile};
+        use std::net::{IpAddr, Ipv4Addr};
+
+        let mut inventory = Inventory::new();
+        let mut resource =
+            Resource::new("POST".to_string(), "https://example.com/upload".to_string());
+        resource.ttfb_ms = 20;
+        resource.duration_ms = Some(5);
+        resource.upload_bytes = Some(50_000);
+        resource.upload_ms = Some(10);
+        inventory.resources.push(resource);
+
+        assert_eq!(NetworkProfile::Slow3g.apply(&mut inventory), 1);
+        let resource = &inventory.resources[0];
+        assert_eq!(resource.ttfb_ms, 400);
+        assert_eq!(resource.duration_ms, None);
+        assert_eq!(resource.mbps, Some(0.4));
+        // 50KB at 400 Kbps
+        assert_eq!(resource.upload_ms, Some(1_000));
+
+        NetworkProfile::Unthrottled.apply(&mut inventory);
+        let resource = &inventory.resources[0];
+        assert_eq!(resource.ttfb_ms, 0);
+        assert_eq!(resource.duration_ms, Some(0));
+        assert_eq!(resource.upload_ms, None);
+
+        // Each client's responses share one link
+        assert!(NetworkLinks::new(NetworkProfile::Unthrottled).is_none());
+        let links = NetworkLinks::new(NetworkProfile::Cable).unwrap();
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        assert!(Arc::ptr_eq(&links.link(client), &links.link(client)));
+        assert!(!Arc::ptr_eq(&links.link(client), &links.link(other)));
+    }
+
+    #[test]
+    fn test_http2_offered_per_recorded_protocol() {
+        use crate::playback::protocols::{Http2Policy, ProtocolAuthority, http1_hosts};
+        use crate::types::HttpProtocol;
+
+        let mut inventory = Inventory::new();
+        for (url, protocol) in [
+            ("https://legacy.example.com/", Some(HttpProtocol::Http11)),
+            (
+                "https://legacy.example.com/app.js",
+                Some(HttpProtocol::Http10),
+            ),
+            ("https://mixed.example.com/", Some(HttpProtocol::Http11)),
+            ("https://mixed.example.com/api", Some(HttpProtocol::H2)),
+            ("https://cdn.example.com/", Some(HttpProtocol::H2)),
+            ("https://old.example.com/", None),
+        ] {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            resource.protocol = protocol;
+            inventory.resources.push(resource);
+        }
+
+        let hosts = http1_hosts(&inventory);
+        assert_eq!(hosts.len(), 1);
+        assert!(hosts.contains("legacy.example.com"));
+
+        let recorded = ProtocolAuthority::new((), Http2Policy::Recorded, hosts.clone());
+        assert!(!recorded.offers_h2("Legacy.Example.com"));
+        assert!(recorded.offers_h2("mixed.example.com"));
+        assert!(recorded.offers_h2("old.example.com"));
+        assert!(recorded.offers_h2("unrecorded.example.com"));
+
+        let always = ProtocolAuthority::new((), Http2Policy::Always, hosts.clone());
+        assert!(always.offers_h2("legacy.example.com"));
+        let never = ProtocolAuthority::new((), Http2Policy::Never, hosts);
+        assert!(!never.offers_h2("cdn.example.com"));
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        use crate::playback::rate_limits::RateLimits;
+        use std::net::{IpAddr, Ipv4Addr};
+        use std::time::{Duration, Instant};
+
+        assert!(RateLimits::new(&[]).unwrap().is_none());
+        for invalid in [
+            "https://example.com/**",
+            "https://example.com/**=0/1s",
+            "=5/1s",
+        ] {
+            assert!(
+                RateLimits::new(&[invalid.to_string()]).is_err(),
+                "{}",
+                invalid
+            );
+        }
+
+        let limits = RateLimits::new(&["https://api.example.com/**=2/10s".to_string()])
+            .unwrap()
+            .unwrap();
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let api = "https://api.example.com/v1/items?page=2";
+
+        assert_eq!(limits.check(client, api, at(0)), None);
+        assert_eq!(limits.check(client, api, at(1_000)), None);
+        // 8.5s left in the window
+        assert_eq!(limits.check(client, api, at(1_500)), Some(9));
+        // Other clients and URLs are not affected
+        assert_eq!(limits.check(other, api, at(1_500)), None);
+        assert_eq!(
+            limits.check(client, "https://www.example.com/", at(1_500)),
+            None
+        );
+        // A new window starts once the old one has passed
+        assert_eq!(limits.check(client, api, at(10_000)), None);
+    }
+
+    #[tokio::test]
+    async fn test_byte_range_selection() {
+        use crate::playback::byte_ranges::select;
+        use crate::playback::transaction::convert_resource_to_transaction;
+        use crate::traits::RealFileSystem;
+        use crate::types::{ByteRange, RangeMap, Transaction};
+
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        // Bytes 0-3 and 10-15 of a 20-byte object, stored back to back
+        let json = r#"{"method":"GET","url":"https://example.com/video.mp4","ttfbMs":10,"durationMs":100,"statusCode":206,"rawHeaders":{"content-type":"video/mp4"},"contentBase64":"YWJjZGVmZ2hpag==","byteRanges":[{"start":0,"end":3},{"start":10,"end":15}],"completeLength":20}"#;
+        let resource: Resource = serde_json::from_str(json).unwrap();
+        let tx =
+            convert_resource_to_transaction(&resource, &inventory_dir, Arc::new(RealFileSystem))
+                .await
+                .unwrap()
+                .unwrap();
+        assert!(tx.byte_ranges.is_some());
+
+        let answer = |tx: &Transaction, range: Option<&str>| {
+            let selected = select(tx, range);
+            let body: Vec<u8> = selected
+                .chunks
+                .iter()
+                .flat_map(|c| c.chunk.to_vec())
+                .collect();
+            let content_range = selected
+                .raw_headers
+                .as_ref()
+                .and_then(|h| h.get("content-range"))
+                .map(|v| v.first().to_string());
+            (
+                selected.status_code,
+                content_range,
+                String::from_utf8(body).unwrap(),
+            )
+        };
+        let partial = |range: &str, body: &str| {
+            (
+                Some(206),
+                Some(format!("bytes {}/20", range)),
+                body.to_string(),
+            )
+        };
+
+        // Without a Range header an incomplete object starts from its first byte
+        assert_eq!(answer(&tx, None), partial("0-3", "abcd"));
+        // Ranges end with the span they start in
+        assert_eq!(answer(&tx, Some("bytes=2-12")), partial("2-3", "cd"));
+        assert_eq!(answer(&tx, Some("bytes=12-")), partial("12-15", "ghij"));
+        assert_eq!(answer(&tx, Some("bytes=-6")), partial("14-15", "ij"));
+        assert_eq!(
+            answer(&tx, Some("bytes=11-12, 14-")),
+            partial("11-12", "fg")
+        );
+        assert_eq!(
+            answer(&tx, Some("bytes=5-8")),
+            (Some(416), Some("bytes */20".to_string()), String::new())
+        );
+        // A slice takes its share of the recorded transfer time
+        assert!(select(&tx, Some("bytes=0-1")).target_close_time < tx.target_close_time);
+
+        let mut complete = tx.clone();
+        complete.byte_ranges = Some(RangeMap {
+            spans: vec![ByteRange { start: 0, end: 9 }],
+            complete_length: Some(10),
+        });
+        assert_eq!(
+            answer(&complete, None),
+            (Some(200), None, "abcdefghij".to_string())
+        );
+        assert_eq!(
+            answer(&complete, Some("bytes=3-4")),
+            (
+                Some(206),
+                Some("bytes 3-4/10".to_string()),
+                "de".to_string()
+            )
+        );
+        // Malformed ranges are ignored
+        assert_eq!(answer(&complete, Some("bytes=4-3")).0, Some(200));
+        assert_eq!(answer(&complete, Some("items=0-1")).0, Some(200));
+    }
+
+    #[tokio::test]
+    async fn test_ranges_of_whole_responses() {
+        use crate::playback::byte_ranges::select_whole;
+        use crate::playback::transaction::{
+            ChunkPacing, TransactionLoader, convert_resource_to_transaction_with_pacing,
+        };
+        use crate::traits::mocks::MockFileSystem;
+        use std::path::Path;
+
+        let inventory_dir = Path::new("/inventory");
+        let content: Vec<u8> = (0..100u8).collect();
+        let fs = Arc::new(MockFileSystem::new());
+        fs.set_file("/inventory/contents/clip.mp4", content.clone());
+        let mut resource = Resource::new(
+            "GET".to_string(),
+            "https://example.com/clip.mp4".to_string(),
+        );
+        resource.content_file_path = Some("contents/clip.mp4".to_string());
+        resource.status_code = Some(200);
+        resource.duration_ms = Some(1000);
+        let mut headers = crate::types::HttpHeaders::new();
+        headers.set(
+            "etag",
+            crate::types::HeaderValue::Single("\"v1\"".to_string()),
+        );
+        resource.raw_headers = Some(headers);
+
+        for stream_threshold in [u64::MAX, 50] {
+            let pacing = ChunkPacing {
+                chunk_size: Some(16),
+                stream_threshold,
+                ..ChunkPacing::default()
+            };
+            let tx = convert_resource_to_transaction_with_pacing(
+                &resource,
+                inventory_dir,
+                fs.clone(),
+                &pacing,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            assert_eq!(tx.streamed.is_some(), stream_threshold == 50);
+            let loader =
+                TransactionLoader::new(Vec::new(), inventory_dir.to_path_buf(), fs.clone())
+                    .with_pacing(pacing);
+
+            let slice = select_whole(&tx, Some("bytes=20-59"), None).unwrap();
+            assert_eq!(slice.status_code, Some(206));
+            let headers = slice.raw_headers.as_ref().unwrap();
+            assert_eq!(
+                headers.get("content-range").unwrap().first(),
+                "bytes 20-59/100"
+            );
+            assert_eq!(headers.get("accept-ranges").unwrap().first(), "bytes");
+            assert_eq!(slice.body_size(), 40);
+            // The slice takes its share of the recorded second
+            assert_eq!(slice.target_close_time, 400);
+            let mut body = Vec::new();
+            let mut index = 0;
+            while let Some(chunk) = loader.chunk(&slice, index).await.unwrap() {
+                assert!(chunk.target_time < 400);
+                body.extend_from_slice(&chunk.chunk);
+                index += 1;
+            }
+            assert_eq!(body, content[20..60].to_vec());
+
+            let tail = select_whole(&tx, Some("bytes=-10"), None).unwrap();
+            assert_eq!(
+                tail.raw_headers
+                    .unwrap()
+                    .get("content-range")
+                    .unwrap()
+                    .first(),
+                "bytes 90-99/100"
+            );
+            let past_end = select_whole(&tx, Some("bytes=100-"), None).unwrap();
+            assert_eq!(past_end.status_code, Some(416));
+            assert_eq!(past_end.body_size(), 0);
+
+            // A changed validator or no valid range sends the whole body
+            assert!(select_whole(&tx, Some("bytes=0-9"), Some("\"v1\"")).is_some());
+            assert!(select_whole(&tx, Some("bytes=0-9"), Some("\"v2\"")).is_none());
+            assert!(select_whole(&tx, Some("items=0-9"), None).is_none());
+            assert!(select_whole(&tx, None, None).is_none());
+        }
+
+        // Only plain 200s are sliced
+        resource.status_code = Some(404);
+        let not_found = convert_resource_to_transaction_with_pacing(
+            &resource,
+            inventory_dir,
+            fs,
+            &ChunkPacing::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(select_whole(&not_found, Some("bytes=0-9"), None).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recorded_failures_replay() {
+        use crate::playback::error_replay::{ErrorReplay, aborted_body, io_error};
+        use crate::playback::transaction::convert_resource_to_transaction;
+        use crate::traits::RealFileSystem;
+        use crate::types::ErrorKind;
+        use http_body_util::BodyExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        // A download the origin broke off after 5 of 1000 bytes
+        let json = r#"{"method":"GET","url":"https://example.com/file.txt","ttfbMs":10,"durationMs":100,"statusCode":200,"rawHeaders":{"content-type":"text/plain","content-length":"1000"},"contentUtf8":"hello","errorKind":"truncatedBody","errorMessage":"error reading a body from connection"}"#;
+        let resource: Resource = serde_json::from_str(json).unwrap();
+        let tx =
+            convert_resource_to_transaction(&resource, &inventory_dir, Arc::new(RealFileSystem))
+                .await
+                .unwrap()
+                .unwrap();
+        // The recorded response is replayed, promising the length it never reached
+        assert_eq!(tx.error_status(), None);
+        assert_eq!(tx.body_size(), 5);
+        assert_eq!(
+            tx.raw_headers
+                .as_ref()
+                .unwrap()
+                .get("content-length")
+                .map(|v| v.first()),
+            Some("1000")
+        );
+        let error = io_error(ErrorKind::TruncatedBody, "cut off");
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        // Requests that got no response close the connection only when asked to, and
+        // the proxy's own failures keep their status
+        assert!(!ErrorReplay::Status.closes_connection(ErrorKind::ConnectionRefused));
+        assert!(ErrorReplay::Connection.closes_connection(ErrorKind::ConnectionRefused));
+        assert!(ErrorReplay::Connection.closes_connection(ErrorKind::ReadTimeout));
+        assert!(!ErrorReplay::Connection.closes_connection(ErrorKind::Internal));
+        let body = aborted_body(ErrorKind::ResetByPeer, "connection reset by peer");
+        assert!(body.collect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_ended_responses() {
+        use crate::playback::open_ended::{OpenEnded, OpenEndedOptions};
+        use crate::playback::transaction::convert_resource_to_transaction;
+        use crate::traits::RealFileSystem;
+        use std::time::Duration;
+
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        // A download the client gave up on after 5 of 1000 bytes
+        let json = r#"{"method":"GET","url":"https://example.com/poll","ttfbMs":10,"durationMs":100,"statusCode":200,"rawHeaders":{"content-type":"text/plain","content-length":"1000"},"contentUtf8":"hello","openEnded":true}"#;
+        let resource: Resource = serde_json::from_str(json).unwrap();
+        let tx =
+            convert_resource_to_transaction(&resource, &inventory_dir, Arc::new(RealFileSystem))
+                .await
+                .unwrap()
+                .unwrap();
+        assert!(tx.open_ended);
+        assert_eq!(tx.body_size(), 5);
+        // Sent chunked, as the length of the whole body is not known
+        assert!(
+            tx.raw_headers
+                .as_ref()
+                .unwrap()
+                .get("content-length")
+                .is_none()
+        );
+
+        let held = |open_ended: OpenEnded, hold_open: Option<u64>| async move {
+            let options = OpenEndedOptions {
+                open_ended,
+                hold_open,
+            };
+            tokio::time::timeout(Duration::from_millis(200), options.hold())
+                .await
+                .is_err()
+        };
+        assert!(!held(OpenEnded::Close, None).await);
+        assert!(!held(OpenEnded::Close, Some(1_000)).await);
+        assert!(!held(OpenEnded::Hold, Some(20)).await);
+        // Until the client disconnects
+        assert!(held(OpenEnded::Hold, None).await);
+    }
+
+    #[test]
+    fn test_cookie_jar_and_vary_on_cookie() {
+        use crate::playback::cookies::CookieJar;
+        use crate::playback::matching::prefer_cookies;
+        use crate::types::{HeaderValue, HttpHeaders, Transaction};
+        use std::time::{Duration, Instant};
+
+        let set_cookies = |values: &[&str], date: Option<&str>| {
+            let mut headers = HttpHeaders::new();
+            if let Some(date) = date {
+                headers.set("date", HeaderValue::Single(date.to_string()));
+            }
+            let values = values.iter().map(|v| v.to_string()).collect();
+            headers.set("set-cookie", HeaderValue::Multiple(values));
+            headers
+        };
+        let client = "10.0.0.2".parse().unwrap();
+        let other = "10.0.0.3".parse().unwrap();
+        let start = Instant::now();
+        let jar = CookieJar::default();
+        jar.store(
+            client,
+            &set_cookies(
+                &[
+                    "session=REDACTED; Path=/; HttpOnly",
+                    "prefs=dark; Max-Age=60",
+                    // An hour after the recorded Date, however long ago that was
+                    "cart=1; Expires=Mon, 05 Jan 2015 11:00:00 GMT",
+                ],
+                Some("Mon, 05 Jan 2015 10:00:00 GMT"),
+            ),
+            start,
+        );
+        assert!(jar.holds(client, "session", start));
+        assert!(!jar.holds(other, "session", start));
+        assert!(jar.holds(client, "prefs", start + Duration::from_secs(59)));
+        assert!(!jar.holds(client, "prefs", start + Duration::from_secs(60)));
+        assert!(jar.holds(client, "cart", start + Duration::from_secs(3599)));
+        // Logging out deletes the cookie
+        jar.store(
+            client,
+            &set_cookies(&["session=; Expires=Thu, 01 Jan 1970 00:00:00 GMT"], None),
+            start,
+        );
+        assert!(!jar.holds(client, "session", start));
+
+        // The same page recorded before and after logging in
+        let page = |index: usize, cookie: Option<&str>| {
+            let request_headers = cookie.map(|cookie| {
+                let mut headers = HttpHeaders::new();
+                headers.set("cookie", HeaderValue::Single(cookie.to_string()));
+                headers
+            });
+            Arc::new(Transaction {
+                method: "GET".to_string(),
+                url: "https://example.com/account".to_string(),
+                ttfb: 0,
+                status_code: Some(200),
+                reason_phrase: None,
+                error_kind: None,
+                error_message: None,
+                raw_headers: None,
+                chunks: vec![],
+                target_close_time: 0,
+                resource_index: index,
+                lazy: false,
+                streamed: None,
+                form_fields: None,
+                upload: None,
+                availability: None,
+                url_pattern: None,
+                request_client: None,
+                event_stream: None,
+                request_body_sha1: None,
+                match_headers: None,
+                byte_ranges: None,
+                open_ended: false,
+                timed: false,
+                request_headers: Some(request_headers.unwrap_or_default()),
+            })
+        };
+        let transactions = vec![
+            page(0, Some("_ga=REDACTED")),
+            page(1, Some("_ga=REDACTED; session=REDACTED")),
+        ];
+        let vary = ["session".to_string()];
+        let preferred = |signed_in: bool, vary: &[String]| {
+            prefer_cookies(&transactions, &transactions[0], vary, |name| {
+                signed_in && name == "session"
+            })
+            .map(|t| t.resource_index)
+        };
+        assert_eq!(preferred(true, &vary), Some(1));
+        assert_eq!(preferred(false, &vary), Some(0));
+        assert_eq!(preferred(true, &[]), None);
+        // A cookie every recording carried does not tell them apart
+        assert_eq!(preferred(true, &["_ga".to_string()]), None);
+    }
+
+    #[test]
+    fn test_conditional_requests() {
+        use crate::playback::conditional::not_modified;
+        use crate::types::{BodyChunk, HeaderValue, HttpHeaders, Transaction};
+
+        let mut recorded_headers = HttpHeaders::new();
+        for (name, value) in [
+            ("content-type", "text/css"),
+            ("content-encoding", "br"),
+            ("etag", "\"v2\""),
+            ("last-modified", "Tue, 03 Jun 2025 10:00:00 GMT"),
+            ("cache-control", "max-age=60"),
+        ] {
+            recorded_headers.set(name, HeaderValue::Single(value.to_string()));
+        }
+        let transaction = Transaction {
+            method: "GET".to_string(),
+            url: "https://example.com/app.css".to_string(),
+            ttfb: 40,
+            status_code: Some(200),
+            reason_phrase: None,
+            error_kind: None,
+            error_message: None,
+            raw_headers: Some(recorded_headers),
+            chunks: vec![BodyChunk {
+                chunk: b"body{}".to_vec().into(),
+                target_time: 10,
+            }],
+            target_close_time: 10,
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability: None,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: false,
+            request_headers: None,
+        };
+        let request = |headers: &[(&'static str, &str)]| {
+            let mut map = hyper::HeaderMap::new();
+            for (name, value) in headers {
+                map.append(*name, value.parse().unwrap());
+            }
+            map
+        };
+
+        let unchanged = not_modified(
+            &transaction,
+            "GET",
+            &request(&[("if-none-match", "\"v1\", W/\"v2\"")]),
+        )
+        .unwrap();
+        assert_eq!(unchanged.status_code, Some(304));
+        assert!(unchanged.chunks.is_empty());
+        assert_eq!(unchanged.ttfb, 40);
+        let headers = unchanged.raw_headers.unwrap();
+        assert_eq!(headers.get("etag").unwrap().first(), "\"v2\"");
+        assert_eq!(headers.get("cache-control").unwrap().first(), "max-age=60");
+        assert!(!headers.contains("content-type"));
+        assert!(!headers.contains("content-encoding"));
+
+        // A changed ETag means a full response, whatever If-Modified-Since says
+        assert!(
+            not_modified(
+                &transaction,
+                "GET",
+                &request(&[
+                    ("if-none-match", "\"v1\""),
+                    ("if-modified-since", "Wed, 04 Jun 2025 10:00:00 GMT"),
+                ]),
+            )
+            .is_none()
+        );
+        let since = |date: &str| {
+            not_modified(
+                &transaction,
+                "HEAD",
+                &request(&[("if-modified-since", date)]),
+            )
+            .is_some()
+        };
+        assert!(since("Tue, 03 Jun 2025 10:00:00 GMT"));
+        assert!(!since("Mon, 02 Jun 2025 10:00:00 GMT"));
+        assert!(!since("yesterday"));
+
+        // Only safe methods, and only when the request has validators
+        let etag = request(&[("if-none-match", "*")]);
+        assert!(not_modified(&transaction, "POST", &etag).is_none());
+        assert!(not_modified(&transaction, "GET", &request(&[])).is_none());
+    }
+
+    #[test]
+    fn test_security_header_presets() {
+        use crate::playback::security_headers::SecurityHeaders;
+        use crate::types::{HeaderValue, HttpHeaders};
+
+        let inventory = || {
+            let mut headers = HttpHeaders::new();
+            for (name, value) in [
+                (
+                    "Strict-Transport-Security",
+                    "max-age=31536000; includeSubDomains",
+                ),
+                ("Content-Security-Policy-Report-Only", "default-src 'self'"),
+                ("Cache-Control", "no-cache"),
+            ] {
+                headers.set(name, HeaderValue::Single(value.to_string()));
+            }
+            let mut page = Resource::new("GET".to_string(), "https://example.com/".to_string());
+            page.content_type_mime = Some("text/html".to_string());
+            page.raw_headers = Some(headers);
+            let mut script =
+                Resource::new("GET".to_string(), "https://example.com/app.js".to_string());
+            script.content_type_mime = Some("application/javascript".to_string());
+            script.raw_headers = Some(HttpHeaders::new());
+
+            let mut inventory = Inventory::new();
+            inventory.resources = vec![page, script];
+            inventory
+        };
+        let names = |inventory: &Inventory, index: usize| -> Vec<String> {
+            inventory.resources[index]
+                .raw_headers
+                .as_ref()
+                .unwrap()
+                .names()
+                .map(str::to_string)
+                .collect()
+        };
+
+        let mut kept = inventory();
+        assert_eq!(SecurityHeaders::Keep.apply(&mut kept), 0);
+        assert_eq!(names(&kept, 0).len(), 3);
+
+        let mut stripped = inventory();
+        assert_eq!(SecurityHeaders::Strip.apply(&mut stripped), 1);
+        assert_eq!(names(&stripped, 0), vec!["Cache-Control"]);
+
+        let mut hardened = inventory();
+        assert_eq!(SecurityHeaders::Harden.apply(&mut hardened), 1);
+        assert_eq!(
+            names(&hardened, 0),
+            vec![
+                "Cache-Control",
+                "Content-Security-Policy",
+                "X-Frame-Options",
+                "X-Content-Type-Options"
+            ]
+        );
+        // Only documents get page policies added
+        assert!(names(&hardened, 1).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_header_overrides_file() {
+        use crate::playback::apply_header_overrides;
+        use crate::traits::mocks::MockFileSystem;
+        use crate::types::{HeaderValue, HttpHeaders};
+
+        let mut headers = HttpHeaders::new();
+        for (name, value) in [
+            ("Content-Security-Policy", "default-src 'self'"),
+            ("Cache-Control", "max-age=3600"),
+        ] {
+            headers.set(name, HeaderValue::Single(value.to_string()));
+        }
+        let mut page = Resource::new("GET".to_string(), "https://example.com/".to_string());
+        page.raw_headers = Some(headers);
+        let font = Resource::new(
+            "GET".to_string(),
+            "https://cdn.example.com/fonts/a.woff2".to_string(),
+        );
+        let mut inventory = Inventory::new();
+        inventory.resources = vec![page, font];
+
+        let inventory_dir = std::path::PathBuf::from("/inventory");
+        let fs = MockFileSystem::new();
+        // No rules file leaves the inventory as loaded
+        let mut unchanged = inventory.clone();
+        apply_header_overrides(&mut unchanged, &inventory_dir, &fs)
+            .await
+            .unwrap();
+        assert_eq!(
+            unchanged.resources[0].raw_headers,
+            inventory.resources[0].raw_headers
+        );
+
+        fs.set_file(
+            "/inventory/header-overrides.yaml",
+            br#"rules:
+  - url: "https://example.com/*"
+    remove: [content-security-policy]
+    set:
+      cache-control: no-store
+  - url: "https://cdn.example.com/**"
+    add:
+      Access-Control-Allow-Origin: "*"
+  - url: "https://nowhere.example/"
+    urlMatch: exact
+    add:
+      X-Unused: "1"
+"#
+            .to_vec(),
+        );
+        apply_header_overrides(&mut inventory, &inventory_dir, &fs)
+            .await
+            .unwrap();
+
+        let page = inventory.resources[0].raw_headers.as_ref().unwrap();
+        // Set keeps the position and recorded name of the header it replaces
+        assert_eq!(
+            page.iter()
+                .map(|(name, value)| (name, value.first()))
+                .collect::<Vec<_>>(),
+            vec![("Cache-Control", "no-store")]
+        );
+        let font = inventory.resources[1].raw_headers.as_ref().unwrap();
+        assert_eq!(
+            font.get("access-control-allow-origin").unwrap().first(),
+            "*"
+        );
+        assert_eq!(font.len(), 1);
+
+        fs.set_file(
+            "/inventory/header-overrides.yaml",
+            b"rules:\n  - remove: [cache-control]\n    unknown: 1\n".to_vec(),
+        );
+        assert!(
+            apply_header_overrides(&mut inventory, &inventory_dir, &fs)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_served_log_records_on_drop() {
+        use crate::playback::served_log::ServedLog;
+
+        let log = ServedLog::new();
+        let recorder = log
+            .begin(
+                "GET",
+                "https://example.com/",
+                200,
+                42,
+                std::time::Instant::now(),
+            )
+            .expecting("https://example.com/?utm=1", 100, 250);
+        assert!(log.entries().is_empty());
+
+        drop(recorder);
+        let entries = log.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].url, "https://example.com/");
+        assert_eq!(entries[0].request_url, "https://example.com/?utm=1");
+        assert_eq!(entries[0].bytes, 42);
+        assert_eq!(entries[0].target_ttfb_ms, 100);
+        assert_eq!(entries[0].target_total_ms, 250);
+        assert!(entries[0].total_ms >= entries[0].ttfb_ms);
+
+        let jsonl = log.to_jsonl().unwrap();
+        assert_eq!(jsonl.lines().count(), 1);
+        let line: serde_json::Value = serde_json::from_str(jsonl.trim_end()).unwrap();
+        assert_eq!(line["requestUrl"], "https://example.com/?utm=1");
+        assert_eq!(line["targetTotalMs"], 250);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_socket_options_carry_over_to_accepted_connections() {
+        use crate::playback::socket::SocketOptions;
+
+        let options = SocketOptions {
+            tcp_nodelay: true,
+            send_buffer_size: Some(16 * 1024),
+            coalesce_writes: true,
+        };
+        let listener = options.bind_listener(0).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let _client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+        assert!(accepted.nodelay().unwrap());
+
+        assert!(options.server_builder().is_some());
+        assert!(SocketOptions::default().server_builder().is_none());
+    }
+
+    #[test]
+    fn test_client_mismatches_ignore_versions_and_coding_order() {
+        use crate::playback::client_check::client_mismatches;
+        use crate::types::ClientMetadata;
+
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert(
+            "user-agent",
+            "Mozilla/5.0 (Windows NT 10.0) Chrome/120.0.6099.109 Safari/537.36"
+                .parse()
+                .unwrap(),
+        );
+        headers.insert("accept-encoding", "gzip, deflate, br".parse().unwrap());
+        headers.insert("viewport-width", "1280".parse().unwrap());
+        let recorded = ClientMetadata::from_headers(&headers).unwrap();
+        assert!(ClientMetadata::from_headers(&hyper::HeaderMap::new()).is_none());
+
+        // A browser update and a reordered, weighted Accept-Encoding are the same client
+        let updated = ClientMetadata {
+            user_agent: Some(
+                "Mozilla/5.0 (Windows NT 10.0) Chrome/121.0.6167.85 Safari/537.36".to_string(),
+            ),
+            accept_encoding: Some("br;q=1.0, gzip, deflate, identity".to_string()),
+            ..Default::default()
+        };
+        assert!(client_mismatches(&recorded, &updated).is_empty());
+
+        let mobile = ClientMetadata {
+            user_agent: Some(
+                "Mozilla/5.0 (Linux; Android 14) Chrome/120.0.6099.109 Mobile Safari/537.36"
+                    .to_string(),
+            ),
+            accept_encoding: Some("gzip, deflate, br;q=0".to_string()),
+            viewport_width: Some("412".to_string()),
+            ..Default::default()
+        };
+        let headers: Vec<&str> = client_mismatches(&recorded, &mobile)
+            .iter()
+            .map(|m| m.header)
+            .collect();
+        assert_eq!(headers, ["Accept-Encoding", "User-Agent", "Viewport-Width"]);
+    }
+
+    #[test]
+    fn test_host_profiles_override_matching_hosts() {
+        use crate::playback::host_profiles::{HostProfile, HostProfiles};
+
+        let mut inventory = Inventory::new();
+        for url in [
+            "https://www.example.com/",
+            "https://static.cdn.example.net/app.js",
+            "https://ads.adnet.com/banner.png",
+            "https://other.org/",
+        ] {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            resource.ttfb_ms = 40;
+            resource.duration_ms = Some(120);
+            resource.mbps = Some(5.0);
+            inventory.resources.push(resource);
+        }
+
+        let profiles = HostProfiles::new(&[
+            "www.example.com=10".to_string(),
+            "*.cdn.example.net=50mbps".to_string(),
+            "*.adnet.com=2,300ms".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(profiles.apply(&mut inventory), 3);
+
+        let r = &inventory.resources;
+        assert_eq!(
+            (r[0].mbps, r[0].duration_ms, r[0].ttfb_ms),
+            (Some(10.0), None, 40)
+        );
+        assert_eq!(
+            (r[1].mbps, r[1].duration_ms, r[1].ttfb_ms),
+            (Some(50.0), None, 40)
+        );
+        assert_eq!(
+            (r[2].mbps, r[2].duration_ms, r[2].ttfb_ms),
+            (Some(2.0), None, 300)
+        );
+        assert_eq!(
+            (r[3].mbps, r[3].duration_ms, r[3].ttfb_ms),
+            (Some(5.0), Some(120), 40)
+        );
+
+        let rtt_only = HostProfile::parse("ads.example=,300ms").unwrap();
+        assert_eq!((rtt_only.mbps, rtt_only.rtt_ms), (None, Some(300)));
+        assert!(rtt_only.matches("ADS.example"));
+        assert!(!rtt_only.matches("ads.example.com"));
+        for invalid in [
+            "ads.example",
+            "=10",
+            "ads.example=",
+            "ads.example=0",
+            "ads.example=fast",
+        ] {
+            assert!(HostProfile::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_host_map_serves_recorded_host() {
+        use crate::playback::host_map::HostMap;
+        use crate::types::{HeaderValue, HttpHeaders, Transaction};
+
+        let map = HostMap::new(&[
+            "staging.example.com=www.example.com".to_string(),
+            "localhost:8080=https://www.example.com".to_string(),
+        ])
+        .unwrap()
+        .unwrap();
+        assert!(HostMap::new(&[]).unwrap().is_none());
+        for invalid in [
+            "staging.example.com",
+            "=www.example.com",
+            "a=ftp://b",
+            "a=b/c",
+        ] {
+            assert!(HostMap::new(&[invalid.to_string()]).is_err(), "{}", invalid);
+        }
+
+        assert!(map.map_request("https://www.example.com/").is_none());
+        let staging = map
+            .map_request("https://Staging.example.com/login?next=%2F")
+            .unwrap();
+        assert_eq!(staging.url, "https://www.example.com/login?next=%2F");
+        assert_eq!(staging.host, "www.example.com");
+        let local = map.map_request("http://localhost:8080/login").unwrap();
+        assert_eq!(local.url, "https://www.example.com/login");
+
+        let mut headers = HttpHeaders::new();
+        headers.set(
+            "Location",
+            HeaderValue::Single("https://www.example.com/home?tab=1".to_string()),
+        );
+        headers.set(
+            "Set-Cookie",
+            HeaderValue::Multiple(vec![
+                "sid=1; Domain=.example.com; Path=/; Secure".to_string(),
+                "pref=2; domain=www.example.com; HttpOnly".to_string(),
+                "lang=ja; Path=/".to_string(),
+            ]),
+        );
+        let transaction = Transaction {
+            method: "POST".to_string(),
+            url: "https://www.example.com/login".to_string(),
+            ttfb: 20,
+            status_code: Some(302),
+            reason_phrase: None,
+            error_kind: None,
+            error_message: None,
+            raw_headers: Some(headers),
+            chunks: Vec::new(),
+            target_close_time: 0,
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability: None,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: false,
+            request_headers: None,
+        };
+        let values = |transaction: &Transaction, name: &str| -> Vec<String> {
+            let headers = transaction.raw_headers.as_ref().unwrap();
+            headers
+                .get(name)
+                .unwrap()
+                .as_vec()
+                .iter()
+                .map(|v| v.to_string())
+                .collect()
+        };
+
+        // The parent domain still covers staging; the recorded host does not
+        let rewritten = staging.rewrite_response(&transaction).unwrap();
+        assert_eq!(
+            values(&rewritten, "location"),
+            ["https://staging.example.com/home?tab=1"]
+        );
+        assert_eq!(
+            values(&rewritten, "set-cookie"),
+            [
+                "sid=1; Domain=.example.com; Path=/; Secure",
+                "pref=2; HttpOnly",
+                "lang=ja; Path=/"
+            ]
+        );
+
+        let rewritten = local.rewrite_response(&transaction).unwrap();
+        assert_eq!(
+            values(&rewritten, "location"),
+            ["http://localhost:8080/home?tab=1"]
+        );
+        assert_eq!(
+            values(&rewritten, "set-cookie"),
+            [
+                "sid=1; Path=/; Secure",
+                "pref=2; HttpOnly",
+                "lang=ja; Path=/"
+            ]
+        );
+
+        // Redirects to other hosts and responses without cookies are left alone
+        let mut elsewhere = transaction.clone();
+        elsewhere.raw_headers = Some(HttpHeaders::new());
+        elsewhere.raw_headers.as_mut().unwrap().set(
+            "location",
+            HeaderValue::Single("https://accounts.example.org/".to_string()),
+        );
+        assert!(staging.rewrite_response(&elsewhere).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_explain_match() {
+        use crate::playback::match_test::{MatchRule, explain_match};
+        use crate::playback::transaction::{ChunkPacing, PreloadPolicy, prepare_transactions};
+        use crate::query::QueryOptions;
+        use crate::traits::mocks::MockFileSystem;
+        use crate::types::{MatchStrategy, UrlMatch};
+
+        let mut inventory = Inventory::new();
+        for (method, url, content) in [
+            ("GET", "https://example.com/search?b=2&a=1", true),
+            ("GET", "https://example.com/api/users/*", true),
+            ("POST", "https://example.com/form", true),
+            ("GET", "https://example.com/empty", false),
+        ] {
+            let mut resource = Resource::new(method.to_string(), url.to_string());
+            resource.status_code = Some(200);
+            resource.content_utf8 = content.then(|| "ok".to_string());
+            inventory.resources.push(resource);
+        }
+        inventory.resources[1].url_match = Some(UrlMatch::Glob);
+        let transactions = prepare_transactions(
+            &inventory,
+            std::path::Path::new("inventory"),
+            Arc::new(MockFileSystem::new()),
+            &PreloadPolicy::None,
+            &ChunkPacing::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        let transactions: Vec<Arc<_>> = transactions.into_iter().map(Arc::new).collect();
+        let explain = |method: &str, url: &str, query: QueryOptions| {
+            explain_match(
+                &inventory,
+                &transactions,
+                method,
+                url,
+                &query,
+                &[MatchStrategy::Exact],
+            )
+            .unwrap()
+        };
+        let sorted = QueryOptions {
+            sort_query: true,
+            ..Default::default()
+        };
+
+        let url = "https://example.com/search?a=1&b=2";
+        let miss = explain("GET", url, QueryOptions::default());
+        assert_eq!(miss.selected, None);
+        assert_eq!(miss.near_misses.len(), 1);
+        assert!(miss.near_misses[0].contains("query differs"));
+        assert_eq!(
+            explain("GET", url, sorted).selected,
+            Some((0, MatchRule::Exact))
+        );
+        assert_eq!(
+            explain("HEAD", url, sorted).selected,
+            Some((0, MatchRule::HeadFromGet))
+        );
+        assert_eq!(
+            explain("GET", "https://example.com/api/users/42", sorted).selected,
+            Some((1, MatchRule::Pattern))
+        );
+
+        let wrong_method = explain("GET", "https://example.com/form", sorted);
+        assert!(wrong_method.near_misses[0].contains("method differs"));
+        let empty = explain("GET", "https://example.com/empty", sorted);
+        assert!(empty.steps.iter().any(|s| s.contains("1 of 4 resources")));
+        assert!(empty.near_misses[0].contains("no content"));
+    }
+
+    #[test]
+    fn test_negotiate_encoding_for_client() {
+        use crate::playback::accept_encoding::negotiate_encoding;
+        use crate::playback::transaction::{compress_content, decompress_content, with_body};
+        use crate::types::{HeaderValue, HttpHeaders, Transaction};
+
+        let content = b"body { color: red; }\n".repeat(200);
+        let mut headers = HttpHeaders::new();
+        headers.set("Content-Type", HeaderValue::Single("text/css".to_string()));
+        headers.set("Content-Encoding", HeaderValue::Single("br".to_string()));
+        let recorded = Transaction {
+            method: "GET".to_string(),
+            url: "https://example.com/app.css".to_string(),
+            ttfb: 80,
+            status_code: Some(200),
+            reason_phrase: None,
+            error_kind: None,
+            error_message: None,
+            raw_headers: Some(headers),
+            chunks: vec![],
+            target_close_time: 500,
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability: None,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: false,
+            request_headers: None,
+        };
+        let brotli = compress_content(&content, &ContentEncodingType::Br).unwrap();
+        let recorded = with_body(&recorded, brotli.into());
+
+        let body = |t: &Transaction| -> Vec<u8> {
+            t.chunks
+                .iter()
+                .flat_map(|c| c.chunk.iter().copied())
+                .collect()
+        };
+        let encoding = |t: &Transaction| {
+            t.raw_headers
+                .as_ref()
+                .unwrap()
+                .get("content-encoding")
+                .map(|v| v.as_vec().join(","))
+        };
+
+        for accepted in ["gzip, deflate, br", "br;q=0.5, gzip", "*"] {
+            assert!(
+                negotiate_encoding(&recorded, Some(accepted))
+                    .unwrap()
+                    .is_none()
+            );
+        }
+
+        let gzip = negotiate_encoding(&recorded, Some("gzip, deflate, br;q=0"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(encoding(&gzip).as_deref(), Some("gzip"));
+        assert_eq!(
+            decompress_content(&body(&gzip), &ContentEncodingType::Gzip).unwrap(),
+            content
+        );
+        // The schedule is kept: same TTFB and close time
+        assert_eq!((gzip.ttfb, gzip.target_close_time), (80, 500));
+
+        let deflate = negotiate_encoding(&recorded, Some("gzip;q=0.2, deflate"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(encoding(&deflate).as_deref(), Some("deflate"));
+
+        let identity = negotiate_encoding(&recorded, None).unwrap().unwrap();
+        assert_eq!(encoding(&identity), None);
+        assert_eq!(body(&identity), content);
+        assert_eq!(
+            identity
+                .raw_headers
+                .as_ref()
+                .unwrap()
+                .get("content-length")
+                .unwrap()
+                .as_vec(),
+            [content.len().to_string()]
+        );
+    }
+
+    #[test]
+    fn test_match_strategies() {
+        use crate::playback::matching::{MatchRequest, find_matching, prefer_request_headers};
+        use crate::query::QueryOptions;
+        use crate::types::{HeaderValue, HttpHeaders, MatchStrategy, Transaction};
+
+        let transaction = |method: &str, url: &str| Transaction {
+            method: method.to_string(),
+            url: url.to_string(),
+            ttfb: 0,
+            status_code: Some(200),
+            reason_phrase: None,
+            error_kind: None,
+            error_message: None,
+            raw_headers: None,
+            chunks: vec![],
+            target_close_time: 0,
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability: None,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: false,
+            request_headers: None,
+        };
+        let mut transactions = vec![
+            transaction("GET", "https://example.com/app.js?v=1"),
+            transaction("GET", "https://example.com/api?user=1"),
+            transaction("GET", "https://example.com/api?user=1&lang=en"),
+            transaction("GET", "https://example.com/feed"),
+            transaction("GET", "https://example.com/feed"),
+            transaction("POST", "https://example.com/search"),
+            transaction("POST", "https://example.com/search"),
+        ];
+        let mut json = HttpHeaders::new();
+        json.set(
+            "Accept",
+            HeaderValue::Single("application/json".to_string()),
+        );
+        transactions[3].match_headers = Some(json.clone());
+        transactions[3].request_headers = Some(json);
+        let mut html = HttpHeaders::new();
+        html.set("Accept", HeaderValue::Single("text/html".to_string()));
+        transactions[4].request_headers = Some(html);
+        transactions[5].request_body_sha1 = Some(crate::check::sha1_hex(b"q=a"));
+        transactions[6].request_body_sha1 = Some(crate::check::sha1_hex(b"q=b"));
+        for (index, t) in transactions.iter_mut().enumerate() {
+            t.resource_index = index;
+        }
+        let transactions: Vec<Arc<Transaction>> = transactions.into_iter().map(Arc::new).collect();
+
+        let find = |request: MatchRequest, strategies: &[MatchStrategy]| {
+            find_matching(
+                &transactions,
+                &request,
+                strategies,
+                &QueryOptions::default(),
+                None,
+            )
+            .map(|(t, strategy)| (t.resource_index, strategy))
+        };
+        let get = |path, query| MatchRequest {
+            method: "GET",
+            host: Some("example.com"),
+            path,
+            query,
+            headers: None,
+            body_sha1: None,
+        };
+        use MatchStrategy::*;
+
+        // A cache-busting version only matches when the query is ignored
+        let busted = get("/app.js", Some("v=2"));
+        assert_eq!(find(busted, &[Exact]), None);
+        assert_eq!(find(busted, &[Exact, IgnoreQuery]), Some((0, IgnoreQuery)));
+
+        // Extra parameters are ignored; the recording with most parameters wins
+        let extra = get("/api", Some("user=1&_=123&lang=en"));
+        assert_eq!(find(extra, &[Exact]), None);
+        assert_eq!(find(extra, &[QuerySubset]), Some((2, QuerySubset)));
+        assert_eq!(find(get("/api", Some("lang=en")), &[QuerySubset]), None);
+
+        // Header matching needs the resource's matchHeaders; exact covers the rest
+        let mut accept = hyper::HeaderMap::new();
+        accept.insert("accept", "application/json".parse().unwrap());
+        let with_accept = MatchRequest {
+            headers: Some(&accept),
+            ..get("/feed", None)
+        };
+        assert_eq!(find(with_accept, &[Header, Exact]), Some((3, Header)));
+        assert_eq!(find(get("/feed", None), &[Header]), None);
+
+        // POSTs to one URL are told apart by their body
+        let hash = crate::check::sha1_hex(b"q=b");
+        let post = |body_sha1| MatchRequest {
+            method: "POST",
+            body_sha1,
+            ..get("/search", None)
+        };
+        assert_eq!(find(post(Some(&hash)), &[BodyHash]), Some((6, BodyHash)));
+        assert_eq!(find(post(Some("unknown")), &[BodyHash]), None);
+        assert_eq!(
+            find(post(Some("unknown")), &[BodyHash, Exact]),
+            Some((5, Exact))
+        );
+
+        // --vary-on serves the recording of the URL requested with the same headers
+        let vary = ["Accept".to_string()];
+        let mut html = hyper::HeaderMap::new();
+        html.insert("accept", "text/html".parse().unwrap());
+        let preferred = |headers: &hyper::HeaderMap, index: usize, vary: &[String]| {
+            prefer_request_headers(&transactions, &transactions[index], headers, vary)
+                .map(|t| t.resource_index)
+        };
+        assert_eq!(preferred(&html, 3, &vary), Some(4));
+        assert_eq!(preferred(&accept, 3, &vary), Some(3));
+        assert_eq!(preferred(&html, 3, &[]), None);
+        // Headers no recording (or every one) was requested with leave repeats in order
+        assert_eq!(preferred(&hyper::HeaderMap::new(), 3, &vary), None);
+        assert_eq!(preferred(&html, 5, &vary), None);
+    }
+
+    #[test]
+    fn test_preflights_pair_with_their_request() {
+        use crate::playback::matching::{MatchRequest, find_matching, preflight_strategies};
+        use crate::query::QueryOptions;
+        use crate::types::{HttpHeaders, MatchStrategy, Transaction};
+
+        // Preflights recorded ahead of a PUT and a DELETE of the same URL
+        let preflight = |index: usize, requested: &str| {
+            let mut sent = hyper::HeaderMap::new();
+            sent.insert("access-control-request-method", requested.parse().unwrap());
+            sent.insert(
+                "access-control-request-headers",
+                "content-type".parse().unwrap(),
+            );
+            Arc::new(Transaction {
+                method: "OPTIONS".to_string(),
+                url: "https://api.example.com/items/1".to_string(),
+                ttfb: 0,
+                status_code: Some(204),
+                reason_phrase: None,
+                error_kind: None,
+                error_message: None,
+                raw_headers: None,
+                chunks: vec![],
+                target_close_time: 0,
+                resource_index: index,
+                lazy: false,
+                streamed: None,
+                form_fields: None,
+                upload: None,
+                availability: None,
+                url_pattern: None,
+                request_client: None,
+                event_stream: None,
+                request_body_sha1: None,
+                match_headers: HttpHeaders::preflight_request("OPTIONS", &sent),
+                byte_ranges: None,
+                open_ended: false,
+                timed: false,
+                request_headers: None,
+            })
+        };
+        let transactions = vec![preflight(0, "PUT"), preflight(1, "DELETE")];
+        let find = |headers: &hyper::HeaderMap| {
+            let request = MatchRequest {
+                method: "OPTIONS",
+                host: Some("api.example.com"),
+                path: "/items/1",
+                query: None,
+                headers: Some(headers),
+                body_sha1: None,
+            };
+            let strategies = preflight_strategies("OPTIONS", headers, &[MatchStrategy::Exact]);
+            find_matching(
+                &transactions,
+                &request,
+                &strategies,
+                &QueryOptions::default(),
+                None,
+            )
+            .map(|(t, strategy)| (t.resource_index, strategy))
+        };
+
+        let mut delete = hyper::HeaderMap::new();
+        delete.insert("access-control-request-method", "DELETE".parse().unwrap());
+        delete.insert(
+            "access-control-request-headers",
+            "content-type".parse().unwrap(),
+        );
+        assert_eq!(find(&delete), Some((1, MatchStrategy::Header)));
+
+        // Other header sets still get a recording of the URL
+        delete.insert("access-control-request-headers", "x-token".parse().unwrap());
+        assert_eq!(find(&delete), Some((0, MatchStrategy::Exact)));
+
+        // Plain OPTIONS requests and other methods keep the configured strategies
+        let plain =
+            preflight_strategies("OPTIONS", &hyper::HeaderMap::new(), &[MatchStrategy::Exact]);
+        assert_eq!(plain.as_ref(), &[MatchStrategy::Exact]);
+        assert!(HttpHeaders::preflight_request("GET", &delete).is_none());
+    }
+
+    #[test]
+    fn test_self_test_check() {
+        use crate::playback::self_test::check;
+        use crate::playback::served_log::ServedEntry;
+
+        let entry = ServedEntry {
+            method: "GET".to_string(),
+            url: "https://example.com/".to_string(),
+            request_url: "https://example.com/".to_string(),
+            status: 200,
+            bytes: 1024,
+            start_ms: 0,
+            ttfb_ms: 130,
+            total_ms: 420,
+            target_ttfb_ms: 100,
+            target_total_ms: 400,
+        };
+        let url = "https://example.com/";
+        assert!(check(url, 200, Some(&entry), 50).is_ok());
+
+        // Unmatched requests fail even when a fallback answered them
+        assert!(check(url, 200, None, 50).is_err());
+        assert!(check(url, 404, Some(&entry), 50).is_err());
+
+        // Early is as wrong as late
+        let early = ServedEntry {
+            total_ms: 300,
+            ..entry.clone()
+        };
+        let error = check(url, 200, Some(&early), 50).unwrap_err();
+        assert!(error.to_string().contains("total time"));
+        assert!(check(url, 200, Some(&entry), 20).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connection_limits_per_client_and_host() {
+        use crate::playback::connection_limits::ConnectionLimits;
+        use crate::types::HostConnections;
+        use std::net::IpAddr;
+        use std::time::Duration;
+
+        let limits = ConnectionLimits::new(&[
+            HostConnections {
+                host: "www.example.com".to_string(),
+                connections: 2,
+                requests: vec![5, 3],
+                multiplexed: false,
+            },
+            HostConnections {
+                host: "cdn.example.com".to_string(),
+                connections: 1,
+                requests: vec![20],
+                multiplexed: true,
+            },
+        ]);
+        let client: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+
+        let first = limits.acquire(client, "www.example.com").await.unwrap();
+        let _second = limits.acquire(client, "www.example.com").await.unwrap();
+        // A third request waits for one of the two recorded connections
+        let third = tokio::time::timeout(
+            Duration::from_millis(20),
+            limits.acquire(client, "www.example.com"),
+        )
+        .await;
+        assert!(third.is_err());
+        // Other clients have connections of their own
+        assert!(limits.acquire(other, "www.example.com").await.is_some());
+
+        drop(first);
+        assert!(limits.acquire(client, "www.example.com").await.is_some());
+
+        // Multiplexed and unrecorded hosts are not limited
+        assert!(limits.acquire(client, "cdn.example.com").await.is_none());
+        assert!(limits.acquire(client, "other.example").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reload_swaps_the_main_inventory() {
+        use crate::control::StatusReport;
+        use crate::playback::PlaybackOptions;
+        use crate::playback::reload::{Reloader, latest_change};
+        use crate::playback::sequence::{RepeatMode, Sequences};
+        use crate::playback::sessions::Session;
+        use crate::playback::transaction::TransactionLoader;
+        use crate::traits::RealFileSystem;
+        use tokio::sync::RwLock;
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let write_inventory = |urls: &[&str]| {
+            let mut inventory = Inventory::new();
+            for url in urls {
+                let mut resource = Resource::new("GET".to_string(), url.to_string());
+                resource.status_code = Some(200);
+                resource.content_utf8 = Some("ok".to_string());
+                inventory.resources.push(resource);
+            }
+            std::fs::write(
+                dir.join("index.json"),
+                serde_json::to_string(&inventory).unwrap(),
+            )
+            .unwrap();
+        };
+        write_inventory(&["https://example.com/a", "https://example.com/a"]);
+
+        let file_system = Arc::new(RealFileSystem);
+        let main = RwLock::new(Session::new(
+            Vec::new(),
+            TransactionLoader::new(Vec::new(), dir.clone(), file_system.clone()),
+        ));
+        let sequences = Sequences::new(RepeatMode::Last);
+        let status = StatusReport::new(serde_json::json!({}));
+        let reloader = Reloader::new(dir.clone(), PlaybackOptions::default(), file_system)
+            .with_status(Some(status.clone()));
+        assert_eq!(reloader.reload(&main, &sequences).await.unwrap(), 2);
+
+        // Repeats start over with the reloaded recordings
+        let transactions = main.read().await.transactions.clone();
+        let first = sequences.next(&transactions, transactions[0].clone());
+        assert!(Arc::ptr_eq(&first, &transactions[0]));
+        assert!(Arc::ptr_eq(
+            &sequences.next(&transactions, transactions[0].clone()),
+            &transactions[1]
+        ));
+
+        let before = latest_change(&dir).await.unwrap();
+        std::fs::create_dir_all(dir.join("contents")).unwrap();
+        std::fs::write(dir.join("contents/new.css"), "body {}").unwrap();
+        assert_ne!(latest_change(&dir).await.unwrap(), before);
+
+        write_inventory(&[
+            "https://example.com/a",
+            "https://example.com/b",
+            "https://example.com/c",
+        ]);
+        assert_eq!(reloader.reload(&main, &sequences).await.unwrap(), 3);
+        // /status describes the memory of the reloaded bodies
+        assert_eq!(status.current()["memory"]["totalBytes"], 6);
+
+        // A broken inventory leaves the previous one in place
+        std::fs::write(dir.join("index.json"), "{").unwrap();
+        assert!(reloader.reload(&main, &sequences).await.is_err());
+        assert_eq!(main.read().await.transactions.len(), 3);
+    }
+
+    #[test]
+    fn test_repeated_requests_replay_in_order() {
+        use crate::playback::sequence::{RepeatMode, Sequences};
+        use crate::types::Transaction;
+
+        let transaction = |index: usize, url: &str| {
+            Arc::new(Transaction {
+                method: "GET".to_string(),
+                url: url.to_string(),
+                ttfb: 0,
+                status_code: Some(200),
+                reason_phrase: None,
+                error_kind: None,
+                error_message: None,
+                raw_headers: None,
+                chunks: vec![],
+                target_close_time: 0,
+                resource_index: index,
+                lazy: false,
+                streamed: None,
+                form_fields: None,
+                upload: None,
+                availability: None,
+                url_pattern: None,
+                request_client: None,
+                event_stream: None,
+                request_body_sha1: None,
+                match_headers: None,
+                byte_ranges: None,
+                open_ended: false,
+                timed: false,
+                request_headers: None,
+            })
+        };
+        let transactions = Arc::new(vec![
+            transaction(0, "https://example.com/poll"),
+            transaction(1, "https://example.com/app.js"),
+            transaction(2, "https://example.com/poll"),
+            transaction(3, "https://example.com/poll"),
+        ]);
+        let serve = |sequences: &Sequences, matched: usize| {
+            sequences
+                .next(&transactions, transactions[matched].clone())
+                .resource_index
+        };
+
+        let last = Sequences::new(RepeatMode::Last);
+        let served: Vec<usize> = (0..5).map(|_| serve(&last, 0)).collect();
+        assert_eq!(served, vec![0, 2, 3, 3, 3]);
+        // A URL recorded once is always answered by that recording
+        assert_eq!(serve(&last, 1), 1);
+        assert_eq!(serve(&last, 1), 1);
+
+        let cycle = Sequences::new(RepeatMode::Cycle);
+        let served: Vec<usize> = (0..5).map(|_| serve(&cycle, 0)).collect();
+        assert_eq!(served, vec![0, 2, 3, 0, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_records_forwarded_responses() {
+        use crate::playback::fallback::{FallbackRecorder, stub_response};
+        use http_body_util::BodyExt;
+        use hudsucker::{Body, hyper::Response};
+
+        let stub = stub_response("https://example.com/assets/app.js?v=2");
+        assert_eq!(stub.status(), 200);
+        assert_eq!(
+            stub.headers()["content-type"],
+            "application/javascript; charset=utf-8"
+        );
+        assert_eq!(
+            stub_response("https://example.com/").headers()["content-type"],
+            "text/html; charset=utf-8"
+        );
+
+        let recorder = FallbackRecorder::default();
+        let client = "127.0.0.1:50000".parse().unwrap();
+        recorder.forwarded(
+            client,
+            "GET",
+            "https://example.com:443/api",
+            "https://example.com/api",
+        );
+        let origin_response = || {
+            Response::builder()
+                .status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(Body::from("{\"ok\":true}"))
+                .unwrap()
+        };
+
+        let res = recorder
+            .capture(
+                client,
+                "GET",
+                "https://example.com:443/api",
+                origin_response(),
+            )
+            .await;
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"{\"ok\":true}");
+        // A response to a request that was not forwarded is not recorded
+        recorder
+            .capture(
+                client,
+                "GET",
+                "https://example.com:443/other",
+                origin_response(),
+            )
+            .await;
+
+        let resources = recorder.take_resources();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].url, "https://example.com/api");
+        assert_eq!(resources[0].status_code, Some(200));
+        assert_eq!(
+            resources[0].content_type_mime.as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(
+            resources[0].raw_body.as_deref(),
+            Some(&b"{\"ok\":true}"[..])
+        );
+        assert!(recorder.take_resources().is_empty());
+    }
+
+    #[test]
+    fn test_watchdog_aborts_stuck_streams() {
+        use crate::playback::watchdog::{AbortReason, StreamWatch, Watchdog, WatchdogOptions};
+        use std::time::{Duration, Instant};
+
+        let watchdog = Arc::new(Watchdog::new(&WatchdogOptions {
+            watchdog_lag: Some(30_000),
+            watchdog_stall: Some(10_000),
+        }));
+        // Stands in for the activity guard and journal entry a stream holds
+        let held = Arc::new(());
+        let unread = StreamWatch::new(Some(&watchdog), "https://example.com/big.bin", held.clone());
+        let late = StreamWatch::new(Some(&watchdog), "https://example.com/app.js", held.clone());
+        assert_eq!(watchdog.active(), 2);
+
+        unread.resume().unwrap();
+        unread.handed_over();
+        // Producing its next chunk, so not waiting on the client
+        late.resume().unwrap();
+
+        let now = Instant::now();
+        assert_eq!(watchdog.sweep(now + Duration::from_secs(5)), 0);
+        assert_eq!(watchdog.sweep(now + Duration::from_secs(11)), 1);
+        assert_eq!(unread.resume(), Err(AbortReason::Stalled));
+        assert_eq!(Arc::strong_count(&held), 2);
+        assert_eq!(watchdog.active(), 1);
+
+        assert_eq!(late.check_lag(1_000), Ok(()));
+        assert_eq!(late.check_lag(31_000), Err(AbortReason::Lagging));
+        assert_eq!(Arc::strong_count(&held), 1);
+        assert_eq!(watchdog.active(), 0);
+
+        // Without a watchdog the guards are only held until the stream ends
+        let unwatched = StreamWatch::new(None, "https://example.com/", held.clone());
+        assert_eq!(unwatched.check_lag(u64::MAX), Ok(()));
+        drop(unwatched);
+        assert_eq!(Arc::strong_count(&held), 1);
+    }
+
+    #[tokio::test]
+    async fn test_memory_plan_keeps_preloads_within_budget() {
+        use crate::playback::memory::{MemoryPlan, Serving};
+        use crate::playback::transaction::{ChunkPacing, PreloadPolicy};
+        use crate::traits::RealFileSystem;
+
+        let temp_dir = TempDir::new().unwrap();
+        let contents = temp_dir.path().join("contents");
+        tokio::fs::create_dir_all(&contents).await.unwrap();
+        let mut inventory = Inventory::new();
+        let mut resource = |url: &str, file: Option<(&str, usize)>| {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            if let Some((name, len)) = file {
+                std::fs::write(contents.join(name), vec![b'x'; len]).unwrap();
+                resource.content_file_path = Some(format!("contents/{}", name));
+            }
+            inventory.resources.push(resource);
+        };
+        resource("https://example.com/app.js", Some(("app.js", 1_000)));
+        resource("https://example.com/big.css", Some(("big.css", 5_000)));
+        resource("https://example.com/empty", None);
+        resource("https://example.com/video.mp4", Some(("video.mp4", 10_000)));
+        inventory.resources[0].content_encoding = Some(ContentEncodingType::Br);
+        inventory.resources[1].content_encoding = Some(ContentEncodingType::Gzip);
+        let mut inline = Resource::new("GET".to_string(), "https://example.com/data".to_string());
+        inline.content_utf8 = Some("a".repeat(100));
+        inventory.resources.push(inline);
+
+        let pacing = ChunkPacing {
+            stream_threshold: 8_000,
+            ..Default::default()
+        };
+        let plan = MemoryPlan::new(
+            &inventory,
+            temp_dir.path(),
+            &RealFileSystem,
+            &PreloadPolicy::All,
+            &pacing,
+            Some(2_000),
+        )
+        .await
+        .unwrap();
+
+        let serving: Vec<Serving> = plan.resources.iter().map(|r| r.serving).collect();
+        assert_eq!(
+            serving,
+            vec![
+                Serving::Preloaded,
+                Serving::OverBudget,
+                Serving::NoBody,
+                Serving::Streamed,
+                Serving::Preloaded,
+            ]
+        );
+        assert_eq!(plan.total_bytes, 6_100);
+        assert_eq!(plan.preloaded_bytes, 1_100);
+
+        let policy = plan.preload_policy(&PreloadPolicy::All);
+        assert!(policy.should_preload(0, "https://example.com/app.js"));
+        assert!(!policy.should_preload(1, "https://example.com/big.css"));
+        assert!(policy.should_preload(3, "https://example.com/video.mp4"));
+
+        // Without a budget every preload stays
+        let plan = MemoryPlan::new(
+            &inventory,
+            temp_dir.path(),
+            &RealFileSystem,
+            &PreloadPolicy::All,
+            &pacing,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(plan.count(Serving::OverBudget), 0);
+        assert_eq!(plan.preloaded_bytes, 6_100);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_forwards_form_body_read_for_matching() {
+        use crate::playback::fallback::Fallback;
+        use crate::playback::hudsucker_handler::PlaybackHandler;
+        use crate::playback::transaction::TransactionLoader;
+        use crate::traits::mocks::MockFileSystem;
+        use http_body_util::BodyExt;
+        use hudsucker::hyper::Request;
+        use hudsucker::{Body, HttpContext, HttpHandler, RequestOrResponse};
+
+        let loader = TransactionLoader::new(
+            Vec::new(),
+            "/inventory".into(),
+            Arc::new(MockFileSystem::new()),
+        );
+        let mut handler = PlaybackHandler::new(Vec::new(), loader)
+            .with_form_matching(true)
+            .with_fallback(Fallback::Origin);
+
+        let form =
+            "--XyZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nhello\r\n--XyZ--\r\n";
+        let ctx = HttpContext {
+            client_addr: "127.0.0.1:50002".parse().unwrap(),
+            request_method: hyper::Method::POST,
+            request_uri: "https://example.com/upload".parse().unwrap(),
+        };
+        let request = Request::builder()
+            .method("POST")
+            .uri("https://example.com/upload")
+            .header("content-type", "multipart/form-data; boundary=XyZ")
+            .body(Body::from(form))
+            .unwrap();
+
+        // Not recorded, so the origin gets the request with the body it was sent
+        let RequestOrResponse::Request(forwarded) = handler.handle_request(&ctx, request).await
+        else {
+            panic!("unrecorded form POST was not forwarded");
+        };
+        let body = forwarded.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, form.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_handler_paces_on_injected_clock() {
+        use crate::playback::hudsucker_handler::PlaybackHandler;
+        use crate::playback::transaction::TransactionLoader;
+        use crate::traits::TimeProvider;
+        use crate::traits::mocks::{MockFileSystem, MockTimeProvider};
+        use crate::types::{Availability, BodyChunk, Transaction};
+        use bytes::Bytes;
+        use http_body_util::BodyExt;
+        use hudsucker::hyper::Request;
+        use hudsucker::{Body, HttpContext, HttpHandler, RequestOrResponse};
+
+        let transaction = |url: &str, availability: Option<Availability>| Transaction {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            ttfb: 100,
+            status_code: Some(200),
+            reason_phrase: None,
+            error_kind: None,
+            error_message: None,
+            raw_headers: None,
+            chunks: vec![
+                BodyChunk {
+                    chunk: Bytes::from_static(b"first"),
+                    target_time: 0,
+                },
+                BodyChunk {
+                    chunk: Bytes::from_static(b"later"),
+                    target_time: 250,
+                },
+            ],
+            target_close_time: 500,
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: true,
+            request_headers: None,
+        };
+        let clock = Arc::new(MockTimeProvider::new(0));
+        let loader = TransactionLoader::new(
+            Vec::new(),
+            "/inventory".into(),
+            Arc::new(MockFileSystem::new()),
+        );
+        let handler = PlaybackHandler::new(
+            vec![
+                transaction("https://example.com/app.js", None),
+                transaction(
+                    "https://example.com/late.js",
+                    Some(Availability {
+                        after_ms: 1000,
+                        status: 503,
+                    }),
+                ),
+            ],
+            loader,
+        )
+        .with_time_provider(clock.clone());
+
+        let get = |url: &'static str| {
+            let ctx = HttpContext {
+                client_addr: "127.0.0.1:50003".parse().unwrap(),
+                request_method: hyper::Method::GET,
+                request_uri: url.parse().unwrap(),
+            };
+            let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+            let mut handler = handler.clone();
+            async move {
+                match handler.handle_request(&ctx, request).await {
+                    RequestOrResponse::Response(response) => response,
+                    RequestOrResponse::Request(_) => panic!("{} was forwarded", url),
+                }
+            }
+        };
+
+        // Headers go out at the TTFB, each chunk at its target time after it, and the
+        // body ends at the close time
+        let response = get("https://example.com/app.js").await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(clock.now_ms(), 100);
+        let mut body = response.into_body();
+        let first = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        assert_eq!((first.as_ref(), clock.now_ms()), (&b"first"[..], 100));
+        let later = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        assert_eq!((later.as_ref(), clock.now_ms()), (&b"later"[..], 350));
+        assert!(body.frame().await.is_none());
+        assert_eq!(clock.now_ms(), 600);
+
+        // Availability counts playback time on the same clock
+        assert_eq!(get("https://example.com/late.js").await.status(), 503);
+        clock.advance(400);
+        assert_eq!(get("https://example.com/late.js").await.status(), 200);
+    }
 }
@@ -0,0 +1,86 @@
+//! Replaying a session faster or slower than it was recorded
+//!
+//! `--speed-factor 2` compresses the whole session to half its recorded duration:
+//! TTFBs, body transfers, uploads, server-sent events and the request timeline all
+//! shrink. The other two scale one side of the network: `--ttfb-scale 1.5` makes every
+//! server response start 50% later, and `--bandwidth-scale 0.5` halves the transfer
+//! rate, so bodies take twice as long. Scaling is applied to the recorded values before
+//! transactions are built, after the timing limits and host profiles.
+
+use anyhow::Result;
+
+use crate::types::Inventory;
+
+#[derive(Debug, Clone, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeScale {
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        value_parser = parse_factor,
+        help = "Replay the session this many times faster than recorded (e.g. 2, or 0.5 for half speed)"
+    )]
+    pub speed_factor: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        value_parser = parse_factor,
+        help = "Multiply recorded TTFBs by this factor (e.g. 1.5 for 50% more latency)"
+    )]
+    pub ttfb_scale: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        value_parser = parse_factor,
+        help = "Multiply recorded transfer rates by this factor (e.g. 0.5 for half the bandwidth)"
+    )]
+    pub bandwidth_scale: Option<f64>,
+}
+
+fn parse_factor(value: &str) -> Result<f64> {
+    match value.trim().trim_end_matches('x').parse::<f64>() {
+        Ok(factor) if factor.is_finite() && factor > 0.0 => Ok(factor),
+        _ => anyhow::bail!("Invalid factor {:?}: expected a positive number", value),
+    }
+}
+
+fn scale(ms: u64, factor: f64) -> u64 {
+    (ms as f64 * factor).round() as u64
+}
+
+impl TimeScale {
+    pub fn is_empty(&self) -> bool {
+        self.speed_factor.is_none() && self.ttfb_scale.is_none() && self.bandwidth_scale.is_none()
+    }
+
+    /// Scale the timing of every resource; returns how many resources changed
+    pub fn apply(&self, inventory: &mut Inventory) -> usize {
+        let speed = self.speed_factor.unwrap_or(1.0);
+        let ttfb_factor = self.ttfb_scale.unwrap_or(1.0) / speed;
+        let bandwidth = self.bandwidth_scale.unwrap_or(1.0) * speed;
+        let transfer_factor = 1.0 / bandwidth;
+
+        let mut adjusted = 0;
+        for resource in &mut inventory.resources {
+            let before = (resource.ttfb_ms, resource.duration_ms, resource.mbps);
+            resource.ttfb_ms = scale(resource.ttfb_ms, ttfb_factor);
+            resource.duration_ms = resource.duration_ms.map(|ms| scale(ms, transfer_factor));
+            // Resources without a recorded duration are paced by their rate
+            resource.mbps = resource.mbps.map(|mbps| mbps * bandwidth);
+            // Events arrive when the server sends them, not as fast as the network allows
+            if let Some(events) = &mut resource.event_stream {
+                for event in events {
+                    event.offset_ms = scale(event.offset_ms, 1.0 / speed);
+                }
+            }
+            resource.upload_ms = resource.upload_ms.map(|ms| scale(ms, 1.0 / speed));
+            resource.request_start_ms = resource.request_start_ms.map(|ms| scale(ms, 1.0 / speed));
+            if before != (resource.ttfb_ms, resource.duration_ms, resource.mbps) {
+                adjusted += 1;
+            }
+        }
+        adjusted
+    }
+}
@@ -0,0 +1,104 @@
+//! Floors and ceilings for recorded timing
+//!
+//! One slow response during recording (a 30s stall, a cold cache on the origin) would
+//! otherwise be reproduced on every replay, and resources served from a local cache
+//! while recording replay with an unrealistic 0ms TTFB. These limits clamp the recorded
+//! values before transactions are built.
+
+use anyhow::Result;
+
+use crate::types::Inventory;
+use crate::utils::parse_duration_ms;
+
+#[derive(Debug, Clone, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingLimits {
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "Replay recorded TTFBs below this as this (e.g. 20ms)"
+    )]
+    pub min_ttfb: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "Replay recorded TTFBs above this as this (e.g. 2s)"
+    )]
+    pub max_ttfb: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "Replay recorded body transfer durations below this as this"
+    )]
+    pub min_transfer: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "Replay recorded body transfer durations above this as this"
+    )]
+    pub max_transfer: Option<u64>,
+}
+
+impl TimingLimits {
+    pub fn is_empty(&self) -> bool {
+        self.min_ttfb.is_none()
+            && self.max_ttfb.is_none()
+            && self.min_transfer.is_none()
+            && self.max_transfer.is_none()
+    }
+
+    /// Clamp every resource's `ttfbMs` and `durationMs` into the configured ranges
+    ///
+    /// Returns how many resources changed. Resources without a recorded `durationMs`
+    /// keep the transfer rate derived from `mbps`.
+    pub fn apply(&self, inventory: &mut Inventory) -> Result<usize> {
+        let ttfb = range("ttfb", self.min_ttfb, self.max_ttfb)?;
+        let transfer = range("transfer", self.min_transfer, self.max_transfer)?;
+
+        let mut adjusted = 0;
+        for resource in &mut inventory.resources {
+            let ttfb_ms = ttfb.clamp(resource.ttfb_ms);
+            let duration_ms = resource.duration_ms.map(|ms| transfer.clamp(ms));
+            if ttfb_ms != resource.ttfb_ms || duration_ms != resource.duration_ms {
+                resource.ttfb_ms = ttfb_ms;
+                resource.duration_ms = duration_ms;
+                adjusted += 1;
+            }
+        }
+        Ok(adjusted)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    min: u64,
+    max: u64,
+}
+
+impl Range {
+    fn clamp(&self, value: u64) -> u64 {
+        value.clamp(self.min, self.max)
+    }
+}
+
+fn range(name: &str, min: Option<u64>, max: Option<u64>) -> Result<Range> {
+    let range = Range {
+        min: min.unwrap_or(0),
+        max: max.unwrap_or(u64::MAX),
+    };
+    if range.min > range.max {
+        anyhow::bail!(
+            "--min-{name} ({}ms) is greater than --max-{name} ({}ms)",
+            range.min,
+            range.max
+        );
+    }
+    Ok(range)
+}
@@ -0,0 +1,202 @@
+//! Substitution of session tokens between a recording and the live client
+//!
+//! A recording holds the tokens its session was issued. A client that presents a
+//! token of its own (from an earlier live login, or one it generated) no longer matches
+//! recorded URLs, and a page that embeds the recorded token sends the client back to
+//! the old value. Each `--token` names where a token travels: a request header or query
+//! parameter, and the JSON field or HTML `name` it is issued in. The recorded value is
+//! learned from replayed responses; the first request presenting a different value maps
+//! the two. From then on requests are matched as if they carried the recorded value and
+//! responses are rewritten to carry the live one.
+
+use anyhow::Result;
+use bytes::Bytes;
+use regex::bytes::Regex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::transaction::{has_content_encoding, with_body};
+use crate::types::Transaction;
+
+/// Where one kind of token appears
+#[derive(Debug)]
+struct TokenSpec {
+    /// Request header or query parameter presenting the token
+    request_name: String,
+    /// Finds the issued value in response bodies
+    issued: Regex,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    /// Latest recorded value per spec, from replayed responses
+    recorded: HashMap<usize, String>,
+    live_to_recorded: HashMap<String, String>,
+    recorded_to_live: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct TokenRewriter {
+    specs: Vec<TokenSpec>,
+    state: Mutex<TokenState>,
+}
+
+impl TokenRewriter {
+    /// Parse `--token` values: `NAME`, or `NAME=FIELD` when the response field that
+    /// issues the token is named differently from the header or parameter carrying it
+    ///
+    /// None when no tokens are configured.
+    pub fn new(tokens: &[String]) -> Result<Option<Self>> {
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        let specs = tokens
+            .iter()
+            .map(|token| {
+                let (request_name, field) = token.split_once('=').unwrap_or((token, token));
+                if request_name.is_empty() || field.is_empty() {
+                    anyhow::bail!("Invalid --token {:?}: expected NAME or NAME=FIELD", token);
+                }
+                let field = regex::escape(field);
+                let issued = Regex::new(&format!(
+                    r#"(?:"{field}"\s*:\s*"|name="{field}"\s+(?:content|value)=")([^"\\]+)""#
+                ))?;
+                Ok(TokenSpec {
+                    request_name: request_name.to_ascii_lowercase(),
+                    issued,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Self {
+            specs,
+            state: Mutex::new(TokenState::default()),
+        }))
+    }
+
+    /// Remember the tokens a replayed response issues
+    pub fn observe_response(&self, body: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        for (index, spec) in self.specs.iter().enumerate() {
+            let issued =
+                spec.issued.captures_iter(body).last().and_then(|captures| {
+                    std::str::from_utf8(&captures[1]).ok().map(str::to_string)
+                });
+            if let Some(value) = issued {
+                state.recorded.insert(index, value);
+            }
+        }
+    }
+
+    /// Map tokens the client presents to the recorded ones they stand in for
+    pub fn observe_request(&self, headers: &hyper::HeaderMap, query: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        for (index, spec) in self.specs.iter().enumerate() {
+            let Some(recorded) = state.recorded.get(&index).cloned() else {
+                continue;
+            };
+            let header = headers
+                .get(spec.request_name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.strip_prefix("Bearer ").unwrap_or(v).trim().to_string());
+            let live = header.or_else(|| query_value(query?, &spec.request_name));
+            let Some(live) = live.filter(|live| *live != recorded && !live.is_empty()) else {
+                continue;
+            };
+            if state.live_to_recorded.get(&live) != Some(&recorded) {
+                tracing::info!("Replaying {} with the client's token", spec.request_name);
+                state
+                    .live_to_recorded
+                    .insert(live.clone(), recorded.clone());
+                state.recorded_to_live.insert(recorded, live);
+            }
+        }
+    }
+
+    /// The query with live token values swapped for recorded ones, for matching
+    pub fn recorded_query<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        let state = self.state.lock().unwrap();
+        if state.live_to_recorded.is_empty() {
+            return Cow::Borrowed(query);
+        }
+        let mut changed = false;
+        let pairs: Vec<Cow<str>> = query
+            .split('&')
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                let is_token = self
+                    .specs
+                    .iter()
+                    .any(|spec| key.eq_ignore_ascii_case(&spec.request_name));
+                match state.live_to_recorded.get(value).filter(|_| is_token) {
+                    Some(recorded) => {
+                        changed = true;
+                        Cow::Owned(format!("{}={}", key, recorded))
+                    }
+                    None => Cow::Borrowed(pair),
+                }
+            })
+            .collect();
+        if changed {
+            Cow::Owned(pairs.join("&"))
+        } else {
+            Cow::Borrowed(query)
+        }
+    }
+
+    /// Learn from and rewrite an in-memory, uncompressed response body
+    ///
+    /// Streamed and compressed bodies are served unchanged.
+    pub fn apply(&self, transaction: &Transaction) -> Option<Transaction> {
+        if transaction.streamed.is_some() || transaction.chunks.is_empty() {
+            return None;
+        }
+        if has_content_encoding(transaction) {
+            return None;
+        }
+
+        let body: Vec<u8> = transaction
+            .chunks
+            .iter()
+            .flat_map(|c| c.chunk.iter().copied())
+            .collect();
+        self.observe_response(&body);
+
+        let state = self.state.lock().unwrap();
+        let mut rewritten = body;
+        let mut changed = false;
+        for (recorded, live) in &state.recorded_to_live {
+            if let Some(replaced) = replace_bytes(&rewritten, recorded.as_bytes(), live.as_bytes())
+            {
+                rewritten = replaced;
+                changed = true;
+            }
+        }
+        changed.then(|| with_body(transaction, Bytes::from(rewritten)))
+    }
+}
+
+fn query_value(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key.eq_ignore_ascii_case(name).then(|| value.to_string())
+    })
+}
+
+/// `haystack` with every `from` replaced by `to`, or None when it has none
+fn replace_bytes(haystack: &[u8], from: &[u8], to: &[u8]) -> Option<Vec<u8>> {
+    if from.is_empty() {
+        return None;
+    }
+    let mut result = Vec::with_capacity(haystack.len());
+    let mut rest = haystack;
+    let mut found = false;
+    while let Some(pos) = rest.windows(from.len()).position(|w| w == from) {
+        result.extend_from_slice(&rest[..pos]);
+        result.extend_from_slice(to);
+        rest = &rest[pos + from.len()..];
+        found = true;
+    }
+    result.extend_from_slice(rest);
+    found.then_some(result)
+}
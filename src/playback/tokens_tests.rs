@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use crate::playback::tokens::TokenRewriter;
+    use crate::types::{BodyChunk, HeaderValue, HttpHeaders, Transaction};
+    use bytes::Bytes;
+
+    fn response(body: &str) -> Transaction {
+        let mut headers = HttpHeaders::new();
+        headers.set(
+            "content-type",
+            HeaderValue::Single("application/json".to_string()),
+        );
+        headers.set(
+            "content-length",
+            HeaderValue::Single(body.len().to_string()),
+        );
+        Transaction {
+            method: "GET".to_string(),
+            url: "https://example.com/api".to_string(),
+            ttfb: 0,
+            status_code: Some(200),
+            reason_phrase: None,
+            error_kind: None,
+            error_message: None,
+            raw_headers: Some(headers),
+            chunks: vec![BodyChunk {
+                chunk: Bytes::from(body.to_string()),
+                target_time: 0,
+            }],
+            target_close_time: 100,
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability: None,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: false,
+            request_headers: None,
+        }
+    }
+
+    fn body(transaction: &Transaction) -> String {
+        let bytes: Vec<u8> = transaction
+            .chunks
+            .iter()
+            .flat_map(|c| c.chunk.iter().copied())
+            .collect();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_no_tokens_configured() {
+        assert!(TokenRewriter::new(&[]).unwrap().is_none());
+        assert!(TokenRewriter::new(&["=field".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_query_token_is_mapped_after_replayed_login() {
+        let tokens = TokenRewriter::new(&["csrf".to_string()]).unwrap().unwrap();
+
+        // Nothing learned yet: the query is matched as sent
+        assert_eq!(
+            tokens.recorded_query("csrf=live&page=2"),
+            "csrf=live&page=2"
+        );
+
+        // The replayed login response issues the recorded token
+        let login = response(r#"{"csrf": "rec123", "user": "me"}"#);
+        assert!(tokens.apply(&login).is_none());
+
+        tokens.observe_request(&hyper::HeaderMap::new(), Some("csrf=live&page=2"));
+        assert_eq!(
+            tokens.recorded_query("csrf=live&page=2"),
+            "csrf=rec123&page=2"
+        );
+        assert_eq!(tokens.recorded_query("other=live"), "other=live");
+
+        // Later responses carry the client's token instead of the recorded one
+        let page = response(r#"{"next": "/list?csrf=rec123"}"#);
+        let rewritten = tokens.apply(&page).unwrap();
+        assert_eq!(body(&rewritten), r#"{"next": "/list?csrf=live"}"#);
+        assert_eq!(
+            rewritten.raw_headers.unwrap().get("content-length"),
+            Some(&HeaderValue::Single("27".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_bearer_header_with_response_field() {
+        let tokens = TokenRewriter::new(&["authorization=access_token".to_string()])
+            .unwrap()
+            .unwrap();
+        tokens.apply(&response(r#"{"access_token":"old.jwt"}"#));
+
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("authorization", "Bearer new.jwt".parse().unwrap());
+        tokens.observe_request(&headers, None);
+
+        let echoed = tokens.apply(&response(r#"{"token":"old.jwt"}"#)).unwrap();
+        assert_eq!(body(&echoed), r#"{"token":"new.jwt"}"#);
+    }
+
+    #[test]
+    fn test_html_meta_token_and_compressed_bodies() {
+        let tokens = TokenRewriter::new(&["x-csrf-token=csrf-token".to_string()])
+            .unwrap()
+            .unwrap();
+        tokens.observe_response(br#"<meta name="csrf-token" content="abc">"#);
+
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("x-csrf-token", "xyz".parse().unwrap());
+        tokens.observe_request(&headers, None);
+
+        let mut compressed = response(r#"<input value="abc">"#);
+        if let Some(headers) = &mut compressed.raw_headers {
+            headers.set("content-encoding", HeaderValue::Single("gzip".to_string()));
+        }
+        assert!(tokens.apply(&compressed).is_none());
+        let plain = tokens.apply(&response(r#"<input value="abc">"#)).unwrap();
+        assert_eq!(body(&plain), r#"<input value="xyz">"#);
+    }
+}
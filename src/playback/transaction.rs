@@ -1,24 +1,230 @@
+use super::body_cache::BodyCache;
+use super::transform::TransformCommand;
+use crate::multipart::form_signature;
 use crate::traits::FileSystem;
-use crate::types::{BodyChunk, Inventory, Resource, Transaction};
+use crate::types::{
+    BodyChunk, ContentEncodingType, EarlyHint, ErrorKind, HeaderValue, HttpHeaders, Inventory,
+    Resource, StreamEvent, StreamedBody, Transaction,
+};
+use crate::utils::is_text_resource;
 use anyhow::Result;
+use bytes::Bytes;
 use encoding_rs::{Encoding, UTF_8};
-use std::path::Path;
+use regex::Regex;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 const CHUNK_SIZE: usize = 1024 * 64; // 64KB chunks
+const MIN_CHUNK_SIZE: usize = 1024; // Smallest derived chunk, to bound per-chunk overhead
 const TARGET_MBPS: f64 = 1.0; // Default target speed in Mbps
+pub const DEFAULT_PACING_RESOLUTION_MS: u64 = 50;
+pub const DEFAULT_STREAM_THRESHOLD: u64 = 64 * 1024 * 1024;
+pub const DEFAULT_BODY_CACHE_SIZE: usize = 256 * 1024 * 1024;
+pub const DEFAULT_INIT_CWND: u64 = 10; // Segments, as in RFC 6928
+const SEGMENT_SIZE: u64 = 1460; // TCP payload of a 1500-byte MTU
+
+/// How response bodies are split into timed chunks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkPacing {
+    /// Fixed chunk size; when None it is derived from the transfer rate
+    pub chunk_size: Option<usize>,
+    /// Target gap between chunks used to derive the chunk size
+    pub resolution_ms: u64,
+    /// Content files at least this large that need no transformation are read chunk by
+    /// chunk while being sent instead of loaded whole
+    pub stream_threshold: u64,
+    /// Hold back the first bytes of each body as a fresh TCP connection would
+    pub slow_start: Option<SlowStart>,
+}
+
+impl Default for ChunkPacing {
+    fn default() -> Self {
+        Self {
+            chunk_size: None,
+            resolution_ms: DEFAULT_PACING_RESOLUTION_MS,
+            stream_threshold: DEFAULT_STREAM_THRESHOLD,
+            slow_start: None,
+        }
+    }
+}
+
+/// Congestion-window growth at the start of a response
+///
+/// The server may send `initial_window` bytes in the first round trip and twice as much
+/// in each following one, so small bodies take whole round trips regardless of the
+/// bandwidth and only large ones reach the recorded rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlowStart {
+    /// Bytes sent in the first round trip
+    pub initial_window: u64,
+    pub rtt_ms: u64,
+}
+
+impl SlowStart {
+    pub fn new(init_cwnd_segments: u64, rtt_ms: u64) -> Self {
+        Self {
+            initial_window: init_cwnd_segments.max(1) * SEGMENT_SIZE,
+            rtt_ms,
+        }
+    }
+
+    /// Time after TTFB at which the byte at `offset` can be sent
+    pub fn ready_ms(&self, offset: u64) -> u64 {
+        let mut window = self.initial_window.max(1);
+        let mut sent = 0u64;
+        let mut rounds = 0u64;
+        loop {
+            sent = sent.saturating_add(window);
+            if offset < sent {
+                return rounds.saturating_mul(self.rtt_ms);
+            }
+            rounds += 1;
+            window = window.saturating_mul(2);
+        }
+    }
+}
+
+impl ChunkPacing {
+    /// Chunk size for a body of `total_size` bytes sent over `transfer_duration_ms`
+    ///
+    /// Without a fixed size, a chunk holds what the recorded rate delivers in one
+    /// resolution interval, so slow transfers stream in small, frequent pieces instead of
+    /// one 64KB burst every half second.
+    pub fn chunk_size_for(&self, total_size: usize, transfer_duration_ms: u64) -> usize {
+        if let Some(chunk_size) = self.chunk_size {
+            return chunk_size.max(1);
+        }
+        let bytes_per_ms = total_size as f64 / transfer_duration_ms.max(1) as f64;
+        let derived = (bytes_per_ms * self.resolution_ms.max(1) as f64) as usize;
+        derived.clamp(MIN_CHUNK_SIZE, CHUNK_SIZE)
+    }
+
+    /// When the chunk at `offset` of a `total_size` body sent over `duration_ms` is due
+    ///
+    /// Chunks follow the recorded rate, computed from the offset so rounding does not
+    /// accumulate over many small chunks, or with `timings` recorded, the recorded
+    /// arrival of their first byte, but never run ahead of slow start.
+    pub fn target_time(
+        &self,
+        offset: u64,
+        total_size: u64,
+        duration_ms: u64,
+        timings: Option<&[[u64; 2]]>,
+    ) -> u64 {
+        let paced = timings
+            .and_then(|timings| arrival_ms(timings, offset, total_size, duration_ms))
+            .unwrap_or_else(|| {
+                (offset as u128 * duration_ms as u128 / total_size.max(1) as u128) as u64
+            });
+        match &self.slow_start {
+            Some(slow_start) => paced.max(slow_start.ready_ms(offset)),
+            None => paced,
+        }
+    }
+
+    /// When a `total_size` body sent over `duration_ms` is complete
+    pub fn close_time(&self, total_size: u64, duration_ms: u64) -> u64 {
+        match &self.slow_start {
+            Some(slow_start) if total_size > 0 => {
+                duration_ms.max(slow_start.ready_ms(total_size - 1))
+            }
+            _ => duration_ms,
+        }
+    }
+}
 
+/// When the byte at `offset` of a `total_size` body arrived according to its recorded
+/// `chunkTimings`, stretched to a transfer of `duration_ms`
+///
+/// The body may have been transferred compressed, or edited since, so offsets are
+/// compared as fractions of the size. A byte is due with the point it arrived by, so a
+/// stall in the recorded transfer is replayed as a stall.
+fn arrival_ms(timings: &[[u64; 2]], offset: u64, total_size: u64, duration_ms: u64) -> Option<u64> {
+    let &[recorded_size, recorded_ms] = timings.last()?;
+    if recorded_size == 0 {
+        return None;
+    }
+    let recorded_offset =
+        (offset as u128 * recorded_size as u128 / total_size.max(1) as u128) as u64;
+    let index = timings.partition_point(|[bytes, _]| *bytes <= recorded_offset);
+    let ms = timings.get(index).map_or(recorded_ms, |[_, ms]| *ms);
+    Some((ms as u128 * duration_ms as u128 / recorded_ms.max(1) as u128) as u64)
+}
+
+/// Which transactions are materialized in memory before the proxy starts
+#[derive(Debug, Clone, Default)]
+pub enum PreloadPolicy {
+    /// Load every body on first use
+    #[default]
+    None,
+    /// Decompress, transform and compress every body up front
+    All,
+    /// Preload only resources whose URL matches one of the patterns
+    Matching(Vec<Regex>),
+    /// Preload the resources flagged at their index in the inventory
+    Resources(Vec<bool>),
+}
+
+impl PreloadPolicy {
+    pub fn should_preload(&self, index: usize, url: &str) -> bool {
+        match self {
+            PreloadPolicy::None => false,
+            PreloadPolicy::All => true,
+            PreloadPolicy::Matching(patterns) => patterns.iter().any(|p| p.is_match(url)),
+            PreloadPolicy::Resources(flags) => flags.get(index).copied().unwrap_or(false),
+        }
+    }
+}
+
+/// Build every transaction with its body in memory
+#[allow(dead_code)]
 pub async fn convert_resources_to_transactions<F: FileSystem>(
     inventory: &Inventory,
     inventory_dir: &Path,
     file_system: Arc<F>,
+) -> Result<Vec<Transaction>> {
+    prepare_transactions(
+        inventory,
+        inventory_dir,
+        file_system,
+        &PreloadPolicy::All,
+        &ChunkPacing::default(),
+        None,
+    )
+    .await
+}
+
+/// Build the transaction list, preloading bodies according to `policy`
+///
+/// Transactions that are not preloaded only carry their metadata; their body is
+/// produced by `TransactionLoader` when first requested. Preloaded bodies go through
+/// `transform` first.
+pub async fn prepare_transactions<F: FileSystem>(
+    inventory: &Inventory,
+    inventory_dir: &Path,
+    file_system: Arc<F>,
+    policy: &PreloadPolicy,
+    pacing: &ChunkPacing,
+    transform: Option<&TransformCommand>,
 ) -> Result<Vec<Transaction>> {
     let mut transactions = Vec::new();
 
-    for resource in &inventory.resources {
-        if let Some(transaction) =
-            convert_resource_to_transaction(resource, inventory_dir, file_system.clone()).await?
-        {
+    for (index, resource) in inventory.resources.iter().enumerate() {
+        let transaction = if policy.should_preload(index, &resource.url) {
+            load_transaction(
+                resource,
+                inventory_dir,
+                file_system.clone(),
+                pacing,
+                transform,
+            )
+            .await?
+        } else {
+            create_lazy_transaction(resource, inventory_dir, file_system.as_ref()).await?
+        };
+
+        if let Some(mut transaction) = transaction {
+            transaction.resource_index = index;
             transactions.push(transaction);
         }
     }
@@ -26,39 +232,242 @@ pub async fn convert_resources_to_transactions<F: FileSystem>(
     Ok(transactions)
 }
 
+/// Create a metadata-only transaction, or None when the resource has no content
+async fn create_lazy_transaction<F: FileSystem>(
+    resource: &Resource,
+    inventory_dir: &Path,
+    file_system: &F,
+) -> Result<Option<Transaction>> {
+    let file_exists = match &resource.content_file_path {
+        Some(file_path) => file_system.exists(&inventory_dir.join(file_path)).await,
+        None => false,
+    };
+    if !file_exists && resource.content_base64.is_none() && resource.content_utf8.is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some(Transaction {
+        method: resource.method.clone(),
+        url: resource.url.clone(),
+        ttfb: resource.ttfb_ms,
+        status_code: resource.status_code,
+        reason_phrase: resource.reason_phrase.clone(),
+        error_kind: resource.error_kind,
+        error_message: resource.error_message.clone(),
+        raw_headers: resource.raw_headers.clone(),
+        chunks: Vec::new(),
+        target_close_time: 0,
+        resource_index: 0,
+        lazy: true,
+        streamed: None,
+        form_fields: resource.request_form.as_deref().map(form_signature),
+        upload: resource.upload_timing(),
+        availability: resource.availability(),
+        url_pattern: resource.url_pattern()?,
+        request_client: resource.request_client.clone(),
+        event_stream: resource.stream_end(),
+        request_body_sha1: resource.request_body_sha1.clone(),
+        match_headers: resource.match_headers.clone(),
+        byte_ranges: resource.range_map(),
+        open_ended: resource.open_ended == Some(true),
+        timed: false,
+        request_headers: resource.request_headers.clone(),
+    }))
+}
+
+/// Loads bodies of lazy transactions on demand
+pub struct TransactionLoader<F: FileSystem> {
+    resources: Vec<Resource>,
+    inventory_dir: PathBuf,
+    file_system: Arc<F>,
+    pacing: ChunkPacing,
+    cache: Option<BodyCache>,
+    transform: Option<TransformCommand>,
+}
+
+impl<F: FileSystem> TransactionLoader<F> {
+    pub fn new(resources: Vec<Resource>, inventory_dir: PathBuf, file_system: Arc<F>) -> Self {
+        Self {
+            resources,
+            inventory_dir,
+            file_system,
+            pacing: ChunkPacing::default(),
+            cache: None,
+            transform: None,
+        }
+    }
+
+    /// Split bodies loaded on demand with these settings
+    pub fn with_pacing(mut self, pacing: ChunkPacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Keep up to `max_bytes` of loaded bodies for later requests (0 disables caching)
+    pub fn with_cache(mut self, max_bytes: usize) -> Self {
+        self.cache = (max_bytes > 0).then(|| BodyCache::new(max_bytes));
+        self
+    }
+
+    /// Pass loaded bodies through `transform` (default: served as stored)
+    pub fn with_transform(mut self, transform: Option<TransformCommand>) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Return a transaction with its body in memory, loading it if necessary
+    pub async fn materialize(&self, transaction: Arc<Transaction>) -> Result<Arc<Transaction>> {
+        if !transaction.lazy {
+            return Ok(transaction);
+        }
+        if let Some(cached) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get(transaction.resource_index))
+        {
+            return Ok(cached);
+        }
+
+        let resource = self
+            .resources
+            .get(transaction.resource_index)
+            .ok_or_else(|| anyhow::anyhow!("Unknown resource for {}", transaction.url))?;
+
+        let mut loaded = load_transaction(
+            resource,
+            &self.inventory_dir,
+            self.file_system.clone(),
+            &self.pacing,
+            self.transform.as_ref(),
+        )
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Content for {} is not available", resource.url))?;
+        loaded.resource_index = transaction.resource_index;
+        let loaded = Arc::new(loaded);
+        if let Some(cache) = &self.cache {
+            cache.insert(loaded.clone());
+        }
+        Ok(loaded)
+    }
+
+    /// The `index`th body chunk, or None past the end
+    ///
+    /// Streamed bodies are read from disk here, one chunk per call, so a slow client
+    /// holds at most the chunk in flight rather than the whole file.
+    pub async fn chunk(
+        &self,
+        transaction: &Transaction,
+        index: usize,
+    ) -> Result<Option<BodyChunk>> {
+        let Some(body) = &transaction.streamed else {
+            return Ok(transaction.chunks.get(index).cloned());
+        };
+        let offset = (index as u64).saturating_mul(body.chunk_size as u64);
+        if offset >= body.len {
+            return Ok(None);
+        }
+        let len = (body.chunk_size as u64).min(body.len - offset) as usize;
+        let chunk = self
+            .file_system
+            .read_range(&body.path, body.offset + offset, len)
+            .await?;
+        if chunk.is_empty() {
+            anyhow::bail!(
+                "{} ended at {} of {} bytes",
+                body.path.display(),
+                offset,
+                body.len
+            );
+        }
+        let target_time = self.pacing.target_time(
+            offset,
+            body.len,
+            transaction.target_close_time,
+            body.timings.as_deref(),
+        );
+        Ok(Some(BodyChunk { chunk, target_time }))
+    }
+}
+
+/// Convert a resource with the default chunk pacing
+#[allow(dead_code)]
 pub async fn convert_resource_to_transaction<F: FileSystem>(
     resource: &Resource,
     inventory_dir: &Path,
     file_system: Arc<F>,
 ) -> Result<Option<Transaction>> {
-    // Load content
-    let content = if let Some(file_path) = &resource.content_file_path {
+    convert_resource_to_transaction_with_pacing(
+        resource,
+        inventory_dir,
+        file_system,
+        &ChunkPacing::default(),
+    )
+    .await
+}
+
+pub async fn convert_resource_to_transaction_with_pacing<F: FileSystem>(
+    resource: &Resource,
+    inventory_dir: &Path,
+    file_system: Arc<F>,
+    pacing: &ChunkPacing,
+) -> Result<Option<Transaction>> {
+    load_transaction(resource, inventory_dir, file_system, pacing, None).await
+}
+
+/// The transaction for `resource` with its body loaded and, unless it streams from
+/// disk, passed through `transform` before it is processed for serving
+async fn load_transaction<F: FileSystem>(
+    resource: &Resource,
+    inventory_dir: &Path,
+    file_system: Arc<F>,
+    pacing: &ChunkPacing,
+    transform: Option<&TransformCommand>,
+) -> Result<Option<Transaction>> {
+    if let Some(transaction) =
+        create_streamed_transaction(resource, inventory_dir, file_system.as_ref(), pacing).await?
+    {
+        return Ok(Some(transaction));
+    }
+
+    // Load content (large files come back memory-mapped, without a copy)
+    let content: Bytes = if let Some(file_path) = &resource.content_file_path {
         // file_path is now relative to inventory_dir (includes "contents/" prefix)
         let full_path = inventory_dir.join(file_path);
         if file_system.exists(&full_path).await {
-            file_system.read(&full_path).await?
+            file_system.read_bytes(&full_path).await?
         } else if let Some(base64_content) = &resource.content_base64 {
             use base64::{Engine as _, engine::general_purpose};
-            general_purpose::STANDARD.decode(base64_content)?
+            Bytes::from(general_purpose::STANDARD.decode(base64_content)?)
         } else if let Some(utf8_content) = &resource.content_utf8 {
-            utf8_content.as_bytes().to_vec()
+            Bytes::copy_from_slice(utf8_content.as_bytes())
         } else {
             return Ok(None);
         }
     } else if let Some(base64_content) = &resource.content_base64 {
         use base64::{Engine as _, engine::general_purpose};
-        general_purpose::STANDARD.decode(base64_content)?
+        Bytes::from(general_purpose::STANDARD.decode(base64_content)?)
     } else if let Some(utf8_content) = &resource.content_utf8 {
-        utf8_content.as_bytes().to_vec()
+        Bytes::copy_from_slice(utf8_content.as_bytes())
     } else {
         return Ok(None);
     };
 
+    // A transformed body is new content, which the recorded size says nothing about
+    let transformed = transform.is_some();
+    let content = match transform {
+        Some(transform) => transform.apply(resource, content).await?,
+        None => content,
+    };
+
     // Process content based on minify flag
     // If minification fails, log warning and use original content
+    let mut minified = false;
     let mut processed_content = if resource.minify.unwrap_or(false) {
-        match minify_content(&content, &resource.content_type_mime) {
-            Ok(minified) => minified,
+        match minify_content(&content, &resource.mime_type().map(str::to_string)) {
+            Ok(content) => {
+                minified = true;
+                Bytes::from(content)
+            }
             Err(e) => {
                 tracing::warn!(
                     "Failed to minify content for {}: {}. Using original content.",
@@ -74,10 +483,10 @@ pub async fn convert_resource_to_transaction<F: FileSystem>(
 
     // Re-encode to original charset if this is a text resource with content_charset
     // If re-encoding fails, log warning and keep UTF-8 content
-    if let Some(charset) = &resource.content_charset {
+    if let Some(charset) = resource.reencode_charset() {
         match re_encode_to_charset(&processed_content, charset) {
             Ok(reencoded) => {
-                processed_content = reencoded;
+                processed_content = Bytes::from(reencoded);
             }
             Err(e) => {
                 tracing::warn!(
@@ -91,23 +500,53 @@ pub async fn convert_resource_to_transaction<F: FileSystem>(
         }
     }
 
-    // Compress content if needed
-    let final_content = if let Some(encoding) = &resource.content_encoding {
-        compress_content(&processed_content, encoding)?
-    } else {
-        processed_content
+    // Compress content if needed (identity bodies are served as loaded)
+    let final_content = match &resource.content_encoding {
+        Some(
+            encoding @ (ContentEncodingType::Gzip
+            | ContentEncodingType::Deflate
+            | ContentEncodingType::Br),
+        ) => Bytes::from(compress_content(&processed_content, encoding)?),
+        _ if minified && !transformed => pad_to_original_size(processed_content, resource),
+        _ => processed_content,
     };
+    if let Some(original_size) = resource
+        .original_size
+        .filter(|&size| size != final_content.len() as u64)
+    {
+        tracing::debug!(
+            "{} replays {} bytes, recorded with {}",
+            resource.url,
+            final_content.len(),
+            original_size
+        );
+    }
 
     // Create chunks and calculate target_close_time
-    let (chunks, target_close_time) = create_chunks(&final_content, resource)?;
+    let content_length = final_content.len();
+    let (chunks, target_close_time) = match &resource.event_stream {
+        Some(events) => create_event_chunks(final_content, events, resource),
+        None => create_paced_chunks(final_content, resource, pacing)?,
+    };
 
     let mut headers = resource.raw_headers.clone().unwrap_or_default();
 
-    // Update content-length
-    headers.insert(
-        "content-length".to_string(),
-        crate::types::HeaderValue::Single(final_content.len().to_string()),
-    );
+    // Update content-length (in place, so header order is unchanged)
+    // Event streams and bodies that never ended have no length; they are sent chunked.
+    // A body that broke off keeps the recorded length it never reached.
+    if resource.event_stream.is_some() || resource.open_ended == Some(true) {
+        headers.remove("content-length");
+    } else if resource.error_kind != Some(ErrorKind::TruncatedBody) {
+        headers.set(
+            "content-length",
+            crate::types::HeaderValue::Single(content_length.to_string()),
+        );
+    }
+
+    // hyper's server cannot send 1xx responses, so recorded 103 hints ride on the final one
+    if let Some(hints) = &resource.early_hints {
+        merge_early_hint_links(&mut headers, hints);
+    }
 
     // NOTE: We do NOT modify Content-Type header here.
     // The original Content-Type from raw_headers is preserved exactly as recorded.
@@ -119,22 +558,193 @@ pub async fn convert_resource_to_transaction<F: FileSystem>(
         url: resource.url.clone(),
         ttfb: resource.ttfb_ms,
         status_code: resource.status_code,
+        reason_phrase: resource.reason_phrase.clone(),
+        error_kind: resource.error_kind,
         error_message: resource.error_message.clone(),
         raw_headers: Some(headers),
         chunks,
         target_close_time,
+        resource_index: 0,
+        lazy: false,
+        streamed: None,
+        form_fields: resource.request_form.as_deref().map(form_signature),
+        upload: resource.upload_timing(),
+        availability: resource.availability(),
+        url_pattern: resource.url_pattern()?,
+        request_client: resource.request_client.clone(),
+        event_stream: resource.stream_end(),
+        request_body_sha1: resource.request_body_sha1.clone(),
+        match_headers: resource.match_headers.clone(),
+        byte_ranges: resource.range_map(),
+        open_ended: resource.open_ended == Some(true),
+        timed: resource.event_stream.is_none() && recorded_timings(resource).is_some(),
+        request_headers: resource.request_headers.clone(),
     }))
 }
 
-pub fn create_chunks(content: &[u8], resource: &Resource) -> Result<(Vec<BodyChunk>, u64)> {
-    let mut chunks = Vec::new();
-    let total_size = content.len();
+/// Pad an uncompressed text body that minifying made smaller than recorded with
+/// trailing spaces, so the replayed Content-Length equals the recorded one
+///
+/// Only called for bodies playback minified itself: a content file that was edited,
+/// overridden or transformed is served at its own size. Whitespace at the end of HTML,
+/// CSS and JavaScript has no effect. Bodies that grew, or are not text, are left as
+/// they are.
+pub fn pad_to_original_size(content: Bytes, resource: &Resource) -> Bytes {
+    let is_text = resource.mime_type().is_some_and(is_text_resource);
+    match resource.original_size {
+        Some(original_size) if is_text && (content.len() as u64) < original_size => {
+            let mut padded = Vec::with_capacity(original_size as usize);
+            padded.extend_from_slice(&content);
+            padded.resize(original_size as usize, b' ');
+            Bytes::from(padded)
+        }
+        _ => content,
+    }
+}
 
-    if total_size == 0 {
-        // If no content, close time is 0 (TTFB is handled separately in serve_transaction)
-        return Ok((chunks, 0));
+/// Whether a content file is served byte for byte: no minifying, charset or compression
+pub fn is_served_verbatim(resource: &Resource) -> bool {
+    !(resource.minify.unwrap_or(false)
+        || resource.reencode_charset().is_some()
+        || resource.event_stream.is_some()
+        || matches!(
+            resource.content_encoding,
+            Some(
+                ContentEncodingType::Gzip | ContentEncodingType::Deflate | ContentEncodingType::Br
+            )
+        ))
+}
+
+/// A transaction that reads its body from disk while sending, for large content files
+/// served byte for byte
+///
+/// None when the body needs minifying, re-encoding or compressing, is a ranged object or
+/// open-ended, or the file is missing or below `pacing.stream_threshold`; those bodies
+/// are loaded into memory.
+async fn create_streamed_transaction<F: FileSystem>(
+    resource: &Resource,
+    inventory_dir: &Path,
+    file_system: &F,
+    pacing: &ChunkPacing,
+) -> Result<Option<Transaction>> {
+    let Some(file_path) = &resource.content_file_path else {
+        return Ok(None);
+    };
+    let full_path = inventory_dir.join(file_path);
+    // Ranged objects are answered in slices of the loaded body, and bodies that never
+    // ended or broke off are not sent with their own length
+    if !is_served_verbatim(resource)
+        || resource.byte_ranges.is_some()
+        || resource.open_ended == Some(true)
+        || resource.error_kind.is_some()
+        || !file_system.exists(&full_path).await
+    {
+        return Ok(None);
+    }
+    let len = file_system.file_len(&full_path).await?;
+    if len == 0 || len < pacing.stream_threshold {
+        return Ok(None);
+    }
+
+    let transfer_duration_ms = transfer_duration_ms(resource, len);
+    let chunk_size = pacing.chunk_size_for(len as usize, transfer_duration_ms);
+
+    let mut headers = resource.raw_headers.clone().unwrap_or_default();
+    headers.set("content-length", HeaderValue::Single(len.to_string()));
+    if let Some(hints) = &resource.early_hints {
+        merge_early_hint_links(&mut headers, hints);
+    }
+
+    Ok(Some(Transaction {
+        method: resource.method.clone(),
+        url: resource.url.clone(),
+        ttfb: resource.ttfb_ms,
+        status_code: resource.status_code,
+        reason_phrase: resource.reason_phrase.clone(),
+        error_kind: resource.error_kind,
+        error_message: resource.error_message.clone(),
+        raw_headers: Some(headers),
+        chunks: Vec::new(),
+        target_close_time: pacing.close_time(len, transfer_duration_ms),
+        resource_index: 0,
+        lazy: false,
+        streamed: Some(StreamedBody {
+            path: full_path,
+            offset: 0,
+            len,
+            chunk_size,
+            timings: recorded_timings(resource).map(<[_]>::to_vec),
+        }),
+        form_fields: resource.request_form.as_deref().map(form_signature),
+        upload: resource.upload_timing(),
+        availability: resource.availability(),
+        url_pattern: resource.url_pattern()?,
+        request_client: resource.request_client.clone(),
+        event_stream: resource.stream_end(),
+        request_body_sha1: resource.request_body_sha1.clone(),
+        match_headers: resource.match_headers.clone(),
+        byte_ranges: resource.range_map(),
+        open_ended: resource.open_ended == Some(true),
+        timed: recorded_timings(resource).is_some(),
+        request_headers: resource.request_headers.clone(),
+    }))
+}
+
+/// Add Link headers from 103 Early Hints that the final response does not repeat
+///
+/// Origins usually send the same preloads again on the final response; only the missing
+/// ones are appended so the browser sees every hint it got from the origin.
+pub fn merge_early_hint_links(headers: &mut HttpHeaders, hints: &[EarlyHint]) {
+    let header_values = |value: Option<&HeaderValue>| -> Vec<String> {
+        match value {
+            Some(HeaderValue::Single(v)) => vec![v.clone()],
+            Some(HeaderValue::Multiple(vs)) => vs.clone(),
+            None => Vec::new(),
+        }
+    };
+
+    let mut links = header_values(headers.get("link"));
+    let original_count = links.len();
+    for hint in hints {
+        for link in header_values(hint.headers.get("link")) {
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
     }
 
+    if links.len() > original_count {
+        let value = if links.len() == 1 {
+            HeaderValue::Single(links.remove(0))
+        } else {
+            HeaderValue::Multiple(links)
+        };
+        headers.set("link", value);
+    }
+}
+
+/// Split a copy of `content` into timed chunks
+#[allow(dead_code)]
+pub fn create_chunks(content: &[u8], resource: &Resource) -> Result<(Vec<BodyChunk>, u64)> {
+    create_chunks_from_bytes(Bytes::copy_from_slice(content), resource)
+}
+
+/// Split shared content into timed chunks; chunks are slices of `content`, not copies
+pub fn create_chunks_from_bytes(
+    content: Bytes,
+    resource: &Resource,
+) -> Result<(Vec<BodyChunk>, u64)> {
+    create_paced_chunks(content, resource, &ChunkPacing::default())
+}
+
+/// The recorded `chunkTimings` of a resource, unless its transfer time was replaced by a
+/// bandwidth (a network or host profile)
+fn recorded_timings(resource: &Resource) -> Option<&[[u64; 2]]> {
+    resource.duration_ms.and(resource.chunk_timings.as_deref())
+}
+
+/// How long sending a body of `total_size` bytes takes, at least 1ms
+fn transfer_duration_ms(resource: &Resource, total_size: u64) -> u64 {
     // Use actual recorded transfer duration (duration_ms)
     // This ensures we reproduce the exact timing from the recording
     let transfer_duration_ms = if let Some(duration_ms) = resource.duration_ms {
@@ -147,106 +757,156 @@ pub fn create_chunks(content: &[u8], resource: &Resource) -> Result<(Vec<BodyChu
     };
 
     // If transfer duration is 0, make it at least 1ms to avoid division by zero
-    let transfer_duration_ms = std::cmp::max(1, transfer_duration_ms);
+    std::cmp::max(1, transfer_duration_ms)
+}
+
+/// Split shared content into chunks sized by `pacing`
+pub fn create_paced_chunks(
+    content: Bytes,
+    resource: &Resource,
+    pacing: &ChunkPacing,
+) -> Result<(Vec<BodyChunk>, u64)> {
+    let mut chunks = Vec::new();
+    let total_size = content.len();
+
+    if total_size == 0 {
+        // If no content, close time is 0 (TTFB is handled separately in serve_transaction)
+        return Ok((chunks, 0));
+    }
 
+    let transfer_duration_ms = transfer_duration_ms(resource, total_size as u64);
+    let max_chunk_size = pacing.chunk_size_for(total_size, transfer_duration_ms);
+    let timings = recorded_timings(resource);
     let mut offset = 0;
-    // Start at 0 - chunks are relative times from TTFB (TTFB is waited separately in proxy.rs)
-    let mut current_time = 0u64;
 
     while offset < total_size {
-        let chunk_size = std::cmp::min(CHUNK_SIZE, total_size - offset);
-        let chunk_data = content[offset..offset + chunk_size].to_vec();
-
+        let chunk_size = std::cmp::min(max_chunk_size, total_size - offset);
+        let chunk_data = content.slice(offset..offset + chunk_size);
+
+        // Each chunk is due when the bytes before it would have arrived at the recorded
+        // rate, or when its first byte arrived if that was recorded. Times are relative
+        // to TTFB (TTFB is waited separately in proxy.rs).
+        let target_time = pacing.target_time(
+            offset as u64,
+            total_size as u64,
+            transfer_duration_ms,
+            timings,
+        );
         chunks.push(BodyChunk {
             chunk: chunk_data,
-            target_time: current_time,
+            target_time,
         });
 
-        // Calculate time for next chunk based on proportional distribution
-        // Each chunk gets its share of the total transfer time based on its size
-        let chunk_duration_ms =
-            ((chunk_size as f64 / total_size as f64) * transfer_duration_ms as f64) as u64;
-        current_time += chunk_duration_ms;
         offset += chunk_size;
     }
 
     // target_close_time is the total transfer duration (relative to TTFB completion)
-    let target_close_time = transfer_duration_ms;
+    let target_close_time = pacing.close_time(total_size as u64, transfer_duration_ms);
 
     Ok((chunks, target_close_time))
 }
 
+/// Split an event-stream body into one chunk per recorded event, each due when the
+/// event arrived
+///
+/// Bytes beyond the recorded events, as in a body edited after recording, go with the
+/// last event. The stream closes at its recorded duration.
+pub fn create_event_chunks(
+    content: Bytes,
+    events: &[StreamEvent],
+    resource: &Resource,
+) -> (Vec<BodyChunk>, u64) {
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    for (n, event) in events.iter().enumerate() {
+        let end = if n + 1 == events.len() {
+            content.len()
+        } else {
+            (offset + event.size as usize).min(content.len())
+        };
+        if end > offset {
+            chunks.push(BodyChunk {
+                chunk: content.slice(offset..end),
+                target_time: event.offset_ms,
+            });
+        }
+        offset = end;
+    }
+    if offset < content.len() {
+        chunks.push(BodyChunk {
+            chunk: content.slice(offset..),
+            target_time: 0,
+        });
+    }
+
+    let last_event = chunks.last().map_or(0, |c| c.target_time);
+    (chunks, resource.duration_ms.unwrap_or(0).max(last_event))
+}
+
+/// Whether the body is sent compressed, so it cannot be rewritten as text
+pub fn has_content_encoding(transaction: &Transaction) -> bool {
+    transaction
+        .raw_headers
+        .as_ref()
+        .and_then(|headers| headers.get("content-encoding"))
+        .is_some_and(|v| {
+            !v.as_vec()
+                .iter()
+                .all(|e| e.eq_ignore_ascii_case("identity"))
+        })
+}
+
+/// A pattern resource's body with its placeholders filled in for the request, None
+/// when it is not a UTF-8 text template
+pub fn render_template(transaction: &Transaction, method: &str, url: &str) -> Option<Transaction> {
+    let pattern = transaction.url_pattern.as_ref()?;
+    if transaction.streamed.is_some() || has_content_encoding(transaction) {
+        return None;
+    }
+    let body: Vec<u8> = transaction
+        .chunks
+        .iter()
+        .flat_map(|c| c.chunk.iter().copied())
+        .collect();
+    let rendered = pattern.render(std::str::from_utf8(&body).ok()?, method, url)?;
+    Some(with_body(transaction, Bytes::from(rendered)))
+}
+
+/// A copy of `transaction` sending `body` with the same chunk size and schedule
+pub fn with_body(transaction: &Transaction, body: Bytes) -> Transaction {
+    let chunk_size = transaction
+        .chunks
+        .first()
+        .map_or(CHUNK_SIZE, |c| c.chunk.len())
+        .max(1);
+    let total = body.len().max(1);
+    let close = transaction.target_close_time;
+
+    let mut rewritten = transaction.clone();
+    rewritten.chunks = (0..body.len())
+        .step_by(chunk_size)
+        .map(|offset| BodyChunk {
+            chunk: body.slice(offset..(offset + chunk_size).min(body.len())),
+            target_time: (offset as u128 * close as u128 / total as u128) as u64,
+        })
+        .collect();
+    if let Some(headers) = &mut rewritten.raw_headers {
+        headers.set(
+            "content-length",
+            HeaderValue::Single(body.len().to_string()),
+        );
+    }
+    rewritten
+}
+
 pub fn minify_content(content: &[u8], mime_type: &Option<String>) -> Result<Vec<u8>> {
     let content_str = String::from_utf8_lossy(content);
 
     let minified = match mime_type.as_deref() {
-        Some("text/html") => {
-            // Simple HTML minification - remove extra whitespace
-            let mut result = String::new();
-            let mut in_tag = false;
-            let mut prev_was_space = false;
-
-            for ch in content_str.chars() {
-                match ch {
-                    '<' => {
-                        in_tag = true;
-                        result.push(ch);
-                        prev_was_space = false;
-                    }
-                    '>' => {
-                        in_tag = false;
-                        result.push(ch);
-                        prev_was_space = false;
-                    }
-                    '\n' | '\r' | '\t' | ' ' => {
-                        if !in_tag && !prev_was_space {
-                            result.push(' ');
-                            prev_was_space = true;
-                        } else if in_tag {
-                            result.push(ch);
-                        }
-                    }
-                    _ => {
-                        result.push(ch);
-                        prev_was_space = false;
-                    }
-                }
-            }
-            result
-        }
-        Some("text/css") => {
-            // Simple CSS minification - preserve @charset at the beginning
-            let mut lines: Vec<&str> = content_str
-                .lines()
-                .map(|line| line.trim())
-                .filter(|line| !line.is_empty())
-                .collect();
-
-            // Extract @charset if it exists (must be first line per CSS spec)
-            let charset_line = if !lines.is_empty() && lines[0].starts_with("@charset") {
-                Some(lines.remove(0))
-            } else {
-                None
-            };
-
-            // Minify the rest (join without separators)
-            let minified_body = lines.join("");
-
-            // Prepend @charset if it existed (must be first, separated by newline)
-            if let Some(charset) = charset_line {
-                format!("{}\n{}", charset, minified_body)
-            } else {
-                minified_body
-            }
-        }
+        Some("text/html") => crate::minify::minify_html(&content_str)?,
+        Some("text/css") => crate::minify::minify_css(&content_str)?,
         Some("application/javascript") | Some("text/javascript") => {
-            // Simple JS minification - remove extra whitespace and newlines
-            content_str
-                .lines()
-                .map(|line| line.trim())
-                .filter(|line| !line.is_empty() && !line.starts_with("//"))
-                .collect::<Vec<_>>()
-                .join("")
+            crate::minify::minify_javascript(&content_str)?
         }
         _ => content_str.to_string(),
     };
@@ -254,8 +914,6 @@ pub fn minify_content(content: &[u8], mime_type: &Option<String>) -> Result<Vec<
     Ok(minified.into_bytes())
 }
 
-use crate::types::ContentEncodingType;
-
 pub fn compress_content(content: &[u8], encoding: &ContentEncodingType) -> Result<Vec<u8>> {
     match encoding {
         ContentEncodingType::Gzip => {
@@ -289,6 +947,26 @@ pub fn compress_content(content: &[u8], encoding: &ContentEncodingType) -> Resul
     }
 }
 
+/// Inverse of `compress_content`
+pub fn decompress_content(content: &[u8], encoding: &ContentEncodingType) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut decompressed = Vec::new();
+    match encoding {
+        ContentEncodingType::Gzip => {
+            flate2::read::GzDecoder::new(content).read_to_end(&mut decompressed)?;
+        }
+        ContentEncodingType::Deflate => {
+            flate2::read::DeflateDecoder::new(content).read_to_end(&mut decompressed)?;
+        }
+        ContentEncodingType::Br => {
+            brotli::BrotliDecompress(&mut std::io::Cursor::new(content), &mut decompressed)?;
+        }
+        _ => decompressed = content.to_vec(),
+    }
+    Ok(decompressed)
+}
+
 pub fn re_encode_to_charset(content: &[u8], charset_name: &str) -> Result<Vec<u8>> {
     // File content is stored as UTF-8, convert it back to original charset
     // Use from_utf8_lossy to handle any invalid UTF-8 sequences that may have been introduced
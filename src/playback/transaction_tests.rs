@@ -1,9 +1,12 @@
 #[cfg(test)]
 mod tests {
     use crate::playback::transaction::*;
+    use crate::traits::FileSystem;
     use crate::traits::mocks::MockFileSystem;
-    use crate::types::{ContentEncodingType, Inventory, Resource};
+    use crate::types::{ContentEncodingType, Inventory, Resource, Transaction};
+    use std::path::Path;
     use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use tempfile::TempDir;
 
     #[tokio::test]
@@ -294,10 +297,9 @@ mod tests {
         resource.ttfb_ms = 100;
 
         // Set raw_headers with Content-Type that includes charset
-        use std::collections::HashMap;
-        let mut raw_headers = HashMap::new();
-        raw_headers.insert(
-            "content-type".to_string(),
+        let mut raw_headers = crate::types::HttpHeaders::new();
+        raw_headers.set(
+            "content-type",
             crate::types::HeaderValue::Single("text/html; charset=Shift_JIS".to_string()),
         );
         resource.raw_headers = Some(raw_headers);
@@ -329,4 +331,498 @@ mod tests {
         let content_type_str = content_type.first();
         assert!(content_type_str.contains("Shift_JIS"));
     }
+
+    #[tokio::test]
+    async fn test_prepare_transactions_lazy_and_materialize() {
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        let mock_fs = Arc::new(MockFileSystem::new());
+
+        let mut inventory = Inventory::new();
+        let mut resource =
+            Resource::new("GET".to_string(), "https://example.com/app.js".to_string());
+        resource.content_utf8 = Some("console.log(1);".to_string());
+        resource.status_code = Some(200);
+        resource.ttfb_ms = 20;
+        inventory.resources.push(resource);
+        // No content at all: skipped in both modes
+        inventory.resources.push(Resource::new(
+            "GET".to_string(),
+            "https://example.com/missing".to_string(),
+        ));
+
+        let transactions = prepare_transactions(
+            &inventory,
+            &inventory_dir,
+            mock_fs.clone(),
+            &PreloadPolicy::None,
+            &ChunkPacing::default(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transactions.len(), 1);
+        assert!(transactions[0].lazy);
+        assert!(transactions[0].chunks.is_empty());
+        assert_eq!(transactions[0].resource_index, 0);
+
+        let loader =
+            TransactionLoader::new(inventory.resources.clone(), inventory_dir.clone(), mock_fs);
+        let loaded = loader
+            .materialize(Arc::new(transactions[0].clone()))
+            .await
+            .unwrap();
+        assert!(!loaded.lazy);
+        assert_eq!(loaded.body_size(), "console.log(1);".len());
+    }
+
+    #[tokio::test]
+    async fn test_materialize_caches_least_recently_used() {
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        let mut inventory = Inventory::new();
+        for name in ["a", "b", "c"] {
+            let mut resource = Resource::new(
+                "GET".to_string(),
+                format!("https://example.com/{}.js", name),
+            );
+            resource.content_utf8 = Some("x".repeat(100));
+            inventory.resources.push(resource);
+        }
+        let transactions: Vec<Arc<Transaction>> = prepare_transactions(
+            &inventory,
+            &inventory_dir,
+            Arc::new(MockFileSystem::new()),
+            &PreloadPolicy::None,
+            &ChunkPacing::default(),
+            None,
+        )
+        .await
+        .unwrap()
+        .into_iter()
+        .map(Arc::new)
+        .collect();
+
+        // Room for two of the three bodies
+        let loader = TransactionLoader::new(
+            inventory.resources.clone(),
+            inventory_dir,
+            Arc::new(MockFileSystem::new()),
+        )
+        .with_cache(250);
+        let a = loader.materialize(transactions[0].clone()).await.unwrap();
+        let b = loader.materialize(transactions[1].clone()).await.unwrap();
+        assert!(Arc::ptr_eq(
+            &a,
+            &loader.materialize(transactions[0].clone()).await.unwrap()
+        ));
+
+        // Loading c evicts b, used less recently than a
+        loader.materialize(transactions[2].clone()).await.unwrap();
+        assert!(Arc::ptr_eq(
+            &a,
+            &loader.materialize(transactions[0].clone()).await.unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &b,
+            &loader.materialize(transactions[1].clone()).await.unwrap()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_prepare_transactions_preload_matching() {
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        let mock_fs = Arc::new(MockFileSystem::new());
+
+        let mut inventory = Inventory::new();
+        for url in ["https://example.com/", "https://example.com/image.png"] {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            resource.content_utf8 = Some("body".to_string());
+            inventory.resources.push(resource);
+        }
+
+        let policy = PreloadPolicy::Matching(vec![regex::Regex::new(r"/$").unwrap()]);
+        let transactions = prepare_transactions(
+            &inventory,
+            &inventory_dir,
+            mock_fs,
+            &policy,
+            &ChunkPacing::default(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transactions.len(), 2);
+        assert!(!transactions[0].lazy);
+        assert_eq!(transactions[0].body_size(), 4);
+        assert!(transactions[1].lazy);
+        assert_eq!(transactions[1].resource_index, 1);
+    }
+
+    #[test]
+    fn test_create_chunks_from_bytes_slices_without_copy() {
+        let mut resource = Resource::new("GET".to_string(), "https://example.com/big".to_string());
+        resource.duration_ms = Some(1000);
+
+        let content = bytes::Bytes::from(vec![7u8; 200 * 1024]);
+        let (chunks, _) = create_chunks_from_bytes(content.clone(), &resource).unwrap();
+
+        assert!(chunks.len() > 1);
+        // Each chunk points into the original buffer
+        let mut offset = 0;
+        for chunk in &chunks {
+            assert_eq!(chunk.chunk.as_ptr(), content[offset..].as_ptr());
+            offset += chunk.chunk.len();
+        }
+        assert_eq!(offset, content.len());
+    }
+
+    #[test]
+    fn test_chunk_pacing_derives_size_from_rate() {
+        let pacing = ChunkPacing::default();
+
+        // 1 Mbps = 125 bytes/ms -> 50ms worth of data per chunk
+        assert_eq!(pacing.chunk_size_for(125_000, 1000), 6250);
+        // Fast transfers are capped at 64KB, very slow ones floored at 1KB
+        assert_eq!(pacing.chunk_size_for(10_000_000, 100), 64 * 1024);
+        assert_eq!(pacing.chunk_size_for(1000, 10_000), 1024);
+
+        let fixed = ChunkPacing {
+            chunk_size: Some(4096),
+            ..ChunkPacing::default()
+        };
+        assert_eq!(fixed.chunk_size_for(125_000, 1000), 4096);
+    }
+
+    #[tokio::test]
+    async fn test_minified_text_is_padded_to_recorded_size() {
+        let inventory_dir = Path::new("/inventory");
+        let fs = Arc::new(MockFileSystem::new());
+        // Beautified on disk; minifying makes it smaller than the 40 bytes recorded
+        fs.set_file(
+            "/inventory/contents/app.css",
+            b"body {\n  margin: 0;\n}\n\nh1 {\n  color: red;\n}\n".to_vec(),
+        );
+
+        let mut resource =
+            Resource::new("GET".to_string(), "https://example.com/app.css".to_string());
+        resource.content_file_path = Some("contents/app.css".to_string());
+        resource.content_type_mime = Some("text/css".to_string());
+        resource.minify = Some(true);
+        resource.original_size = Some(40);
+
+        let transaction = convert_resource_to_transaction(&resource, inventory_dir, fs.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction.body_size(), 40);
+        assert_eq!(
+            transaction.raw_headers.unwrap().get("content-length"),
+            Some(&crate::types::HeaderValue::Single("40".to_string()))
+        );
+
+        // An edited content file that is not minified is served at its own size
+        let mut edited = resource.clone();
+        edited.minify = None;
+        let transaction = convert_resource_to_transaction(&edited, inventory_dir, fs.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction.body_size(), 44);
+
+        // Binary bodies are never padded
+        let mut image = resource.clone();
+        image.content_type_mime = Some("image/png".to_string());
+        image.minify = None;
+        image.original_size = Some(1000);
+        let transaction = convert_resource_to_transaction(&image, inventory_dir, fs)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(transaction.body_size() < 1000);
+    }
+
+    #[test]
+    fn test_create_paced_chunks_spreads_slow_transfer() {
+        let mut resource = Resource::new("GET".to_string(), "https://example.com/slow".to_string());
+        resource.duration_ms = Some(1000);
+
+        let content = bytes::Bytes::from(vec![0u8; 125_000]);
+        let pacing = ChunkPacing {
+            chunk_size: None,
+            resolution_ms: 100,
+            ..ChunkPacing::default()
+        };
+        let (chunks, target_close_time) = create_paced_chunks(content, &resource, &pacing).unwrap();
+
+        assert_eq!(chunks.len(), 10);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.chunk.len(), 12_500);
+            assert_eq!(chunk.target_time, i as u64 * 100);
+        }
+        assert_eq!(target_close_time, 1000);
+    }
+
+    #[test]
+    fn test_slow_start_ramps_fast_transfer() {
+        let mut resource =
+            Resource::new("GET".to_string(), "https://example.com/app.js".to_string());
+        resource.duration_ms = Some(10);
+
+        // 10 segments of 1460 bytes, doubling every 100ms round trip
+        let slow_start = SlowStart::new(10, 100);
+        assert_eq!(slow_start.ready_ms(0), 0);
+        assert_eq!(slow_start.ready_ms(14_599), 0);
+        assert_eq!(slow_start.ready_ms(14_600), 100);
+        assert_eq!(slow_start.ready_ms(43_800), 200);
+
+        let content = bytes::Bytes::from(vec![0u8; 102_200]);
+        let pacing = ChunkPacing {
+            chunk_size: Some(14_600),
+            slow_start: Some(slow_start),
+            ..ChunkPacing::default()
+        };
+        let (chunks, target_close_time) = create_paced_chunks(content, &resource, &pacing).unwrap();
+
+        let times: Vec<u64> = chunks.iter().map(|c| c.target_time).collect();
+        assert_eq!(times, vec![0, 100, 100, 200, 200, 200, 200]);
+        assert_eq!(target_close_time, 200);
+
+        // A body that fits the initial window keeps its recorded timing
+        let small = bytes::Bytes::from(vec![0u8; 10_000]);
+        let (_, target_close_time) = create_paced_chunks(small, &resource, &pacing).unwrap();
+        assert_eq!(target_close_time, 10);
+    }
+
+    #[tokio::test]
+    async fn test_recorded_chunk_timings_replay_stalls() {
+        let mut resource = Resource::new(
+            "GET".to_string(),
+            "https://example.com/feed.bin".to_string(),
+        );
+        resource.duration_ms = Some(2010);
+        // Transferred compressed to half its size, with a 2s stall after 25KB
+        resource.chunk_timings = Some(vec![[10_000, 5], [25_000, 10], [50_000, 2010]]);
+
+        let content = bytes::Bytes::from(vec![0u8; 100_000]);
+        let pacing = ChunkPacing {
+            chunk_size: Some(10_000),
+            ..ChunkPacing::default()
+        };
+        let times = |resource: &Resource| {
+            let (chunks, close) = create_paced_chunks(content.clone(), resource, &pacing).unwrap();
+            (
+                chunks.iter().map(|c| c.target_time).collect::<Vec<u64>>(),
+                close,
+            )
+        };
+        assert_eq!(
+            times(&resource),
+            (vec![5, 5, 10, 10, 10, 2010, 2010, 2010, 2010, 2010], 2010)
+        );
+
+        // Scaled transfer times stretch the recorded shape
+        let mut faster = resource.clone();
+        faster.duration_ms = Some(1005);
+        assert_eq!(
+            times(&faster),
+            (vec![2, 2, 5, 5, 5, 1005, 1005, 1005, 1005, 1005], 1005)
+        );
+
+        // A bandwidth in place of the recorded duration paces evenly
+        let mut profiled = resource.clone();
+        profiled.duration_ms = None;
+        profiled.mbps = Some(8.0);
+        assert_eq!(
+            times(&profiled),
+            (vec![0, 10, 20, 30, 40, 50, 60, 70, 80, 90], 100)
+        );
+
+        // Bodies streamed from disk follow the same timings
+        let fs = Arc::new(MockFileSystem::new());
+        fs.set_file("/inventory/contents/feed.bin", content.to_vec());
+        resource.content_file_path = Some("contents/feed.bin".to_string());
+        resource.status_code = Some(200);
+        let pacing = ChunkPacing {
+            stream_threshold: 50_000,
+            ..pacing
+        };
+        let transaction = convert_resource_to_transaction_with_pacing(
+            &resource,
+            Path::new("/inventory"),
+            fs.clone(),
+            &pacing,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(transaction.streamed.is_some() && transaction.timed);
+        let loader =
+            TransactionLoader::new(Vec::new(), "/inventory".into(), fs).with_pacing(pacing);
+        let mut streamed = Vec::new();
+        while let Some(chunk) = loader.chunk(&transaction, streamed.len()).await.unwrap() {
+            streamed.push(chunk.target_time);
+        }
+        assert_eq!(streamed, times(&resource).0);
+    }
+
+    /// Counts body bytes handed out by the file system
+    struct CountingFileSystem {
+        inner: MockFileSystem,
+        bytes_read: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl FileSystem for CountingFileSystem {
+        async fn read(&self, path: &Path) -> anyhow::Result<Vec<u8>> {
+            let content = self.inner.read(path).await?;
+            self.bytes_read.fetch_add(content.len(), Ordering::SeqCst);
+            Ok(content)
+        }
+
+        async fn file_len(&self, path: &Path) -> anyhow::Result<u64> {
+            // Stands in for a metadata lookup, so it is not counted
+            Ok(self.inner.read(path).await?.len() as u64)
+        }
+
+        async fn read_range(
+            &self,
+            path: &Path,
+            offset: u64,
+            len: usize,
+        ) -> anyhow::Result<bytes::Bytes> {
+            let chunk = self.inner.read_range(path, offset, len).await?;
+            self.bytes_read.fetch_add(chunk.len(), Ordering::SeqCst);
+            Ok(chunk)
+        }
+
+        async fn write(&self, path: &Path, content: &[u8]) -> anyhow::Result<()> {
+            self.inner.write(path, content).await
+        }
+
+        async fn create_dir_all(&self, path: &Path) -> anyhow::Result<()> {
+            self.inner.create_dir_all(path).await
+        }
+
+        async fn exists(&self, path: &Path) -> bool {
+            self.inner.exists(path).await
+        }
+
+        async fn read_to_string(&self, path: &Path) -> anyhow::Result<String> {
+            self.inner.read_to_string(path).await
+        }
+
+        async fn write_string(&self, path: &Path, content: &str) -> anyhow::Result<()> {
+            self.inner.write_string(path, content).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_body_streams_as_slow_reader_consumes() {
+        let inventory_dir = Path::new("/inventory");
+        let content: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let fs = Arc::new(CountingFileSystem {
+            inner: MockFileSystem::new(),
+            bytes_read: AtomicUsize::new(0),
+        });
+        fs.inner
+            .set_file("/inventory/contents/big.bin", content.clone());
+
+        let mut resource =
+            Resource::new("GET".to_string(), "https://example.com/big.bin".to_string());
+        resource.content_file_path = Some("contents/big.bin".to_string());
+        resource.status_code = Some(200);
+        resource.duration_ms = Some(1000);
+        let pacing = ChunkPacing {
+            chunk_size: Some(64 * 1024),
+            stream_threshold: 512 * 1024,
+            ..ChunkPacing::default()
+        };
+
+        let transaction = convert_resource_to_transaction_with_pacing(
+            &resource,
+            inventory_dir,
+            fs.clone(),
+            &pacing,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(transaction.chunks.is_empty());
+        assert_eq!(transaction.body_size(), content.len());
+        assert_eq!(transaction.target_close_time, 1000);
+        assert_eq!(fs.bytes_read.load(Ordering::SeqCst), 0);
+
+        // A reader that pauses between chunks only ever pulls what it asked for
+        let loader = TransactionLoader::new(Vec::new(), inventory_dir.to_path_buf(), fs.clone())
+            .with_pacing(pacing);
+        let mut received = Vec::new();
+        let mut index = 0;
+        while let Some(chunk) = loader.chunk(&transaction, index).await.unwrap() {
+            assert_eq!(chunk.target_time, index as u64 * 1000 / 16);
+            received.extend_from_slice(&chunk.chunk);
+            assert_eq!(fs.bytes_read.load(Ordering::SeqCst), received.len());
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+            index += 1;
+        }
+        assert_eq!(index, 16);
+        assert_eq!(received, content);
+    }
+
+    #[tokio::test]
+    async fn test_small_or_compressed_bodies_are_not_streamed() {
+        let inventory_dir = Path::new("/inventory");
+        let fs = Arc::new(MockFileSystem::new());
+        fs.set_file("/inventory/contents/app.js", vec![b'a'; 4096]);
+
+        let mut resource =
+            Resource::new("GET".to_string(), "https://example.com/app.js".to_string());
+        resource.content_file_path = Some("contents/app.js".to_string());
+        let pacing = ChunkPacing {
+            stream_threshold: 1024,
+            ..ChunkPacing::default()
+        };
+
+        let streamed = convert_resource_to_transaction_with_pacing(
+            &resource,
+            inventory_dir,
+            fs.clone(),
+            &pacing,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(streamed.streamed.is_some());
+
+        resource.content_encoding = Some(ContentEncodingType::Gzip);
+        let compressed = convert_resource_to_transaction_with_pacing(
+            &resource,
+            inventory_dir,
+            fs.clone(),
+            &pacing,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(compressed.streamed.is_none());
+        assert!(!compressed.chunks.is_empty());
+
+        resource.content_encoding = None;
+        let small = convert_resource_to_transaction_with_pacing(
+            &resource,
+            inventory_dir,
+            fs,
+            &ChunkPacing::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(small.streamed.is_none());
+    }
 }
@@ -0,0 +1,71 @@
+//! External programs that edit content files as playback loads them
+//!
+//! `--transform` runs a command through the shell once for every body loaded from the
+//! inventory, before it is minified, re-encoded and compressed, so the program sees what
+//! the content file holds and an experiment such as injecting a script into every page
+//! needs no hand edits. It gets the resource as one JSON object on stdin and prints the
+//! body to serve on stdout, as is; printing nothing keeps the body. Bodies are passed as
+//! `bodyUtf8` when they are valid UTF-8, else as `bodyBase64`. Bodies large enough to be
+//! streamed from disk are served without it, as with `--filter-cmd`.
+
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose};
+use bytes::Bytes;
+use serde::Serialize;
+
+use super::filter::pipe_through;
+use crate::types::{HttpHeaders, Resource};
+
+/// What the program reads on stdin
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TransformInput<'a> {
+    method: &'a str,
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<&'a HttpHeaders>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime_type: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_utf8: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TransformCommand {
+    command: String,
+}
+
+impl TransformCommand {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+
+    /// The body `resource` is served with, given `content` loaded for it
+    pub async fn apply(&self, resource: &Resource, content: Bytes) -> Result<Bytes> {
+        let text = std::str::from_utf8(&content).ok();
+        let input = TransformInput {
+            method: &resource.method,
+            url: &resource.url,
+            status_code: resource.status_code,
+            headers: resource.raw_headers.as_ref(),
+            mime_type: resource.mime_type(),
+            body_utf8: text,
+            body_base64: text
+                .is_none()
+                .then(|| general_purpose::STANDARD.encode(&content)),
+        };
+
+        let output = pipe_through(&self.command, "--transform", &serde_json::to_vec(&input)?)
+            .await
+            .with_context(|| format!("Failed to transform {}", resource.url))?;
+        Ok(if output.is_empty() {
+            content
+        } else {
+            Bytes::from(output)
+        })
+    }
+}
@@ -0,0 +1,249 @@
+//! Aborting replayed bodies that stopped making progress
+//!
+//! During long soak runs a body can fall far behind its recorded schedule (an
+//! overloaded machine, a stalled disk), or sit unread because the client stopped
+//! reading without closing the connection. Either way the stream keeps its transaction,
+//! its network activity and its journal entry alive indefinitely. The watchdog logs
+//! such streams as `watchdog` events and aborts them: a late stream ends with an error
+//! at its next chunk, and an unread one releases what it holds right away and ends with
+//! an error if it is ever read again.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::utils::parse_duration_ms;
+
+#[derive(Debug, Clone, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchdogOptions {
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "Abort response bodies that fall this far behind their recorded schedule (e.g. 30s)"
+    )]
+    pub watchdog_lag: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration_ms,
+        help = "Abort response bodies the client has not read from for this long (e.g. 60s)"
+    )]
+    pub watchdog_stall: Option<u64>,
+}
+
+impl WatchdogOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.watchdog_lag.is_some() || self.watchdog_stall.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AbortReason {
+    /// The body fell further behind its schedule than `--watchdog-lag`
+    Lagging,
+    /// The client did not read for longer than `--watchdog-stall`
+    Stalled,
+}
+
+impl fmt::Display for AbortReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbortReason::Lagging => write!(f, "fell behind schedule"),
+            AbortReason::Stalled => write!(f, "client stopped reading"),
+        }
+    }
+}
+
+struct Watched {
+    url: String,
+    started: Instant,
+    state: Mutex<WatchedState>,
+}
+
+#[derive(Default)]
+struct WatchedState {
+    // When the last chunk was handed to the client; None while the next one is produced
+    waiting_since: Option<Instant>,
+    chunks: usize,
+    aborted: Option<AbortReason>,
+    // Whatever the stream keeps alive until it ends, dropped early on abort
+    guards: Option<Box<dyn Send>>,
+}
+
+/// Streams being served, checked for stalls by `run`
+pub struct Watchdog {
+    max_lag_ms: Option<u64>,
+    max_stall: Option<Duration>,
+    streams: Mutex<HashMap<u64, Arc<Watched>>>,
+    next_id: AtomicU64,
+    aborted: AtomicU64,
+}
+
+impl Watchdog {
+    pub fn new(options: &WatchdogOptions) -> Self {
+        Self {
+            max_lag_ms: options.watchdog_lag,
+            max_stall: options.watchdog_stall.map(Duration::from_millis),
+            streams: Mutex::default(),
+            next_id: AtomicU64::new(0),
+            aborted: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of streams currently being served
+    pub fn active(&self) -> usize {
+        self.streams.lock().unwrap().len()
+    }
+
+    /// Abort streams whose client has not read for longer than the stall limit
+    ///
+    /// Returns how many were aborted.
+    pub fn sweep(&self, now: Instant) -> usize {
+        let Some(max_stall) = self.max_stall else {
+            return 0;
+        };
+        let stalled: Vec<(u64, Arc<Watched>)> = self
+            .streams
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, watched)| {
+                watched
+                    .state
+                    .lock()
+                    .unwrap()
+                    .waiting_since
+                    .is_some_and(|since| now.saturating_duration_since(since) >= max_stall)
+            })
+            .map(|(id, watched)| (*id, watched.clone()))
+            .collect();
+        for (id, watched) in &stalled {
+            self.abort(*id, watched, AbortReason::Stalled, now);
+        }
+        stalled.len()
+    }
+
+    /// Sweep for stalled streams until the proxy stops
+    pub async fn run(self: Arc<Self>) {
+        let Some(max_stall) = self.max_stall else {
+            return;
+        };
+        let period = (max_stall / 4).clamp(Duration::from_millis(100), Duration::from_secs(1));
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if self.sweep(Instant::now()) > 0 {
+                debug!(
+                    "Watchdog: {} streams active, {} aborted so far",
+                    self.active(),
+                    self.aborted.load(Ordering::Relaxed)
+                );
+            }
+        }
+    }
+
+    fn abort(&self, id: u64, watched: &Watched, reason: AbortReason, now: Instant) {
+        let guards = {
+            let mut state = watched.state.lock().unwrap();
+            if state.aborted.is_some() {
+                return;
+            }
+            state.aborted = Some(reason);
+            warn!(
+                target: "watchdog",
+                event = "stream_aborted",
+                reason = ?reason,
+                url = %watched.url,
+                age_ms = now.saturating_duration_since(watched.started).as_millis() as u64,
+                chunks = state.chunks,
+                "Aborted response body of {}: {}",
+                watched.url,
+                reason
+            );
+            state.guards.take()
+        };
+        drop(guards);
+        self.streams.lock().unwrap().remove(&id);
+        self.aborted.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// One response body, holding `guards` until it ends or the watchdog aborts it
+pub struct StreamWatch {
+    id: u64,
+    watched: Arc<Watched>,
+    watchdog: Option<Arc<Watchdog>>,
+}
+
+impl StreamWatch {
+    /// Start watching a body of `url`; without a watchdog only `guards` are held
+    pub fn new<G: Send + 'static>(watchdog: Option<&Arc<Watchdog>>, url: &str, guards: G) -> Self {
+        let watched = Arc::new(Watched {
+            url: url.to_string(),
+            started: Instant::now(),
+            state: Mutex::new(WatchedState {
+                guards: Some(Box::new(guards)),
+                ..Default::default()
+            }),
+        });
+        let id = match watchdog {
+            Some(watchdog) => {
+                let id = watchdog.next_id.fetch_add(1, Ordering::Relaxed);
+                watchdog.streams.lock().unwrap().insert(id, watched.clone());
+                id
+            }
+            None => 0,
+        };
+        Self {
+            id,
+            watched,
+            watchdog: watchdog.cloned(),
+        }
+    }
+
+    /// The client asked for the next chunk; fails if the stream was aborted meanwhile
+    pub fn resume(&self) -> Result<(), AbortReason> {
+        let mut state = self.watched.state.lock().unwrap();
+        state.waiting_since = None;
+        state.aborted.map_or(Ok(()), Err)
+    }
+
+    /// The next chunk is `behind_ms` late; fails once that is beyond the lag limit
+    pub fn check_lag(&self, behind_ms: u64) -> Result<(), AbortReason> {
+        let Some(watchdog) = &self.watchdog else {
+            return Ok(());
+        };
+        if watchdog.max_lag_ms.is_some_and(|max| behind_ms > max) {
+            watchdog.abort(self.id, &self.watched, AbortReason::Lagging, Instant::now());
+            return Err(AbortReason::Lagging);
+        }
+        Ok(())
+    }
+
+    /// A chunk was handed to the client
+    pub fn handed_over(&self) {
+        let mut state = self.watched.state.lock().unwrap();
+        state.chunks += 1;
+        state.waiting_since = Some(Instant::now());
+    }
+
+    /// Drop the guards now, though the stream stays open
+    pub fn release(&self) {
+        let guards = self.watched.state.lock().unwrap().guards.take();
+        drop(guards);
+    }
+}
+
+impl Drop for StreamWatch {
+    fn drop(&mut self) {
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.streams.lock().unwrap().remove(&self.id);
+        }
+    }
+}
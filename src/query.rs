@@ -0,0 +1,84 @@
+//! Query string canonicalization
+//!
+//! Many frameworks emit query parameters in a different order on every run. With these
+//! options `?a=1&b=2` and `?b=2&a=1` name the same resource, both when a recording
+//! stores URLs and when playback matches requests against them.
+
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryOptions {
+    #[arg(
+        long,
+        help = "Sort query parameters by name so their order does not distinguish resources"
+    )]
+    pub sort_query: bool,
+
+    #[arg(
+        long,
+        help = "Lowercase query parameter names so their case does not distinguish resources"
+    )]
+    pub lowercase_query_keys: bool,
+}
+
+impl QueryOptions {
+    fn is_identity(&self) -> bool {
+        !self.sort_query && !self.lowercase_query_keys
+    }
+
+    /// Canonical form of a query string (without the leading `?`)
+    ///
+    /// Sorting is stable, so repeated parameters keep their relative order.
+    pub fn canonicalize<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        if self.is_identity() {
+            return Cow::Borrowed(query);
+        }
+
+        let mut params: Vec<Cow<str>> = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                if !self.lowercase_query_keys {
+                    return Cow::Borrowed(param);
+                }
+                match param.split_once('=') {
+                    Some((key, value)) => Cow::Owned(format!("{}={}", key.to_lowercase(), value)),
+                    None => Cow::Owned(param.to_lowercase()),
+                }
+            })
+            .collect();
+        if self.sort_query {
+            params.sort_by(|a, b| param_key(a).cmp(param_key(b)));
+        }
+        Cow::Owned(params.join("&"))
+    }
+
+    /// `url` with its query string canonicalized
+    pub fn canonicalize_url(&self, url: &str) -> String {
+        if self.is_identity() {
+            return url.to_string();
+        }
+        let (without_fragment, fragment) = match url.find('#') {
+            Some(pos) => url.split_at(pos),
+            None => (url, ""),
+        };
+        match without_fragment.split_once('?') {
+            Some((base, query)) => {
+                let query = self.canonicalize(query);
+                if query.is_empty() {
+                    format!("{}{}", base, fragment)
+                } else {
+                    format!("{}?{}{}", base, query, fragment)
+                }
+            }
+            None => url.to_string(),
+        }
+    }
+}
+
+fn param_key(param: &str) -> &str {
+    param.split_once('=').map_or(param, |(key, _)| key)
+}
+
+mod tests;
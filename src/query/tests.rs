@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod query_tests {
+    use crate::query::QueryOptions;
+
+    #[test]
+    fn test_default_leaves_query_untouched() {
+        let options = QueryOptions::default();
+        assert_eq!(options.canonicalize("b=2&A=1&&"), "b=2&A=1&&");
+        assert_eq!(
+            options.canonicalize_url("https://example.com/?b=2&a=1"),
+            "https://example.com/?b=2&a=1"
+        );
+    }
+
+    #[test]
+    fn test_sort_query() {
+        let options = QueryOptions {
+            sort_query: true,
+            lowercase_query_keys: false,
+        };
+        assert_eq!(options.canonicalize("b=2&a=1"), "a=1&b=2");
+        assert_eq!(options.canonicalize("a=1&b=2"), "a=1&b=2");
+        // Repeated names keep their order; names sort before their values
+        assert_eq!(options.canonicalize("t=2&s=x&t=1"), "s=x&t=2&t=1");
+        assert_eq!(options.canonicalize("ab=1&a=2"), "a=2&ab=1");
+        assert_eq!(
+            options.canonicalize_url("https://example.com/app.js?v=3&cb=9#top"),
+            "https://example.com/app.js?cb=9&v=3#top"
+        );
+        assert_eq!(
+            options.canonicalize_url("https://example.com/"),
+            "https://example.com/"
+        );
+    }
+
+    #[test]
+    fn test_lowercase_query_keys() {
+        let options = QueryOptions {
+            sort_query: true,
+            lowercase_query_keys: true,
+        };
+        assert_eq!(options.canonicalize("Page=2&ID=Ab"), "id=Ab&page=2");
+        assert_eq!(options.canonicalize("Flag&x=1"), "flag&x=1");
+    }
+}
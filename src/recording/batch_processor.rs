@@ -1,35 +1,84 @@
+use super::source_maps::{self, SourceMaps};
+use super::{byte_ranges, integrity, sniff};
 use crate::traits::{FileSystem, TimeProvider};
 use crate::types::{ContentEncodingType, Inventory, Resource};
 use crate::utils::{
     extract_charset_from_content_type, extract_charset_from_css, extract_charset_from_html,
-    generate_file_path_from_url, is_text_resource,
+    file_path_segment, generate_file_path_from_url, is_text_resource, with_occurrence_suffix,
 };
 use anyhow::Result;
 use encoding_rs::{Encoding, UTF_8};
 use flate2::read::GzDecoder;
+use std::collections::HashMap;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::{error, info};
 
+/// Leading bytes of a spilled body read to tell its type
+const SNIFF_BYTES: usize = 1024;
+
+/// How text bodies are stored in content files
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CharsetPolicy {
+    /// Decoded to UTF-8 (beautified when minified) and re-encoded when served
+    #[default]
+    Utf8,
+    /// The bytes as received, served as they are; `contentCharset` is kept for reference
+    Original,
+    /// UTF-8 in `contents/` as with utf8, plus the bytes as received in `originals/`
+    Both,
+}
+
 /// Batch processor for processing all resources at shutdown time
 /// This allows us to keep proxy runtime overhead minimal for accurate timing
 pub struct BatchProcessor<F: FileSystem, T: TimeProvider> {
+    inventory_dir: PathBuf,
     contents_dir: PathBuf,
+    uploads_dir: PathBuf,
+    originals_dir: PathBuf,
     file_system: Arc<F>,
     _time_provider: Arc<T>,
+    inline_base64_max_bytes: Option<usize>,
+    charset: CharsetPolicy,
+    source_maps: Option<SourceMaps>,
 }
 
 impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
     pub fn new(inventory_dir: PathBuf, file_system: Arc<F>, time_provider: Arc<T>) -> Self {
         let contents_dir = inventory_dir.join("contents");
         Self {
+            inventory_dir: inventory_dir.clone(),
             contents_dir,
+            uploads_dir: inventory_dir.join("uploads"),
+            originals_dir: inventory_dir.join("originals"),
             file_system,
             _time_provider: time_provider,
+            inline_base64_max_bytes: None,
+            charset: CharsetPolicy::default(),
+            source_maps: None,
         }
     }
 
+    /// Also embed binary bodies up to `max_bytes` as contentBase64 (None: never)
+    pub fn with_inline_base64_max_bytes(mut self, max_bytes: Option<usize>) -> Self {
+        self.inline_base64_max_bytes = max_bytes;
+        self
+    }
+
+    /// Store text bodies as `policy` asks (default: UTF-8)
+    pub fn with_charset(mut self, policy: CharsetPolicy) -> Self {
+        self.charset = policy;
+        self
+    }
+
+    /// Store the source maps of beautified scripts (default: none)
+    pub fn with_source_maps(mut self, source_maps: Option<SourceMaps>) -> Self {
+        self.source_maps = source_maps;
+        self
+    }
+
     /// Process all resources in the inventory at shutdown time
     /// This includes:
     /// - Decompressing response bodies
@@ -37,13 +86,33 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
     /// - Detecting and extracting charset information
     /// - Beautifying minified content
     /// - Saving to content files
+    /// - Storing source maps of beautified scripts
+    /// - Linking redirects to the requests that followed them
     pub async fn process_all(&self, inventory: &mut Inventory) -> Result<()> {
         info!(
             "Batch processing {} resources...",
             inventory.resources.len()
         );
 
+        byte_ranges::stitch_ranges(&mut inventory.resources);
+        assign_occurrences(&mut inventory.resources);
+        let redirects = crate::redirects::link_redirects(&mut inventory.resources);
+        if redirects > 0 {
+            info!(
+                "Linked {} redirects to the requests that followed them",
+                redirects
+            );
+        }
+
         for (idx, resource) in inventory.resources.iter_mut().enumerate() {
+            if let Err(e) = self.save_uploaded_files(resource).await {
+                error!(
+                    "Failed to save uploaded files of resource #{} ({}): {}",
+                    idx + 1,
+                    resource.url,
+                    e
+                );
+            }
             if let Err(e) = self.process_resource(resource).await {
                 error!(
                     "Failed to process resource #{} ({}): {}",
@@ -54,11 +123,30 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
             }
         }
 
+        if let Some(source_maps) = &self.source_maps {
+            source_maps
+                .store_all(
+                    &mut inventory.resources,
+                    &self.inventory_dir,
+                    self.file_system.as_ref(),
+                )
+                .await;
+        }
+
         info!("Batch processing completed");
         Ok(())
     }
 
     async fn process_resource(&self, resource: &mut Resource) -> Result<()> {
+        // Bodies spilled to disk are copied when they need no decoding, and loaded if not
+        if let (None, Some(path)) = (&resource.raw_body, resource.raw_body_file.clone()) {
+            resource.raw_body_file = None;
+            if self.store_spilled(resource, &path).await? {
+                return Ok(());
+            }
+            resource.raw_body = Some(self.file_system.read(&path).await?);
+        }
+
         // Extract raw body (if present)
         let raw_body = match &resource.raw_body {
             Some(body) => body.clone(),
@@ -66,30 +154,52 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
                 return Ok(()); // No body to process
             }
         };
+        resource.original_size = Some(raw_body.len() as u64);
+
+        // What gets stored must start from the bytes the client was sent
+        if let Some(difference) = resource
+            .forwarded_body
+            .as_ref()
+            .and_then(|forwarded| integrity::discrepancy(forwarded, &raw_body))
+        {
+            tracing::warn!(
+                "Integrity check: recorded body of {} differs from what the client received: {}",
+                resource.url,
+                difference
+            );
+        }
 
         // Decompress body
-        let decompressed_body = self.decompress_body(&raw_body, &resource.content_encoding)?;
+        let decompressed_body = self.decompress_body(
+            &raw_body,
+            &resource.content_encoding,
+            resource.open_ended == Some(true),
+        )?;
+
+        // Missing or mislabeled types are told from the body
+        resource.sniffed_mime =
+            sniff::sniff_mime(resource.content_type_mime.as_deref(), &decompressed_body)
+                .map(str::to_string);
 
-        // Determine if this is a text resource
-        let is_text = resource
-            .content_type_mime
-            .as_deref()
-            .map(is_text_resource)
-            .unwrap_or(false);
+        // Determine if this is a text resource; a ranged object is served in byte slices
+        let is_text = resource.byte_ranges.is_none()
+            && resource.mime_type().map(is_text_resource).unwrap_or(false);
 
         if is_text {
             // Extract and save charset from Content-Type for text resources
-            if let Some(content_type_header) = resource
+            let content_type_header = resource
                 .raw_headers
                 .as_ref()
-                .and_then(|h| h.get("content-type"))
-            {
-                let ct_str = content_type_header.as_vec().join("; ");
-                let mut charset = extract_charset_from_content_type(&ct_str);
+                .and_then(|h| h.get("content-type"));
+            // A sniffed type was not declared, so neither was a charset
+            if content_type_header.is_some() || resource.sniffed_mime.is_some() {
+                let mut charset = content_type_header.and_then(|content_type_header| {
+                    extract_charset_from_content_type(&content_type_header.as_vec().join("; "))
+                });
 
                 // If HTTP header doesn't have charset, try to detect from content
                 if charset.is_none() {
-                    let mime = resource.content_type_mime.as_deref().unwrap_or("");
+                    let mime = resource.mime_type().unwrap_or("");
                     charset = if mime == "text/html" {
                         extract_charset_from_html(&decompressed_body)
                     } else if mime == "text/css" {
@@ -102,11 +212,16 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
                 resource.content_charset = charset;
             }
 
-            // Try to process as text, fallback to binary if it fails
-            if let Err(e) = self
+            if self.charset == CharsetPolicy::Original {
+                // The charset found above is only a note; playback sends these bytes
+                resource.charset_preserved = Some(true);
+                self.process_binary_resource(resource, &decompressed_body)
+                    .await?;
+            } else if let Err(e) = self
                 .process_text_resource(resource, &decompressed_body)
                 .await
             {
+                // Try to process as text, fallback to binary if it fails
                 tracing::warn!(
                     "Failed to process as text resource ({}), falling back to binary: {}",
                     resource.url,
@@ -114,6 +229,8 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
                 );
                 self.process_binary_resource(resource, &decompressed_body)
                     .await?;
+            } else if self.charset == CharsetPolicy::Both {
+                self.save_original(resource, &decompressed_body).await?;
             }
         } else {
             self.process_binary_resource(resource, &decompressed_body)
@@ -126,30 +243,103 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
         Ok(())
     }
 
+    /// Store a body spilled to disk by copying its file, unless it is compressed, text or
+    /// to be inlined and has to be loaded; returns whether it was stored
+    ///
+    /// The copy is checked against the forwarded body by size only, as reading back a
+    /// body that was too large to hold is what spilling avoids.
+    async fn store_spilled(&self, resource: &mut Resource, path: &Path) -> Result<bool> {
+        let encoded = resource
+            .content_encoding
+            .as_ref()
+            .is_some_and(|encoding| *encoding != ContentEncodingType::Identity);
+        if encoded || self.inline_base64_max_bytes.is_some() {
+            return Ok(false);
+        }
+        let head = self.file_system.read_range(path, 0, SNIFF_BYTES).await?;
+        resource.sniffed_mime =
+            sniff::sniff_mime(resource.content_type_mime.as_deref(), &head).map(str::to_string);
+        if resource.mime_type().is_some_and(is_text_resource) {
+            return Ok(false);
+        }
+
+        let file_path = stored_file_path(resource)?;
+        let full_path = self.contents_dir.join(&file_path);
+        if let Some(parent) = full_path.parent() {
+            self.file_system.create_dir_all(parent).await?;
+        }
+        let size = self.file_system.copy(path, &full_path).await?;
+        resource.original_size = Some(size);
+        if let Some(forwarded) = resource.forwarded_body.as_ref().filter(|f| f.size != size) {
+            tracing::warn!(
+                "Integrity check: {:?} written for {} holds {} bytes instead of {}",
+                full_path,
+                resource.url,
+                size,
+                forwarded.size
+            );
+        }
+        resource.content_file_path = Some(format!("contents/{}", file_path));
+        Ok(true)
+    }
+
+    /// Write the file parts of a multipart request to `uploads/<content path>/`
+    async fn save_uploaded_files(&self, resource: &mut Resource) -> Result<()> {
+        if resource.request_form.is_none() {
+            return Ok(());
+        }
+        let base_path = content_file_path(resource)?;
+        let parts = resource.request_form.iter_mut().flatten();
+
+        for (index, part) in parts.enumerate() {
+            let Some(data) = part.data.take() else {
+                continue;
+            };
+            // Browsers used to send full client paths; only the last component is kept
+            let file_name = part
+                .filename
+                .as_deref()
+                .and_then(|f| f.rsplit(['/', '\\']).next())
+                .filter(|f| !f.is_empty())
+                .map(|f| format!("{}-{}", index, file_path_segment(f)))
+                .unwrap_or_else(|| format!("{}.bin", index));
+            let file_path = format!("{}/{}", base_path, file_name);
+            let full_path = self.uploads_dir.join(&file_path);
+
+            if let Some(parent) = full_path.parent() {
+                self.file_system.create_dir_all(parent).await?;
+            }
+            self.file_system.write(&full_path, &data).await?;
+            part.file_path = Some(format!("uploads/{}", file_path));
+        }
+
+        Ok(())
+    }
+
+    /// Decompress `body`; the prefix of a body that never ended is decompressed as far
+    /// as it goes
     fn decompress_body(
         &self,
         body: &[u8],
         encoding: &Option<ContentEncodingType>,
+        prefix: bool,
     ) -> Result<Vec<u8>> {
-        match encoding {
-            Some(ContentEncodingType::Gzip) => {
-                let mut decoder = GzDecoder::new(body);
-                let mut decompressed = Vec::new();
-                decoder.read_to_end(&mut decompressed)?;
-                Ok(decompressed)
-            }
-            Some(ContentEncodingType::Deflate) => {
-                let mut decompressed = Vec::new();
-                let mut decoder = flate2::read::DeflateDecoder::new(body);
-                decoder.read_to_end(&mut decompressed)?;
-                Ok(decompressed)
-            }
+        let mut decompressed = Vec::new();
+        let decoded = match encoding {
+            Some(ContentEncodingType::Gzip) => GzDecoder::new(body)
+                .read_to_end(&mut decompressed)
+                .map(drop),
+            Some(ContentEncodingType::Deflate) => flate2::read::DeflateDecoder::new(body)
+                .read_to_end(&mut decompressed)
+                .map(drop),
             Some(ContentEncodingType::Br) => {
-                let mut decompressed = Vec::new();
-                brotli::BrotliDecompress(&mut std::io::Cursor::new(body), &mut decompressed)?;
-                Ok(decompressed)
+                brotli::BrotliDecompress(&mut std::io::Cursor::new(body), &mut decompressed)
             }
-            _ => Ok(body.to_vec()),
+            _ => return Ok(body.to_vec()),
+        };
+        match decoded {
+            Err(e) if !prefix => Err(e.into()),
+            _ => Ok(decompressed),
         }
     }
 
@@ -162,7 +352,7 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
         // If beautification fails, log warning and save original UTF-8 content
         let original_lines = utf8_content.lines().count();
         let (beautified, is_minified) =
-            match self.beautify_content(&utf8_content, &resource.content_type_mime) {
+            match self.beautify_content(&utf8_content, resource.mime_type()) {
                 Ok(beautified) => {
                     let beautified_lines = beautified.lines().count();
                     let is_minified = beautified_lines >= original_lines * 2;
@@ -180,6 +370,17 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
             };
 
         resource.minify = Some(is_minified);
+        let is_script = matches!(
+            resource.mime_type(),
+            Some("application/javascript" | "text/javascript")
+        );
+        if is_minified && is_script && self.source_maps.is_some() {
+            resource.source_map_url = source_maps::source_map_url(
+                &resource.url,
+                &utf8_content,
+                resource.raw_headers.as_ref(),
+            );
+        }
 
         // Save content to file
         // If minified, save the beautified version for better editability
@@ -192,15 +393,14 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
             utf8_content
         };
 
-        let file_path = generate_file_path_from_url(&resource.url, &resource.method)?;
+        let file_path = stored_file_path(resource)?;
         let full_path = self.contents_dir.join(&file_path);
 
         if let Some(parent) = full_path.parent() {
             self.file_system.create_dir_all(parent).await?;
         }
 
-        self.file_system
-            .write(&full_path, content_to_save.as_bytes())
+        self.write_verified(&full_path, content_to_save.as_bytes(), &resource.url)
             .await?;
         // Store path relative to inventory dir (with "contents/" prefix)
         resource.content_file_path = Some(format!("contents/{}", file_path));
@@ -209,25 +409,59 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
     }
 
     async fn process_binary_resource(&self, resource: &mut Resource, body: &[u8]) -> Result<()> {
-        // Save binary content as base64
-        use base64::{Engine as _, engine::general_purpose};
-        resource.content_base64 = Some(general_purpose::STANDARD.encode(body));
+        // The file is the source of truth; inline base64 is opt-in for small bodies only
+        if self
+            .inline_base64_max_bytes
+            .is_some_and(|max_bytes| body.len() <= max_bytes)
+        {
+            use base64::{Engine as _, engine::general_purpose};
+            resource.content_base64 = Some(general_purpose::STANDARD.encode(body));
+        }
 
-        // Also save to file
-        let file_path = generate_file_path_from_url(&resource.url, &resource.method)?;
+        let file_path = stored_file_path(resource)?;
         let full_path = self.contents_dir.join(&file_path);
 
         if let Some(parent) = full_path.parent() {
             self.file_system.create_dir_all(parent).await?;
         }
 
-        self.file_system.write(&full_path, body).await?;
+        self.write_verified(&full_path, body, &resource.url).await?;
         // Store path relative to inventory dir (with "contents/" prefix)
         resource.content_file_path = Some(format!("contents/{}", file_path));
 
         Ok(())
     }
 
+    /// Write a text body as received to `originals/`, mirroring its UTF-8 content file
+    async fn save_original(&self, resource: &mut Resource, body: &[u8]) -> Result<()> {
+        let file_path = stored_file_path(resource)?;
+        let full_path = self.originals_dir.join(&file_path);
+
+        if let Some(parent) = full_path.parent() {
+            self.file_system.create_dir_all(parent).await?;
+        }
+
+        self.write_verified(&full_path, body, &resource.url).await?;
+        resource.original_file_path = Some(format!("originals/{}", file_path));
+
+        Ok(())
+    }
+
+    /// Write a file and read it back, warning when it does not hold `content`
+    async fn write_verified(&self, path: &Path, content: &[u8], url: &str) -> Result<()> {
+        self.file_system.write(path, content).await?;
+        let written = self.file_system.read(path).await?;
+        if let Some(difference) = integrity::discrepancy(&integrity::digest(content), &written) {
+            tracing::warn!(
+                "Integrity check: {:?} written for {} holds {}",
+                path,
+                url,
+                difference
+            );
+        }
+        Ok(())
+    }
+
     fn convert_to_utf8(&self, body: &[u8], charset: &Option<String>) -> (String, &'static str) {
         let encoding = if let Some(charset_name) = charset {
             Encoding::for_label(charset_name.as_bytes()).unwrap_or(UTF_8)
@@ -239,8 +473,8 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
         (cow.into_owned(), encoding_used.name())
     }
 
-    fn beautify_content(&self, content: &str, mime_type: &Option<String>) -> Result<String> {
-        match mime_type.as_deref() {
+    fn beautify_content(&self, content: &str, mime_type: Option<&str>) -> Result<String> {
+        match mime_type {
             Some("text/html") => crate::beautify::format_html(content),
             Some("application/javascript") | Some("text/javascript") => {
                 crate::beautify::format_javascript(content)
@@ -250,3 +484,47 @@ impl<F: FileSystem, T: TimeProvider> BatchProcessor<F, T> {
         }
     }
 }
+
+/// Number repeated method+URL captures so each keeps its own timing and content file
+pub fn assign_occurrences(resources: &mut [Resource]) {
+    let mut totals: HashMap<(String, String), u32> = HashMap::new();
+    for resource in resources.iter() {
+        *totals
+            .entry((resource.method.clone(), resource.url.clone()))
+            .or_default() += 1;
+    }
+
+    let mut seen: HashMap<(String, String), u32> = HashMap::new();
+    for resource in resources.iter_mut() {
+        let key = (resource.method.clone(), resource.url.clone());
+        if totals.get(&key).copied().unwrap_or(0) < 2 {
+            continue;
+        }
+        let next = seen.entry(key).or_default();
+        resource.occurrence = Some(*next);
+        *next += 1;
+    }
+}
+
+/// Content file path for a resource, disambiguated by its occurrence index
+pub fn content_file_path(resource: &Resource) -> Result<String> {
+    let file_path = generate_file_path_from_url(&resource.url, &resource.method)?;
+    Ok(with_occurrence_suffix(
+        &file_path,
+        resource.occurrence.unwrap_or(0),
+    ))
+}
+
+/// Content file path for a resource's body, with the sniffed type's extension when the
+/// URL gives none
+fn stored_file_path(resource: &Resource) -> Result<String> {
+    let file_path = content_file_path(resource)?;
+    let name = file_path.rsplit('/').next().unwrap_or(&file_path);
+    let has_extension = name
+        .rfind('.')
+        .is_some_and(|dot| !name[dot..].starts_with(".~"));
+    match resource.sniffed_mime.as_deref().and_then(sniff::extension) {
+        Some(extension) if !has_extension => Ok(format!("{}.{}", file_path, extension)),
+        _ => Ok(file_path),
+    }
+}
@@ -0,0 +1,179 @@
+//! Stitching partial responses into one object
+//!
+//! Media players fetch audio and video in many `Range` requests, each answered
+//! `206 Partial Content` with a slice of the file. Kept one resource per response, the
+//! slices would only answer the exact ranges the recorded player asked for. Before the
+//! inventory is processed, the 206 responses to GETs of the same URL are merged: slices
+//! that overlap or touch become one span, the content file holds the spans back to back
+//! in object order, and `byteRanges` says which parts of the object they are. Playback
+//! then answers any range that starts within a recorded span. Slices sent in a content
+//! encoding, or shorter than their Content-Range, are kept as recorded.
+
+use std::collections::{HashMap, HashSet};
+use tracing::{info, warn};
+
+use super::integrity;
+use crate::types::{ByteRange, ContentEncodingType, Resource};
+
+/// A 206 response that can be stitched
+struct Part {
+    index: usize,
+    range: ByteRange,
+    complete_length: Option<u64>,
+}
+
+/// Parse `bytes START-END/LENGTH`, LENGTH being `*` when unknown
+fn parse_content_range(value: &str) -> Option<(ByteRange, Option<u64>)> {
+    let rest = value.trim().strip_prefix("bytes ")?;
+    let (range, complete_length) = rest.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let range = ByteRange {
+        start: start.trim().parse().ok()?,
+        end: end.trim().parse().ok()?,
+    };
+    if range.end < range.start {
+        return None;
+    }
+    let complete_length = match complete_length.trim() {
+        "*" => None,
+        length => Some(length.parse().ok()?),
+    };
+    Some((range, complete_length))
+}
+
+fn part(index: usize, resource: &Resource) -> Option<Part> {
+    if resource.method != "GET" || resource.status_code != Some(206) {
+        return None;
+    }
+    if !matches!(
+        resource.content_encoding,
+        None | Some(ContentEncodingType::Identity)
+    ) {
+        return None;
+    }
+    let body = resource.raw_body.as_ref()?;
+    let content_range = resource.raw_headers.as_ref()?.get("content-range")?;
+    let (range, complete_length) = parse_content_range(content_range.first())?;
+    (range.length() == body.len() as u64).then_some(Part {
+        index,
+        range,
+        complete_length,
+    })
+}
+
+/// Replace the partial responses for each URL with one resource holding all their bytes
+pub fn stitch_ranges(resources: &mut Vec<Resource>) {
+    let mut parts_by_url: HashMap<&str, Vec<Part>> = HashMap::new();
+    for (index, resource) in resources.iter().enumerate() {
+        if let Some(part) = part(index, resource) {
+            parts_by_url
+                .entry(resource.url.as_str())
+                .or_default()
+                .push(part);
+        }
+    }
+
+    let mut stitched = Vec::new();
+    for (url, mut parts) in parts_by_url {
+        // Slices of different versions of the object cannot be combined
+        let complete_length = parts[0].complete_length;
+        if parts
+            .iter()
+            .any(|part| part.complete_length != complete_length)
+        {
+            warn!(
+                "Partial responses of {} disagree on the object's length; kept as recorded",
+                url
+            );
+            continue;
+        }
+        parts.sort_by_key(|part| (part.range.start, part.index));
+        stitched.push((parts, complete_length));
+    }
+
+    let mut merged: HashSet<usize> = HashSet::new();
+    for (parts, complete_length) in stitched {
+        for part in &parts {
+            let resource = &resources[part.index];
+            let body = resource.raw_body.as_deref().unwrap_or_default();
+            if let Some(difference) = resource
+                .forwarded_body
+                .as_ref()
+                .and_then(|forwarded| integrity::discrepancy(forwarded, body))
+            {
+                warn!(
+                    "Integrity check: recorded body of {} ({}-{}) differs from what the client received: {}",
+                    resource.url, part.range.start, part.range.end, difference
+                );
+            }
+        }
+
+        let mut spans: Vec<ByteRange> = Vec::new();
+        let mut content = Vec::new();
+        for part in &parts {
+            let body = resources[part.index]
+                .raw_body
+                .as_deref()
+                .unwrap_or_default();
+            match spans.last_mut() {
+                Some(last) if part.range.start <= last.end + 1 => {
+                    // Overlapping bytes are taken from the slice that starts first
+                    if part.range.end > last.end {
+                        let skip = (last.end + 1 - part.range.start) as usize;
+                        content.extend_from_slice(&body[skip..]);
+                        last.end = part.range.end;
+                    }
+                }
+                _ => {
+                    spans.push(part.range);
+                    content.extend_from_slice(body);
+                }
+            }
+        }
+
+        // The first request keeps its place in the session and its TTFB; the transfer
+        // time is that of all slices together
+        let base = parts
+            .iter()
+            .map(|part| part.index)
+            .min()
+            .unwrap_or_default();
+        let duration_ms: u64 = parts
+            .iter()
+            .filter_map(|part| resources[part.index].duration_ms)
+            .sum();
+        merged.extend(parts.iter().map(|part| part.index).filter(|&i| i != base));
+
+        let resource = &mut resources[base];
+        info!(
+            "Stitched {} partial responses of {} into {} bytes in {} spans",
+            parts.len(),
+            resource.url,
+            content.len(),
+            spans.len()
+        );
+        resource.duration_ms = Some(duration_ms);
+        resource.mbps = (duration_ms > 0).then(|| {
+            let bits_per_second = (content.len() as f64 * 8.0) / (duration_ms as f64 / 1000.0);
+            bits_per_second / (1000.0 * 1000.0)
+        });
+        if let Some(headers) = resource.raw_headers.as_mut() {
+            headers.remove("content-range");
+            headers.remove("content-length");
+        }
+        resource.raw_body = Some(content);
+        // Each slice arrived on its own schedule
+        resource.chunk_timings = None;
+        resource.forwarded_body = None;
+        resource.byte_ranges = Some(spans);
+        resource.complete_length = complete_length;
+    }
+
+    if !merged.is_empty() {
+        let mut index = 0;
+        resources.retain(|_| {
+            index += 1;
+            !merged.contains(&(index - 1))
+        });
+    }
+}
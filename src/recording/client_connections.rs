@@ -0,0 +1,52 @@
+//! Connections the recording browser spread its requests over
+//!
+//! Over HTTP/1.1 a browser opens a handful of connections per host and queues requests
+//! behind busy ones, so how many it opened shapes the TTFBs it observed as much as the
+//! server does. Each CONNECT tunnel (or plain proxy connection) arrives from its own
+//! client port, so requests are counted per host and client address.
+
+use hyper::Version;
+use indexmap::IndexMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use crate::types::HostConnections;
+
+#[derive(Debug, Default)]
+struct HostState {
+    // Requests per client address, in order of the connection's first request
+    connections: IndexMap<SocketAddr, u64>,
+    multiplexed: bool,
+}
+
+/// Requests per client connection and host, in order of first request to the host
+#[derive(Debug, Default)]
+pub struct ConnectionUsage {
+    hosts: Mutex<IndexMap<String, HostState>>,
+}
+
+impl ConnectionUsage {
+    /// Note a request for `host` arriving on the client connection from `client_addr`
+    pub fn request(&self, host: &str, client_addr: SocketAddr, version: Version) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = hosts.entry(host.to_string()).or_default();
+        *state.connections.entry(client_addr).or_insert(0) += 1;
+        if version >= Version::HTTP_2 {
+            state.multiplexed = true;
+        }
+    }
+
+    pub fn summary(&self) -> Vec<HostConnections> {
+        self.hosts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(host, state)| HostConnections {
+                host: host.clone(),
+                connections: state.connections.len() as u64,
+                requests: state.connections.values().copied().collect(),
+                multiplexed: state.multiplexed,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,121 @@
+//! Time spent opening upstream connections, kept out of TTFB
+//!
+//! The proxy's client pools upstream connections, so only a request that finds no idle
+//! connection to its origin pays for DNS, TCP and the TLS handshake. Left in ttfbMs,
+//! that cost lands on whichever HTTPS request happened to open the tunnel, and plain
+//! HTTP or reused connections look faster by comparison. The connector below notes how
+//! long each connection took to open; the recording handler charges it to the request
+//! that was waiting for it and stores it as connectMs instead. It also notes the
+//! address each connection went to.
+
+use hyper::Uri;
+use hyper_util::client::legacy::connect::{Connection, HttpInfo};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use super::origin_addresses::OriginAddresses;
+
+/// Connections not claimed by a request within this time are forgotten
+const UNCLAIMED_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+struct ConnectEvent {
+    host: String,
+    started: Instant,
+    elapsed: Duration,
+}
+
+/// Opened upstream connections waiting to be charged to a request
+#[derive(Debug, Default)]
+pub struct ConnectTimings {
+    events: Mutex<Vec<ConnectEvent>>,
+}
+
+impl ConnectTimings {
+    fn record(&self, host: String, started: Instant, elapsed: Duration) {
+        let mut events = self.events.lock().unwrap();
+        events.retain(|event| event.started.elapsed() < UNCLAIMED_TTL);
+        events.push(ConnectEvent {
+            host,
+            started,
+            elapsed,
+        });
+    }
+
+    /// Time spent opening a connection to `host` for a request sent at `request_start`
+    ///
+    /// A connection opened after the request was sent was opened for it (or for a
+    /// request sent at the same time, which costs the same). Each connection is charged
+    /// once.
+    pub fn take(&self, host: &str, request_start: Instant) -> Option<Duration> {
+        let mut events = self.events.lock().unwrap();
+        let index = events
+            .iter()
+            .position(|event| event.host == host && event.started >= request_start)?;
+        Some(events.remove(index).elapsed)
+    }
+}
+
+/// Connector wrapper that reports how long each connection took to open, and where to
+#[derive(Debug, Clone)]
+pub struct TimedConnector<C> {
+    inner: C,
+    timings: Arc<ConnectTimings>,
+    addresses: Option<Arc<OriginAddresses>>,
+}
+
+impl<C> TimedConnector<C> {
+    pub fn new(inner: C, timings: Arc<ConnectTimings>) -> Self {
+        Self {
+            inner,
+            timings,
+            addresses: None,
+        }
+    }
+
+    /// Note the remote address of each connection
+    pub fn with_addresses(mut self, addresses: Arc<OriginAddresses>) -> Self {
+        self.addresses = Some(addresses);
+        self
+    }
+}
+
+impl<C> tower::Service<Uri> for TimedConnector<C>
+where
+    C: tower::Service<Uri>,
+    C::Response: Connection,
+    C::Future: Send + 'static,
+{
+    type Response = C::Response;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<C::Response, C::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let host = uri.host().unwrap_or_default().to_string();
+        let timings = self.timings.clone();
+        let addresses = self.addresses.clone();
+        let started = Instant::now();
+        let connecting = self.inner.call(uri);
+        Box::pin(async move {
+            let connection = connecting.await?;
+            let elapsed = started.elapsed();
+            if let Some(addresses) = addresses {
+                // Only plain TCP connections (under any TLS) carry the remote address
+                let mut extras = http::Extensions::new();
+                connection.connected().get_extras(&mut extras);
+                if let Some(info) = extras.get::<HttpInfo>() {
+                    addresses.connected(&host, info.remote_addr());
+                }
+            }
+            timings.record(host, started, elapsed);
+            Ok(connection)
+        })
+    }
+}
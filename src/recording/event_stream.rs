@@ -0,0 +1,192 @@
+//! Server-Sent Events recorded as they arrive
+//!
+//! A `text/event-stream` response may stay open for the whole session, so it cannot be
+//! buffered like other bodies: the browser would see no event until the stream ends.
+//! Its body is passed through frame by frame instead, noting when each event (ended by
+//! a blank line) came in. The resources are added to the inventory when recording
+//! stops, including streams that are still open at that point.
+
+use bytes::Bytes;
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use super::integrity::BodyDigester;
+use crate::types::{Resource, StreamEvent};
+
+/// Whether a response is an uncompressed event stream
+pub fn is_event_stream(headers: &hyper::HeaderMap) -> bool {
+    let mime = headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|ct| ct.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase());
+    let encoded = headers
+        .get("content-encoding")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|e| !e.eq_ignore_ascii_case("identity"));
+    mime.as_deref() == Some("text/event-stream") && !encoded
+}
+
+/// One stream being recorded
+#[derive(Debug)]
+struct Capture {
+    // Everything but the body and its timing
+    resource: Resource,
+    headers_received: Instant,
+    received: Mutex<Received>,
+    ended: OnceLock<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Received {
+    body: Vec<u8>,
+    // The frames as passed to the client, to check the reassembled body against
+    forwarded: BodyDigester,
+    events: Vec<StreamEvent>,
+    // Start of the event not yet ended by a blank line
+    event_start: usize,
+}
+
+impl Capture {
+    fn receive(&self, data: &[u8]) {
+        let offset_ms = self.headers_received.elapsed().as_millis() as u64;
+        let mut received = self.received.lock().unwrap();
+        // Look back far enough to find a blank line split across frames
+        let search_from = received
+            .body
+            .len()
+            .saturating_sub(3)
+            .max(received.event_start);
+        received.forwarded.update(data);
+        received.body.extend_from_slice(data);
+        let mut position = search_from;
+        while let Some(end) = event_end(&received.body[position..]) {
+            let end = position + end;
+            let size = (end - received.event_start) as u64;
+            received.events.push(StreamEvent { offset_ms, size });
+            received.event_start = end;
+            position = end;
+        }
+    }
+
+    /// The recorded resource, with events up to now for a stream still open
+    fn resource(&self, now: Instant) -> Resource {
+        let received = self.received.lock().unwrap();
+        let mut events = received.events.clone();
+        let ended = self.ended.get().copied();
+        let end_ms = ended
+            .unwrap_or(now)
+            .saturating_duration_since(self.headers_received)
+            .as_millis() as u64;
+        // Bytes after the last blank line are sent when the stream ended
+        if received.event_start < received.body.len() {
+            events.push(StreamEvent {
+                offset_ms: end_ms,
+                size: (received.body.len() - received.event_start) as u64,
+            });
+        }
+
+        let mut resource = self.resource.clone();
+        resource.duration_ms = Some(end_ms);
+        resource.raw_body = Some(received.body.clone());
+        resource.forwarded_body = Some(received.forwarded.finish());
+        resource.event_stream = Some(events);
+        resource.event_stream_open = ended.is_none().then_some(true);
+        resource
+    }
+}
+
+/// Offset just past the first blank line (`\n\n`, `\r\n\r\n` or `\r\r`) in `data`
+fn event_end(data: &[u8]) -> Option<usize> {
+    (0..data.len()).find_map(|i| {
+        [&b"\r\n\r\n"[..], b"\n\n", b"\r\r"]
+            .iter()
+            .find(|blank| data[i..].starts_with(blank))
+            .map(|blank| i + blank.len())
+    })
+}
+
+/// Every event stream of the session, in the order their responses arrived
+#[derive(Debug, Default)]
+pub struct EventStreams {
+    captures: Mutex<Vec<Arc<Capture>>>,
+}
+
+impl EventStreams {
+    /// Pass `body` through, recording it into `resource` event by event
+    pub fn capture<B>(
+        &self,
+        resource: Resource,
+        headers_received: Instant,
+        body: B,
+    ) -> CapturedBody<B> {
+        let capture = Arc::new(Capture {
+            resource,
+            headers_received,
+            received: Mutex::new(Received::default()),
+            ended: OnceLock::new(),
+        });
+        self.captures.lock().unwrap().push(capture.clone());
+        CapturedBody {
+            inner: body,
+            capture,
+        }
+    }
+
+    /// The recorded streams as resources; streams still open count up to `now`
+    pub fn resources(&self, now: Instant) -> Vec<Resource> {
+        self.captures
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|capture| capture.resource(now))
+            .collect()
+    }
+}
+
+/// Response body that records each frame as the client is sent it
+pub struct CapturedBody<B> {
+    inner: B,
+    capture: Arc<Capture>,
+}
+
+impl<B> HttpBody for CapturedBody<B>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.capture.receive(data);
+                }
+                if self.inner.is_end_stream() {
+                    let _ = self.capture.ended.set(Instant::now());
+                }
+            }
+            Poll::Ready(None) | Poll::Ready(Some(Err(_))) => {
+                let _ = self.capture.ended.set(Instant::now());
+            }
+            Poll::Pending => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -1,41 +1,79 @@
-use http_body_util::{BodyExt, Full};
+use bytes::Bytes;
+use http_body::Body as _;
+use http_body_util::{BodyExt, Full, combinators::BoxBody};
 use hudsucker::{
     Body, HttpContext, HttpHandler, RequestOrResponse, hyper::Request, hyper::Response,
 };
 use std::collections::HashMap;
 use std::future::Future;
-use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 use tokio::sync::Mutex;
-use tracing::{error, info};
-
+use tracing::{error, info, warn};
+
+use super::client_connections::ConnectionUsage;
+use super::connect_timing::ConnectTimings;
+use super::event_stream::{EventStreams, is_event_stream};
+use super::integrity::BodyDigester;
+use super::opaque_tunnel::{OpaqueHosts, OpaqueTunnels};
+use super::open_ended::{BodyReader, OpenBodies};
+use super::spill::{DEFAULT_SPILL_THRESHOLD, SpillFile, read_chunks};
+use super::spool::Spool;
+use super::upload_timing::{TimedUpload, UploadProgress};
+use crate::browser::{ActivityGuard, NetworkActivity};
+use crate::control::{ControlEndpoints, is_control_request};
+use crate::query::QueryOptions;
+use crate::redact::Redactor;
 use crate::types::Inventory;
-use crate::types::Resource;
+use crate::types::{
+    ClientMetadata, EarlyHint, ErrorKind, FormPart, HttpHeaders, HttpProtocol, Resource,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct RequestInfo {
     request_start: Instant,
     elapsed_since_start: u64,
-}
-
-/// Unique key for matching requests and responses using HttpContext information
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
-struct RequestKey {
-    client_addr: SocketAddr,
-    method: String,
-    url: String,
+    // Filled by hyper's informational callback while the request is in flight
+    early_hints: Arc<std::sync::Mutex<Vec<EarlyHint>>>,
+    // Parts of a multipart/form-data upload
+    form: Option<Vec<FormPart>>,
+    // User-Agent, Accept-Encoding and client hints of the request
+    client: Option<ClientMetadata>,
+    // All headers of the request, secret values redacted
+    headers: HttpHeaders,
+    // Access-Control-Request-* headers of a CORS preflight
+    preflight: Option<HttpHeaders>,
+    // How far the request body has been forwarded, for requests that have one
+    upload: Option<Arc<UploadProgress>>,
+    // Keeps the request counted as in flight until its response is recorded
+    _activity: ActivityGuard,
 }
 
 #[derive(Clone)]
 pub struct RecordingHandler {
     shared_inventory: Arc<Mutex<Inventory>>,
     start_time: Arc<Instant>,
-    // Request info indexed by (client_addr, method, url) from HttpContext
-    // With ideamans-hudsucker 0.25+, HttpContext includes request_method and request_uri
-    // This allows accurate request-response correlation even with HTTP/2 multiplexing
-    request_infos: Arc<Mutex<HashMap<RequestKey, RequestInfo>>>,
-    request_counter: Arc<Mutex<u64>>,
+    // Request info indexed by request ID
+    request_infos: Arc<Mutex<HashMap<u64, RequestInfo>>>,
+    request_counter: Arc<AtomicU64>,
+    // hudsucker clones the handler for every request and calls handle_response or
+    // handle_error on the clone that handled the request, so the ID assigned there
+    // identifies the response exactly, however requests are multiplexed or reordered
+    request_id: Option<u64>,
+    activity: Arc<NetworkActivity>,
+    control: Option<Arc<ControlEndpoints>>,
+    spool: Option<Arc<Spool>>,
+    // Bodies growing past this many bytes are written into the spool as they arrive
+    spill_threshold: u64,
+    query: QueryOptions,
+    request_header_redactor: Arc<Redactor>,
+    connect_timings: Option<Arc<ConnectTimings>>,
+    opaque_hosts: Arc<OpaqueHosts>,
+    opaque_tunnels: Arc<OpaqueTunnels>,
+    event_streams: Arc<EventStreams>,
+    open_bodies: Arc<OpenBodies>,
+    client_connections: Arc<ConnectionUsage>,
 }
 
 impl RecordingHandler {
@@ -44,13 +82,89 @@ impl RecordingHandler {
             shared_inventory: Arc::new(Mutex::new(inventory)),
             start_time: Arc::new(Instant::now()),
             request_infos: Arc::new(Mutex::new(HashMap::new())),
-            request_counter: Arc::new(Mutex::new(0)),
+            request_counter: Arc::new(AtomicU64::new(0)),
+            request_id: None,
+            activity: Arc::new(NetworkActivity::new()),
+            control: None,
+            spool: None,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+            query: QueryOptions::default(),
+            request_header_redactor: Arc::new(Redactor::for_request_headers(&[])),
+            connect_timings: None,
+            opaque_hosts: Arc::new(OpaqueHosts::default()),
+            opaque_tunnels: Arc::new(OpaqueTunnels::default()),
+            event_streams: Arc::new(EventStreams::default()),
+            open_bodies: Arc::new(OpenBodies::default()),
+            client_connections: Arc::new(ConnectionUsage::default()),
         }
     }
 
+    /// Serve the proxy's own `hpp.local` endpoints instead of recording them
+    pub fn with_control(mut self, control: ControlEndpoints) -> Self {
+        self.control = Some(Arc::new(control));
+        self
+    }
+
+    /// Also write every recorded response to the crash-recovery spool
+    pub fn with_spool(mut self, spool: Arc<Spool>) -> Self {
+        self.spool = Some(spool);
+        self
+    }
+
+    /// Write bodies larger than `bytes` into the spool as they arrive instead of holding
+    /// them in memory
+    pub fn with_spill_threshold(mut self, bytes: u64) -> Self {
+        self.spill_threshold = bytes;
+        self
+    }
+
+    /// Store resource URLs with their query strings canonicalized
+    pub fn with_query_options(mut self, query: QueryOptions) -> Self {
+        self.query = query;
+        self
+    }
+
+    /// Store the values of these request headers redacted, besides cookies and credentials
+    pub fn with_redacted_request_headers(mut self, names: &[String]) -> Self {
+        self.request_header_redactor = Arc::new(Redactor::for_request_headers(names));
+        self
+    }
+
+    /// Take upstream connection setup out of TTFB, using times noted by the connector
+    pub fn with_connect_timings(mut self, timings: Arc<ConnectTimings>) -> Self {
+        self.connect_timings = Some(timings);
+        self
+    }
+
+    /// Relay CONNECT tunnels to these hosts without decrypting them
+    pub fn with_opaque_hosts(mut self, hosts: OpaqueHosts) -> Self {
+        self.opaque_hosts = Arc::new(hosts);
+        self
+    }
+
     pub fn get_inventory(&self) -> Arc<Mutex<Inventory>> {
         self.shared_inventory.clone()
     }
+
+    pub fn get_activity(&self) -> Arc<NetworkActivity> {
+        self.activity.clone()
+    }
+
+    pub fn get_opaque_tunnels(&self) -> Arc<OpaqueTunnels> {
+        self.opaque_tunnels.clone()
+    }
+
+    pub fn get_event_streams(&self) -> Arc<EventStreams> {
+        self.event_streams.clone()
+    }
+
+    pub fn get_open_bodies(&self) -> Arc<OpenBodies> {
+        self.open_bodies.clone()
+    }
+
+    pub fn get_client_connections(&self) -> Arc<ConnectionUsage> {
+        self.client_connections.clone()
+    }
 }
 
 impl HttpHandler for RecordingHandler {
@@ -66,18 +180,29 @@ impl HttpHandler for RecordingHandler {
 
         let start_time = Arc::clone(&self.start_time);
         let request_infos = Arc::clone(&self.request_infos);
-        let request_counter = Arc::clone(&self.request_counter);
+        // Generate unique request ID
+        let request_id = self.request_counter.fetch_add(1, Ordering::Relaxed) + 1;
+        self.request_id = Some(request_id);
+        let activity = Arc::clone(&self.activity);
+        let control = self.control.clone();
+        let opaque_hosts = self.opaque_hosts.clone();
+        let opaque_tunnels = self.opaque_tunnels.clone();
+        let client_connections = self.client_connections.clone();
+        let request_header_redactor = self.request_header_redactor.clone();
 
         async move {
-            // Generate unique request ID
-            let request_id = {
-                let mut counter = request_counter.lock().await;
-                *counter += 1;
-                *counter
-            };
+            let mut req = req;
+            if let Some(control) = control.filter(|_| is_control_request(&req)) {
+                return RequestOrResponse::Response(control.respond(&req));
+            }
 
             // Skip CONNECT requests - they are for tunnel establishment, not actual HTTP requests
             if method == "CONNECT" {
+                if uri.host().is_some_and(|host| opaque_hosts.matches(host)) {
+                    info!("Relaying CONNECT tunnel without interception: {}", uri);
+                    opaque_tunnels.relay(req);
+                    return RequestOrResponse::Response(Response::new(Body::empty()));
+                }
                 info!("Skipping CONNECT request (tunnel): {}", uri);
                 return RequestOrResponse::Request(req);
             }
@@ -109,22 +234,82 @@ impl HttpHandler for RecordingHandler {
                 }
             };
 
-            // Store request information for correlation with response
-            // With ideamans-hudsucker 0.25+, we can use (client_addr, method, url) as unique key
-            // because HttpContext includes request_method and request_uri in handle_response
-            let key = RequestKey {
-                client_addr,
-                method: method.to_string(),
-                url: url.clone(),
-            };
+            if let Some(host) = url
+                .parse::<hyper::Uri>()
+                .ok()
+                .and_then(|u| u.host().map(|host| host.to_string()))
+            {
+                client_connections.request(&host, client_addr, req.version());
+            }
+
+            // Form uploads are buffered so their parts can be kept, then forwarded as sent
+            let mut form = None;
+            let mut upload = None;
+            if let Some(boundary) = headers
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+                .and_then(crate::multipart::boundary)
+            {
+                let (parts, body) = req.into_parts();
+                let body_bytes = match body.collect().await {
+                    Ok(collected) => collected.to_bytes(),
+                    Err(e) => {
+                        error!("Failed to read request body of {}: {}", url, e);
+                        return RequestOrResponse::Response(
+                            Response::builder()
+                                .status(hyper::StatusCode::BAD_GATEWAY)
+                                .body(Body::from(format!("Failed to read request body: {}", e)))
+                                .unwrap(),
+                        );
+                    }
+                };
+                match crate::multipart::parse(&body_bytes, &boundary) {
+                    Ok(parts) => form = Some(crate::multipart::to_form_parts(parts)),
+                    Err(e) => warn!("Not recording form fields of {}: {}", url, e),
+                }
+                upload = Some(Arc::new(UploadProgress::completed(&body_bytes)));
+                req = Request::from_parts(parts, Body::from(Full::new(body_bytes)));
+            } else if !req.body().is_end_stream() {
+                let progress = Arc::new(UploadProgress::default());
+                let (parts, body) = req.into_parts();
+                let body = BoxBody::new(TimedUpload::new(body, progress.clone()));
+                req = Request::from_parts(parts, Body::from(body));
+                upload = Some(progress);
+            }
+
+            // 103 Early Hints never reach handle_response, hyper reports them to this
+            // callback on the forwarded request instead
+            let early_hints = Arc::new(std::sync::Mutex::new(Vec::new()));
+            {
+                let early_hints = Arc::clone(&early_hints);
+                hyper::ext::on_informational(&mut req, move |res| {
+                    if res.status().as_u16() != 103 {
+                        return;
+                    }
+                    let hint = EarlyHint {
+                        offset_ms: request_start.elapsed().as_millis() as u64,
+                        headers: to_http_headers(res.headers()),
+                    };
+                    if let Ok(mut hints) = early_hints.lock() {
+                        hints.push(hint);
+                    }
+                });
+            }
 
             {
                 let mut infos = request_infos.lock().await;
                 infos.insert(
-                    key,
+                    request_id,
                     RequestInfo {
                         request_start,
                         elapsed_since_start,
+                        early_hints,
+                        form,
+                        client: ClientMetadata::from_headers(&headers),
+                        headers: request_header_redactor.redact_headers(&to_http_headers(&headers)),
+                        preflight: HttpHeaders::preflight_request(method.as_str(), &headers),
+                        upload,
+                        _activity: activity.begin(),
                     },
                 );
             }
@@ -142,120 +327,163 @@ impl HttpHandler for RecordingHandler {
         let client_addr = ctx.client_addr;
         let request_method = ctx.request_method.clone();
         let request_uri = ctx.request_uri.clone();
+        let request_id = self.request_id.take();
 
         let start_time = Arc::clone(&self.start_time);
         let request_infos = Arc::clone(&self.request_infos);
         let shared_inventory = Arc::clone(&self.shared_inventory);
+        let spool = self.spool.clone();
+        let spill_threshold = self.spill_threshold;
+        let query = self.query;
+        let connect_timings = self.connect_timings.clone();
+        let event_streams = self.event_streams.clone();
+        let open_bodies = self.open_bodies.clone();
 
         async move {
             let headers = res.headers().clone();
+            let protocol = HttpProtocol::from_version(res.version());
 
             // Record TTFB (time to first byte)
             let ttfb_instant = Instant::now();
 
             info!("Recording response: {}", status);
 
-            // Build request key from HttpContext (available in ideamans-hudsucker 0.25+)
-            // This allows accurate request-response correlation even with HTTP/2 multiplexing
             let url = request_uri.to_string();
-            let key = RequestKey {
-                client_addr,
-                method: request_method.to_string(),
-                url: url.clone(),
-            };
 
-            // Retrieve and remove request info using the key
-            let request_info = {
-                let mut infos = request_infos.lock().await;
-                infos.remove(&key)
+            // Retrieve and remove the info of the request this response answers
+            let mut request_info = match request_id {
+                Some(request_id) => request_infos.lock().await.remove(&request_id),
+                None => None,
             };
 
+            let request_form = request_info.as_mut().and_then(|info| info.form.take());
+            let request_client = request_info.as_mut().and_then(|info| info.client.take());
+            let request_headers = request_info
+                .as_mut()
+                .map(|info| std::mem::take(&mut info.headers));
+            let preflight = request_info.as_mut().and_then(|info| info.preflight.take());
+
+            // Opening a new upstream connection is not part of the server's response time
+            let connect_ms = connect_timings
+                .as_ref()
+                .zip(request_info.as_ref())
+                .zip(request_uri.host())
+                .and_then(|((timings, info), host)| timings.take(host, info.request_start))
+                .map(|elapsed| elapsed.as_millis() as u64);
+            // Known once the whole request body has been forwarded
+            let request_body_sha1 = request_info
+                .as_ref()
+                .and_then(|info| info.upload.as_ref())
+                .filter(|progress| progress.bytes() > 0)
+                .and_then(|progress| progress.sha1().map(|sha1| sha1.to_string()));
+
             let (parts, body) = res.into_parts();
 
-            // Buffer the entire response body (as-is, possibly compressed)
-            let body_bytes = match body.collect().await {
-                Ok(collected) => collected.to_bytes(),
-                Err(e) => {
-                    error!("Failed to read response body: {}", e);
-                    return Response::from_parts(parts, Body::empty());
+            // Event streams are passed through as they arrive and recorded event by event
+            if is_event_stream(&headers) {
+                let mut resource =
+                    Resource::new(request_method.to_string(), query.canonicalize_url(&url));
+                resource.status_code = Some(status.as_u16());
+                resource.request_client = request_client;
+                resource.request_headers = request_headers;
+                resource.request_body_sha1 = request_body_sha1;
+                resource.connect_ms = connect_ms;
+                resource.protocol = protocol;
+                if let Some(info) = &request_info {
+                    let ttfb = ttfb_instant.duration_since(info.request_start).as_millis() as u64;
+                    resource.request_start_ms = Some(info.elapsed_since_start);
+                    resource.ttfb_ms = ttfb.saturating_sub(connect_ms.unwrap_or(0));
                 }
-            };
+                resource.raw_headers = Some(to_http_headers(&headers));
+                resource.content_type_mime = Some("text/event-stream".to_string());
+                info!("Recording event stream {} as events arrive", url);
 
-            let (method_str, url_for_resource, ttfb_ms, duration_ms) = if let Some(info) =
-                request_info
-            {
-                // Calculate TTFB relative to request start (pure TTFB duration)
-                let ttfb = ttfb_instant.duration_since(info.request_start).as_millis() as u64;
-                // Store only the pure TTFB, not the absolute time
-                let ttfb_ms = ttfb;
-
-                // Calculate download end time relative to request start (not proxy start)
-                let download_end = Instant::now();
-                let download_end_ms =
-                    download_end.duration_since(info.request_start).as_millis() as u64;
-
-                // Calculate duration from TTFB to download end
-                let duration_ms = download_end_ms.saturating_sub(ttfb_ms);
-
-                info!(
-                    "Matched response with request: {} {} (TTFB: {}ms, duration: {}ms, request offset: {}ms)",
-                    request_method, url, ttfb, duration_ms, info.elapsed_since_start
-                );
+                let body = event_streams.capture(resource, ttfb_instant, body);
+                return Response::from_parts(parts, Body::from(BoxBody::new(body)));
+            }
 
-                (
-                    request_method.to_string(),
-                    url.clone(),
-                    ttfb_ms,
-                    duration_ms,
-                )
-            } else {
-                // Fallback - this should not happen with ideamans-hudsucker 0.25+ unless request was not recorded
-                error!(
-                    "No matching request info found for: {} {} (client: {})",
-                    request_method, url, client_addr
-                );
-                let elapsed = ttfb_instant.duration_since(*start_time).as_millis() as u64;
-                let download_end = Instant::now();
-                let download_end_elapsed =
-                    download_end.duration_since(*start_time).as_millis() as u64;
-                let duration = download_end_elapsed.saturating_sub(elapsed);
-                (request_method.to_string(), url.clone(), elapsed, duration)
-            };
+            // Bytes of the request body and when they had all been sent, from request start
+            let upload = request_info.as_ref().and_then(|info| {
+                let progress = info.upload.as_ref().filter(|p| p.bytes() > 0)?;
+                let sent = progress
+                    .finished()?
+                    .min(ttfb_instant)
+                    .saturating_duration_since(info.request_start);
+                Some((progress.bytes(), sent.as_millis() as u64))
+            });
+            let (method_str, url_for_resource, ttfb_ms, request_start_ms, early_hints) =
+                if let Some(info) = request_info {
+                    // Calculate TTFB relative to request start (pure TTFB duration)
+                    let ttfb = ttfb_instant.duration_since(info.request_start).as_millis() as u64;
+                    // Store only the pure TTFB, not the absolute time, connection setup
+                    // or the upload the server waited for
+                    let upload_end_ms = upload.map_or(0, |(_, sent_ms)| sent_ms);
+                    let ttfb_ms = ttfb.saturating_sub(upload_end_ms.max(connect_ms.unwrap_or(0)));
+
+                    info!(
+                        "Matched response with request: {} {} (TTFB: {}ms, request offset: {}ms)",
+                        request_method, url, ttfb, info.elapsed_since_start
+                    );
+
+                    let early_hints = info
+                        .early_hints
+                        .lock()
+                        .map(|hints| hints.clone())
+                        .unwrap_or_default();
+
+                    (
+                        request_method.to_string(),
+                        url.clone(),
+                        ttfb_ms,
+                        Some(info.elapsed_since_start),
+                        early_hints,
+                    )
+                } else {
+                    // Fallback - only for responses to requests that were not recorded
+                    error!(
+                        "No matching request info found for: {} {} (client: {})",
+                        request_method, url, client_addr
+                    );
+                    let elapsed = ttfb_instant.duration_since(*start_time).as_millis() as u64;
+                    (
+                        request_method.to_string(),
+                        url.clone(),
+                        elapsed,
+                        None,
+                        Vec::new(),
+                    )
+                };
 
             // Create resource with minimal processing
-            let mut resource = Resource::new(method_str, url_for_resource);
+            let mut resource = Resource::new(method_str, query.canonicalize_url(&url_for_resource));
+            resource.request_start_ms = request_start_ms;
             resource.status_code = Some(status.as_u16());
+            // hyper only attaches a ReasonPhrase when it differs from the canonical one
+            resource.reason_phrase = parts
+                .extensions
+                .get::<hyper::ext::ReasonPhrase>()
+                .map(|reason| String::from_utf8_lossy(reason.as_bytes()).into_owned());
+            if !early_hints.is_empty() {
+                info!("Recorded {} early hint(s) for {}", early_hints.len(), url);
+                resource.early_hints = Some(early_hints);
+            }
+            resource.request_form = request_form;
+            resource.request_client = request_client;
+            resource.request_headers = request_headers;
+            resource.request_body_sha1 = request_body_sha1;
+            // Pairs a preflight with the request it was sent ahead of
+            resource.match_headers = preflight;
             resource.ttfb_ms = ttfb_ms;
-            resource.duration_ms = Some(duration_ms);
+            resource.connect_ms = connect_ms;
+            resource.protocol = protocol;
+            if let Some((bytes, sent_ms)) = upload {
+                resource.upload_bytes = Some(bytes);
+                resource.upload_ms = Some(sent_ms.saturating_sub(connect_ms.unwrap_or(0)));
+            }
 
             // Store response headers
-            // Multiple headers with the same name (like Set-Cookie) are collected into arrays
-            let mut resource_headers = std::collections::HashMap::new();
-            for (name, value) in headers.iter() {
-                if let Ok(value_str) = value.to_str() {
-                    let header_name = name.to_string();
-                    let value_string = value_str.to_string();
-
-                    resource_headers
-                        .entry(header_name)
-                        .and_modify(|existing| {
-                            // If header already exists, convert to Multiple or append to existing Multiple
-                            match existing {
-                                crate::types::HeaderValue::Single(first) => {
-                                    *existing = crate::types::HeaderValue::Multiple(vec![
-                                        first.clone(),
-                                        value_string.clone(),
-                                    ]);
-                                }
-                                crate::types::HeaderValue::Multiple(values) => {
-                                    values.push(value_string.clone());
-                                }
-                            }
-                        })
-                        .or_insert_with(|| crate::types::HeaderValue::Single(value_string));
-                }
-            }
-            resource.raw_headers = Some(resource_headers);
+            // Names keep the order they arrived in so playback can emit them the same way
+            resource.raw_headers = Some(to_http_headers(&headers));
 
             // Detect content-encoding (for later decompression during shutdown)
             #[allow(clippy::collapsible_if)]
@@ -275,9 +503,105 @@ impl HttpHandler for RecordingHandler {
                     Some(ct.split(';').next().unwrap_or(ct).trim().to_string());
             }
 
+            // Buffer the entire response body (as-is, possibly compressed), in the spool
+            // once it grows too large to hold; one that does not end, because the client
+            // gives up on it first or recording stops, is recorded as far as it arrived
+            let reader = open_bodies.begin(resource.clone(), ttfb_instant);
+            let mut spill: Option<SpillFile> = None;
+            // Once writing to the spool failed, the rest of the body is held in memory
+            let mut spill_failed = false;
+            let mut received = 0;
+            // Every frame as it passes through, independent of how the buffer reassembles it
+            let mut forwarded = BodyDigester::default();
+            let mut body = std::pin::pin!(body);
+            while let Some(frame) = body.frame().await {
+                let data = match frame {
+                    Ok(frame) => match frame.into_data() {
+                        Ok(data) => data,
+                        Err(_) => continue,
+                    },
+                    Err(e) => {
+                        error!("Failed to read response body of {}: {}", url, e);
+                        if let Some(mut file) = spill.take() {
+                            let _ = file.finish().await;
+                        }
+                        reader.break_off(&e.to_string());
+                        return Response::from_parts(parts, Body::empty());
+                    }
+                };
+                let arrived = Instant::now();
+                received += data.len() as u64;
+                forwarded.update(&data);
+                reader.receive(&data, arrived);
+                match (spill.take(), &spool) {
+                    (Some(mut file), _) => match file.write(&data).await {
+                        Ok(()) => spill = Some(file),
+                        Err(e) => {
+                            error!(
+                                "Failed to spill response body of {}, holding it in memory: {}",
+                                url, e
+                            );
+                            spill_failed = true;
+                            if let Err(e) = unspill(file, &reader, &data).await {
+                                error!("Not recording {}: {}", url, e);
+                                reader.discard();
+                                return body_lost(&e);
+                            }
+                        }
+                    },
+                    (None, Some(spool)) if received > spill_threshold && !spill_failed => {
+                        let path = spool.body_path();
+                        info!("Writing the body of {} to {:?} as it arrives", url, path);
+                        let buffered = reader.spill(path.clone());
+                        match SpillFile::create(path.clone(), &buffered).await {
+                            Ok(file) => spill = Some(file),
+                            Err(e) => {
+                                error!(
+                                    "Failed to spill response body of {}, holding it in memory: {}",
+                                    url, e
+                                );
+                                spill_failed = true;
+                                let _ = tokio::fs::remove_file(&path).await;
+                                reader.unspill(buffered);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            let spilled = match spill {
+                Some(mut file) => match file.finish().await {
+                    Ok(spilled) => Some(spilled),
+                    Err(e) => {
+                        error!(
+                            "Failed to spill response body of {}, holding it in memory: {}",
+                            url, e
+                        );
+                        if let Err(e) = unspill(file, &reader, &[]).await {
+                            error!("Not recording {}: {}", url, e);
+                            reader.discard();
+                            return body_lost(&e);
+                        }
+                        None
+                    }
+                },
+                None => None,
+            };
+            let (body, chunk_timings) = reader.finish();
+            let body_bytes = Bytes::from(body);
+
+            // Calculate duration from TTFB to download end; a spilled body ended when its
+            // last frame arrived, however long writing it took
+            let duration_ms = match (&spilled, chunk_timings.last()) {
+                (Some(_), Some([_, ms])) => *ms,
+                _ => ttfb_instant.elapsed().as_millis() as u64,
+            };
+            resource.duration_ms = Some(duration_ms);
+            resource.chunk_timings = (!chunk_timings.is_empty()).then_some(chunk_timings);
+
             // Calculate mbps (megabits per second) from body size and duration
             // Use compressed body size (what was actually transferred)
-            let body_size = body_bytes.len() as f64;
+            let body_size = received as f64;
             #[allow(clippy::collapsible_if)]
             if let Some(dur_ms) = resource.duration_ms {
                 if dur_ms > 0 {
@@ -292,7 +616,24 @@ impl HttpHandler for RecordingHandler {
             }
 
             // Store raw body (as-is, possibly compressed) for later processing
-            resource.raw_body = Some(body_bytes.to_vec());
+            match &spilled {
+                Some(spilled) => {
+                    resource.raw_body_file = Some(spilled.path.clone());
+                    resource.forwarded_body = Some(spilled.digest.clone());
+                }
+                None => {
+                    resource.raw_body = Some(body_bytes.to_vec());
+                    resource.forwarded_body = Some(forwarded.finish());
+                }
+            }
+
+            let spooled = match &spool {
+                Some(spool) => spool.append(&resource).await,
+                None => Ok(()),
+            };
+            if let Err(e) = spooled {
+                error!("Failed to spool {}: {}", resource.url, e);
+            }
 
             // Add resource to inventory
             {
@@ -301,7 +642,110 @@ impl HttpHandler for RecordingHandler {
             }
 
             // Return response with the buffered body
-            Response::from_parts(parts, Body::from(Full::new(body_bytes)))
+            match spilled {
+                Some(spilled) => {
+                    Response::from_parts(parts, Body::from_stream(read_chunks(spilled.path)))
+                }
+                None => Response::from_parts(parts, Body::from(Full::new(body_bytes))),
+            }
+        }
+    }
+
+    fn handle_error(
+        &mut self,
+        ctx: &HttpContext,
+        err: hyper_util::client::legacy::Error,
+    ) -> impl Future<Output = Response<Body>> + Send {
+        let method = ctx.request_method.to_string();
+        let url = ctx.request_uri.to_string();
+        let request_id = self.request_id.take();
+        // hyper's own message only names the stage that failed, its sources say why
+        let message = error_chain(&err);
+
+        let request_infos = Arc::clone(&self.request_infos);
+        let shared_inventory = Arc::clone(&self.shared_inventory);
+        let spool = self.spool.clone();
+        let query = self.query;
+
+        async move {
+            let kind = ErrorKind::from_message(&message);
+            error!(
+                "Failed to forward {} {} ({:?}): {}",
+                method, url, kind, message
+            );
+
+            let request_info = match request_id {
+                Some(request_id) => request_infos.lock().await.remove(&request_id),
+                None => None,
+            };
+            let mut resource = Resource::new(method, query.canonicalize_url(&url));
+            resource.error_kind = Some(kind);
+            resource.error_message = Some(message.clone());
+            if let Some(info) = request_info {
+                resource.request_start_ms = Some(info.elapsed_since_start);
+                // How long the client waited for the failure
+                resource.ttfb_ms = info.request_start.elapsed().as_millis() as u64;
+                resource.request_client = info.client;
+                resource.request_headers = Some(info.headers);
+            }
+
+            let spooled = match &spool {
+                Some(spool) => spool.append(&resource).await,
+                None => Ok(()),
+            };
+            if let Err(e) = spooled {
+                error!("Failed to spool {}: {}", resource.url, e);
+            }
+            shared_inventory.lock().await.resources.push(resource);
+
+            Response::builder()
+                .status(kind.status())
+                .body(Body::from(message))
+                .unwrap()
+        }
+    }
+}
+
+/// Take a body back into memory from its spill file, followed by `data` that could not
+/// be written to it
+async fn unspill(file: SpillFile, reader: &BodyReader, data: &[u8]) -> anyhow::Result<()> {
+    let mut body = file.reclaim().await?;
+    body.extend_from_slice(data);
+    reader.unspill(body);
+    Ok(())
+}
+
+/// Answer for a body that was lost on its way through the spool; the origin's headers
+/// would promise a body that is not there
+fn body_lost(err: &anyhow::Error) -> Response<Body> {
+    Response::builder()
+        .status(hyper::StatusCode::BAD_GATEWAY)
+        .body(Body::from(format!(
+            "Failed to buffer response body: {}",
+            err
+        )))
+        .unwrap()
+}
+
+/// An error's message followed by those of its sources
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+/// Convert a header map, collecting repeated headers (like Set-Cookie) into arrays
+fn to_http_headers(headers: &hyper::HeaderMap) -> HttpHeaders {
+    let mut result = HttpHeaders::new();
+    for (name, value) in headers.iter() {
+        if let Ok(value_str) = value.to_str() {
+            result.append(name.as_str(), value_str.to_string());
         }
     }
+    result
 }
@@ -0,0 +1,61 @@
+//! Checking that recorded bodies are the bytes the browser got
+//!
+//! A body passes through several hands between the client and its content file: the
+//! handler buffers it (or passes an event stream through frame by frame while
+//! reassembling it), and batch processing decodes, converts and writes it. The bytes
+//! forwarded to the client are digested as they pass through: frame by frame as they
+//! arrive, apart from the buffer they are reassembled in, or as they are written to the
+//! spool file the client is answered from. The body batch processing starts from is
+//! compared against that digest, and every content file is read back after it is
+//! written. A difference is logged as a warning, so a truncated or mis-assembled body
+//! shows up while recording instead of as a broken page in playback.
+//! Responses recovered from the spool carry no digest and are only read back.
+
+use sha1::{Digest, Sha1};
+
+use crate::types::BodyDigest;
+
+pub fn digest(bytes: &[u8]) -> BodyDigest {
+    let mut digester = BodyDigester::default();
+    digester.update(bytes);
+    digester.finish()
+}
+
+/// Digest of a body that arrives in pieces
+#[derive(Debug, Clone, Default)]
+pub struct BodyDigester {
+    size: u64,
+    hasher: Sha1,
+}
+
+impl BodyDigester {
+    pub fn update(&mut self, data: &[u8]) {
+        self.size += data.len() as u64;
+        self.hasher.update(data);
+    }
+
+    pub fn finish(&self) -> BodyDigest {
+        BodyDigest {
+            size: self.size,
+            sha1: hex::encode(self.hasher.clone().finalize()),
+        }
+    }
+}
+
+/// How `persisted` differs from the body `expected` describes, if at all
+pub fn discrepancy(expected: &BodyDigest, persisted: &[u8]) -> Option<String> {
+    if persisted.len() as u64 != expected.size {
+        return Some(format!(
+            "{} bytes instead of {}",
+            persisted.len(),
+            expected.size
+        ));
+    }
+    let actual = digest(persisted);
+    (actual.sha1 != expected.sha1).then(|| {
+        format!(
+            "same length, SHA-1 {} instead of {}",
+            actual.sha1, expected.sha1
+        )
+    })
+}
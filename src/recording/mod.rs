@@ -1,26 +1,174 @@
-use crate::types::{DeviceType, Inventory};
-use crate::utils::get_port_or_default;
-use anyhow::Result;
-use std::path::PathBuf;
+use crate::browser::BrowserOptions;
+use crate::exit_status::RunFailure;
+use crate::lock::InventoryLock;
+use crate::manifest::{RunManifest, RunMode};
+use crate::query::QueryOptions;
+use crate::server::ServerHooks;
+use crate::traits::{RealFileSystem, RealHttpClient};
+use crate::types::{DeviceType, Inventory, Resource};
+use crate::utils::{get_port_or_default, parse_size_bytes};
+use anyhow::{Context, Result};
+use batch_processor::CharsetPolicy;
+use refresh::{RefreshOptions, RefreshPolicy, RefreshSummary};
+use source_maps::SourceMaps;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-mod batch_processor;
+pub mod batch_processor;
+mod byte_ranges;
+mod client_connections;
+mod connect_timing;
+mod event_stream;
 mod hudsucker_handler;
+mod integrity;
+mod opaque_tunnel;
+mod open_ended;
+mod origin_addresses;
 mod processor;
 pub mod proxy;
+mod refresh;
 mod signal_handler;
+mod sniff;
+mod source_maps;
+mod spill;
+mod spool;
 mod tests;
+mod upload_timing;
 
 #[cfg(test)]
 mod processor_tests;
 
+/// Optional recording features
+#[derive(Debug, Clone, Default, clap::Args, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingOptions {
+    #[command(flatten)]
+    pub browser: BrowserOptions,
+
+    #[command(flatten)]
+    pub query: QueryOptions,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Also embed binary bodies up to this size as contentBase64 in index.json (default: never)"
+    )]
+    pub inline_base64_max_bytes: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "utf8",
+        help = "Store text bodies converted to UTF-8 (utf8, re-encoded when served), as received (original), or both, with the received bytes under originals/"
+    )]
+    pub charset: CharsetPolicy,
+
+    #[arg(
+        long,
+        help = "Take over the inventory lock even if another process appears to hold it"
+    )]
+    pub force: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Write at most this many spooled bodies at once; further writes wait (default: unlimited)"
+    )]
+    pub max_open_files: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size_bytes,
+        help = "Write response bodies larger than this to disk as they arrive instead of holding them in memory (default: 16MB)"
+    )]
+    pub spill_threshold: Option<u64>,
+
+    #[arg(
+        long = "redact-request-header",
+        value_name = "NAME",
+        help = "Store the value of this request header as REDACTED, like Cookie, Authorization and Proxy-Authorization always are (repeatable)"
+    )]
+    pub redact_request_headers: Vec<String>,
+
+    #[arg(
+        long = "no-intercept",
+        value_name = "REGEX",
+        help = "Relay CONNECT tunnels to matching hosts (e.g. certificate-pinned ones) without decrypting them; only their traffic volume is recorded (repeatable)"
+    )]
+    pub no_intercept: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Update an existing inventory, replacing only resources whose status or content changed and adding new ones"
+    )]
+    pub refresh: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "changed",
+        requires = "refresh",
+        help = "Which resources --refresh replaces: changed (status or content differs) or expired (recorded Cache-Control/Expires lifetime has run out)"
+    )]
+    pub refresh_policy: RefreshPolicy,
+
+    #[arg(
+        long,
+        conflicts_with = "refresh",
+        help = "Store the source map of each beautified script next to its content file, fetching it from the origin when it was not recorded"
+    )]
+    pub source_maps: bool,
+
+    #[arg(
+        long,
+        requires = "source_maps",
+        help = "Also write the original sources stored source maps embed under <content file>.sources/"
+    )]
+    pub source_map_sources: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the run manifest to this path instead of manifest.json in the inventory"
+    )]
+    pub manifest: Option<PathBuf>,
+
+    /// Set when the proxy runs embedded through `ProxyServer`
+    #[arg(skip)]
+    #[serde(skip)]
+    pub hooks: ServerHooks,
+}
+
+impl RecordingOptions {
+    /// How batch processing stores source maps, or None without --source-maps
+    pub fn source_maps(&self) -> Result<Option<SourceMaps>> {
+        if !self.source_maps {
+            return Ok(None);
+        }
+        let client = Arc::new(RealHttpClient::new()?);
+        Ok(Some(
+            SourceMaps::new(client).with_sources(self.source_map_sources),
+        ))
+    }
+}
+
 pub async fn run_recording_mode(
     entry_url: Option<String>,
     port: Option<u16>,
     device: DeviceType,
     inventory_dir: PathBuf,
+    options: RecordingOptions,
 ) -> Result<()> {
     let port = get_port_or_default(port)?;
 
+    if options.browser.launch_chrome && entry_url.is_none() {
+        anyhow::bail!("--launch-chrome requires an entry URL");
+    }
+
+    // Held until the inventory has been written
+    let _lock = InventoryLock::acquire(&inventory_dir, "recording", options.force)?;
+
     println!("Starting recording mode on port {}", port);
     println!("Device type: {:?}", device);
     println!("Inventory directory: {:?}", inventory_dir);
@@ -29,9 +177,98 @@ pub async fn run_recording_mode(
         println!("Entry URL: {}", url);
     }
 
+    // Load before recording starts so a missing inventory fails fast
+    let refresh_base = if options.refresh {
+        let existing = crate::playback::load_inventory(&inventory_dir, Arc::new(RealFileSystem))
+            .await
+            .with_context(|| {
+                format!(
+                    "--refresh needs an existing inventory in {:?}",
+                    inventory_dir
+                )
+            })?;
+        println!("Refreshing {} recorded resources", existing.resources.len());
+        Some(existing)
+    } else {
+        None
+    };
+
+    let manifest = RunManifest::start(RunMode::Recording, port, &options)?
+        .with_seed(refresh_base.as_ref().and_then(|existing| existing.seed));
+    let manifest_path = options
+        .manifest
+        .clone()
+        .unwrap_or_else(|| inventory_dir.join("manifest.json"));
+
     let mut inventory = Inventory::new();
     inventory.entry_url = entry_url.clone();
     inventory.device_type = Some(device);
 
-    proxy::start_recording_proxy(port, inventory, inventory_dir).await
+    let summary = proxy::start_recording_proxy(
+        port,
+        inventory,
+        inventory_dir.clone(),
+        options,
+        refresh_base,
+    )
+    .await?;
+    manifest
+        .finish_or_warn(&manifest_path, &inventory_dir, &summary)
+        .await;
+    Ok(())
+}
+
+/// Merge resources recorded outside a recording session into an inventory
+///
+/// Resources already in the inventory are replaced when their status or content changed.
+pub async fn add_resources(
+    inventory_dir: &Path,
+    resources: Vec<Resource>,
+) -> Result<RefreshSummary> {
+    let existing = crate::playback::load_inventory(inventory_dir, Arc::new(RealFileSystem)).await?;
+    let mut session = Inventory::new();
+    session.resources = resources;
+
+    let (merged, summary) = refresh::refresh_inventory(
+        existing,
+        &mut session,
+        inventory_dir,
+        Arc::new(RealFileSystem),
+        RefreshOptions::default(),
+        chrono::Utc::now(),
+    )
+    .await?;
+    proxy::save_inventory(&merged, inventory_dir).await?;
+    Ok(summary)
+}
+
+/// Build the inventory of a recording that stopped before it could be saved
+pub async fn run_recover_mode(
+    inventory_dir: PathBuf,
+    inline_base64_max_bytes: Option<usize>,
+    charset: CharsetPolicy,
+    force: bool,
+) -> Result<()> {
+    let _lock = InventoryLock::acquire(&inventory_dir, "recover", force)?;
+
+    let mut inventory = spool::load_spool(&inventory_dir, Arc::new(RealFileSystem)).await?;
+    println!(
+        "Recovered {} resources from {:?}",
+        inventory.resources.len(),
+        spool::spool_dir(&inventory_dir)
+    );
+
+    proxy::finish_inventory(
+        &mut inventory,
+        &inventory_dir,
+        inline_base64_max_bytes,
+        charset,
+        None,
+    )
+    .await
+    .context(RunFailure::SaveFailed)?;
+    tokio::fs::remove_dir_all(spool::spool_dir(&inventory_dir)).await?;
+
+    println!("Inventory saved to {:?}", inventory_dir.join("index.json"));
+    Ok(())
 }
@@ -0,0 +1,180 @@
+//! CONNECT tunnels passed through without decrypting
+//!
+//! Hosts that pin their certificates cannot be recorded through the MITM CA. With
+//! `--no-intercept`, their tunnels are relayed byte for byte instead, and only how much
+//! went through and for how long is kept, as `opaqueTunnels` in the inventory.
+
+use anyhow::{Context as _, Result};
+use hudsucker::hyper::{Request, upgrade};
+use hyper_util::rt::TokioIo;
+use regex::Regex;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+use crate::types::OpaqueTunnel;
+
+/// Hosts whose CONNECT tunnels are relayed instead of intercepted
+#[derive(Debug, Clone, Default)]
+pub struct OpaqueHosts {
+    patterns: Vec<Regex>,
+}
+
+impl OpaqueHosts {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|p| {
+                Regex::new(p).with_context(|| format!("Invalid --no-intercept pattern {:?}", p))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { patterns })
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        self.patterns.iter().any(|p| p.is_match(host))
+    }
+}
+
+/// Counters of one relayed tunnel
+#[derive(Debug)]
+struct TunnelStats {
+    host: String,
+    opened: Instant,
+    closed: OnceLock<Instant>,
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+/// Every relayed tunnel of the session, open or closed
+#[derive(Debug, Default)]
+pub struct OpaqueTunnels {
+    tunnels: Mutex<Vec<Arc<TunnelStats>>>,
+}
+
+impl OpaqueTunnels {
+    fn open(&self, host: &str) -> Arc<TunnelStats> {
+        let stats = Arc::new(TunnelStats {
+            host: host.to_string(),
+            opened: Instant::now(),
+            closed: OnceLock::new(),
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+        });
+        self.tunnels.lock().unwrap().push(stats.clone());
+        stats
+    }
+
+    /// Traffic per host in order of first connection
+    ///
+    /// Tunnels still open (browsers keep them alive) count up to `now`.
+    pub fn summary(&self, now: Instant) -> Vec<OpaqueTunnel> {
+        let mut summary: Vec<OpaqueTunnel> = Vec::new();
+        for stats in self.tunnels.lock().unwrap().iter() {
+            let index = match summary.iter().position(|t| t.host == stats.host) {
+                Some(index) => index,
+                None => {
+                    summary.push(OpaqueTunnel {
+                        host: stats.host.clone(),
+                        ..Default::default()
+                    });
+                    summary.len() - 1
+                }
+            };
+            let closed = stats.closed.get().copied().unwrap_or(now);
+            let entry = &mut summary[index];
+            entry.connections += 1;
+            entry.bytes_sent += stats.sent.load(Ordering::Relaxed);
+            entry.bytes_received += stats.received.load(Ordering::Relaxed);
+            entry.duration_ms += closed.saturating_duration_since(stats.opened).as_millis() as u64;
+        }
+        summary
+    }
+
+    /// Relay the tunnel a CONNECT request asks for, counting its bytes
+    ///
+    /// Runs in the background; the caller answers the CONNECT with 200 to start it.
+    pub fn relay<B: Send + 'static>(self: &Arc<Self>, mut req: Request<B>) {
+        let Some(authority) = req.uri().authority().cloned() else {
+            return;
+        };
+        let tunnels = self.clone();
+        tokio::spawn(async move {
+            let upgraded = match upgrade::on(&mut req).await {
+                Ok(upgraded) => upgraded,
+                Err(e) => {
+                    warn!("Upgrade error for tunnel to {}: {}", authority, e);
+                    return;
+                }
+            };
+            let server = match TcpStream::connect(authority.as_str()).await {
+                Ok(server) => server,
+                Err(e) => {
+                    warn!("Failed to connect tunnel to {}: {}", authority, e);
+                    return;
+                }
+            };
+            let stats = tunnels.open(authority.host());
+            let mut client = TokioIo::new(upgraded);
+            let mut server = CountingStream {
+                inner: server,
+                stats: stats.clone(),
+            };
+            if let Err(e) = tokio::io::copy_bidirectional(&mut client, &mut server).await {
+                debug!("Tunnel to {} closed: {}", authority, e);
+            }
+            let _ = stats.closed.set(Instant::now());
+        });
+    }
+}
+
+/// Upstream side of a tunnel, counting what is written to and read from it
+struct CountingStream {
+    inner: TcpStream,
+    stats: Arc<TunnelStats>,
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let polled = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = buf.filled().len() - before;
+        self.stats
+            .received
+            .fetch_add(read as u64, Ordering::Relaxed);
+        polled
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let polled = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = &polled {
+            self.stats
+                .sent
+                .fetch_add(*written as u64, Ordering::Relaxed);
+        }
+        polled
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,188 @@
+//! Responses that never ended while recording
+//!
+//! Bodies are buffered before the client gets them, so a long poll or a stream that is
+//! not an event stream holds its response until the client gives up on it, and one
+//! still running when recording stops never finishes at all. Either way the resource
+//! used to be lost. Each body is registered here while it is read: when the client
+//! disconnects or recording stops first, what had arrived by then is recorded and
+//! marked `openEnded`, so playback can replay that prefix and then hold the connection
+//! or close it. When the origin breaks the body off, the prefix is recorded with
+//! `errorKind: truncatedBody` instead, and playback cuts it off at the same place. A
+//! body spilled to disk is recorded from its file.
+//!
+//! Whether it ends or not, when each part of a body arrived is noted here too, as
+//! `chunkTimings` points of bytes received so far and milliseconds since the headers.
+//! Frames arriving within [`TIMING_RESOLUTION_MS`] of a point are counted into it, so a
+//! long download adds a point per interval rather than per frame.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::types::{ErrorKind, Resource};
+
+pub const TIMING_RESOLUTION_MS: u64 = 10;
+
+/// A body being read
+#[derive(Debug, Clone)]
+struct Pending {
+    // Everything but the body and its timing
+    resource: Resource,
+    headers_received: Instant,
+    body: Vec<u8>,
+    // Where the body is written instead, once it grew too large to hold
+    spilled_to: Option<PathBuf>,
+    len: u64,
+    timings: Vec<[u64; 2]>,
+}
+
+impl Pending {
+    /// The recorded resource with the bytes received up to `cut_off`
+    fn into_resource(self, cut_off: Instant) -> Resource {
+        let duration_ms = cut_off
+            .saturating_duration_since(self.headers_received)
+            .as_millis() as u64;
+        let mut resource = self.resource;
+        resource.duration_ms = Some(duration_ms);
+        resource.mbps = (duration_ms > 0).then(|| {
+            let bits_per_second = (self.len as f64 * 8.0) / (duration_ms as f64 / 1000.0);
+            bits_per_second / (1000.0 * 1000.0)
+        });
+        match self.spilled_to {
+            Some(path) => resource.raw_body_file = Some(path),
+            None => resource.raw_body = Some(self.body),
+        }
+        resource.chunk_timings = (!self.timings.is_empty()).then_some(self.timings);
+        resource.open_ended = Some(true);
+        resource
+    }
+}
+
+/// Every body being read, and those cut off before they ended
+#[derive(Debug, Default)]
+pub struct OpenBodies {
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<u64, Pending>>,
+    cut_off: Mutex<Vec<Resource>>,
+}
+
+impl OpenBodies {
+    /// Start reading the body of `resource`, whose headers arrived at `headers_received`
+    pub fn begin(self: &Arc<Self>, resource: Resource, headers_received: Instant) -> BodyReader {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.pending.lock().unwrap().insert(
+            id,
+            Pending {
+                resource,
+                headers_received,
+                body: Vec::new(),
+                spilled_to: None,
+                len: 0,
+                timings: Vec::new(),
+            },
+        );
+        BodyReader {
+            bodies: self.clone(),
+            id,
+        }
+    }
+
+    fn take(&self, id: u64) -> Option<Pending> {
+        self.pending.lock().unwrap().remove(&id)
+    }
+
+    /// The bodies that were cut off, followed by those still being read, up to `now`
+    pub fn resources(&self, now: Instant) -> Vec<Resource> {
+        let mut resources = self.cut_off.lock().unwrap().clone();
+        let pending = self.pending.lock().unwrap();
+        let mut ids: Vec<&u64> = pending.keys().collect();
+        ids.sort();
+        resources.extend(
+            ids.into_iter()
+                .map(|id| pending[id].clone().into_resource(now)),
+        );
+        resources
+    }
+}
+
+/// Collects one body; dropped before `finish` it records what arrived as open-ended
+#[derive(Debug)]
+pub struct BodyReader {
+    bodies: Arc<OpenBodies>,
+    id: u64,
+}
+
+impl BodyReader {
+    /// Take in a frame that arrived at `arrived`
+    pub fn receive(&self, data: &[u8], arrived: Instant) {
+        if let Some(pending) = self.bodies.pending.lock().unwrap().get_mut(&self.id) {
+            pending.len += data.len() as u64;
+            if pending.spilled_to.is_none() {
+                pending.body.extend_from_slice(data);
+            }
+            let ms = arrived
+                .saturating_duration_since(pending.headers_received)
+                .as_millis() as u64;
+            match pending.timings.last_mut() {
+                Some(last) if ms < last[1] + TIMING_RESOLUTION_MS => last[0] = pending.len,
+                _ => pending.timings.push([pending.len, ms]),
+            }
+        }
+    }
+
+    /// Hand over what arrived so far, to be written to `path` along with the rest
+    pub fn spill(&self, path: PathBuf) -> Vec<u8> {
+        match self.bodies.pending.lock().unwrap().get_mut(&self.id) {
+            Some(pending) => {
+                pending.spilled_to = Some(path);
+                std::mem::take(&mut pending.body)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Hold the body in memory again after writing it to disk failed; `body` is
+    /// everything that arrived so far
+    pub fn unspill(&self, body: Vec<u8>) {
+        if let Some(pending) = self.bodies.pending.lock().unwrap().get_mut(&self.id) {
+            pending.spilled_to = None;
+            pending.body = body;
+        }
+    }
+
+    /// Forget a body that could not be kept whole, without recording any of it
+    pub fn discard(self) {
+        self.bodies.take(self.id);
+    }
+
+    /// Record what arrived of a body the origin broke off with `message`
+    pub fn break_off(self, message: &str) {
+        if let Some(pending) = self.bodies.take(self.id) {
+            let mut resource = pending.into_resource(Instant::now());
+            resource.open_ended = None;
+            resource.error_kind = Some(ErrorKind::TruncatedBody);
+            resource.error_message = Some(message.to_string());
+            self.bodies.cut_off.lock().unwrap().push(resource);
+        }
+    }
+
+    /// The whole body, which ended normally (empty once spilled), and when its parts
+    /// arrived
+    pub fn finish(self) -> (Vec<u8>, Vec<[u64; 2]>) {
+        self.bodies
+            .take(self.id)
+            .map(|pending| (pending.body, pending.timings))
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for BodyReader {
+    fn drop(&mut self) {
+        if let Some(pending) = self.bodies.take(self.id) {
+            let resource = pending.into_resource(Instant::now());
+            self.bodies.cut_off.lock().unwrap().push(resource);
+        }
+    }
+}
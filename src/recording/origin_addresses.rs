@@ -0,0 +1,108 @@
+//! Addresses origins resolved to and were reached at
+//!
+//! A CDN can answer the same hostname from a different point of presence on every
+//! recording, so a timing difference between two inventories may come from the route
+//! rather than the application. The resolver below notes every address DNS returned
+//! for a host, and the connector notes which one each connection went to, so the
+//! inventory shows where its traffic actually came from.
+
+use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
+use indexmap::IndexMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::types::{IpVersion, OriginAddress};
+
+/// Addresses per origin host, in order of first lookup
+#[derive(Debug, Default)]
+pub struct OriginAddresses {
+    origins: Mutex<IndexMap<String, OriginAddress>>,
+}
+
+fn push_unique(addresses: &mut Vec<String>, address: String) {
+    if !addresses.contains(&address) {
+        addresses.push(address);
+    }
+}
+
+impl OriginAddresses {
+    fn update(&self, host: &str, update: impl FnOnce(&mut OriginAddress)) {
+        let mut origins = self.origins.lock().unwrap();
+        let origin = origins
+            .entry(host.to_string())
+            .or_insert_with(|| OriginAddress {
+                host: host.to_string(),
+                ..Default::default()
+            });
+        update(origin);
+    }
+
+    /// Note the addresses DNS returned for `host`
+    pub fn resolved(&self, host: &str, addresses: &[SocketAddr]) {
+        self.update(host, |origin| {
+            for address in addresses {
+                push_unique(&mut origin.resolved, address.ip().to_string());
+            }
+        });
+    }
+
+    /// Note that a connection to `host` was opened to `address`
+    pub fn connected(&self, host: &str, address: SocketAddr) {
+        self.update(host, |origin| {
+            push_unique(&mut origin.connected, address.ip().to_string());
+            let version = if address.is_ipv4() {
+                IpVersion::Ipv4
+            } else {
+                IpVersion::Ipv6
+            };
+            origin.ip_version = match origin.ip_version {
+                Some(previous) if previous != version => Some(IpVersion::Both),
+                _ => Some(version),
+            };
+        });
+    }
+
+    pub fn summary(&self) -> Vec<OriginAddress> {
+        self.origins.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// The system resolver, noting what it returns
+#[derive(Debug, Clone)]
+pub struct RecordingResolver {
+    inner: GaiResolver,
+    addresses: Arc<OriginAddresses>,
+}
+
+impl RecordingResolver {
+    pub fn new(addresses: Arc<OriginAddresses>) -> Self {
+        Self {
+            inner: GaiResolver::new(),
+            addresses,
+        }
+    }
+}
+
+impl tower::Service<Name> for RecordingResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let host = name.as_str().to_string();
+        let addresses = self.addresses.clone();
+        let resolving = self.inner.call(name);
+        Box::pin(async move {
+            let resolved: Vec<SocketAddr> = resolving.await?.collect();
+            addresses.resolved(&host, &resolved);
+            Ok(resolved.into_iter())
+        })
+    }
+}
@@ -17,6 +17,7 @@ pub struct RequestProcessor<F: FileSystem, T: TimeProvider> {
     contents_dir: PathBuf,
     file_system: Arc<F>,
     time_provider: Arc<T>,
+    inline_base64_max_bytes: Option<usize>,
 }
 
 impl<F: FileSystem, T: TimeProvider> RequestProcessor<F, T> {
@@ -28,9 +29,17 @@ impl<F: FileSystem, T: TimeProvider> RequestProcessor<F, T> {
             contents_dir,
             file_system,
             time_provider,
+            inline_base64_max_bytes: None,
         }
     }
 
+    /// Also embed binary bodies up to `max_bytes` as contentBase64 (None: never)
+    #[allow(dead_code)]
+    pub fn with_inline_base64_max_bytes(mut self, max_bytes: Option<usize>) -> Self {
+        self.inline_base64_max_bytes = max_bytes;
+        self
+    }
+
     #[allow(dead_code)]
     pub async fn process_response_body(
         &self,
@@ -191,11 +200,15 @@ impl<F: FileSystem, T: TimeProvider> RequestProcessor<F, T> {
         resource: &mut Resource,
         body: &[u8],
     ) -> Result<()> {
-        // Save binary content as base64
-        use base64::{Engine as _, engine::general_purpose};
-        resource.content_base64 = Some(general_purpose::STANDARD.encode(body));
+        // The file is the source of truth; inline base64 is opt-in for small bodies only
+        if self
+            .inline_base64_max_bytes
+            .is_some_and(|max_bytes| body.len() <= max_bytes)
+        {
+            use base64::{Engine as _, engine::general_purpose};
+            resource.content_base64 = Some(general_purpose::STANDARD.encode(body));
+        }
 
-        // Also save to file
         let file_path = generate_file_path_from_url(&resource.url, &resource.method)?;
         let full_path = self.contents_dir.join(&file_path);
 
@@ -97,9 +97,43 @@ mod tests {
         let expected_path = inventory_dir.join("contents/get/https/example.com/image.png");
         assert!(mock_fs.file_exists(&expected_path.to_string_lossy()));
 
-        // Verify resource was updated
+        // Verify resource was updated; the body lives only in the file by default
         assert!(resource.content_file_path.is_some());
-        assert!(resource.content_base64.is_some());
+        assert!(resource.content_base64.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_process_binary_resource_inline_base64_threshold() {
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        let mock_fs = Arc::new(MockFileSystem::new());
+        let mock_time = Arc::new(MockTimeProvider::new(1000));
+
+        let processor = RequestProcessor::new(inventory_dir.clone(), mock_fs.clone(), mock_time)
+            .with_inline_base64_max_bytes(Some(16));
+
+        let mut small = Resource::new(
+            "GET".to_string(),
+            "https://example.com/small.png".to_string(),
+        );
+        processor
+            .process_binary_resource(&mut small, &[0u8; 16])
+            .await
+            .unwrap();
+        assert!(small.content_file_path.is_some());
+        assert!(small.content_base64.is_some());
+
+        let mut large = Resource::new(
+            "GET".to_string(),
+            "https://example.com/large.png".to_string(),
+        );
+        processor
+            .process_binary_resource(&mut large, &[0u8; 17])
+            .await
+            .unwrap();
+        assert!(large.content_file_path.is_some());
+        assert!(large.content_base64.is_none());
     }
 
     #[tokio::test]
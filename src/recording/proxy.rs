@@ -1,11 +1,22 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::Serialize;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::{error, info};
 
-use super::batch_processor::BatchProcessor;
+use super::RecordingOptions;
+use super::batch_processor::{BatchProcessor, CharsetPolicy};
+use super::connect_timing::{ConnectTimings, TimedConnector};
 use super::hudsucker_handler::RecordingHandler;
+use super::opaque_tunnel::OpaqueHosts;
+use super::origin_addresses::{OriginAddresses, RecordingResolver};
+use super::refresh::{RefreshOptions, refresh_inventory};
+use super::source_maps::SourceMaps;
+use super::spill::DEFAULT_SPILL_THRESHOLD;
+use super::spool::Spool;
+use crate::control::ControlEndpoints;
+use crate::exit_status::RunFailure;
+use crate::manifest::RecordingSummary;
 use crate::traits::{FileSystem, RealFileSystem, RealTimeProvider};
 use crate::types::Inventory;
 
@@ -13,16 +24,24 @@ use hudsucker::{
     Proxy as HudsuckerProxy,
     certificate_authority::RcgenAuthority,
     rcgen::{CertificateParams, DistinguishedName, Issuer, KeyPair},
-    rustls::crypto::aws_lc_rs,
+    rustls::{ClientConfig, crypto::aws_lc_rs},
+    tokio_tungstenite::Connector,
 };
+use hyper_rustls::ConfigBuilderExt;
+use hyper_util::client::legacy::connect::HttpConnector;
 
 pub async fn start_recording_proxy(
     port: u16,
     inventory: Inventory,
     inventory_dir: PathBuf,
-) -> Result<()> {
+    options: RecordingOptions,
+    refresh_base: Option<Inventory>,
+) -> Result<RecordingSummary> {
     info!("Starting HTTPS MITM recording proxy on port {}", port);
 
+    let entry_url = inventory.entry_url.clone();
+    let device = inventory.device_type.clone();
+
     // Generate a self-signed CA certificate for MITM
     let key_pair = KeyPair::generate()?;
     let mut params = CertificateParams::new(vec!["http-playback-proxy.local".to_string()])?;
@@ -43,12 +62,50 @@ pub async fn start_recording_proxy(
 
     let ca = RcgenAuthority::new(issuer, 1_000, aws_lc_rs::default_provider());
 
+    // The same CA is offered for download at http://hpp.local/, next to /_start
+    let control = ControlEndpoints::new(cert.pem(), cert.der().to_vec())
+        .with_entry_url(entry_url.clone())
+        .with_shutdown(options.hooks.shutdown_handle());
+
+    // Responses are spooled to disk as they arrive so a crash does not lose the session
+    let spool = Spool::create(&inventory_dir, &inventory, options.max_open_files).await?;
+
+    // Upstream connections are timed so their setup is not counted as TTFB
+    let connect_timings = Arc::new(ConnectTimings::default());
+
     // Create the recording handler
-    let handler = RecordingHandler::new(inventory);
+    let handler = RecordingHandler::new(inventory)
+        .with_control(control)
+        .with_spool(spool.clone())
+        .with_spill_threshold(options.spill_threshold.unwrap_or(DEFAULT_SPILL_THRESHOLD))
+        .with_query_options(options.query)
+        .with_redacted_request_headers(&options.redact_request_headers)
+        .with_connect_timings(connect_timings.clone())
+        .with_opaque_hosts(OpaqueHosts::new(&options.no_intercept)?);
     let handler_inventory = handler.get_inventory();
+    let activity = handler.get_activity();
+    let opaque_tunnels = handler.get_opaque_tunnels();
+    let event_streams = handler.get_event_streams();
+    let open_bodies = handler.get_open_bodies();
+    let client_connections = handler.get_client_connections();
 
-    // Build the proxy with standard TLS configuration
-    let crypto_provider = aws_lc_rs::default_provider();
+    // Build the proxy with standard TLS configuration (what with_rustls_connector sets up),
+    // with the connector wrapped to time connection setup and DNS lookups noted
+    let origin_addresses = Arc::new(OriginAddresses::default());
+    let mut http =
+        HttpConnector::new_with_resolver(RecordingResolver::new(origin_addresses.clone()));
+    // Both schemes pass through; HttpsConnector decides which get TLS
+    http.enforce_http(false);
+    let tls_config = ClientConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_webpki_roots()
+        .with_no_client_auth();
+    let https = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(tls_config.clone())
+        .https_or_http()
+        .enable_http1()
+        .enable_http2()
+        .wrap_connector(http);
 
     // Bind to the socket first to get the actual port (important when port=0)
     let listener =
@@ -60,13 +117,17 @@ pub async fn start_recording_proxy(
     let proxy = HudsuckerProxy::builder()
         .with_listener(listener)
         .with_ca(ca)
-        .with_rustls_connector(crypto_provider)
+        .with_http_connector(
+            TimedConnector::new(https, connect_timings).with_addresses(origin_addresses.clone()),
+        )
+        .with_websocket_connector(Connector::Rustls(Arc::new(tls_config)))
         .with_http_handler(handler)
         .build()?;
 
     // Start the proxy server
     info!("HTTPS MITM Proxy listening on 127.0.0.1:{}", actual_port);
     info!("Configure your client to trust the self-signed CA certificate");
+    info!("Download the CA certificate through the proxy at http://hpp.local/");
     info!("Send SIGTERM or press Ctrl+C to stop recording and save inventory");
 
     // Run proxy and signal handler concurrently
@@ -75,11 +136,20 @@ pub async fn start_recording_proxy(
             error!("Proxy server error: {}", e);
         }
     });
+    options.hooks.listening(actual_port);
 
-    // Wait for shutdown signal
-    if let Err(e) = super::signal_handler::wait_for_shutdown_signal().await {
-        error!("Signal handler error: {}", e);
-    }
+    // Wait for shutdown signal (or the end of the headless browser session)
+    crate::browser::wait_for_shutdown_or_browser(
+        options
+            .hooks
+            .wait_for_shutdown(super::signal_handler::wait_for_shutdown_signal()),
+        &options.browser,
+        actual_port,
+        entry_url.as_deref(),
+        device.as_ref(),
+        activity,
+    )
+    .await;
 
     // Signal received, stop accepting new connections
     info!("Shutdown signal received, stopping proxy...");
@@ -93,15 +163,86 @@ pub async fn start_recording_proxy(
 
     // Get mutable access to inventory for batch processing
     let mut inventory = handler_inventory.lock().await;
+    inventory.opaque_tunnels = opaque_tunnels.summary(std::time::Instant::now());
+    inventory.origins = origin_addresses.summary();
+    inventory.client_connections = client_connections.summary();
+    inventory
+        .resources
+        .extend(event_streams.resources(std::time::Instant::now()));
+    inventory
+        .resources
+        .extend(open_bodies.resources(std::time::Instant::now()));
+
+    match refresh_base {
+        Some(existing) => {
+            let (merged, summary) = refresh_inventory(
+                existing,
+                &mut inventory,
+                &inventory_dir,
+                Arc::new(RealFileSystem),
+                RefreshOptions {
+                    inline_base64_max_bytes: options.inline_base64_max_bytes,
+                    charset: options.charset,
+                    policy: options.refresh_policy,
+                },
+                chrono::Utc::now(),
+            )
+            .await?;
+            save_inventory(&merged, &inventory_dir)
+                .await
+                .context(RunFailure::SaveFailed)?;
+            info!(
+                "Refresh: {} unchanged, {} updated, {} added, {} not requested",
+                summary.unchanged, summary.updated, summary.added, summary.kept
+            );
+            *inventory = merged;
+        }
+        None => {
+            finish_inventory(
+                &mut inventory,
+                &inventory_dir,
+                options.inline_base64_max_bytes,
+                options.charset,
+                options.source_maps()?,
+            )
+            .await
+            .context(RunFailure::SaveFailed)?;
+        }
+    }
+    if let Err(e) = spool.remove().await {
+        error!("Failed to clean up spool: {}", e);
+    }
+
+    info!(
+        "Inventory saved successfully with {} resources",
+        inventory.resources.len()
+    );
+    info!("Shutdown complete");
 
-    // Batch process all resources
+    // Abort proxy task
+    proxy_task.abort();
+
+    Ok(RecordingSummary::of(&inventory))
+}
+
+/// Batch process recorded resources and write index.json
+pub async fn finish_inventory(
+    inventory: &mut Inventory,
+    inventory_dir: &Path,
+    inline_base64_max_bytes: Option<usize>,
+    charset: CharsetPolicy,
+    source_maps: Option<SourceMaps>,
+) -> Result<()> {
     let batch_processor = BatchProcessor::new(
-        inventory_dir.clone(),
+        inventory_dir.to_path_buf(),
         Arc::new(RealFileSystem),
         Arc::new(RealTimeProvider::new()),
-    );
+    )
+    .with_inline_base64_max_bytes(inline_base64_max_bytes)
+    .with_charset(charset)
+    .with_source_maps(source_maps);
 
-    if let Err(e) = batch_processor.process_all(&mut inventory).await {
+    if let Err(e) = batch_processor.process_all(inventory).await {
         error!("Failed to batch process resources: {}", e);
         return Err(e);
     }
@@ -110,20 +251,11 @@ pub async fn start_recording_proxy(
 
     // Save inventory after processing
     info!("Saving inventory...");
-    if let Err(e) = save_inventory(&inventory, &inventory_dir).await {
+    if let Err(e) = save_inventory(inventory, inventory_dir).await {
         error!("Failed to save inventory: {}", e);
         return Err(e);
     }
 
-    info!(
-        "Inventory saved successfully with {} resources",
-        inventory.resources.len()
-    );
-    info!("Shutdown complete");
-
-    // Abort proxy task
-    proxy_task.abort();
-
     Ok(())
 }
 
@@ -0,0 +1,225 @@
+//! Merge a refresh session into an existing inventory
+//!
+//! A refresh records as usual, then decides per resource whether the stored version
+//! or the session's wins; resources the session did not request are left as they are.
+//! The session is processed into a staging directory first, and only the content files
+//! of resources that replace stored ones are moved into the inventory.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::batch_processor::{BatchProcessor, CharsetPolicy};
+use crate::check::{sha1_hex, stored_content};
+use crate::traits::{FileSystem, RealTimeProvider};
+use crate::types::{Inventory, Resource};
+
+const STAGING_DIR_NAME: &str = ".refresh";
+
+/// Method, URL and occurrence: one recorded response
+type ResourceKey = (String, String, u32);
+
+/// Status and content hash, compared between stored and refreshed versions
+type Fingerprint = (Option<u16>, Option<String>);
+
+/// Which recorded resources a refresh replaces
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RefreshPolicy {
+    /// Those whose status or content differs from the session's
+    #[default]
+    Changed,
+    /// Those whose recorded Cache-Control/Expires lifetime has run out
+    Expired,
+}
+
+/// How a refresh processes its session, and which stored resources it replaces
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RefreshOptions {
+    pub inline_base64_max_bytes: Option<usize>,
+    pub charset: CharsetPolicy,
+    pub policy: RefreshPolicy,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RefreshSummary {
+    pub unchanged: usize,
+    pub updated: usize,
+    pub added: usize,
+    pub kept: usize,
+}
+
+fn resource_key(resource: &Resource) -> ResourceKey {
+    (
+        resource.method.clone(),
+        resource.url.clone(),
+        resource.occurrence.unwrap_or(0),
+    )
+}
+
+async fn fingerprint<F: FileSystem>(
+    resource: &Resource,
+    inventory_dir: &Path,
+    file_system: &F,
+) -> Result<Fingerprint> {
+    let hash = stored_content(resource, inventory_dir, file_system)
+        .await?
+        .map(|content| sha1_hex(&content));
+    Ok((resource.status_code, hash))
+}
+
+fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// When a recorded response stops being fresh, per its Cache-Control and Expires headers
+///
+/// `max-age` counts from the `Date` header minus `Age`. None means it was never fresh:
+/// `no-cache`, `no-store`, or no explicit lifetime.
+pub fn fresh_until(resource: &Resource) -> Option<DateTime<Utc>> {
+    let headers = resource.raw_headers.as_ref()?;
+    let header = |name: &str| headers.get(name).map(|v| v.as_vec().join(", "));
+
+    if let Some(cache_control) = header("cache-control") {
+        let directives: Vec<String> = cache_control
+            .split(',')
+            .map(|d| d.trim().to_ascii_lowercase())
+            .collect();
+        if directives
+            .iter()
+            .any(|d| d == "no-cache" || d == "no-store")
+        {
+            return None;
+        }
+        let max_age = directives
+            .iter()
+            .find_map(|d| d.strip_prefix("max-age="))
+            .and_then(|v| v.trim_matches('"').parse::<i64>().ok());
+        if let Some(max_age) = max_age {
+            let date = parse_http_date(&header("date")?)?;
+            let age = header("age")
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .unwrap_or(0);
+            return Some(date + Duration::seconds(max_age - age));
+        }
+    }
+
+    parse_http_date(&header("expires")?)
+}
+
+pub fn is_expired(resource: &Resource, now: DateTime<Utc>) -> bool {
+    fresh_until(resource).is_none_or(|until| until <= now)
+}
+
+/// Batch process `session` and merge it into the inventory `existing` was loaded from
+pub async fn refresh_inventory<F: FileSystem>(
+    existing: Inventory,
+    session: &mut Inventory,
+    inventory_dir: &Path,
+    file_system: Arc<F>,
+    options: RefreshOptions,
+    now: DateTime<Utc>,
+) -> Result<(Inventory, RefreshSummary)> {
+    let staging_dir = inventory_dir.join(STAGING_DIR_NAME);
+    BatchProcessor::new(
+        staging_dir.clone(),
+        file_system.clone(),
+        Arc::new(RealTimeProvider::new()),
+    )
+    .with_inline_base64_max_bytes(options.inline_base64_max_bytes)
+    .with_charset(options.charset)
+    .process_all(session)
+    .await?;
+
+    let mut refreshed = HashMap::new();
+    for (index, resource) in session.resources.drain(..).enumerate() {
+        refreshed.insert(resource_key(&resource), (index, resource));
+    }
+
+    let Inventory {
+        entry_url,
+        device_type,
+        resources,
+        opaque_tunnels,
+        seed,
+        match_strategies,
+        origins,
+        client_connections,
+    } = existing;
+    let mut summary = RefreshSummary::default();
+    let mut merged = Inventory {
+        entry_url: session.entry_url.clone().or(entry_url),
+        device_type,
+        resources: Vec::with_capacity(resources.len()),
+        // Tunnel volume is measured per session, so the new one replaces the old
+        opaque_tunnels: if session.opaque_tunnels.is_empty() {
+            opaque_tunnels
+        } else {
+            session.opaque_tunnels.clone()
+        },
+        seed,
+        match_strategies,
+        // Addresses are looked up per session, like tunnel volume
+        origins: if session.origins.is_empty() {
+            origins
+        } else {
+            session.origins.clone()
+        },
+        client_connections: if session.client_connections.is_empty() {
+            client_connections
+        } else {
+            session.client_connections.clone()
+        },
+    };
+    let mut accepted = Vec::new();
+
+    for resource in resources {
+        let Some((_, fresh)) = refreshed.remove(&resource_key(&resource)) else {
+            summary.kept += 1;
+            merged.resources.push(resource);
+            continue;
+        };
+        let replace = match options.policy {
+            RefreshPolicy::Changed => {
+                fingerprint(&resource, inventory_dir, file_system.as_ref()).await?
+                    != fingerprint(&fresh, &staging_dir, file_system.as_ref()).await?
+            }
+            RefreshPolicy::Expired => is_expired(&resource, now),
+        };
+        if replace {
+            summary.updated += 1;
+            accepted.push(merged.resources.len());
+            merged.resources.push(fresh);
+        } else {
+            summary.unchanged += 1;
+            merged.resources.push(resource);
+        }
+    }
+
+    // Newly seen resources go last, in the order they were recorded
+    let mut added: Vec<(usize, Resource)> = refreshed.into_values().collect();
+    added.sort_by_key(|(index, _)| *index);
+    summary.added = added.len();
+    accepted.extend(merged.resources.len()..merged.resources.len() + added.len());
+    merged
+        .resources
+        .extend(added.into_iter().map(|(_, resource)| resource));
+
+    for index in accepted {
+        let resource = &merged.resources[index];
+        let file_paths = [&resource.content_file_path, &resource.original_file_path];
+        for file_path in file_paths.into_iter().flatten() {
+            let content = file_system.read(&staging_dir.join(file_path)).await?;
+            file_system
+                .write(&inventory_dir.join(file_path), &content)
+                .await?;
+        }
+    }
+    let _ = tokio::fs::remove_dir_all(&staging_dir).await;
+
+    Ok((merged, summary))
+}
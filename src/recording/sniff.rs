@@ -0,0 +1,109 @@
+//! Telling a body's type from its leading bytes
+//!
+//! Some origins send no Content-Type, a generic `application/octet-stream`, or a text
+//! type on an image. Batch processing decides between text handling (charset conversion,
+//! beautifying) and binary storage from the type, so a mislabeled PNG would be mangled as
+//! text and an unlabeled HTML page stored without being made editable. The decompressed
+//! body is checked against a table of signatures: a missing or generic type is replaced
+//! by whatever the signature says, a text type only by a binary signature. The result is
+//! kept as `sniffedMime` next to the recorded `contentTypeMime`, and the content file of
+//! an extensionless URL gets the sniffed type's extension.
+
+/// Offset and bytes of a signature, the MIME type it stands for and the extension
+/// content files of that type get
+type Signature = (usize, &'static [u8], &'static str, &'static str);
+
+const BINARY_SIGNATURES: &[Signature] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png", "png"),
+    (0, b"\xff\xd8\xff", "image/jpeg", "jpg"),
+    (0, b"GIF87a", "image/gif", "gif"),
+    (0, b"GIF89a", "image/gif", "gif"),
+    (8, b"WEBP", "image/webp", "webp"),
+    (4, b"ftypavif", "image/avif", "avif"),
+    (0, b"\x00\x00\x01\x00", "image/x-icon", "ico"),
+    (4, b"ftyp", "video/mp4", "mp4"),
+    (0, b"\x1a\x45\xdf\xa3", "video/webm", "webm"),
+    (0, b"ID3", "audio/mpeg", "mp3"),
+    (0, b"OggS", "audio/ogg", "ogg"),
+    (0, b"wOF2", "font/woff2", "woff2"),
+    (0, b"wOFF", "font/woff", "woff"),
+    (0, b"OTTO", "font/otf", "otf"),
+    (0, b"\x00\x01\x00\x00\x00", "font/ttf", "ttf"),
+    (0, b"%PDF-", "application/pdf", "pdf"),
+    (0, b"PK\x03\x04", "application/zip", "zip"),
+    (0, b"\x1f\x8b", "application/gzip", "gz"),
+    (0, b"\x00asm", "application/wasm", "wasm"),
+];
+
+/// Lowercase starts of markup documents, after leading whitespace
+const MARKUP_SIGNATURES: &[(&str, &str, &str)] = &[
+    ("<!doctype html", "text/html", "html"),
+    ("<html", "text/html", "html"),
+    ("<head", "text/html", "html"),
+    ("<body", "text/html", "html"),
+    ("<svg", "image/svg+xml", "svg"),
+];
+
+/// Declared types that say nothing about the body
+const GENERIC_TYPES: &[&str] = &[
+    "application/octet-stream",
+    "binary/octet-stream",
+    "application/unknown",
+    "application/x-unknown",
+];
+
+fn binary_signature(body: &[u8]) -> Option<&'static str> {
+    BINARY_SIGNATURES
+        .iter()
+        .find(|(offset, magic, _, _)| body.get(*offset..offset + magic.len()) == Some(*magic))
+        .map(|(_, _, mime, _)| *mime)
+}
+
+fn markup_signature(body: &[u8]) -> Option<&'static str> {
+    let body = body.strip_prefix(b"\xef\xbb\xbf").unwrap_or(body);
+    let start = body.iter().position(|b| !b.is_ascii_whitespace())?;
+    let head = &body[start..body.len().min(start + 16)];
+    MARKUP_SIGNATURES
+        .iter()
+        .find(|(prefix, _, _)| {
+            head.len() >= prefix.len()
+                && head[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+        })
+        .map(|(_, mime, _)| *mime)
+}
+
+fn is_textual(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || mime.ends_with("javascript")
+        || mime.ends_with("json")
+        || mime.ends_with("xml")
+}
+
+/// The type `body` should be handled as when it is not the declared one
+///
+/// None when the declared type stands, or nothing is known about the body.
+pub fn sniff_mime(declared: Option<&str>, body: &[u8]) -> Option<&'static str> {
+    let declared = declared
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .filter(|mime| !mime.is_empty());
+    let sniffed = match declared.as_deref() {
+        Some(mime) if is_textual(mime) => binary_signature(body),
+        Some(mime) if !GENERIC_TYPES.contains(&mime) => None,
+        _ => binary_signature(body).or_else(|| markup_signature(body)),
+    };
+    sniffed.filter(|sniffed| declared.as_deref() != Some(*sniffed))
+}
+
+/// File extension for a sniffed type
+pub fn extension(mime: &str) -> Option<&'static str> {
+    BINARY_SIGNATURES
+        .iter()
+        .map(|(_, _, known, extension)| (*known, *extension))
+        .chain(
+            MARKUP_SIGNATURES
+                .iter()
+                .map(|(_, known, extension)| (*known, *extension)),
+        )
+        .find(|(known, _)| *known == mime)
+        .map(|(_, extension)| extension)
+}
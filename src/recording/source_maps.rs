@@ -0,0 +1,203 @@
+//! Source maps of beautified scripts
+//!
+//! A minified script names its source map in a trailing `//# sourceMappingURL=` comment
+//! or a `SourceMap` header, but browsers only fetch the map while DevTools is open. With
+//! `--source-maps`, batch processing stores the map of every script it beautifies next
+//! to the content file as `<file>.map` (`sourceMapFilePath`): from the recording when
+//! the map was requested during the session, from the `data:` URL when it is inline, and
+//! fetched from the origin otherwise. With `--source-map-sources`, the original sources
+//! the map embeds (`sourcesContent`) are also written under `<file>.sources/`
+//! (`sourcesDir`) to read and edit alongside the beautified file. Playback serves the
+//! beautified file: edits made to the original sources have to be carried over to it.
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::traits::{FileSystem, HttpClient};
+use crate::types::{HttpHeaders, Resource};
+use crate::utils::file_path_segment;
+
+/// The parts of a source map read to write out its sources
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+    #[serde(default)]
+    sources: Vec<Option<String>>,
+    #[serde(default)]
+    sources_content: Vec<Option<String>>,
+}
+
+/// Where the source maps of beautified scripts come from and what is kept of them
+#[derive(Clone)]
+pub struct SourceMaps {
+    client: Arc<dyn HttpClient>,
+    sources: bool,
+}
+
+impl SourceMaps {
+    /// Fetch maps that were not recorded with `client`
+    pub fn new(client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            client,
+            sources: false,
+        }
+    }
+
+    /// Also write the original sources each map embeds
+    pub fn with_sources(mut self, sources: bool) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Store the maps of `resources` that have a `source_map_url`, under the inventory
+    /// in `inventory_dir`; a map that cannot be had is skipped with a warning
+    pub async fn store_all<F: FileSystem>(
+        &self,
+        resources: &mut [Resource],
+        inventory_dir: &Path,
+        file_system: &F,
+    ) {
+        // Maps the browser requested during the session are not fetched again
+        let mut recorded: HashMap<String, String> = HashMap::new();
+        for resource in resources.iter() {
+            if let (Some(200), Some(path)) = (resource.status_code, &resource.content_file_path) {
+                recorded
+                    .entry(resource.url.clone())
+                    .or_insert_with(|| path.clone());
+            }
+        }
+
+        for resource in resources.iter_mut() {
+            let (Some(map_url), Some(script_path)) = (
+                resource.source_map_url.take(),
+                resource.content_file_path.clone(),
+            ) else {
+                continue;
+            };
+            if let Err(e) = self
+                .store(
+                    resource,
+                    &map_url,
+                    &script_path,
+                    &recorded,
+                    inventory_dir,
+                    file_system,
+                )
+                .await
+            {
+                warn!(
+                    "Failed to store the source map {} of {}: {}",
+                    map_url, resource.url, e
+                );
+            }
+        }
+    }
+
+    async fn store<F: FileSystem>(
+        &self,
+        resource: &mut Resource,
+        map_url: &str,
+        script_path: &str,
+        recorded: &HashMap<String, String>,
+        inventory_dir: &Path,
+        file_system: &F,
+    ) -> Result<()> {
+        let map = match (decode_data_url(map_url), recorded.get(map_url)) {
+            (Some(map), _) => map?,
+            (None, Some(path)) => file_system.read(&inventory_dir.join(path)).await?,
+            (None, None) => {
+                let response = self.client.request("GET", map_url, None, None).await?;
+                if response.status_code != 200 {
+                    bail!("Status {}", response.status_code);
+                }
+                response.body
+            }
+        };
+        let parsed: RawSourceMap = serde_json::from_slice(&map).context("Not a source map")?;
+
+        let map_path = format!("{}.map", script_path);
+        file_system
+            .write(&inventory_dir.join(&map_path), &map)
+            .await?;
+        resource.source_map_file_path = Some(map_path);
+
+        if !self.sources {
+            return Ok(());
+        }
+        let sources_dir = format!("{}.sources", script_path);
+        let mut written = 0;
+        for (source, content) in parsed.sources.iter().zip(&parsed.sources_content) {
+            let (Some(source), Some(content)) = (source, content) else {
+                continue;
+            };
+            let Some(relative) = source_file_path(source) else {
+                continue;
+            };
+            let full_path = inventory_dir.join(&sources_dir).join(relative);
+            if let Some(parent) = full_path.parent() {
+                file_system.create_dir_all(parent).await?;
+            }
+            file_system.write(&full_path, content.as_bytes()).await?;
+            written += 1;
+        }
+        if written > 0 {
+            resource.sources_dir = Some(sources_dir);
+        }
+        Ok(())
+    }
+}
+
+/// The absolute URL of the source map a script at `script_url` names, by its
+/// `SourceMap` header or its last `sourceMappingURL` comment
+pub fn source_map_url(
+    script_url: &str,
+    script: &str,
+    headers: Option<&HttpHeaders>,
+) -> Option<String> {
+    let header = headers.and_then(|headers| {
+        ["sourcemap", "x-sourcemap"]
+            .iter()
+            .find_map(|name| headers.get(name))
+            .map(|value| value.first().trim().to_string())
+    });
+    let comment = || {
+        script.lines().rev().find_map(|line| {
+            let line = line.trim();
+            line.strip_prefix("//# sourceMappingURL=")
+                .or_else(|| line.strip_prefix("//@ sourceMappingURL="))
+                .map(|url| url.trim().to_string())
+        })
+    };
+    let reference = header.or_else(comment).filter(|url| !url.is_empty())?;
+    let base = url::Url::parse(script_url).ok()?;
+    base.join(&reference).ok().map(String::from)
+}
+
+/// The contents of a `data:` URL, or None when `url` is not one
+fn decode_data_url(url: &str) -> Option<Result<Vec<u8>>> {
+    let (meta, data) = url.strip_prefix("data:")?.split_once(',')?;
+    Some(if meta.ends_with(";base64") {
+        use base64::{Engine as _, engine::general_purpose};
+        general_purpose::STANDARD
+            .decode(data)
+            .context("Invalid base64 in data URL")
+    } else {
+        Ok(urlencoding::decode_binary(data.as_bytes()).into_owned())
+    })
+}
+
+/// Where a map's source is written under the sources directory: its path without
+/// scheme, empty, `.` or `..` segments, so every source stays inside
+fn source_file_path(source: &str) -> Option<String> {
+    let path = source.split_once("://").map_or(source, |(_, path)| path);
+    let segments: Vec<String> = path
+        .split(['/', '\\'])
+        .filter(|segment| !matches!(*segment, "" | "." | ".."))
+        .map(file_path_segment)
+        .collect();
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
@@ -0,0 +1,119 @@
+//! Bodies too large to hold in memory while recording
+//!
+//! The handler reads a whole response body before forwarding it, so the transfer is
+//! timed as the origin sent it, and keeps it until batch processing at shutdown. A
+//! download of a few hundred MB held that way exhausts memory. Once a body grows past
+//! `--spill-threshold`, what arrived so far and every later frame go to a file in the
+//! spool instead. Each frame's arrival is noted before it is written, so disk speed
+//! does not count as transfer time. The client is then
+//! sent the body from that file, and batch processing copies it into `contents/`
+//! rather than loading it, unless it has to be decoded. Should writing fail, the file
+//! is read back and the body held in memory after all.
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::{Stream, stream};
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::integrity::BodyDigester;
+use crate::types::BodyDigest;
+
+pub const DEFAULT_SPILL_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// How much of a spilled body is read at a time when forwarding it
+const READ_CHUNK: usize = 64 * 1024;
+
+/// A body being written to disk as it arrives
+#[derive(Debug)]
+pub struct SpillFile {
+    path: PathBuf,
+    file: tokio::fs::File,
+    digester: BodyDigester,
+    // Bytes known to be in the file, i.e. those of writes that succeeded
+    written: u64,
+}
+
+/// A body that was written to disk whole
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpilledBody {
+    pub path: PathBuf,
+    pub digest: BodyDigest,
+}
+
+impl SpillFile {
+    /// Start writing a body to `path` with the `received` bytes
+    pub async fn create(path: PathBuf, received: &[u8]) -> Result<Self> {
+        let file = tokio::fs::File::create(&path)
+            .await
+            .with_context(|| format!("Failed to create {:?}", path))?;
+        let mut spill = Self {
+            path,
+            file,
+            digester: BodyDigester::default(),
+            written: 0,
+        };
+        spill.write(received).await?;
+        Ok(spill)
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.digester.update(data);
+        self.file
+            .write_all(data)
+            .await
+            .with_context(|| format!("Failed to write {:?}", self.path))?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    pub async fn finish(&mut self) -> Result<SpilledBody> {
+        self.file
+            .flush()
+            .await
+            .with_context(|| format!("Failed to write {:?}", self.path))?;
+        Ok(SpilledBody {
+            path: self.path.clone(),
+            digest: self.digester.finish(),
+        })
+    }
+
+    /// Read back what was written, after a write failed, and remove the file
+    pub async fn reclaim(mut self) -> Result<Vec<u8>> {
+        let _ = self.file.flush().await;
+        drop(self.file);
+        let read = tokio::fs::read(&self.path).await;
+        let _ = tokio::fs::remove_file(&self.path).await;
+        let mut body = read.with_context(|| format!("Failed to read back {:?}", self.path))?;
+        anyhow::ensure!(
+            body.len() as u64 >= self.written,
+            "{:?} holds {} of the {} bytes written",
+            self.path,
+            body.len(),
+            self.written
+        );
+        // A failed write may have left part of its data behind
+        body.truncate(self.written as usize);
+        Ok(body)
+    }
+}
+
+/// The contents of a spilled body, read a chunk at a time as the client takes them
+pub fn read_chunks(path: PathBuf) -> impl Stream<Item = std::io::Result<Bytes>> + Send + Sync {
+    stream::try_unfold(None, move |file: Option<tokio::fs::File>| {
+        let path = path.clone();
+        async move {
+            let mut file = match file {
+                Some(file) => file,
+                None => tokio::fs::File::open(&path).await?,
+            };
+            let mut buffer = vec![0; READ_CHUNK];
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok(None);
+            }
+            buffer.truncate(read);
+            Ok(Some((Bytes::from(buffer), Some(file))))
+        }
+    })
+}
@@ -0,0 +1,175 @@
+//! On-disk spool of recorded responses
+//!
+//! Responses are kept in memory until shutdown, when batch processing writes the
+//! inventory. If the process dies before that, the spool under `<inventory>/.spool`
+//! still holds every response recorded so far: the body as received and the resource
+//! as one JSON line. Bodies too large to hold in memory are written into it as they
+//! arrive. The `recover` command turns it into a regular inventory.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, Semaphore};
+use tracing::warn;
+
+use crate::traits::FileSystem;
+use crate::types::{Inventory, Resource};
+
+pub const SPOOL_DIR_NAME: &str = ".spool";
+const SESSION_FILE: &str = "session.json";
+const RESOURCES_FILE: &str = "resources.jsonl";
+const BODIES_DIR: &str = "bodies";
+
+/// One line of resources.jsonl
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpooledResource {
+    #[serde(flatten)]
+    resource: Resource,
+    // Relative to the spool directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_file: Option<String>,
+}
+
+pub fn spool_dir(inventory_dir: &Path) -> PathBuf {
+    inventory_dir.join(SPOOL_DIR_NAME)
+}
+
+/// Appends recorded responses to the spool as they complete
+#[derive(Debug)]
+pub struct Spool {
+    dir: PathBuf,
+    resources: Mutex<tokio::fs::File>,
+    counter: AtomicU64,
+    // Caps concurrent body writes so bursts of responses do not exhaust file handles
+    write_permits: Option<Semaphore>,
+}
+
+impl Spool {
+    /// Start a spool for a new session, refusing to overwrite one that was never recovered
+    pub async fn create(
+        inventory_dir: &Path,
+        session: &Inventory,
+        max_open_files: Option<usize>,
+    ) -> Result<Arc<Self>> {
+        let dir = spool_dir(inventory_dir);
+        if tokio::fs::try_exists(dir.join(RESOURCES_FILE)).await? {
+            anyhow::bail!(
+                "{:?} holds an unfinished recording. Run `recover` to turn it into an inventory, \
+                 or delete the directory to discard it",
+                dir
+            );
+        }
+
+        tokio::fs::create_dir_all(dir.join(BODIES_DIR))
+            .await
+            .with_context(|| format!("Failed to create spool directory {:?}", dir))?;
+        tokio::fs::write(dir.join(SESSION_FILE), serde_json::to_vec(session)?).await?;
+        let resources = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(RESOURCES_FILE))
+            .await?;
+
+        Ok(Arc::new(Self {
+            dir,
+            resources: Mutex::new(resources),
+            counter: AtomicU64::new(0),
+            write_permits: max_open_files.map(|n| Semaphore::new(n.max(1))),
+        }))
+    }
+
+    /// A new file in the spool for a body written as it arrives
+    pub fn body_path(&self) -> PathBuf {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        self.dir.join(format!("{}/{:06}.bin", BODIES_DIR, n))
+    }
+
+    /// Persist a recorded resource and its body (taken from `raw_body`, or already in
+    /// the spool at `raw_body_file`)
+    pub async fn append(&self, resource: &Resource) -> Result<()> {
+        let body_file = match (&resource.raw_body, &resource.raw_body_file) {
+            (Some(body), _) => {
+                let n = self.counter.fetch_add(1, Ordering::Relaxed);
+                let relative = format!("{}/{:06}.bin", BODIES_DIR, n);
+                let _permit = match &self.write_permits {
+                    Some(permits) => Some(permits.acquire().await?),
+                    None => None,
+                };
+                tokio::fs::write(self.dir.join(&relative), body).await?;
+                Some(relative)
+            }
+            (None, Some(path)) => path
+                .strip_prefix(&self.dir)
+                .ok()
+                .map(|relative| relative.to_string_lossy().replace('\\', "/")),
+            (None, None) => None,
+        };
+
+        let mut line = serde_json::to_vec(&SpooledResource {
+            resource: resource.clone(),
+            body_file,
+        })?;
+        line.push(b'\n');
+
+        // The body is on disk before the line that refers to it
+        let mut resources = self.resources.lock().await;
+        resources.write_all(&line).await?;
+        resources.flush().await?;
+        Ok(())
+    }
+
+    /// Discard the spool once the inventory has been saved
+    pub async fn remove(&self) -> Result<()> {
+        tokio::fs::remove_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Failed to remove spool directory {:?}", self.dir))
+    }
+}
+
+/// Rebuild the in-memory inventory of an interrupted session, bodies included
+///
+/// A truncated last line (the process died mid-write) is skipped with a warning.
+pub async fn load_spool<F: FileSystem>(
+    inventory_dir: &Path,
+    file_system: Arc<F>,
+) -> Result<Inventory> {
+    let dir = spool_dir(inventory_dir);
+    let resources_path = dir.join(RESOURCES_FILE);
+    if !file_system.exists(&resources_path).await {
+        anyhow::bail!("No spooled recording found in {:?}", dir);
+    }
+
+    let mut inventory: Inventory = match file_system.read_to_string(&dir.join(SESSION_FILE)).await {
+        Ok(session) => serde_json::from_str(&session)?,
+        Err(_) => Inventory::new(),
+    };
+    inventory.resources.clear();
+
+    let content = file_system.read_to_string(&resources_path).await?;
+    for (line_number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let spooled: SpooledResource = match serde_json::from_str(line) {
+            Ok(spooled) => spooled,
+            Err(e) => {
+                warn!("Skipping unreadable spool line {}: {}", line_number + 1, e);
+                continue;
+            }
+        };
+        let mut resource = spooled.resource;
+        if let Some(body_file) = spooled.body_file {
+            match file_system.read(&dir.join(&body_file)).await {
+                Ok(body) => resource.raw_body = Some(body),
+                Err(e) => warn!("Missing spooled body for {}: {}", resource.url, e),
+            }
+        }
+        inventory.resources.push(resource);
+    }
+
+    Ok(inventory)
+}
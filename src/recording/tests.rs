@@ -96,4 +96,1308 @@ mod recording_tests {
         let invalid = ContentEncodingType::from_str("invalid-encoding");
         assert!(invalid.is_err());
     }
+
+    #[tokio::test]
+    async fn test_batch_processor_repeated_urls_get_distinct_files() {
+        use crate::recording::batch_processor::BatchProcessor;
+        use crate::traits::mocks::{MockFileSystem, MockTimeProvider};
+        use crate::types::Resource;
+        use std::sync::Arc;
+
+        let inventory_dir = std::path::PathBuf::from("/inventory");
+        let mock_fs = Arc::new(MockFileSystem::new());
+        let processor = BatchProcessor::new(
+            inventory_dir.clone(),
+            mock_fs.clone(),
+            Arc::new(MockTimeProvider::new(0)),
+        );
+
+        let mut inventory = Inventory::new();
+        for (ttfb, body) in [(10, b"first".to_vec()), (20, b"second".to_vec())] {
+            let mut resource =
+                Resource::new("GET".to_string(), "https://example.com/poll".to_string());
+            resource.ttfb_ms = ttfb;
+            resource.raw_body = Some(body);
+            inventory.resources.push(resource);
+        }
+        inventory.resources.push(Resource::new(
+            "GET".to_string(),
+            "https://example.com/once".to_string(),
+        ));
+
+        processor.process_all(&mut inventory).await.unwrap();
+
+        let first = &inventory.resources[0];
+        let second = &inventory.resources[1];
+        assert_eq!(first.occurrence, Some(0));
+        assert_eq!(second.occurrence, Some(1));
+        assert_eq!(first.ttfb_ms, 10);
+        assert_eq!(second.ttfb_ms, 20);
+        assert_eq!(
+            first.content_file_path.as_deref(),
+            Some("contents/get/https/example.com/poll")
+        );
+        assert_eq!(
+            second.content_file_path.as_deref(),
+            Some("contents/get/https/example.com/poll@1")
+        );
+        assert_eq!(inventory.resources[2].occurrence, None);
+
+        let first_file = inventory_dir.join("contents/get/https/example.com/poll");
+        let second_file = inventory_dir.join("contents/get/https/example.com/poll@1");
+        assert_eq!(
+            mock_fs.get_file(&first_file.to_string_lossy()),
+            Some(b"first".to_vec())
+        );
+        assert_eq!(
+            mock_fs.get_file(&second_file.to_string_lossy()),
+            Some(b"second".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_processor_charset_policies() {
+        use crate::recording::batch_processor::{BatchProcessor, CharsetPolicy};
+        use crate::traits::mocks::{MockFileSystem, MockTimeProvider};
+        use crate::types::{HttpHeaders, Resource};
+        use std::sync::Arc;
+
+        let css = "a{content:\"こんにちは\"}";
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode(css);
+        let process = |policy: CharsetPolicy| {
+            let sjis = sjis.to_vec();
+            async move {
+                let mock_fs = Arc::new(MockFileSystem::new());
+                let mut resource =
+                    Resource::new("GET".to_string(), "https://example.com/a.css".to_string());
+                let mut headers = HttpHeaders::new();
+                headers.append("Content-Type", "text/css; charset=Shift_JIS".to_string());
+                resource.raw_headers = Some(headers);
+                resource.content_type_mime = Some("text/css".to_string());
+                resource.raw_body = Some(sjis);
+                let mut inventory = Inventory::new();
+                inventory.resources.push(resource);
+                BatchProcessor::new(
+                    "/inventory".into(),
+                    mock_fs.clone(),
+                    Arc::new(MockTimeProvider::new(0)),
+                )
+                .with_charset(policy)
+                .process_all(&mut inventory)
+                .await
+                .unwrap();
+                let file = |path: Option<&String>| {
+                    path.and_then(|p| mock_fs.get_file(&format!("/inventory/{}", p)))
+                };
+                let resource = inventory.resources.remove(0);
+                let content = file(resource.content_file_path.as_ref()).unwrap();
+                let original = file(resource.original_file_path.as_ref());
+                (resource, content, original)
+            }
+        };
+
+        // Converted to UTF-8 and re-encoded when served
+        let (resource, content, original) = process(CharsetPolicy::Utf8).await;
+        assert!(String::from_utf8(content).unwrap().contains("こんにちは"));
+        assert_eq!(resource.reencode_charset(), Some("Shift_JIS"));
+        assert_eq!(original, None);
+
+        // Kept byte for byte, with the charset as a note
+        let (resource, content, original) = process(CharsetPolicy::Original).await;
+        assert_eq!(content, sjis.to_vec());
+        assert_eq!(resource.content_charset.as_deref(), Some("Shift_JIS"));
+        assert_eq!(resource.charset_preserved, Some(true));
+        assert_eq!(resource.reencode_charset(), None);
+        assert_eq!(resource.minify, None);
+        assert_eq!(original, None);
+
+        let (resource, content, original) = process(CharsetPolicy::Both).await;
+        assert!(String::from_utf8(content).unwrap().contains("こんにちは"));
+        assert_eq!(resource.reencode_charset(), Some("Shift_JIS"));
+        assert_eq!(
+            resource.original_file_path.as_deref(),
+            Some("originals/get/https/example.com/a.css")
+        );
+        assert_eq!(original, Some(sjis.to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_batch_processor_saves_uploaded_files() {
+        use crate::recording::batch_processor::BatchProcessor;
+        use crate::traits::mocks::{MockFileSystem, MockTimeProvider};
+        use crate::types::{FormPart, Resource};
+        use std::sync::Arc;
+
+        let inventory_dir = std::path::PathBuf::from("/inventory");
+        let mock_fs = Arc::new(MockFileSystem::new());
+        let processor = BatchProcessor::new(
+            inventory_dir.clone(),
+            mock_fs.clone(),
+            Arc::new(MockTimeProvider::new(0)),
+        );
+
+        let part =
+            |name: &str, value: Option<&str>, filename: Option<&str>, data: Option<&[u8]>| {
+                FormPart {
+                    name: name.to_string(),
+                    value: value.map(str::to_string),
+                    filename: filename.map(str::to_string),
+                    content_type: None,
+                    file_path: None,
+                    size: 0,
+                    data: data.map(<[u8]>::to_vec),
+                }
+            };
+        let mut resource =
+            Resource::new("POST".to_string(), "https://example.com/upload".to_string());
+        resource.request_form = Some(vec![
+            part("title", Some("Cat"), None, None),
+            part(
+                "photo",
+                None,
+                Some("C:\\Users\\me\\cat.jpg"),
+                Some(&b"jpeg"[..]),
+            ),
+            part("blob", None, None, Some(&b"\xff"[..])),
+        ]);
+        let mut inventory = Inventory::new();
+        inventory.resources.push(resource);
+
+        processor.process_all(&mut inventory).await.unwrap();
+
+        let form = inventory.resources[0].request_form.as_ref().unwrap();
+        assert_eq!(form[0].file_path, None);
+        assert_eq!(
+            form[1].file_path.as_deref(),
+            Some("uploads/post/https/example.com/upload/1-cat.jpg")
+        );
+        assert_eq!(
+            form[2].file_path.as_deref(),
+            Some("uploads/post/https/example.com/upload/2.bin")
+        );
+        assert!(form.iter().all(|part| part.data.is_none()));
+        assert_eq!(
+            mock_fs.get_file("/inventory/uploads/post/https/example.com/upload/1-cat.jpg"),
+            Some(b"jpeg".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_time_is_charged_to_one_request() {
+        use crate::recording::connect_timing::{ConnectTimings, TimedConnector};
+        use hyper_util::client::legacy::connect::{Connected, Connection};
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+        use tower::ServiceExt;
+
+        // A connection without TCP details, as a test double gives
+        struct Opened;
+        impl Connection for Opened {
+            fn connected(&self) -> Connected {
+                Connected::new()
+            }
+        }
+
+        let timings = Arc::new(ConnectTimings::default());
+        let connector = TimedConnector::new(
+            tower::service_fn(|_uri: hyper::Uri| async {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                Ok::<_, std::io::Error>(Opened)
+            }),
+            timings.clone(),
+        );
+
+        let request_start = Instant::now();
+        connector
+            .oneshot("https://example.com/".parse().unwrap())
+            .await
+            .unwrap();
+
+        // Another origin, or a request sent after the connection opened, pays nothing
+        assert_eq!(timings.take("other.example", request_start), None);
+        assert_eq!(timings.take("example.com", Instant::now()), None);
+
+        let connect = timings.take("example.com", request_start).unwrap();
+        assert!(connect >= Duration::from_millis(30), "{:?}", connect);
+        // A reused connection is not charged again
+        assert_eq!(timings.take("example.com", request_start), None);
+    }
+
+    #[tokio::test]
+    async fn test_origin_addresses_per_host() {
+        use crate::recording::origin_addresses::{OriginAddresses, RecordingResolver};
+        use crate::types::IpVersion;
+        use hyper_util::client::legacy::connect::dns::Name;
+        use std::net::SocketAddr;
+        use std::str::FromStr;
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let addresses = Arc::new(OriginAddresses::default());
+        let v4: SocketAddr = "192.0.2.10:443".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::10]:443".parse().unwrap();
+        addresses.resolved("cdn.example", &[v4, v6, v4]);
+        addresses.connected("cdn.example", v4);
+        addresses.connected("cdn.example", v4);
+        addresses.connected("api.example", v6);
+
+        let origins = addresses.summary();
+        assert_eq!(origins[0].host, "cdn.example");
+        assert_eq!(origins[0].resolved, vec!["192.0.2.10", "2001:db8::10"]);
+        assert_eq!(origins[0].connected, vec!["192.0.2.10"]);
+        assert_eq!(origins[0].ip_version, Some(IpVersion::Ipv4));
+        assert!(origins[1].resolved.is_empty());
+        assert_eq!(origins[1].ip_version, Some(IpVersion::Ipv6));
+
+        addresses.connected("api.example", "198.51.100.7:443".parse().unwrap());
+        assert_eq!(addresses.summary()[1].ip_version, Some(IpVersion::Both));
+
+        // The resolver passes its lookups on and notes them
+        let resolved: Vec<SocketAddr> = RecordingResolver::new(addresses.clone())
+            .oneshot(Name::from_str("localhost").unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert!(!resolved.is_empty());
+        let localhost = addresses.summary();
+        let localhost = localhost.iter().find(|o| o.host == "localhost").unwrap();
+        assert!(!localhost.resolved.is_empty());
+    }
+
+    #[test]
+    fn test_connection_usage_per_host() {
+        use crate::recording::client_connections::ConnectionUsage;
+        use hyper::Version;
+
+        let usage = ConnectionUsage::default();
+        let first = "127.0.0.1:50001".parse().unwrap();
+        let second = "127.0.0.1:50002".parse().unwrap();
+        usage.request("www.example.com", first, Version::HTTP_11);
+        usage.request("www.example.com", second, Version::HTTP_11);
+        usage.request("www.example.com", first, Version::HTTP_11);
+        usage.request("cdn.example.com", second, Version::HTTP_2);
+
+        let hosts = usage.summary();
+        assert_eq!(hosts[0].host, "www.example.com");
+        assert_eq!(hosts[0].connections, 2);
+        assert_eq!(hosts[0].requests, vec![2, 1]);
+        assert!(!hosts[0].multiplexed);
+        assert_eq!(hosts[1].connections, 1);
+        assert!(hosts[1].multiplexed);
+    }
+
+    #[tokio::test]
+    async fn test_responses_correlate_with_their_own_request() {
+        use crate::recording::hudsucker_handler::RecordingHandler;
+        use hudsucker::hyper::{Request, Response};
+        use hudsucker::{Body, HttpContext, HttpHandler, RequestOrResponse};
+
+        let handler = RecordingHandler::new(Inventory::new());
+        let ctx = HttpContext {
+            client_addr: "127.0.0.1:50001".parse().unwrap(),
+            request_method: hyper::Method::GET,
+            request_uri: "https://example.com/api".parse().unwrap(),
+        };
+        let request = |user_agent: &str| {
+            Request::builder()
+                .uri("https://example.com/api")
+                .header("user-agent", user_agent)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // Two identical requests in flight on one multiplexed connection, hudsucker
+        // handling each with its own clone of the handler
+        let mut first = handler.clone();
+        let mut second = handler.clone();
+        for (clone, user_agent) in [(&mut first, "first"), (&mut second, "second")] {
+            let forwarded = clone.handle_request(&ctx, request(user_agent)).await;
+            assert!(matches!(forwarded, RequestOrResponse::Request(_)));
+        }
+
+        // Answered in reverse order
+        second
+            .handle_response(&ctx, Response::new(Body::from("second")))
+            .await;
+        first
+            .handle_response(&ctx, Response::new(Body::from("first")))
+            .await;
+
+        let inventory = handler.get_inventory();
+        let inventory = inventory.lock().await;
+        assert_eq!(inventory.resources.len(), 2);
+        for resource in &inventory.resources {
+            let body = String::from_utf8(resource.raw_body.clone().unwrap()).unwrap();
+            let user_agent = resource.request_client.as_ref().unwrap().user_agent.clone();
+            assert_eq!(user_agent, Some(body));
+            assert!(resource.request_start_ms.is_some());
+        }
+    }
+
+    #[test]
+    fn test_integrity_discrepancies() {
+        use crate::recording::integrity::{BodyDigester, digest, discrepancy};
+
+        let forwarded = digest(b"body { color: red }");
+        assert_eq!(forwarded.size, 19);
+        assert_eq!(discrepancy(&forwarded, b"body { color: red }"), None);
+
+        // A truncated body and one with the same length but other bytes
+        assert_eq!(
+            discrepancy(&forwarded, b"body { color"),
+            Some("12 bytes instead of 19".to_string())
+        );
+        assert!(
+            discrepancy(&forwarded, b"body { color: rod }")
+                .unwrap()
+                .starts_with("same length")
+        );
+
+        // Frames digest to the same as the whole body
+        let mut digester = BodyDigester::default();
+        digester.update(b"body { ");
+        digester.update(b"color: red }");
+        assert_eq!(digester.finish(), forwarded);
+    }
+
+    #[tokio::test]
+    async fn test_upload_progress_finishes_with_last_frame() {
+        use crate::recording::upload_timing::{TimedUpload, UploadProgress};
+        use http_body_util::{BodyExt, Full};
+        use std::sync::Arc;
+
+        let progress = Arc::new(UploadProgress::default());
+        let body = TimedUpload::new(
+            Full::new(bytes::Bytes::from_static(b"name=value&file=...")),
+            progress.clone(),
+        );
+        assert_eq!(progress.finished(), None);
+
+        let sent = body.collect().await.unwrap().to_bytes();
+        assert_eq!(progress.bytes(), sent.len() as u64);
+        assert!(progress.finished().is_some());
+
+        assert_eq!(
+            progress.sha1(),
+            Some(crate::check::sha1_hex(&sent).as_str())
+        );
+
+        let buffered = UploadProgress::completed(b"name=value");
+        assert_eq!(buffered.bytes(), 10);
+        assert!(buffered.finished().is_some());
+        assert_eq!(
+            buffered.sha1(),
+            Some(crate::check::sha1_hex(b"name=value").as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_opaque_tunnel_counts_relayed_bytes() {
+        use crate::recording::opaque_tunnel::{OpaqueHosts, OpaqueTunnels};
+        use http_body_util::Empty;
+        use hyper::{Response, server::conn::http1, service::service_fn};
+        use hyper_util::rt::TokioIo;
+        use std::sync::Arc;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        let hosts = OpaqueHosts::new(&[r"^pinned\.".to_string()]).unwrap();
+        assert!(hosts.matches("pinned.example.com"));
+        assert!(!hosts.matches("cdn.example.com"));
+        assert!(OpaqueHosts::new(&["(".to_string()]).is_err());
+
+        // An origin answering every write with the same bytes
+        let origin = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let origin_addr = origin.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = origin.accept().await.unwrap();
+            let mut buf = [0u8; 64];
+            while let Ok(n) = socket.read(&mut buf).await {
+                if n == 0 || socket.write_all(&buf[..n]).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let tunnels = Arc::new(OpaqueTunnels::default());
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        let relay = tunnels.clone();
+        tokio::spawn(async move {
+            let (socket, _) = proxy.accept().await.unwrap();
+            let service = service_fn(move |req| {
+                relay.relay(req);
+                async {
+                    Ok::<_, std::convert::Infallible>(Response::new(Empty::<bytes::Bytes>::new()))
+                }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(socket), service)
+                .with_upgrades()
+                .await;
+        });
+
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        let connect = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", origin_addr);
+        client.write_all(connect.as_bytes()).await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            client.read_exact(&mut byte).await.unwrap();
+            head.push(byte[0]);
+        }
+        assert!(head.starts_with(b"HTTP/1.1 200"));
+
+        client.write_all(b"opaque bytes").await.unwrap();
+        let mut echoed = [0u8; 12];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"opaque bytes");
+        drop(client);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let summary = tunnels.summary(std::time::Instant::now());
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].host, "127.0.0.1");
+        assert_eq!(summary[0].connections, 1);
+        assert_eq!(summary[0].bytes_sent, 12);
+        assert_eq!(summary[0].bytes_received, 12);
+    }
+
+    #[tokio::test]
+    async fn test_spool_round_trip() {
+        use crate::recording::spool::{Spool, load_spool, spool_dir};
+        use crate::traits::RealFileSystem;
+        use crate::types::Resource;
+        use std::io::Write;
+
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+
+        let mut session = Inventory::new();
+        session.entry_url = Some("https://example.com/".to_string());
+        session.device_type = Some(DeviceType::Mobile);
+
+        let spool = Spool::create(&inventory_dir, &session, Some(2))
+            .await
+            .unwrap();
+        let mut page = Resource::new("GET".to_string(), "https://example.com/".to_string());
+        page.status_code = Some(200);
+        page.raw_body = Some(b"<html></html>".to_vec());
+        spool.append(&page).await.unwrap();
+        let redirect = Resource::new("GET".to_string(), "https://example.com/old".to_string());
+        spool.append(&redirect).await.unwrap();
+
+        // A second session must not overwrite the unrecovered one
+        assert!(Spool::create(&inventory_dir, &session, None).await.is_err());
+
+        // Simulate a crash in the middle of writing a line
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(spool_dir(&inventory_dir).join("resources.jsonl"))
+            .unwrap();
+        file.write_all(b"{\"method\":\"GET\",\"url\":").unwrap();
+
+        let recovered = load_spool(&inventory_dir, std::sync::Arc::new(RealFileSystem))
+            .await
+            .unwrap();
+        assert_eq!(recovered.entry_url.as_deref(), Some("https://example.com/"));
+        assert_eq!(recovered.device_type, Some(DeviceType::Mobile));
+        assert_eq!(recovered.resources.len(), 2);
+        assert_eq!(
+            recovered.resources[0].raw_body.as_deref(),
+            Some(&b"<html></html>"[..])
+        );
+        assert_eq!(recovered.resources[0].status_code, Some(200));
+        assert!(recovered.resources[1].raw_body.is_none());
+
+        spool.remove().await.unwrap();
+        assert!(!spool_dir(&inventory_dir).exists());
+    }
+
+    fn text_resource(path: &str, body: &str, ttfb_ms: u64) -> crate::types::Resource {
+        let mut resource =
+            crate::types::Resource::new("GET".to_string(), format!("https://example.com{}", path));
+        resource.status_code = Some(200);
+        resource.ttfb_ms = ttfb_ms;
+        resource.content_type_mime = Some("text/plain".to_string());
+        resource.raw_body = Some(body.as_bytes().to_vec());
+        resource
+    }
+
+    #[tokio::test]
+    async fn test_refresh_keeps_unchanged_resources() {
+        use crate::recording::batch_processor::{BatchProcessor, CharsetPolicy};
+        use crate::recording::refresh::{
+            RefreshOptions, RefreshPolicy, RefreshSummary, refresh_inventory,
+        };
+        use crate::traits::FileSystem;
+        use crate::traits::mocks::{MockFileSystem, MockTimeProvider};
+        use std::path::Path;
+        use std::sync::Arc;
+
+        let file_system = Arc::new(MockFileSystem::new());
+        let inventory_dir = Path::new("/inventory");
+
+        let mut existing = Inventory::new();
+        existing.resources = vec![
+            text_resource("/a.txt", "A", 10),
+            text_resource("/b.txt", "B", 20),
+            text_resource("/c.txt", "C", 30),
+        ];
+        BatchProcessor::new(
+            inventory_dir.to_path_buf(),
+            file_system.clone(),
+            Arc::new(MockTimeProvider::new(0)),
+        )
+        .process_all(&mut existing)
+        .await
+        .unwrap();
+
+        let mut session = Inventory::new();
+        session.resources = vec![
+            text_resource("/a.txt", "A", 99),
+            text_resource("/b.txt", "B2", 99),
+            text_resource("/d.txt", "D", 99),
+        ];
+
+        let (merged, summary) = refresh_inventory(
+            existing,
+            &mut session,
+            inventory_dir,
+            file_system.clone(),
+            RefreshOptions {
+                inline_base64_max_bytes: None,
+                charset: CharsetPolicy::Utf8,
+                policy: RefreshPolicy::Changed,
+            },
+            chrono::Utc::now(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            summary,
+            RefreshSummary {
+                unchanged: 1,
+                updated: 1,
+                added: 1,
+                kept: 1,
+            }
+        );
+        let urls: Vec<&str> = merged.resources.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://example.com/a.txt",
+                "https://example.com/b.txt",
+                "https://example.com/c.txt",
+                "https://example.com/d.txt",
+            ]
+        );
+        // Unchanged keeps its recorded timing, changed takes the new one
+        assert_eq!(merged.resources[0].ttfb_ms, 10);
+        assert_eq!(merged.resources[1].ttfb_ms, 99);
+        assert_eq!(merged.resources[2].ttfb_ms, 30);
+
+        let b_path = inventory_dir.join(merged.resources[1].content_file_path.as_ref().unwrap());
+        assert_eq!(file_system.read(&b_path).await.unwrap(), b"B2");
+        let d_path = inventory_dir.join(merged.resources[3].content_file_path.as_ref().unwrap());
+        assert_eq!(file_system.read(&d_path).await.unwrap(), b"D");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_expired_policy() {
+        use crate::recording::batch_processor::{BatchProcessor, CharsetPolicy};
+        use crate::recording::refresh::{RefreshOptions, RefreshPolicy, refresh_inventory};
+        use crate::traits::FileSystem;
+        use crate::traits::mocks::{MockFileSystem, MockTimeProvider};
+        use crate::types::{HeaderValue, HttpHeaders};
+        use std::path::Path;
+        use std::sync::Arc;
+
+        fn with_headers(
+            mut resource: crate::types::Resource,
+            headers: &[(&str, &str)],
+        ) -> crate::types::Resource {
+            let mut raw_headers = HttpHeaders::new();
+            for (name, value) in headers {
+                raw_headers.set(name, HeaderValue::Single(value.to_string()));
+            }
+            resource.raw_headers = Some(raw_headers);
+            resource
+        }
+
+        let file_system = Arc::new(MockFileSystem::new());
+        let inventory_dir = Path::new("/inventory");
+        let date = "Mon, 01 Jan 2024 00:00:00 GMT";
+
+        let mut existing = Inventory::new();
+        existing.resources = vec![
+            with_headers(
+                text_resource("/fresh.txt", "A", 10),
+                &[("date", date), ("cache-control", "public, max-age=86400")],
+            ),
+            with_headers(
+                text_resource("/stale.txt", "B", 20),
+                &[("date", date), ("cache-control", "max-age=60")],
+            ),
+        ];
+        BatchProcessor::new(
+            inventory_dir.to_path_buf(),
+            file_system.clone(),
+            Arc::new(MockTimeProvider::new(0)),
+        )
+        .process_all(&mut existing)
+        .await
+        .unwrap();
+
+        let mut session = Inventory::new();
+        session.resources = vec![
+            text_resource("/fresh.txt", "A2", 99),
+            text_resource("/stale.txt", "B", 99),
+        ];
+
+        // One hour after the recording: only the 60 second lifetime has run out
+        let now = chrono::DateTime::parse_from_rfc2822("Mon, 01 Jan 2024 01:00:00 GMT")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let (merged, summary) = refresh_inventory(
+            existing,
+            &mut session,
+            inventory_dir,
+            file_system.clone(),
+            RefreshOptions {
+                inline_base64_max_bytes: None,
+                charset: CharsetPolicy::Utf8,
+                policy: RefreshPolicy::Expired,
+            },
+            now,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((summary.unchanged, summary.updated), (1, 1));
+        assert_eq!(merged.resources[0].ttfb_ms, 10);
+        assert_eq!(merged.resources[1].ttfb_ms, 99);
+
+        // The fresh resource's stored content is not overwritten by the session's
+        let a_path = inventory_dir.join(merged.resources[0].content_file_path.as_ref().unwrap());
+        assert_eq!(file_system.read(&a_path).await.unwrap(), b"A");
+    }
+
+    #[test]
+    fn test_fresh_until() {
+        use crate::recording::refresh::{fresh_until, is_expired};
+        use crate::types::{HeaderValue, HttpHeaders};
+
+        let resource_with = |headers: &[(&str, &str)]| {
+            let mut resource = text_resource("/x.txt", "x", 0);
+            let mut raw_headers = HttpHeaders::new();
+            for (name, value) in headers {
+                raw_headers.set(name, HeaderValue::Single(value.to_string()));
+            }
+            resource.raw_headers = Some(raw_headers);
+            resource
+        };
+        let at = |value: &str| {
+            chrono::DateTime::parse_from_rfc2822(value)
+                .unwrap()
+                .with_timezone(&chrono::Utc)
+        };
+        let date = "Mon, 01 Jan 2024 00:00:00 GMT";
+
+        // max-age counts from Date minus Age and wins over Expires
+        let resource = resource_with(&[
+            ("Date", date),
+            ("Cache-Control", "max-age=600"),
+            ("Age", "60"),
+            ("Expires", "Tue, 02 Jan 2024 00:00:00 GMT"),
+        ]);
+        assert_eq!(
+            fresh_until(&resource),
+            Some(at("Mon, 01 Jan 2024 00:09:00 GMT"))
+        );
+
+        let resource = resource_with(&[("Expires", "Tue, 02 Jan 2024 00:00:00 GMT")]);
+        assert_eq!(
+            fresh_until(&resource),
+            Some(at("Tue, 02 Jan 2024 00:00:00 GMT"))
+        );
+        assert!(!is_expired(&resource, at("Mon, 01 Jan 2024 12:00:00 GMT")));
+
+        // Never fresh: revalidation required or no lifetime at all
+        let resource = resource_with(&[("Date", date), ("Cache-Control", "no-cache, max-age=600")]);
+        assert_eq!(fresh_until(&resource), None);
+        assert!(is_expired(&resource, at(date)));
+        assert_eq!(fresh_until(&resource_with(&[("Date", date)])), None);
+    }
+
+    #[tokio::test]
+    async fn test_event_stream_capture() {
+        use crate::recording::event_stream::EventStreams;
+        use crate::types::Resource;
+        use bytes::Bytes;
+        use http_body::Frame;
+        use http_body_util::{BodyExt, StreamBody};
+        use std::time::Instant;
+
+        // The blank line ending the second event is split across frames
+        let frames = ["data: a\n\nda", "ta: b\r\n", "\r\ndata: c"]
+            .map(|part| Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from(part))));
+        let body = StreamBody::new(futures::stream::iter(frames));
+
+        let streams = EventStreams::default();
+        let resource = Resource::new("GET".to_string(), "https://example.com/events".to_string());
+        let captured = streams.capture(resource, Instant::now(), body);
+        let received = captured.collect().await.unwrap().to_bytes();
+        assert_eq!(&received[..], b"data: a\n\ndata: b\r\n\r\ndata: c");
+
+        let resources = streams.resources(Instant::now());
+        assert_eq!(resources.len(), 1);
+        let resource = &resources[0];
+        let sizes: Vec<u64> = resource
+            .event_stream
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|event| event.size)
+            .collect();
+        // The trailing bytes without a blank line are sent when the stream ended
+        assert_eq!(sizes, vec![9, 11, 7]);
+        assert_eq!(resource.raw_body.as_deref(), Some(&received[..]));
+        assert_eq!(resource.event_stream_open, None);
+
+        // A stream still open when recording stops is held open in playback
+        let open = EventStreams::default();
+        let _body = open.capture(
+            Resource::new("GET".to_string(), "https://example.com/live".to_string()),
+            Instant::now(),
+            http_body_util::Empty::<Bytes>::new(),
+        );
+        let resources = open.resources(Instant::now());
+        assert_eq!(resources[0].event_stream_open, Some(true));
+        assert_eq!(resources[0].event_stream.as_deref(), Some(&[][..]));
+    }
+
+    #[tokio::test]
+    async fn test_partial_responses_are_stitched() {
+        use crate::recording::batch_processor::BatchProcessor;
+        use crate::traits::mocks::{MockFileSystem, MockTimeProvider};
+        use crate::types::{ByteRange, HeaderValue, HttpHeaders, Resource};
+        use std::sync::Arc;
+
+        let inventory_dir = std::path::PathBuf::from("/inventory");
+        let mock_fs = Arc::new(MockFileSystem::new());
+        let processor = BatchProcessor::new(
+            inventory_dir.clone(),
+            mock_fs.clone(),
+            Arc::new(MockTimeProvider::new(0)),
+        );
+
+        let video = "https://example.com/video.mp4";
+        let mut inventory = Inventory::new();
+        for (url, content_range, body) in [
+            (video, "bytes 0-3/20", "abcd"),
+            ("https://example.com/", "", "<p>page</p>"),
+            (video, "bytes 10-15/20", "klmnop"),
+            (video, "bytes 2-5/20", "cdef"),
+        ] {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            resource.ttfb_ms = 10 + inventory.resources.len() as u64;
+            resource.duration_ms = Some(50);
+            resource.raw_body = Some(body.as_bytes().to_vec());
+            let mut headers = HttpHeaders::new();
+            if content_range.is_empty() {
+                resource.status_code = Some(200);
+            } else {
+                resource.status_code = Some(206);
+                resource.content_type_mime = Some("video/mp4".to_string());
+                headers.set(
+                    "content-range",
+                    HeaderValue::Single(content_range.to_string()),
+                );
+                headers.set(
+                    "content-length",
+                    HeaderValue::Single(body.len().to_string()),
+                );
+            }
+            resource.raw_headers = Some(headers);
+            inventory.resources.push(resource);
+        }
+
+        processor.process_all(&mut inventory).await.unwrap();
+
+        assert_eq!(inventory.resources.len(), 2);
+        let stitched = &inventory.resources[0];
+        assert_eq!(stitched.url, video);
+        assert_eq!(stitched.occurrence, None);
+        assert_eq!(stitched.ttfb_ms, 10);
+        assert_eq!(stitched.duration_ms, Some(150));
+        assert_eq!(
+            stitched.byte_ranges,
+            Some(vec![
+                ByteRange { start: 0, end: 5 },
+                ByteRange { start: 10, end: 15 },
+            ])
+        );
+        assert_eq!(stitched.complete_length, Some(20));
+        let headers = stitched.raw_headers.as_ref().unwrap();
+        assert!(!headers.contains("content-range"));
+        assert!(!headers.contains("content-length"));
+
+        let file = inventory_dir.join(stitched.content_file_path.as_deref().unwrap());
+        assert_eq!(
+            mock_fs.get_file(&file.to_string_lossy()),
+            Some(b"abcdefklmnop".to_vec())
+        );
+        assert_eq!(inventory.resources[1].byte_ranges, None);
+    }
+
+    #[test]
+    fn test_sniff_mime() {
+        use crate::recording::sniff::{extension, sniff_mime};
+
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+        let html = b"\xef\xbb\xbf\n  <!DOCTYPE html><html></html>";
+        assert_eq!(sniff_mime(None, png), Some("image/png"));
+        assert_eq!(sniff_mime(None, html), Some("text/html"));
+        assert_eq!(
+            sniff_mime(Some("application/octet-stream"), b"<svg xmlns=\"\"/>"),
+            Some("image/svg+xml")
+        );
+        // Text types only give way to binary signatures
+        assert_eq!(sniff_mime(Some("text/html"), png), Some("image/png"));
+        assert_eq!(sniff_mime(Some("text/plain"), html), None);
+        // Specific types and matching ones stand
+        assert_eq!(sniff_mime(Some("image/jpeg"), png), None);
+        assert_eq!(sniff_mime(Some("Image/PNG"), png), None);
+        assert_eq!(sniff_mime(None, b"plain words"), None);
+
+        assert_eq!(extension("image/png"), Some("png"));
+        assert_eq!(extension("text/html"), Some("html"));
+        assert_eq!(extension("application/json"), None);
+    }
+
+    #[tokio::test]
+    async fn test_batch_processor_sniffs_missing_types() {
+        use crate::recording::batch_processor::BatchProcessor;
+        use crate::traits::mocks::{MockFileSystem, MockTimeProvider};
+        use crate::types::Resource;
+        use std::sync::Arc;
+
+        let inventory_dir = std::path::PathBuf::from("/inventory");
+        let mock_fs = Arc::new(MockFileSystem::new());
+        let processor = BatchProcessor::new(
+            inventory_dir.clone(),
+            mock_fs.clone(),
+            Arc::new(MockTimeProvider::new(0)),
+        );
+
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\xff\xfe".to_vec();
+        let mut inventory = Inventory::new();
+        for (url, mime, body) in [
+            ("https://example.com/avatar?id=3", None, png.clone()),
+            ("https://example.com/logo", Some("text/html"), png),
+            (
+                "https://example.com/page",
+                None,
+                b"<html><body><p>a</p></body></html>".to_vec(),
+            ),
+        ] {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            resource.content_type_mime = mime.map(str::to_string);
+            resource.raw_body = Some(body);
+            inventory.resources.push(resource);
+        }
+
+        processor.process_all(&mut inventory).await.unwrap();
+
+        let avatar = &inventory.resources[0];
+        assert_eq!(avatar.sniffed_mime.as_deref(), Some("image/png"));
+        assert_eq!(avatar.content_type_mime, None);
+        assert_eq!(
+            avatar.content_file_path.as_deref(),
+            Some("contents/get/https/example.com/avatar~id%3D3.png")
+        );
+        assert_eq!(avatar.minify, None);
+
+        // A mislabeled image is stored byte for byte, not decoded as text
+        let logo = &inventory.resources[1];
+        assert_eq!(logo.sniffed_mime.as_deref(), Some("image/png"));
+        assert_eq!(logo.content_type_mime.as_deref(), Some("text/html"));
+        assert_eq!(logo.minify, None);
+        let file = inventory_dir.join(logo.content_file_path.as_deref().unwrap());
+        assert_eq!(
+            mock_fs
+                .get_file(&file.to_string_lossy())
+                .map(|body| body.len()),
+            Some(18)
+        );
+
+        // An unlabeled page gets text handling
+        let page = &inventory.resources[2];
+        assert_eq!(page.mime_type(), Some("text/html"));
+        assert!(page.minify.is_some());
+        assert_eq!(
+            page.content_file_path.as_deref(),
+            Some("contents/get/https/example.com/page.html")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_processor_stores_source_maps() {
+        use crate::recording::batch_processor::BatchProcessor;
+        use crate::recording::source_maps::SourceMaps;
+        use crate::traits::HttpResponse;
+        use crate::traits::mocks::{MockFileSystem, MockHttpClient, MockTimeProvider};
+        use crate::types::Resource;
+        use base64::{Engine as _, engine::general_purpose};
+        use std::sync::Arc;
+
+        let inventory_dir = std::path::PathBuf::from("/inventory");
+        let mock_fs = Arc::new(MockFileSystem::new());
+        let client = Arc::new(MockHttpClient::new());
+        let fetched_map = r#"{"version":3,"sources":["webpack:///./src/index.js","../../etc/passwd"],"sourcesContent":["export const a = 1;\n","secret"],"mappings":"AAAA"}"#;
+        client.set_response(
+            "GET:https://example.com/js/app.js.map",
+            HttpResponse {
+                status_code: 200,
+                headers: Default::default(),
+                body: fetched_map.as_bytes().to_vec(),
+                elapsed_ms: 10,
+            },
+        );
+        let processor = BatchProcessor::new(
+            inventory_dir.clone(),
+            mock_fs.clone(),
+            Arc::new(MockTimeProvider::new(0)),
+        )
+        .with_source_maps(Some(SourceMaps::new(client.clone()).with_sources(true)));
+
+        let minified = "function a(b){if(b){return b+1}return 0}var c=a(1);var d=a(2);";
+        let inline_map = r#"{"version":3,"sources":["inline.ts"],"mappings":"AAAA"}"#;
+        let recorded_map = r#"{"version":3,"sources":[],"mappings":""}"#;
+        let mut inventory = Inventory::new();
+        for (url, mime, body) in [
+            (
+                "https://example.com/js/app.js",
+                "application/javascript",
+                format!("{}\n//# sourceMappingURL=app.js.map", minified),
+            ),
+            (
+                "https://example.com/js/inline.js",
+                "application/javascript",
+                format!(
+                    "{}\n//# sourceMappingURL=data:application/json;base64,{}",
+                    minified,
+                    general_purpose::STANDARD.encode(inline_map)
+                ),
+            ),
+            (
+                "https://example.com/js/vendor.js",
+                "text/javascript",
+                format!("{}\n//# sourceMappingURL=/maps/vendor.js.map", minified),
+            ),
+            (
+                "https://example.com/maps/vendor.js.map",
+                "application/json",
+                recorded_map.to_string(),
+            ),
+        ] {
+            let mut resource = Resource::new("GET".to_string(), url.to_string());
+            resource.status_code = Some(200);
+            resource.content_type_mime = Some(mime.to_string());
+            resource.raw_body = Some(body.into_bytes());
+            inventory.resources.push(resource);
+        }
+
+        processor.process_all(&mut inventory).await.unwrap();
+
+        let stored = |path: &str| {
+            mock_fs
+                .get_file(&inventory_dir.join(path).to_string_lossy())
+                .map(|body| String::from_utf8(body).unwrap())
+        };
+
+        // Fetched from the origin, with its sources kept inside the sources directory
+        let app = &inventory.resources[0];
+        assert_eq!(app.minify, Some(true));
+        assert_eq!(app.source_map_url, None);
+        assert_eq!(
+            app.source_map_file_path.as_deref(),
+            Some("contents/get/https/example.com/js/app.js.map")
+        );
+        assert_eq!(
+            stored(app.source_map_file_path.as_deref().unwrap()).as_deref(),
+            Some(fetched_map)
+        );
+        let sources_dir = app.sources_dir.as_deref().unwrap();
+        assert_eq!(
+            sources_dir,
+            "contents/get/https/example.com/js/app.js.sources"
+        );
+        assert_eq!(
+            stored(&format!("{}/src/index.js", sources_dir)).as_deref(),
+            Some("export const a = 1;\n")
+        );
+        assert_eq!(
+            stored(&format!("{}/etc/passwd", sources_dir)).as_deref(),
+            Some("secret")
+        );
+
+        // Decoded from the script, with no sources embedded to write
+        let inline = &inventory.resources[1];
+        assert_eq!(
+            stored(inline.source_map_file_path.as_deref().unwrap()).as_deref(),
+            Some(inline_map)
+        );
+        assert_eq!(inline.sources_dir, None);
+
+        // Taken from the recording
+        let vendor = &inventory.resources[2];
+        assert_eq!(
+            stored(vendor.source_map_file_path.as_deref().unwrap()).as_deref(),
+            Some(recorded_map)
+        );
+        assert_eq!(
+            client.get_requests(),
+            vec![(
+                "GET".to_string(),
+                "https://example.com/js/app.js.map".to_string()
+            )]
+        );
+        assert_eq!(inventory.resources[3].source_map_file_path, None);
+    }
+
+    #[tokio::test]
+    async fn test_large_bodies_are_spilled_to_disk() {
+        use crate::recording::batch_processor::BatchProcessor;
+        use crate::recording::integrity::digest;
+        use crate::recording::open_ended::OpenBodies;
+        use crate::recording::spill::{SpillFile, read_chunks};
+        use crate::recording::spool::Spool;
+        use crate::traits::RealFileSystem;
+        use crate::traits::mocks::MockTimeProvider;
+        use crate::types::Resource;
+        use futures::TryStreamExt;
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        let temp_dir = TempDir::new().unwrap();
+        let inventory_dir = temp_dir.path().to_path_buf();
+        let spool = Spool::create(&inventory_dir, &Inventory::new(), None)
+            .await
+            .unwrap();
+        let bodies = Arc::new(OpenBodies::default());
+        let headers_received = Instant::now();
+        let image: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut resource = Resource::new(
+            "GET".to_string(),
+            "https://example.com/photo.png".to_string(),
+        );
+        resource.status_code = Some(200);
+        resource.content_type_mime = Some("image/png".to_string());
+
+        // What arrived before the threshold is written first, then each later frame
+        let reader = bodies.begin(resource.clone(), headers_received);
+        let at = |ms| headers_received + Duration::from_millis(ms);
+        reader.receive(&image[..1_000], at(10));
+        let path = spool.body_path();
+        let buffered = reader.spill(path.clone());
+        assert_eq!(buffered, &image[..1_000]);
+        let mut file = SpillFile::create(path.clone(), &buffered).await.unwrap();
+        for (frame, ms) in image[1_000..].chunks(99_000).zip([20, 40, 45]) {
+            reader.receive(frame, at(ms));
+            file.write(frame).await.unwrap();
+        }
+        let (body, chunk_timings) = reader.finish();
+        assert!(body.is_empty());
+        // The last frame came within the timing resolution of the one before
+        assert_eq!(
+            chunk_timings,
+            vec![[1_000, 10], [100_000, 20], [200_000, 40]]
+        );
+        let spilled = file.finish().await.unwrap();
+        assert_eq!(spilled.digest, digest(&image));
+
+        // The client is sent the file in chunks
+        let chunks: Vec<bytes::Bytes> = read_chunks(path.clone()).try_collect().await.unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), image);
+
+        // The spool refers to the file rather than writing it again
+        resource.raw_body_file = Some(path.clone());
+        resource.forwarded_body = Some(spilled.digest);
+        spool.append(&resource).await.unwrap();
+
+        // A spilled body cut off by the client is recorded from its file too
+        let cut_off = bodies.begin(resource.clone(), headers_received);
+        cut_off.receive(&image[..10], at(5));
+        let cut_off_path = spool.body_path();
+        cut_off.spill(cut_off_path.clone());
+        drop(cut_off);
+        let resources = bodies.resources(headers_received + Duration::from_millis(100));
+        assert_eq!(resources[0].raw_body_file.as_ref(), Some(&cut_off_path));
+        assert!(resources[0].raw_body.is_none());
+
+        // Binary bodies are copied into contents/ without being loaded
+        let mut inventory = Inventory::new();
+        inventory.resources.push(resource);
+        let processor = BatchProcessor::new(
+            inventory_dir.clone(),
+            Arc::new(RealFileSystem),
+            Arc::new(MockTimeProvider::new(0)),
+        );
+        processor.process_all(&mut inventory).await.unwrap();
+        let stored = &inventory.resources[0];
+        assert!(stored.raw_body_file.is_none());
+        assert_eq!(stored.original_size, Some(200_000));
+        let content = inventory_dir.join(stored.content_file_path.as_ref().unwrap());
+        assert_eq!(std::fs::read(content).unwrap(), image);
+        assert!(path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_spill_keeps_body_in_memory() {
+        use crate::recording::hudsucker_handler::RecordingHandler;
+        use crate::recording::spill::SpillFile;
+        use crate::recording::spool::Spool;
+        use http_body_util::BodyExt;
+        use hudsucker::hyper::{Request, Response};
+        use hudsucker::{Body, HttpContext, HttpHandler, RequestOrResponse};
+
+        let temp_dir = TempDir::new().unwrap();
+        let spool = Spool::create(temp_dir.path(), &Inventory::new(), None)
+            .await
+            .unwrap();
+
+        // What was written is read back whole, and the file removed
+        let path = spool.body_path();
+        let mut file = SpillFile::create(path.clone(), b"first ").await.unwrap();
+        file.write(b"second").await.unwrap();
+        assert_eq!(file.reclaim().await.unwrap(), b"first second");
+        assert!(!path.exists());
+
+        // Without a directory for body files, the body stays in memory
+        std::fs::remove_dir_all(spool.body_path().parent().unwrap()).unwrap();
+        let mut handler = RecordingHandler::new(Inventory::new())
+            .with_spool(spool)
+            .with_spill_threshold(10);
+        let ctx = HttpContext {
+            client_addr: "127.0.0.1:50004".parse().unwrap(),
+            request_method: hyper::Method::GET,
+            request_uri: "https://example.com/video.mp4".parse().unwrap(),
+        };
+        let request = Request::builder()
+            .uri("https://example.com/video.mp4")
+            .body(Body::empty())
+            .unwrap();
+        let forwarded = handler.handle_request(&ctx, request).await;
+        assert!(matches!(forwarded, RequestOrResponse::Request(_)));
+
+        let frames: Vec<std::io::Result<bytes::Bytes>> = ["0123456789", "abcdefghij", "KLMNO"]
+            .into_iter()
+            .map(|frame| Ok(bytes::Bytes::from(frame)))
+            .collect();
+        let response = handler
+            .handle_response(
+                &ctx,
+                Response::new(Body::from_stream(futures::stream::iter(frames))),
+            )
+            .await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "0123456789abcdefghijKLMNO");
+
+        let inventory = handler.get_inventory();
+        let inventory = inventory.lock().await;
+        assert_eq!(inventory.resources.len(), 1);
+        let resource = &inventory.resources[0];
+        assert_eq!(
+            resource.raw_body.as_deref(),
+            Some(&b"0123456789abcdefghijKLMNO"[..])
+        );
+        assert!(resource.raw_body_file.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_bodies_are_recorded_open_ended() {
+        use crate::recording::batch_processor::BatchProcessor;
+        use crate::recording::open_ended::OpenBodies;
+        use crate::traits::mocks::{MockFileSystem, MockTimeProvider};
+        use crate::types::{ContentEncodingType, ErrorKind, Inventory, Resource};
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+        use std::path::PathBuf;
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        let bodies = Arc::new(OpenBodies::default());
+        let headers_received = Instant::now();
+        let resource =
+            |path: &str| Resource::new("GET".to_string(), format!("https://example.com/{}", path));
+
+        // A body that ends is not kept here
+        let done = bodies.begin(resource("done"), headers_received);
+        done.receive(b"all of it", headers_received);
+        assert_eq!(done.finish().0, b"all of it");
+
+        // One the client gave up on, and one still arriving when recording stops
+        let dropped = bodies.begin(resource("poll"), headers_received);
+        dropped.receive(b"first ", headers_received + Duration::from_millis(100));
+        dropped.receive(b"part", headers_received + Duration::from_millis(300));
+        drop(dropped);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let text: Vec<String> = (0..2_000)
+            .map(|i| (i * 7_919 % 10_007).to_string())
+            .collect();
+        encoder.write_all(text.join(" ").as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut gzipped = resource("stream");
+        gzipped.content_encoding = Some(ContentEncodingType::Gzip);
+        gzipped.content_type_mime = Some("text/plain".to_string());
+        let open = bodies.begin(gzipped, headers_received);
+        open.receive(&compressed[..compressed.len() / 2], headers_received);
+
+        let resources = bodies.resources(headers_received + Duration::from_millis(500));
+        let urls: Vec<&str> = resources.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec!["https://example.com/poll", "https://example.com/stream"]
+        );
+        assert_eq!(resources[0].raw_body.as_deref(), Some(&b"first part"[..]));
+        assert_eq!(resources[0].open_ended, Some(true));
+        assert_eq!(resources[0].chunk_timings, Some(vec![[6, 100], [10, 300]]));
+        assert_eq!(resources[1].duration_ms, Some(500));
+        drop(open);
+
+        // One the origin broke off is recorded as truncated rather than open-ended
+        let broken = bodies.begin(resource("download"), headers_received);
+        broken.receive(b"first", headers_received);
+        broken.break_off("error reading a body from connection");
+        let truncated = bodies.resources(Instant::now()).pop().unwrap();
+        assert_eq!(truncated.url, "https://example.com/download");
+        assert_eq!(truncated.raw_body.as_deref(), Some(&b"first"[..]));
+        assert_eq!(truncated.open_ended, None);
+        assert_eq!(truncated.error_kind, Some(ErrorKind::TruncatedBody));
+
+        // The cut-off gzip stream is stored as far as it decompresses
+        let mut inventory = Inventory::new();
+        inventory.resources = resources;
+        let mock_fs = Arc::new(MockFileSystem::new());
+        let processor = BatchProcessor::new(
+            PathBuf::from("/inventory"),
+            mock_fs.clone(),
+            Arc::new(MockTimeProvider::new(0)),
+        );
+        processor.process_all(&mut inventory).await.unwrap();
+        let stream = &inventory.resources[1];
+        let stored = stream
+            .content_file_path
+            .as_ref()
+            .and_then(|p| mock_fs.get_file(&format!("/inventory/{}", p)))
+            .unwrap();
+        assert!(stored.starts_with(b"0 7919 5831 "));
+        assert!(stored.len() < text.join(" ").len());
+        assert_eq!(stream.open_ended, Some(true));
+    }
 }
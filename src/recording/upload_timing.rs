@@ -0,0 +1,109 @@
+//! Time the client spends sending a request body
+//!
+//! The origin usually answers only after the whole upload has arrived, so for a large
+//! POST or PUT the measured TTFB is mostly upload time. The forwarded request body is
+//! wrapped to note when its last byte was handed to the upstream connection; the
+//! recording handler stores that as uploadMs and keeps only the server's own response
+//! time in ttfbMs. The body is hashed on the way through for body-hash matching.
+
+use bytes::Bytes;
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use sha1::{Digest, Sha1};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Bytes of a request body forwarded so far, and when the last one went out
+#[derive(Debug, Default)]
+pub struct UploadProgress {
+    bytes: AtomicU64,
+    hasher: Mutex<Sha1>,
+    finished: OnceLock<Instant>,
+    sha1: OnceLock<String>,
+}
+
+impl UploadProgress {
+    /// Progress of a body that was read in full before forwarding
+    pub fn completed(body: &[u8]) -> Self {
+        let progress = Self::default();
+        progress.add(body);
+        progress.finish();
+        progress
+    }
+
+    fn add(&self, data: &[u8]) {
+        self.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.hasher.lock().unwrap().update(data);
+    }
+
+    fn finish(&self) {
+        if self.finished.set(Instant::now()).is_ok() {
+            let digest = self.hasher.lock().unwrap().clone().finalize();
+            let _ = self.sha1.set(hex::encode(digest));
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// When the whole body had been sent, None while it is still in flight
+    pub fn finished(&self) -> Option<Instant> {
+        self.finished.get().copied()
+    }
+
+    /// SHA-1 of the whole body, None while it is still in flight
+    pub fn sha1(&self) -> Option<&str> {
+        self.sha1.get().map(|sha1| sha1.as_str())
+    }
+}
+
+/// Request body that reports its progress as the upstream connection reads it
+pub struct TimedUpload<B> {
+    inner: B,
+    progress: Arc<UploadProgress>,
+}
+
+impl<B> TimedUpload<B> {
+    pub fn new(inner: B, progress: Arc<UploadProgress>) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<B> HttpBody for TimedUpload<B>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.progress.add(data);
+                }
+                if self.inner.is_end_stream() {
+                    self.progress.finish();
+                }
+            }
+            Poll::Ready(None) => self.progress.finish(),
+            _ => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,363 @@
+//! Inventory redaction
+//!
+//! Writes a sanitized copy of an inventory that can be shared with vendors or attached
+//! to bug reports. A YAML rules file names the headers, cookies and query parameters
+//! whose values are replaced, and regexes applied to text bodies and form fields. The
+//! source inventory is never modified. Content files whose URL changed are moved to the
+//! path of the redacted URL, so no secret survives in a file name either.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::check::stored_content;
+use crate::lock::InventoryLock;
+use crate::recording::batch_processor::content_file_path;
+use crate::traits::{FileSystem, RealFileSystem};
+use crate::types::{HeaderValue, HttpHeaders, Inventory, Resource};
+use crate::utils::{generate_file_path_from_url, with_occurrence_suffix};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RedactRules {
+    /// Text written in place of every redacted value
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+    /// Header names (any case) whose values are replaced entirely
+    #[serde(default)]
+    pub headers: Vec<String>,
+    /// Cookie names whose values are replaced in Cookie and Set-Cookie headers
+    #[serde(default)]
+    pub cookies: Vec<String>,
+    /// Query parameter names whose values are replaced in URLs
+    #[serde(default)]
+    pub query_params: Vec<String>,
+    /// Regexes replaced in text bodies and form fields
+    #[serde(default)]
+    pub body_patterns: Vec<BodyPattern>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BodyPattern {
+    pub pattern: String,
+    /// Replacement with `$1`-style group references (default: the rules' replacement)
+    pub replacement: Option<String>,
+}
+
+fn default_replacement() -> String {
+    "REDACTED".to_string()
+}
+
+/// Request headers whose values are never stored, whatever else is redacted
+///
+/// `Cookie` is not among them: its cookie values are replaced but the names kept, so
+/// playback can tell a signed-in request from an anonymous one.
+pub const SECRET_REQUEST_HEADERS: &[&str] = &["authorization", "proxy-authorization"];
+
+/// Compiled redaction rules
+#[derive(Debug)]
+pub struct Redactor {
+    replacement: String,
+    headers: Vec<String>,
+    cookies: Vec<String>,
+    // Every cookie value, not only those listed in `cookies`
+    all_cookies: bool,
+    query_params: Vec<String>,
+    body_patterns: Vec<(Regex, String)>,
+}
+
+impl Redactor {
+    pub fn new(rules: &RedactRules) -> Result<Self> {
+        let body_patterns = rules
+            .body_patterns
+            .iter()
+            .map(|rule| {
+                let regex = Regex::new(&rule.pattern)
+                    .map_err(|e| anyhow::anyhow!("Invalid pattern {}: {}", rule.pattern, e))?;
+                let replacement = rule
+                    .replacement
+                    .clone()
+                    .unwrap_or_else(|| rules.replacement.clone());
+                Ok((regex, replacement))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            replacement: rules.replacement.clone(),
+            headers: rules
+                .headers
+                .iter()
+                .map(|name| name.to_ascii_lowercase())
+                .collect(),
+            cookies: rules.cookies.clone(),
+            all_cookies: false,
+            query_params: rules.query_params.clone(),
+            body_patterns,
+        })
+    }
+
+    /// Replaces the values of [`SECRET_REQUEST_HEADERS`], of every cookie and of the
+    /// `extra` headers, for storing request headers
+    pub fn for_request_headers(extra: &[String]) -> Self {
+        let headers = SECRET_REQUEST_HEADERS
+            .iter()
+            .map(|name| name.to_string())
+            .chain(extra.iter().map(|name| name.to_ascii_lowercase()))
+            .collect();
+        Self {
+            replacement: default_replacement(),
+            headers,
+            cookies: Vec::new(),
+            all_cookies: true,
+            query_params: Vec::new(),
+            body_patterns: Vec::new(),
+        }
+    }
+
+    /// `url` with the values of secret query parameters replaced
+    pub fn redact_url(&self, url: &str) -> String {
+        let Some(query_start) = url.find('?') else {
+            return url.to_string();
+        };
+        let (base, rest) = url.split_at(query_start + 1);
+        let (query, fragment) = match rest.find('#') {
+            Some(pos) => rest.split_at(pos),
+            None => (rest, ""),
+        };
+        let query: Vec<String> = query
+            .split('&')
+            .map(|param| match param.split_once('=') {
+                Some((name, _)) if self.is_secret_param(name) => {
+                    format!("{}={}", name, urlencoding::encode(&self.replacement))
+                }
+                _ => param.to_string(),
+            })
+            .collect();
+        format!("{}{}{}", base, query.join("&"), fragment)
+    }
+
+    fn is_secret_param(&self, name: &str) -> bool {
+        let name = urlencoding::decode(name).map_or_else(|_| name.to_string(), |n| n.into_owned());
+        self.query_params.contains(&name)
+    }
+
+    /// Headers with secret values replaced, in their recorded order
+    pub fn redact_headers(&self, headers: &HttpHeaders) -> HttpHeaders {
+        let mut redacted = HttpHeaders::new();
+        for (name, value) in headers.iter() {
+            let value = match value {
+                HeaderValue::Single(v) => HeaderValue::Single(self.redact_header_value(name, v)),
+                HeaderValue::Multiple(values) => HeaderValue::Multiple(
+                    values
+                        .iter()
+                        .map(|v| self.redact_header_value(name, v))
+                        .collect(),
+                ),
+            };
+            redacted.set(name, value);
+        }
+        redacted
+    }
+
+    fn redact_header_value(&self, name: &str, value: &str) -> String {
+        let name = name.to_ascii_lowercase();
+        if self.headers.contains(&name) {
+            return self.replacement.clone();
+        }
+        match name.as_str() {
+            "cookie" => value
+                .split("; ")
+                .map(|pair| self.redact_cookie_pair(pair))
+                .collect::<Vec<_>>()
+                .join("; "),
+            // Only the leading name=value; the attributes are not secret
+            "set-cookie" => match value.split_once(';') {
+                Some((pair, attributes)) => {
+                    format!("{};{}", self.redact_cookie_pair(pair), attributes)
+                }
+                None => self.redact_cookie_pair(value),
+            },
+            "location" | "content-location" => self.redact_url(value),
+            _ => value.to_string(),
+        }
+    }
+
+    fn redact_cookie_pair(&self, pair: &str) -> String {
+        match pair.split_once('=') {
+            Some((name, _))
+                if self.all_cookies || self.cookies.iter().any(|c| c == name.trim()) =>
+            {
+                format!("{}={}", name, self.replacement)
+            }
+            _ => pair.to_string(),
+        }
+    }
+
+    /// `text` with every body pattern replaced
+    pub fn redact_text(&self, text: &str) -> String {
+        self.body_patterns
+            .iter()
+            .fold(text.to_string(), |text, (regex, replacement)| {
+                regex.replace_all(&text, replacement.as_str()).into_owned()
+            })
+    }
+
+    /// Redact everything stored in index.json for a resource; content files are
+    /// handled by [`redact_inventory`]
+    pub fn redact_resource(&self, resource: &mut Resource) {
+        resource.url = self.redact_url(&resource.url);
+        if let Some(headers) = &resource.raw_headers {
+            resource.raw_headers = Some(self.redact_headers(headers));
+        }
+        if let Some(headers) = &resource.request_headers {
+            resource.request_headers = Some(self.redact_headers(headers));
+        }
+        if let Some(headers) = &resource.match_headers {
+            resource.match_headers = Some(self.redact_headers(headers));
+        }
+        for hint in resource.early_hints.iter_mut().flatten() {
+            hint.headers = self.redact_headers(&hint.headers);
+        }
+        for part in resource.request_form.iter_mut().flatten() {
+            if let Some(value) = &part.value {
+                part.value = Some(self.redact_text(value));
+            }
+        }
+        if let Some(utf8) = &resource.content_utf8 {
+            resource.content_utf8 = Some(self.redact_text(utf8));
+        }
+    }
+}
+
+pub async fn run_redact(
+    inventory_dir: PathBuf,
+    rules_path: PathBuf,
+    output_dir: PathBuf,
+    force: bool,
+) -> Result<()> {
+    let rules = std::fs::read_to_string(&rules_path)
+        .with_context(|| format!("Failed to read rules {:?}", rules_path))?;
+    let rules: RedactRules = serde_yaml::from_str(&rules)
+        .with_context(|| format!("Failed to parse rules {:?}", rules_path))?;
+    let redactor = Redactor::new(&rules)?;
+
+    if output_dir == inventory_dir {
+        anyhow::bail!("The redacted copy must be written to another directory");
+    }
+    let index_path = output_dir.join("index.json");
+    if index_path.exists() && !force {
+        anyhow::bail!("{:?} already exists; use --force to replace it", index_path);
+    }
+    let _lock = InventoryLock::acquire(&output_dir, "redact", force)?;
+
+    let file_system = Arc::new(RealFileSystem);
+    let inventory = crate::playback::load_inventory(&inventory_dir, file_system.clone()).await?;
+    let redacted = redact_inventory(
+        &inventory,
+        &inventory_dir,
+        &output_dir,
+        &redactor,
+        file_system.clone(),
+    )
+    .await?;
+    crate::recording::proxy::save_inventory_with_fs(&redacted, &output_dir, file_system).await?;
+
+    println!(
+        "Wrote a redacted copy of {} resources to {:?}",
+        redacted.resources.len(),
+        output_dir
+    );
+    Ok(())
+}
+
+/// Redacted copy of `inventory`, with its content and upload files written under
+/// `output_dir`
+pub async fn redact_inventory<F: FileSystem>(
+    inventory: &Inventory,
+    inventory_dir: &Path,
+    output_dir: &Path,
+    redactor: &Redactor,
+    file_system: Arc<F>,
+) -> Result<Inventory> {
+    let mut redacted = inventory.clone();
+    redacted.entry_url = inventory
+        .entry_url
+        .as_deref()
+        .map(|u| redactor.redact_url(u));
+
+    // Paths of resources whose URL stays are kept, so moved files must avoid them
+    let mut used_paths: HashSet<String> = inventory
+        .resources
+        .iter()
+        .filter(|r| redactor.redact_url(&r.url) == r.url)
+        .filter_map(|r| r.content_file_path.clone())
+        .collect();
+
+    for (original, resource) in inventory.resources.iter().zip(&mut redacted.resources) {
+        redactor.redact_resource(resource);
+        if resource.url != original.url {
+            move_files(original, resource, &mut used_paths)?;
+        }
+
+        if let Some(to) = &resource.content_file_path {
+            match stored_content(original, inventory_dir, &*file_system).await? {
+                Some(content) => {
+                    let content = match std::str::from_utf8(&content) {
+                        Ok(text) => redactor.redact_text(text).into_bytes(),
+                        Err(_) => content,
+                    };
+                    file_system.write(&output_dir.join(to), &content).await?;
+                }
+                None => warn!("Content file of {} is missing", original.url),
+            }
+        }
+
+        let parts = original.request_form.iter().flatten();
+        for (from, to) in parts.zip(resource.request_form.iter().flatten()) {
+            if let (Some(from), Some(to)) = (&from.file_path, &to.file_path) {
+                let content = file_system.read(&inventory_dir.join(from)).await?;
+                file_system.write(&output_dir.join(to), &content).await?;
+            }
+        }
+    }
+    Ok(redacted)
+}
+
+/// Give a resource whose URL was redacted the content and upload paths of its new URL
+fn move_files(
+    original: &Resource,
+    resource: &mut Resource,
+    used_paths: &mut HashSet<String>,
+) -> Result<()> {
+    let base = generate_file_path_from_url(&resource.url, &resource.method)?;
+    let mut occurrence = resource.occurrence.unwrap_or(0);
+    let mut new_path = with_occurrence_suffix(&base, occurrence);
+    // Two URLs differing only in a secret now share one path
+    while used_paths.contains(&format!("contents/{}", new_path)) {
+        occurrence += 1;
+        new_path = with_occurrence_suffix(&base, occurrence);
+    }
+    if resource.content_file_path.is_some() {
+        used_paths.insert(format!("contents/{}", new_path));
+        resource.content_file_path = Some(format!("contents/{}", new_path));
+    }
+
+    let old_uploads = format!("uploads/{}/", content_file_path(original)?);
+    for part in resource.request_form.iter_mut().flatten() {
+        let moved = part
+            .file_path
+            .as_deref()
+            .and_then(|path| path.strip_prefix(&old_uploads))
+            .map(|name| format!("uploads/{}/{}", new_path, name));
+        if moved.is_some() {
+            part.file_path = moved;
+        }
+    }
+    Ok(())
+}
+
+mod tests;
@@ -0,0 +1,171 @@
+#[cfg(test)]
+mod redact_tests {
+    use crate::redact::{RedactRules, Redactor, redact_inventory};
+    use crate::traits::mocks::MockFileSystem;
+    use crate::types::{HeaderValue, HttpHeaders, Inventory, Resource};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn redactor() -> Redactor {
+        let rules: RedactRules = serde_yaml::from_str(
+            r#"
+headers: [Authorization, x-api-key]
+cookies: [session]
+queryParams: [token]
+bodyPatterns:
+  - pattern: '"password":\s*"[^"]*"'
+    replacement: '"password":"***"'
+  - pattern: '\b\d{4}-\d{4}-\d{4}-\d{4}\b'
+"#,
+        )
+        .unwrap();
+        Redactor::new(&rules).unwrap()
+    }
+
+    #[test]
+    fn test_redact_url_query_params() {
+        let redactor = redactor();
+        assert_eq!(
+            redactor.redact_url("https://api.example/data?token=abc123&page=2#top"),
+            "https://api.example/data?token=REDACTED&page=2#top"
+        );
+        assert_eq!(
+            redactor.redact_url("https://api.example/data?page=2"),
+            "https://api.example/data?page=2"
+        );
+        assert_eq!(
+            redactor.redact_url("https://api.example/"),
+            "https://api.example/"
+        );
+    }
+
+    #[test]
+    fn test_redact_headers_and_cookies() {
+        let mut headers = HttpHeaders::new();
+        headers.set("X-API-Key", HeaderValue::Single("secret".to_string()));
+        headers.append("Set-Cookie", "session=abc; Path=/; HttpOnly".to_string());
+        headers.append("Set-Cookie", "theme=dark".to_string());
+        headers.set(
+            "Location",
+            HeaderValue::Single("/next?token=abc".to_string()),
+        );
+        headers.set(
+            "Cookie",
+            HeaderValue::Single("theme=dark; session=abc".to_string()),
+        );
+
+        let redacted = redactor().redact_headers(&headers);
+        let names: Vec<&str> = redacted.names().collect();
+        assert_eq!(names, vec!["X-API-Key", "Set-Cookie", "Location", "Cookie"]);
+        assert_eq!(redacted.get("x-api-key").unwrap().first(), "REDACTED");
+        assert_eq!(
+            redacted.get("set-cookie").unwrap().as_vec(),
+            vec!["session=REDACTED; Path=/; HttpOnly", "theme=dark"]
+        );
+        assert_eq!(
+            redacted.get("location").unwrap().first(),
+            "/next?token=REDACTED"
+        );
+        assert_eq!(
+            redacted.get("cookie").unwrap().first(),
+            "theme=dark; session=REDACTED"
+        );
+    }
+
+    #[test]
+    fn test_redact_request_headers() {
+        let mut headers = HttpHeaders::new();
+        headers.set("Accept", HeaderValue::Single("text/html".to_string()));
+        headers.set(
+            "Cookie",
+            HeaderValue::Single("session=abc; theme=dark".to_string()),
+        );
+        headers.set("Authorization", HeaderValue::Single("Bearer x".to_string()));
+        headers.set("X-Api-Key", HeaderValue::Single("secret".to_string()));
+
+        // Cookie values and credentials always, other headers as listed
+        let redacted = Redactor::for_request_headers(&[]).redact_headers(&headers);
+        assert_eq!(redacted.get("accept").unwrap().first(), "text/html");
+        assert_eq!(
+            redacted.get("cookie").unwrap().first(),
+            "session=REDACTED; theme=REDACTED"
+        );
+        assert_eq!(redacted.get("authorization").unwrap().first(), "REDACTED");
+        assert_eq!(redacted.get("x-api-key").unwrap().first(), "secret");
+        let redacted =
+            Redactor::for_request_headers(&["X-API-KEY".to_string()]).redact_headers(&headers);
+        assert_eq!(redacted.get("x-api-key").unwrap().first(), "REDACTED");
+    }
+
+    #[test]
+    fn test_redact_text_patterns() {
+        assert_eq!(
+            redactor()
+                .redact_text(r#"{"user":"a","password": "hunter2","card":"1234-5678-9012-3456"}"#),
+            r#"{"user":"a","password":"***","card":"REDACTED"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redact_inventory_moves_files_of_redacted_urls() {
+        let file_system = Arc::new(MockFileSystem::new());
+        let mut inventory = Inventory::new();
+        inventory.entry_url = Some("https://api.example/?token=abc".to_string());
+
+        let mut secret = Resource::new(
+            "GET".to_string(),
+            "https://api.example/data.json?token=abc".to_string(),
+        );
+        secret.content_file_path =
+            Some("contents/get/https/api.example/data~token%3Dabc.json".to_string());
+        file_system.set_file(
+            "/src/contents/get/https/api.example/data~token%3Dabc.json",
+            br#"{"password":"hunter2"}"#.to_vec(),
+        );
+        inventory.resources.push(secret);
+
+        let mut plain = Resource::new("GET".to_string(), "https://api.example/app.js".to_string());
+        plain.content_file_path = Some("contents/get/https/api.example/app.js".to_string());
+        file_system.set_file(
+            "/src/contents/get/https/api.example/app.js",
+            b"console.log(1);".to_vec(),
+        );
+        inventory.resources.push(plain);
+
+        let redacted = redact_inventory(
+            &inventory,
+            Path::new("/src"),
+            Path::new("/out"),
+            &redactor(),
+            file_system.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            redacted.entry_url.as_deref(),
+            Some("https://api.example/?token=REDACTED")
+        );
+        let moved = redacted.resources[0].content_file_path.as_deref().unwrap();
+        assert!(!moved.contains("abc"));
+        assert_eq!(
+            file_system.get_file(&format!("/out/{}", moved)).unwrap(),
+            br#"{"password":"***"}"#
+        );
+        assert_eq!(
+            redacted.resources[1].content_file_path,
+            inventory.resources[1].content_file_path
+        );
+        assert_eq!(
+            file_system
+                .get_file("/out/contents/get/https/api.example/app.js")
+                .unwrap(),
+            b"console.log(1);"
+        );
+        // The source is left as it was
+        assert_eq!(
+            inventory.resources[0].url,
+            "https://api.example/data.json?token=abc"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Redirect chains of an inventory
+//!
+//! A page reached through `http://example.com` → `https://example.com/` →
+//! `https://www.example.com/` spends a round trip on every hop before its document
+//! starts. Batch processing links each recorded redirect to the request the client
+//! followed it with (`redirectTo`), so the chains survive in the inventory. Playback
+//! replays every hop with its recorded timing, or with `--redirects collapse` answers
+//! each redirect at once and straight with the end of its chain. The `redirects`
+//! command lists the chains with the time each cost the client.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::traits::RealFileSystem;
+use crate::types::{HeaderValue, Inventory, RedirectTarget, Resource};
+
+/// How playback answers recorded redirects
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RedirectMode {
+    /// Every hop with its recorded timing
+    #[default]
+    Replay,
+    /// Every redirect at once, pointing at the end of its chain
+    Collapse,
+}
+
+impl RedirectMode {
+    /// Rewrite the redirects of every chain, returning how many changed
+    pub fn apply(self, inventory: &mut Inventory) -> usize {
+        if self == RedirectMode::Replay {
+            return 0;
+        }
+        let mut changed = 0;
+        for chain in redirect_chains(&inventory.resources) {
+            let Some(destination) = chain.destination else {
+                continue;
+            };
+            let location = inventory.resources[destination].url.clone();
+            for hop in chain.hops {
+                let resource = &mut inventory.resources[hop];
+                resource
+                    .raw_headers
+                    .get_or_insert_default()
+                    .set("location", HeaderValue::Single(location.clone()));
+                resource.ttfb_ms = 0;
+                resource.duration_ms = Some(0);
+                resource.mbps = None;
+                resource.chunk_timings = None;
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
+
+/// The method and absolute URL a client follows a redirect response with, None for
+/// other responses
+pub fn followed_request(resource: &Resource) -> Option<(String, String)> {
+    let method = match (resource.status_code?, resource.method.as_str()) {
+        // Browsers turn a redirected POST into a GET, as 303 asks of every method
+        (301 | 302, "POST") | (303, _) => "GET",
+        (301 | 302 | 307 | 308, method) => method,
+        _ => return None,
+    };
+    let location = resource.raw_headers.as_ref()?.get("location")?.first();
+    let mut url = url::Url::parse(&resource.url).ok()?.join(location).ok()?;
+    url.set_fragment(None);
+    Some((method.to_string(), url.into()))
+}
+
+/// Link each redirect to the first request for its target sent after it, returning
+/// how many were linked
+///
+/// Occurrences must be assigned first: the link names the target by method, URL and
+/// occurrence.
+pub fn link_redirects(resources: &mut [Resource]) -> usize {
+    let mut linked = 0;
+    for index in 0..resources.len() {
+        let Some((method, url)) = followed_request(&resources[index]) else {
+            continue;
+        };
+        let sent_ms = resources[index].request_start_ms.unwrap_or(0);
+        let target = resources
+            .iter()
+            .enumerate()
+            .filter(|(other, resource)| {
+                *other != index
+                    && resource.method == method
+                    && resource.url == url
+                    && resource.request_start_ms.unwrap_or(0) >= sent_ms
+            })
+            .min_by_key(|(_, resource)| resource.request_start_ms.unwrap_or(0))
+            .map(|(_, resource)| resource.occurrence);
+        resources[index].redirect_to = target.map(|occurrence| RedirectTarget {
+            method,
+            url,
+            occurrence,
+        });
+        linked += usize::from(resources[index].redirect_to.is_some());
+    }
+    linked
+}
+
+/// Redirects followed one after another, from a redirect no other redirect led to
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedirectChain {
+    /// Indexes of the redirect responses in `resources`, starting with the entry point
+    pub hops: Vec<usize>,
+    /// Index of the response the chain ends with, None when the last redirect was not
+    /// followed while recording or the chain loops
+    pub destination: Option<usize>,
+}
+
+impl RedirectChain {
+    /// Time the chain cost the client: from the entry request to the request for the
+    /// destination, else the recorded time of every hop
+    pub fn redirect_ms(&self, resources: &[Resource]) -> u64 {
+        let entry = &resources[self.hops[0]];
+        let destination = self.destination.map(|index| &resources[index]);
+        match (
+            entry.request_start_ms,
+            destination.and_then(|d| d.request_start_ms),
+        ) {
+            (Some(sent), Some(arrived)) if arrived >= sent => arrived - sent,
+            _ => self
+                .hops
+                .iter()
+                .map(|&hop| resources[hop].ttfb_ms + resources[hop].duration_ms.unwrap_or(0))
+                .sum(),
+        }
+    }
+}
+
+/// Every redirect chain among `resources`, in the order of their entry points
+pub fn redirect_chains(resources: &[Resource]) -> Vec<RedirectChain> {
+    let by_target: HashMap<(&str, &str, Option<u32>), usize> = resources
+        .iter()
+        .enumerate()
+        .map(|(index, r)| ((r.method.as_str(), r.url.as_str(), r.occurrence), index))
+        .collect();
+    let next = |index: usize| {
+        let target = resources[index].redirect_to.as_ref()?;
+        by_target
+            .get(&(
+                target.method.as_str(),
+                target.url.as_str(),
+                target.occurrence,
+            ))
+            .copied()
+    };
+    let targeted: HashSet<usize> = (0..resources.len()).filter_map(next).collect();
+
+    let mut chains = Vec::new();
+    for entry in 0..resources.len() {
+        if followed_request(&resources[entry]).is_none() || targeted.contains(&entry) {
+            continue;
+        }
+        let mut chain = RedirectChain {
+            hops: vec![entry],
+            destination: None,
+        };
+        while let Some(following) = chain.hops.last().and_then(|&hop| next(hop)) {
+            if chain.hops.contains(&following) {
+                break;
+            }
+            if followed_request(&resources[following]).is_none() {
+                chain.destination = Some(following);
+                break;
+            }
+            chain.hops.push(following);
+        }
+        chains.push(chain);
+    }
+    chains
+}
+
+/// One chain as the `redirects` command reports it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainReport {
+    pub entry_url: String,
+    /// Every URL redirected from, then the destination's
+    pub urls: Vec<String>,
+    pub statuses: Vec<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination_status: Option<u16>,
+    pub redirect_ms: u64,
+}
+
+/// Reports of the redirect chains of `inventory`
+pub fn chain_reports(inventory: &Inventory) -> Vec<ChainReport> {
+    let resources = &inventory.resources;
+    redirect_chains(resources)
+        .into_iter()
+        .map(|chain| ChainReport {
+            entry_url: resources[chain.hops[0]].url.clone(),
+            urls: chain
+                .hops
+                .iter()
+                .chain(&chain.destination)
+                .map(|&index| resources[index].url.clone())
+                .collect(),
+            statuses: chain
+                .hops
+                .iter()
+                .filter_map(|&hop| resources[hop].status_code)
+                .collect(),
+            destination_status: chain
+                .destination
+                .and_then(|index| resources[index].status_code),
+            redirect_ms: chain.redirect_ms(resources),
+        })
+        .collect()
+}
+
+pub async fn run_redirects(inventory_dir: PathBuf, json: bool) -> Result<()> {
+    let inventory =
+        crate::playback::load_inventory(&inventory_dir, Arc::new(RealFileSystem)).await?;
+    let reports = chain_reports(&inventory);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+    if reports.is_empty() {
+        println!("No redirects recorded in {:?}", inventory_dir);
+        return Ok(());
+    }
+    for report in &reports {
+        let destination = match report.destination_status {
+            Some(status) => format!("ends with {}", status),
+            None => "destination not recorded".to_string(),
+        };
+        println!(
+            "{}: {} redirects, {}ms ({})",
+            report.entry_url,
+            report.statuses.len(),
+            report.redirect_ms,
+            destination
+        );
+        for (url, status) in report.urls.iter().zip(&report.statuses) {
+            println!("  {} {}", status, url);
+        }
+        if report.destination_status.is_some() {
+            println!("  -> {}", report.urls.last().unwrap());
+        }
+    }
+    Ok(())
+}
+
+mod tests;
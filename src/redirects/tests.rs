@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod redirects_tests {
+    use crate::recording::batch_processor::assign_occurrences;
+    use crate::redirects::{RedirectMode, chain_reports, link_redirects, redirect_chains};
+    use crate::types::{HeaderValue, HttpHeaders, Inventory, RedirectTarget, Resource};
+
+    fn resource(method: &str, url: &str, start_ms: u64, status: u16) -> Resource {
+        let mut resource = Resource::new(method.to_string(), url.to_string());
+        resource.request_start_ms = Some(start_ms);
+        resource.ttfb_ms = 40;
+        resource.duration_ms = Some(10);
+        resource.status_code = Some(status);
+        resource
+    }
+
+    fn redirect(method: &str, url: &str, start_ms: u64, status: u16, location: &str) -> Resource {
+        let mut resource = resource(method, url, start_ms, status);
+        let mut headers = HttpHeaders::new();
+        headers.set("Location", HeaderValue::Single(location.to_string()));
+        resource.raw_headers = Some(headers);
+        resource
+    }
+
+    /// http → https → www, a form post answered with 303, and a redirect whose target
+    /// was never requested
+    fn recorded() -> Inventory {
+        let mut inventory = Inventory::new();
+        inventory.resources = vec![
+            redirect("GET", "http://example.com/", 0, 301, "https://example.com/"),
+            redirect(
+                "GET",
+                "https://example.com/",
+                60,
+                302,
+                "//www.example.com/#top",
+            ),
+            resource("GET", "https://www.example.com/", 130, 200),
+            resource("GET", "https://www.example.com/", 900, 200),
+            redirect("POST", "https://www.example.com/login", 1000, 303, "/home"),
+            resource("GET", "https://www.example.com/home", 1070, 200),
+            redirect("GET", "https://www.example.com/old", 1200, 308, "/gone"),
+        ];
+        assign_occurrences(&mut inventory.resources);
+        link_redirects(&mut inventory.resources);
+        inventory
+    }
+
+    #[test]
+    fn test_redirects_link_to_the_request_that_followed() {
+        let inventory = recorded();
+        let r = &inventory.resources;
+        assert_eq!(
+            r[1].redirect_to,
+            Some(RedirectTarget {
+                method: "GET".to_string(),
+                url: "https://www.example.com/".to_string(),
+                occurrence: Some(0),
+            })
+        );
+        assert_eq!(
+            r[0].redirect_to.as_ref().unwrap().url,
+            "https://example.com/"
+        );
+        // A redirected POST is followed with a GET
+        assert_eq!(r[4].redirect_to.as_ref().unwrap().method, "GET");
+        assert!(r[2].redirect_to.is_none());
+        assert!(r[6].redirect_to.is_none());
+
+        let serialized = serde_json::to_value(&r[1]).unwrap();
+        assert_eq!(
+            serialized["redirectTo"],
+            serde_json::json!({"method": "GET", "url": "https://www.example.com/", "occurrence": 0})
+        );
+    }
+
+    #[test]
+    fn test_chains_and_their_latency() {
+        let inventory = recorded();
+        let chains = redirect_chains(&inventory.resources);
+        assert_eq!(chains.len(), 3);
+        assert_eq!(chains[0].hops, vec![0, 1]);
+        assert_eq!(chains[0].destination, Some(2));
+        assert_eq!(chains[1].hops, vec![4]);
+        assert_eq!(chains[1].destination, Some(5));
+        assert_eq!(chains[2].hops, vec![6]);
+        assert_eq!(chains[2].destination, None);
+
+        let reports = chain_reports(&inventory);
+        assert_eq!(reports[0].entry_url, "http://example.com/");
+        assert_eq!(
+            reports[0].urls,
+            [
+                "http://example.com/",
+                "https://example.com/",
+                "https://www.example.com/"
+            ]
+        );
+        assert_eq!(reports[0].statuses, [301, 302]);
+        assert_eq!(reports[0].destination_status, Some(200));
+        assert_eq!(reports[0].redirect_ms, 130);
+        assert_eq!(reports[1].redirect_ms, 70);
+        assert_eq!(reports[2].urls, ["https://www.example.com/old"]);
+        assert_eq!(reports[2].destination_status, None);
+
+        // Without request start times the hops' own time is counted
+        let mut untimed = inventory.clone();
+        for resource in &mut untimed.resources {
+            resource.request_start_ms = None;
+        }
+        assert_eq!(chain_reports(&untimed)[0].redirect_ms, 100);
+
+        // Each redirect is followed by the next request for its target, so a chain
+        // going back and forth ends where a redirect was not followed
+        let mut looping = Inventory::new();
+        looping.resources = vec![
+            redirect("GET", "https://example.com/a", 0, 302, "/b"),
+            redirect("GET", "https://example.com/b", 50, 302, "/a"),
+            redirect("GET", "https://example.com/a", 100, 302, "/b"),
+        ];
+        assign_occurrences(&mut looping.resources);
+        link_redirects(&mut looping.resources);
+        let chains = redirect_chains(&looping.resources);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].hops, vec![0, 1, 2]);
+        assert_eq!(chains[0].destination, None);
+    }
+
+    #[test]
+    fn test_collapse_points_redirects_at_the_destination() {
+        let mut replayed = recorded();
+        assert_eq!(RedirectMode::Replay.apply(&mut replayed), 0);
+
+        let mut collapsed = recorded();
+        assert_eq!(RedirectMode::Collapse.apply(&mut collapsed), 3);
+        let location = |resource: &Resource| {
+            resource
+                .raw_headers
+                .as_ref()
+                .unwrap()
+                .get("location")
+                .unwrap()
+                .first()
+                .to_string()
+        };
+        let r = &collapsed.resources;
+        assert_eq!(location(&r[0]), "https://www.example.com/");
+        assert_eq!(location(&r[1]), "https://www.example.com/");
+        assert_eq!((r[0].ttfb_ms, r[0].duration_ms), (0, Some(0)));
+        assert_eq!(location(&r[4]), "https://www.example.com/home");
+        // Redirects without a recorded destination keep their timing
+        assert_eq!(location(&r[6]), "/gone");
+        assert_eq!(r[6].ttfb_ms, 40);
+        assert_eq!(r[2].ttfb_ms, 40);
+    }
+}
@@ -0,0 +1,275 @@
+//! Running a proxy inside another program
+//!
+//! A harness that spawns the binary waits for its `READY port=...` line on stdout and
+//! stops it with `POST http://hpp.local/_shutdown` (or SIGINT), which saves the
+//! inventory. `ProxyServer` runs recording or playback as a task on the caller's runtime
+//! instead: `start` returns once the proxy listens, with the port it bound (use port 0
+//! to let the OS pick one), and `RunningProxy::shutdown` stops it the way a signal
+//! would, waiting until the inventory is written. An embedded proxy does not install
+//! signal handlers, so Ctrl+C keeps its usual meaning for the host.
+
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+use crate::playback::{PlaybackOptions, run_playback_mode};
+use crate::recording::{RecordingOptions, run_recording_mode};
+use crate::types::DeviceType;
+
+/// Asks a running proxy to stop the way SIGINT does, saving a recording first
+///
+/// Clones share one request. `POST http://hpp.local/_shutdown` goes through the proxy's
+/// own handle, so orchestrators that spawn the binary need not send signals.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the shutdown; the proxy finishes in the background
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    async fn requested(&self) {
+        let mut requested = self.0.subscribe();
+        let _ = requested.wait_for(|requested| *requested).await;
+    }
+}
+
+/// Connections between a running proxy and the program that started it
+///
+/// Carried in the mode's options; the default prints a `READY port=...` line once the
+/// proxy listens and stops on signals or a shutdown request.
+#[derive(Debug, Clone, Default)]
+pub struct ServerHooks {
+    listening: Option<mpsc::UnboundedSender<u16>>,
+    shutdown: ShutdownHandle,
+    // Embedded proxies leave signals to the host program
+    embedded: bool,
+}
+
+impl ServerHooks {
+    /// Report the port the proxy is listening on
+    pub fn listening(&self, port: u16) {
+        match &self.listening {
+            Some(listening) => {
+                let _ = listening.send(port);
+            }
+            // Orchestrators spawning the binary wait for this line instead of polling
+            None => println!("READY port={}", port),
+        }
+    }
+
+    /// The handle stopping this proxy, for the `/_shutdown` endpoint
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Wait for `signal` or a shutdown request; embedded proxies wait for the request only
+    pub async fn wait_for_shutdown<S>(&self, signal: S) -> Result<(), std::io::Error>
+    where
+        S: std::future::Future<Output = Result<(), std::io::Error>>,
+    {
+        if self.embedded {
+            self.shutdown.requested().await;
+            tracing::info!("Shutdown requested by the embedding program");
+            return Ok(());
+        }
+        tokio::select! {
+            result = signal => result,
+            () = self.shutdown.requested() => {
+                tracing::info!("Shutdown requested through {}", crate::control::SHUTDOWN_PATH);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Mode {
+    Recording {
+        entry_url: Option<String>,
+        device: DeviceType,
+        options: Box<RecordingOptions>,
+    },
+    Playback {
+        options: Box<PlaybackOptions>,
+    },
+}
+
+/// A recording or playback proxy to start in-process
+#[derive(Debug)]
+pub struct ProxyServer {
+    mode: Mode,
+    inventory_dir: PathBuf,
+    port: u16,
+}
+
+impl ProxyServer {
+    /// Record into `inventory_dir`, as the `recording` command does
+    pub fn recording(inventory_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            mode: Mode::Recording {
+                entry_url: None,
+                device: DeviceType::Mobile,
+                options: Box::default(),
+            },
+            inventory_dir: inventory_dir.into(),
+            port: 0,
+        }
+    }
+
+    /// Replay the inventory in `inventory_dir`, as the `playback` command does
+    pub fn playback(inventory_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            mode: Mode::Playback {
+                options: Box::default(),
+            },
+            inventory_dir: inventory_dir.into(),
+            port: 0,
+        }
+    }
+
+    /// Listen on this port (default 0: any free port, see `RunningProxy::port`)
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Entry URL of a recording
+    pub fn with_entry_url(mut self, url: impl Into<String>) -> Self {
+        if let Mode::Recording { entry_url, .. } = &mut self.mode {
+            *entry_url = Some(url.into());
+        }
+        self
+    }
+
+    /// Device type of a recording (default mobile)
+    pub fn with_device(mut self, device_type: DeviceType) -> Self {
+        if let Mode::Recording { device, .. } = &mut self.mode {
+            *device = device_type;
+        }
+        self
+    }
+
+    /// Options of a recording, as given on the command line
+    pub fn with_recording_options(mut self, recording: RecordingOptions) -> Self {
+        if let Mode::Recording { options, .. } = &mut self.mode {
+            **options = recording;
+        }
+        self
+    }
+
+    /// Options of a playback, as given on the command line
+    pub fn with_playback_options(mut self, playback: PlaybackOptions) -> Self {
+        if let Mode::Playback { options } = &mut self.mode {
+            **options = playback;
+        }
+        self
+    }
+
+    /// Start the proxy and wait until it listens
+    ///
+    /// Fails if the proxy stops before binding its port, e.g. for a missing inventory.
+    pub async fn start(self) -> Result<RunningProxy> {
+        let (listening_tx, mut listening_rx) = mpsc::unbounded_channel();
+        let shutdown = ShutdownHandle::new();
+        let hooks = ServerHooks {
+            listening: Some(listening_tx),
+            shutdown: shutdown.clone(),
+            embedded: true,
+        };
+
+        let port = Some(self.port);
+        let inventory_dir = self.inventory_dir;
+        let mut task = match self.mode {
+            Mode::Recording {
+                entry_url,
+                device,
+                mut options,
+            } => {
+                options.hooks = hooks;
+                tokio::spawn(run_recording_mode(
+                    entry_url,
+                    port,
+                    device,
+                    inventory_dir,
+                    *options,
+                ))
+            }
+            Mode::Playback { mut options } => {
+                options.hooks = hooks;
+                tokio::spawn(run_playback_mode(port, inventory_dir, *options))
+            }
+        };
+
+        tokio::select! {
+            Some(port) = listening_rx.recv() => Ok(RunningProxy {
+                port,
+                shutdown: ShutdownOnDrop(shutdown),
+                task,
+            }),
+            result = &mut task => match result? {
+                Ok(()) => anyhow::bail!("Proxy stopped before listening"),
+                Err(e) => Err(e),
+            },
+        }
+    }
+}
+
+/// A proxy started by `ProxyServer::start`
+#[derive(Debug)]
+pub struct RunningProxy {
+    port: u16,
+    shutdown: ShutdownOnDrop,
+    task: JoinHandle<Result<()>>,
+}
+
+// A proxy whose RunningProxy is dropped stops as if shut down
+#[derive(Debug)]
+struct ShutdownOnDrop(ShutdownHandle);
+
+impl Drop for ShutdownOnDrop {
+    fn drop(&mut self) {
+        self.0.shutdown();
+    }
+}
+
+impl RunningProxy {
+    /// The port the proxy listens on
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The proxy's address for clients, e.g. `http://127.0.0.1:18080`
+    pub fn proxy_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    /// A handle to stop the proxy from elsewhere, e.g. while another task awaits `wait`
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.0.clone()
+    }
+
+    /// Stop the proxy and wait until it has finished, including saving a recording
+    pub async fn shutdown(self) -> Result<()> {
+        self.shutdown.0.shutdown();
+        self.task.await?
+    }
+
+    /// Wait until the proxy stops by itself, e.g. at the end of a `--launch-chrome` session
+    pub async fn wait(self) -> Result<()> {
+        self.task.await?
+    }
+}
+
+mod tests;
@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod server_tests {
+    use crate::recording::proxy::save_inventory;
+    use crate::server::ProxyServer;
+    use crate::types::{Inventory, Resource};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_playback_server_reports_port_and_shuts_down() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut inventory = Inventory::new();
+        let mut resource = Resource::new("GET".to_string(), "https://example.com/".to_string());
+        resource.status_code = Some(200);
+        resource.content_utf8 = Some("<html></html>".to_string());
+        inventory.resources.push(resource);
+        save_inventory(&inventory, temp_dir.path()).await.unwrap();
+
+        let proxy = ProxyServer::playback(temp_dir.path())
+            .start()
+            .await
+            .unwrap();
+        assert_ne!(proxy.port(), 0);
+        assert_eq!(
+            proxy.proxy_url(),
+            format!("http://127.0.0.1:{}", proxy.port())
+        );
+        tokio::net::TcpStream::connect(("127.0.0.1", proxy.port()))
+            .await
+            .unwrap();
+        proxy.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_handle_ends_wait_for_shutdown() {
+        use crate::server::ServerHooks;
+
+        let hooks = ServerHooks::default();
+        let handle = hooks.shutdown_handle();
+        let waiting = tokio::spawn(async move {
+            hooks
+                .wait_for_shutdown(std::future::pending::<Result<(), std::io::Error>>())
+                .await
+        });
+        handle.shutdown();
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiting)
+            .await
+            .expect("a shutdown request stops waiting for signals")
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_start_fails_without_inventory() {
+        let temp_dir = TempDir::new().unwrap();
+        let result = ProxyServer::playback(temp_dir.path().join("missing"))
+            .start()
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bodiless_responses_keep_connection_framing() {
+        use crate::types::{HeaderValue, HttpHeaders};
+        use std::time::Duration;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        // Send a request on the connection and read its response up to the end of the head
+        async fn exchange(stream: &mut TcpStream, request: &str) -> String {
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                let mut byte = [0u8; 1];
+                let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut byte))
+                    .await
+                    .expect("response head in time")
+                    .unwrap();
+                assert_eq!(read, 1, "connection closed after {:?}", head);
+                head.push(byte[0]);
+            }
+            String::from_utf8(head).unwrap().to_ascii_lowercase()
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut inventory = Inventory::new();
+        for (path, status, body) in [
+            ("/empty", 204, "stale body"),
+            ("/cached", 304, "cached body"),
+            ("/page", 200, "<p>page</p>"),
+        ] {
+            let mut resource =
+                Resource::new("GET".to_string(), format!("http://example.test{}", path));
+            resource.status_code = Some(status);
+            resource.content_utf8 = Some(body.to_string());
+            let mut headers = HttpHeaders::new();
+            headers.set(
+                "content-length",
+                HeaderValue::Single(body.len().to_string()),
+            );
+            resource.raw_headers = Some(headers);
+            inventory.resources.push(resource);
+        }
+        save_inventory(&inventory, temp_dir.path()).await.unwrap();
+        let proxy = ProxyServer::playback(temp_dir.path())
+            .start()
+            .await
+            .unwrap();
+
+        // One keep-alive connection for every request
+        let mut stream = TcpStream::connect(("127.0.0.1", proxy.port()))
+            .await
+            .unwrap();
+        let no_content = exchange(
+            &mut stream,
+            "GET http://example.test/empty HTTP/1.1\r\nHost: example.test\r\n\r\n",
+        )
+        .await;
+        assert!(no_content.starts_with("http/1.1 204"), "{}", no_content);
+        assert!(!no_content.contains("content-length"), "{}", no_content);
+
+        // A body left behind by the 204 would be read as the start of this response
+        let not_modified = exchange(
+            &mut stream,
+            "GET http://example.test/cached HTTP/1.1\r\nHost: example.test\r\n\r\n",
+        )
+        .await;
+        assert!(not_modified.starts_with("http/1.1 304"), "{}", not_modified);
+        assert!(!not_modified.contains("content-length"), "{}", not_modified);
+
+        let head = exchange(
+            &mut stream,
+            "HEAD http://example.test/page HTTP/1.1\r\nHost: example.test\r\n\r\n",
+        )
+        .await;
+        assert!(head.starts_with("http/1.1 200"), "{}", head);
+        assert!(head.contains("content-length: 11"), "{}", head);
+
+        let page = exchange(&mut stream, "GET http://example.test/page HTTP/1.1\r\nHost: example.test\r\nConnection: close\r\n\r\n").await;
+        assert!(page.starts_with("http/1.1 200"), "{}", page);
+        assert!(page.contains("content-length: 11"), "{}", page);
+        let mut body = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut body))
+            .await
+            .expect("connection closed after the body")
+            .unwrap();
+        assert_eq!(body, b"<p>page</p>");
+
+        proxy.shutdown().await.unwrap();
+    }
+}
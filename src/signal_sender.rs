@@ -19,9 +19,11 @@ pub enum SignalKind {
     Int,
 }
 
-impl SignalKind {
+impl std::str::FromStr for SignalKind {
+    type Err = anyhow::Error;
+
     /// Parse signal kind from string
-    pub fn from_str(s: &str) -> Result<Self> {
+    fn from_str(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
             "ctrl-break" => Ok(Self::CtrlBreak),
             "ctrl-c" => Ok(Self::CtrlC),
@@ -1,6 +1,31 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use bytes::Bytes;
 use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Files at least this large are memory-mapped instead of read into memory
+const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// Tail of a precise sleep spent polling instead of on tokio's millisecond timer
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+/// Below this remaining time a precise sleep spins without yielding to other tasks
+const SPIN_WITHOUT_YIELD: Duration = Duration::from_micros(200);
+
+/// How waits until a deadline are carried out
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SleepMode {
+    /// tokio's timer: cheap, but wakes up to a millisecond or two late
+    #[default]
+    Coarse,
+    /// Timer for the bulk of the wait, then poll the clock until the deadline
+    ///
+    /// Costs CPU on a runtime worker for the last couple of milliseconds of every
+    /// wait, in exchange for sub-millisecond accuracy of short TTFBs and chunk gaps.
+    Precise,
+}
 
 /// HTTP client abstraction for making requests
 #[async_trait]
@@ -29,7 +54,27 @@ pub struct HttpResponse {
 #[async_trait]
 pub trait FileSystem: Send + Sync {
     async fn read(&self, path: &Path) -> Result<Vec<u8>>;
+    /// Read a file as shared bytes; implementations may avoid copying large files
+    async fn read_bytes(&self, path: &Path) -> Result<Bytes> {
+        Ok(Bytes::from(self.read(path).await?))
+    }
+    async fn file_len(&self, path: &Path) -> Result<u64> {
+        Ok(self.read_bytes(path).await?.len() as u64)
+    }
+    /// Read up to `len` bytes starting at `offset`; empty past the end of the file
+    async fn read_range(&self, path: &Path, offset: u64, len: usize) -> Result<Bytes> {
+        let content = self.read_bytes(path).await?;
+        let start = (offset as usize).min(content.len());
+        let end = start.saturating_add(len).min(content.len());
+        Ok(content.slice(start..end))
+    }
     async fn write(&self, path: &Path, content: &[u8]) -> Result<()>;
+    /// Copy a file, returning its size; implementations may avoid reading it into memory
+    async fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        let content = self.read(from).await?;
+        self.write(to, &content).await?;
+        Ok(content.len() as u64)
+    }
     async fn create_dir_all(&self, path: &Path) -> Result<()>;
     async fn exists(&self, path: &Path) -> bool;
     async fn read_to_string(&self, path: &Path) -> Result<String>;
@@ -37,10 +82,15 @@ pub trait FileSystem: Send + Sync {
 }
 
 /// Time abstraction for testing timing behavior
-#[allow(dead_code)]
+///
+/// Playback takes TTFB and chunk deadlines from `now` and waits for them with
+/// `sleep_until`, so its schedule can be driven by a mock clock instead of real sleeps.
+#[async_trait]
 pub trait TimeProvider: Send + Sync {
     fn now_ms(&self) -> u64;
     fn elapsed_since(&self, start: u64) -> u64;
+    fn now(&self) -> Instant;
+    async fn sleep_until(&self, deadline: Instant);
 }
 
 /// Port finder abstraction
@@ -51,17 +101,39 @@ pub trait PortFinder: Send + Sync {
 
 /// Real implementations
 pub struct RealFileSystem;
+
+/// HTTP client talking to origins directly; redirects are returned, not followed
+pub struct RealHttpClient {
+    client: reqwest::Client,
+}
+
+impl RealHttpClient {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        Ok(Self { client })
+    }
+}
 pub struct RealTimeProvider {
     #[allow(dead_code)]
     start_time: std::time::Instant,
+    sleep_mode: SleepMode,
 }
 
 impl RealTimeProvider {
     pub fn new() -> Self {
         Self {
             start_time: std::time::Instant::now(),
+            sleep_mode: SleepMode::default(),
         }
     }
+
+    /// Wait for deadlines this way
+    pub fn with_sleep_mode(mut self, sleep_mode: SleepMode) -> Self {
+        self.sleep_mode = sleep_mode;
+        self
+    }
 }
 
 impl Default for RealTimeProvider {
@@ -70,12 +142,76 @@ impl Default for RealTimeProvider {
     }
 }
 
+/// Wait until `deadline` using the given mode
+pub async fn sleep_until(deadline: Instant, mode: SleepMode) {
+    match mode {
+        SleepMode::Coarse => {
+            if deadline > Instant::now() {
+                tokio::time::sleep_until(tokio::time::Instant::from_std(deadline)).await;
+            }
+        }
+        SleepMode::Precise => {
+            let coarse_deadline = deadline
+                .checked_sub(SPIN_MARGIN)
+                .filter(|coarse| *coarse > Instant::now());
+            if let Some(coarse_deadline) = coarse_deadline {
+                tokio::time::sleep_until(tokio::time::Instant::from_std(coarse_deadline)).await;
+            }
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                if deadline - now > SPIN_WITHOUT_YIELD {
+                    // Let other tasks on this worker run between polls
+                    tokio::task::yield_now().await;
+                } else {
+                    std::hint::spin_loop();
+                }
+            }
+        }
+    }
+}
+
 #[async_trait]
 impl FileSystem for RealFileSystem {
     async fn read(&self, path: &Path) -> Result<Vec<u8>> {
         Ok(tokio::fs::read(path).await?)
     }
 
+    async fn read_bytes(&self, path: &Path) -> Result<Bytes> {
+        let metadata = tokio::fs::metadata(path).await?;
+        if metadata.len() < MMAP_THRESHOLD {
+            return Ok(Bytes::from(tokio::fs::read(path).await?));
+        }
+
+        // Large assets are served straight from the page cache so playback RSS
+        // only grows with the parts clients actually fetch.
+        let path = path.to_path_buf();
+        let mmap = tokio::task::spawn_blocking(move || -> Result<memmap2::Mmap> {
+            let file = std::fs::File::open(&path)?;
+            // Safety: content files must not be truncated while being served; playback
+            // that picks up edits (--watch) reads them through PooledFileSystem with
+            // memory maps off instead.
+            Ok(unsafe { memmap2::Mmap::map(&file)? })
+        })
+        .await??;
+        Ok(Bytes::from_owner(mmap))
+    }
+
+    async fn file_len(&self, path: &Path) -> Result<u64> {
+        Ok(tokio::fs::metadata(path).await?.len())
+    }
+
+    async fn read_range(&self, path: &Path, offset: u64, len: usize) -> Result<Bytes> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+        let mut file = tokio::fs::File::open(path).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        let mut buffer = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut buffer).await?;
+        Ok(Bytes::from(buffer))
+    }
+
     async fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
         if let Some(parent) = path.parent() {
             tokio::fs::create_dir_all(parent).await?;
@@ -88,6 +224,13 @@ impl FileSystem for RealFileSystem {
         Ok(())
     }
 
+    async fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        if let Some(parent) = to.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        Ok(tokio::fs::copy(from, to).await?)
+    }
+
     async fn create_dir_all(&self, path: &Path) -> Result<()> {
         tokio::fs::create_dir_all(path).await?;
         Ok(())
@@ -106,6 +249,142 @@ impl FileSystem for RealFileSystem {
     }
 }
 
+/// File system that caps how many files are open at once
+///
+/// Reads and writes beyond the limit wait for a slot instead of failing with "too many
+/// open files"; directory operations are not counted. Without a limit it is a plain
+/// pass-through.
+pub struct PooledFileSystem<F: FileSystem> {
+    inner: F,
+    permits: Option<Semaphore>,
+    memory_maps: bool,
+}
+
+impl<F: FileSystem> PooledFileSystem<F> {
+    pub fn new(inner: F, max_open: Option<usize>) -> Self {
+        Self {
+            inner,
+            permits: max_open.map(|n| Semaphore::new(n.max(1))),
+            memory_maps: true,
+        }
+    }
+
+    /// Let `read_bytes` memory-map large files (default); turned off when files may be
+    /// truncated while served, which would fault a mapped read
+    pub fn with_memory_maps(mut self, enabled: bool) -> Self {
+        self.memory_maps = enabled;
+        self
+    }
+
+    async fn acquire(&self) -> Result<Option<SemaphorePermit<'_>>> {
+        match &self.permits {
+            Some(permits) => Ok(Some(permits.acquire().await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl<F: FileSystem> FileSystem for PooledFileSystem<F> {
+    async fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let _permit = self.acquire().await?;
+        self.inner.read(path).await
+    }
+
+    async fn read_bytes(&self, path: &Path) -> Result<Bytes> {
+        // A memory map outlives its file handle, so the slot is only held while opening
+        let _permit = self.acquire().await?;
+        if self.memory_maps {
+            self.inner.read_bytes(path).await
+        } else {
+            Ok(Bytes::from(self.inner.read(path).await?))
+        }
+    }
+
+    async fn file_len(&self, path: &Path) -> Result<u64> {
+        self.inner.file_len(path).await
+    }
+
+    async fn read_range(&self, path: &Path, offset: u64, len: usize) -> Result<Bytes> {
+        let _permit = self.acquire().await?;
+        self.inner.read_range(path, offset, len).await
+    }
+
+    async fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let _permit = self.acquire().await?;
+        self.inner.write(path, content).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        let _permit = self.acquire().await?;
+        self.inner.copy(from, to).await
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.inner.create_dir_all(path).await
+    }
+
+    async fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path).await
+    }
+
+    async fn read_to_string(&self, path: &Path) -> Result<String> {
+        let _permit = self.acquire().await?;
+        self.inner.read_to_string(path).await
+    }
+
+    async fn write_string(&self, path: &Path, content: &str) -> Result<()> {
+        let _permit = self.acquire().await?;
+        self.inner.write_string(path, content).await
+    }
+}
+
+#[async_trait]
+impl HttpClient for RealHttpClient {
+    async fn request(
+        &self,
+        method: &str,
+        url: &str,
+        headers: Option<&std::collections::HashMap<String, String>>,
+        body: Option<&[u8]>,
+    ) -> Result<HttpResponse> {
+        let started = std::time::Instant::now();
+        let mut request = self
+            .client
+            .request(reqwest::Method::from_bytes(method.as_bytes())?, url);
+        for (name, value) in headers.into_iter().flatten() {
+            request = request.header(name, value);
+        }
+        if let Some(body) = body {
+            request = request.body(body.to_vec());
+        }
+
+        let response = request.send().await?;
+        let status_code = response.status().as_u16();
+        let mut response_headers = std::collections::HashMap::new();
+        for (name, value) in response.headers() {
+            if let Ok(value) = value.to_str() {
+                response_headers
+                    .entry(name.to_string())
+                    .and_modify(|existing: &mut String| {
+                        existing.push_str(", ");
+                        existing.push_str(value);
+                    })
+                    .or_insert_with(|| value.to_string());
+            }
+        }
+        let body = response.bytes().await?.to_vec();
+
+        Ok(HttpResponse {
+            status_code,
+            headers: response_headers,
+            body,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+#[async_trait]
 impl TimeProvider for RealTimeProvider {
     fn now_ms(&self) -> u64 {
         self.start_time.elapsed().as_millis() as u64
@@ -115,6 +394,14 @@ impl TimeProvider for RealTimeProvider {
         let now = self.now_ms();
         now.saturating_sub(start)
     }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        sleep_until(deadline, self.sleep_mode).await
+    }
 }
 
 #[cfg(test)]
@@ -124,6 +411,7 @@ pub mod mocks {
     use std::sync::{Arc, Mutex};
 
     /// Mock HTTP client for testing
+    #[derive(Default)]
     pub struct MockHttpClient {
         responses: Arc<Mutex<HashMap<String, HttpResponse>>>,
         requests: Arc<Mutex<Vec<(String, String)>>>, // (method, url)
@@ -179,6 +467,7 @@ pub mod mocks {
     }
 
     /// Mock file system for testing
+    #[derive(Default)]
     pub struct MockFileSystem {
         files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
         directories: Arc<Mutex<std::collections::HashSet<String>>>,
@@ -264,35 +553,56 @@ pub mod mocks {
     }
 
     /// Mock time provider for testing
+    ///
+    /// Time only moves when advanced or slept through: a wait returns at once with the
+    /// clock moved to its deadline, so schedules play out exactly and instantly.
     pub struct MockTimeProvider {
-        current_time: Arc<Mutex<u64>>,
+        origin: Instant,
+        current_time: Arc<Mutex<std::time::Duration>>,
     }
 
     #[allow(dead_code)]
     impl MockTimeProvider {
         pub fn new(initial_time: u64) -> Self {
             Self {
-                current_time: Arc::new(Mutex::new(initial_time)),
+                origin: Instant::now(),
+                current_time: Arc::new(Mutex::new(std::time::Duration::from_millis(initial_time))),
             }
         }
 
         pub fn advance(&self, ms: u64) {
-            *self.current_time.lock().unwrap() += ms;
+            *self.current_time.lock().unwrap() += std::time::Duration::from_millis(ms);
         }
 
         pub fn set_time(&self, ms: u64) {
-            *self.current_time.lock().unwrap() = ms;
+            *self.current_time.lock().unwrap() = std::time::Duration::from_millis(ms);
         }
     }
 
+    #[async_trait]
     impl TimeProvider for MockTimeProvider {
         fn now_ms(&self) -> u64 {
-            *self.current_time.lock().unwrap()
+            self.current_time.lock().unwrap().as_millis() as u64
         }
 
         fn elapsed_since(&self, start: u64) -> u64 {
             let now = self.now_ms();
             now.saturating_sub(start)
         }
+
+        fn now(&self) -> Instant {
+            self.origin + *self.current_time.lock().unwrap()
+        }
+
+        async fn sleep_until(&self, deadline: Instant) {
+            {
+                let mut current = self.current_time.lock().unwrap();
+                *current = (*current).max(deadline.saturating_duration_since(self.origin));
+            }
+            // Other tasks waiting on the clock get to run, as after a real sleep
+            tokio::task::yield_now().await;
+        }
     }
 }
+
+mod tests;
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod traits_tests {
+    use crate::traits::{FileSystem, PooledFileSystem};
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Reads take a while and report the highest number that overlapped
+    #[derive(Default)]
+    struct SlowFileSystem {
+        open: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl FileSystem for SlowFileSystem {
+        async fn read(&self, _path: &Path) -> Result<Vec<u8>> {
+            let open = self.open.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(open, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.open.fetch_sub(1, Ordering::SeqCst);
+            Ok(b"content".to_vec())
+        }
+
+        async fn write(&self, _path: &Path, _content: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        async fn create_dir_all(&self, _path: &Path) -> Result<()> {
+            Ok(())
+        }
+
+        async fn exists(&self, _path: &Path) -> bool {
+            true
+        }
+
+        async fn read_to_string(&self, path: &Path) -> Result<String> {
+            Ok(String::from_utf8(self.read(path).await?)?)
+        }
+
+        async fn write_string(&self, path: &Path, content: &str) -> Result<()> {
+            self.write(path, content.as_bytes()).await
+        }
+    }
+
+    async fn peak_concurrent_reads(max_open: Option<usize>) -> usize {
+        let file_system = Arc::new(PooledFileSystem::new(SlowFileSystem::default(), max_open));
+        let reads = (0..8).map(|_| {
+            let file_system = file_system.clone();
+            tokio::spawn(async move { file_system.read(Path::new("/file")).await })
+        });
+        for read in futures::future::join_all(reads).await {
+            assert_eq!(read.unwrap().unwrap(), b"content");
+        }
+        file_system.inner.peak.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn test_pooled_file_system_caps_open_files() {
+        assert_eq!(peak_concurrent_reads(Some(2)).await, 2);
+        assert_eq!(peak_concurrent_reads(None).await, 8);
+    }
+
+    /// Serves `read_bytes` from somewhere other than `read`, as memory maps do
+    struct MappingFileSystem;
+
+    #[async_trait]
+    impl FileSystem for MappingFileSystem {
+        async fn read(&self, _path: &Path) -> Result<Vec<u8>> {
+            Ok(b"copied".to_vec())
+        }
+
+        async fn read_bytes(&self, _path: &Path) -> Result<bytes::Bytes> {
+            Ok(bytes::Bytes::from_static(b"mapped"))
+        }
+
+        async fn write(&self, _path: &Path, _content: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        async fn create_dir_all(&self, _path: &Path) -> Result<()> {
+            Ok(())
+        }
+
+        async fn exists(&self, _path: &Path) -> bool {
+            true
+        }
+
+        async fn read_to_string(&self, _path: &Path) -> Result<String> {
+            Ok(String::new())
+        }
+
+        async fn write_string(&self, _path: &Path, _content: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pooled_file_system_can_turn_off_memory_maps() {
+        let path = Path::new("/file");
+        let mapped = PooledFileSystem::new(MappingFileSystem, None);
+        assert_eq!(mapped.read_bytes(path).await.unwrap(), "mapped");
+
+        let copied = PooledFileSystem::new(MappingFileSystem, None).with_memory_maps(false);
+        assert_eq!(copied.read_bytes(path).await.unwrap(), "copied");
+    }
+}
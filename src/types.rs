@@ -1,8 +1,12 @@
+use bytes::Bytes;
 use clap::ValueEnum;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::path::PathBuf;
 use std::str::FromStr;
 
+use crate::url_pattern::UrlPattern;
+
 /// HTTP header value that can be either a single string or multiple strings (for headers like Set-Cookie)
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(untagged)]
@@ -42,7 +46,158 @@ impl HeaderValue {
     }
 }
 
-pub type HttpHeaders = HashMap<String, HeaderValue>;
+/// Response headers in the order the origin sent them
+///
+/// Entries are keyed by lowercased name, so every lookup is case-insensitive, and keep
+/// the name as recorded, which is what serialization and playback emit. Serialized as a
+/// JSON object whose key order matches the original response; names that differ only
+/// in case are merged into one entry when loaded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HttpHeaders {
+    entries: IndexMap<String, HeaderEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct HeaderEntry {
+    name: String,
+    value: HeaderValue,
+}
+
+impl HttpHeaders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HeaderValue> {
+        self.entries
+            .get(&name.to_ascii_lowercase())
+            .map(|entry| &entry.value)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(&name.to_ascii_lowercase())
+    }
+
+    /// The `Access-Control-Request-*` headers of a CORS preflight, None for other requests
+    ///
+    /// Recorded as a preflight's `matchHeaders`, so playback tells apart preflights of
+    /// one URL sent ahead of different methods or header sets.
+    pub fn preflight_request(method: &str, headers: &hyper::HeaderMap) -> Option<Self> {
+        if method != "OPTIONS" {
+            return None;
+        }
+        let requested_method = headers
+            .get("access-control-request-method")?
+            .to_str()
+            .ok()?;
+        let mut result = Self::new();
+        result.append(
+            "access-control-request-method",
+            requested_method.to_string(),
+        );
+        let requested_headers = headers
+            .get("access-control-request-headers")
+            .and_then(|v| v.to_str().ok());
+        if let Some(requested_headers) = requested_headers {
+            result.append(
+                "access-control-request-headers",
+                requested_headers.to_string(),
+            );
+        }
+        Some(result)
+    }
+
+    /// Replace a header in place (keeping its position and recorded name) or append it
+    pub fn set(&mut self, name: &str, value: HeaderValue) {
+        match self.entries.get_mut(&name.to_ascii_lowercase()) {
+            Some(entry) => entry.value = value,
+            None => {
+                self.entries.insert(
+                    name.to_ascii_lowercase(),
+                    HeaderEntry {
+                        name: name.to_string(),
+                        value,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Add one value, turning an existing header into a list (as for Set-Cookie)
+    pub fn append(&mut self, name: &str, value: String) {
+        match self.entries.get_mut(&name.to_ascii_lowercase()) {
+            Some(entry) => match &mut entry.value {
+                HeaderValue::Single(first) => {
+                    entry.value = HeaderValue::Multiple(vec![std::mem::take(first), value]);
+                }
+                HeaderValue::Multiple(values) => values.push(value),
+            },
+            None => self.set(name, HeaderValue::Single(value)),
+        }
+    }
+
+    /// Remove a header, keeping the order of the others
+    #[allow(dead_code)]
+    pub fn remove(&mut self, name: &str) -> Option<HeaderValue> {
+        self.entries
+            .shift_remove(&name.to_ascii_lowercase())
+            .map(|entry| entry.value)
+    }
+
+    /// Headers in recorded order, with their recorded names
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &HeaderValue)> {
+        self.entries
+            .values()
+            .map(|entry| (entry.name.as_str(), &entry.value))
+    }
+
+    #[allow(dead_code)]
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.values().map(|entry| entry.name.as_str())
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Serialize for HttpHeaders {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (name, value) in self.iter() {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for HttpHeaders {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let recorded = IndexMap::<String, HeaderValue>::deserialize(deserializer)?;
+        let mut headers = HttpHeaders::new();
+        for (name, value) in recorded {
+            match value {
+                HeaderValue::Single(value) => headers.append(&name, value),
+                HeaderValue::Multiple(values) if !headers.contains(&name) => {
+                    headers.set(&name, HeaderValue::Multiple(values))
+                }
+                HeaderValue::Multiple(values) => {
+                    for value in values {
+                        headers.append(&name, value);
+                    }
+                }
+            }
+        }
+        Ok(headers)
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -69,18 +224,166 @@ impl FromStr for ContentEncodingType {
     }
 }
 
+/// A 103 Early Hints response as the origin sent it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EarlyHint {
+    // Milliseconds from the request to this interim response
+    pub offset_ms: u64,
+    pub headers: HttpHeaders,
+}
+
+/// One part of a recorded multipart/form-data request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FormPart {
+    pub name: String,
+    // Text fields only; file parts are stored on disk instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    // Relative to the inventory directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    pub size: u64,
+
+    // Uploaded bytes, held only until batch processing writes them to file_path
+    #[serde(skip)]
+    pub data: Option<Vec<u8>>,
+}
+
+/// One event of a recorded `text/event-stream` response
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamEvent {
+    // Milliseconds from the response headers to the arrival of the event's last byte
+    pub offset_ms: u64,
+    // Bytes of the event in the body, including the blank line ending it
+    pub size: u64,
+}
+
+/// Request headers that describe the client, for telling whether playback is driven by
+/// the same kind of client that was recorded
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sec_ch_ua: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sec_ch_ua_mobile: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sec_ch_ua_platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub viewport_width: Option<String>,
+}
+
+impl ClientMetadata {
+    /// The client headers of a request, or None when it sent none of them
+    pub fn from_headers(headers: &hyper::HeaderMap) -> Option<Self> {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let metadata = Self {
+            user_agent: get("user-agent"),
+            accept_encoding: get("accept-encoding"),
+            sec_ch_ua: get("sec-ch-ua"),
+            sec_ch_ua_mobile: get("sec-ch-ua-mobile"),
+            sec_ch_ua_platform: get("sec-ch-ua-platform"),
+            viewport_width: get("viewport-width").or_else(|| get("sec-ch-viewport-width")),
+        };
+        (metadata != Self::default()).then_some(metadata)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Resource {
     pub method: String,
     pub url: String,
+    // 0-based index among resources with the same method and URL, set only when repeated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occurrence: Option<u32>,
+    // Milliseconds from the start of the recording session to this request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_start_ms: Option<u64>,
     pub ttfb_ms: u64,
+    // Time spent opening the upstream connection this request waited for (DNS, TCP,
+    // TLS); not included in ttfb_ms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_ms: Option<u64>,
+    // Protocol the origin answered over
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<HttpProtocol>,
+    // Time the client took to send the request body and its size; not included in
+    // ttfb_ms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_bytes: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub duration_ms: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mbps: Option<f64>,
+    // When the body arrived: [bytes, ms] points, each saying the first `bytes` bytes of
+    // the body as transferred had arrived `ms` after the headers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_timings: Option<Vec<[u64; 2]>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub status_code: Option<u16>,
+    // Non-standard reason phrase sent by the origin (e.g. "429 Slow Down")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason_phrase: Option<String>,
+    // Interim 103 responses received before the final one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub early_hints: Option<Vec<EarlyHint>>,
+    // For a redirect: the recorded request the client followed it with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_to: Option<RedirectTarget>,
+    // Parts of a multipart/form-data request body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_form: Option<Vec<FormPart>>,
+    // User-Agent, Accept-Encoding and client hints the request was sent with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_client: Option<ClientMetadata>,
+    // SHA-1 of the request body, for requests that had one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body_sha1: Option<String>,
+    // Headers of the recorded request, in the order they were sent; values of secret
+    // ones (cookies, credentials) are replaced with REDACTED
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_headers: Option<HttpHeaders>,
+    // Request headers this recording answers under the header matching strategy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_headers: Option<HttpHeaders>,
+    // Server-Sent Events of the body in arrival order, replayed one by one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_stream: Option<Vec<StreamEvent>>,
+    // The event stream was still open when recording stopped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_stream_open: Option<bool>,
+    // The body had not ended when the client gave up on it or recording stopped; the
+    // content is what had arrived by then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_ended: Option<bool>,
+    // For an object recorded in partial (206) responses: the spans the content file
+    // holds back to back, and the object's full length when the server stated it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_ranges: Option<Vec<ByteRange>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub complete_length: Option<u64>,
+    // Why no response was received; errorMessage has the details
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error_message: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -89,8 +392,18 @@ pub struct Resource {
     pub content_encoding: Option<ContentEncodingType>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content_type_mime: Option<String>,
+    // Type told from the body's leading bytes when Content-Type was missing, generic or
+    // contradicted by them; handled as the resource's type in its place
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sniffed_mime: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content_charset: Option<String>,
+    // The content file holds the body in contentCharset as received, not converted to UTF-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charset_preserved: Option<bool>,
+    // The text body as received, next to its UTF-8 content file (recording --charset both)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_file_path: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content_file_path: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -99,11 +412,109 @@ pub struct Resource {
     pub content_base64: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub minify: Option<bool>,
+    // The script's source map, next to its beautified content file (recording --source-maps)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_map_file_path: Option<String>,
+    // The original sources its source map embeds (recording --source-map-sources)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources_dir: Option<String>,
+    // Body size as received (before decompression), i.e. the recorded Content-Length
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_size: Option<u64>,
+    // Playback answers with unavailable_status (default 404) until this many ms after it
+    // started, for assets that appeared mid-session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available_after_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unavailable_status: Option<u16>,
+    // How `url` is matched: exactly (default), or as a glob or regex standing for a
+    // family of URLs, in which case the body is a template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url_match: Option<UrlMatch>,
 
     // Raw body bytes (as received from upstream, possibly compressed)
     // This field is used only during recording and is not serialized to index.json
     #[serde(skip)]
     pub raw_body: Option<Vec<u8>>,
+
+    // File holding the raw body instead, for bodies too large to keep in memory
+    // (recording only, not serialized)
+    #[serde(skip)]
+    pub raw_body_file: Option<PathBuf>,
+
+    // Size and hash of the body as forwarded to the client, checked against raw_body
+    // before it is stored (recording only, not serialized)
+    #[serde(skip)]
+    pub forwarded_body: Option<BodyDigest>,
+
+    // Source map the script names, stored by batch processing (recording only, not
+    // serialized)
+    #[serde(skip)]
+    pub source_map_url: Option<String>,
+}
+
+/// The recorded resource a redirect led to, by method, URL and occurrence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedirectTarget {
+    pub method: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occurrence: Option<u32>,
+}
+
+/// Bytes `start` to `end` of an object, both inclusive as in Content-Range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// Which parts of an object a ranged transaction's body holds
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeMap {
+    /// Stored back to back in the body, in object order
+    pub spans: Vec<ByteRange>,
+    pub complete_length: Option<u64>,
+}
+
+/// Size and SHA-1 of a body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BodyDigest {
+    pub size: u64,
+    pub sha1: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlMatch {
+    #[default]
+    Exact,
+    Glob,
+    Regex,
+}
+
+/// How playback decides that a recording answers a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchStrategy {
+    /// Same method, host, path and query string
+    Exact,
+    /// Same method, host and path; the query string is ignored
+    IgnoreQuery,
+    /// Same method, host and path, with every recorded query parameter in the request;
+    /// extra request parameters are ignored and the recording with most parameters wins
+    QuerySubset,
+    /// Exact, and the request carries the resource's `matchHeaders` with the same values
+    Header,
+    /// Exact, and the request body has the recorded `requestBodySha1`
+    BodyHash,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ValueEnum, PartialEq)]
@@ -121,12 +532,181 @@ pub struct Inventory {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub device_type: Option<DeviceType>,
     pub resources: Vec<Resource>,
+    /// Traffic through CONNECT tunnels that were passed through without decrypting
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub opaque_tunnels: Vec<OpaqueTunnel>,
+    /// Seed the randomized content of a generated inventory was drawn from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Matching strategies playback tries in order when no --match is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_strategies: Option<Vec<MatchStrategy>>,
+    /// Addresses each origin host resolved to and was connected at while recording
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub origins: Vec<OriginAddress>,
+    /// How the recording client spread its requests to each host over connections
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub client_connections: Vec<HostConnections>,
+}
+
+/// Traffic volume of the undecrypted tunnels to one host during a recording
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpaqueTunnel {
+    pub host: String,
+    pub connections: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Total time the tunnels were open
+    pub duration_ms: u64,
+}
+
+/// Where one origin host was reached during a recording
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OriginAddress {
+    pub host: String,
+    /// Every address DNS returned, in the resolver's order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolved: Vec<String>,
+    /// Addresses upstream connections were opened to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub connected: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip_version: Option<IpVersion>,
+}
+
+/// Client connections the recording browser opened to one host
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostConnections {
+    pub host: String,
+    pub connections: u64,
+    /// Requests carried by each connection, in the order the connections were opened
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requests: Vec<u64>,
+    /// Requests shared a connection concurrently (HTTP/2)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub multiplexed: bool,
+}
+
+/// HTTP protocol of a connection, by its ALPN name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HttpProtocol {
+    #[serde(rename = "http/1.0")]
+    Http10,
+    #[serde(rename = "http/1.1")]
+    Http11,
+    #[serde(rename = "h2")]
+    H2,
+    #[serde(rename = "h3")]
+    H3,
+}
+
+impl HttpProtocol {
+    pub fn from_version(version: hyper::Version) -> Option<Self> {
+        match version {
+            hyper::Version::HTTP_10 => Some(HttpProtocol::Http10),
+            hyper::Version::HTTP_11 => Some(HttpProtocol::Http11),
+            hyper::Version::HTTP_2 => Some(HttpProtocol::H2),
+            hyper::Version::HTTP_3 => Some(HttpProtocol::H3),
+            _ => None,
+        }
+    }
+
+    /// Parse an ALPN name or a version as HAR files write it (`HTTP/1.1`, `http/2.0`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "http/1.0" => Some(HttpProtocol::Http10),
+            "http/1.1" => Some(HttpProtocol::Http11),
+            "h2" | "http/2" | "http/2.0" => Some(HttpProtocol::H2),
+            name if name.starts_with("h3") || name.starts_with("http/3") => Some(HttpProtocol::H3),
+            _ => None,
+        }
+    }
+}
+
+/// Why a request got no response, or only part of one, from the origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    DnsFailure,
+    ConnectionRefused,
+    ConnectTimeout,
+    TlsError,
+    ResetByPeer,
+    ReadTimeout,
+    /// The response arrived but its body broke off; recorded as far as it arrived
+    TruncatedBody,
+    /// Anything else, and errors recorded before kinds were
+    Internal,
+}
+
+impl ErrorKind {
+    /// Classify an error description: a proxy error chain or a browser's `net::ERR_*` code
+    pub fn from_message(message: &str) -> Self {
+        let message = message.to_ascii_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|needle| message.contains(needle));
+        if has(&[
+            "name_not_resolved",
+            "dns error",
+            "failed to lookup address",
+            "name or service not known",
+            "no such host",
+            "nodename nor servname",
+        ]) {
+            ErrorKind::DnsFailure
+        } else if has(&["connection refused", "connection_refused"]) {
+            ErrorKind::ConnectionRefused
+        } else if has(&["cert", "ssl", "tls", "handshake"]) {
+            ErrorKind::TlsError
+        } else if has(&["connection_timed_out"])
+            || (has(&["connect error", "(connect)"]) && has(&["timed out", "timeout"]))
+        {
+            ErrorKind::ConnectTimeout
+        } else if has(&["timed_out", "timed out", "timeout"]) {
+            ErrorKind::ReadTimeout
+        } else if has(&[
+            "reset",
+            "connection_closed",
+            "empty_response",
+            "broken pipe",
+            "connection closed",
+            "incomplete message",
+        ]) {
+            ErrorKind::ResetByPeer
+        } else {
+            ErrorKind::Internal
+        }
+    }
+
+    /// Status a proxy answers with when forwarding failed this way
+    pub fn status(self) -> u16 {
+        match self {
+            ErrorKind::ConnectTimeout | ErrorKind::ReadTimeout => 504,
+            ErrorKind::DnsFailure
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::TlsError
+            | ErrorKind::ResetByPeer
+            | ErrorKind::TruncatedBody => 502,
+            ErrorKind::Internal => 500,
+        }
+    }
+}
+
+/// IP version of the connections to an origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpVersion {
+    Ipv4,
+    Ipv6,
+    Both,
 }
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct BodyChunk {
-    pub chunk: Vec<u8>,
+    pub chunk: Bytes,
     pub target_time: u64,
 }
 
@@ -138,10 +718,88 @@ pub struct Transaction {
     // TODO: Will be used for timing validation once playback timing issues are fixed
     pub ttfb: u64,
     pub status_code: Option<u16>,
+    pub reason_phrase: Option<String>,
+    pub error_kind: Option<ErrorKind>,
     pub error_message: Option<String>,
     pub raw_headers: Option<HttpHeaders>,
     pub chunks: Vec<BodyChunk>,
     pub target_close_time: u64, // Ideal connection close time in ms
+    pub resource_index: usize,  // Index of the source resource in the inventory
+    pub lazy: bool,             // Body is loaded on first use instead of preloaded
+    pub streamed: Option<StreamedBody>, // Body is read from disk chunk by chunk while sent
+    pub form_fields: Option<Vec<(String, String)>>, // Recorded multipart fields, for --match-form
+    pub upload: Option<UploadTiming>, // Request body is read at the recorded rate before TTFB
+    pub availability: Option<Availability>, // Not served before some time into playback
+    pub url_pattern: Option<UrlPattern>, // Answers a family of URLs; the body is a template
+    pub request_client: Option<ClientMetadata>, // Client the resource was recorded with
+    pub event_stream: Option<StreamEnd>, // Chunks are events sent at their target times
+    pub request_body_sha1: Option<String>, // For the body-hash matching strategy
+    pub match_headers: Option<HttpHeaders>, // For the header matching strategy
+    pub byte_ranges: Option<RangeMap>, // Body holds these parts of an object recorded in ranges
+    pub open_ended: bool,       // Body is what arrived of a response that never ended
+    pub timed: bool, // Chunks are due when they arrived while recording, not at an even rate
+    pub request_headers: Option<HttpHeaders>, // Headers of the recorded request, for --vary-on
+}
+
+/// What an event stream does after its last recorded event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamEnd {
+    Close,
+    // Stays open until the client disconnects, as it was when recording stopped
+    HoldOpen,
+}
+
+/// When a resource becomes available during playback, and what is answered before
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Availability {
+    pub after_ms: u64,
+    pub status: u16,
+}
+
+/// How long the recorded client took to send its request body
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UploadTiming {
+    pub bytes: u64,
+    pub duration_ms: u64,
+}
+
+/// A body too large to hold in memory, read from its content file as the client consumes it
+#[derive(Debug, Clone)]
+pub struct StreamedBody {
+    pub path: PathBuf,
+    /// Where the body starts in the file, past zero when a range of it is sent
+    pub offset: u64,
+    pub len: u64,
+    pub chunk_size: usize,
+    /// The recorded `chunkTimings` of the whole file, which chunks are due by
+    pub timings: Option<Vec<[u64; 2]>>,
+}
+
+impl StreamedBody {
+    pub fn chunk_count(&self) -> usize {
+        self.len.div_ceil(self.chunk_size as u64) as usize
+    }
+}
+
+impl Transaction {
+    /// Total size of the body, whether held in memory or streamed from disk
+    pub fn body_size(&self) -> usize {
+        match &self.streamed {
+            Some(body) => body.len as usize,
+            None => self.chunks.iter().map(|c| c.chunk.len()).sum(),
+        }
+    }
+
+    /// Status answered instead of the recording when the origin did not respond
+    pub fn error_status(&self) -> Option<u16> {
+        match (self.error_kind, &self.error_message) {
+            // The recorded status and what arrived of the body are replayed
+            (Some(ErrorKind::TruncatedBody), _) => None,
+            (Some(kind), _) => Some(kind.status()),
+            (None, Some(_)) => Some(ErrorKind::Internal.status()),
+            (None, None) => None,
+        }
+    }
 }
 
 impl Resource {
@@ -149,20 +807,108 @@ impl Resource {
         Self {
             method,
             url,
+            occurrence: None,
+            request_start_ms: None,
             ttfb_ms: 0,
+            connect_ms: None,
+            protocol: None,
+            upload_ms: None,
+            upload_bytes: None,
             duration_ms: None,
             mbps: None,
+            chunk_timings: None,
             status_code: None,
+            reason_phrase: None,
+            early_hints: None,
+            redirect_to: None,
+            request_form: None,
+            request_client: None,
+            request_body_sha1: None,
+            request_headers: None,
+            match_headers: None,
+            event_stream: None,
+            event_stream_open: None,
+            open_ended: None,
+            byte_ranges: None,
+            complete_length: None,
+            error_kind: None,
             error_message: None,
             raw_headers: None,
             content_encoding: None,
             content_type_mime: None,
+            sniffed_mime: None,
             content_charset: None,
+            charset_preserved: None,
+            original_file_path: None,
             content_file_path: None,
             content_utf8: None,
             content_base64: None,
             minify: None,
+            source_map_file_path: None,
+            sources_dir: None,
+            original_size: None,
+            available_after_ms: None,
+            unavailable_status: None,
+            url_match: None,
             raw_body: None,
+            raw_body_file: None,
+            forwarded_body: None,
+            source_map_url: None,
+        }
+    }
+
+    /// The pattern `url` stands for, for resources answering a family of URLs
+    pub fn url_pattern(&self) -> anyhow::Result<Option<UrlPattern>> {
+        UrlPattern::new(&self.url, self.url_match.unwrap_or_default())
+    }
+
+    /// Deferred availability, defaulting to 404 before the resource appears
+    pub fn availability(&self) -> Option<Availability> {
+        self.available_after_ms.map(|after_ms| Availability {
+            after_ms,
+            status: self.unavailable_status.unwrap_or(404),
+        })
+    }
+
+    /// How the replayed event stream ends, for resources recorded as one
+    pub fn stream_end(&self) -> Option<StreamEnd> {
+        self.event_stream.as_ref()?;
+        Some(if self.event_stream_open == Some(true) {
+            StreamEnd::HoldOpen
+        } else {
+            StreamEnd::Close
+        })
+    }
+
+    /// Charset the UTF-8 content is re-encoded to when served, None when sent as stored
+    pub fn reencode_charset(&self) -> Option<&str> {
+        self.content_charset
+            .as_deref()
+            .filter(|_| self.charset_preserved != Some(true))
+    }
+
+    /// MIME type the body is handled as: the sniffed one, else the Content-Type's
+    pub fn mime_type(&self) -> Option<&str> {
+        self.sniffed_mime
+            .as_deref()
+            .or(self.content_type_mime.as_deref())
+    }
+
+    /// The recorded spans of an object stitched from partial responses
+    pub fn range_map(&self) -> Option<RangeMap> {
+        Some(RangeMap {
+            spans: self.byte_ranges.clone()?,
+            complete_length: self.complete_length,
+        })
+    }
+
+    /// Recorded upload timing, when the request had a body that took time to send
+    pub fn upload_timing(&self) -> Option<UploadTiming> {
+        match (self.upload_bytes, self.upload_ms) {
+            (Some(bytes), Some(duration_ms)) if bytes > 0 && duration_ms > 0 => {
+                Some(UploadTiming { bytes, duration_ms })
+            }
+            _ => None,
         }
     }
 }
@@ -173,6 +919,11 @@ impl Inventory {
             entry_url: None,
             device_type: None,
             resources: Vec::new(),
+            opaque_tunnels: Vec::new(),
+            seed: None,
+            match_strategies: None,
+            origins: Vec::new(),
+            client_connections: Vec::new(),
         }
     }
 }
@@ -1,8 +1,10 @@
 #[cfg(test)]
 mod types_tests {
     use crate::types::{
-        BodyChunk, ContentEncodingType, DeviceType, Inventory, Resource, Transaction,
+        Availability, BodyChunk, ContentEncodingType, DeviceType, ErrorKind, HeaderValue,
+        HttpHeaders, HttpProtocol, Inventory, Resource, Transaction,
     };
+    use bytes::Bytes;
     use serde::Serialize;
 
     use std::str::FromStr;
@@ -137,14 +139,36 @@ mod types_tests {
         assert_eq!(resource.status_code, Some(200));
     }
 
+    #[test]
+    fn test_resource_availability() {
+        let json = r#"[
+            {"method": "GET", "url": "https://example.com/flags.json", "ttfbMs": 0,
+             "availableAfterMs": 30000},
+            {"method": "GET", "url": "https://example.com/new.js", "ttfbMs": 0,
+             "availableAfterMs": 5000, "unavailableStatus": 503},
+            {"method": "GET", "url": "https://example.com/", "ttfbMs": 0}
+        ]"#;
+        let resources: Vec<Resource> = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            resources[0].availability(),
+            Some(Availability {
+                after_ms: 30_000,
+                status: 404
+            })
+        );
+        assert_eq!(resources[1].availability().unwrap().status, 503);
+        assert_eq!(resources[2].availability(), None);
+    }
+
     #[test]
     fn test_body_chunk_creation() {
         let chunk = BodyChunk {
-            chunk: b"test data".to_vec(),
+            chunk: Bytes::from_static(b"test data"),
             target_time: 1000,
         };
 
-        assert_eq!(chunk.chunk, b"test data");
+        assert_eq!(chunk.chunk, &b"test data"[..]);
         assert_eq!(chunk.target_time, 1000);
     }
 
@@ -152,11 +176,11 @@ mod types_tests {
     fn test_transaction_creation() {
         let chunks = vec![
             BodyChunk {
-                chunk: b"chunk1".to_vec(),
+                chunk: Bytes::from_static(b"chunk1"),
                 target_time: 100,
             },
             BodyChunk {
-                chunk: b"chunk2".to_vec(),
+                chunk: Bytes::from_static(b"chunk2"),
                 target_time: 200,
             },
         ];
@@ -166,10 +190,27 @@ mod types_tests {
             url: "https://example.com".to_string(),
             ttfb: 50,
             status_code: Some(200),
+            reason_phrase: None,
+            error_kind: None,
             error_message: None,
             raw_headers: None,
             chunks,
             target_close_time: 300, // Example close time
+            resource_index: 0,
+            lazy: false,
+            streamed: None,
+            form_fields: None,
+            upload: None,
+            availability: None,
+            url_pattern: None,
+            request_client: None,
+            event_stream: None,
+            request_body_sha1: None,
+            match_headers: None,
+            byte_ranges: None,
+            open_ended: false,
+            timed: false,
+            request_headers: None,
         };
 
         assert_eq!(transaction.method, "GET");
@@ -178,5 +219,133 @@ mod types_tests {
         assert_eq!(transaction.status_code, Some(200));
         assert_eq!(transaction.chunks.len(), 2);
         assert_eq!(transaction.target_close_time, 300);
+        assert_eq!(transaction.body_size(), 12);
+    }
+
+    #[test]
+    fn test_error_kind_from_message() {
+        let cases = [
+            ("net::ERR_NAME_NOT_RESOLVED", ErrorKind::DnsFailure),
+            (
+                "client error (Connect): dns error: failed to lookup address information",
+                ErrorKind::DnsFailure,
+            ),
+            (
+                "client error (Connect): tcp connect error: Connection timed out",
+                ErrorKind::ConnectTimeout,
+            ),
+            (
+                "client error (Connect): tcp connect error: Connection refused (os error 111)",
+                ErrorKind::ConnectionRefused,
+            ),
+            ("net::ERR_CONNECTION_REFUSED", ErrorKind::ConnectionRefused),
+            ("net::ERR_CERT_AUTHORITY_INVALID", ErrorKind::TlsError),
+            ("connection reset by peer", ErrorKind::ResetByPeer),
+            ("operation timed out", ErrorKind::ReadTimeout),
+            ("something else went wrong", ErrorKind::Internal),
+        ];
+        for (message, kind) in cases {
+            assert_eq!(ErrorKind::from_message(message), kind, "{}", message);
+        }
+
+        assert_eq!(ErrorKind::ConnectTimeout.status(), 504);
+        assert_eq!(ErrorKind::ResetByPeer.status(), 502);
+        assert_eq!(ErrorKind::Internal.status(), 500);
+
+        let mut resource = Resource::new("GET".to_string(), "https://example.com/".to_string());
+        resource.error_kind = Some(ErrorKind::DnsFailure);
+        let json = serde_json::to_string(&resource).unwrap();
+        assert!(json.contains(r#""errorKind":"dnsFailure""#));
+    }
+
+    #[test]
+    fn test_http_protocol_names() {
+        assert_eq!(
+            HttpProtocol::from_version(hyper::Version::HTTP_2),
+            Some(HttpProtocol::H2)
+        );
+        assert_eq!(
+            HttpProtocol::from_name("HTTP/1.1"),
+            Some(HttpProtocol::Http11)
+        );
+        assert_eq!(HttpProtocol::from_name("http/2.0"), Some(HttpProtocol::H2));
+        assert_eq!(HttpProtocol::from_name("h3-29"), Some(HttpProtocol::H3));
+        assert_eq!(HttpProtocol::from_name(""), None);
+
+        let mut resource = Resource::new("GET".to_string(), "https://example.com/".to_string());
+        resource.protocol = Some(HttpProtocol::H2);
+        let json = serde_json::to_string(&resource).unwrap();
+        assert!(json.contains(r#""protocol":"h2""#));
+        let parsed: Resource = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.protocol, Some(HttpProtocol::H2));
+    }
+
+    #[test]
+    fn test_http_headers_preserve_order_and_casing() {
+        let json = r#"{"Server":"nginx","date":"Mon","X-Custom-B":"b","Content-Type":"text/html","x-custom-a":["1","2"]}"#;
+        let headers: HttpHeaders = serde_json::from_str(json).unwrap();
+
+        let names: Vec<&str> = headers.names().collect();
+        assert_eq!(
+            names,
+            vec!["Server", "date", "X-Custom-B", "Content-Type", "x-custom-a"]
+        );
+
+        // Round trip keeps the original order
+        assert_eq!(serde_json::to_string(&headers).unwrap(), json);
+    }
+
+    #[test]
+    fn test_http_headers_case_insensitive_access() {
+        let mut headers = HttpHeaders::new();
+        headers.set("Content-Type", HeaderValue::single("text/html".to_string()));
+        headers.set("Content-Length", HeaderValue::single("10".to_string()));
+        headers.set("ETag", HeaderValue::single("\"abc\"".to_string()));
+
+        assert_eq!(
+            headers.get("content-type").map(|v| v.first()),
+            Some("text/html")
+        );
+        assert!(headers.contains("etag"));
+
+        // Replacing keeps the recorded name and position
+        headers.set("content-length", HeaderValue::single("20".to_string()));
+        let names: Vec<&str> = headers.names().collect();
+        assert_eq!(names, vec!["Content-Type", "Content-Length", "ETag"]);
+        assert_eq!(headers.get("Content-Length").unwrap().first(), "20");
+
+        // Missing headers are appended
+        headers.set("vary", HeaderValue::single("Accept".to_string()));
+        assert_eq!(headers.names().last(), Some("vary"));
+
+        assert_eq!(
+            headers.remove("CONTENT-LENGTH"),
+            Some(HeaderValue::single("20".to_string()))
+        );
+        let names: Vec<&str> = headers.names().collect();
+        assert_eq!(names, vec!["Content-Type", "ETag", "vary"]);
+    }
+
+    #[test]
+    fn test_http_headers_merge_values_across_casings() {
+        let mut headers = HttpHeaders::new();
+        headers.append("Set-Cookie", "a=1".to_string());
+        headers.append("set-cookie", "b=2".to_string());
+        assert_eq!(headers.len(), 1);
+        assert_eq!(
+            headers.get("SET-COOKIE"),
+            Some(&HeaderValue::multiple(vec![
+                "a=1".to_string(),
+                "b=2".to_string()
+            ]))
+        );
+
+        // Inventories written with duplicate casings load as one header
+        let json = r#"{"Link":"</a.css>","link":["</b.js>","</c.js>"]}"#;
+        let headers: HttpHeaders = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            serde_json::to_string(&headers).unwrap(),
+            r#"{"Link":["</a.css>","</b.js>","</c.js>"]}"#
+        );
     }
 }
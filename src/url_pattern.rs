@@ -0,0 +1,96 @@
+//! Hand-authored resources standing for a family of URLs
+//!
+//! A resource with `"urlMatch": "glob"` or `"regex"` answers every request whose URL
+//! matches its `url`, when no resource has that exact URL. In a glob, `*` stands for
+//! any run of characters within one path segment and `**` for any run at all; a regex
+//! has to match the whole URL. The body of such a resource is a template: `{{url}}`,
+//! `{{method}}`, `{{1}}`, `{{2}}`, ... (wildcards or capture groups in order) and
+//! `{{name}}` (named groups) are replaced with the values of the request.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::types::UrlMatch;
+
+#[derive(Debug, Clone)]
+pub struct UrlPattern {
+    regex: Regex,
+}
+
+impl UrlPattern {
+    /// The pattern a resource's `url` stands for, None for exact URLs
+    pub fn new(url: &str, kind: UrlMatch) -> Result<Option<Self>> {
+        let source = match kind {
+            UrlMatch::Exact => return Ok(None),
+            UrlMatch::Glob => glob_to_regex(url),
+            UrlMatch::Regex => format!("^(?:{})$", url),
+        };
+        let regex =
+            Regex::new(&source).with_context(|| format!("Invalid URL pattern {:?}", url))?;
+        Ok(Some(Self { regex }))
+    }
+
+    pub fn is_match(&self, url: &str) -> bool {
+        self.regex.is_match(url)
+    }
+
+    /// `template` with the placeholders filled in for a request, None when `url` does
+    /// not match or nothing was replaced
+    pub fn render(&self, template: &str, method: &str, url: &str) -> Option<String> {
+        let captures = self.regex.captures(url)?;
+        let value = |key: &str| -> Option<&str> {
+            match key {
+                "url" => Some(url),
+                "method" => Some(method),
+                _ => match key.parse::<usize>() {
+                    Ok(index) if index > 0 => captures.get(index).map(|m| m.as_str()),
+                    Ok(_) => None,
+                    Err(_) => captures.name(key).map(|m| m.as_str()),
+                },
+            }
+        };
+
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        let mut changed = false;
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let key = rest[start + 2..start + 2 + len].trim();
+            rendered.push_str(&rest[..start]);
+            match value(key) {
+                Some(value) => {
+                    rendered.push_str(value);
+                    changed = true;
+                }
+                // Unknown placeholders are left as written
+                None => rendered.push_str(&rest[start..start + 2 + len + 2]),
+            }
+            rest = &rest[start + 2 + len + 2..];
+        }
+        rendered.push_str(rest);
+        changed.then_some(rendered)
+    }
+}
+
+/// Anchored regex for a glob, each wildcard a capture group
+fn glob_to_regex(glob: &str) -> String {
+    let mut source = String::from("^");
+    let mut rest = glob;
+    while let Some(star) = rest.find('*') {
+        source.push_str(&regex::escape(&rest[..star]));
+        if rest[star..].starts_with("**") {
+            source.push_str("(.*)");
+            rest = &rest[star + 2..];
+        } else {
+            source.push_str("([^/]*)");
+            rest = &rest[star + 1..];
+        }
+    }
+    source.push_str(&regex::escape(rest));
+    source.push('$');
+    source
+}
+
+mod tests;
@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod url_pattern_tests {
+    use crate::types::UrlMatch;
+    use crate::url_pattern::UrlPattern;
+
+    #[test]
+    fn test_exact_urls_are_not_patterns() {
+        assert!(
+            UrlPattern::new("https://example.com/*", UrlMatch::Exact)
+                .unwrap()
+                .is_none()
+        );
+        assert!(UrlPattern::new("https://example.com/(", UrlMatch::Regex).is_err());
+    }
+
+    #[test]
+    fn test_glob() {
+        let pattern = UrlPattern::new("https://api.example.com/api/users/*", UrlMatch::Glob)
+            .unwrap()
+            .unwrap();
+        assert!(pattern.is_match("https://api.example.com/api/users/42"));
+        assert!(pattern.is_match("https://api.example.com/api/users/"));
+        assert!(!pattern.is_match("https://api.example.com/api/users/42/posts"));
+        assert!(!pattern.is_match("https://apixexample.com/api/users/42"));
+
+        let deep = UrlPattern::new("https://cdn.example.com/**.png", UrlMatch::Glob)
+            .unwrap()
+            .unwrap();
+        assert!(deep.is_match("https://cdn.example.com/img/2024/logo.png"));
+        assert!(!deep.is_match("https://cdn.example.com/img/logo.jpg"));
+    }
+
+    #[test]
+    fn test_regex_matches_whole_url() {
+        let pattern = UrlPattern::new(
+            r"https://api\.example\.com/items/(?P<id>\d+)",
+            UrlMatch::Regex,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(pattern.is_match("https://api.example.com/items/7"));
+        assert!(!pattern.is_match("https://api.example.com/items/7?full=1"));
+        assert!(!pattern.is_match("https://api.example.com/items/abc"));
+    }
+
+    #[test]
+    fn test_render_template() {
+        let pattern = UrlPattern::new("https://api.example.com/users/*/posts/*", UrlMatch::Glob)
+            .unwrap()
+            .unwrap();
+        let url = "https://api.example.com/users/42/posts/7";
+        assert_eq!(
+            pattern
+                .render(
+                    r#"{"user": "{{1}}", "post": {{ 2 }}, "via": "{{method}} {{url}}", "x": "{{nope}}"}"#,
+                    "GET",
+                    url
+                )
+                .unwrap(),
+            r#"{"user": "42", "post": 7, "via": "GET https://api.example.com/users/42/posts/7", "x": "{{nope}}"}"#
+        );
+        // Nothing to fill in, or another URL
+        assert_eq!(pattern.render("static {{nope}}", "GET", url), None);
+        assert_eq!(
+            pattern.render("{{1}}", "GET", "https://api.example.com/users"),
+            None
+        );
+
+        let named = UrlPattern::new(r"https://example\.com/(?P<slug>[a-z-]+)", UrlMatch::Regex)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            named
+                .render(
+                    "<h1>{{slug}}</h1>",
+                    "GET",
+                    "https://example.com/hello-world"
+                )
+                .unwrap(),
+            "<h1>hello-world</h1>"
+        );
+    }
+}
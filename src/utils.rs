@@ -20,11 +20,129 @@ pub fn get_port_or_default(port: Option<u16>) -> Result<u16> {
     }
 }
 
+/// Parse a duration such as `30s`, `1500ms`, `2m` or `1h` into milliseconds
+///
+/// A bare number is taken as seconds.
+pub fn parse_duration_ms(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid duration: {:?}", value))?;
+    let factor = match unit.trim() {
+        "ms" => 1.0,
+        "" | "s" => 1000.0,
+        "m" => 60_000.0,
+        "h" => 3_600_000.0,
+        other => anyhow::bail!("Unknown duration unit {:?} in {:?}", other, value),
+    };
+    Ok((number * factor).round() as u64)
+}
+
+/// Parse a size such as `512MB`, `2G` or `64KiB` into bytes (units are powers of 1024)
+///
+/// A bare number is taken as bytes.
+pub fn parse_size_bytes(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size: {:?}", value))?;
+    let factor = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1u64,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        other => anyhow::bail!("Unknown size unit {:?} in {:?}", other, value),
+    };
+    Ok((number * factor as f64).round() as u64)
+}
+
+/// Parse a bandwidth such as `100mbps`, `500kbps` or `1gbps` into megabits per second
+///
+/// A bare number is taken as megabits per second.
+pub fn parse_bandwidth_mbps(value: &str) -> Result<f64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid bandwidth: {:?}", value))?;
+    let factor = match unit.trim().to_ascii_lowercase().as_str() {
+        "k" | "kbps" | "kbit" => 0.001,
+        "" | "m" | "mbps" | "mbit" => 1.0,
+        "g" | "gbps" | "gbit" => 1000.0,
+        other => anyhow::bail!("Unknown bandwidth unit {:?} in {:?}", other, value),
+    };
+    if number <= 0.0 {
+        anyhow::bail!("Bandwidth must be positive: {:?}", value);
+    }
+    Ok(number * factor)
+}
+
+/// Deterministic pseudo-random numbers (SplitMix64)
+///
+/// Everything randomized draws from one of these, so the same `--seed` produces the
+/// same output on every run and platform.
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// A generator for one `label` (e.g. a URL), independent of those for other labels
+    pub fn derive(seed: u64, label: &str) -> Self {
+        // FNV-1a
+        let hash = label.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        Self::new(seed ^ hash)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(len + 8);
+        while bytes.len() < len {
+            bytes.extend_from_slice(&self.next_u64().to_le_bytes());
+        }
+        bytes.truncate(len);
+        bytes
+    }
+}
+
+/// Longest path segment kept whole; leaves room for the query suffix within 255 bytes
+const MAX_SEGMENT_BYTES: usize = 100;
+
+/// Content file path for a URL: `<method>/<scheme>/<host>/<path>`
+///
+/// Punycode hosts and percent-encoded UTF-8 segments are decoded so the tree stays
+/// readable. Playback never derives paths from URLs; it follows `contentFilePath` in
+/// index.json, so the mapping is stored with each resource.
 #[allow(dead_code)]
 pub fn generate_file_path_from_url(url: &str, method: &str) -> Result<String> {
     let parsed_url = Url::parse(url)?;
     let scheme = parsed_url.scheme();
-    let host = parsed_url.host_str().unwrap_or("localhost");
+    let host = file_path_host(parsed_url.host_str().unwrap_or("localhost"));
     let path = parsed_url.path();
 
     let mut file_path = format!("{}/{}/{}", method.to_lowercase(), scheme, host);
@@ -36,7 +154,7 @@ pub fn generate_file_path_from_url(url: &str, method: &str) -> Result<String> {
     } else {
         for segment in &path_segments {
             file_path.push('/');
-            file_path.push_str(segment);
+            file_path.push_str(&file_path_segment(segment));
         }
 
         // If path ends with '/', add index.html
@@ -92,6 +210,85 @@ pub fn generate_file_path_from_url(url: &str, method: &str) -> Result<String> {
     Ok(file_path)
 }
 
+/// Unicode form of an IDN host, or the host unchanged
+fn file_path_host(host: &str) -> String {
+    if host.split('.').any(|label| label.starts_with("xn--")) {
+        let (unicode, result) = idna::domain_to_unicode(host);
+        if result.is_ok() {
+            return unicode;
+        }
+    }
+    host.to_string()
+}
+
+/// Decode a percent-encoded path segment where the result is a safe file name, and cap
+/// its length
+pub fn file_path_segment(segment: &str) -> String {
+    let name = match urlencoding::decode(segment) {
+        Ok(decoded) if is_safe_file_name(&decoded) => decoded.into_owned(),
+        _ => segment.to_string(),
+    };
+    cap_file_name(&name)
+}
+
+fn is_safe_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.chars().any(|c| {
+            c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+        })
+}
+
+/// Cut names longer than MAX_SEGMENT_BYTES and append a hash of the full name,
+/// keeping a short extension
+fn cap_file_name(name: &str) -> String {
+    if name.len() <= MAX_SEGMENT_BYTES {
+        return name.to_string();
+    }
+
+    let mut hasher = Sha1::new();
+    hasher.update(name.as_bytes());
+    let hash = hex::encode(hasher.finalize());
+    let hash = &hash[..8];
+
+    let ext = name
+        .rfind('.')
+        .map(|pos| &name[pos..])
+        .filter(|ext| ext.len() <= 10)
+        .unwrap_or("");
+    let mut end = MAX_SEGMENT_BYTES - ext.len() - hash.len() - 1;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}~{}{}", &name[..end], hash, ext)
+}
+
+/// Suffix a content file path with `@N` for the Nth repeat of the same method and URL
+///
+/// The first occurrence keeps the plain path, so single captures are unaffected.
+pub fn with_occurrence_suffix(file_path: &str, occurrence: u32) -> String {
+    if occurrence == 0 {
+        return file_path.to_string();
+    }
+
+    let name_start = file_path.rfind('/').map(|pos| pos + 1).unwrap_or(0);
+    let last_segment = &file_path[name_start..];
+    // Keep the real extension last, but not a `.~hash` query marker
+    match last_segment.rfind('.') {
+        Some(dot_pos) if !last_segment[dot_pos..].starts_with(".~") => {
+            let split = name_start + dot_pos;
+            format!(
+                "{}@{}{}",
+                &file_path[..split],
+                occurrence,
+                &file_path[split..]
+            )
+        }
+        _ => format!("{}@{}", file_path, occurrence),
+    }
+}
+
 #[allow(dead_code)]
 pub fn is_text_resource(content_type: &str) -> bool {
     let content_type = content_type.to_lowercase();
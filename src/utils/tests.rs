@@ -1,8 +1,10 @@
 #[cfg(test)]
 mod utils_tests {
     use crate::utils::{
-        extract_charset_from_content_type, extract_charset_from_css, extract_charset_from_html,
-        find_available_port, generate_file_path_from_url, get_port_or_default, is_text_resource,
+        SeededRng, extract_charset_from_content_type, extract_charset_from_css,
+        extract_charset_from_html, find_available_port, generate_file_path_from_url,
+        get_port_or_default, is_text_resource, parse_bandwidth_mbps, parse_duration_ms,
+        parse_size_bytes, with_occurrence_suffix,
     };
 
     #[test]
@@ -139,6 +141,40 @@ mod utils_tests {
         assert!(result.contains("get/https/example.com"));
     }
 
+    #[test]
+    fn test_generate_file_path_decodes_idn_and_utf8() {
+        let result = generate_file_path_from_url(
+            "https://xn--bcher-kva.example/%E6%97%A5%E6%9C%AC/a%20b.html",
+            "GET",
+        )
+        .unwrap();
+        assert_eq!(result, "get/https/bücher.example/日本/a b.html");
+
+        // Decoding that would introduce a separator or reserved character is skipped
+        let result = generate_file_path_from_url("https://example.com/a%2Fb/c%3Fd", "GET").unwrap();
+        assert_eq!(result, "get/https/example.com/a%2Fb/c%3Fd");
+    }
+
+    #[test]
+    fn test_generate_file_path_caps_long_segments() {
+        let long_name = format!("{}.js", "あ".repeat(60));
+        let result =
+            generate_file_path_from_url(&format!("https://example.com/{}", long_name), "GET")
+                .unwrap();
+        let file_name = result.rsplit('/').next().unwrap();
+        assert!(file_name.len() <= 100, "{} is too long", file_name);
+        assert!(file_name.ends_with(".js"));
+        assert!(file_name.starts_with("あ"));
+
+        // Different names sharing a long prefix stay distinct
+        let other = generate_file_path_from_url(
+            &format!("https://example.com/{}x.js", "あ".repeat(60)),
+            "GET",
+        )
+        .unwrap();
+        assert_ne!(result, other);
+    }
+
     #[test]
     fn test_generate_file_path_methods() {
         let url = "https://api.example.com/data";
@@ -299,4 +335,92 @@ mod utils_tests {
         let css = b"@CHARSET \"UTF-8\"; .foo { }";
         assert_eq!(extract_charset_from_css(css), Some("utf-8".to_string()));
     }
+
+    #[test]
+    fn test_with_occurrence_suffix() {
+        // First occurrence keeps the plain path
+        assert_eq!(
+            with_occurrence_suffix("get/https/example.com/api/data.json", 0),
+            "get/https/example.com/api/data.json"
+        );
+        assert_eq!(
+            with_occurrence_suffix("get/https/example.com/api/data.json", 1),
+            "get/https/example.com/api/data@1.json"
+        );
+        assert_eq!(
+            with_occurrence_suffix("get/https/example.com/script~v%3D1.js", 2),
+            "get/https/example.com/script~v%3D1@2.js"
+        );
+        assert_eq!(
+            with_occurrence_suffix("get/https/example.com/api/poll", 3),
+            "get/https/example.com/api/poll@3"
+        );
+        // A `.~hash` query marker is not treated as an extension
+        assert_eq!(
+            with_occurrence_suffix("get/https/example.com/api~aaaa.~0123abcd", 1),
+            "get/https/example.com/api~aaaa.~0123abcd@1"
+        );
+    }
+
+    #[test]
+    fn test_parse_duration_ms() {
+        assert_eq!(parse_duration_ms("30s").unwrap(), 30_000);
+        assert_eq!(parse_duration_ms("1500ms").unwrap(), 1_500);
+        assert_eq!(parse_duration_ms("2m").unwrap(), 120_000);
+        assert_eq!(parse_duration_ms("1h").unwrap(), 3_600_000);
+        assert_eq!(parse_duration_ms("1.5s").unwrap(), 1_500);
+        // Bare numbers are seconds
+        assert_eq!(parse_duration_ms("45").unwrap(), 45_000);
+
+        assert!(parse_duration_ms("").is_err());
+        assert!(parse_duration_ms("10 days").is_err());
+        assert!(parse_duration_ms("s").is_err());
+    }
+
+    #[test]
+    fn test_parse_bandwidth_mbps() {
+        assert_eq!(parse_bandwidth_mbps("100").unwrap(), 100.0);
+        assert_eq!(parse_bandwidth_mbps("100mbps").unwrap(), 100.0);
+        assert_eq!(parse_bandwidth_mbps("500kbps").unwrap(), 0.5);
+        assert_eq!(parse_bandwidth_mbps("1.5Gbps").unwrap(), 1500.0);
+
+        assert!(parse_bandwidth_mbps("").is_err());
+        assert!(parse_bandwidth_mbps("0").is_err());
+        assert!(parse_bandwidth_mbps("10 bytes").is_err());
+    }
+
+    #[test]
+    fn test_parse_size_bytes() {
+        assert_eq!(parse_size_bytes("512").unwrap(), 512);
+        assert_eq!(parse_size_bytes("64KB").unwrap(), 64 * 1024);
+        assert_eq!(parse_size_bytes("512MB").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_size_bytes("1.5g").unwrap(), 1536 * 1024 * 1024);
+        assert_eq!(parse_size_bytes("2 GiB").unwrap(), 2 * 1024 * 1024 * 1024);
+
+        assert!(parse_size_bytes("").is_err());
+        assert!(parse_size_bytes("10 pages").is_err());
+    }
+
+    #[test]
+    fn test_seeded_rng_is_reproducible() {
+        let draw = |seed, label| SeededRng::derive(seed, label).bytes(20);
+        assert_eq!(
+            draw(7, "https://example.com/a"),
+            draw(7, "https://example.com/a")
+        );
+        assert_ne!(
+            draw(7, "https://example.com/a"),
+            draw(8, "https://example.com/a")
+        );
+        assert_ne!(
+            draw(7, "https://example.com/a"),
+            draw(7, "https://example.com/b")
+        );
+        assert_eq!(draw(7, "https://example.com/a").len(), 20);
+
+        // SplitMix64 reference values for seed 0
+        let mut rng = SeededRng::new(0);
+        assert_eq!(rng.next_u64(), 0xe220a8397b1dcdaf);
+        assert_eq!(rng.next_u64(), 0x6e789e6aa1b965f4);
+    }
 }
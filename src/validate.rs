@@ -0,0 +1,238 @@
+//! Checking an inventory for problems before playback runs into them
+//!
+//! Hand-editing `index.json` and the content files easily leaves an inventory that
+//! loads with errors or silently serves the wrong thing: a content file renamed while
+//! beautifying, a base64 body cut short, a misspelled `contentEncoding`, a resource
+//! copied without changing its URL. `validate` reads the inventory the way playback
+//! does, resource by resource so one bad entry does not hide the others, and reports
+//! every problem it finds with the resource it belongs to.
+
+use anyhow::Result;
+use base64::{Engine as _, engine::general_purpose};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::traits::{FileSystem, RealFileSystem};
+use crate::types::{ContentEncodingType, Inventory, Resource};
+
+/// A problem with the inventory or one of its resources
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    /// 0-based index in `resources`, None for the inventory as a whole
+    pub resource: Option<usize>,
+    /// Method and URL of the resource, as far as they could be read
+    pub label: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.resource, &self.label) {
+            (Some(index), Some(label)) => {
+                write!(f, "resource #{} ({}): {}", index + 1, label, self.message)
+            }
+            (Some(index), None) => write!(f, "resource #{}: {}", index + 1, self.message),
+            (None, _) => write!(f, "index.json: {}", self.message),
+        }
+    }
+}
+
+pub async fn run_validate(inventory_dir: PathBuf) -> Result<()> {
+    let issues = validate_inventory(&inventory_dir, Arc::new(RealFileSystem)).await?;
+    if issues.is_empty() {
+        println!("Inventory {:?} is valid", inventory_dir);
+        return Ok(());
+    }
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    anyhow::bail!("{} problems found in {:?}", issues.len(), inventory_dir)
+}
+
+/// Every problem found in the inventory in `inventory_dir`
+///
+/// Fails only when `index.json` cannot be read or is not JSON at all.
+pub async fn validate_inventory<F: FileSystem>(
+    inventory_dir: &Path,
+    file_system: Arc<F>,
+) -> Result<Vec<Issue>> {
+    let index_path = inventory_dir.join("index.json");
+    let content = file_system.read_to_string(&index_path).await?;
+    let mut document: Value = serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("{:?} is not valid JSON: {}", index_path, e))?;
+
+    let mut issues = Vec::new();
+    let inventory_issue = |message: String| Issue {
+        resource: None,
+        label: None,
+        message,
+    };
+    let resources = match document.get_mut("resources").map(Value::take) {
+        Some(Value::Array(resources)) => resources,
+        Some(_) => {
+            issues.push(inventory_issue("`resources` is not an array".to_string()));
+            Vec::new()
+        }
+        None => {
+            issues.push(inventory_issue("`resources` is missing".to_string()));
+            Vec::new()
+        }
+    };
+    // Everything but the resources, which are checked one by one below
+    document["resources"] = Value::Array(Vec::new());
+    if let Err(e) = serde_json::from_value::<Inventory>(document) {
+        issues.push(inventory_issue(format!("does not match the schema: {}", e)));
+    }
+
+    let mut parsed = Vec::new();
+    for (index, value) in resources.into_iter().enumerate() {
+        let label = match (value.get("method"), value.get("url")) {
+            (Some(Value::String(method)), Some(Value::String(url))) => {
+                Some(format!("{} {}", method, url))
+            }
+            _ => None,
+        };
+        let issue = |message: String| Issue {
+            resource: Some(index),
+            label: label.clone(),
+            message,
+        };
+        // Reported on its own, as serde's message does not name the field
+        let unknown_encoding = value.get("contentEncoding").filter(|encoding| {
+            !encoding.is_null()
+                && serde_json::from_value::<ContentEncodingType>((*encoding).clone()).is_err()
+        });
+        if let Some(encoding) = unknown_encoding {
+            issues.push(issue(format!(
+                "unknown contentEncoding {}; expected gzip, br, deflate, compress or identity",
+                encoding
+            )));
+            continue;
+        }
+        match serde_json::from_value::<Resource>(value) {
+            Ok(resource) => {
+                for message in check_resource(&resource, inventory_dir, file_system.as_ref()).await
+                {
+                    issues.push(issue(message));
+                }
+                parsed.push((index, resource));
+            }
+            Err(e) => issues.push(issue(format!("does not match the schema: {}", e))),
+        }
+    }
+
+    // Repeated recordings are told apart by occurrence; anything else is a leftover copy
+    let mut first: HashMap<(String, &str, Option<u32>), usize> = HashMap::new();
+    for (index, resource) in &parsed {
+        let key = (
+            resource.method.to_ascii_uppercase(),
+            resource.url.as_str(),
+            resource.occurrence,
+        );
+        if let Some(original) = first.get(&key) {
+            issues.push(Issue {
+                resource: Some(*index),
+                label: Some(format!("{} {}", resource.method, resource.url)),
+                message: format!(
+                    "duplicates resource #{} (same method, URL and occurrence); remove one or give them distinct occurrences",
+                    original + 1
+                ),
+            });
+        } else {
+            first.insert(key, *index);
+        }
+    }
+
+    for (index, resource) in &parsed {
+        let Some(target) = &resource.redirect_to else {
+            continue;
+        };
+        let key = (
+            target.method.to_ascii_uppercase(),
+            target.url.as_str(),
+            target.occurrence,
+        );
+        if !first.contains_key(&key) {
+            issues.push(Issue {
+                resource: Some(*index),
+                label: Some(format!("{} {}", resource.method, resource.url)),
+                message: format!(
+                    "redirectTo {} {} names no recorded resource",
+                    target.method, target.url
+                ),
+            });
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Problems with the content and file references of one resource
+async fn check_resource<F: FileSystem>(
+    resource: &Resource,
+    inventory_dir: &Path,
+    file_system: &F,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    match resource.url_pattern() {
+        Err(e) => problems.push(format!("invalid urlMatch pattern: {}", e)),
+        Ok(None) if url::Url::parse(&resource.url).is_err() => {
+            problems.push(format!("url {:?} is not an absolute URL", resource.url))
+        }
+        Ok(_) => {}
+    }
+
+    let files = [
+        ("contentFilePath", resource.content_file_path.as_deref()),
+        ("originalFilePath", resource.original_file_path.as_deref()),
+        (
+            "sourceMapFilePath",
+            resource.source_map_file_path.as_deref(),
+        ),
+        ("sourcesDir", resource.sources_dir.as_deref()),
+    ]
+    .into_iter()
+    .chain(
+        resource
+            .request_form
+            .iter()
+            .flatten()
+            .map(|part| ("requestForm filePath", part.file_path.as_deref())),
+    );
+    for (field, file_path) in files {
+        let Some(file_path) = file_path else {
+            continue;
+        };
+        let relative = Path::new(file_path);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            problems.push(format!(
+                "{} {:?} must be a path inside the inventory directory",
+                field, file_path
+            ));
+        } else if !file_system.exists(&inventory_dir.join(relative)).await {
+            problems.push(format!(
+                "{} {:?} does not exist (renamed or moved while editing?)",
+                field, file_path
+            ));
+        }
+    }
+
+    let decoded = resource
+        .content_base64
+        .as_ref()
+        .map(|base64| general_purpose::STANDARD.decode(base64));
+    if let Some(Err(e)) = decoded {
+        problems.push(format!("contentBase64 is not valid base64: {}", e));
+    }
+
+    problems
+}
+
+mod tests;
@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod validate_tests {
+    use crate::traits::RealFileSystem;
+    use crate::validate::validate_inventory;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_validate_reports_broken_resources() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir.join("contents/get/https/example.com")).unwrap();
+        std::fs::write(
+            dir.join("contents/get/https/example.com/index.html"),
+            "<p>ok</p>",
+        )
+        .unwrap();
+        let index = r#"{
+            "deviceType": "mobile",
+            "resources": [
+                {"method": "GET", "url": "https://example.com/", "ttfbMs": 10,
+                 "contentFilePath": "contents/get/https/example.com/index.html"},
+                {"method": "GET", "url": "https://example.com/app.js", "ttfbMs": 10,
+                 "contentFilePath": "contents/get/https/example.com/app.min.js"},
+                {"method": "GET", "url": "https://example.com/logo.png", "ttfbMs": 10,
+                 "contentBase64": "iVBORw0KGgo*"},
+                {"method": "GET", "url": "https://example.com/font.woff2", "ttfbMs": 10,
+                 "contentEncoding": "zstd"},
+                {"method": "get", "url": "https://example.com/", "ttfbMs": 12},
+                {"method": "GET", "url": "https://example.com/poll", "ttfbMs": "slow"},
+                {"method": "GET", "url": "https://example.com/secret", "ttfbMs": 10,
+                 "contentFilePath": "../outside.txt"},
+                {"method": "GET", "url": "https://example.com/api/(", "ttfbMs": 10,
+                 "urlMatch": "regex"},
+                {"method": "GET", "url": "https://example.com/poll", "ttfbMs": 10,
+                 "occurrence": 0},
+                {"method": "GET", "url": "https://example.com/poll", "ttfbMs": 10,
+                 "occurrence": 1},
+                {"method": "GET", "url": "http://example.com/", "ttfbMs": 10, "statusCode": 301,
+                 "redirectTo": {"method": "GET", "url": "https://example.com/"}},
+                {"method": "GET", "url": "https://example.com/old", "ttfbMs": 10, "statusCode": 302,
+                 "redirectTo": {"method": "GET", "url": "https://example.com/new"}}
+            ]
+        }"#;
+        std::fs::write(dir.join("index.json"), index).unwrap();
+
+        let issues = validate_inventory(dir, Arc::new(RealFileSystem))
+            .await
+            .unwrap();
+        let found: Vec<(Option<usize>, String)> = issues
+            .iter()
+            .map(|issue| (issue.resource, issue.to_string()))
+            .collect();
+        let resources: Vec<Option<usize>> = found.iter().map(|(index, _)| *index).collect();
+        assert_eq!(
+            resources,
+            vec![
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(5),
+                Some(6),
+                Some(7),
+                Some(4),
+                Some(11)
+            ],
+            "{:#?}",
+            found
+        );
+        assert!(found[0].1.contains("app.min.js\" does not exist"));
+        assert!(found[1].1.contains("contentBase64 is not valid base64"));
+        assert!(found[2].1.contains("unknown contentEncoding \"zstd\""));
+        assert!(found[3].1.contains("does not match the schema"));
+        assert!(found[4].1.contains("must be a path inside the inventory"));
+        assert!(found[5].1.contains("invalid urlMatch pattern"));
+        assert_eq!(
+            found[6].1,
+            "resource #5 (get https://example.com/): duplicates resource #1 (same method, URL and occurrence); remove one or give them distinct occurrences"
+        );
+        assert_eq!(
+            found[7].1,
+            "resource #12 (GET https://example.com/old): redirectTo GET https://example.com/new names no recorded resource"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_inventory_level_problems() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+
+        std::fs::write(dir.join("index.json"), r#"{"resources": []}"#).unwrap();
+        let fs = Arc::new(RealFileSystem);
+        assert!(
+            validate_inventory(dir, fs.clone())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        std::fs::write(dir.join("index.json"), r#"{"deviceType": "watch"}"#).unwrap();
+        let issues = validate_inventory(dir, fs.clone()).await.unwrap();
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].to_string(), "index.json: `resources` is missing");
+        assert!(issues[1].message.contains("unknown variant `watch`"));
+
+        std::fs::write(dir.join("index.json"), "{").unwrap();
+        assert!(validate_inventory(dir, fs).await.is_err());
+    }
+}
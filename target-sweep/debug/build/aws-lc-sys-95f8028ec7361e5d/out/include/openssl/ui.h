@@ -0,0 +1,11 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0 OR ISC
+
+#ifndef UI_H
+#define UI_H
+
+#endif //UI_H
+
+// Intentionally empty.
+
+// Prevent compiler fall-through picking up "openssl/ui.h" from the system path.